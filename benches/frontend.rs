@@ -0,0 +1,71 @@
+//! Times the lexer, the parser and name resolution on programs from
+//! [radi::corpus], with `cargo bench`. Giving a word, as in `cargo bench --
+//! parse`, only runs the benchmarks whose names contain it.
+//!
+//! Each benchmark is measured with criterion, which compares it with the
+//! last run and writes its reports to `target/criterion`.
+
+use std::{hint::black_box, path::Path};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use radi::{
+    char_reader::StrCharReader,
+    corpus,
+    parse_manager::{FileSystem, Overlay, ParseManager},
+    parse_str,
+    resolver::{self, ResolveOptions},
+    ErrorStream, StringStorage, Tokens,
+};
+
+/// The sizes of the programs, in nodes of syntax.
+const SIZES: [usize; 2] = [10_000, 100_000];
+
+/// Does what's measured with the source of a program.
+type Bench = fn(&str);
+
+/// The benchmarks, each of which is run on a program of each size.
+const BENCHES: [(&str, Bench); 3] = [("lex", lex), ("parse", parse), ("resolve", resolve)];
+
+fn frontend(c: &mut Criterion) {
+    let programs = SIZES.map(|nodes| (nodes, corpus::generate(nodes)));
+    for (name, bench) in BENCHES {
+        let mut group = c.benchmark_group(name);
+        for (nodes, src) in &programs {
+            group.throughput(Throughput::Bytes(src.len() as u64));
+            group.bench_with_input(BenchmarkId::from_parameter(nodes), src, |b, src| {
+                b.iter(|| bench(src))
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, frontend);
+criterion_main!(benches);
+
+fn lex(src: &str) {
+    let storage = StringStorage::new();
+    let mut tokens = Tokens::of(StrCharReader::new(src), &storage);
+    while let Some(token) = tokens.next().unwrap() {
+        black_box(token);
+    }
+}
+
+fn parse(src: &str) {
+    let storage = StringStorage::new();
+    let errors = ErrorStream::buffered();
+    black_box(parse_str(src, &storage, &errors).unwrap());
+}
+
+fn resolve(src: &str) {
+    let storage = StringStorage::new();
+    let errors = ErrorStream::buffered();
+    let path = Path::new("corpus.radi");
+    let mut sources = Overlay::new(FileSystem);
+    sources.insert(path, src.to_string());
+    let mut manager = ParseManager::new(&storage, &errors, ".").with_sources(sources);
+    manager.load(path).unwrap();
+    let options = ResolveOptions { prelude: false };
+    black_box(resolver::resolve(&manager, &options, &errors));
+    assert_eq!(errors.error_count(), 0);
+}
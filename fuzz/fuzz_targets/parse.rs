@@ -0,0 +1,9 @@
+//! Parses bytes, which must neither panic nor overflow the stack whatever
+//! they are.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = radi::parse_bytes(data);
+});
@@ -0,0 +1,18 @@
+//! Checks that the lossless token stream prints back to exactly the source
+//! it was split from, whatever the source is.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use radi::{tokenizer, StringStorage};
+
+fuzz_target!(|src: &str| {
+    let storage = StringStorage::new();
+    let pieces = tokenizer::lossless(src, &storage);
+    assert_eq!(tokenizer::print(&pieces), src);
+    let mut pos = 0;
+    for piece in &pieces {
+        assert_eq!(piece.span.start as usize, pos);
+        assert_eq!(&src[pos..piece.span.end as usize], piece.text);
+        pos = piece.span.end as usize;
+    }
+});
@@ -0,0 +1,18 @@
+//! Tokenizes bytes as a stream, a buffer at a time, so that characters and
+//! bytes that aren't UTF-8 are split across the ends of buffers.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use radi::{char_reader::IoCharReader, StringStorage, Tokens};
+
+fuzz_target!(|data: &[u8]| {
+    // the first byte picks how large the buffer is
+    let Some((&size, data)) = data.split_first() else {
+        return;
+    };
+    let chars = IoCharReader::new(data).with_buf_size(4 + size as usize);
+    let storage = StringStorage::new();
+    let mut tokens = Tokens::of(chars, &storage);
+    // the tokenizer stops at the first error
+    while let Ok(Some(_)) = tokens.next() {}
+});
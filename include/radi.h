@@ -0,0 +1,50 @@
+/* The radi front end as a C library, built with the `capi` feature. See
+ * src/capi.rs for how to build it. */
+
+#ifndef RADI_H
+#define RADI_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RADI_WARNING 0
+#define RADI_ERROR 1
+
+/* The result of parsing a string of source, which owns every string read
+ * from it. */
+typedef struct RadiParse RadiParse;
+
+typedef struct RadiDiagnostic {
+    /* RADI_WARNING or RADI_ERROR. */
+    uint32_t severity;
+    /* Owned by the parse. */
+    const char *message;
+    /* Whether start and end are byte offsets into the source. */
+    bool has_span;
+    uint32_t start;
+    uint32_t end;
+} RadiDiagnostic;
+
+/* Parses `len` bytes of UTF-8. Returns NULL if they aren't valid UTF-8. */
+RadiParse *radi_parse(const uint8_t *src, size_t len);
+
+void radi_parse_free(RadiParse *parse);
+
+/* The syntax tree as JSON, or NULL if the source didn't parse. */
+const char *radi_parse_ast_json(const RadiParse *parse);
+
+size_t radi_parse_diagnostic_count(const RadiParse *parse);
+
+/* Writes the diagnostic at `index` to `out`. Returns whether there is one. */
+bool radi_parse_diagnostic(const RadiParse *parse, size_t index, RadiDiagnostic *out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,67 @@
+//! The front end as a Node.js addon, so that editors and build tools written
+//! in JavaScript can call it in the same process instead of running `radi`
+//! for every change. It's built with `npm run build`, which leaves the addon
+//! and its bindings next to `package.json`.
+
+use std::path::PathBuf;
+
+use napi_derive::napi;
+use radi::{parser, ErrorStream, Severity, SourceMap, StringStorage};
+
+/// What's wrong with a source, with the byte offsets into it that it points
+/// at, if any.
+#[napi(object)]
+pub struct Diagnostic {
+    /// `"warning"` or `"error"`.
+    pub severity: String,
+    pub message: String,
+    pub start: Option<u32>,
+    pub end: Option<u32>,
+}
+
+/// The syntax tree of `source` as JSON, in the format of `--emit=json-ast`,
+/// or `null` if it doesn't parse.
+#[napi]
+pub fn parse(source: String) -> Option<String> {
+    parser::parse_to_json(&source).0
+}
+
+/// What's wrong with `source`.
+#[napi]
+pub fn diagnostics(source: String) -> Vec<Diagnostic> {
+    let (_, diagnostics) = parser::parse_to_json(&source);
+    diagnostics
+        .into_iter()
+        .map(|diagnostic| Diagnostic {
+            severity: match diagnostic.severity {
+                Severity::Warning => "warning",
+                Severity::Error => "error",
+            }
+            .to_string(),
+            message: diagnostic.message,
+            start: diagnostic.span.map(|span| span.start),
+            end: diagnostic.span.map(|span| span.end),
+        })
+        .collect()
+}
+
+/// What's wrong with `source` as `radi` prints it, with the lines that each
+/// diagnostic points at, which are said to be in the file at `path`.
+#[napi]
+pub fn format_diagnostics(source: String, path: Option<String>) -> String {
+    let mut source_map = SourceMap::new();
+    let path = PathBuf::from(path.unwrap_or_else(|| "<input>".to_string()));
+    // the first file starts at offset zero, where `parse_str` puts it
+    let file = source_map.add(path, source);
+
+    let storage = StringStorage::new();
+    let errors = ErrorStream::buffered();
+    if let Err(err) = parser::parse_str(&source_map.file(file).src, &storage, &errors) {
+        errors.error(err);
+    }
+    errors
+        .take_diagnostics()
+        .iter()
+        .map(|diagnostic| errors.format(diagnostic, Some(&source_map), false))
+        .collect()
+}
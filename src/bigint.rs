@@ -0,0 +1,232 @@
+//! Arbitrary-precision integers, for the interpreter and for the literals
+//! that backends embed.
+//!
+//! A [BigInt] is a sign and a magnitude of 32-bit limbs, least significant
+//! first, with no leading zero limbs. Zero has no limbs and is never
+//! negative. The runtimes of the compiled backends use the same layout and
+//! the same simple algorithms: schoolbook multiplication, and division one
+//! bit at a time.
+
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display, Formatter},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn from_i64(i: i64) -> BigInt {
+        let magnitude = i.unsigned_abs();
+        BigInt::new(i < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+
+    /// Parses a decimal number with an optional leading `-`.
+    pub fn parse(s: &str) -> Option<BigInt> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let mut limbs = Vec::new();
+        for digit in digits.bytes() {
+            let mut carry = (digit - b'0') as u64;
+            for limb in limbs.iter_mut() {
+                let t = *limb as u64 * 10 + carry;
+                *limb = t as u32;
+                carry = t >> 32;
+            }
+            if carry != 0 {
+                limbs.push(carry as u32);
+            }
+        }
+        Some(BigInt::new(negative, limbs))
+    }
+
+    fn new(negative: bool, mut limbs: Vec<u32>) -> BigInt {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigInt {
+            negative: negative && !limbs.is_empty(),
+            limbs,
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn limbs(&self) -> &[u32] {
+        &self.limbs
+    }
+
+    /// Returns the value if it fits in an `i64`.
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.limbs.len() > 2 {
+            return None;
+        }
+        let magnitude = self
+            .limbs
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &limb| acc << 32 | limb as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    pub fn neg(&self) -> BigInt {
+        BigInt::new(!self.negative, self.limbs.clone())
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add(&self.limbs, &other.limbs));
+        }
+        match compare(&self.limbs, &other.limbs) {
+            Ordering::Less => BigInt::new(other.negative, sub(&other.limbs, &self.limbs)),
+            _ => BigInt::new(self.negative, sub(&self.limbs, &other.limbs)),
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> BigInt {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        let mut limbs = vec![0; self.limbs.len() + other.limbs.len()];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let t = limbs[i + j] as u64 + a as u64 * b as u64 + carry;
+                limbs[i + j] = t as u32;
+                carry = t >> 32;
+            }
+            limbs[i + other.limbs.len()] = carry as u32;
+        }
+        BigInt::new(self.negative != other.negative, limbs)
+    }
+
+    /// Divides, truncating towards zero, and returns the quotient and the
+    /// remainder, which has the sign of `self`. Returns `None` when
+    /// dividing by zero.
+    pub fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.limbs.is_empty() {
+            return None;
+        }
+
+        let mut quotient = vec![0; self.limbs.len()];
+        let mut remainder = vec![0; other.limbs.len() + 1];
+        for bit in (0..self.limbs.len() * 32).rev() {
+            let mut carry = self.limbs[bit / 32] >> (bit % 32) & 1;
+            for limb in remainder.iter_mut() {
+                let next = *limb >> 31;
+                *limb = *limb << 1 | carry;
+                carry = next;
+            }
+            if compare(&remainder, &other.limbs) != Ordering::Less {
+                remainder = sub(&remainder, &other.limbs);
+                quotient[bit / 32] |= 1 << (bit % 32);
+            }
+        }
+
+        Some((
+            BigInt::new(self.negative != other.negative, quotient),
+            BigInt::new(self.negative, remainder),
+        ))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare(&self.limbs, &other.limbs),
+            (true, true) => compare(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Writes the number in decimal, nine digits at a time.
+impl Display for BigInt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut limbs = self.limbs.clone();
+        let mut chunks = Vec::new();
+        while !limbs.is_empty() {
+            let mut remainder = 0u64;
+            for limb in limbs.iter_mut().rev() {
+                let t = remainder << 32 | *limb as u64;
+                *limb = (t / 1_000_000_000) as u32;
+                remainder = t % 1_000_000_000;
+            }
+            chunks.push(remainder);
+            while limbs.last() == Some(&0) {
+                limbs.pop();
+            }
+        }
+
+        if self.negative {
+            write!(f, "-")?;
+        }
+        match chunks.split_last() {
+            None => write!(f, "0"),
+            Some((first, rest)) => {
+                write!(f, "{first}")?;
+                rest.iter()
+                    .rev()
+                    .try_for_each(|chunk| write!(f, "{chunk:09}"))
+            }
+        }
+    }
+}
+
+/// Compares magnitudes, which may have leading zero limbs.
+fn compare(a: &[u32], b: &[u32]) -> Ordering {
+    let len = a.len().max(b.len());
+    let limb = |limbs: &[u32], i: usize| limbs.get(i).copied().unwrap_or(0);
+    (0..len)
+        .rev()
+        .map(|i| limb(a, i).cmp(&limb(b, i)))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+fn add(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut limbs = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let t = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        limbs.push(t as u32);
+        carry = t >> 32;
+    }
+    limbs.push(carry as u32);
+    limbs
+}
+
+/// Subtracts magnitudes, where `a` is at least `b`.
+fn sub(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut limbs = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &limb) in a.iter().enumerate() {
+        let t = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        limbs.push(t as u32);
+        borrow = (t < 0) as i64;
+    }
+    limbs
+}
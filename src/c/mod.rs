@@ -0,0 +1,613 @@
+//! Translates the HIR to portable C99, for platforms that other backends
+//! don't support.
+//!
+//! Values are pointers to the `Value` struct defined by the runtime in
+//! `runtime.c`, which is copied to the start of every program. Literals are
+//! static, and everything else is allocated with `malloc` and never freed.
+//! Each lambda becomes a function taking its closure and its argument, and
+//! top-level defs become static variables.
+//!
+//! Since C doesn't specify the order that function arguments are evaluated
+//! in, every intermediate value is stored in its own variable first. The
+//! output has to be linked with the math library, as in `cc out.c -lm`.
+//!
+//! Each statement is preceded by a `#line` directive when it comes from a
+//! different line of radi source than the one before, so that C compilers,
+//! debuggers and profilers point at the radi code rather than at the C.
+//!
+//! An `@extern` function is wrapped in a closure that converts its arguments
+//! and result. Its prototype is bound to the C symbol with an assembler
+//! label, which GCC and Clang support, so that the prototype may differ from
+//! one in a standard header.
+use std::{fmt::Write, rc::Rc};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    bigint::BigInt,
+    hir::{
+        Arm, ArmPattern, Expr, ExprKind, Extern, ExternType, Group, Literal, Num, Prim, Program,
+        VarId,
+    },
+    source_map::{FileId, Lines, SourceMap},
+    tokenizer::Span,
+};
+
+const RUNTIME: &str = include_str!("runtime.c");
+
+/// Translates a program to a C source file. `main` is called with `()`
+/// after the top-level expressions are evaluated. The `#line` directives
+/// name the files of `source_map` by their paths.
+pub fn compile(program: &Program, main: Option<VarId>, source_map: &SourceMap) -> String {
+    let mut codegen = Codegen {
+        lines: Lines::new(source_map),
+        files: FxHashMap::default(),
+        statics: String::new(),
+        prototypes: String::new(),
+        functions: String::new(),
+        strings: FxHashMap::default(),
+        externs: FxHashMap::default(),
+        globals: FxHashSet::default(),
+        next_static: 0,
+        next_lambda: 0,
+    };
+    for (var, _) in program.globals.iter().flat_map(|g| g.bindings.iter()) {
+        codegen.globals.insert(*var);
+        writeln!(codegen.statics, "static Value *{} = &rt_unit;", name(*var)).unwrap();
+    }
+
+    let mut f = Function::default();
+    for group in program.globals.iter() {
+        codegen.group(&mut f, group);
+    }
+    for expr in program.init.iter() {
+        let value = codegen.expr(&mut f, expr);
+        f.line(format!("(void){value};"));
+    }
+    if let Some(main) = main {
+        writeln!(f.body, "    rt_apply({}, &rt_unit);", name(main)).unwrap();
+    }
+
+    let mut out = String::from(RUNTIME);
+    out.push('\n');
+    out.push_str(&codegen.statics);
+    out.push('\n');
+    out.push_str(&codegen.prototypes);
+    out.push('\n');
+    out.push_str(&codegen.functions);
+    out.push_str("int main(void) {\n");
+    out.push_str(&f.body);
+    out.push_str("    return 0;\n}\n");
+    out
+}
+
+fn name(var: VarId) -> String {
+    format!("v{}", var.0)
+}
+
+/// Writes a string as a C string literal. Everything but printable ASCII
+/// is escaped in octal, which unlike hex escapes can't run into the next
+/// character.
+fn literal(s: &str) -> String {
+    let mut out = String::from("\"");
+    for b in s.bytes() {
+        match b {
+            b'"' | b'\\' => write!(out, "\\{}", b as char).unwrap(),
+            // avoid forming trigraphs
+            b'?' => out.push_str("\\?"),
+            0x20..=0x7e => out.push(b as char),
+            _ => write!(out, "\\{b:03o}").unwrap(),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A line of radi source, as the line number and the file name literal of a
+/// `#line` directive.
+type Position = (usize, Rc<str>);
+
+/// The body of a function being generated.
+#[derive(Default)]
+struct Function {
+    body: String,
+    indent: usize,
+    temps: usize,
+    /// The position of the expression that lines are being written for.
+    at: Option<Position>,
+    /// The position that C compilers give the next line of the body, once a
+    /// `#line` directive has set one.
+    next: Option<Position>,
+}
+
+impl Function {
+    fn line(&mut self, line: impl AsRef<str>) {
+        if self.at.is_some() && self.at != self.next {
+            let (number, file) = self.at.clone().unwrap();
+            // the file name can be left out when it's the one already set
+            match &self.next {
+                Some((_, next)) if Rc::ptr_eq(next, &file) => {
+                    writeln!(self.body, "#line {number}").unwrap()
+                }
+                _ => writeln!(self.body, "#line {number} {file}").unwrap(),
+            }
+            self.next = self.at.clone();
+        }
+        if let Some((number, _)) = &mut self.next {
+            *number += 1;
+        }
+        for _ in 0..self.indent + 1 {
+            self.body.push_str("    ");
+        }
+        self.body.push_str(line.as_ref());
+        self.body.push('\n');
+    }
+
+    /// Declares the variable for `var`. Unused variables are cast to `void`
+    /// to keep C compilers from warning about them.
+    fn declare(&mut self, var: VarId, value: impl AsRef<str>) {
+        let var = name(var);
+        self.line(format!("Value *{var} = {}; (void){var};", value.as_ref()));
+    }
+
+    /// Stores a value in a new variable, returning its name.
+    fn temp(&mut self, value: impl AsRef<str>) -> String {
+        let temp = format!("t{}", self.temps);
+        self.temps += 1;
+        self.line(format!("Value *{temp} = {};", value.as_ref()));
+        temp
+    }
+}
+
+struct Codegen<'a> {
+    lines: Lines<'a>,
+    /// The `#line` literal of each file that code has come from.
+    files: FxHashMap<FileId, Rc<str>>,
+    statics: String,
+    prototypes: String,
+    functions: String,
+    strings: FxHashMap<String, String>,
+    /// The wrapper function of each extern, by its name and C types.
+    externs: FxHashMap<String, String>,
+    /// The top-level defs, which are static variables.
+    globals: FxHashSet<VarId>,
+    next_static: usize,
+    next_lambda: usize,
+}
+
+impl Codegen<'_> {
+    /// Adds a static value, returning its address.
+    fn static_value(&mut self, tag: &str, init: String) -> String {
+        let name = format!("lit{}", self.next_static);
+        self.next_static += 1;
+        writeln!(
+            self.statics,
+            "static Value {name} = {{ {tag}, {{ {init} }} }};"
+        )
+        .unwrap();
+        format!("(&{name})")
+    }
+
+    fn string(&mut self, s: &str) -> String {
+        if let Some(address) = self.strings.get(s) {
+            return address.clone();
+        }
+        let init = format!(".s = {{ {}, {} }}", s.len(), literal(s));
+        let address = self.static_value("STRING", init);
+        self.strings.insert(s.to_string(), address.clone());
+        address
+    }
+
+    /// A static big integer, with its limbs in an array of their own.
+    fn big_int(&mut self, digits: &str) -> String {
+        let value = BigInt::parse(digits).unwrap();
+        let limbs = if value.limbs().is_empty() {
+            "NULL".to_string()
+        } else {
+            let limbs = format!("limbs{}", self.next_static);
+            let items: Vec<_> = value
+                .limbs()
+                .iter()
+                .map(|limb| format!("{limb}u"))
+                .collect();
+            writeln!(
+                self.statics,
+                "static const uint32_t {limbs}[] = {{ {} }};",
+                items.join(", ")
+            )
+            .unwrap();
+            limbs
+        };
+        let init = format!(
+            ".big = {{ {}, {}, {limbs} }}",
+            value.limbs().len(),
+            value.is_negative() as u8
+        );
+        self.static_value("BIGINT", init)
+    }
+
+    /// The line of radi source that `span` starts on.
+    fn position(&mut self, span: Span) -> Position {
+        let (file, line, _) = self.lines.position(span.start);
+        let map = self.lines.map();
+        let name = self
+            .files
+            .entry(file)
+            .or_insert_with(|| literal(&map.file(file).path.to_string_lossy()).into());
+        (line + 1, name.clone())
+    }
+
+    /// Generates the statements that compute `expr`, returning a C
+    /// expression for its value that has no side effects.
+    fn expr(&mut self, f: &mut Function, expr: &Expr) -> String {
+        let at = Some(self.position(expr.span));
+        let outer = std::mem::replace(&mut f.at, at);
+        let value = self.expr_kind(f, expr);
+        f.at = outer;
+        value
+    }
+
+    fn expr_kind(&mut self, f: &mut Function, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Literal(lit) => match *lit {
+                // `-9223372036854775808` is the negation of a constant too
+                // large for an int64_t
+                Literal::Int(i64::MIN) => self.static_value("INT", ".i = INT64_MIN".to_string()),
+                Literal::Int(i) => self.static_value("INT", format!(".i = INT64_C({i})")),
+                Literal::Float(x) => self.static_value("FLOAT", format!(".f = {x:?}")),
+                Literal::BigInt(digits) => self.big_int(digits.0),
+                Literal::String(s) => self.string(s.0),
+                Literal::Bool(true) => "(&rt_true)".to_string(),
+                Literal::Bool(false) => "(&rt_false)".to_string(),
+                Literal::Unit => "(&rt_unit)".to_string(),
+            },
+            ExprKind::Var(var) => name(*var),
+            ExprKind::Builtin(builtin) => {
+                let id = builtin.name().to_uppercase();
+                let init = format!(".builtin = {{ {id}, {} }}", literal(builtin.name()));
+                self.static_value("BUILTIN", init)
+            }
+            ExprKind::Extern(external) => {
+                let wrapper = self.external(external);
+                f.temp(format!("rt_closure({wrapper}, 0)"))
+            }
+            ExprKind::Lambda { .. } => {
+                let closure = self.closure(f, expr);
+                self.fill(f, &closure, expr);
+                closure
+            }
+            ExprKind::Apply { func, arg, .. } => {
+                let func = self.expr(f, func);
+                let arg = self.expr(f, arg);
+                f.temp(format!("rt_apply({func}, {arg})"))
+            }
+            ExprKind::Let { var, value, body } => {
+                let value = self.expr(f, value);
+                f.declare(*var, value);
+                self.expr(f, body)
+            }
+            ExprKind::LetRec { group, body } => {
+                self.group(f, group);
+                self.expr(f, body)
+            }
+            ExprKind::Case { scrutinee, arms } => {
+                let scrutinee = self.expr(f, scrutinee);
+                let result = format!("t{}", f.temps);
+                f.temps += 1;
+                f.line(format!("Value *{result};"));
+                self.arms(f, &scrutinee, &result, arms);
+                result
+            }
+            ExprKind::Seq(items) => {
+                let mut result = "(&rt_unit)".to_string();
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.line(format!("(void){result};"));
+                    }
+                    result = self.expr(f, item);
+                }
+                result
+            }
+            ExprKind::Tuple(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.expr(f, item))
+                    .collect::<Vec<_>>();
+                let tuple = f.temp(format!("rt_tuple({})", items.len()));
+                for (i, item) in items.iter().enumerate() {
+                    f.line(format!("{tuple}->as.t.items[{i}] = {item};"));
+                }
+                tuple
+            }
+            ExprKind::Project { tuple, index } => {
+                let tuple = self.expr(f, tuple);
+                format!("{tuple}->as.t.items[{index}]")
+            }
+            ExprKind::Record(fields) => {
+                let values = fields
+                    .iter()
+                    .map(|(_, value)| self.expr(f, value))
+                    .collect::<Vec<_>>();
+                let record = f.temp(format!("rt_record({})", fields.len()));
+                for (i, ((name, _), value)) in fields.iter().zip(values).enumerate() {
+                    f.line(format!("{record}->as.r.names[{i}] = {};", literal(name.0)));
+                    f.line(format!("{record}->as.r.values[{i}] = {value};"));
+                }
+                record
+            }
+            ExprKind::Field { record, name } => {
+                let record = self.expr(f, record);
+                f.temp(format!("rt_field({record}, {})", literal(name.0)))
+            }
+            ExprKind::Variant { name, payload } => {
+                let payload = self.expr(f, payload);
+                f.temp(format!("rt_variant({}, {payload})", literal(name.0)))
+            }
+            ExprKind::NewRef(value) => {
+                let value = self.expr(f, value);
+                f.temp(format!("rt_ref({value})"))
+            }
+            ExprKind::Load(cell) => {
+                let cell = self.expr(f, cell);
+                f.temp(format!("{cell}->as.ref"))
+            }
+            ExprKind::Store { cell, value } => {
+                let cell = self.expr(f, cell);
+                let value = self.expr(f, value);
+                f.line(format!("{cell}->as.ref = {value};"));
+                "(&rt_unit)".to_string()
+            }
+            ExprKind::Prim { op, args } => {
+                let args = args.iter().map(|arg| self.expr(f, arg)).collect::<Vec<_>>();
+                f.temp(prim(*op, &args))
+            }
+        }
+    }
+
+    /// Declares an extern and generates the function that calls it with
+    /// the items of a closure's argument, returning the function's name.
+    fn external(&mut self, external: &Extern) -> String {
+        let params = match external.params.len() {
+            0 => "void".to_string(),
+            _ => external
+                .params
+                .iter()
+                .map(|&ty| c_type(ty))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        let ret = c_type(external.ret);
+        let space = if ret.ends_with('*') { "" } else { " " };
+        let key = format!("{ret}{space}{}({params})", external.name.0);
+        if let Some(wrapper) = self.externs.get(&key) {
+            return wrapper.clone();
+        }
+
+        let id = self.externs.len();
+        let symbol = literal(external.name.0);
+        writeln!(
+            self.prototypes,
+            "extern {ret}{space}c{id}({params}) __asm__(RT_SYMBOL({symbol}));"
+        )
+        .unwrap();
+
+        let args = external
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, &ty)| {
+                let arg = match external.params.len() {
+                    1 => "arg".to_string(),
+                    _ => format!("arg->as.t.items[{i}]"),
+                };
+                match ty {
+                    ExternType::Int => format!("{arg}->as.i"),
+                    ExternType::Float => format!("{arg}->as.f"),
+                    ExternType::String => format!("rt_to_c_string({arg})"),
+                    ExternType::Bool => format!("{arg}->as.b"),
+                    ExternType::Unit => unreachable!("unit parameter"),
+                }
+            })
+            .collect::<Vec<_>>();
+        let call = format!("c{id}({})", args.join(", "));
+        let body = match external.ret {
+            ExternType::Int => format!("return rt_int({call});"),
+            ExternType::Float => format!("return rt_float({call});"),
+            ExternType::String => format!("return rt_from_c_string({call});"),
+            ExternType::Bool => format!("return rt_bool({call});"),
+            ExternType::Unit => format!("{call};\n    return &rt_unit;"),
+        };
+
+        let wrapper = format!("extern{id}");
+        let signature = format!("static Value *{wrapper}(Value *env, Value *arg)");
+        writeln!(self.prototypes, "{signature};").unwrap();
+        writeln!(
+            self.functions,
+            "{signature} {{\n    (void)env, (void)arg;\n    {body}\n}}\n"
+        )
+        .unwrap();
+        self.externs.insert(key, wrapper.clone());
+        wrapper
+    }
+
+    fn group(&mut self, f: &mut Function, group: &Group) {
+        let global = |this: &Self, var: VarId| this.globals.contains(&var);
+        if !group.recursive {
+            for (var, value) in group.bindings.iter() {
+                let value = self.expr(f, value);
+                if global(self, *var) {
+                    f.line(format!("{} = {value};", name(*var)));
+                } else {
+                    f.declare(*var, value);
+                }
+            }
+            return;
+        }
+
+        // the closures are created before any of them captures anything,
+        // so that they can capture each other
+        for (var, _) in group.bindings.iter() {
+            if !global(self, *var) {
+                f.declare(*var, "&rt_unit");
+            }
+        }
+        let mut closures = Vec::new();
+        for (var, value) in group.bindings.iter() {
+            if let ExprKind::Lambda { .. } = value.kind {
+                let closure = self.closure(f, value);
+                f.line(format!("{} = {closure};", name(*var)));
+                closures.push(closure);
+            }
+        }
+        let mut closures = closures.into_iter();
+        for (var, value) in group.bindings.iter() {
+            match value.kind {
+                ExprKind::Lambda { .. } => self.fill(f, &closures.next().unwrap(), value),
+                _ => {
+                    let value = self.expr(f, value);
+                    f.line(format!("{} = {value};", name(*var)));
+                }
+            }
+        }
+    }
+
+    /// The variables a lambda captures in its closure. Top-level defs are
+    /// static, so they don't need to be captured.
+    fn captures(&self, lambda: &Expr) -> Vec<VarId> {
+        let ExprKind::Lambda { captures, .. } = &lambda.kind else {
+            unreachable!()
+        };
+        captures
+            .iter()
+            .copied()
+            .filter(|var| !self.globals.contains(var))
+            .collect()
+    }
+
+    /// Generates the function for a lambda and allocates a closure for it.
+    /// Its captures are stored separately by [Codegen::fill].
+    fn closure(&mut self, f: &mut Function, lambda: &Expr) -> String {
+        let ExprKind::Lambda { param, body, .. } = &lambda.kind else {
+            unreachable!()
+        };
+        let captures = self.captures(lambda);
+
+        let func = format!("lambda{}", self.next_lambda);
+        self.next_lambda += 1;
+        let signature = format!("static Value *{func}(Value *env, Value *{})", name(*param));
+        writeln!(self.prototypes, "{signature};").unwrap();
+
+        let mut g = Function {
+            at: Some(self.position(lambda.span)),
+            ..Function::default()
+        };
+        g.line(format!("(void)env, (void){};", name(*param)));
+        for (i, var) in captures.iter().enumerate() {
+            g.declare(*var, format!("env->as.c.captures[{i}]"));
+        }
+        let result = self.expr(&mut g, body);
+        g.line(format!("return {result};"));
+        writeln!(self.functions, "{signature} {{\n{}}}\n", g.body).unwrap();
+
+        f.temp(format!("rt_closure({func}, {})", captures.len()))
+    }
+
+    /// Stores the captured values of a lambda in its closure.
+    fn fill(&mut self, f: &mut Function, closure: &str, lambda: &Expr) {
+        for (i, var) in self.captures(lambda).into_iter().enumerate() {
+            f.line(format!("{closure}->as.c.captures[{i}] = {};", name(var)));
+        }
+    }
+
+    /// Generates the arms of a case, assigning the value of the arm that
+    /// matches to `result`.
+    fn arms(&mut self, f: &mut Function, scrutinee: &str, result: &str, arms: &[Arm]) {
+        let Some((arm, rest)) = arms.split_first() else {
+            // the type checker makes sure some arm matches
+            f.line("abort();");
+            return;
+        };
+
+        let condition = match &arm.pattern {
+            ArmPattern::Wildcard => {
+                let value = self.expr(f, &arm.body);
+                f.line(format!("{result} = {value};"));
+                return;
+            }
+            ArmPattern::Bool(true) => format!("{scrutinee}->as.b"),
+            ArmPattern::Bool(false) => format!("!{scrutinee}->as.b"),
+            ArmPattern::Variant { name, .. } => {
+                format!("strcmp({scrutinee}->as.v.name, {}) == 0", literal(name.0))
+            }
+        };
+        f.line(format!("if ({condition}) {{"));
+        f.indent += 1;
+        if let ArmPattern::Variant {
+            bind: Some(bind), ..
+        } = arm.pattern
+        {
+            f.declare(bind, format!("{scrutinee}->as.v.payload"));
+        }
+        let value = self.expr(f, &arm.body);
+        f.line(format!("{result} = {value};"));
+        f.indent -= 1;
+        f.line("} else {");
+        f.indent += 1;
+        self.arms(f, scrutinee, result, rest);
+        f.indent -= 1;
+        f.line("}");
+    }
+}
+
+/// A C expression applying an operation to arguments without side effects.
+fn prim(op: Prim, args: &[String]) -> String {
+    let binary =
+        |op: &str, field: &str| format!("{}->as.{field} {op} {}->as.{field}", args[0], args[1]);
+    match op {
+        Prim::Add(Num::Int) => format!("rt_int(rt_add_int({}))", int_args(args)),
+        Prim::Sub(Num::Int) => format!("rt_int(rt_sub_int({}))", int_args(args)),
+        Prim::Mul(Num::Int) => format!("rt_int(rt_mul_int({}))", int_args(args)),
+        Prim::Div(Num::Int) => format!("rt_int(rt_div_int({}))", int_args(args)),
+        Prim::Mod(Num::Int) => format!("rt_int(rt_rem_int({}))", int_args(args)),
+        Prim::Add(Num::Float) => format!("rt_float({})", binary("+", "f")),
+        Prim::Sub(Num::Float) => format!("rt_float({})", binary("-", "f")),
+        Prim::Mul(Num::Float) => format!("rt_float({})", binary("*", "f")),
+        Prim::Div(Num::Float) => format!("rt_float({})", binary("/", "f")),
+        Prim::Mod(Num::Float) => format!("rt_float(fmod({}->as.f, {}->as.f))", args[0], args[1]),
+        Prim::Add(Num::BigInt) => format!("rt_big_add({}, {}, 0)", args[0], args[1]),
+        Prim::Sub(Num::BigInt) => format!("rt_big_add({}, {}, 1)", args[0], args[1]),
+        Prim::Mul(Num::BigInt) => format!("rt_big_mul({}, {})", args[0], args[1]),
+        Prim::Div(Num::BigInt) => format!("rt_big_divmod({}, {}, 0)", args[0], args[1]),
+        Prim::Mod(Num::BigInt) => format!("rt_big_divmod({}, {}, 1)", args[0], args[1]),
+        Prim::Lt(num) => compare("<", num, args),
+        Prim::LtEq(num) => compare("<=", num, args),
+        Prim::Gt(num) => compare(">", num, args),
+        Prim::GtEq(num) => compare(">=", num, args),
+        Prim::Concat => format!("rt_concat({}, {})", args[0], args[1]),
+        Prim::Eq => format!("rt_bool(rt_equals({}, {}))", args[0], args[1]),
+        Prim::NotEq => format!("rt_bool(!rt_equals({}, {}))", args[0], args[1]),
+        Prim::Not => format!("rt_bool(!{}->as.b)", args[0]),
+    }
+}
+
+fn c_type(ty: ExternType) -> &'static str {
+    match ty {
+        ExternType::Int => "int64_t",
+        ExternType::Float => "double",
+        ExternType::String => "const char *",
+        ExternType::Bool => "int",
+        ExternType::Unit => "void",
+    }
+}
+
+fn int_args(args: &[String]) -> String {
+    format!("{}->as.i, {}->as.i", args[0], args[1])
+}
+
+fn compare(op: &str, num: Num, args: &[String]) -> String {
+    match num {
+        Num::Int => format!("rt_bool({}->as.i {op} {}->as.i)", args[0], args[1]),
+        Num::Float => format!("rt_bool({}->as.f {op} {}->as.f)", args[0], args[1]),
+        Num::BigInt => format!("rt_bool(rt_big_cmp({}, {}) {op} 0)", args[0], args[1]),
+    }
+}
@@ -0,0 +1,503 @@
+/* The runtime that every program compiled to C starts with. Its functions
+ * aren't static so that compilers don't warn about the ones a program
+ * doesn't use. */
+#include <inttypes.h>
+#include <math.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct Value Value;
+typedef Value *(*Code)(Value *env, Value *arg);
+
+enum Tag { INT, FLOAT, BIGINT, STRING, BOOL, UNIT, TUPLE, RECORD, VARIANT, CLOSURE, BUILTIN, REF };
+enum Builtin {
+    PRINT, ADD, SUB, MUL, DIV, MOD, NEG,
+    STRING_LENGTH, SUBSTRING, INT_TO_STRING, TO_FLOAT, TO_INT, SQRT, FLOOR,
+    TO_BIG, BIG_TO_INT, BIG_TO_STRING
+};
+
+struct Value {
+    enum Tag tag;
+    union {
+        int64_t i;
+        double f;
+        int b;
+        struct { size_t len; int neg; const uint32_t *limbs; } big;
+        struct { size_t len; const char *bytes; } s;
+        struct { size_t len; Value **items; } t;
+        struct { size_t len; const char **names; Value **values; } r;
+        struct { const char *name; Value *payload; } v;
+        struct { Code code; size_t len; Value **captures; } c;
+        struct { enum Builtin id; const char *name; } builtin;
+        Value *ref;
+    } as;
+};
+
+/* `@extern` functions are declared under names of their own and bound to
+ * their C symbols with assembler labels, so that their declarations can't
+ * clash with the ones in the headers above. */
+#define RT_STRINGIFY(x) #x
+#define RT_EXPAND(x) RT_STRINGIFY(x)
+#define RT_SYMBOL(name) RT_EXPAND(__USER_LABEL_PREFIX__) name
+
+Value rt_unit = { UNIT, { 0 } };
+Value rt_true = { BOOL, { .b = 1 } };
+Value rt_false = { BOOL, { .b = 0 } };
+
+void rt_fail(const char *error) {
+    fflush(stdout);
+    fprintf(stderr, "RUNTIME ERROR: %s\n", error);
+    exit(1);
+}
+
+/* Nothing is ever freed. */
+void *rt_alloc(size_t size) {
+    void *p = malloc(size ? size : 1);
+    if (!p) rt_fail("OutOfMemory");
+    return p;
+}
+
+Value *rt_new(enum Tag tag) {
+    Value *v = rt_alloc(sizeof(Value));
+    v->tag = tag;
+    return v;
+}
+
+Value *rt_int(int64_t i) {
+    Value *v = rt_new(INT);
+    v->as.i = i;
+    return v;
+}
+
+Value *rt_float(double f) {
+    Value *v = rt_new(FLOAT);
+    v->as.f = f;
+    return v;
+}
+
+Value *rt_bool(int b) {
+    return b ? &rt_true : &rt_false;
+}
+
+Value *rt_tuple(size_t len) {
+    Value *v = rt_new(TUPLE);
+    v->as.t.len = len;
+    v->as.t.items = rt_alloc(len * sizeof(Value *));
+    return v;
+}
+
+Value *rt_record(size_t len) {
+    Value *v = rt_new(RECORD);
+    v->as.r.len = len;
+    v->as.r.names = rt_alloc(len * sizeof(const char *));
+    v->as.r.values = rt_alloc(len * sizeof(Value *));
+    return v;
+}
+
+Value *rt_variant(const char *name, Value *payload) {
+    Value *v = rt_new(VARIANT);
+    v->as.v.name = name;
+    v->as.v.payload = payload;
+    return v;
+}
+
+Value *rt_closure(Code code, size_t len) {
+    Value *v = rt_new(CLOSURE);
+    v->as.c.code = code;
+    v->as.c.len = len;
+    v->as.c.captures = rt_alloc(len * sizeof(Value *));
+    return v;
+}
+
+Value *rt_ref(Value *value) {
+    Value *v = rt_new(REF);
+    v->as.ref = value;
+    return v;
+}
+
+Value *rt_field(Value *record, const char *name) {
+    size_t i;
+    for (i = 0; i < record->as.r.len; i++) {
+        if (strcmp(record->as.r.names[i], name) == 0) return record->as.r.values[i];
+    }
+    abort();
+}
+
+Value *rt_concat(Value *a, Value *b) {
+    Value *v = rt_new(STRING);
+    char *bytes = rt_alloc(a->as.s.len + b->as.s.len);
+    memcpy(bytes, a->as.s.bytes, a->as.s.len);
+    memcpy(bytes + a->as.s.len, b->as.s.bytes, b->as.s.len);
+    v->as.s.len = a->as.s.len + b->as.s.len;
+    v->as.s.bytes = bytes;
+    return v;
+}
+
+/* Copies a string to pass it to C, which expects it to end with a NUL. */
+const char *rt_to_c_string(Value *s) {
+    char *bytes = rt_alloc(s->as.s.len + 1);
+    memcpy(bytes, s->as.s.bytes, s->as.s.len);
+    bytes[s->as.s.len] = '\0';
+    return bytes;
+}
+
+/* Copies a string returned from C, which might reuse its buffer. NULL is
+ * the empty string. */
+Value *rt_from_c_string(const char *s) {
+    Value *v = rt_new(STRING);
+    size_t len = s ? strlen(s) : 0;
+    char *bytes = rt_alloc(len);
+    if (len) memcpy(bytes, s, len);
+    v->as.s.len = len;
+    v->as.s.bytes = bytes;
+    return v;
+}
+
+/* Offsets are in bytes, and are clamped to the string. */
+Value *rt_substring(Value *s, int64_t start, int64_t end) {
+    Value *v = rt_new(STRING);
+    int64_t len = (int64_t)s->as.s.len;
+    if (start < 0) start = 0;
+    if (start > len) start = len;
+    if (end < start) end = start;
+    if (end > len) end = len;
+    v->as.s.len = (size_t)(end - start);
+    v->as.s.bytes = s->as.s.bytes + start;
+    return v;
+}
+
+Value *rt_int_to_string(int64_t i) {
+    Value *v = rt_new(STRING);
+    char *bytes = rt_alloc(21);
+    v->as.s.len = (size_t)snprintf(bytes, 21, "%" PRId64, i);
+    v->as.s.bytes = bytes;
+    return v;
+}
+
+/* Truncates towards zero. NaN fails both comparisons. */
+int64_t rt_to_int(double f) {
+    if (!(f >= -9223372036854775808.0 && f < 9223372036854775808.0)) rt_fail("Overflow");
+    return (int64_t)f;
+}
+
+int64_t rt_add_int(int64_t a, int64_t b) {
+    if ((b > 0 && a > INT64_MAX - b) || (b < 0 && a < INT64_MIN - b)) rt_fail("Overflow");
+    return a + b;
+}
+
+int64_t rt_sub_int(int64_t a, int64_t b) {
+    if ((b < 0 && a > INT64_MAX + b) || (b > 0 && a < INT64_MIN + b)) rt_fail("Overflow");
+    return a - b;
+}
+
+int64_t rt_mul_int(int64_t a, int64_t b) {
+    if (a == -1 && b == INT64_MIN) rt_fail("Overflow");
+    if (b == -1 && a == INT64_MIN) rt_fail("Overflow");
+    if (a != 0 && b != 0 && a != -1 && b != -1) {
+        if (a > 0 ? (b > 0 ? a > INT64_MAX / b : b < INT64_MIN / a)
+                  : (b > 0 ? a < INT64_MIN / b : a < INT64_MAX / b)) {
+            rt_fail("Overflow");
+        }
+    }
+    return a * b;
+}
+
+int64_t rt_div_int(int64_t a, int64_t b) {
+    if (b == 0) rt_fail("DivisionByZero");
+    if (a == INT64_MIN && b == -1) rt_fail("Overflow");
+    return a / b;
+}
+
+int64_t rt_rem_int(int64_t a, int64_t b) {
+    if (b == 0) rt_fail("DivisionByZero");
+    if (a == INT64_MIN && b == -1) rt_fail("Overflow");
+    return a % b;
+}
+
+/* Big integers are a sign and a magnitude of 32-bit limbs, least
+ * significant first, with no leading zero limbs. Zero has no limbs and is
+ * never negative. */
+Value *rt_big(size_t len, int neg, const uint32_t *limbs) {
+    Value *v = rt_new(BIGINT);
+    while (len && !limbs[len - 1]) len--;
+    v->as.big.len = len;
+    v->as.big.neg = neg && len;
+    v->as.big.limbs = limbs;
+    return v;
+}
+
+Value *rt_big_from_int(int64_t i) {
+    uint32_t *limbs = rt_alloc(2 * sizeof(uint32_t));
+    uint64_t m = i < 0 ? -(uint64_t)i : (uint64_t)i;
+    limbs[0] = (uint32_t)m;
+    limbs[1] = (uint32_t)(m >> 32);
+    return rt_big(2, i < 0, limbs);
+}
+
+int64_t rt_big_to_int(Value *v) {
+    uint64_t m = 0;
+    size_t i;
+    if (v->as.big.len > 2) rt_fail("Overflow");
+    for (i = v->as.big.len; i-- > 0;) m = m << 32 | v->as.big.limbs[i];
+    if (!v->as.big.neg) {
+        if (m > (uint64_t)INT64_MAX) rt_fail("Overflow");
+        return (int64_t)m;
+    }
+    if (m > (uint64_t)INT64_MAX + 1) rt_fail("Overflow");
+    return m == (uint64_t)INT64_MAX + 1 ? INT64_MIN : -(int64_t)m;
+}
+
+/* Compares magnitudes, which may have leading zero limbs. */
+int rt_mag_cmp(const uint32_t *a, size_t alen, const uint32_t *b, size_t blen) {
+    size_t i = alen > blen ? alen : blen;
+    while (i-- > 0) {
+        uint32_t x = i < alen ? a[i] : 0, y = i < blen ? b[i] : 0;
+        if (x != y) return x < y ? -1 : 1;
+    }
+    return 0;
+}
+
+/* Subtracts the magnitude `b` from `a` in place, where `a` is at least `b`. */
+void rt_mag_sub(uint32_t *a, size_t alen, const uint32_t *b, size_t blen) {
+    int64_t borrow = 0;
+    size_t i;
+    for (i = 0; i < alen; i++) {
+        int64_t t = (int64_t)a[i] - (int64_t)(i < blen ? b[i] : 0) - borrow;
+        a[i] = (uint32_t)t;
+        borrow = t < 0;
+    }
+}
+
+int rt_big_cmp(Value *a, Value *b) {
+    int c;
+    if (a->as.big.neg != b->as.big.neg) return a->as.big.neg ? -1 : 1;
+    c = rt_mag_cmp(a->as.big.limbs, a->as.big.len, b->as.big.limbs, b->as.big.len);
+    return a->as.big.neg ? -c : c;
+}
+
+/* Adds `b` to `a`, or subtracts it if `flip` is set. */
+Value *rt_big_add(Value *a, Value *b, int flip) {
+    size_t alen = a->as.big.len, blen = b->as.big.len, len = (alen > blen ? alen : blen) + 1, i;
+    int bneg = b->as.big.neg != (flip != 0);
+    uint32_t *limbs = rt_alloc(len * sizeof(uint32_t));
+    Value *t;
+    if (a->as.big.neg == bneg) {
+        uint64_t carry = 0;
+        for (i = 0; i < len; i++) {
+            carry += (uint64_t)(i < alen ? a->as.big.limbs[i] : 0) + (i < blen ? b->as.big.limbs[i] : 0);
+            limbs[i] = (uint32_t)carry;
+            carry >>= 32;
+        }
+        return rt_big(len, bneg, limbs);
+    }
+    /* subtract the smaller magnitude from the larger one, whose sign the
+     * result has */
+    if (rt_mag_cmp(a->as.big.limbs, alen, b->as.big.limbs, blen) < 0) {
+        t = a, a = b, b = t;
+    } else {
+        bneg = a->as.big.neg;
+    }
+    memset(limbs, 0, len * sizeof(uint32_t));
+    memcpy(limbs, a->as.big.limbs, a->as.big.len * sizeof(uint32_t));
+    rt_mag_sub(limbs, len, b->as.big.limbs, b->as.big.len);
+    return rt_big(len, bneg, limbs);
+}
+
+Value *rt_big_mul(Value *a, Value *b) {
+    size_t alen = a->as.big.len, blen = b->as.big.len, i, j;
+    uint32_t *limbs = rt_alloc((alen + blen) * sizeof(uint32_t));
+    memset(limbs, 0, (alen + blen) * sizeof(uint32_t));
+    for (i = 0; i < alen; i++) {
+        uint64_t carry = 0;
+        for (j = 0; j < blen; j++) {
+            carry += limbs[i + j] + (uint64_t)a->as.big.limbs[i] * b->as.big.limbs[j];
+            limbs[i + j] = (uint32_t)carry;
+            carry >>= 32;
+        }
+        limbs[i + blen] = (uint32_t)carry;
+    }
+    return rt_big(alen + blen, a->as.big.neg != b->as.big.neg, limbs);
+}
+
+/* Divides one bit at a time, truncating towards zero, and returns the
+ * quotient or, if `rem` is set, the remainder, which has the sign of `a`. */
+Value *rt_big_divmod(Value *a, Value *b, int rem) {
+    size_t alen = a->as.big.len, blen = b->as.big.len, bit, i;
+    uint32_t *q, *r;
+    if (!blen) rt_fail("DivisionByZero");
+    q = rt_alloc(alen * sizeof(uint32_t));
+    r = rt_alloc((blen + 1) * sizeof(uint32_t));
+    memset(q, 0, alen * sizeof(uint32_t));
+    memset(r, 0, (blen + 1) * sizeof(uint32_t));
+    for (bit = alen * 32; bit-- > 0;) {
+        uint32_t carry = a->as.big.limbs[bit / 32] >> (bit % 32) & 1;
+        for (i = 0; i <= blen; i++) {
+            uint32_t next = r[i] >> 31;
+            r[i] = r[i] << 1 | carry;
+            carry = next;
+        }
+        if (rt_mag_cmp(r, blen + 1, b->as.big.limbs, blen) >= 0) {
+            rt_mag_sub(r, blen + 1, b->as.big.limbs, blen);
+            q[bit / 32] |= (uint32_t)1 << (bit % 32);
+        }
+    }
+    return rem ? rt_big(blen + 1, a->as.big.neg, r) : rt_big(alen, a->as.big.neg != b->as.big.neg, q);
+}
+
+/* Writes the digits from the end, nine at a time. */
+Value *rt_big_to_string(Value *v) {
+    size_t len = v->as.big.len, size = len * 10 + 2, start = size, i;
+    uint32_t *limbs = rt_alloc(len * sizeof(uint32_t));
+    char *bytes = rt_alloc(size);
+    Value *s = rt_new(STRING);
+    int digits;
+    memcpy(limbs, v->as.big.limbs, len * sizeof(uint32_t));
+    do {
+        uint64_t chunk = 0;
+        for (i = len; i-- > 0;) {
+            uint64_t t = chunk << 32 | limbs[i];
+            limbs[i] = (uint32_t)(t / 1000000000);
+            chunk = t % 1000000000;
+        }
+        while (len && !limbs[len - 1]) len--;
+        /* every chunk but the first is padded with zeros */
+        for (digits = 0; digits < 9 && (len || chunk || !digits); digits++) {
+            bytes[--start] = (char)('0' + chunk % 10);
+            chunk /= 10;
+        }
+    } while (len);
+    if (v->as.big.neg) bytes[--start] = '-';
+    s->as.s.len = size - start;
+    s->as.s.bytes = bytes + start;
+    return s;
+}
+
+/* Floats are written with the fewest digits that read back the same. */
+void rt_show_float(double f) {
+    char buf[32];
+    int precision;
+    if (isnan(f)) { printf("NaN"); return; }
+    if (isinf(f)) { printf(f > 0 ? "inf" : "-inf"); return; }
+    for (precision = 1; precision < 17; precision++) {
+        snprintf(buf, sizeof buf, "%.*g", precision, f);
+        if (strtod(buf, NULL) == f) break;
+    }
+    snprintf(buf, sizeof buf, "%.*g", precision, f);
+    printf("%s", buf);
+    if (!strpbrk(buf, ".e")) printf(".0");
+}
+
+void rt_show(Value *v, int nested) {
+    size_t i;
+    switch (v->tag) {
+    case INT: printf("%" PRId64, v->as.i); break;
+    case FLOAT: rt_show_float(v->as.f); break;
+    case BIGINT: rt_show(rt_big_to_string(v), 0); break;
+    case STRING:
+        if (nested) putchar('"');
+        fwrite(v->as.s.bytes, 1, v->as.s.len, stdout);
+        if (nested) putchar('"');
+        break;
+    case BOOL: printf(v->as.b ? "true" : "false"); break;
+    case UNIT: printf("()"); break;
+    case TUPLE:
+        putchar('(');
+        for (i = 0; i < v->as.t.len; i++) {
+            if (i > 0) printf(", ");
+            rt_show(v->as.t.items[i], 1);
+        }
+        putchar(')');
+        break;
+    case RECORD:
+        printf(".{");
+        for (i = 0; i < v->as.r.len; i++) {
+            printf("%s%s: ", i > 0 ? ", " : " ", v->as.r.names[i]);
+            rt_show(v->as.r.values[i], 1);
+        }
+        printf(" }");
+        break;
+    case VARIANT:
+        printf("|%s", v->as.v.name);
+        if (v->as.v.payload->tag != UNIT) {
+            printf(": ");
+            rt_show(v->as.v.payload, 1);
+        }
+        break;
+    case CLOSURE: printf("<function>"); break;
+    case BUILTIN: printf("<builtin %s>", v->as.builtin.name); break;
+    case REF:
+        putchar('^');
+        rt_show(v->as.ref, 1);
+        break;
+    }
+}
+
+/* Functions are never equal, and references are equal if they are the same cell. */
+int rt_equals(Value *a, Value *b) {
+    size_t i;
+    if (a->tag != b->tag) return 0;
+    switch (a->tag) {
+    case INT: return a->as.i == b->as.i;
+    case FLOAT: return a->as.f == b->as.f;
+    case BIGINT: return rt_big_cmp(a, b) == 0;
+    case STRING:
+        return a->as.s.len == b->as.s.len && memcmp(a->as.s.bytes, b->as.s.bytes, a->as.s.len) == 0;
+    case BOOL: return a->as.b == b->as.b;
+    case UNIT: return 1;
+    case TUPLE:
+        for (i = 0; i < a->as.t.len; i++) {
+            if (!rt_equals(a->as.t.items[i], b->as.t.items[i])) return 0;
+        }
+        return 1;
+    case RECORD:
+        for (i = 0; i < a->as.r.len; i++) {
+            if (!rt_equals(a->as.r.values[i], rt_field(b, a->as.r.names[i]))) return 0;
+        }
+        return 1;
+    case VARIANT:
+        return strcmp(a->as.v.name, b->as.v.name) == 0 && rt_equals(a->as.v.payload, b->as.v.payload);
+    case REF: return a == b;
+    default: return 0;
+    }
+}
+
+Value *rt_builtin(enum Builtin id, Value *arg) {
+    int64_t x, y;
+    switch (id) {
+    case PRINT:
+        rt_show(arg, 0);
+        putchar('\n');
+        return &rt_unit;
+    case NEG: return rt_int(rt_sub_int(0, arg->as.i));
+    case STRING_LENGTH: return rt_int((int64_t)arg->as.s.len);
+    case SUBSTRING:
+        return rt_substring(arg->as.t.items[0], arg->as.t.items[1]->as.i, arg->as.t.items[2]->as.i);
+    case INT_TO_STRING: return rt_int_to_string(arg->as.i);
+    case TO_FLOAT: return rt_float((double)arg->as.i);
+    case TO_INT: return rt_int(rt_to_int(arg->as.f));
+    case SQRT: return rt_float(sqrt(arg->as.f));
+    case FLOOR: return rt_float(floor(arg->as.f));
+    case TO_BIG: return rt_big_from_int(arg->as.i);
+    case BIG_TO_INT: return rt_int(rt_big_to_int(arg));
+    case BIG_TO_STRING: return rt_big_to_string(arg);
+    default: break;
+    }
+    /* the arithmetic builtins take a pair of integers */
+    x = arg->as.t.items[0]->as.i;
+    y = arg->as.t.items[1]->as.i;
+    switch (id) {
+    case ADD: return rt_int(rt_add_int(x, y));
+    case SUB: return rt_int(rt_sub_int(x, y));
+    case MUL: return rt_int(rt_mul_int(x, y));
+    case DIV: return rt_int(rt_div_int(x, y));
+    case MOD: return rt_int(rt_rem_int(x, y));
+    default: abort();
+    }
+}
+
+Value *rt_apply(Value *func, Value *arg) {
+    if (func->tag == CLOSURE) return func->as.c.code(func, arg);
+    return rt_builtin(func->as.builtin.id, arg);
+}
@@ -0,0 +1,154 @@
+//! The front end as a C library, for editors and build systems that aren't
+//! written in Rust. It's built with the `capi` feature, as a shared or static
+//! library:
+//!
+//! ```text
+//! cargo rustc --lib --release --features capi --crate-type cdylib
+//! ```
+//!
+//! and declared for C in `include/radi.h`.
+//!
+//! A parse is an opaque [RadiParse] handle that owns everything read from
+//! it, which stays valid until the handle is freed with [radi_parse_free].
+//! Nothing borrows from the source that was parsed, so it can be freed as
+//! soon as [radi_parse] returns.
+
+use std::{
+    ffi::{c_char, CString},
+    ptr, slice,
+};
+
+use crate::{
+    errors::{DiagnosticRecord, Severity},
+    parser,
+};
+
+/// The result of parsing a string of source.
+pub struct RadiParse {
+    /// The syntax tree as JSON, if the source parsed.
+    ast: Option<CString>,
+    diagnostics: Vec<(DiagnosticRecord, CString)>,
+}
+
+/// A diagnostic reported while parsing, as handed out by
+/// [radi_parse_diagnostic].
+#[repr(C)]
+pub struct RadiDiagnostic {
+    /// [RADI_WARNING] or [RADI_ERROR].
+    pub severity: u32,
+    /// The message, as a NUL-terminated string owned by the parse.
+    pub message: *const c_char,
+    /// Whether the diagnostic points at a span of the source. If it doesn't,
+    /// `start` and `end` are zero.
+    pub has_span: bool,
+    /// The byte offsets into the source of where the diagnostic starts and
+    /// ends.
+    pub start: u32,
+    pub end: u32,
+}
+
+pub const RADI_WARNING: u32 = 0;
+pub const RADI_ERROR: u32 = 1;
+
+/// Parses the `len` bytes of UTF-8 source at `src`. Returns a null pointer
+/// if they aren't valid UTF-8.
+///
+/// # Safety
+///
+/// `src` must point to `len` readable bytes, or may be null if `len` is zero.
+#[no_mangle]
+pub unsafe extern "C" fn radi_parse(src: *const u8, len: usize) -> *mut RadiParse {
+    let bytes = if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(src, len)
+    };
+    let Ok(src) = std::str::from_utf8(bytes) else {
+        return ptr::null_mut();
+    };
+
+    let (ast, diagnostics) = parser::parse_to_json(src);
+    let diagnostics = diagnostics
+        .into_iter()
+        .map(|record| {
+            let message = c_string(record.message.clone());
+            (record, message)
+        })
+        .collect();
+
+    Box::into_raw(Box::new(RadiParse {
+        ast: ast.map(c_string),
+        diagnostics,
+    }))
+}
+
+/// Frees a parse, and with it every string read from it.
+///
+/// # Safety
+///
+/// `parse` must have been returned by [radi_parse] and not freed yet, or be
+/// null.
+#[no_mangle]
+pub unsafe extern "C" fn radi_parse_free(parse: *mut RadiParse) {
+    if !parse.is_null() {
+        drop(Box::from_raw(parse));
+    }
+}
+
+/// The syntax tree as JSON, in the format of `--emit=json-ast`, or a null
+/// pointer if the source didn't parse.
+///
+/// # Safety
+///
+/// `parse` must be a live parse returned by [radi_parse].
+#[no_mangle]
+pub unsafe extern "C" fn radi_parse_ast_json(parse: *const RadiParse) -> *const c_char {
+    let parse = &*parse;
+    parse.ast.as_ref().map_or(ptr::null(), |ast| ast.as_ptr())
+}
+
+/// The number of diagnostics reported while parsing.
+///
+/// # Safety
+///
+/// `parse` must be a live parse returned by [radi_parse].
+#[no_mangle]
+pub unsafe extern "C" fn radi_parse_diagnostic_count(parse: *const RadiParse) -> usize {
+    let parse = &*parse;
+    parse.diagnostics.len()
+}
+
+/// Writes the diagnostic at `index` to `out`. Returns whether there is one.
+///
+/// # Safety
+///
+/// `parse` must be a live parse returned by [radi_parse], and `out` must be
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn radi_parse_diagnostic(
+    parse: *const RadiParse,
+    index: usize,
+    out: *mut RadiDiagnostic,
+) -> bool {
+    let parse = &*parse;
+    let Some((record, message)) = parse.diagnostics.get(index) else {
+        return false;
+    };
+    let span = record.span;
+    out.write(RadiDiagnostic {
+        severity: match record.severity {
+            Severity::Warning => RADI_WARNING,
+            Severity::Error => RADI_ERROR,
+        },
+        message: message.as_ptr(),
+        has_span: span.is_some(),
+        start: span.map_or(0, |span| span.start),
+        end: span.map_or(0, |span| span.end),
+    });
+    true
+}
+
+/// A string for C, without the NUL characters that would cut it short.
+fn c_string(string: String) -> CString {
+    CString::new(string.replace('\0', "")).expect("the NUL characters were removed")
+}
@@ -1,8 +1,29 @@
 use std::io::{self, Read};
 
 pub trait CharReader {
-    fn next(&mut self) -> io::Result<Option<(usize, char)>>;
-    fn peek(&mut self) -> io::Result<Option<(usize, char)>>;
+    fn next(&mut self) -> io::Result<Option<(u32, char)>>;
+    fn peek(&mut self) -> io::Result<Option<(u32, char)>>;
+
+    /// The input from index `start` up to `end`, if the reader holds all of
+    /// its input in memory. Readers that stream it return `None`.
+    fn slice(&self, _start: u32, _end: u32) -> Option<&str> {
+        None
+    }
+
+    /// The characters that the reader has ready in memory, from the next one
+    /// on, along with the index of the first of them, so that runs of them
+    /// can be scanned all at once. The chunk is only empty at the end of the
+    /// input. Readers that can't hand out their characters like this return
+    /// `None`, and are read a character at a time.
+    fn chunk(&mut self) -> io::Result<Option<(u32, &str)>> {
+        Ok(None)
+    }
+
+    /// Skips the first `len` bytes of the last chunk, which must end on a
+    /// character boundary.
+    fn consume(&mut self, _len: usize) {
+        unreachable!("only readers that hand out chunks are consumed from")
+    }
 }
 
 pub struct CharReaderSaver<'r, R> {
@@ -31,7 +52,7 @@ impl<'r, R: CharReader> CharReaderSaver<'r, R> {
 }
 
 impl<'r, R: CharReader> CharReader for CharReaderSaver<'r, R> {
-    fn next(&mut self) -> io::Result<Option<(usize, char)>> {
+    fn next(&mut self) -> io::Result<Option<(u32, char)>> {
         if let Some((idx, ch)) = self.chars.next()? {
             self.saved.push(ch);
 
@@ -41,41 +62,128 @@ impl<'r, R: CharReader> CharReader for CharReaderSaver<'r, R> {
         }
     }
 
-    fn peek(&mut self) -> io::Result<Option<(usize, char)>> {
+    fn peek(&mut self) -> io::Result<Option<(u32, char)>> {
         self.chars.peek()
     }
+
+    fn slice(&self, start: u32, end: u32) -> Option<&str> {
+        self.chars.slice(start, end)
+    }
+}
+
+/// Reads a string that is already in memory, which it can hand out slices
+/// of instead of the characters being copied out one at a time.
+pub struct StrCharReader<'a> {
+    src: &'a str,
+    /// The offset into `src` of the next character.
+    pos: usize,
+    /// The index that the first character is reported at.
+    start: u32,
+}
+
+impl<'a> StrCharReader<'a> {
+    pub fn new(src: &'a str) -> StrCharReader<'a> {
+        Self::starting_at(src, 0)
+    }
+
+    /// Creates a reader whose first character is reported at index `start`
+    /// rather than zero.
+    pub fn starting_at(src: &'a str, start: u32) -> StrCharReader<'a> {
+        StrCharReader { src, pos: 0, start }
+    }
+}
+
+impl CharReader for StrCharReader<'_> {
+    fn next(&mut self) -> io::Result<Option<(u32, char)>> {
+        let next = self.peek()?;
+        if let Some((_, ch)) = next {
+            self.pos += ch.len_utf8();
+        }
+        Ok(next)
+    }
+
+    fn peek(&mut self) -> io::Result<Option<(u32, char)>> {
+        let ch = self.src[self.pos..].chars().next();
+        Ok(ch.map(|ch| (self.start + self.pos as u32, ch)))
+    }
+
+    fn slice(&self, start: u32, end: u32) -> Option<&str> {
+        self.src
+            .get((start - self.start) as usize..(end - self.start) as usize)
+    }
+
+    fn chunk(&mut self) -> io::Result<Option<(u32, &str)>> {
+        Ok(Some((self.start + self.pos as u32, &self.src[self.pos..])))
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.pos += len;
+    }
 }
 
-pub struct IoCharReader<const BUF_SIZE: usize, R> {
+/// The size in bytes of the buffer of an [IoCharReader] that isn't sized
+/// for its input.
+pub const DEFAULT_BUF_SIZE: usize = 8 * 1024;
+
+/// The bounds of the size that [IoCharReader::sized_for] gives the buffer.
+/// The smallest size still fits any character, which is at most four bytes.
+const MIN_BUF_SIZE: usize = 64;
+const MAX_BUF_SIZE: usize = 64 * 1024;
+
+/// Reads characters from a stream of UTF-8, a buffer at a time.
+pub struct IoCharReader<R> {
     read: R,
     cursor: usize,
-    buf: [u8; BUF_SIZE],
+    buf: Box<[u8]>,
     len: usize,
     overflow: usize,
-    index: usize,
-    peek: Option<(usize, char)>,
+    index: u32,
+    peek: Option<(u32, char)>,
 }
 
-impl<const BUF_SIZE: usize, R: Read> IoCharReader<BUF_SIZE, R> {
-    pub const fn new(read: R) -> IoCharReader<BUF_SIZE, R> {
+impl<R: Read> IoCharReader<R> {
+    pub fn new(read: R) -> IoCharReader<R> {
+        Self::starting_at(read, 0)
+    }
+
+    /// Creates a reader whose first character is reported at index `start`
+    /// rather than zero.
+    pub fn starting_at(read: R, start: u32) -> IoCharReader<R> {
         IoCharReader {
             read,
             cursor: 0,
-            buf: [0; BUF_SIZE],
+            buf: vec![0; DEFAULT_BUF_SIZE].into(),
             len: 0,
             overflow: 0,
-            index: 0,
+            index: start,
             peek: None,
         }
     }
 
+    /// Gives the reader a buffer of `size` bytes, which must be at least four
+    /// so that any character fits in it.
+    pub fn with_buf_size(mut self, size: usize) -> IoCharReader<R> {
+        assert!(size >= 4, "the buffer must fit any character");
+        self.buf = vec![0; size].into();
+        self
+    }
+
+    /// Sizes the buffer for input that is `len` bytes long: to hold all of
+    /// it if it's small, so that it's read in one go, and to hold only a part
+    /// of it at a time if it's large.
+    pub fn sized_for(self, len: usize) -> IoCharReader<R> {
+        self.with_buf_size(len.clamp(MIN_BUF_SIZE, MAX_BUF_SIZE))
+    }
+
     fn fill_buffer(&mut self) -> io::Result<()> {
         // reset cursor to zero for new buffer
         self.cursor = 0;
         // copy overflow to beginning
         self.buf.copy_within(self.len..self.len + self.overflow, 0);
         // read input
-        self.len = self.read.read(&mut self.buf[self.overflow..])? + self.overflow;
+        let read = self.read.read(&mut self.buf[self.overflow..])?;
+        self.len = read + self.overflow;
+        self.overflow = 0;
 
         if self.len == 0 {
             return Ok(());
@@ -94,17 +202,28 @@ impl<const BUF_SIZE: usize, R: Read> IoCharReader<BUF_SIZE, R> {
                 self.len = e.valid_up_to();
 
                 if self.len == 0 {
-                    panic!("BUF_SIZE is too small!")
+                    return match read {
+                        // the input ends partway through a character
+                        0 => Err(io::Error::from(io::ErrorKind::InvalidData)),
+                        // the rest of the character is yet to be read
+                        _ => self.fill_buffer(),
+                    };
                 }
 
                 Ok(())
             }
         }
     }
+
+    /// The characters in the buffer that haven't been read yet.
+    fn buffered(&self) -> &str {
+        // SAFETY: We verify the validity of the bytes in `fill_buffer`.
+        unsafe { std::str::from_utf8_unchecked(&self.buf[self.cursor..self.len]) }
+    }
 }
 
-impl<const BUF_SIZE: usize, R: Read> CharReader for IoCharReader<BUF_SIZE, R> {
-    fn next(&mut self) -> io::Result<Option<(usize, char)>> {
+impl<R: Read> CharReader for IoCharReader<R> {
+    fn next(&mut self) -> io::Result<Option<(u32, char)>> {
         if let Some(peek) = self.peek.take() {
             return Ok(Some(peek));
         }
@@ -113,21 +232,18 @@ impl<const BUF_SIZE: usize, R: Read> CharReader for IoCharReader<BUF_SIZE, R> {
             self.fill_buffer()?
         }
 
-        // SAFETY: We verify the validity of the bytes in `fill_buffer`.
-        let s = unsafe { std::str::from_utf8_unchecked(&self.buf[self.cursor..self.len]) };
-
-        if let Some(ch) = s.chars().next() {
+        if let Some(ch) = self.buffered().chars().next() {
             self.cursor += ch.len_utf8();
 
             let index = self.index;
-            self.index += ch.len_utf8();
+            self.index += ch.len_utf8() as u32;
             Ok(Some((index, ch)))
         } else {
             Ok(None)
         }
     }
 
-    fn peek(&mut self) -> io::Result<Option<(usize, char)>> {
+    fn peek(&mut self) -> io::Result<Option<(u32, char)>> {
         if let Some(peek) = self.peek {
             Ok(Some(peek))
         } else if let Some(peek) = self.next()? {
@@ -137,4 +253,21 @@ impl<const BUF_SIZE: usize, R: Read> CharReader for IoCharReader<BUF_SIZE, R> {
             Ok(None)
         }
     }
+
+    fn chunk(&mut self) -> io::Result<Option<(u32, &str)>> {
+        // a peeked character is still in the buffer, just before the cursor
+        if let Some((index, ch)) = self.peek.take() {
+            self.cursor -= ch.len_utf8();
+            self.index = index;
+        }
+        if self.cursor >= self.len {
+            self.fill_buffer()?
+        }
+        Ok(Some((self.index, self.buffered())))
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.cursor += len;
+        self.index += len as u32;
+    }
 }
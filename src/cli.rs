@@ -0,0 +1,663 @@
+//! The command line interface, declared with clap. Each subcommand takes the
+//! groups of options below that apply to it, and `radi help <command>`
+//! prints them along with what they do. A path given without a command is
+//! checked, as by `radi check`, which also takes several paths and checks
+//! each of them.
+//!
+//! The commands that compile a file can instead read it from stdin, when
+//! the path is `-`, or take it as the value of `-e`.
+
+use std::io::Write;
+
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Command {
+    #[default]
+    Check,
+    Build,
+    Run,
+    Repl,
+    Serve,
+    Lsp,
+    Fmt,
+    Highlight,
+    Doc,
+    Index,
+    Tags,
+    Rename,
+    Diff,
+    Completions,
+    GenCorpus,
+    GenGrammar,
+}
+
+impl Command {
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::Check => "check",
+            Command::Build => "build",
+            Command::Run => "run",
+            Command::Repl => "repl",
+            Command::Serve => "serve",
+            Command::Lsp => "lsp",
+            Command::Fmt => "fmt",
+            Command::Highlight => "highlight",
+            Command::Doc => "doc",
+            Command::Index => "index",
+            Command::Tags => "tags",
+            Command::Rename => "rename",
+            Command::Diff => "diff",
+            Command::Completions => "completions",
+            Command::GenCorpus => "gen-corpus",
+            Command::GenGrammar => "gen-grammar",
+        }
+    }
+}
+
+/// What was asked for on the command line.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub command: Command,
+    /// The files or projects to work on, or `-` for stdin. Without any, the
+    /// project around the current directory is used. Only `check` takes more
+    /// than one.
+    pub paths: Vec<String>,
+    /// The source given with `-e`, which is compiled instead of a file.
+    pub source: Option<String>,
+    /// Whether the prelude was made available or not with `--prelude` or
+    /// `--no-prelude`, over what the manifest says.
+    pub prelude: Option<bool>,
+    pub opt_level: Option<u8>,
+    pub no_cache: bool,
+    pub watch: bool,
+    pub timings: bool,
+    /// How to print `--stats`, if they were asked for.
+    pub stats: Option<String>,
+    pub dump_hir: bool,
+    /// The passes given to `--disable-pass`, then those given to
+    /// `--enable-pass`.
+    pub toggled_passes: Vec<(String, bool)>,
+    pub fix: bool,
+    pub definition_at: Option<usize>,
+    pub references_at: Option<usize>,
+    pub hover_at: Option<usize>,
+    pub completions_at: Option<usize>,
+    pub semantic_tokens: bool,
+    pub emit: Option<String>,
+    pub target: Option<String>,
+    pub output: Option<String>,
+    pub gc_stats: bool,
+    pub heap_size: Option<usize>,
+    /// Where `run` writes the profile of the program, if it's profiled.
+    pub profile: Option<String>,
+    /// Whether `run` compiles the program to native code instead of
+    /// interpreting it.
+    pub jit: bool,
+    /// Where `serve` listens.
+    pub address: Option<String>,
+    /// How many seconds each program that `serve` runs may take.
+    pub timeout: Option<usize>,
+    pub format: Option<String>,
+    /// For `rename`, the offset of the symbol and its new name.
+    pub rename: Option<(usize, String)>,
+    /// For `completions`, the shell to complete in.
+    pub shell: Option<Shell>,
+    /// For `gen-corpus`, about how many nodes the program should have.
+    pub nodes: Option<usize>,
+}
+
+#[derive(Parser)]
+#[command(
+    name = "radi",
+    about = "Compiles, runs and answers questions about radi programs",
+    after_help = "Without a command, the paths are checked."
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Sub,
+}
+
+#[derive(Subcommand)]
+enum Sub {
+    /// Checks a program for errors, and answers editor queries about it
+    Check(Check),
+    /// Compiles a program to WebAssembly or C
+    Build(Build),
+    /// Runs a program with the interpreter, or natively with `--jit`
+    Run(Run),
+    /// Starts an interactive session
+    Repl {
+        #[command(flatten)]
+        prelude: Prelude,
+        #[command(flatten)]
+        heap: Heap,
+    },
+    /// Serves the compiler over HTTP, for playgrounds and bots
+    Serve {
+        #[command(flatten)]
+        prelude: Prelude,
+        /// How many objects can be alive at once
+        #[arg(long, value_name = "objects")]
+        heap_size: Option<usize>,
+        /// The address and port to listen on
+        #[arg(long, value_name = "address")]
+        address: Option<String>,
+        /// How long each program may take to compile and run
+        #[arg(long, value_name = "seconds")]
+        timeout: Option<usize>,
+    },
+    /// Answers an editor's questions about a program over the Language Server Protocol
+    Lsp,
+    /// Formats files in place (not implemented yet)
+    Fmt {
+        /// The files or projects to format
+        #[arg(value_name = "path")]
+        paths: Vec<String>,
+    },
+    /// Prints a file with its syntax highlighted
+    Highlight {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        compile: Compile,
+        /// How to highlight the file
+        #[arg(long, value_name = "format", value_parser = ["ansi", "html"])]
+        format: Option<String>,
+    },
+    /// Generates the documentation of a program's public defs
+    Doc {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        compile: Compile,
+        /// Where to write the documentation
+        #[arg(short, value_name = "path")]
+        output: Option<String>,
+    },
+    /// Writes an index of a program's symbols for code navigation
+    Index {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        compile: Compile,
+        /// What format to write the index in
+        #[arg(long, value_name = "format", value_parser = ["lsif", "scip"])]
+        format: Option<String>,
+        /// Where to write the index
+        #[arg(short, value_name = "path")]
+        output: Option<String>,
+    },
+    /// Writes a tags file of a program's defs for vim or emacs
+    Tags {
+        /// The file or project to write the tags of, or `-` for stdin
+        #[arg(value_name = "path")]
+        path: Option<String>,
+        #[command(flatten)]
+        compile: Compile,
+        /// Which editor's format to write the tags in
+        #[arg(long, value_name = "format", value_parser = ["ctags", "etags"])]
+        format: Option<String>,
+        /// Where to write the tags, or `-` for stdout
+        #[arg(short, value_name = "path")]
+        output: Option<String>,
+    },
+    /// Renames the symbol at an offset into a file everywhere it's used
+    Rename {
+        /// The file the symbol is in, which is written back renamed
+        #[arg(value_name = "path", value_parser = not_stdin)]
+        path: String,
+        /// The offset of the symbol into the file, in bytes
+        #[arg(value_name = "offset")]
+        offset: usize,
+        /// What to rename the symbol to
+        #[arg(value_name = "new name")]
+        name: String,
+        #[command(flatten)]
+        compile: Compile,
+    },
+    /// Compares the defs of two versions of a file, ignoring formatting
+    Diff {
+        #[arg(value_name = "old")]
+        old: String,
+        #[arg(value_name = "new")]
+        new: String,
+    },
+    /// Prints a script that completes radi's commands in a shell
+    Completions {
+        #[arg(value_name = "shell")]
+        shell: Shell,
+    },
+    /// Generates a large program to benchmark the compiler with
+    GenCorpus {
+        /// About how many nodes of syntax the program should have
+        #[arg(long, value_name = "count")]
+        nodes: Option<usize>,
+        /// Where to write the program
+        #[arg(short, value_name = "path")]
+        output: Option<String>,
+    },
+    /// Generates a TextMate or tree-sitter grammar for editors to highlight radi with
+    GenGrammar {
+        /// Which editors' format to write the grammar in
+        #[arg(long, value_name = "format", value_parser = ["textmate", "tree-sitter"])]
+        format: Option<String>,
+        /// Where to write the grammar
+        #[arg(short, value_name = "path")]
+        output: Option<String>,
+    },
+}
+
+/// What `--emit` can compile the entry file to.
+const EMIT: [&str; 10] = [
+    "tokens",
+    "ast",
+    "ast:dot",
+    "ast:sexp",
+    "json-ast",
+    "ast-stats",
+    "hir",
+    "c",
+    "wasm",
+    "mono-stats",
+];
+
+#[derive(clap::Args)]
+struct Check {
+    /// The files or projects to check, or `-` for stdin
+    #[arg(value_name = "path", conflicts_with = "source")]
+    paths: Vec<String>,
+    /// Compiles the given source instead of a file
+    #[arg(short = 'e', value_name = "source")]
+    source: Option<String>,
+    #[command(flatten)]
+    compile: Compile,
+    #[command(flatten)]
+    optimize: Optimize,
+    /// Applies the fixes suggested by lints
+    #[arg(long, conflicts_with = "source")]
+    fix: bool,
+    /// What to compile the entry file to, written to `-o` or stdout
+    #[arg(long, value_name = "format", value_parser = EMIT)]
+    emit: Option<String>,
+    /// Where to write what `--emit` compiles to, or `-` for stdout
+    #[arg(short, value_name = "path", requires = "emit")]
+    output: Option<String>,
+    /// Prints where the symbol at an offset into the entry file is defined
+    #[arg(long, value_name = "offset")]
+    definition_at: Option<usize>,
+    /// Prints the references to the symbol at an offset
+    #[arg(long, value_name = "offset")]
+    references_at: Option<usize>,
+    /// Prints the type and documentation of the symbol at an offset
+    #[arg(long, value_name = "offset")]
+    hover_at: Option<usize>,
+    /// Prints the names that can be completed at an offset
+    #[arg(long, value_name = "offset")]
+    completions_at: Option<usize>,
+    /// Prints the class of each token in the entry file
+    #[arg(long)]
+    semantic_tokens: bool,
+}
+
+#[derive(clap::Args)]
+struct Build {
+    #[command(flatten)]
+    input: Input,
+    #[command(flatten)]
+    compile: Compile,
+    #[command(flatten)]
+    optimize: Optimize,
+    /// What to compile the entry file to, written to `-o` or stdout
+    #[arg(long, value_name = "format", value_parser = EMIT)]
+    emit: Option<String>,
+    /// The target to compile WebAssembly for
+    #[arg(long, value_name = "target", value_parser = ["wasm32"])]
+    target: Option<String>,
+    /// Where to write the output, or `-` for stdout
+    #[arg(short, value_name = "path")]
+    output: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct Run {
+    #[command(flatten)]
+    input: Input,
+    #[command(flatten)]
+    compile: Compile,
+    #[command(flatten)]
+    optimize: Optimize,
+    #[command(flatten)]
+    heap: Heap,
+    /// Writes how much each stack of calls evaluates, as collapsed stacks for flamegraphs
+    #[arg(long, value_name = "path")]
+    profile: Option<String>,
+    /// Compiles the program to native code and runs that, if radi was built with `jit`
+    #[arg(long, conflicts_with_all = ["profile", "gc_stats", "heap_size"])]
+    jit: bool,
+}
+
+/// The file that a command compiles, when it compiles a single one.
+#[derive(clap::Args)]
+struct Input {
+    /// The file or project to compile, or `-` for stdin
+    #[arg(value_name = "path", conflicts_with = "source")]
+    path: Option<String>,
+    /// Compiles the given source instead of a file
+    #[arg(short = 'e', value_name = "source")]
+    source: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct Prelude {
+    /// Doesn't make the prelude available
+    #[arg(long, overrides_with = "prelude")]
+    no_prelude: bool,
+    /// Makes the prelude available, even if radi.toml says not to
+    #[arg(long, overrides_with = "no_prelude")]
+    prelude: bool,
+}
+
+/// The options of every command that compiles a program.
+#[derive(clap::Args)]
+struct Compile {
+    #[command(flatten)]
+    prelude: Prelude,
+    /// Parses every file instead of using the parse cache
+    #[arg(long)]
+    no_cache: bool,
+    /// Prints the time and allocations of each pass
+    #[arg(long)]
+    timings: bool,
+    /// Prints the time of each pass, the peak memory and the size of the program
+    #[arg(
+        long,
+        value_name = "format",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "table",
+        value_parser = ["table", "json"],
+    )]
+    stats: Option<String>,
+    /// Skips a pass of the compiler
+    #[arg(long, value_name = "pass")]
+    disable_pass: Vec<String>,
+    /// Runs a pass of the compiler that is skipped by default
+    #[arg(long, value_name = "pass")]
+    enable_pass: Vec<String>,
+}
+
+/// The options of the commands that compile a program all the way to code.
+#[derive(clap::Args)]
+struct Optimize {
+    /// How much to optimize the HIR, where 0 doesn't fold constants
+    #[arg(long, value_name = "level", value_parser = clap::value_parser!(u8).range(0..=1))]
+    opt_level: Option<u8>,
+    /// Compiles again whenever a file changes
+    #[arg(long)]
+    watch: bool,
+    /// Prints the HIR once it's optimized
+    #[arg(long)]
+    dump_hir: bool,
+}
+
+/// The options of the interpreter's heap.
+#[derive(clap::Args)]
+struct Heap {
+    /// Prints statistics about the garbage collector at the end
+    #[arg(long)]
+    gc_stats: bool,
+    /// How many objects can be alive at once
+    #[arg(long, value_name = "objects")]
+    heap_size: Option<usize>,
+}
+
+/// Rejects `-` as the path of a file that's written back to.
+fn not_stdin(path: &str) -> Result<String, String> {
+    match path {
+        "-" => Err("the file is written back to, so it can't be stdin".to_string()),
+        _ => Ok(path.to_string()),
+    }
+}
+
+impl Input {
+    fn apply(self, args: &mut Args) {
+        args.paths.extend(self.path);
+        args.source = self.source;
+    }
+}
+
+impl Prelude {
+    fn apply(self, args: &mut Args) {
+        args.prelude = match (self.prelude, self.no_prelude) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        };
+    }
+}
+
+impl Compile {
+    fn apply(self, args: &mut Args) {
+        self.prelude.apply(args);
+        args.no_cache = self.no_cache;
+        args.timings = self.timings;
+        args.stats = self.stats;
+        let disabled = self.disable_pass.into_iter().map(|name| (name, false));
+        let enabled = self.enable_pass.into_iter().map(|name| (name, true));
+        args.toggled_passes = disabled.chain(enabled).collect();
+    }
+}
+
+impl Optimize {
+    fn apply(self, args: &mut Args) {
+        args.opt_level = self.opt_level;
+        args.watch = self.watch;
+        args.dump_hir = self.dump_hir;
+    }
+}
+
+impl Heap {
+    fn apply(self, args: &mut Args) {
+        args.gc_stats = self.gc_stats;
+        args.heap_size = self.heap_size;
+    }
+}
+
+impl From<Sub> for Args {
+    fn from(sub: Sub) -> Args {
+        let mut args = Args::default();
+        match sub {
+            Sub::Check(check) => {
+                args.command = Command::Check;
+                args.paths = check.paths;
+                args.source = check.source;
+                check.compile.apply(&mut args);
+                check.optimize.apply(&mut args);
+                args.fix = check.fix;
+                args.emit = check.emit;
+                args.output = check.output;
+                args.definition_at = check.definition_at;
+                args.references_at = check.references_at;
+                args.hover_at = check.hover_at;
+                args.completions_at = check.completions_at;
+                args.semantic_tokens = check.semantic_tokens;
+            }
+            Sub::Build(build) => {
+                args.command = Command::Build;
+                build.input.apply(&mut args);
+                build.compile.apply(&mut args);
+                build.optimize.apply(&mut args);
+                args.emit = build.emit;
+                args.target = build.target;
+                args.output = build.output;
+            }
+            Sub::Run(run) => {
+                args.command = Command::Run;
+                run.input.apply(&mut args);
+                run.compile.apply(&mut args);
+                run.optimize.apply(&mut args);
+                run.heap.apply(&mut args);
+                args.profile = run.profile;
+                args.jit = run.jit;
+            }
+            Sub::Repl { prelude, heap } => {
+                args.command = Command::Repl;
+                prelude.apply(&mut args);
+                heap.apply(&mut args);
+            }
+            Sub::Serve {
+                prelude,
+                heap_size,
+                address,
+                timeout,
+            } => {
+                args.command = Command::Serve;
+                prelude.apply(&mut args);
+                args.heap_size = heap_size;
+                args.address = address;
+                args.timeout = timeout;
+            }
+            Sub::Lsp => args.command = Command::Lsp,
+            Sub::Fmt { paths } => {
+                args.command = Command::Fmt;
+                args.paths = paths;
+            }
+            Sub::Highlight {
+                input,
+                compile,
+                format,
+            } => {
+                args.command = Command::Highlight;
+                input.apply(&mut args);
+                compile.apply(&mut args);
+                args.format = format;
+            }
+            Sub::Doc {
+                input,
+                compile,
+                output,
+            } => {
+                args.command = Command::Doc;
+                input.apply(&mut args);
+                compile.apply(&mut args);
+                args.output = output;
+            }
+            Sub::Index {
+                input,
+                compile,
+                format,
+                output,
+            } => {
+                args.command = Command::Index;
+                input.apply(&mut args);
+                compile.apply(&mut args);
+                args.format = format;
+                args.output = output;
+            }
+            Sub::Tags {
+                path,
+                compile,
+                format,
+                output,
+            } => {
+                args.command = Command::Tags;
+                args.paths.extend(path);
+                compile.apply(&mut args);
+                args.format = format;
+                args.output = output;
+            }
+            Sub::Rename {
+                path,
+                offset,
+                name,
+                compile,
+            } => {
+                args.command = Command::Rename;
+                args.paths.push(path);
+                args.rename = Some((offset, name));
+                compile.apply(&mut args);
+            }
+            Sub::Diff { old, new } => {
+                args.command = Command::Diff;
+                args.paths = vec![old, new];
+            }
+            Sub::Completions { shell } => {
+                args.command = Command::Completions;
+                args.shell = Some(shell);
+            }
+            Sub::GenCorpus { nodes, output } => {
+                args.command = Command::GenCorpus;
+                args.nodes = nodes;
+                args.output = output;
+            }
+            Sub::GenGrammar { format, output } => {
+                args.command = Command::GenGrammar;
+                args.format = format;
+                args.output = output;
+            }
+        }
+        args
+    }
+}
+
+/// Reads the arguments that radi was run with, without the name of the
+/// program. The error is for clap to print, and is help rather than a
+/// mistake when `--help` was given.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, clap::Error> {
+    let mut args = args.into_iter().peekable();
+    // without a command, the arguments are for `check`
+    let command = Cli::command();
+    let check = match args.peek() {
+        Some(arg) => {
+            let help = ["help", "-h", "--help"].contains(&arg.as_str());
+            !help && command.find_subcommand(arg).is_none()
+        }
+        None => true,
+    };
+    let program = std::iter::once("radi".to_string());
+    let check = check.then(|| "check".to_string());
+    let cli = Cli::try_parse_from(program.chain(check).chain(args))?;
+    let args = Args::from(cli.command);
+
+    let error = |kind, message: String| {
+        let mut command = Cli::command();
+        // names the subcommand `radi check` rather than `check` in usage
+        command.build();
+        let command = command.find_subcommand_mut(args.command.name());
+        command
+            .expect("every command is a subcommand")
+            .error(kind, message)
+    };
+    let stdin = args.paths.iter().any(|path| path == "-");
+    if args.fix && stdin {
+        // fixes are written back to the files they are for
+        let message = "`--fix` can't be given with `-`".to_string();
+        return Err(error(ErrorKind::ArgumentConflict, message));
+    }
+    if args.paths.len() > 1 {
+        // each of these is about a single file
+        let single = [
+            ("-", stdin),
+            ("--watch", args.watch),
+            ("--definition-at", args.definition_at.is_some()),
+            ("--references-at", args.references_at.is_some()),
+            ("--hover-at", args.hover_at.is_some()),
+            ("--completions-at", args.completions_at.is_some()),
+            ("--semantic-tokens", args.semantic_tokens),
+            ("-o", args.output.is_some()),
+        ];
+        if let Some((flag, _)) = single.into_iter().find(|(_, given)| *given) {
+            let message = format!("`{flag}` can't be given with more than one path");
+            return Err(error(ErrorKind::ArgumentConflict, message));
+        }
+    }
+    Ok(args)
+}
+
+/// Writes a script that completes radi's commands, options and their values
+/// in `shell`.
+pub fn completions(shell: Shell, out: &mut impl Write) {
+    clap_complete::generate(shell, &mut Cli::command(), "radi", out);
+}
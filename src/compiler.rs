@@ -0,0 +1,135 @@
+//! The front end of the compiler behind one type, for programs that embed
+//! radi instead of running the `radi` binary.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    errors::ErrorStream,
+    lints::LintConfig,
+    parse_manager::{FileSystem, NoFiles, Overlay, ParseManager, SourceProvider},
+    passes::{self, Artifacts, Context, PassManager},
+    resolver::ResolveOptions,
+    source_map::SourceMap,
+    string_storage::StringStorage,
+};
+
+/// Compiles programs as far as `radi check` does: through name resolution,
+/// type checking and the lints, to the optimized HIR.
+///
+/// Diagnostics are reported to the stream the compiler is made with. With a
+/// buffered stream, they can be taken from it with
+/// [ErrorStream::take_diagnostics] and their spans looked up in
+/// [Compiler::source_map].
+pub struct Compiler<'s> {
+    storage: &'s StringStorage,
+    errors: &'s ErrorStream<'s>,
+    options: ResolveOptions,
+    lints: BTreeMap<String, LintConfig>,
+    /// Whether the modules that programs use are read from disk.
+    files: bool,
+    /// The files of the last compilation.
+    manager: Option<ParseManager<'s>>,
+}
+
+impl<'s> Compiler<'s> {
+    pub fn new(storage: &'s StringStorage, errors: &'s ErrorStream<'s>) -> Compiler<'s> {
+        Compiler {
+            storage,
+            errors,
+            options: ResolveOptions::default(),
+            lints: BTreeMap::new(),
+            files: true,
+            manager: None,
+        }
+    }
+
+    /// Whether programs can use the builtins of the prelude, which they can
+    /// by default.
+    pub fn with_prelude(mut self, prelude: bool) -> Compiler<'s> {
+        self.options.prelude = prelude;
+        self
+    }
+
+    /// Configures the lints by name, as the `[lints]` of a manifest does.
+    pub fn with_lints(mut self, lints: BTreeMap<String, LintConfig>) -> Compiler<'s> {
+        self.lints = lints;
+        self
+    }
+
+    /// Whether source compiled with [Compiler::compile_str] can use modules
+    /// on disk, which it can by default. Without them, it can only use the
+    /// prelude.
+    pub fn with_files(mut self, files: bool) -> Compiler<'s> {
+        self.files = files;
+        self
+    }
+
+    /// Compiles the program whose entry file is at `path`. The modules it
+    /// uses are looked up relative to the directory of the file.
+    pub fn compile(&mut self, path: impl AsRef<Path>) -> Artifacts<'s> {
+        let path = path.as_ref();
+        let manager = ParseManager::new(self.storage, self.errors, root(path));
+        self.run(manager, path)
+    }
+
+    /// Compiles `src` as though it were the contents of the file at `path`.
+    /// The modules it uses are still read from disk, unless the compiler is
+    /// made [without files](Compiler::with_files).
+    pub fn compile_str(&mut self, path: impl AsRef<Path>, src: &str) -> Artifacts<'s> {
+        let path = path.as_ref();
+        let manager = ParseManager::new(self.storage, self.errors, root(path));
+        let manager = match self.files {
+            true => manager.with_sources(overlay(FileSystem, path, src)),
+            false => manager.with_sources(overlay(NoFiles, path, src)),
+        };
+        self.run(manager, path)
+    }
+
+    /// The files of the last compilation, which the spans of its
+    /// diagnostics and artifacts point into.
+    pub fn source_map(&self) -> Option<&SourceMap> {
+        self.manager.as_ref().map(ParseManager::source_map)
+    }
+
+    fn run(&mut self, manager: ParseManager<'s>, path: &Path) -> Artifacts<'s> {
+        let mut passes = PassManager::new();
+        passes.add(passes::Parse {
+            path: path.to_owned(),
+            prelude: self.options.prelude,
+            project: false,
+        });
+        passes.add(passes::Resolve {
+            options: self.options,
+        });
+        passes.add(passes::Effects);
+        passes.add(passes::Typecheck);
+        passes.add(passes::Lints {
+            config: self.lints.clone(),
+        });
+        passes.add(passes::Lower);
+        passes.add(passes::ConstEval);
+        passes.add(passes::Fold);
+
+        let mut cx = Context {
+            storage: self.storage,
+            errors: self.errors,
+            manager: self.manager.insert(manager),
+        };
+        passes.run(&mut cx)
+    }
+}
+
+/// `base` with `src` as the contents of the file at `path`.
+fn overlay<P: SourceProvider>(base: P, path: &Path, src: &str) -> Overlay<P> {
+    let mut sources = Overlay::new(base);
+    sources.insert(path, src.to_string());
+    sources
+}
+
+/// The directory that the modules used by the file at `path` are in.
+fn root(path: &Path) -> PathBuf {
+    path.parent().unwrap_or(Path::new("")).to_owned()
+}
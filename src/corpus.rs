@@ -0,0 +1,122 @@
+//! Generates large programs to measure the compiler against, for `radi
+//! gen-corpus` and the benchmarks. The same number of nodes always gives the
+//! same program, so timings taken on different versions of the compiler are
+//! of the same work.
+
+use std::fmt::Write;
+
+/// The state that the random number generator starts in.
+const SEED: u64 = 0x5eed_5eed_5eed_5eed;
+
+/// Generates a program of about `nodes` nodes of syntax. It's a list of
+/// functions from two Ints to an Int, each of which only calls those before
+/// it, so that the program resolves and typechecks without errors.
+pub fn generate(nodes: usize) -> String {
+    let mut generator = Generator {
+        out: String::new(),
+        state: SEED,
+        nodes: 1,
+        defs: 0,
+    };
+    writeln!(
+        generator.out,
+        "// Generated by `radi gen-corpus --nodes {nodes}`."
+    )
+    .unwrap();
+    while generator.nodes < nodes {
+        generator.def(nodes - generator.nodes);
+    }
+    generator.out
+}
+
+struct Generator {
+    out: String,
+    /// The state of the random number generator, a splitmix64.
+    state: u64,
+    /// How many nodes have been generated so far, counting the module's.
+    nodes: usize,
+    defs: usize,
+}
+
+impl Generator {
+    /// Generates a def of at most `budget` nodes, or of a few more when the
+    /// budget is too small for any def.
+    fn def(&mut self, budget: usize) {
+        // the lambda and its parameters
+        self.nodes += 4;
+        let budget = (8 + self.below(56)).min(budget.saturating_sub(4)).max(1);
+        write!(self.out, "\npub def f{}(a, b) {{ ", self.defs).unwrap();
+        self.expr(budget);
+        self.out.push_str(" }\n");
+        self.defs += 1;
+    }
+
+    /// Generates an Int expression of at most `budget` nodes, in terms of
+    /// the parameters `a` and `b` and the defs before the current one.
+    fn expr(&mut self, budget: usize) {
+        let choice = match budget {
+            0..=2 => 0,
+            3..=4 => 1,
+            _ if self.defs == 0 => 1 + self.below(2),
+            _ => 1 + self.below(3),
+        };
+        match choice {
+            0 => {
+                self.nodes += 1;
+                match self.below(3) {
+                    0 => self.out.push('a'),
+                    1 => self.out.push('b'),
+                    _ => {
+                        let n = self.below(100);
+                        write!(self.out, "{n}").unwrap();
+                    }
+                }
+            }
+            1 => {
+                self.nodes += 1;
+                let lhs = 1 + self.below(budget - 2);
+                let op = ["+", "-", "*"][self.below(3)];
+                self.out.push('(');
+                self.expr(lhs);
+                write!(self.out, " {op} ").unwrap();
+                self.expr(budget - 1 - lhs);
+                self.out.push(')');
+            }
+            2 => {
+                // the branch and its comparison
+                self.nodes += 2;
+                let budget = budget - 2;
+                self.out.push_str("(case ");
+                self.expr(budget / 4);
+                self.out.push_str(" < ");
+                self.expr(budget / 4);
+                self.out.push_str(" { ");
+                self.expr(budget / 4);
+                self.out.push_str(" } else { ");
+                self.expr(budget - budget / 4 * 3);
+                self.out.push_str(" })");
+            }
+            _ => {
+                // the call, the callee and the tuple of arguments
+                self.nodes += 3;
+                let budget = budget - 3;
+                let callee = self.below(self.defs);
+                write!(self.out, "f{callee}(").unwrap();
+                self.expr(budget / 2);
+                self.out.push_str(", ");
+                self.expr(budget - budget / 2);
+                self.out.push(')');
+            }
+        }
+    }
+
+    /// A random number below `n`, which must not be zero.
+    fn below(&mut self, n: usize) -> usize {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z % n as u64) as usize
+    }
+}
@@ -0,0 +1,260 @@
+//! Structural diffs for `radi diff`, which compare two versions of a module
+//! by their syntax trees rather than their text, so that formatting,
+//! comments and the order that defs are in don't show up as changes.
+//!
+//! The defs of the two versions are matched up by name, with defs of the
+//! same name in a scope matched in the order they're in, and impls by their
+//! trait and type. A def whose value is an object, a trait or an impl in
+//! both versions is compared def by def, and the defs inside it are named by
+//! their path, as in `a.b`. The uses of the module are compared as a set,
+//! and the expressions at its top level as a whole.
+
+use std::fmt::Write;
+
+use crate::{
+    parser::{expr_sexp, Def, Expr, ExprKind, Module, Use},
+    SourceMap, Span,
+};
+
+/// Something that differs between the two versions.
+#[derive(Debug)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// What changed, as `def a.b`, `use std.io` or `top level`.
+    pub what: String,
+    /// The 1-based line it's on in the old version, unless it was added.
+    pub old_line: Option<usize>,
+    /// The 1-based line it's on in the new version, unless it was removed.
+    pub new_line: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// The changes from `old` to `new`, whose spans are both in `source_map`.
+/// The uses come first, then the defs, with those that were removed from a
+/// scope before the rest of it in the order of the new version, and then
+/// the top level.
+pub fn diff(source_map: &SourceMap, old: &Module, new: &Module) -> Vec<Change> {
+    let mut differ = Differ {
+        source_map,
+        changes: Vec::new(),
+    };
+    let path = |item: &Use| {
+        let path = item.path.iter().map(|segment| segment.name.0);
+        format!("use {}", path.collect::<Vec<_>>().join("."))
+    };
+    let old_uses = old.uses.iter().map(|item| (path(item), item.span));
+    let new_uses = new.uses.iter().map(|item| (path(item), item.span));
+    differ.match_up(old_uses.collect(), new_uses.collect(), |_, _, _| {});
+
+    let (old_defs, old_body) = top_level(old);
+    let (new_defs, new_body) = top_level(new);
+    differ.defs("", old_defs, new_defs);
+
+    let sexps = |body: &[&Expr]| body.iter().map(|e| expr_sexp(e)).collect::<Vec<_>>();
+    if sexps(&old_body) != sexps(&new_body) {
+        let (old_line, new_line) = (
+            old_body.first().map(|e| differ.line(e.span)),
+            new_body.first().map(|e| differ.line(e.span)),
+        );
+        let kind = match (old_line, new_line) {
+            (None, _) => ChangeKind::Added,
+            (_, None) => ChangeKind::Removed,
+            _ => ChangeKind::Changed,
+        };
+        differ.changes.push(Change {
+            kind,
+            what: "top level".to_string(),
+            old_line,
+            new_line,
+        });
+    }
+    differ.changes
+}
+
+/// The changes as lines of `+`, `-` or `~` and what was added, removed or
+/// changed, followed by where it is in `old_path` and `new_path`.
+pub fn report(changes: &[Change], old_path: &str, new_path: &str) -> String {
+    let mut out = String::new();
+    for change in changes {
+        let sign = match change.kind {
+            ChangeKind::Added => '+',
+            ChangeKind::Removed => '-',
+            ChangeKind::Changed => '~',
+        };
+        let places = [(old_path, change.old_line), (new_path, change.new_line)];
+        let places = places
+            .iter()
+            .filter_map(|(path, line)| line.map(|line| format!("{path}:{line}")));
+        let places = places.collect::<Vec<_>>().join(", ");
+        let _ = writeln!(out, "{sign} {} ({places})", change.what);
+    }
+    out
+}
+
+/// The defs at the top level of `module`, and the expressions after them.
+fn top_level<'m, 's>(module: &'m Module<'s>) -> (&'m [Def<'s>], Vec<&'m Expr<'s>>) {
+    match &module.body.kind {
+        ExprKind::Object(scope) => (&scope.defs, scope.body.iter().collect()),
+        // an empty module
+        ExprKind::Tuple { items } if items.is_empty() => (&[], Vec::new()),
+        _ => (&[], vec![&module.body]),
+    }
+}
+
+struct Differ<'a> {
+    source_map: &'a SourceMap,
+    changes: Vec<Change>,
+}
+
+impl Differ<'_> {
+    fn line(&self, span: Span) -> usize {
+        self.source_map
+            .locate(span)
+            .map_or(0, |file| file.line_col(span.start).0)
+    }
+
+    /// Matches up the defs of a scope, whose path is `prefix`, and compares
+    /// those that are in both versions.
+    fn defs(&mut self, prefix: &str, old: &[Def], new: &[Def]) {
+        let keyed = |defs: &[Def]| {
+            defs.iter()
+                .map(|def| (what(prefix, def), def.name_span))
+                .collect::<Vec<_>>()
+        };
+        self.match_up(keyed(old), keyed(new), |differ, i, j| {
+            differ.def(prefix, &old[i], &new[j])
+        });
+    }
+
+    /// Reports the items of `old` that aren't in `new` as removed, and then
+    /// goes through `new`, reporting those that aren't in `old` as added and
+    /// calling `same` with the indices of those that are. Items are named by
+    /// what they are, and matched in order when several have the same name.
+    fn match_up(
+        &mut self,
+        old: Vec<(String, Span)>,
+        new: Vec<(String, Span)>,
+        mut same: impl FnMut(&mut Self, usize, usize),
+    ) {
+        let mut matches = vec![None; new.len()];
+        let mut matched = vec![false; old.len()];
+        for (j, (what, _)) in new.iter().enumerate() {
+            let i = (0..old.len()).find(|&i| !matched[i] && old[i].0 == *what);
+            if let Some(i) = i {
+                matched[i] = true;
+                matches[j] = Some(i);
+            }
+        }
+
+        for (i, (what, span)) in old.iter().enumerate() {
+            if !matched[i] {
+                self.changes.push(Change {
+                    kind: ChangeKind::Removed,
+                    what: what.clone(),
+                    old_line: Some(self.line(*span)),
+                    new_line: None,
+                });
+            }
+        }
+        for (j, (what, span)) in new.into_iter().enumerate() {
+            match matches[j] {
+                Some(i) => same(self, i, j),
+                None => {
+                    let new_line = Some(self.line(span));
+                    self.changes.push(Change {
+                        kind: ChangeKind::Added,
+                        what,
+                        old_line: None,
+                        new_line,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Compares two versions of a def in the scope whose path is `prefix`.
+    fn def(&mut self, prefix: &str, old: &Def, new: &Def) {
+        let same_header = old.public == new.public
+            && old.attributes.len() == new.attributes.len()
+            && old
+                .attributes
+                .iter()
+                .zip(new.attributes.iter())
+                .all(|(a, b)| {
+                    a.name.0 == b.name.0 && a.arg.map(|arg| arg.0) == b.arg.map(|arg| arg.0)
+                });
+        let (same, nested) = match (members(&old.value), members(&new.value)) {
+            (Some((old_head, old_defs)), Some((new_head, new_defs))) => (
+                same_header && old_head == new_head,
+                Some((old_defs, new_defs)),
+            ),
+            _ => {
+                let same_value = expr_sexp(&old.value) == expr_sexp(&new.value);
+                (same_header && same_value, None)
+            }
+        };
+        if !same {
+            self.changes.push(Change {
+                kind: ChangeKind::Changed,
+                what: what(prefix, new),
+                old_line: Some(self.line(old.name_span)),
+                new_line: Some(self.line(new.name_span)),
+            });
+        }
+        if let Some((old_defs, new_defs)) = nested {
+            let path = path(prefix, new);
+            self.defs(&path, old_defs, new_defs);
+        }
+    }
+}
+
+/// The defs inside the value of a def that's an object, a trait or an impl,
+/// along with everything else about the value, as an s-expression of it
+/// without them.
+fn members<'e, 's>(value: &'e Expr<'s>) -> Option<(String, &'e [Def<'s>])> {
+    match &value.kind {
+        ExprKind::Object(scope) => {
+            let mut head = String::from("(object");
+            for e in scope.body.iter() {
+                let _ = write!(head, " {}", expr_sexp(e));
+            }
+            if scope.trailing_semi && !scope.body.is_empty() {
+                head.push_str(" ;");
+            }
+            head.push(')');
+            Some((head, &scope.defs))
+        }
+        ExprKind::Trait { param, defs, .. } => Some((format!("(trait {})", param.0), defs)),
+        ExprKind::Impl { ty, defs } => Some((format!("(impl {})", expr_sexp(ty)), defs)),
+        _ => None,
+    }
+}
+
+/// The path of `def` inside the scope whose path is `prefix`. An impl is
+/// named by its trait and the type it's for, since a trait has many.
+fn path(prefix: &str, def: &Def) -> String {
+    let name = match &def.value.kind {
+        ExprKind::Impl { ty, .. } => format!("{}({})", def.name.0, expr_sexp(ty)),
+        _ => def.name.0.to_string(),
+    };
+    match prefix.is_empty() {
+        true => name,
+        false => format!("{prefix}.{name}"),
+    }
+}
+
+/// What `def` is, as it's reported, such as `def a.b` or `impl Show(Int)`.
+fn what(prefix: &str, def: &Def) -> String {
+    let keyword = match &def.value.kind {
+        ExprKind::Trait { .. } => "trait",
+        ExprKind::Impl { .. } => "impl",
+        _ => "def",
+    };
+    format!("{keyword} {}", path(prefix, def))
+}
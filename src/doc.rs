@@ -0,0 +1,225 @@
+//! Documentation for `radi doc`: the public defs of every module the entry
+//! imports, with their types and the `//` comments above them, written out
+//! as static HTML pages and as a JSON index for other tools to read.
+
+use std::{collections::VecDeque, io, path::Path};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    highlight::escape,
+    parse_manager::ParseManager,
+    parser,
+    resolver::{self, Resolution, SymbolKind},
+    source_map::FileId,
+    tokenizer::Span,
+    typeck::Typing,
+};
+
+/// The documentation of a module.
+#[derive(Debug)]
+pub struct ModuleDoc {
+    /// The dotted path that the module is imported by.
+    pub name: String,
+    pub path: String,
+    /// The public defs of the module, in source order.
+    pub items: Vec<ItemDoc>,
+}
+
+/// The documentation of a public def.
+#[derive(Debug)]
+pub struct ItemDoc {
+    pub name: String,
+    /// The type of the def, if type checking has run.
+    pub ty: Option<String>,
+    pub doc: Option<String>,
+    pub line: usize,
+}
+
+impl ItemDoc {
+    /// How the def is shown in the documentation, like `def name :: Type`.
+    pub fn signature(&self) -> String {
+        match &self.ty {
+            Some(ty) => format!("def {} :: {ty}", self.name),
+            None => format!("def {}", self.name),
+        }
+    }
+}
+
+/// Collects the documentation of `entry` and of the modules it imports,
+/// directly or not, in the order they're reached from the entry. The prelude
+/// is left out.
+pub fn collect(
+    manager: &ParseManager,
+    resolution: &Resolution,
+    typing: Option<&Typing>,
+    entry: FileId,
+) -> Vec<ModuleDoc> {
+    let defs = resolution
+        .symbols
+        .iter()
+        .enumerate()
+        .filter(|(_, symbol)| symbol.kind == SymbolKind::Def)
+        .map(|(i, symbol)| (symbol.span, resolver::SymbolId(i as u32)))
+        .collect::<FxHashMap<Span, _>>();
+
+    let graph = manager.graph();
+    let mut seen = FxHashSet::default();
+    let mut queue = VecDeque::from([entry]);
+    let mut out = Vec::new();
+    while let Some(file) = queue.pop_front() {
+        if Some(file) == manager.prelude() || !seen.insert(file) {
+            continue;
+        }
+        queue.extend(graph.dependencies(file));
+        let Some(module) = manager.module(file) else {
+            continue;
+        };
+        let source = manager.source_map().file(file);
+        let parser::ExprKind::Object(scope) = &module.ast.body.kind else {
+            continue;
+        };
+        let items = scope
+            .defs
+            .iter()
+            .filter(|def| def.public)
+            .map(|def| {
+                let ty = typing.and_then(|typing| {
+                    let id = defs.get(&def.name_span)?;
+                    Some(typing.display(*typing.symbols.get(id)?))
+                });
+                ItemDoc {
+                    name: def.name.0.to_string(),
+                    ty,
+                    doc: resolver::doc_comment(source, def.span.start),
+                    line: source.line_col(def.name_span.start).0,
+                }
+            })
+            .collect();
+        out.push(ModuleDoc {
+            name: module.name.clone(),
+            path: source.path.display().to_string(),
+            items,
+        });
+    }
+    out
+}
+
+/// Writes a page for each module, an `index.html` that links to them and an
+/// `index.json` into `dir`, creating it if it doesn't exist.
+pub fn write(dir: &Path, modules: &[ModuleDoc]) -> io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    for module in modules {
+        std::fs::write(dir.join(page(module)), module_html(module))?;
+    }
+    std::fs::write(dir.join("index.html"), index_html(modules))?;
+    std::fs::write(dir.join("index.json"), json(modules))
+}
+
+/// The file name of the page of a module.
+fn page(module: &ModuleDoc) -> String {
+    format!("{}.html", module.name)
+}
+
+const STYLE: &str = "body { font-family: sans-serif; max-width: 50em; margin: auto; }\n\
+    pre { background: #f4f4f4; padding: 0.5em; }\n\
+    .item { margin-bottom: 2em; }\n";
+
+fn header(title: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{}</title>\n<style>\n{STYLE}</style>\n</head>\n<body>\n",
+        escape(title)
+    )
+}
+
+const FOOTER: &str = "</body>\n</html>\n";
+
+fn index_html(modules: &[ModuleDoc]) -> String {
+    let mut out = header("Modules");
+    out.push_str("<h1>Modules</h1>\n<ul>\n");
+    for module in modules {
+        out.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>\n",
+            escape(&page(module)),
+            escape(&module.name)
+        ));
+    }
+    out.push_str("</ul>\n");
+    out.push_str(FOOTER);
+    out
+}
+
+fn module_html(module: &ModuleDoc) -> String {
+    let mut out = header(&module.name);
+    out.push_str(&format!(
+        "<p><a href=\"index.html\">Modules</a></p>\n<h1>Module {}</h1>\n",
+        escape(&module.name)
+    ));
+    if module.items.is_empty() {
+        out.push_str("<p>This module has no public defs.</p>\n");
+    }
+    for item in &module.items {
+        out.push_str(&format!(
+            "<div class=\"item\" id=\"{}\">\n<pre><code>{}</code></pre>\n",
+            escape(&item.name),
+            escape(&item.signature())
+        ));
+        // blank lines separate paragraphs
+        for paragraph in item.doc.iter().flat_map(|doc| doc.split("\n\n")) {
+            out.push_str(&format!("<p>{}</p>\n", escape(paragraph.trim())));
+        }
+        out.push_str("</div>\n");
+    }
+    out.push_str(FOOTER);
+    out
+}
+
+/// The index of every module and its items, as JSON.
+pub fn json(modules: &[ModuleDoc]) -> String {
+    let modules = modules
+        .iter()
+        .map(|module| {
+            let items = module
+                .items
+                .iter()
+                .map(|item| {
+                    format!(
+                        "{{\"name\": {}, \"signature\": {}, \"type\": {}, \"doc\": {}, \"line\": {}}}",
+                        json_string(&item.name),
+                        json_string(&item.signature()),
+                        item.ty.as_deref().map_or("null".to_string(), json_string),
+                        item.doc.as_deref().map_or("null".to_string(), json_string),
+                        item.line
+                    )
+                })
+                .collect::<Vec<_>>();
+            format!(
+                "{{\"name\": {}, \"path\": {}, \"page\": {}, \"items\": [{}]}}",
+                json_string(&module.name),
+                json_string(&module.path),
+                json_string(&page(module)),
+                items.join(", ")
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("{{\"modules\": [{}]}}\n", modules.join(", "))
+}
+
+pub(crate) fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            _ if ch.is_control() => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
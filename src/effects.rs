@@ -0,0 +1,304 @@
+//! This stage checks how the operators that deal with mutable state are used:
+//! - `set place value` may only assign to a place, which is either a binding
+//!   declared with `set` or a dereferenced reference `r^`.
+//! - `val x` reads the current value of a mutable binding, so it may only be
+//!   used on mutable bindings (or a tuple of them).
+//! - `^x` of a mutable binding is a reference to the binding itself, which
+//!   may not outlive the scope the binding is declared in. It can't be the
+//!   result of that scope, or be assigned to a place declared outside it.
+//!   A reference that escapes through a def or a function call isn't caught.
+//!
+//! `^` of anything else copies the value into a new cell, which may go
+//! anywhere.
+use rustc_hash::FxHashMap;
+
+use crate::{
+    errors::ErrorStream,
+    resolver::{
+        AccessRhs, Expr, ExprKind, Module, Pattern, PatternKind, Resolution, Scope, SymbolId, UnOp,
+    },
+    tokenizer::{Intern, Span},
+};
+
+#[derive(Debug)]
+pub struct EffectError<'s> {
+    pub kind: EffectErrorKind<'s>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum EffectErrorKind<'s> {
+    /// `set` was used on something that isn't a binding or a dereference.
+    NotAPlace,
+    /// `set` was used on a binding that wasn't declared with `set`.
+    Immutable(Intern<'s>),
+    /// `val` was used on a binding that wasn't declared with `set`.
+    ValOfImmutable(Intern<'s>),
+    /// `val` was used on something other than a binding.
+    InvalidVal,
+    /// A reference to a mutable binding escapes the scope it's declared in.
+    RefEscapes(Intern<'s>),
+}
+
+pub fn check<'s>(modules: &[Module<'s>], resolution: &Resolution<'s>, errors: &ErrorStream<'s>) {
+    let mut checker = Checker {
+        resolution,
+        errors,
+        depths: FxHashMap::default(),
+        depth: 0,
+    };
+    for module in modules {
+        // the top-level defs of a module live as long as the program, so
+        // references to them may go anywhere
+        match &module.body.kind {
+            ExprKind::Object(scope) => {
+                for def in scope.defs.iter() {
+                    checker.expr(&def.value);
+                }
+                for expr in scope.body.iter() {
+                    checker.expr(expr);
+                }
+            }
+            _ => checker.expr(&module.body),
+        }
+    }
+}
+
+struct Checker<'s, 'e> {
+    resolution: &'e Resolution<'s>,
+    errors: &'e ErrorStream<'s>,
+    /// How many scopes deep each local mutable binding is declared. Other
+    /// bindings are treated as if they outlive every scope, since a reference
+    /// held by an immutable binding may point anywhere.
+    depths: FxHashMap<SymbolId, usize>,
+    depth: usize,
+}
+
+impl<'s> Checker<'s, '_> {
+    fn expr(&mut self, expr: &Expr<'s>) {
+        match &expr.kind {
+            ExprKind::Object(scope) => {
+                self.depth += 1;
+                self.scope(scope);
+                for def in scope.defs.iter() {
+                    self.escapes(&def.value);
+                }
+                self.depth -= 1;
+            }
+            ExprKind::Block(scope) => {
+                self.depth += 1;
+                self.scope(scope);
+                if let (false, Some(last)) = (scope.trailing_semi, scope.body.last()) {
+                    self.escapes(last);
+                }
+                self.depth -= 1;
+            }
+            ExprKind::Lambda { arg, body } => {
+                self.depth += 1;
+                self.pattern(arg);
+                self.expr(body);
+                self.escapes(body);
+                self.depth -= 1;
+            }
+            ExprKind::BinOp { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprKind::UnOp { op: UnOp::Val, arg } => {
+                self.val(arg);
+                self.expr(arg);
+            }
+            ExprKind::UnOp { arg, .. } => self.expr(arg),
+            ExprKind::Access { expr, prop } => {
+                self.expr(expr);
+                if let AccessRhs::Expr(prop) = prop {
+                    self.expr(prop);
+                }
+            }
+            ExprKind::Branch {
+                cond,
+                on_true,
+                on_false,
+            } => {
+                self.expr(cond);
+                self.expr(on_true);
+                if let Some(on_false) = on_false {
+                    self.expr(on_false);
+                }
+            }
+            ExprKind::Tuple { items } => items.iter().for_each(|item| self.expr(item)),
+            ExprKind::Apply { a, b } | ExprKind::TypeAssertion { a, b } => {
+                self.expr(a);
+                self.expr(b);
+            }
+            ExprKind::Set { place, value } => {
+                self.set(place, value);
+                self.expr(place);
+                self.expr(value);
+            }
+            ExprKind::Match { scrutinee, arms } => {
+                self.expr(scrutinee);
+                for arm in arms.iter() {
+                    self.depth += 1;
+                    self.pattern(&arm.pattern);
+                    self.expr(&arm.body);
+                    self.escapes(&arm.body);
+                    self.depth -= 1;
+                }
+            }
+            ExprKind::Variant(items) => items
+                .iter()
+                .filter_map(|item| item.value.as_ref())
+                .for_each(|value| self.expr(value)),
+            ExprKind::Impl { defs, .. } => defs.iter().for_each(|def| self.expr(&def.value)),
+            ExprKind::Ident(_)
+            | ExprKind::Literal(_)
+            | ExprKind::Arrow { .. }
+            | ExprKind::Extern { .. }
+            | ExprKind::Trait { .. }
+            | ExprKind::Error => {}
+        }
+    }
+
+    /// Checks the defs and body of a scope, whose defs are declared at the
+    /// current depth.
+    fn scope(&mut self, scope: &Scope<'s>) {
+        for def in scope.defs.iter() {
+            self.declare(def.symbol);
+        }
+        for def in scope.defs.iter() {
+            self.expr(&def.value);
+        }
+        for expr in scope.body.iter() {
+            self.expr(expr);
+        }
+    }
+
+    fn pattern(&mut self, pat: &Pattern<'s>) {
+        match &pat.kind {
+            PatternKind::Bind(symbol) => self.declare(*symbol),
+            PatternKind::Tuple(items) => items.iter().for_each(|item| self.pattern(item)),
+            PatternKind::Typed { pat, .. } => self.pattern(pat),
+            PatternKind::Variant { value, .. } => {
+                if let Some(value) = value {
+                    self.pattern(value);
+                }
+            }
+            PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Error => {}
+        }
+    }
+
+    fn declare(&mut self, symbol: SymbolId) {
+        if self.resolution.symbol(symbol).mutable {
+            self.depths.insert(symbol, self.depth);
+        }
+    }
+
+    fn depth_of(&self, symbol: SymbolId) -> usize {
+        self.depths.get(&symbol).copied().unwrap_or(0)
+    }
+
+    fn set(&mut self, place: &Expr<'s>, value: &Expr<'s>) {
+        let depth = match &place.kind {
+            ExprKind::Ident(symbol) => {
+                let symbol = *symbol;
+                let info = self.resolution.symbol(symbol);
+                if !info.mutable {
+                    self.error(EffectErrorKind::Immutable(info.name), place.span);
+                    return;
+                }
+                self.depth_of(symbol)
+            }
+            // the cell a reference points to may have been declared anywhere
+            ExprKind::UnOp {
+                op: UnOp::Deref, ..
+            } => 0,
+            ExprKind::Error => return,
+            _ => {
+                self.error(EffectErrorKind::NotAPlace, place.span);
+                return;
+            }
+        };
+
+        let mut refs = Vec::new();
+        self.results(value, &mut refs);
+        for (symbol, span) in refs {
+            if self.depth_of(symbol) > depth {
+                let name = self.resolution.symbol(symbol).name;
+                self.error(EffectErrorKind::RefEscapes(name), span);
+            }
+        }
+    }
+
+    fn val(&mut self, arg: &Expr<'s>) {
+        match &arg.kind {
+            ExprKind::Ident(symbol) => {
+                let symbol = self.resolution.symbol(*symbol);
+                if !symbol.mutable {
+                    self.error(EffectErrorKind::ValOfImmutable(symbol.name), arg.span);
+                }
+            }
+            ExprKind::Tuple { items } => items.iter().for_each(|item| self.val(item)),
+            ExprKind::Error => {}
+            _ => self.error(EffectErrorKind::InvalidVal, arg.span),
+        }
+    }
+
+    /// Reports the references to bindings of the current scope that are part
+    /// of `expr`, the scope's result.
+    fn escapes(&mut self, expr: &Expr<'s>) {
+        let mut refs = Vec::new();
+        self.results(expr, &mut refs);
+        for (symbol, span) in refs {
+            if self.depths.get(&symbol) == Some(&self.depth) {
+                let name = self.resolution.symbol(symbol).name;
+                self.error(EffectErrorKind::RefEscapes(name), span);
+            }
+        }
+    }
+
+    /// Collects the references to mutable bindings that `expr` can evaluate
+    /// to, or that are part of the data it evaluates to.
+    fn results(&self, expr: &Expr<'s>, out: &mut Vec<(SymbolId, Span)>) {
+        match &expr.kind {
+            ExprKind::UnOp { op: UnOp::Ref, arg } => {
+                if let ExprKind::Ident(symbol) = arg.kind {
+                    if self.resolution.symbol(symbol).mutable {
+                        out.push((symbol, expr.span));
+                    }
+                }
+            }
+            ExprKind::Block(scope) if !scope.trailing_semi => {
+                if let Some(last) = scope.body.last() {
+                    self.results(last, out);
+                }
+            }
+            ExprKind::Object(scope) => scope
+                .defs
+                .iter()
+                .for_each(|def| self.results(&def.value, out)),
+            ExprKind::Branch {
+                on_true, on_false, ..
+            } => {
+                self.results(on_true, out);
+                if let Some(on_false) = on_false {
+                    self.results(on_false, out);
+                }
+            }
+            ExprKind::Match { arms, .. } => {
+                arms.iter().for_each(|arm| self.results(&arm.body, out))
+            }
+            ExprKind::Tuple { items } => items.iter().for_each(|item| self.results(item, out)),
+            ExprKind::Variant(items) => items
+                .iter()
+                .filter_map(|item| item.value.as_ref())
+                .for_each(|value| self.results(value, out)),
+            ExprKind::TypeAssertion { a, .. } => self.results(a, out),
+            _ => {}
+        }
+    }
+
+    fn error(&self, kind: EffectErrorKind<'s>, span: Span) {
+        self.errors.error(EffectError { kind, span });
+    }
+}
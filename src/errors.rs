@@ -1,28 +1,318 @@
-use std::{io, marker::PhantomData};
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Write,
+    io::{self, IsTerminal},
+};
 
 use crate::{
-    parser::ParseErrorKind,
+    doc,
+    effects::{EffectError, EffectErrorKind},
+    highlight,
+    hir::{ConstError, ConstErrorKind, FoldWarning, FoldWarningKind, LowerError, LowerErrorKind},
+    lints::{LintKind, LintWarning},
+    parse_manager::{ManifestError, ModuleError, ModuleErrorKind},
+    parser::{ExpandError, ExpandErrorKind, Expansion, ParseError, ParseErrorKind},
+    resolver::{self, ResolveError, ResolveErrorKind},
+    source_map::{SourceFile, SourceMap},
+    string_storage::StringStorage,
     tokenizer::{Span, TokenizationError, TokenizationErrorKind},
+    typeck::{TypeError, TypeErrorKind},
 };
 
+/// Receives the diagnostics reported during compilation.
+///
+/// By default, diagnostics are printed as soon as they are reported. A
+/// buffered stream instead holds on to them so that they can be forwarded to
+/// another stream later, which is how diagnostics from work done in parallel
+/// are reported in a deterministic order, or taken by whoever embeds the
+/// compiler. A deferred stream holds on to them until they are rendered
+/// with the source they point at.
 pub struct ErrorStream<'s> {
-    phantom: PhantomData<&'s str>,
+    buffer: Option<RefCell<Vec<Diagnostic<'s>>>>,
+    /// Whether [ErrorStream::render] prints the buffered diagnostics.
+    deferred: bool,
+    errors: Cell<usize>,
+    warnings: Cell<usize>,
+    /// The macro expansions in the modules parsed so far, which diagnostics
+    /// in the templates of macros are traced back to.
+    expansions: RefCell<Vec<Expansion<'s>>>,
+}
+
+#[derive(Debug)]
+pub struct Diagnostic<'s> {
+    pub severity: Severity,
+    pub error: CompilationError<'s>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A diagnostic as plain data, which is the schema that diagnostics are
+/// serialized with. Its message is the kind of error as it's printed, since
+/// the kinds themselves borrow from the compilation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiagnosticRecord {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl DiagnosticRecord {
+    /// The diagnostic as a JSON object on one line, with its `severity`, its
+    /// `message` and its `span` as `[start, end]`, or `null` if it has none.
+    pub fn json(&self) -> String {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        let span = self.span.map_or("null".to_string(), |span| {
+            format!("[{},{}]", span.start, span.end)
+        });
+        format!(
+            "{{\"severity\":\"{severity}\",\"message\":{},\"span\":{span}}}",
+            doc::json_string(&self.message)
+        )
+    }
+}
+
+impl Diagnostic<'_> {
+    pub fn record(&self) -> DiagnosticRecord {
+        DiagnosticRecord {
+            severity: self.severity,
+            message: self.error.kind.message(),
+            span: self.error.span,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Diagnostic<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.record().serialize(serializer)
+    }
+}
+
+impl Default for ErrorStream<'_> {
+    fn default() -> Self {
+        ErrorStream::new()
+    }
 }
 
 impl<'s> ErrorStream<'s> {
     pub fn new() -> ErrorStream<'s> {
         ErrorStream {
-            phantom: PhantomData,
+            buffer: None,
+            deferred: false,
+            errors: Cell::new(0),
+            warnings: Cell::new(0),
+            expansions: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn buffered() -> ErrorStream<'s> {
+        ErrorStream {
+            buffer: Some(RefCell::new(Vec::new())),
+            deferred: false,
+            errors: Cell::new(0),
+            warnings: Cell::new(0),
+            expansions: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn deferred() -> ErrorStream<'s> {
+        ErrorStream {
+            deferred: true,
+            ..ErrorStream::buffered()
         }
     }
 
     pub fn warning(&self, warning: impl Into<CompilationError<'s>>) {
-        eprintln!("WARNING: {:?}", warning.into())
+        self.emit(Diagnostic {
+            severity: Severity::Warning,
+            error: warning.into(),
+        })
     }
 
     pub fn error(&self, error: impl Into<CompilationError<'s>>) {
-        eprintln!("ERROR: {:?}", error.into())
+        self.emit(Diagnostic {
+            severity: Severity::Error,
+            error: error.into(),
+        })
     }
+
+    pub fn emit(&self, diagnostic: Diagnostic<'s>) {
+        let count = match diagnostic.severity {
+            Severity::Warning => &self.warnings,
+            Severity::Error => &self.errors,
+        };
+        count.set(count.get() + 1);
+        match &self.buffer {
+            Some(buffer) => buffer.borrow_mut().push(diagnostic),
+            None => self.print(&diagnostic, None),
+        }
+    }
+
+    /// Prints the diagnostics held by a deferred stream with the lines of
+    /// source they point at, and forgets them. They still count towards
+    /// [ErrorStream::error_count].
+    pub fn render(&self, source_map: &SourceMap) {
+        if self.deferred {
+            for diagnostic in self.take_diagnostics() {
+                self.print(&diagnostic, Some(source_map));
+            }
+        }
+    }
+
+    fn print(&self, diagnostic: &Diagnostic<'s>, source_map: Option<&SourceMap>) {
+        let color = io::stderr().is_terminal();
+        eprint!("{}", self.format(diagnostic, source_map, color));
+    }
+
+    /// A diagnostic as it's printed, with the lines of source it points at if
+    /// they're in `source_map`, and where the macros it came from were
+    /// expanded. The source is highlighted with ANSI colors if `color` is set.
+    pub fn format(
+        &self,
+        diagnostic: &Diagnostic<'s>,
+        source_map: Option<&SourceMap>,
+        color: bool,
+    ) -> String {
+        let severity = match diagnostic.severity {
+            Severity::Warning => "WARNING",
+            Severity::Error => "ERROR",
+        };
+        let error = &diagnostic.error;
+        let message = error.kind.message();
+        let Some(span) = error.span else {
+            return format!("{severity}: {message}\n");
+        };
+        let mut out = match source_map.and_then(|source_map| source_map.locate(span)) {
+            Some(file) => {
+                let tokens = resolver::semantic_tokens(file, &StringStorage::new(), None);
+                format!(
+                    "{severity}: {message} at {}\n{}\n",
+                    position(file, span),
+                    highlight::snippet(file, &tokens, span, color)
+                )
+            }
+            None => format!("{severity}: {error:?}\n"),
+        };
+        // other places in the source that the error is about
+        let notes = match &error.kind {
+            CompilationErrorKind::Resolve(ResolveErrorKind::UsedBeforeDefinition {
+                name,
+                definition,
+            }) => {
+                // a def used in its own value is defined before the use
+                let when = match definition.start > span.start {
+                    true => "defined later",
+                    false => "still being defined",
+                };
+                vec![(format!("`{}` is {when} here", name.0), *definition)]
+            }
+            CompilationErrorKind::Type(
+                TypeErrorKind::NoField {
+                    closed: Some(closed),
+                    ..
+                }
+                | TypeErrorKind::NoCase {
+                    closed: Some(closed),
+                    ..
+                },
+            ) => vec![("the type is closed here".to_string(), *closed)],
+            // each `use` in the cycle, in order
+            CompilationErrorKind::Module(ModuleErrorKind::Cycle(edges)) => edges
+                .iter()
+                .map(|edge| (format!("`{}` uses `{}`", edge.from, edge.to), edge.span))
+                .collect(),
+            _ => Vec::new(),
+        };
+        for (note, at) in notes {
+            let _ = match source_map.and_then(|source_map| source_map.locate(at)) {
+                Some(file) => writeln!(out, "  NOTE: {note}, at {}", position(file, at)),
+                None => writeln!(out, "  NOTE: {note}, at {at:?}"),
+            };
+        }
+        for expansion in self.expansions_at(span) {
+            let call = source_map.and_then(|source_map| source_map.locate(expansion.call));
+            let _ = match call {
+                Some(file) => writeln!(
+                    out,
+                    "  NOTE: in the expansion of `{}!` at {}",
+                    expansion.name.0,
+                    position(file, expansion.call)
+                ),
+                None => writeln!(
+                    out,
+                    "  NOTE: in the expansion of `{}!` at {:?}",
+                    expansion.name.0, expansion.call
+                ),
+            };
+        }
+        out
+    }
+
+    /// Records where macros were expanded in a module that was just parsed.
+    pub fn expanded(&self, expansions: &[Expansion<'s>]) {
+        self.expansions.borrow_mut().extend_from_slice(expansions);
+    }
+
+    /// The expansions whose macro arm `span` is inside of, which a
+    /// diagnostic at `span` may have come from.
+    pub fn expansions_at(&self, span: Span) -> Vec<Expansion<'s>> {
+        self.expansions
+            .borrow()
+            .iter()
+            .filter(|expansion| {
+                expansion.definition.start <= span.start && span.end <= expansion.definition.end
+            })
+            .copied()
+            .collect()
+    }
+
+    /// The number of errors reported so far, which later stages of the
+    /// compiler check to avoid running on a broken program.
+    pub fn error_count(&self) -> usize {
+        self.errors.get()
+    }
+
+    /// The number of warnings reported so far.
+    pub fn warning_count(&self) -> usize {
+        self.warnings.get()
+    }
+
+    /// Forgets the errors and warnings reported so far, for a compilation
+    /// that starts over.
+    pub fn reset(&self) {
+        self.errors.set(0);
+        self.warnings.set(0);
+    }
+
+    /// Takes the diagnostics held by a buffered stream.
+    pub fn into_diagnostics(self) -> Vec<Diagnostic<'s>> {
+        self.buffer.map(RefCell::into_inner).unwrap_or_default()
+    }
+
+    /// Takes the diagnostics held by a buffered stream so far, leaving it
+    /// empty.
+    pub fn take_diagnostics(&self) -> Vec<Diagnostic<'s>> {
+        self.buffer.as_ref().map(RefCell::take).unwrap_or_default()
+    }
+}
+
+/// Where `span` starts in `file`, as `path:line:column`.
+fn position(file: &SourceFile, span: Span) -> String {
+    let (line, col) = file.line_col(span.start);
+    format!("{}:{}:{}", file.path.display(), line, col)
 }
 
 #[derive(Debug)]
@@ -34,23 +324,166 @@ pub struct CompilationError<'s> {
 #[derive(Debug)]
 pub enum CompilationErrorKind<'s> {
     Parse(ParseErrorKind<'s>),
+    Expand(ExpandErrorKind<'s>),
+    Resolve(ResolveErrorKind<'s>),
+    Type(TypeErrorKind<'s>),
+    Effect(EffectErrorKind<'s>),
+    Lower(LowerErrorKind<'s>),
+    Const(ConstErrorKind<'s>),
+    Fold(FoldWarningKind),
+    /// What a lint found, and the name of the lint.
+    Lint(&'static str, LintKind<'s>),
+    Module(ModuleErrorKind),
+    Manifest(ManifestError),
     Tokenization(TokenizationErrorKind),
     Io(io::Error),
 }
 
+impl CompilationErrorKind<'_> {
+    /// The error as it's printed. Most kinds are printed as they're
+    /// debugged, but a cycle of modules is printed as the names of the
+    /// modules, since its spans are printed as notes, and a file that
+    /// couldn't be read as its path and why. An unexpected token is printed
+    /// as its kind, since the error is printed at where the token is.
+    pub fn message(&self) -> String {
+        match self {
+            CompilationErrorKind::Parse(ParseErrorKind::Unexpected(Some(token))) => {
+                format!("Parse(Unexpected({:?}))", token.kind)
+            }
+            CompilationErrorKind::Parse(ParseErrorKind::Unexpected(None)) => {
+                "Parse(UnexpectedEof)".to_string()
+            }
+            CompilationErrorKind::Module(ModuleErrorKind::Cycle(edges)) => {
+                let modules = edges.iter().map(|edge| edge.from.as_str());
+                let first = edges.first().map(|edge| edge.from.as_str());
+                let modules = modules.chain(first).collect::<Vec<_>>();
+                format!("Cycle({})", modules.join(" -> "))
+            }
+            CompilationErrorKind::Module(ModuleErrorKind::Unreadable(path, err)) => {
+                format!("Unreadable({}: {err})", path.display())
+            }
+            kind => format!("{kind:?}"),
+        }
+    }
+}
+
 impl<'s> From<ParseErrorKind<'s>> for CompilationError<'s> {
     fn from(err: ParseErrorKind<'s>) -> Self {
         if let ParseErrorKind::TokenizationError(err) = err {
             err.into()
         } else {
+            // an unexpected token is where the error is
+            let span = match &err {
+                ParseErrorKind::Unexpected(Some(token)) => Some(token.span),
+                _ => None,
+            };
             CompilationError {
                 kind: CompilationErrorKind::Parse(err),
-                span: None,
+                span,
             }
         }
     }
 }
 
+impl<'s> From<ParseError<'s>> for CompilationError<'s> {
+    fn from(err: ParseError<'s>) -> Self {
+        let span = err.span();
+        let mut err: CompilationError = err.into_kind().into();
+        err.span = err.span.or(span);
+        err
+    }
+}
+
+impl<'s> From<ExpandError<'s>> for CompilationError<'s> {
+    fn from(err: ExpandError<'s>) -> Self {
+        CompilationError {
+            kind: CompilationErrorKind::Expand(err.kind),
+            span: Some(err.span),
+        }
+    }
+}
+
+impl<'s> From<ManifestError> for CompilationError<'s> {
+    fn from(err: ManifestError) -> Self {
+        CompilationError {
+            kind: CompilationErrorKind::Manifest(err),
+            span: None,
+        }
+    }
+}
+
+impl<'s> From<ModuleError> for CompilationError<'s> {
+    fn from(err: ModuleError) -> Self {
+        CompilationError {
+            kind: CompilationErrorKind::Module(err.kind),
+            span: Some(err.span),
+        }
+    }
+}
+
+impl<'s> From<ResolveError<'s>> for CompilationError<'s> {
+    fn from(err: ResolveError<'s>) -> Self {
+        CompilationError {
+            kind: CompilationErrorKind::Resolve(err.kind),
+            span: Some(err.span),
+        }
+    }
+}
+
+impl<'s> From<TypeError<'s>> for CompilationError<'s> {
+    fn from(err: TypeError<'s>) -> Self {
+        CompilationError {
+            kind: CompilationErrorKind::Type(err.kind),
+            span: Some(err.span),
+        }
+    }
+}
+
+impl<'s> From<EffectError<'s>> for CompilationError<'s> {
+    fn from(err: EffectError<'s>) -> Self {
+        CompilationError {
+            kind: CompilationErrorKind::Effect(err.kind),
+            span: Some(err.span),
+        }
+    }
+}
+
+impl<'s> From<LowerError<'s>> for CompilationError<'s> {
+    fn from(err: LowerError<'s>) -> Self {
+        CompilationError {
+            kind: CompilationErrorKind::Lower(err.kind),
+            span: Some(err.span),
+        }
+    }
+}
+
+impl<'s> From<ConstError<'s>> for CompilationError<'s> {
+    fn from(err: ConstError<'s>) -> Self {
+        CompilationError {
+            kind: CompilationErrorKind::Const(err.kind),
+            span: Some(err.span),
+        }
+    }
+}
+
+impl<'s> From<FoldWarning> for CompilationError<'s> {
+    fn from(warning: FoldWarning) -> Self {
+        CompilationError {
+            kind: CompilationErrorKind::Fold(warning.kind),
+            span: Some(warning.span),
+        }
+    }
+}
+
+impl<'s> From<LintWarning<'s>> for CompilationError<'s> {
+    fn from(warning: LintWarning<'s>) -> Self {
+        CompilationError {
+            kind: CompilationErrorKind::Lint(warning.lint, warning.kind),
+            span: Some(warning.span),
+        }
+    }
+}
+
 impl<'s> From<TokenizationError> for CompilationError<'s> {
     fn from(err: TokenizationError) -> Self {
         if let TokenizationErrorKind::Io(io_err) = err.kind {
@@ -0,0 +1,131 @@
+//! Running a program under a [Debugger], which evaluation pauses for before
+//! calls so that it can look at what's in scope and say how to go on.
+//!
+//! Evaluation pauses at a call once its function and argument have been
+//! evaluated, before the call is made. Between pauses it runs until a call
+//! that the last [Step] asks for, or one that the debugger has a breakpoint
+//! at.
+use std::io::Write;
+
+use super::{execute, Gc, Heap, Interpreter, Result, Value};
+use crate::{
+    hir::{self, Program, VarId},
+    tokenizer::Span,
+};
+
+/// What evaluation pauses for.
+pub trait Debugger {
+    /// Whether evaluation should pause at the call at `span` even when it
+    /// isn't stepping. A breakpoint is usually a span of source that this
+    /// checks `span` starts inside of.
+    fn breakpoint(&mut self, span: Span) -> bool;
+
+    /// Looks at a paused call, and says where to pause next.
+    fn pause(&mut self, pause: &Pause) -> Step;
+}
+
+/// How to go on from a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Only pause at breakpoints.
+    Continue,
+    /// Pause at the next call, which is inside the function being called if
+    /// that calls anything.
+    Into,
+    /// Pause at the next call that isn't made from inside the one being
+    /// made.
+    Over,
+    /// Pause at the next call made after the function that's paused in has
+    /// returned.
+    Out,
+}
+
+/// A call that evaluation has paused before.
+#[derive(Debug)]
+pub struct Pause {
+    pub span: Span,
+    /// How many calls are in progress, which is one more inside a function
+    /// that's called than where it was called from.
+    pub depth: usize,
+    /// The name of the def, member or builtin being called, if it is one.
+    pub callee: Option<String>,
+    /// The argument, as `print` would show it.
+    pub arg: String,
+    /// The named variables in scope, innermost frame first, with their values
+    /// as `print` would show them.
+    pub bindings: Vec<(String, String)>,
+}
+
+/// A debugger attached to an interpreter, with what it last said to do.
+pub(super) struct Attached<'d> {
+    debugger: &'d mut dyn Debugger,
+    step: Step,
+    /// The depth of the call it last paused at.
+    depth: usize,
+}
+
+/// Runs `program` like [super::run], pausing for `debugger` along the way.
+pub fn debug<'p, 's>(
+    program: &'p Program<'s>,
+    main: Option<VarId>,
+    out: &mut dyn Write,
+    heap: &mut Heap<'p, 's>,
+    debugger: &mut dyn Debugger,
+) -> Result {
+    let mut interpreter = Interpreter::new(heap, out, None, &program.vars);
+    interpreter.debugger = Some(Attached {
+        debugger,
+        step: Step::Continue,
+        depth: 0,
+    });
+    execute(interpreter, program, main)
+}
+
+impl<'p, 's> Interpreter<'_, '_, 'p, 's> {
+    /// Pauses before `callee` is called with `arg` at `span`, if the debugger
+    /// wants to, where `env` is the frame the call is made from.
+    pub(super) fn pause(&mut self, callee: &hir::Expr<'s>, arg: &Value, span: Span, env: Gc) {
+        let depth = self.depth;
+        let Some(attached) = &mut self.debugger else {
+            return;
+        };
+        let stepped = match attached.step {
+            Step::Continue => false,
+            Step::Into => true,
+            Step::Over => depth <= attached.depth,
+            Step::Out => depth < attached.depth,
+        };
+        if !stepped && !attached.debugger.breakpoint(span) {
+            return;
+        }
+
+        let pause = Pause {
+            span,
+            depth,
+            callee: self.name(callee).map(String::from),
+            arg: self.heap.display(arg).to_string(),
+            bindings: self.bindings(env),
+        };
+        let attached = self.debugger.as_mut().unwrap();
+        attached.step = attached.debugger.pause(&pause);
+        attached.depth = depth;
+    }
+
+    /// The named variables of `env` and the frames it's inside of.
+    fn bindings(&self, env: Gc) -> Vec<(String, String)> {
+        let mut bindings = Vec::new();
+        let mut next = Some(env);
+        while let Some(env) = next {
+            let frame = self.heap.frame(env);
+            let mut vars = frame.vars.iter().collect::<Vec<_>>();
+            vars.sort_by_key(|(var, _)| **var);
+            for (var, value) in vars {
+                if let Some(name) = self.vars.get(var.0 as usize).and_then(|var| var.name) {
+                    bindings.push((name.0.to_string(), self.heap.display(value).to_string()));
+                }
+            }
+            next = frame.parent;
+        }
+        bindings
+    }
+}
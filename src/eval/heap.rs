@@ -0,0 +1,302 @@
+//! The interpreter's garbage-collected heap.
+//!
+//! Everything that can be part of a cycle lives here: tuples, records,
+//! variants, closures, references and the frames that closures keep alive.
+//! Values refer to heap objects through [Gc] handles, which are indices into
+//! the heap's slots.
+//!
+//! Collection is mark-and-sweep. It only ever happens while allocating, and
+//! the interpreter passes in everything it's holding on to at that point as
+//! roots, so nothing else has to be tracked.
+//!
+//! Strings and big integers are reference counted rather than collected, but
+//! the bytes they take up are still counted against the heap: each one made
+//! is added to the count, and each collection recounts the ones that are
+//! still reachable.
+use std::{
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    hir::{self, VarId},
+    tokenizer::Intern,
+};
+
+use super::Value;
+
+/// How many objects are allocated before the first collection.
+const INITIAL_THRESHOLD: usize = 1 << 12;
+
+/// How many bytes of strings and big integers are made before the first
+/// collection.
+const INITIAL_BYTES: usize = 1 << 20;
+
+/// A handle to an object on the [Heap].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gc(u32);
+
+#[derive(Debug)]
+pub enum Object<'p, 's> {
+    Tuple(Box<[Value]>),
+    Record(Box<[(Intern<'s>, Value)]>),
+    Variant(Intern<'s>, Value),
+    Closure(Closure<'p, 's>),
+    Ref(Value),
+    Frame(Frame),
+}
+
+#[derive(Debug)]
+pub struct Closure<'p, 's> {
+    pub param: VarId,
+    pub body: &'p hir::Expr<'s>,
+    pub env: Gc,
+}
+
+/// The variables bound while evaluating a call, or the globals.
+#[derive(Debug)]
+pub struct Frame {
+    pub vars: FxHashMap<VarId, Value>,
+    pub parent: Option<Gc>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapOptions {
+    /// How many objects may be live at once before evaluation fails.
+    pub max_objects: usize,
+    /// How many bytes the strings and big integers that are live at once
+    /// may take up before evaluation fails.
+    pub max_bytes: usize,
+    /// Whether to print [GcStats] once the program is done.
+    pub stats: bool,
+}
+
+impl Default for HeapOptions {
+    fn default() -> Self {
+        HeapOptions {
+            max_objects: 1 << 26,
+            max_bytes: usize::MAX,
+            stats: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GcStats {
+    pub collections: usize,
+    pub allocated: usize,
+    pub freed: usize,
+    /// The most objects that were live at once.
+    pub peak: usize,
+}
+
+impl Display for GcStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} collections, {} objects allocated, {} freed, at most {} live",
+            self.collections, self.allocated, self.freed, self.peak
+        )
+    }
+}
+
+pub struct Heap<'p, 's> {
+    slots: Vec<Option<Object<'p, 's>>>,
+    marks: Vec<bool>,
+    free: Vec<u32>,
+    live: usize,
+    /// How many objects may be live before the next collection.
+    threshold: usize,
+    max_objects: usize,
+    /// The bytes of the strings and big integers that were reachable at the
+    /// last collection, and of those made since.
+    bytes: usize,
+    /// How many bytes there may be before the next collection.
+    byte_threshold: usize,
+    max_bytes: usize,
+    stats: GcStats,
+}
+
+impl<'p, 's> Heap<'p, 's> {
+    pub fn new(options: HeapOptions) -> Heap<'p, 's> {
+        Heap {
+            slots: Vec::new(),
+            marks: Vec::new(),
+            free: Vec::new(),
+            live: 0,
+            threshold: INITIAL_THRESHOLD.min(options.max_objects),
+            max_objects: options.max_objects,
+            bytes: 0,
+            byte_threshold: INITIAL_BYTES.min(options.max_bytes),
+            max_bytes: options.max_bytes,
+            stats: GcStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> GcStats {
+        self.stats
+    }
+
+    pub fn get(&self, gc: Gc) -> &Object<'p, 's> {
+        self.slots[gc.0 as usize]
+            .as_ref()
+            .expect("object should be live")
+    }
+
+    pub fn get_mut(&mut self, gc: Gc) -> &mut Object<'p, 's> {
+        self.slots[gc.0 as usize]
+            .as_mut()
+            .expect("object should be live")
+    }
+
+    pub fn frame(&self, gc: Gc) -> &Frame {
+        match self.get(gc) {
+            Object::Frame(frame) => frame,
+            object => unreachable!("expected a frame but got {object:?}"),
+        }
+    }
+
+    pub fn frame_mut(&mut self, gc: Gc) -> &mut Frame {
+        match self.get_mut(gc) {
+            Object::Frame(frame) => frame,
+            object => unreachable!("expected a frame but got {object:?}"),
+        }
+    }
+
+    /// Whether the next allocation should collect first.
+    pub fn should_collect(&self) -> bool {
+        self.live >= self.threshold || self.bytes >= self.byte_threshold
+    }
+
+    /// Whether making a string or big integer of `bytes` should collect
+    /// first.
+    pub fn should_collect_for(&self, bytes: usize) -> bool {
+        self.bytes.saturating_add(bytes) > self.byte_threshold
+    }
+
+    /// Counts a string or big integer of `bytes` that's about to be made, or
+    /// returns false if there's no room for it. Like [Heap::alloc], this
+    /// never collects.
+    pub fn charge(&mut self, bytes: usize) -> bool {
+        match self.bytes.checked_add(bytes) {
+            Some(total) if total <= self.max_bytes => {
+                self.bytes = total;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Allocates an object, or returns `None` if the heap is full. This
+    /// never collects, so [Heap::should_collect] has to be checked first.
+    pub fn alloc(&mut self, object: Object<'p, 's>) -> Option<Gc> {
+        if self.live >= self.max_objects {
+            return None;
+        }
+
+        self.live += 1;
+        self.stats.allocated += 1;
+        self.stats.peak = self.stats.peak.max(self.live);
+        Some(match self.free.pop() {
+            Some(index) => {
+                self.slots[index as usize] = Some(object);
+                Gc(index)
+            }
+            None => {
+                self.slots.push(Some(object));
+                self.marks.push(false);
+                Gc(self.slots.len() as u32 - 1)
+            }
+        })
+    }
+
+    /// Frees every object that can't be reached from the roots: the given
+    /// values and frames, and what `pending` refers to.
+    pub fn collect(&mut self, values: &[Value], frames: &[Gc], pending: Option<&Object>) {
+        let mut reached = Reached::default();
+        values.iter().for_each(|value| reached.push(value));
+        reached.worklist.extend_from_slice(frames);
+        if let Some(object) = pending {
+            reached.children(object);
+        }
+
+        while let Some(gc) = reached.worklist.pop() {
+            let mark = &mut self.marks[gc.0 as usize];
+            if !*mark {
+                *mark = true;
+                reached.children(self.get(gc));
+            }
+        }
+
+        for (index, (slot, mark)) in self.slots.iter_mut().zip(&mut self.marks).enumerate() {
+            if std::mem::take(mark) {
+                continue;
+            }
+            if slot.take().is_some() {
+                self.free.push(index as u32);
+                self.live -= 1;
+                self.stats.freed += 1;
+            }
+        }
+
+        self.stats.collections += 1;
+        self.threshold = (self.live * 2).max(INITIAL_THRESHOLD).min(self.max_objects);
+        self.bytes = reached.bytes;
+        self.byte_threshold = (self.bytes.saturating_mul(2))
+            .max(INITIAL_BYTES)
+            .min(self.max_bytes);
+    }
+}
+
+/// What a collection has reached so far.
+#[derive(Default)]
+struct Reached {
+    /// The objects whose children are still to be reached.
+    worklist: Vec<Gc>,
+    /// The addresses of the strings and big integers reached, so that one
+    /// shared by several values is counted once.
+    shared: FxHashSet<usize>,
+    /// The bytes that those strings and big integers take up.
+    bytes: usize,
+}
+
+impl Reached {
+    fn push(&mut self, value: &Value) {
+        match value {
+            Value::Tuple(gc)
+            | Value::Record(gc)
+            | Value::Variant(gc)
+            | Value::Closure(gc)
+            | Value::Ref(gc) => self.worklist.push(*gc),
+            Value::BigInt(i) if self.shared.insert(Rc::as_ptr(i) as usize) => {
+                self.bytes += size_of_val(i.limbs());
+            }
+            Value::String(s) if self.shared.insert(Rc::as_ptr(s) as *const u8 as usize) => {
+                self.bytes += s.len();
+            }
+            Value::Int(_)
+            | Value::Float(_)
+            | Value::BigInt(_)
+            | Value::String(_)
+            | Value::Bool(_)
+            | Value::Unit
+            | Value::Builtin(_)
+            | Value::Extern(_) => {}
+        }
+    }
+
+    fn children(&mut self, object: &Object) {
+        match object {
+            Object::Tuple(items) => items.iter().for_each(|item| self.push(item)),
+            Object::Record(fields) => fields.iter().for_each(|(_, value)| self.push(value)),
+            Object::Variant(_, value) | Object::Ref(value) => self.push(value),
+            Object::Closure(closure) => self.worklist.push(closure.env),
+            Object::Frame(frame) => {
+                frame.vars.values().for_each(|value| self.push(value));
+                self.worklist.extend(frame.parent);
+            }
+        }
+    }
+}
@@ -0,0 +1,735 @@
+//! A tree-walking interpreter for the HIR.
+//!
+//! Each call gets a [Frame] holding the variables bound while evaluating the
+//! callee's body, and closures keep the frame they were created in alive.
+//! Since lowering gives every variable in the program its own [VarId], a
+//! frame never has to shadow anything, and the defs of a recursive group can
+//! simply be added to the current frame after the closures that refer to
+//! them have been created.
+//!
+//! Frames and compound values live on a garbage-collected [Heap]. Whatever
+//! the interpreter holds on to while it evaluates something that might
+//! allocate has to be reachable from its roots: the frames of the calls in
+//! progress, and the values pushed onto its stack.
+//!
+//! A call that lowering marked as a tail call doesn't nest inside the call
+//! it's made from: the caller's frame is dropped and the callee runs in its
+//! place, so a function that ends by calling itself runs in constant stack
+//! however many times it recurs.
+use std::{io::Write, rc::Rc, time::Instant};
+
+use rustc_hash::FxHashMap;
+
+mod debug;
+mod heap;
+mod profile;
+mod value;
+pub use debug::*;
+pub use heap::*;
+pub use profile::*;
+pub use value::*;
+
+use crate::{
+    bigint::BigInt,
+    hir::{self, ArmPattern, ExprKind, Literal, Num, Prim, Program, VarId},
+    resolver::Builtin,
+    tokenizer::Span,
+};
+
+/// How deeply calls may nest before evaluation is stopped.
+const MAX_DEPTH: usize = 10_000;
+
+/// The size of stack that the interpreter has to run on to reach
+/// [MAX_DEPTH].
+pub const STACK_SIZE: usize = 1 << 30;
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub span: Span,
+    /// The calls that were in progress, innermost first. A tail call took
+    /// the place of the call it was made from, so that one isn't here.
+    pub trace: Vec<Call>,
+}
+
+impl RuntimeError {
+    fn new(kind: RuntimeErrorKind, span: Span) -> RuntimeError {
+        RuntimeError {
+            kind,
+            span,
+            trace: Vec::new(),
+        }
+    }
+}
+
+/// A call in the trace of a [RuntimeError].
+#[derive(Debug)]
+pub struct Call {
+    /// The name of the def or member that was called, if it was one.
+    pub name: Option<String>,
+    /// Where it was called from, or `None` for `main`, which is called by
+    /// the interpreter itself.
+    pub span: Option<Span>,
+}
+
+#[derive(Debug)]
+pub enum RuntimeErrorKind {
+    DivisionByZero,
+    Overflow,
+    /// A def was used before its value was computed, which can happen when
+    /// defs that aren't functions refer to each other.
+    Uninitialized,
+    StackOverflow,
+    /// More objects were live at once than the heap allows, or their strings
+    /// and big integers took up more bytes than it allows.
+    OutOfMemory,
+    /// A value that isn't a reference was dereferenced or assigned through,
+    /// which the type checker rules out wherever it can tell.
+    NotAReference,
+    /// Evaluation made more calls than it was allowed to.
+    StepLimit,
+    /// Evaluation went on past its deadline.
+    Timeout,
+    /// An `@extern` function was called, which only compiled programs can do.
+    ExternCall(String),
+    /// No arm of a case matched its scrutinee, which lowering rules out for
+    /// every `match` it accepts.
+    NoMatch,
+    Io(std::io::Error),
+}
+
+type Result<T = Value> = std::result::Result<T, RuntimeError>;
+
+/// What the body of a lambda comes to: a value, or a tail call that's left
+/// for [Interpreter::apply] to make, with what the function was.
+enum Tail<'p, 's> {
+    Value(Value),
+    Call(Value, Value, &'p hir::Expr<'s>, Span),
+}
+
+/// Evaluates the globals and top-level expressions of `program`, and then
+/// calls `main` with `()` if it's given. Output from `print` goes to `out`.
+pub fn run<'p, 's>(
+    program: &'p Program<'s>,
+    main: Option<VarId>,
+    out: &mut dyn Write,
+    heap: &mut Heap<'p, 's>,
+) -> Result {
+    execute(
+        Interpreter::new(heap, out, None, &program.vars),
+        program,
+        main,
+    )
+}
+
+/// Runs `program` like [run], failing with [RuntimeErrorKind::Timeout] if
+/// it's still going at `deadline`.
+pub fn run_until<'p, 's>(
+    program: &'p Program<'s>,
+    main: Option<VarId>,
+    out: &mut dyn Write,
+    heap: &mut Heap<'p, 's>,
+    deadline: Instant,
+) -> Result {
+    let mut interpreter = Interpreter::new(heap, out, None, &program.vars);
+    interpreter.deadline = Some(deadline);
+    execute(interpreter, program, main)
+}
+
+fn execute<'p, 's>(
+    mut interpreter: Interpreter<'_, '_, 'p, 's>,
+    program: &'p Program<'s>,
+    main: Option<VarId>,
+) -> Result {
+    let globals = interpreter.alloc(Object::Frame(Frame::new(None)), Span { start: 0, end: 0 })?;
+    interpreter.frames.push(globals);
+
+    for group in program.globals.iter() {
+        interpreter.group(group, globals)?;
+    }
+    let mut result = Value::Unit;
+    for expr in program.init.iter() {
+        result = interpreter.expr(expr, globals)?;
+    }
+
+    if let Some(main) = main {
+        let main = interpreter.get(globals, main).unwrap();
+        result = interpreter
+            .apply(main, Value::Unit, None, Span { start: 0, end: 0 })
+            .map_err(|mut err| {
+                if let Some(call) = err.trace.last_mut() {
+                    *call = Call {
+                        name: Some("main".to_string()),
+                        span: None,
+                    };
+                }
+                err
+            })?;
+    }
+
+    Ok(result)
+}
+
+/// Evaluates expressions one at a time in a shared frame, with a limit on
+/// how many calls each of them may make. This is how `@const` defs are
+/// evaluated at compile time, where a program that doesn't terminate has to
+/// be stopped.
+pub struct Evaluator<'p, 's> {
+    heap: Heap<'p, 's>,
+    frame: Gc,
+    steps: usize,
+}
+
+impl<'p, 's> Evaluator<'p, 's> {
+    pub fn new(steps: usize) -> Evaluator<'p, 's> {
+        let mut heap = Heap::new(HeapOptions::default());
+        let frame = heap.alloc(Object::Frame(Frame::new(None))).unwrap();
+        Evaluator { heap, frame, steps }
+    }
+
+    /// The heap that the values this returns live on.
+    pub fn heap(&self) -> &Heap<'p, 's> {
+        &self.heap
+    }
+
+    /// Binds `var` for the expressions evaluated after this, which also keeps
+    /// `value` alive.
+    pub fn bind(&mut self, var: VarId, value: Value) {
+        self.heap.frame_mut(self.frame).vars.insert(var, value);
+    }
+
+    /// Evaluates `expr`. Anything it prints is discarded. The result has to
+    /// be bound before the next evaluation, or it might be collected.
+    pub fn eval(&mut self, expr: &'p hir::Expr<'s>) -> Result {
+        let mut out = std::io::sink();
+        let mut interpreter = Interpreter::new(&mut self.heap, &mut out, Some(self.steps), &[]);
+        interpreter.frames.push(self.frame);
+        interpreter.expr(expr, self.frame)
+    }
+}
+
+impl Frame {
+    fn new(parent: Option<Gc>) -> Frame {
+        Frame {
+            vars: FxHashMap::default(),
+            parent,
+        }
+    }
+}
+
+struct Interpreter<'h, 'o, 'p, 's> {
+    heap: &'h mut Heap<'p, 's>,
+    out: &'o mut dyn Write,
+    depth: usize,
+    /// How many more calls may be made, if that's limited.
+    steps: Option<usize>,
+    /// When to stop making calls, if evaluation has a deadline.
+    deadline: Option<Instant>,
+    /// Values that are being held on to while something else is evaluated.
+    stack: Vec<Value>,
+    /// The frames of the calls in progress.
+    frames: Vec<Gc>,
+    /// The variables of the program, which name the functions in traces.
+    vars: &'p [hir::Var<'s>],
+    /// What to pause for, when debugging.
+    debugger: Option<Attached<'o>>,
+    /// What to count evaluation in, when profiling.
+    profile: Option<&'o mut Profile<'s>>,
+}
+
+impl<'h, 'o, 'p, 's> Interpreter<'h, 'o, 'p, 's> {
+    fn new(
+        heap: &'h mut Heap<'p, 's>,
+        out: &'o mut dyn Write,
+        steps: Option<usize>,
+        vars: &'p [hir::Var<'s>],
+    ) -> Interpreter<'h, 'o, 'p, 's> {
+        Interpreter {
+            heap,
+            out,
+            depth: 0,
+            steps,
+            deadline: None,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            vars,
+            debugger: None,
+            profile: None,
+        }
+    }
+
+    /// Allocates an object, collecting garbage first if it's time to.
+    fn alloc(&mut self, object: Object<'p, 's>, span: Span) -> Result<Gc> {
+        if self.heap.should_collect() {
+            self.heap.collect(&self.stack, &self.frames, Some(&object));
+        }
+        self.heap
+            .alloc(object)
+            .ok_or(RuntimeError::new(RuntimeErrorKind::OutOfMemory, span))
+    }
+
+    /// Makes room for a string or big integer of at most `bytes`, collecting
+    /// garbage first if it's time to.
+    fn reserve(&mut self, bytes: usize, span: Span) -> Result<()> {
+        if self.heap.should_collect_for(bytes) {
+            self.heap.collect(&self.stack, &self.frames, None);
+        }
+        match self.heap.charge(bytes) {
+            true => Ok(()),
+            false => Err(RuntimeError::new(RuntimeErrorKind::OutOfMemory, span)),
+        }
+    }
+
+    fn get(&self, mut env: Gc, var: VarId) -> Option<Value> {
+        loop {
+            let frame = self.heap.frame(env);
+            match frame.vars.get(&var) {
+                Some(value) => return Some(value.clone()),
+                None => env = frame.parent?,
+            }
+        }
+    }
+
+    fn set(&mut self, env: Gc, var: VarId, value: Value) {
+        self.heap.frame_mut(env).vars.insert(var, value);
+    }
+
+    /// Evaluates `exprs` in order, keeping the results alive until they're
+    /// all done.
+    fn exprs(
+        &mut self,
+        exprs: impl IntoIterator<Item = &'p hir::Expr<'s>>,
+        env: Gc,
+    ) -> Result<Vec<Value>> {
+        let base = self.stack.len();
+        for expr in exprs {
+            let value = self.expr(expr, env)?;
+            self.stack.push(value);
+        }
+        Ok(self.stack.split_off(base))
+    }
+
+    fn expr(&mut self, expr: &'p hir::Expr<'s>, env: Gc) -> Result {
+        self.tick();
+        let span = expr.span;
+        Ok(match &expr.kind {
+            ExprKind::Literal(lit) => match *lit {
+                Literal::Int(i) => Value::Int(i),
+                Literal::Float(x) => Value::Float(x),
+                Literal::BigInt(digits) => Value::BigInt(Rc::new(BigInt::parse(digits.0).unwrap())),
+                Literal::String(s) => Value::String(s.0.into()),
+                Literal::Bool(b) => Value::Bool(b),
+                Literal::Unit => Value::Unit,
+            },
+            ExprKind::Var(var) => self
+                .get(env, *var)
+                .ok_or(RuntimeError::new(RuntimeErrorKind::Uninitialized, span))?,
+            ExprKind::Builtin(builtin) => Value::Builtin(*builtin),
+            ExprKind::Extern(external) => Value::Extern(external.name.0.into()),
+            ExprKind::Lambda { param, body, .. } => {
+                let closure = Closure {
+                    param: *param,
+                    body,
+                    env,
+                };
+                Value::Closure(self.alloc(Object::Closure(closure), span)?)
+            }
+            ExprKind::Apply { func, arg, .. } => {
+                let callee = &**func;
+                let [func, arg] = <[_; 2]>::try_from(self.exprs([&**func, &**arg], env)?).unwrap();
+                self.pause(callee, &arg, span, env);
+                self.apply(func, arg, Some(callee), span)?
+            }
+            ExprKind::Let { var, value, body } => {
+                let value = self.expr(value, env)?;
+                self.set(env, *var, value);
+                self.expr(body, env)?
+            }
+            ExprKind::LetRec { group, body } => {
+                self.group(group, env)?;
+                self.expr(body, env)?
+            }
+            ExprKind::Case { scrutinee, arms } => {
+                let arm = self.arm(scrutinee, arms, env)?;
+                self.expr(arm, env)?
+            }
+            ExprKind::Seq(items) => {
+                let mut result = Value::Unit;
+                for item in items.iter() {
+                    result = self.expr(item, env)?;
+                }
+                result
+            }
+            ExprKind::Tuple(items) => {
+                let items = self.exprs(items.iter(), env)?;
+                Value::Tuple(self.alloc(Object::Tuple(items.into()), span)?)
+            }
+            ExprKind::Project { tuple, index } => match self.expr(tuple, env)? {
+                Value::Tuple(gc) => match self.heap.get(gc) {
+                    Object::Tuple(items) => items[*index].clone(),
+                    object => unreachable!("projected out of {object:?}"),
+                },
+                value => unreachable!("projected out of {value:?}"),
+            },
+            ExprKind::Record(fields) => {
+                let values = self.exprs(fields.iter().map(|(_, value)| value), env)?;
+                let fields = fields.iter().map(|(name, _)| *name).zip(values).collect();
+                Value::Record(self.alloc(Object::Record(fields), span)?)
+            }
+            ExprKind::Field { record, name } => match self.expr(record, env)? {
+                Value::Record(gc) => match self.heap.get(gc) {
+                    Object::Record(fields) => fields
+                        .iter()
+                        .find(|(n, _)| n.0 == name.0)
+                        .map(|(_, value)| value.clone())
+                        .unwrap(),
+                    object => unreachable!("accessed a field of {object:?}"),
+                },
+                value => unreachable!("accessed a field of {value:?}"),
+            },
+            ExprKind::Variant { name, payload } => {
+                let payload = self.expr(payload, env)?;
+                Value::Variant(self.alloc(Object::Variant(*name, payload), span)?)
+            }
+            ExprKind::NewRef(value) => {
+                let value = self.expr(value, env)?;
+                Value::Ref(self.alloc(Object::Ref(value), span)?)
+            }
+            ExprKind::Load(cell) => match self.expr(cell, env)? {
+                Value::Ref(gc) => match self.heap.get(gc) {
+                    Object::Ref(value) => value.clone(),
+                    object => unreachable!("dereferenced {object:?}"),
+                },
+                _ => return Err(RuntimeError::new(RuntimeErrorKind::NotAReference, span)),
+            },
+            ExprKind::Store { cell, value } => {
+                let [cell, value] =
+                    <[_; 2]>::try_from(self.exprs([&**cell, &**value], env)?).unwrap();
+                match cell {
+                    Value::Ref(gc) => *self.heap.get_mut(gc) = Object::Ref(value),
+                    _ => return Err(RuntimeError::new(RuntimeErrorKind::NotAReference, span)),
+                }
+                Value::Unit
+            }
+            ExprKind::Prim { op, args } => {
+                let args = self.exprs(args.iter(), env)?;
+                // the operands are roots while room is made for the result
+                let base = self.stack.len();
+                self.stack.extend_from_slice(&args);
+                let reserved = self.reserve(payload(*op, &args), span);
+                self.stack.truncate(base);
+                reserved?;
+                prim(self.heap, *op, &args, span)?
+            }
+        })
+    }
+
+    /// Evaluates the body of a lambda, stopping short of making a tail call.
+    fn body(&mut self, expr: &'p hir::Expr<'s>, env: Gc) -> Result<Tail<'p, 's>> {
+        match &expr.kind {
+            ExprKind::Apply {
+                func,
+                arg,
+                tail: true,
+            } => {
+                let callee = &**func;
+                let [func, arg] = <[_; 2]>::try_from(self.exprs([&**func, &**arg], env)?).unwrap();
+                self.pause(callee, &arg, expr.span, env);
+                Ok(Tail::Call(func, arg, callee, expr.span))
+            }
+            ExprKind::Let { var, value, body } => {
+                let value = self.expr(value, env)?;
+                self.set(env, *var, value);
+                self.body(body, env)
+            }
+            ExprKind::LetRec { group, body } => {
+                self.group(group, env)?;
+                self.body(body, env)
+            }
+            ExprKind::Case { scrutinee, arms } => {
+                let arm = self.arm(scrutinee, arms, env)?;
+                self.body(arm, env)
+            }
+            ExprKind::Seq(items) if !items.is_empty() => {
+                let (last, rest) = items.split_last().unwrap();
+                for item in rest {
+                    self.expr(item, env)?;
+                }
+                self.body(last, env)
+            }
+            _ => self.expr(expr, env).map(Tail::Value),
+        }
+    }
+
+    /// Evaluates the scrutinee of a case, binds the payload of the variant
+    /// it is if the arm it matches asks for it, and returns the arm's body.
+    fn arm(
+        &mut self,
+        scrutinee: &'p hir::Expr<'s>,
+        arms: &'p [hir::Arm<'s>],
+        env: Gc,
+    ) -> Result<&'p hir::Expr<'s>> {
+        let scrutinee_span = scrutinee.span;
+        let scrutinee = self.expr(scrutinee, env)?;
+        let variant = match &scrutinee {
+            Value::Variant(gc) => match self.heap.get(*gc) {
+                Object::Variant(case, payload) => Some((*case, payload.clone())),
+                object => unreachable!("expected a variant but got {object:?}"),
+            },
+            _ => None,
+        };
+        let arm = arms
+            .iter()
+            .find(|arm| match (&arm.pattern, &scrutinee, &variant) {
+                (ArmPattern::Bool(b), Value::Bool(value), _) => b == value,
+                (ArmPattern::Variant { name, .. }, _, Some((case, _))) => name.0 == case.0,
+                (ArmPattern::Wildcard, _, _) => true,
+                _ => false,
+            })
+            .ok_or(RuntimeError::new(RuntimeErrorKind::NoMatch, scrutinee_span))?;
+        if let (
+            ArmPattern::Variant {
+                bind: Some(bind), ..
+            },
+            Some((_, payload)),
+        ) = (&arm.pattern, variant)
+        {
+            self.set(env, *bind, payload);
+        }
+        Ok(&arm.body)
+    }
+
+    fn group(&mut self, group: &'p hir::Group<'s>, env: Gc) -> Result<()> {
+        for (var, value) in group.bindings.iter() {
+            let value = self.expr(value, env)?;
+            self.set(env, *var, value);
+        }
+        Ok(())
+    }
+
+    /// Calls `func`, which was the value of `callee` if that's known, with
+    /// the call at `span`.
+    fn apply(
+        &mut self,
+        mut func: Value,
+        mut arg: Value,
+        mut callee: Option<&'p hir::Expr<'s>>,
+        mut span: Span,
+    ) -> Result {
+        // each tail call the body comes to goes around again in this frame
+        loop {
+            let gc = match func {
+                Value::Closure(gc) => gc,
+                Value::Builtin(builtin) => return self.builtin(builtin, arg, span),
+                Value::Extern(name) => {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::ExternCall(name.to_string()),
+                        span,
+                    ))
+                }
+                func => unreachable!("applied {func:?}"),
+            };
+            if self.depth == MAX_DEPTH {
+                return Err(RuntimeError::new(RuntimeErrorKind::StackOverflow, span));
+            }
+            if let Some(steps) = &mut self.steps {
+                if *steps == 0 {
+                    return Err(RuntimeError::new(RuntimeErrorKind::StepLimit, span));
+                }
+                *steps -= 1;
+            }
+            if self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Err(RuntimeError::new(RuntimeErrorKind::Timeout, span));
+            }
+
+            let (param, body, env) = match self.heap.get(gc) {
+                Object::Closure(closure) => (closure.param, closure.body, closure.env),
+                object => unreachable!("applied {object:?}"),
+            };
+            let mut frame = Frame::new(Some(env));
+            frame.vars.insert(param, arg);
+            let frame = self.alloc(Object::Frame(frame), span)?;
+
+            self.frames.push(frame);
+            self.depth += 1;
+            self.enter(callee);
+            let result = self.body(body, frame);
+            self.exit();
+            self.depth -= 1;
+            self.frames.pop();
+            match result {
+                Ok(Tail::Value(value)) => return Ok(value),
+                Ok(Tail::Call(next, next_arg, next_callee, next_span)) => {
+                    (func, arg, callee, span) = (next, next_arg, Some(next_callee), next_span)
+                }
+                Err(mut err) => {
+                    err.trace.push(Call {
+                        name: callee
+                            .and_then(|callee| self.name(callee))
+                            .map(String::from),
+                        span: Some(span),
+                    });
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// The name of the function that `callee` evaluates to, if it's a def, a
+    /// member or a builtin.
+    fn name(&self, callee: &hir::Expr<'s>) -> Option<&'s str> {
+        match &callee.kind {
+            ExprKind::Var(var) => Some(self.vars.get(var.0 as usize)?.name?.0),
+            ExprKind::Field { name, .. } => Some(name.0),
+            ExprKind::Builtin(builtin) => Some(builtin.name()),
+            _ => None,
+        }
+    }
+
+    fn builtin(&mut self, builtin: Builtin, arg: Value, span: Span) -> Result {
+        let items = |arg: Value| match arg {
+            Value::Tuple(gc) => match self.heap.get(gc) {
+                Object::Tuple(items) => items.to_vec(),
+                object => unreachable!("expected a tuple but got {object:?}"),
+            },
+            arg => unreachable!("expected a tuple but got {arg:?}"),
+        };
+        let error = |kind| RuntimeError::new(kind, span);
+
+        match (builtin, arg) {
+            (Builtin::Print, arg) => {
+                writeln!(self.out, "{}", self.heap.display(&arg))
+                    .map_err(|err| error(RuntimeErrorKind::Io(err)))?;
+                Ok(Value::Unit)
+            }
+            (Builtin::Add, arg) => prim(self.heap, Prim::Add(Num::Int), &items(arg), span),
+            (Builtin::Sub, arg) => prim(self.heap, Prim::Sub(Num::Int), &items(arg), span),
+            (Builtin::Mul, arg) => prim(self.heap, Prim::Mul(Num::Int), &items(arg), span),
+            (Builtin::Div, arg) => prim(self.heap, Prim::Div(Num::Int), &items(arg), span),
+            (Builtin::Mod, arg) => prim(self.heap, Prim::Mod(Num::Int), &items(arg), span),
+            (Builtin::Neg, arg) => {
+                prim(self.heap, Prim::Sub(Num::Int), &[Value::Int(0), arg], span)
+            }
+            (Builtin::StringLength, Value::String(s)) => Ok(Value::Int(s.len() as i64)),
+            (Builtin::Substring, arg) => match &*items(arg) {
+                [Value::String(s), Value::Int(start), Value::Int(end)] => {
+                    // offsets are in bytes, and are clamped to the string
+                    let start = (*start).clamp(0, s.len() as i64) as usize;
+                    let end = (*end).clamp(start as i64, s.len() as i64) as usize;
+                    // a byte that isn't valid UTF-8 becomes three of a replacement character
+                    self.reserve((end - start) * 3, span)?;
+                    let bytes = &s.as_bytes()[start..end];
+                    Ok(Value::String(String::from_utf8_lossy(bytes).into()))
+                }
+                args => unreachable!("substring of {args:?}"),
+            },
+            (Builtin::IntToString, Value::Int(i)) => {
+                self.reserve(INT_DIGITS, span)?;
+                Ok(Value::String(i.to_string().into()))
+            }
+            (Builtin::ToFloat, Value::Int(i)) => Ok(Value::Float(i as f64)),
+            (Builtin::ToInt, Value::Float(x)) => {
+                // NaN fails both comparisons
+                if x >= -(2f64.powi(63)) && x < 2f64.powi(63) {
+                    Ok(Value::Int(x as i64))
+                } else {
+                    Err(error(RuntimeErrorKind::Overflow))
+                }
+            }
+            (Builtin::ToBig, Value::Int(i)) => {
+                self.reserve(size_of::<i64>(), span)?;
+                Ok(Value::BigInt(Rc::new(BigInt::from_i64(i))))
+            }
+            (Builtin::BigToInt, Value::BigInt(i)) => i
+                .to_i64()
+                .map(Value::Int)
+                .ok_or_else(|| error(RuntimeErrorKind::Overflow)),
+            (Builtin::BigToString, Value::BigInt(i)) => {
+                // each limb has at most 10 decimal digits, and there's a sign
+                self.reserve(i.limbs().len() * 10 + 1, span)?;
+                Ok(Value::String(i.to_string().into()))
+            }
+            (Builtin::Sqrt, Value::Float(x)) => Ok(Value::Float(x.sqrt())),
+            (Builtin::Floor, Value::Float(x)) => Ok(Value::Float(x.floor())),
+            (builtin, arg) => unreachable!("applied {} to {arg:?}", builtin.name()),
+        }
+    }
+}
+
+/// The most bytes that a string holding an `Int` takes up.
+const INT_DIGITS: usize = 20;
+
+/// The most bytes that the string or big integer that `op` makes of `args`
+/// takes up, or 0 if it doesn't make one.
+fn payload(op: Prim, args: &[Value]) -> usize {
+    match (op, args) {
+        (Prim::Concat, [Value::String(a), Value::String(b)]) => a.len() + b.len(),
+        (
+            Prim::Add(_) | Prim::Sub(_) | Prim::Mul(_) | Prim::Div(_) | Prim::Mod(_),
+            [Value::BigInt(a), Value::BigInt(b)],
+        ) => size_of_val(a.limbs()) + size_of_val(b.limbs()) + size_of::<u32>(),
+        _ => 0,
+    }
+}
+
+fn prim(heap: &Heap, op: Prim, args: &[Value], span: Span) -> Result {
+    let error = |kind| RuntimeError::new(kind, span);
+    let int = |f: fn(i64, i64) -> Option<i64>, a: i64, b: i64| {
+        f(a, b).map(Value::Int).ok_or_else(|| {
+            error(if b == 0 {
+                RuntimeErrorKind::DivisionByZero
+            } else {
+                RuntimeErrorKind::Overflow
+            })
+        })
+    };
+
+    Ok(match (op, args) {
+        (Prim::Add(_), [Value::Int(a), Value::Int(b)]) => int(i64::checked_add, *a, *b)?,
+        (Prim::Sub(_), [Value::Int(a), Value::Int(b)]) => int(i64::checked_sub, *a, *b)?,
+        (Prim::Mul(_), [Value::Int(a), Value::Int(b)]) => int(i64::checked_mul, *a, *b)?,
+        (Prim::Div(_), [Value::Int(a), Value::Int(b)]) => int(i64::checked_div, *a, *b)?,
+        (Prim::Mod(_), [Value::Int(a), Value::Int(b)]) => int(i64::checked_rem, *a, *b)?,
+        (Prim::Add(_), [Value::Float(a), Value::Float(b)]) => Value::Float(a + b),
+        (Prim::Sub(_), [Value::Float(a), Value::Float(b)]) => Value::Float(a - b),
+        (Prim::Mul(_), [Value::Float(a), Value::Float(b)]) => Value::Float(a * b),
+        (Prim::Div(_), [Value::Float(a), Value::Float(b)]) => Value::Float(a / b),
+        (Prim::Mod(_), [Value::Float(a), Value::Float(b)]) => Value::Float(a % b),
+        (Prim::Add(_), [Value::BigInt(a), Value::BigInt(b)]) => Value::BigInt(Rc::new(a.add(b))),
+        (Prim::Sub(_), [Value::BigInt(a), Value::BigInt(b)]) => Value::BigInt(Rc::new(a.sub(b))),
+        (Prim::Mul(_), [Value::BigInt(a), Value::BigInt(b)]) => Value::BigInt(Rc::new(a.mul(b))),
+        (Prim::Div(_) | Prim::Mod(_), [Value::BigInt(a), Value::BigInt(b)]) => {
+            let (quotient, remainder) = a
+                .div_rem(b)
+                .ok_or_else(|| error(RuntimeErrorKind::DivisionByZero))?;
+            let result = if matches!(op, Prim::Div(_)) {
+                quotient
+            } else {
+                remainder
+            };
+            Value::BigInt(Rc::new(result))
+        }
+        (Prim::Lt(_), [Value::Int(a), Value::Int(b)]) => Value::Bool(a < b),
+        (Prim::LtEq(_), [Value::Int(a), Value::Int(b)]) => Value::Bool(a <= b),
+        (Prim::Gt(_), [Value::Int(a), Value::Int(b)]) => Value::Bool(a > b),
+        (Prim::GtEq(_), [Value::Int(a), Value::Int(b)]) => Value::Bool(a >= b),
+        (Prim::Lt(_), [Value::Float(a), Value::Float(b)]) => Value::Bool(a < b),
+        (Prim::LtEq(_), [Value::Float(a), Value::Float(b)]) => Value::Bool(a <= b),
+        (Prim::Gt(_), [Value::Float(a), Value::Float(b)]) => Value::Bool(a > b),
+        (Prim::GtEq(_), [Value::Float(a), Value::Float(b)]) => Value::Bool(a >= b),
+        (Prim::Lt(_), [Value::BigInt(a), Value::BigInt(b)]) => Value::Bool(a < b),
+        (Prim::LtEq(_), [Value::BigInt(a), Value::BigInt(b)]) => Value::Bool(a <= b),
+        (Prim::Gt(_), [Value::BigInt(a), Value::BigInt(b)]) => Value::Bool(a > b),
+        (Prim::GtEq(_), [Value::BigInt(a), Value::BigInt(b)]) => Value::Bool(a >= b),
+        (Prim::Concat, [Value::String(a), Value::String(b)]) => {
+            Value::String(format!("{a}{b}").into())
+        }
+        (Prim::Eq, [a, b]) => Value::Bool(heap.equals(a, b)),
+        (Prim::NotEq, [a, b]) => Value::Bool(!heap.equals(a, b)),
+        (Prim::Not, [Value::Bool(b)]) => Value::Bool(!b),
+        (op, args) => unreachable!("{op} applied to {args:?}"),
+    })
+}
@@ -0,0 +1,132 @@
+//! Counting how much evaluation each stack of calls does, for
+//! `radi run --profile`.
+//!
+//! Rather than sampling, the profiler counts the expressions evaluated while
+//! each stack of calls is in progress, so a program profiles the same way
+//! on every run. A stack is made of the names of the functions called, as
+//! traces name them, so that the calls of a def made from different places
+//! add up. A tail call takes the place of its caller on the stack, as it
+//! does in the interpreter.
+use std::{fmt::Write as _, io::Write};
+
+use rustc_hash::FxHashMap;
+
+use super::{execute, Heap, Interpreter, Result};
+use crate::hir::{self, Program, VarId};
+
+/// How many expressions each stack of calls evaluated.
+#[derive(Debug)]
+pub struct Profile<'s> {
+    /// The stacks that have been in progress, each as the stack it was
+    /// called from and the name of its innermost call. The first is the
+    /// stack of top-level code, which has no calls.
+    stacks: Vec<(usize, &'s str)>,
+    /// The stack that calling each name from each stack makes.
+    calls: FxHashMap<(usize, &'s str), usize>,
+    /// How many expressions each stack evaluated itself, rather than in the
+    /// calls it made.
+    counts: Vec<u64>,
+    /// The stack in progress.
+    current: usize,
+}
+
+impl Default for Profile<'_> {
+    fn default() -> Self {
+        Profile {
+            stacks: vec![(0, "(top-level)")],
+            calls: FxHashMap::default(),
+            counts: vec![0],
+            current: 0,
+        }
+    }
+}
+
+impl<'s> Profile<'s> {
+    fn enter(&mut self, name: &'s str) {
+        let next = self.stacks.len();
+        let stack = *self.calls.entry((self.current, name)).or_insert(next);
+        if stack == next {
+            self.stacks.push((self.current, name));
+            self.counts.push(0);
+        }
+        self.current = stack;
+    }
+
+    fn exit(&mut self) {
+        self.current = self.stacks[self.current].0;
+    }
+
+    /// The profile as collapsed stacks, the format that `flamegraph.pl` and
+    /// `inferno` read: a line for each stack that evaluated anything, with
+    /// the names of its calls from the outermost in separated by `;`, and
+    /// then its count. The lines are sorted.
+    pub fn collapsed(&self) -> String {
+        let mut lines = Vec::new();
+        for (stack, &count) in self.counts.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let mut names = Vec::new();
+            let mut next = stack;
+            while next != 0 {
+                let (caller, name) = self.stacks[next];
+                names.push(name);
+                next = caller;
+            }
+            if names.is_empty() {
+                names.push(self.stacks[0].1);
+            }
+            names.reverse();
+            lines.push(format!("{} {count}", names.join(";")));
+        }
+        lines.sort();
+        let mut out = String::new();
+        for line in lines {
+            let _ = writeln!(out, "{line}");
+        }
+        out
+    }
+}
+
+/// Runs `program` like [super::run], counting what it evaluates in
+/// `profile`.
+pub fn profile<'p, 's>(
+    program: &'p Program<'s>,
+    main: Option<VarId>,
+    out: &mut dyn Write,
+    heap: &mut Heap<'p, 's>,
+    profile: &mut Profile<'s>,
+) -> Result {
+    let mut interpreter = Interpreter::new(heap, out, None, &program.vars);
+    interpreter.profile = Some(profile);
+    execute(interpreter, program, main)
+}
+
+impl<'p, 's> Interpreter<'_, '_, 'p, 's> {
+    /// Counts an expression evaluated by the stack in progress.
+    pub(super) fn tick(&mut self) {
+        if let Some(profile) = &mut self.profile {
+            profile.counts[profile.current] += 1;
+        }
+    }
+
+    /// Pushes the call of `callee` onto the stack, where the only call
+    /// without a callee is the interpreter's own call of `main`.
+    pub(super) fn enter(&mut self, callee: Option<&hir::Expr<'s>>) {
+        if self.profile.is_none() {
+            return;
+        }
+        let name = match callee {
+            Some(callee) => self.name(callee).unwrap_or("(lambda)"),
+            None => "main",
+        };
+        self.profile.as_mut().unwrap().enter(name);
+    }
+
+    /// Pops the innermost call off the stack.
+    pub(super) fn exit(&mut self) {
+        if let Some(profile) = &mut self.profile {
+            profile.exit();
+        }
+    }
+}
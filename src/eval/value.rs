@@ -0,0 +1,153 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+};
+
+use crate::{bigint::BigInt, resolver::Builtin};
+
+use super::{Gc, Heap, Object};
+
+/// A value, which is either small enough to be copied around or a handle to
+/// an object on the [Heap]. Strings can't be part of a cycle, so they are
+/// shared with reference counting instead, and so are big integers.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    BigInt(Rc<BigInt>),
+    String(Rc<str>),
+    Bool(bool),
+    Unit,
+    Builtin(Builtin),
+    /// A C function, which the interpreter can't call.
+    Extern(Rc<str>),
+    Tuple(Gc),
+    Record(Gc),
+    Variant(Gc),
+    Closure(Gc),
+    Ref(Gc),
+}
+
+impl Heap<'_, '_> {
+    /// Structural equality. Functions are never equal to anything, and
+    /// references are equal if they point to the same cell.
+    pub fn equals(&self, a: &Value, b: &Value) -> bool {
+        match (a, b) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::BigInt(a), Value::BigInt(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Unit, Value::Unit) => true,
+            (Value::Tuple(a), Value::Tuple(b)) => match (self.get(*a), self.get(*b)) {
+                (Object::Tuple(a), Object::Tuple(b)) => {
+                    a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| self.equals(a, b))
+                }
+                _ => false,
+            },
+            (Value::Record(a), Value::Record(b)) => match (self.get(*a), self.get(*b)) {
+                (Object::Record(a), Object::Record(b)) => {
+                    a.len() == b.len()
+                        && a.iter().all(|(name, a)| {
+                            b.iter()
+                                .find(|(n, _)| n.0 == name.0)
+                                .is_some_and(|(_, b)| self.equals(a, b))
+                        })
+                }
+                _ => false,
+            },
+            (Value::Variant(a), Value::Variant(b)) => match (self.get(*a), self.get(*b)) {
+                (Object::Variant(a, x), Object::Variant(b, y)) => a.0 == b.0 && self.equals(x, y),
+                _ => false,
+            },
+            (Value::Ref(a), Value::Ref(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Displays a value the way `print` writes it.
+    pub fn display<'h>(&'h self, value: &'h Value) -> Displayed<'h> {
+        Displayed {
+            heap: self,
+            value,
+            nested: false,
+        }
+    }
+
+    /// Displays a value the way it's written inside other values, with
+    /// strings quoted.
+    pub fn display_quoted<'h>(&'h self, value: &'h Value) -> Displayed<'h> {
+        Displayed {
+            heap: self,
+            value,
+            nested: true,
+        }
+    }
+}
+
+pub struct Displayed<'h> {
+    heap: &'h Heap<'h, 'h>,
+    value: &'h Value,
+    /// Whether the value is inside another value, where strings are quoted.
+    nested: bool,
+}
+
+impl Displayed<'_> {
+    fn nested(&self, f: &mut Formatter<'_>, value: &Value) -> fmt::Result {
+        let nested = Displayed {
+            heap: self.heap,
+            value,
+            nested: true,
+        };
+        write!(f, "{nested}")
+    }
+}
+
+impl Display for Displayed<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.value {
+            Value::Int(i) => write!(f, "{i}"),
+            Value::Float(x) => write!(f, "{x:?}"),
+            Value::BigInt(i) => write!(f, "{i}"),
+            Value::String(s) if self.nested => write!(f, "{s:?}"),
+            Value::String(s) => write!(f, "{s}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Unit => write!(f, "()"),
+            Value::Builtin(builtin) => write!(f, "<builtin {}>", builtin.name()),
+            Value::Extern(name) => write!(f, "<extern {name}>"),
+            Value::Closure(_) => write!(f, "<function>"),
+            Value::Tuple(gc) | Value::Record(gc) | Value::Variant(gc) | Value::Ref(gc) => {
+                match self.heap.get(*gc) {
+                    Object::Tuple(items) => {
+                        write!(f, "(")?;
+                        for (i, item) in items.iter().enumerate() {
+                            if i > 0 {
+                                write!(f, ", ")?;
+                            }
+                            self.nested(f, item)?;
+                        }
+                        write!(f, ")")
+                    }
+                    Object::Record(fields) => {
+                        write!(f, ".{{")?;
+                        for (i, (name, value)) in fields.iter().enumerate() {
+                            write!(f, "{}{}: ", if i > 0 { ", " } else { " " }, name.0)?;
+                            self.nested(f, value)?;
+                        }
+                        write!(f, " }}")
+                    }
+                    Object::Variant(name, Value::Unit) => write!(f, "|{}", name.0),
+                    Object::Variant(name, payload) => {
+                        write!(f, "|{}: ", name.0)?;
+                        self.nested(f, payload)
+                    }
+                    Object::Ref(value) => {
+                        write!(f, "^")?;
+                        self.nested(f, value)
+                    }
+                    Object::Closure(_) | Object::Frame(_) => unreachable!(),
+                }
+            }
+        }
+    }
+}
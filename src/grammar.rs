@@ -0,0 +1,352 @@
+//! Grammars for `radi gen-grammar`, which editors highlight radi with.
+//! Rather than being written by hand, they're taken from the tokenizer and
+//! the parser so that they can't disagree with them: the keywords are
+//! [KEYWORDS], the operators and string escapes are whichever short strings
+//! the tokenizer reads as one, classified the way `radi highlight` classifies
+//! them, and the binary operators bind as tightly as [PRECEDENCE] says.
+
+use std::fmt::Write;
+
+use crate::{
+    char_reader::StrCharReader,
+    doc::json_string,
+    parser::PRECEDENCE,
+    resolver::{self, TokenClass},
+    source_map::SourceFile,
+    string_storage::StringStorage,
+    tokenizer::{TokenKind, Tokens, KEYWORDS},
+};
+
+/// The characters that operators and punctuation are made of.
+const SYMBOLS: &str = "!#$%&'()*+,-./:;<=>?@[\\]^`{|}~";
+
+/// A character that can't be next to a keyword or a number, since it would
+/// make them part of a name.
+const NAME_CHAR: &str = "[\\p{L}\\p{N}_]";
+
+/// The grammar, as the JSON of a `.tmLanguage.json` file.
+pub fn textmate() -> String {
+    let storage = StringStorage::new();
+    let (operators, punctuation) = symbols(&storage);
+
+    let keywords = KEYWORDS.map(|(keyword, _)| keyword).join("|");
+    let escapes = escapes(&storage)
+        .iter()
+        .map(|&ch| regex_escape(&ch.to_string()))
+        .collect::<String>();
+
+    let mut repository = vec![
+        (
+            "comment",
+            "{\"patterns\":[\
+             {\"name\":\"comment.line.double-slash.radi\",\"match\":\"//.*$\"},\
+             {\"include\":\"#block-comment\"}]}"
+                .to_string(),
+        ),
+        (
+            // block comments nest, as they do in the tokenizer
+            "block-comment",
+            format!(
+                "{{\"name\":\"comment.block.radi\",\"begin\":{},\"end\":{},\
+                 \"patterns\":[{{\"include\":\"#block-comment\"}}]}}",
+                json_string("/\\*"),
+                json_string("\\*/")
+            ),
+        ),
+        (
+            "string",
+            format!(
+                "{{\"name\":\"string.quoted.double.radi\",\"begin\":\"\\\"\",\"end\":\"\\\"\",\
+                 \"patterns\":[\
+                 {{\"name\":\"constant.character.escape.radi\",\"match\":{}}},\
+                 {{\"name\":\"invalid.illegal.escape.radi\",\"match\":{}}}]}}",
+                json_string(&format!("\\\\[{escapes}]")),
+                json_string("\\\\.")
+            ),
+        ),
+        (
+            // as `Tokens::number` reads them, with digits on both sides of a
+            // point
+            "number",
+            format!(
+                "{{\"name\":\"constant.numeric.radi\",\"match\":{}}}",
+                json_string(&format!("(?<!{NAME_CHAR})\\d+(?:\\.\\d+)?"))
+            ),
+        ),
+        (
+            "keyword",
+            format!(
+                "{{\"name\":\"keyword.control.radi\",\"match\":{}}}",
+                json_string(&format!("(?<!{NAME_CHAR})(?:{keywords})(?!{NAME_CHAR})"))
+            ),
+        ),
+    ];
+    if !operators.is_empty() {
+        repository.push((
+            "operator",
+            format!(
+                "{{\"name\":\"keyword.operator.radi\",\"match\":{}}}",
+                json_string(&alternation(&operators))
+            ),
+        ));
+    }
+    if !punctuation.is_empty() {
+        repository.push((
+            "punctuation",
+            format!(
+                "{{\"name\":\"punctuation.radi\",\"match\":{}}}",
+                json_string(&alternation(&punctuation))
+            ),
+        ));
+    }
+
+    // the top level tries every rule but the one only comments include
+    let includes = repository
+        .iter()
+        .filter(|(name, _)| *name != "block-comment")
+        .map(|(name, _)| format!("{{\"include\":{}}}", json_string(&format!("#{name}"))))
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "{{\n  \"$schema\": \"https://raw.githubusercontent.com/martinring/tmlanguage/master/tmlanguage.json\",\n  \
+         \"name\": \"radi\",\n  \"scopeName\": \"source.radi\",\n  \"fileTypes\": [\"radi\"],\n  \
+         \"patterns\": [{}],\n  \"repository\": {{\n",
+        includes.join(",")
+    );
+    for (i, (name, rule)) in repository.iter().enumerate() {
+        let comma = if i + 1 < repository.len() { "," } else { "" };
+        let _ = writeln!(out, "    {}: {rule}{comma}", json_string(name));
+    }
+    out.push_str("  }\n}\n");
+    out
+}
+
+/// A tree-sitter `grammar.js` for radi, with the binary operators at the
+/// levels of the parser's [PRECEDENCE]. It's a skeleton to build a highlighter
+/// on rather than a parser as exact as radi's: tree-sitter may ask for some
+/// of its rules to be marked as conflicts, and block comments don't nest in it
+/// without an external scanner.
+pub fn tree_sitter() -> String {
+    let storage = StringStorage::new();
+    let escapes = escapes(&storage)
+        .iter()
+        .map(|&ch| regex_escape(&ch.to_string()))
+        .collect::<String>();
+    // the binary operators are above the arrows of types, and the prefix
+    // and then suffix operators are above them
+    let binary = |level: usize| level + 2;
+    let prefix = binary(PRECEDENCE.len());
+    let suffix = prefix + 1;
+
+    let mut out = String::from(
+        "// Generated by `radi gen-grammar --format=tree-sitter`.\n\n\
+         module.exports = grammar({\n  \
+         name: \"radi\",\n\n  \
+         extras: ($) => [/\\s/, $.line_comment, $.block_comment],\n\n  \
+         word: ($) => $.name,\n\n  \
+         rules: {\n    \
+         source_file: ($) => seq(repeat($.use), optional($._scope)),\n\n    \
+         use: ($) => seq(\"use\", sep1($.name, \".\"), \";\"),\n\n    \
+         // the expressions of a scope are separated by semicolons, and the\n    \
+         // last one can go without\n    \
+         _scope: ($) =>\n      \
+         choice(\n        \
+         seq(repeat1(choice($.def, $.trait, $.impl, $.macro, seq($._expression, \";\"))), optional($._expression)),\n        \
+         $._expression,\n      \
+         ),\n\n    \
+         def: ($) =>\n      \
+         seq(\n        \
+         repeat($.attribute),\n        \
+         optional(\"pub\"),\n        \
+         \"def\",\n        \
+         field(\"name\", $.name),\n        \
+         choice(\n          \
+         seq(\"::\", field(\"type\", $._type), \";\"),\n          \
+         field(\"value\", choice($.block, $.object, $.lambda, $.case, $.match)),\n          \
+         seq(field(\"value\", $._item), \";\"),\n        \
+         ),\n      \
+         ),\n\n    \
+         attribute: ($) => seq(\"@\", $.name, optional(seq(\"(\", $.string, \")\"))),\n\n    \
+         // only the declarations of methods can go in a trait\n    \
+         trait: ($) =>\n      \
+         seq(\n        \
+         optional(\"pub\"),\n        \
+         \"trait\",\n        \
+         field(\"name\", $.name),\n        \
+         \"(\",\n        \
+         field(\"parameter\", $.name),\n        \
+         \")\",\n        \
+         \"{\",\n        \
+         repeat($.def),\n        \
+         \"}\",\n      \
+         ),\n\n    \
+         impl: ($) =>\n      \
+         seq(\"impl\", field(\"name\", $.name), \"(\", field(\"type\", $._type), \")\", \"{\", repeat($.def), \"}\"),\n\n    \
+         macro: ($) =>\n      \
+         seq(\"macro\", field(\"name\", $.name), \"(\", sep(choice($.name, $._literal), \",\"), \")\", $.block),\n\n    \
+         _expression: ($) => choice($.tuple, $._item),\n\n    \
+         tuple: ($) => seq($._item, repeat1(seq(\",\", $._item))),\n\n    \
+         _item: ($) =>\n      \
+         choice($.block, $.object, $.lambda, $.case, $.match, $.type_assertion, $._conditional),\n\n    \
+         block: ($) => seq(\"{\", optional($._scope), \"}\"),\n\n    \
+         object: ($) => seq(\".{\", optional($._scope), \"}\"),\n\n    \
+         lambda: ($) =>\n      \
+         seq(\n        \
+         field(\"parameter\", choice($.type_assertion, $._operand)),\n        \
+         field(\"body\", choice($.block, $.object)),\n      \
+         ),\n\n    \
+         // further conditions follow `else` without another `case`\n    \
+         case: ($) => seq(\"case\", $._arms),\n\n    \
+         _arms: ($) =>\n      \
+         seq(\n        \
+         field(\"condition\", $._expression),\n        \
+         field(\"consequence\", $.block),\n        \
+         optional(seq(\"else\", choice(field(\"alternative\", $.block), $._arms))),\n      \
+         ),\n\n    \
+         match: ($) =>\n      \
+         seq(\"match\", field(\"scrutinee\", $._expression), \"{\", repeat(seq($.arm, optional(\";\"))), \"}\"),\n\n    \
+         arm: ($) => seq(field(\"pattern\", $._expression), \"=>\", field(\"body\", $._expression)),\n\n    \
+         // `?` and `:` bind looser than any binary operator, and nest to the right\n    \
+         _conditional: ($) => choice($.conditional, $._operand),\n\n    \
+         conditional: ($) =>\n      \
+         prec.right(\n        \
+         seq(\n          \
+         field(\"condition\", $._operand),\n          \
+         \"?\",\n          \
+         field(\"consequence\", $._conditional),\n          \
+         \":\",\n          \
+         field(\"alternative\", $._conditional),\n        \
+         ),\n      \
+         ),\n\n    \
+         type_assertion: ($) => seq($._conditional, \"::\", $._type),\n\n    \
+         _type: ($) => choice($.arrow, $._operand),\n\n    \
+         arrow: ($) => prec.right(1, seq($._operand, \"->\", $._type)),\n\n    \
+         _operand: ($) => choice($.binary, $.unary, $._suffixed),\n\n    \
+         binary: ($) =>\n      \
+         choice(\n",
+    );
+    for (level, ops) in PRECEDENCE.iter().enumerate() {
+        let ops = ops.iter().map(|op| json_string(op.symbol()));
+        let _ = writeln!(
+            out,
+            "        prec.left({}, seq(field(\"left\", $._operand), \
+             field(\"operator\", choice({})), field(\"right\", $._operand))),",
+            binary(level),
+            ops.collect::<Vec<_>>().join(", ")
+        );
+    }
+    let _ = write!(
+        out,
+        "      ),\n\n    \
+         unary: ($) =>\n      \
+         prec({prefix}, seq(field(\"operator\", choice(\"!\", \"set\", \"val\", \"^\")), field(\"operand\", $._operand))),\n\n    \
+         _suffixed: ($) => choice($.deref, $.access, $.apply, $._atom),\n\n    \
+         deref: ($) => prec({suffix}, seq($._suffixed, \"^\")),\n\n    \
+         access: ($) => prec({suffix}, seq($._suffixed, \".\", field(\"property\", $.name))),\n\n    \
+         apply: ($) => prec({suffix}, seq(field(\"function\", $._suffixed), field(\"argument\", $._atom))),\n\n    \
+         _atom: ($) => choice($.parenthesized, $.variant, $.macro_call, $._literal, $.name),\n\n    \
+         parenthesized: ($) => seq(\"(\", optional($._scope), \")\"),\n\n    \
+         variant: ($) => prec.right(repeat1($.variant_item)),\n\n    \
+         variant_item: ($) => seq(\"|\", field(\"name\", $.name), optional(seq(\":\", field(\"value\", $._expression)))),\n\n    \
+         macro_call: ($) => seq(field(\"name\", $.name), \"!\", \"(\", sep($._item, \",\"), \")\"),\n\n    \
+         _literal: ($) => choice($.number, $.string),\n\n    \
+         number: (_) => /\\d+(\\.\\d+)?/,\n\n    \
+         string: ($) => seq('\"', repeat(choice($.escape_sequence, token.immediate(prec(1, /[^\"\\\\]+/)))), '\"'),\n\n    \
+         escape_sequence: (_) => token.immediate(/\\\\[{escapes}]/),\n\n    \
+         name: (_) => /[\\p{{L}}_][\\p{{L}}\\p{{N}}_]*/u,\n\n    \
+         line_comment: (_) => token(seq(\"//\", /.*/)),\n\n    \
+         block_comment: (_) => token(seq(\"/*\", /[^*]*\\*+([^/*][^*]*\\*+)*/, \"/\")),\n  \
+         }},\n}});\n\n\
+         function sep(rule, separator) {{\n  \
+         return optional(sep1(rule, separator));\n}}\n\n\
+         function sep1(rule, separator) {{\n  \
+         return seq(rule, repeat(seq(separator, rule)));\n}}\n"
+    );
+    out
+}
+
+/// The strings of one or two [SYMBOLS] that the tokenizer reads as a single
+/// token, split into operators and other punctuation by how they're
+/// highlighted. Each is sorted longest first, so that a regex that tries
+/// them in order matches the longest, as the tokenizer does.
+fn symbols(storage: &StringStorage) -> (Vec<String>, Vec<String>) {
+    let singles = SYMBOLS.chars().map(String::from);
+    let pairs = SYMBOLS
+        .chars()
+        .flat_map(|a| SYMBOLS.chars().map(move |b| format!("{a}{b}")));
+    let (mut operators, mut punctuation) = (Vec::new(), Vec::new());
+    for candidate in singles.chain(pairs) {
+        let mut tokens = Tokens::of(StrCharReader::new(&candidate), storage);
+        let token = match (tokens.next(), tokens.next()) {
+            (Ok(Some(token)), Ok(None)) => token,
+            _ => continue,
+        };
+        if token.span.end as usize != candidate.len() || !is_fixed(token.kind) {
+            continue;
+        }
+        let file = SourceFile {
+            path: "<grammar>".into(),
+            src: candidate.clone(),
+            start: 0,
+        };
+        let classes = resolver::semantic_tokens(&file, storage, None);
+        match classes.first().map(|token| token.class) {
+            Some(TokenClass::Operator) => operators.push(candidate),
+            _ => punctuation.push(candidate),
+        }
+    }
+    operators.sort_by_key(|operator| std::cmp::Reverse(operator.len()));
+    punctuation.sort_by_key(|punctuation| std::cmp::Reverse(punctuation.len()));
+    (operators, punctuation)
+}
+
+/// Whether a token is always spelled the same, unlike a name or a literal.
+fn is_fixed(kind: TokenKind) -> bool {
+    !matches!(
+        kind,
+        TokenKind::Float(_)
+            | TokenKind::Integer(_)
+            | TokenKind::BigInteger(_)
+            | TokenKind::Name(_)
+            | TokenKind::String(_)
+    )
+}
+
+/// The ASCII characters that can follow a `\` in a string.
+fn escapes(storage: &StringStorage) -> Vec<char> {
+    (' '..='~')
+        .chain(['\0'])
+        .filter(|&ch| {
+            let literal = format!("\"\\{ch}\"");
+            let mut tokens = Tokens::of(StrCharReader::new(&literal), storage);
+            matches!(
+                (tokens.next(), tokens.next()),
+                (Ok(Some(token)), Ok(None)) if token.span.end as usize == literal.len()
+            )
+        })
+        .collect()
+}
+
+/// A regex that matches any of `options`, trying them in order.
+fn alternation(options: &[String]) -> String {
+    let options = options.iter().map(|option| regex_escape(option));
+    options.collect::<Vec<_>>().join("|")
+}
+
+/// Escapes the characters of `text` that mean something in a regex.
+fn regex_escape(text: &str) -> String {
+    let mut out = String::new();
+    for ch in text.chars() {
+        match ch {
+            '\0' => out.push_str("\\x00"),
+            _ if "\\^$.|?*+()[]{}/-".contains(ch) => {
+                out.push('\\');
+                out.push(ch);
+            }
+            _ => out.push(ch),
+        }
+    }
+    out
+}
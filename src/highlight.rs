@@ -0,0 +1,183 @@
+//! Renders source code with each token marked by what it is, for `radi
+//! highlight` and for the excerpts shown with runtime errors. Tokens are
+//! classified by [semantic_tokens](crate::resolver::semantic_tokens), and
+//! the text between them is left as it is, apart from comments.
+
+use std::ops::Range;
+
+use crate::{
+    resolver::{SemanticToken, TokenClass},
+    source_map::SourceFile,
+    tokenizer::{trivia, PieceKind, Span},
+};
+
+/// How a piece of the source is highlighted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Plain,
+    Comment,
+    /// A token, and whether it's where a symbol is bound.
+    Token(TokenClass, bool),
+}
+
+/// Splits `file` into pieces by how they're highlighted, given the tokens
+/// of the file in source order.
+pub fn segments<'a>(file: &'a SourceFile, tokens: &[SemanticToken]) -> Vec<(&'a str, Style)> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    for token in tokens {
+        let range = file.range(token.span);
+        gap(&file.src[pos..range.start], &mut out);
+        let end = range.end;
+        out.push((
+            &file.src[range],
+            Style::Token(token.class, token.declaration),
+        ));
+        pos = end;
+    }
+    gap(&file.src[pos..], &mut out);
+    out.retain(|(text, _)| !text.is_empty());
+    out
+}
+
+/// Splits the text between two tokens into comments and the rest.
+fn gap<'a>(text: &'a str, out: &mut Vec<(&'a str, Style)>) {
+    for (text, kind) in trivia(text) {
+        let style = match kind {
+            PieceKind::LineComment | PieceKind::BlockComment => Style::Comment,
+            _ => Style::Plain,
+        };
+        out.push((text, style));
+    }
+}
+
+/// The HTML class for tokens of a class.
+fn class_name(class: TokenClass) -> &'static str {
+    match class {
+        TokenClass::Keyword => "keyword",
+        TokenClass::Def => "def",
+        TokenClass::Parameter => "parameter",
+        TokenClass::Builtin => "builtin",
+        TokenClass::Module => "module",
+        TokenClass::Property => "property",
+        TokenClass::Variant => "variant",
+        TokenClass::Operator => "operator",
+        TokenClass::Number => "number",
+        TokenClass::String => "string",
+    }
+}
+
+/// Renders `file` as an HTML `<pre>` element, with each token wrapped in a
+/// `<span>` whose class names what it is. Where a symbol is bound also has
+/// the class `declaration`.
+pub fn html(file: &SourceFile, tokens: &[SemanticToken]) -> String {
+    let mut out = String::from("<pre class=\"radi\"><code>");
+    for (text, style) in segments(file, tokens) {
+        let text = escape(text);
+        match style {
+            Style::Plain => out.push_str(&text),
+            Style::Comment => out.push_str(&format!("<span class=\"comment\">{text}</span>")),
+            Style::Token(class, declaration) => {
+                let declaration = if declaration { " declaration" } else { "" };
+                let class = class_name(class);
+                out.push_str(&format!(
+                    "<span class=\"{class}{declaration}\">{text}</span>"
+                ));
+            }
+        }
+    }
+    out.push_str("</code></pre>\n");
+    out
+}
+
+/// Escapes the characters that are special in HTML.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// The escape code that starts the color of a style.
+fn ansi_color(style: Style) -> Option<&'static str> {
+    let Style::Token(class, declaration) = style else {
+        return (style == Style::Comment).then_some("\x1b[90m");
+    };
+    Some(match class {
+        TokenClass::Keyword => "\x1b[1;35m",
+        TokenClass::Def if declaration => "\x1b[1;34m",
+        TokenClass::Def => "\x1b[34m",
+        TokenClass::Parameter => "\x1b[36m",
+        TokenClass::Builtin => "\x1b[33m",
+        TokenClass::Module => "\x1b[1;32m",
+        TokenClass::Property => "\x1b[3m",
+        TokenClass::Variant => "\x1b[35m",
+        TokenClass::Operator => return None,
+        TokenClass::Number => "\x1b[33m",
+        TokenClass::String => "\x1b[32m",
+    })
+}
+
+/// Renders `file` for a terminal, colored with ANSI escape codes.
+pub fn ansi(file: &SourceFile, tokens: &[SemanticToken]) -> String {
+    ansi_range(file, tokens, 0..file.src.len())
+}
+
+/// Renders the part of `file` in `range`, which is relative to the start of
+/// the file, colored with ANSI escape codes.
+fn ansi_range(file: &SourceFile, tokens: &[SemanticToken], range: Range<usize>) -> String {
+    let mut out = String::new();
+    let mut pos = 0;
+    for (text, style) in segments(file, tokens) {
+        let (start, end) = (pos, pos + text.len());
+        pos = end;
+        let (start, end) = (start.max(range.start), end.min(range.end));
+        if start >= end {
+            continue;
+        }
+        let text = &file.src[start..end];
+        match ansi_color(style) {
+            Some(color) => out.push_str(&format!("{color}{text}{RESET}")),
+            None => out.push_str(text),
+        }
+    }
+    out
+}
+
+const RESET: &str = "\x1b[0m";
+
+/// Renders the line of `file` that `span` starts on, with its number and
+/// with the span underlined, for showing where a diagnostic is. The line is
+/// colored if `color` is set.
+pub fn snippet(file: &SourceFile, tokens: &[SemanticToken], span: Span, color: bool) -> String {
+    let range = file.range(span);
+    let start = range.start;
+    let line_start = file.src[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = file.src[start..]
+        .find('\n')
+        .map_or(file.src.len(), |i| start + i);
+    let line = match color {
+        true => ansi_range(file, tokens, line_start..line_end),
+        false => file.src[line_start..line_end].to_string(),
+    };
+    let end = range.end.clamp(start, line_end);
+    let indent = file.src[line_start..start].chars().count();
+    let carets = file.src[start..end].chars().count().max(1);
+
+    let (number, _) = file.line_col(span.start);
+    let number = number.to_string();
+    let margin = " ".repeat(number.len());
+    let underline = format!("{}{}", " ".repeat(indent), "^".repeat(carets));
+    let underline = match color {
+        true => format!("\x1b[1;31m{underline}{RESET}"),
+        false => underline,
+    };
+    format!("{number} | {line}\n{margin} | {underline}")
+}
@@ -0,0 +1,315 @@
+//! Compile-time evaluation of `@const` defs.
+//!
+//! The value of a `@const` def is computed with the interpreter and replaced
+//! with the result, so backends only ever see the literal data. A const def
+//! whose value is a function is kept as it is, but can be called by other
+//! const defs.
+//!
+//! Const code may only refer to other const defs and to what it binds
+//! itself, and mustn't print or call C functions. Since it might not
+//! terminate, the number of calls it makes is limited.
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    errors::ErrorStream,
+    eval::{Evaluator, Object, RuntimeErrorKind, Value, STACK_SIZE},
+    resolver::Builtin,
+    string_storage::StringStorage,
+    tokenizer::{Intern, Span},
+};
+
+use super::*;
+
+/// How many calls the evaluation of a single const def may make.
+const MAX_STEPS: usize = 1_000_000;
+
+#[derive(Debug)]
+pub struct ConstError<'s> {
+    pub kind: ConstErrorKind<'s>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ConstErrorKind<'s> {
+    /// Const code referred to a variable that isn't a const def. Variables
+    /// introduced by lowering have no name.
+    NotConstant(Option<Intern<'s>>),
+    /// Const code prints or calls a C function.
+    Effect,
+    /// A const def evaluated to something other than data, like a function
+    /// or a reference.
+    NotData,
+    Runtime(RuntimeErrorKind),
+}
+
+/// Evaluates the `@const` defs of a program and embeds their values.
+pub fn evaluate_consts<'s>(
+    program: &mut Program<'s>,
+    storage: &'s StringStorage,
+    errors: &ErrorStream<'s>,
+) {
+    if !program.vars.iter().any(|var| var.constant) {
+        return;
+    }
+
+    // Const code gets as much stack as the interpreter has when it runs
+    // the program, so the results are evaluated on a thread of their own
+    // and the errors are reported once it's done.
+    let program_ref = &*program;
+    let (mut values, const_errors) = std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, move || {
+                let mut consts = Consts {
+                    program: program_ref,
+                    storage,
+                    evaluator: Evaluator::new(MAX_STEPS),
+                    known: FxHashSet::default(),
+                    failed: FxHashSet::default(),
+                    values: FxHashMap::default(),
+                    errors: Vec::new(),
+                };
+                for group in program_ref.globals.iter() {
+                    consts.group(group);
+                }
+                for expr in program_ref.init.iter() {
+                    consts.expr(expr);
+                }
+                (consts.values, consts.errors)
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    });
+    for err in const_errors {
+        errors.error(err);
+    }
+
+    for group in program.globals.iter_mut() {
+        replace_group(group, &mut values);
+    }
+    for expr in program.init.iter_mut() {
+        replace(expr, &mut values);
+    }
+}
+
+struct Consts<'p, 's> {
+    program: &'p Program<'s>,
+    storage: &'s StringStorage,
+    evaluator: Evaluator<'p, 's>,
+    /// The const defs that are bound in the evaluator.
+    known: FxHashSet<VarId>,
+    /// The const defs that couldn't be evaluated. Errors have already been
+    /// reported for them, so const defs that use them are skipped.
+    failed: FxHashSet<VarId>,
+    /// The values to replace the evaluated defs with.
+    values: FxHashMap<VarId, Expr<'s>>,
+    errors: Vec<ConstError<'s>>,
+}
+
+impl<'p, 's> Consts<'p, 's> {
+    fn expr(&mut self, expr: &'p Expr<'s>) {
+        match &expr.kind {
+            ExprKind::Let { var, value, body } => {
+                self.expr(value);
+                self.bindings(&[(*var, &**value)]);
+                self.expr(body);
+            }
+            ExprKind::LetRec { group, body } => {
+                self.group(group);
+                self.expr(body);
+            }
+            _ => expr.for_each_child(|child| self.expr(child)),
+        }
+    }
+
+    fn group(&mut self, group: &'p Group<'s>) {
+        for (_, value) in group.bindings.iter() {
+            self.expr(value);
+        }
+        let bindings = group
+            .bindings
+            .iter()
+            .map(|(var, value)| (*var, value))
+            .collect::<Vec<_>>();
+        self.bindings(&bindings);
+    }
+
+    /// Evaluates the const defs among bindings that are bound together.
+    fn bindings(&mut self, bindings: &[(VarId, &'p Expr<'s>)]) {
+        let consts = bindings
+            .iter()
+            .filter(|(var, _)| self.program.var(*var).constant)
+            .copied()
+            .collect::<Vec<_>>();
+        if consts.is_empty() {
+            return;
+        }
+
+        let group = consts.iter().map(|(var, _)| *var).collect::<FxHashSet<_>>();
+        let mut ok = true;
+        for (_, value) in &consts {
+            ok &= self.check(value, &group);
+        }
+        if !ok {
+            self.failed.extend(group);
+            return;
+        }
+
+        // Functions are bound first, so that the defs of a recursive group
+        // can call each other.
+        let (functions, data): (Vec<_>, Vec<_>) = consts
+            .into_iter()
+            .partition(|(_, value)| matches!(value.kind, ExprKind::Lambda { .. }));
+        for (var, value) in functions {
+            match self.evaluator.eval(value) {
+                Ok(closure) => {
+                    self.evaluator.bind(var, closure);
+                    self.known.insert(var);
+                }
+                Err(err) => self.fail(var, ConstErrorKind::Runtime(err.kind), err.span),
+            }
+        }
+        for (var, value) in data {
+            match self.evaluator.eval(value) {
+                Ok(result) => match self.embed(&result, value.span) {
+                    Some(expr) => {
+                        self.evaluator.bind(var, result);
+                        self.known.insert(var);
+                        self.values.insert(var, expr);
+                    }
+                    None => self.fail(var, ConstErrorKind::NotData, value.span),
+                },
+                Err(err) => self.fail(var, ConstErrorKind::Runtime(err.kind), err.span),
+            }
+        }
+    }
+
+    /// Checks that `value` only refers to const defs and doesn't print,
+    /// reporting an error otherwise. Defs in `group` are being evaluated
+    /// together with it.
+    fn check(&mut self, value: &Expr<'s>, group: &FxHashSet<VarId>) -> bool {
+        let mut bound = FxHashSet::default();
+        let mut used = Vec::new();
+        let mut ok = true;
+        walk(value, &mut |expr| match &expr.kind {
+            ExprKind::Var(var) => used.push((*var, expr.span)),
+            ExprKind::Builtin(Builtin::Print) | ExprKind::Extern(_) => {
+                self.errors.push(ConstError {
+                    kind: ConstErrorKind::Effect,
+                    span: expr.span,
+                });
+                ok = false;
+            }
+            ExprKind::Lambda { param, .. } => {
+                bound.insert(*param);
+            }
+            ExprKind::Let { var, .. } => {
+                bound.insert(*var);
+            }
+            ExprKind::LetRec { group, .. } => {
+                bound.extend(group.bindings.iter().map(|(var, _)| *var));
+            }
+            ExprKind::Case { arms, .. } => {
+                bound.extend(arms.iter().filter_map(|arm| match arm.pattern {
+                    ArmPattern::Variant { bind, .. } => bind,
+                    _ => None,
+                }));
+            }
+            _ => {}
+        });
+
+        for (var, span) in used {
+            if bound.contains(&var) || group.contains(&var) || self.known.contains(&var) {
+                continue;
+            }
+            ok = false;
+            if !self.failed.contains(&var) {
+                self.errors.push(ConstError {
+                    kind: ConstErrorKind::NotConstant(self.program.var(var).name),
+                    span,
+                });
+            }
+        }
+        ok
+    }
+
+    fn fail(&mut self, var: VarId, kind: ConstErrorKind<'s>, span: Span) {
+        self.errors.push(ConstError { kind, span });
+        self.failed.insert(var);
+    }
+
+    /// Converts the value of a const def back into an expression, if it's
+    /// data.
+    fn embed(&self, value: &Value, span: Span) -> Option<Expr<'s>> {
+        let kind = match value {
+            Value::Int(i) => ExprKind::Literal(Literal::Int(*i)),
+            Value::Float(x) => ExprKind::Literal(Literal::Float(*x)),
+            Value::BigInt(i) => {
+                ExprKind::Literal(Literal::BigInt(Intern(self.storage.intern(i.to_string()))))
+            }
+            Value::String(s) => {
+                ExprKind::Literal(Literal::String(Intern(self.storage.intern(s.to_string()))))
+            }
+            Value::Bool(b) => ExprKind::Literal(Literal::Bool(*b)),
+            Value::Unit => ExprKind::Literal(Literal::Unit),
+            Value::Tuple(gc) | Value::Record(gc) | Value::Variant(gc) => {
+                match self.evaluator.heap().get(*gc) {
+                    Object::Tuple(items) => ExprKind::Tuple(
+                        items
+                            .iter()
+                            .map(|item| self.embed(item, span))
+                            .collect::<Option<_>>()?,
+                    ),
+                    Object::Record(fields) => ExprKind::Record(
+                        fields
+                            .iter()
+                            .map(|(name, value)| Some((*name, self.embed(value, span)?)))
+                            .collect::<Option<_>>()?,
+                    ),
+                    Object::Variant(name, payload) => ExprKind::Variant {
+                        name: *name,
+                        payload: Box::new(self.embed(payload, span)?),
+                    },
+                    _ => return None,
+                }
+            }
+            Value::Builtin(builtin) => ExprKind::Builtin(*builtin),
+            Value::Closure(_) | Value::Ref(_) | Value::Extern(_) => return None,
+        };
+        Some(Expr { kind, span })
+    }
+}
+
+/// Calls `f` on `expr` and every expression within it.
+fn walk<'e, 's>(expr: &'e Expr<'s>, f: &mut impl FnMut(&'e Expr<'s>)) {
+    f(expr);
+    expr.for_each_child(|child| walk(child, f));
+}
+
+fn replace_group<'s>(group: &mut Group<'s>, values: &mut FxHashMap<VarId, Expr<'s>>) {
+    for (var, value) in group.bindings.iter_mut() {
+        match values.remove(var) {
+            Some(embedded) => *value = embedded,
+            None => replace(value, values),
+        }
+    }
+}
+
+fn replace<'s>(expr: &mut Expr<'s>, values: &mut FxHashMap<VarId, Expr<'s>>) {
+    match &mut expr.kind {
+        ExprKind::Let { var, value, body } => {
+            match values.remove(var) {
+                Some(embedded) => **value = embedded,
+                None => replace(value, values),
+            }
+            replace(body, values);
+        }
+        ExprKind::LetRec { group, body } => {
+            replace_group(group, values);
+            replace(body, values);
+        }
+        _ => expr.for_each_child_mut(|child| replace(child, values)),
+    }
+}
@@ -0,0 +1,208 @@
+//! Constant folding: operations whose operands are all literals are
+//! computed at compile time, and branches on literal booleans are replaced
+//! with the arm they take.
+//!
+//! Operations that would fail at runtime, like integer overflow, are left
+//! in place with a warning, so that they still fail if they're reached.
+
+use crate::{errors::ErrorStream, resolver::Builtin, tokenizer::Span};
+
+use super::*;
+
+#[derive(Debug)]
+pub struct FoldWarning {
+    pub kind: FoldWarningKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum FoldWarningKind {
+    /// An integer operation on literals overflows.
+    Overflow,
+    /// An integer literal is divided by zero.
+    DivisionByZero,
+}
+
+/// Folds the constant expressions in a program.
+pub fn fold<'s>(program: &mut Program<'s>, errors: &ErrorStream<'s>) {
+    let folder = Folder { errors };
+    for group in program.globals.iter_mut() {
+        folder.group(group);
+    }
+    for expr in program.init.iter_mut() {
+        folder.expr(expr);
+    }
+}
+
+struct Folder<'s, 'e> {
+    errors: &'e ErrorStream<'s>,
+}
+
+impl<'s> Folder<'s, '_> {
+    fn group(&self, group: &mut Group<'s>) {
+        for (_, value) in group.bindings.iter_mut() {
+            self.expr(value);
+        }
+    }
+
+    fn expr(&self, expr: &mut Expr<'s>) {
+        match &mut expr.kind {
+            ExprKind::Literal(_)
+            | ExprKind::Var(_)
+            | ExprKind::Builtin(_)
+            | ExprKind::Extern(_) => {}
+            ExprKind::Lambda { body, .. } => self.expr(body),
+            ExprKind::Apply { func, arg, .. } => {
+                self.expr(func);
+                self.expr(arg);
+                if let Some(lit) = self.builtin(func, arg, expr.span) {
+                    expr.kind = ExprKind::Literal(lit);
+                }
+            }
+            ExprKind::Let { value, body, .. } => {
+                self.expr(value);
+                self.expr(body);
+            }
+            ExprKind::LetRec { group, body } => {
+                self.group(group);
+                self.expr(body);
+            }
+            ExprKind::Case { scrutinee, arms } => {
+                self.expr(scrutinee);
+                for arm in arms.iter_mut() {
+                    self.expr(&mut arm.body);
+                }
+                if let ExprKind::Literal(Literal::Bool(value)) = scrutinee.kind {
+                    let taken = arms.iter().position(|arm| match arm.pattern {
+                        ArmPattern::Bool(b) => b == value,
+                        ArmPattern::Wildcard => true,
+                        ArmPattern::Variant { .. } => false,
+                    });
+                    if let Some(taken) = taken {
+                        let mut arms = std::mem::take(arms).into_vec();
+                        *expr = arms.swap_remove(taken).body;
+                    }
+                }
+            }
+            ExprKind::Seq(items) | ExprKind::Tuple(items) => {
+                for item in items.iter_mut() {
+                    self.expr(item);
+                }
+            }
+            ExprKind::Project { tuple: value, .. }
+            | ExprKind::Field { record: value, .. }
+            | ExprKind::Variant { payload: value, .. }
+            | ExprKind::NewRef(value)
+            | ExprKind::Load(value) => self.expr(value),
+            ExprKind::Record(fields) => {
+                for (_, value) in fields.iter_mut() {
+                    self.expr(value);
+                }
+            }
+            ExprKind::Store { cell, value } => {
+                self.expr(cell);
+                self.expr(value);
+            }
+            ExprKind::Prim { op, args } => {
+                for arg in args.iter_mut() {
+                    self.expr(arg);
+                }
+                let literals = args
+                    .iter()
+                    .map(|arg| match arg.kind {
+                        ExprKind::Literal(lit) => Some(lit),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>();
+                if let Some(lit) = literals.and_then(|lits| self.prim(*op, &lits, expr.span)) {
+                    expr.kind = ExprKind::Literal(lit);
+                }
+            }
+        }
+    }
+
+    /// Folds a call to an arithmetic builtin on literals.
+    fn builtin(&self, func: &Expr<'s>, arg: &Expr<'s>, span: Span) -> Option<Literal<'s>> {
+        let ExprKind::Builtin(builtin) = func.kind else {
+            return None;
+        };
+        let int = |expr: &Expr| match expr.kind {
+            ExprKind::Literal(Literal::Int(i)) => Some(Literal::Int(i)),
+            _ => None,
+        };
+
+        let op = match builtin {
+            Builtin::Neg => {
+                let x = int(arg)?;
+                return self.prim(Prim::Sub(Num::Int), &[Literal::Int(0), x], span);
+            }
+            Builtin::Add => Prim::Add(Num::Int),
+            Builtin::Sub => Prim::Sub(Num::Int),
+            Builtin::Mul => Prim::Mul(Num::Int),
+            Builtin::Div => Prim::Div(Num::Int),
+            Builtin::Mod => Prim::Mod(Num::Int),
+            _ => return None,
+        };
+        match &arg.kind {
+            ExprKind::Tuple(items) if items.len() == 2 => {
+                self.prim(op, &[int(&items[0])?, int(&items[1])?], span)
+            }
+            _ => None,
+        }
+    }
+
+    fn prim(&self, op: Prim, args: &[Literal<'s>], span: Span) -> Option<Literal<'s>> {
+        use Literal::*;
+
+        let int = |f: fn(i64, i64) -> Option<i64>, a: i64, b: i64| match f(a, b) {
+            Some(i) => Some(Int(i)),
+            None => {
+                let kind = if b == 0 {
+                    FoldWarningKind::DivisionByZero
+                } else {
+                    FoldWarningKind::Overflow
+                };
+                self.errors.warning(FoldWarning { kind, span });
+                None
+            }
+        };
+
+        match (op, args) {
+            (Prim::Add(_), [Int(a), Int(b)]) => int(i64::checked_add, *a, *b),
+            (Prim::Sub(_), [Int(a), Int(b)]) => int(i64::checked_sub, *a, *b),
+            (Prim::Mul(_), [Int(a), Int(b)]) => int(i64::checked_mul, *a, *b),
+            (Prim::Div(_), [Int(a), Int(b)]) => int(i64::checked_div, *a, *b),
+            (Prim::Mod(_), [Int(a), Int(b)]) => int(i64::checked_rem, *a, *b),
+            (Prim::Add(_), [Float(a), Float(b)]) => Some(Float(a + b)),
+            (Prim::Sub(_), [Float(a), Float(b)]) => Some(Float(a - b)),
+            (Prim::Mul(_), [Float(a), Float(b)]) => Some(Float(a * b)),
+            (Prim::Div(_), [Float(a), Float(b)]) => Some(Float(a / b)),
+            (Prim::Mod(_), [Float(a), Float(b)]) => Some(Float(a % b)),
+            (Prim::Lt(_), [Int(a), Int(b)]) => Some(Bool(a < b)),
+            (Prim::LtEq(_), [Int(a), Int(b)]) => Some(Bool(a <= b)),
+            (Prim::Gt(_), [Int(a), Int(b)]) => Some(Bool(a > b)),
+            (Prim::GtEq(_), [Int(a), Int(b)]) => Some(Bool(a >= b)),
+            (Prim::Lt(_), [Float(a), Float(b)]) => Some(Bool(a < b)),
+            (Prim::LtEq(_), [Float(a), Float(b)]) => Some(Bool(a <= b)),
+            (Prim::Gt(_), [Float(a), Float(b)]) => Some(Bool(a > b)),
+            (Prim::GtEq(_), [Float(a), Float(b)]) => Some(Bool(a >= b)),
+            (Prim::Eq, [a, b]) => equals(a, b).map(Bool),
+            (Prim::NotEq, [a, b]) => equals(a, b).map(|eq| Bool(!eq)),
+            (Prim::Not, [Bool(b)]) => Some(Bool(!b)),
+            _ => None,
+        }
+    }
+}
+
+fn equals(a: &Literal, b: &Literal) -> Option<bool> {
+    Some(match (a, b) {
+        (Literal::Int(a), Literal::Int(b)) => a == b,
+        (Literal::Float(a), Literal::Float(b)) => a == b,
+        (Literal::BigInt(a), Literal::BigInt(b)) | (Literal::String(a), Literal::String(b)) => {
+            a.0 == b.0
+        }
+        (Literal::Bool(a), Literal::Bool(b)) => a == b,
+        (Literal::Unit, Literal::Unit) => true,
+        _ => return None,
+    })
+}
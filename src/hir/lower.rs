@@ -0,0 +1,1611 @@
+use rustc_hash::FxHashMap;
+
+use crate::{
+    bigint::BigInt,
+    errors::ErrorStream,
+    resolver::{self as r, Builtin, Resolution, SymbolId, SymbolKind},
+    scc,
+    string_storage::StringStorage,
+    tokenizer::{Intern, Span},
+    typeck::{Type, TypeId, Typing},
+};
+
+use super::*;
+
+#[derive(Debug)]
+pub struct LowerError<'s> {
+    pub kind: LowerErrorKind<'s>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum LowerErrorKind<'s> {
+    /// `set` was used on something that can't be assigned to.
+    NotAPlace,
+    /// `set` was used on a binding that wasn't declared with `set`.
+    Immutable(Intern<'s>),
+    IntegerTooLarge,
+    /// Some values of the scrutinee of a `match` aren't matched by any arm.
+    NonExhaustive,
+    Unsupported(&'static str),
+}
+
+/// Lowers a resolved and type checked compilation unit. This should only be
+/// called on programs without errors.
+pub fn lower<'s>(
+    modules: &[r::Module<'s>],
+    resolution: &Resolution<'s>,
+    typing: &Typing<'s>,
+    storage: &'s StringStorage,
+    errors: &ErrorStream<'s>,
+) -> Program<'s> {
+    let mut lowerer = Lowerer {
+        resolution,
+        typing,
+        storage,
+        errors,
+        vars: Vec::new(),
+        symbols: FxHashMap::default(),
+        dictionaries: Vec::new(),
+        needs: impl_needs(typing),
+    };
+
+    let top_level = modules
+        .iter()
+        .filter_map(|module| match &module.body.kind {
+            r::ExprKind::Object(scope) => Some(scope.defs.iter()),
+            _ => None,
+        })
+        .flatten()
+        .flat_map(r::Def::values)
+        .collect::<Vec<_>>();
+    let globals = lowerer.defs(&top_level);
+
+    let init = modules
+        .iter()
+        .flat_map(|module| match &module.body.kind {
+            r::ExprKind::Object(scope) => scope.body.iter().collect::<Vec<_>>(),
+            _ => vec![&module.body],
+        })
+        .map(|expr| lowerer.expr(expr))
+        .collect::<Box<[_]>>();
+
+    let mut program = Program {
+        vars: lowerer.vars,
+        globals: globals.into_boxed_slice(),
+        init,
+        symbols: lowerer.symbols,
+    };
+    for group in program.globals.iter_mut() {
+        group
+            .bindings
+            .iter_mut()
+            .for_each(|(_, value)| tail_calls(value, false));
+    }
+    program
+        .init
+        .iter_mut()
+        .for_each(|expr| tail_calls(expr, false));
+    program
+}
+
+struct Lowerer<'s, 'e> {
+    resolution: &'e Resolution<'s>,
+    typing: &'e Typing<'s>,
+    storage: &'s StringStorage,
+    errors: &'e ErrorStream<'s>,
+    vars: Vec<Var<'s>>,
+    symbols: FxHashMap<SymbolId, VarId>,
+    /// The variables holding the dictionaries that the constrained defs
+    /// being lowered were given, by the trait and the generic variable each
+    /// is the impl for.
+    dictionaries: Vec<((SymbolId, TypeId), VarId)>,
+    /// The methods of the impls that the uses at each offset pass in a
+    /// dictionary or call, sorted by offset.
+    needs: Vec<(u32, SymbolId)>,
+}
+
+impl<'s, 'e> Lowerer<'s, 'e> {
+    fn expr(&mut self, expr: &r::Expr<'s>) -> Expr<'s> {
+        let span = expr.span;
+        let kind = match &expr.kind {
+            r::ExprKind::Object(scope) => {
+                let groups = self.defs(&scope.defs.iter().collect::<Vec<_>>());
+                let fields = scope
+                    .defs
+                    .iter()
+                    .map(|def| {
+                        let name = self.resolution.symbol(def.symbol).name;
+                        (name, self.read(def.symbol, def.span))
+                    })
+                    .collect();
+                let record = Expr {
+                    kind: ExprKind::Record(fields),
+                    span,
+                };
+
+                let mut items = self.exprs(&scope.body);
+                items.push(record);
+                return wrap(groups, seq(items, span));
+            }
+            r::ExprKind::Block(scope) => {
+                let groups = self.defs(&scope.defs.iter().collect::<Vec<_>>());
+                let mut items = self.exprs(&scope.body);
+                if scope.trailing_semi || items.is_empty() {
+                    items.push(unit(span));
+                }
+                return wrap(groups, seq(items, span));
+            }
+            r::ExprKind::Lambda { arg, body } => {
+                let mut lets = Vec::new();
+                let param = self.param(arg, &mut lets);
+                let body = self.expr(body);
+                let mut captures = self.resolution.captures[&span]
+                    .iter()
+                    .map(|capture| self.var(capture.symbol))
+                    .collect::<Vec<_>>();
+                // dictionaries aren't symbols, so resolution didn't see them
+                if !self.dictionaries.is_empty() {
+                    let mut free = Vec::new();
+                    free_vars(&body, &mut vec![param], &mut free);
+                    let dictionaries = self.dictionaries.iter().map(|&(_, var)| var);
+                    captures.extend(dictionaries.filter(|var| free.contains(var)));
+                }
+
+                ExprKind::Lambda {
+                    param,
+                    body: Box::new(lets.into_iter().rev().fold(body, |body, (var, value)| {
+                        let span = body.span;
+                        Expr {
+                            kind: ExprKind::Let {
+                                var,
+                                value: Box::new(value),
+                                body: Box::new(body),
+                            },
+                            span,
+                        }
+                    })),
+                    captures: captures.into(),
+                }
+            }
+            r::ExprKind::BinOp { op, lhs, rhs } => return self.bin_op(*op, lhs, rhs, span),
+            r::ExprKind::UnOp { op, arg } => match op {
+                r::UnOp::Not => ExprKind::Prim {
+                    op: Prim::Not,
+                    args: Box::new([self.expr(arg)]),
+                },
+                r::UnOp::Val => return self.expr(arg),
+                r::UnOp::Ref => return self.reference(arg),
+                r::UnOp::Deref => ExprKind::Load(Box::new(self.expr(arg))),
+            },
+            r::ExprKind::Access { expr: record, prop } => match prop {
+                r::AccessRhs::Prop(name) => ExprKind::Field {
+                    record: Box::new(self.expr(record)),
+                    name: *name,
+                },
+                r::AccessRhs::Expr(_) => {
+                    return self.error(LowerErrorKind::Unsupported("computed access"), span)
+                }
+            },
+            r::ExprKind::Branch {
+                cond,
+                on_true,
+                on_false,
+            } => {
+                let on_false = match on_false {
+                    Some(on_false) => self.expr(on_false),
+                    None => unit(span),
+                };
+                ExprKind::Case {
+                    scrutinee: Box::new(self.expr(cond)),
+                    arms: Box::new([
+                        Arm {
+                            pattern: ArmPattern::Bool(true),
+                            body: self.expr(on_true),
+                        },
+                        Arm {
+                            pattern: ArmPattern::Wildcard,
+                            body: on_false,
+                        },
+                    ]),
+                }
+            }
+            r::ExprKind::Tuple { items } => match &**items {
+                [] => ExprKind::Literal(Literal::Unit),
+                [item] => return self.expr(item),
+                items => ExprKind::Tuple(self.exprs(items).into_boxed_slice()),
+            },
+            r::ExprKind::Apply { a, b } => ExprKind::Apply {
+                func: Box::new(self.expr(a)),
+                arg: Box::new(self.expr(b)),
+                tail: false,
+            },
+            r::ExprKind::TypeAssertion { a, .. } => match a.kind {
+                r::ExprKind::Literal(r::Literal::Integer(i))
+                    if self.typing.coercions.contains(&a.span) =>
+                {
+                    let ty = self
+                        .typing
+                        .type_of(a.span)
+                        .map(|ty| self.typing.types.get(ty));
+                    match ty {
+                        Some(Type::BigInt) => {
+                            let big = BigInt::parse(&i.to_string()).unwrap();
+                            ExprKind::Literal(self.big_int(&big))
+                        }
+                        _ => ExprKind::Literal(Literal::Float(i as f64)),
+                    }
+                }
+                _ => return self.expr(a),
+            },
+            r::ExprKind::Set { place, value } => {
+                let cell = match &place.kind {
+                    r::ExprKind::Ident(symbol) if self.resolution.symbol(*symbol).mutable => Expr {
+                        kind: ExprKind::Var(self.var(*symbol)),
+                        span: place.span,
+                    },
+                    r::ExprKind::Ident(symbol) => {
+                        let name = self.resolution.symbol(*symbol).name;
+                        return self.error(LowerErrorKind::Immutable(name), place.span);
+                    }
+                    r::ExprKind::UnOp {
+                        op: r::UnOp::Deref,
+                        arg,
+                    } => self.expr(arg),
+                    _ => return self.error(LowerErrorKind::NotAPlace, place.span),
+                };
+                ExprKind::Store {
+                    cell: Box::new(cell),
+                    value: Box::new(self.expr(value)),
+                }
+            }
+            r::ExprKind::Variant(items) => match &**items {
+                [item] => ExprKind::Variant {
+                    name: item.name,
+                    payload: Box::new(match &item.value {
+                        Some(value) => self.expr(value),
+                        None => unit(item.span),
+                    }),
+                },
+                _ => {
+                    return self.error(
+                        LowerErrorKind::Unsupported("a variant value with more than one case"),
+                        span,
+                    )
+                }
+            },
+            r::ExprKind::Match { scrutinee, arms } => return self.r#match(scrutinee, arms, span),
+            r::ExprKind::Ident(symbol) => return self.read(*symbol, span),
+            r::ExprKind::Literal(lit) => return self.literal(*lit, span),
+            r::ExprKind::Extern { name, .. } => ExprKind::Extern(self.external(*name, span)),
+            r::ExprKind::Arrow { .. } => unreachable!("function types aren't values"),
+            r::ExprKind::Trait { .. } | r::ExprKind::Impl { .. } => {
+                unreachable!("traits and impls are only at the top level of a module")
+            }
+            r::ExprKind::Error => ExprKind::Literal(Literal::Unit),
+        };
+
+        Expr { kind, span }
+    }
+
+    fn literal(&mut self, lit: r::Literal<'s>, span: Span) -> Expr<'s> {
+        let lit = match lit {
+            r::Literal::Integer(i) => match i64::try_from(i) {
+                Ok(i) => Literal::Int(i),
+                Err(_) => return self.error(LowerErrorKind::IntegerTooLarge, span),
+            },
+            r::Literal::Float(f) => Literal::Float(f),
+            r::Literal::BigInteger(digits) => self.big_int(&BigInt::parse(digits.0).unwrap()),
+            r::Literal::String(s) => Literal::String(s),
+        };
+
+        Expr {
+            kind: ExprKind::Literal(lit),
+            span,
+        }
+    }
+
+    fn big_int(&self, big: &BigInt) -> Literal<'s> {
+        Literal::BigInt(Intern(self.storage.intern(big.to_string())))
+    }
+
+    /// Works out how to call the C function `name` from the type that the
+    /// type checker has already made sure is suitable.
+    fn external(&self, name: Intern<'s>, span: Span) -> Extern<'s> {
+        let types = &self.typing.types;
+        let convert = |ty| match types.get(ty) {
+            Type::Int => ExternType::Int,
+            Type::Float => ExternType::Float,
+            Type::String => ExternType::String,
+            Type::Bool => ExternType::Bool,
+            _ => ExternType::Unit,
+        };
+        let Some(&Type::Function(param, ret)) = self.typing.type_of(span).map(|ty| types.get(ty))
+        else {
+            unreachable!("extern without a function type")
+        };
+        let params = match types.get(param) {
+            Type::Tuple(items) => items.iter().map(|&item| convert(item)).collect(),
+            _ => Box::new([convert(param)]) as Box<[_]>,
+        };
+
+        Extern {
+            name,
+            params,
+            ret: convert(ret),
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[r::Expr<'s>]) -> Vec<Expr<'s>> {
+        exprs.iter().map(|expr| self.expr(expr)).collect()
+    }
+
+    fn bin_op(
+        &mut self,
+        op: r::BinOp,
+        lhs: &r::Expr<'s>,
+        rhs: &r::Expr<'s>,
+        span: Span,
+    ) -> Expr<'s> {
+        if self.typing.overloads.contains(&span) {
+            return self.overload(op, lhs, rhs, span);
+        }
+        let operand = self
+            .typing
+            .type_of(lhs.span)
+            .map(|ty| self.typing.types.get(ty));
+        let num = match operand {
+            Some(Type::Float) => Num::Float,
+            Some(Type::BigInt) => Num::BigInt,
+            _ => Num::Int,
+        };
+        let is_string = matches!(operand, Some(Type::String));
+
+        let prim = match op {
+            r::BinOp::Add if is_string => Prim::Concat,
+            r::BinOp::Add => Prim::Add(num),
+            r::BinOp::Sub => Prim::Sub(num),
+            r::BinOp::Mul => Prim::Mul(num),
+            r::BinOp::Div => Prim::Div(num),
+            r::BinOp::Mod => Prim::Mod(num),
+            r::BinOp::Lt => Prim::Lt(num),
+            r::BinOp::LtEq => Prim::LtEq(num),
+            r::BinOp::Gt => Prim::Gt(num),
+            r::BinOp::GtEq => Prim::GtEq(num),
+            r::BinOp::Equal => Prim::Eq,
+            r::BinOp::NotEqual => Prim::NotEq,
+            r::BinOp::And | r::BinOp::Or => {
+                // `a && b` is `case a { b } else { false }`, and `a || b` is
+                // `case a { true } else { b }`
+                let (lhs, rhs) = (self.expr(lhs), self.expr(rhs));
+                let short = Expr {
+                    kind: ExprKind::Literal(Literal::Bool(op == r::BinOp::Or)),
+                    span,
+                };
+                let (on_true, on_false) = match op {
+                    r::BinOp::And => (rhs, short),
+                    _ => (short, rhs),
+                };
+                return Expr {
+                    kind: ExprKind::Case {
+                        scrutinee: Box::new(lhs),
+                        arms: Box::new([
+                            Arm {
+                                pattern: ArmPattern::Bool(true),
+                                body: on_true,
+                            },
+                            Arm {
+                                pattern: ArmPattern::Wildcard,
+                                body: on_false,
+                            },
+                        ]),
+                    },
+                    span,
+                };
+            }
+        };
+
+        Expr {
+            kind: ExprKind::Prim {
+                op: prim,
+                args: Box::new([self.expr(lhs), self.expr(rhs)]),
+            },
+            span,
+        }
+    }
+
+    /// Lowers an operator that the type checker found calls a member of its
+    /// left operand, as [r::BinOp::member] says.
+    fn overload(
+        &mut self,
+        op: r::BinOp,
+        lhs: &r::Expr<'s>,
+        rhs: &r::Expr<'s>,
+        span: Span,
+    ) -> Expr<'s> {
+        let member = Expr {
+            kind: ExprKind::Field {
+                record: Box::new(self.expr(lhs)),
+                name: Intern(op.member().unwrap()),
+            },
+            span: lhs.span,
+        };
+        let call = Expr {
+            kind: ExprKind::Apply {
+                func: Box::new(member),
+                arg: Box::new(self.expr(rhs)),
+                tail: false,
+            },
+            span,
+        };
+        let (op, args) = match op {
+            r::BinOp::Equal => return call,
+            r::BinOp::NotEqual => (Prim::Not, vec![call]),
+            r::BinOp::Gt | r::BinOp::GtEq | r::BinOp::Lt | r::BinOp::LtEq => {
+                let zero = Expr {
+                    kind: ExprKind::Literal(Literal::Int(0)),
+                    span,
+                };
+                let op = match op {
+                    r::BinOp::Gt => Prim::Gt(Num::Int),
+                    r::BinOp::GtEq => Prim::GtEq(Num::Int),
+                    r::BinOp::Lt => Prim::Lt(Num::Int),
+                    _ => Prim::LtEq(Num::Int),
+                };
+                (op, vec![call, zero])
+            }
+            _ => return call,
+        };
+        Expr {
+            kind: ExprKind::Prim {
+                op,
+                args: args.into(),
+            },
+            span,
+        }
+    }
+
+    /// Lowers `^arg`. A mutable binding already is a cell, and anything else
+    /// is copied into a new one.
+    fn reference(&mut self, arg: &r::Expr<'s>) -> Expr<'s> {
+        let kind = match &arg.kind {
+            r::ExprKind::Ident(symbol) if self.resolution.symbol(*symbol).mutable => {
+                ExprKind::Var(self.var(*symbol))
+            }
+            r::ExprKind::UnOp {
+                op: r::UnOp::Deref,
+                arg,
+            } => return self.expr(arg),
+            _ => ExprKind::NewRef(Box::new(self.expr(arg))),
+        };
+
+        Expr {
+            kind,
+            span: arg.span,
+        }
+    }
+
+    /// Lowers a read of a symbol, which loads from its cell if it's mutable.
+    fn read(&mut self, symbol: SymbolId, span: Span) -> Expr<'s> {
+        let kind = match self.resolution.symbol(symbol).kind {
+            SymbolKind::Builtin(Builtin::True) => ExprKind::Literal(Literal::Bool(true)),
+            SymbolKind::Builtin(Builtin::False) => ExprKind::Literal(Literal::Bool(false)),
+            SymbolKind::Builtin(builtin) => match self.intrinsic(builtin, span) {
+                Some(kind) => kind,
+                None => ExprKind::Builtin(builtin),
+            },
+            SymbolKind::Method(trait_) => return self.method(symbol, trait_, span),
+            _ => {
+                let var = self.var(symbol);
+                if self.vars[var.0 as usize].cell {
+                    ExprKind::Load(Box::new(Expr {
+                        kind: ExprKind::Var(var),
+                        span,
+                    }))
+                } else {
+                    ExprKind::Var(var)
+                }
+            }
+        };
+
+        let expr = Expr { kind, span };
+        let typing = self.typing;
+        match typing.constraints.get(&symbol) {
+            Some(constraints) => self.apply_dictionaries(expr, constraints, span),
+            None => expr,
+        }
+    }
+
+    /// Lowers the value of a constrained def to a function of the
+    /// dictionaries of the impls it needs, each a record of the methods of
+    /// an impl. It takes a tuple of them if it needs more than one.
+    fn take_dictionaries(
+        &mut self,
+        constraints: &[(SymbolId, TypeId)],
+        value: &r::Expr<'s>,
+    ) -> Expr<'s> {
+        let span = value.span;
+        let vars = constraints.iter().map(|_| self.temp()).collect::<Vec<_>>();
+        let len = self.dictionaries.len();
+        for (&(trait_, ty), &var) in constraints.iter().zip(&vars) {
+            let ty = self.typing.types.find(ty);
+            self.dictionaries.push(((trait_, ty), var));
+        }
+        let body = self.expr(value);
+        self.dictionaries.truncate(len);
+
+        let (param, body) = match vars[..] {
+            [var] => (var, body),
+            _ => {
+                let param = self.temp();
+                let body = vars
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .fold(body, |body, (index, &var)| {
+                        let tuple = Expr {
+                            kind: ExprKind::Var(param),
+                            span,
+                        };
+                        let value = Expr {
+                            kind: ExprKind::Project {
+                                tuple: Box::new(tuple),
+                                index,
+                            },
+                            span,
+                        };
+                        Expr {
+                            kind: ExprKind::Let {
+                                var,
+                                value: Box::new(value),
+                                body: Box::new(body),
+                            },
+                            span,
+                        }
+                    });
+                (param, body)
+            }
+        };
+        let mut captures = Vec::new();
+        free_vars(&body, &mut vec![param], &mut captures);
+
+        Expr {
+            kind: ExprKind::Lambda {
+                param,
+                body: Box::new(body),
+                captures: captures.into(),
+            },
+            span,
+        }
+    }
+
+    /// Applies a constrained def to the dictionaries that its use at `span`
+    /// needs, or to its own if the use is from within its own group.
+    fn apply_dictionaries(
+        &mut self,
+        func: Expr<'s>,
+        constraints: &[(SymbolId, TypeId)],
+        span: Span,
+    ) -> Expr<'s> {
+        let typing = self.typing;
+        let instances = typing.dictionaries.get(&span);
+        let mut args = constraints
+            .iter()
+            .enumerate()
+            .map(|(i, &(trait_, ty))| {
+                let ty = instances.map_or(ty, |instances| instances[i].1);
+                self.dictionary(trait_, ty, span)
+            })
+            .collect::<Vec<_>>();
+        let arg = match args.len() {
+            1 => args.pop().unwrap(),
+            _ => Expr {
+                kind: ExprKind::Tuple(args.into()),
+                span,
+            },
+        };
+
+        Expr {
+            kind: ExprKind::Apply {
+                func: Box::new(func),
+                arg: Box::new(arg),
+                tail: false,
+            },
+            span,
+        }
+    }
+
+    /// The dictionary of the impl of `trait_` for `ty`, which is a record of
+    /// its methods if `ty` is known, and otherwise one of the dictionaries
+    /// that the constrained defs being lowered were given.
+    fn dictionary(&mut self, trait_: SymbolId, ty: TypeId, span: Span) -> Expr<'s> {
+        let ty = self.typing.types.find(ty);
+        let kind = match self.typing.types.get(ty) {
+            Type::Var { .. } => ExprKind::Var(self.given(trait_, ty)),
+            _ => {
+                let key = (trait_, self.typing.display(ty));
+                let methods = self.typing.impls[&key].iter();
+                let names = self.resolution.traits[&trait_].iter();
+                let fields = names
+                    .zip(methods)
+                    .map(|(&declared, &method)| {
+                        let name = self.resolution.symbol(declared).name;
+                        let kind = ExprKind::Var(self.var(method));
+                        (name, Expr { kind, span })
+                    })
+                    .collect();
+                ExprKind::Record(fields)
+            }
+        };
+
+        Expr { kind, span }
+    }
+
+    /// Lowers a use of a method: to the method of the impl for the type it's
+    /// used at if that's known, and otherwise to a field of the dictionary
+    /// that the def being lowered was given for it.
+    fn method(&mut self, symbol: SymbolId, trait_: SymbolId, span: Span) -> Expr<'s> {
+        let (_, ty) = self.typing.dictionaries[&span][0];
+        let ty = self.typing.types.find(ty);
+        let name = self.resolution.symbol(symbol).name;
+        let kind = match self.typing.types.get(ty) {
+            Type::Var { .. } => {
+                let dictionary = Expr {
+                    kind: ExprKind::Var(self.given(trait_, ty)),
+                    span,
+                };
+                ExprKind::Field {
+                    record: Box::new(dictionary),
+                    name,
+                }
+            }
+            _ => {
+                let methods = &self.resolution.traits[&trait_];
+                let index = methods.iter().position(|&m| m == symbol).unwrap();
+                let key = (trait_, self.typing.display(ty));
+                ExprKind::Var(self.var(self.typing.impls[&key][index]))
+            }
+        };
+
+        Expr { kind, span }
+    }
+
+    /// The variable holding the dictionary of `trait_` for the generic
+    /// variable `ty`, which one of the defs being lowered was given.
+    fn given(&self, trait_: SymbolId, ty: TypeId) -> VarId {
+        self.dictionaries
+            .iter()
+            .rev()
+            .find(|(key, _)| *key == (trait_, ty))
+            .map(|&(_, var)| var)
+            .expect("a generic constraint is on a def being lowered")
+    }
+
+    /// Expands the builtins that build and take apart lists, options and
+    /// results, which are ordinary variants at runtime. Lists are `|Nil` or
+    /// `|Cons: (head, tail)`.
+    fn intrinsic(&mut self, builtin: Builtin, span: Span) -> Option<ExprKind<'s>> {
+        let expr = |kind| Expr { kind, span };
+        let var = |var| expr(ExprKind::Var(var));
+        let project = |var, index| {
+            expr(ExprKind::Project {
+                tuple: Box::new(expr(ExprKind::Var(var))),
+                index,
+            })
+        };
+        let call = |func, arg| {
+            expr(ExprKind::Apply {
+                func: Box::new(func),
+                arg: Box::new(arg),
+                tail: false,
+            })
+        };
+        let arm = |name, bind, body| Arm {
+            pattern: ArmPattern::Variant {
+                name: Intern(name),
+                bind,
+            },
+            body,
+        };
+
+        let param = match builtin {
+            Builtin::Nil => {
+                return Some(ExprKind::Variant {
+                    name: Intern("Nil"),
+                    payload: Box::new(unit(span)),
+                })
+            }
+            Builtin::Cons | Builtin::MatchList | Builtin::MatchOption | Builtin::MatchResult => {
+                self.temp()
+            }
+            _ => return None,
+        };
+        let body = match builtin {
+            Builtin::Cons => ExprKind::Variant {
+                name: Intern("Cons"),
+                payload: Box::new(var(param)),
+            },
+            // `(list, on_nil, on_cons)`, where `on_cons` takes the payload
+            Builtin::MatchList | Builtin::MatchOption => {
+                let name = match builtin {
+                    Builtin::MatchList => "Cons",
+                    _ => "Some",
+                };
+                let payload = self.temp();
+                ExprKind::Case {
+                    scrutinee: Box::new(project(param, 0)),
+                    arms: Box::new([
+                        arm(name, Some(payload), call(project(param, 2), var(payload))),
+                        Arm {
+                            pattern: ArmPattern::Wildcard,
+                            body: project(param, 1),
+                        },
+                    ]),
+                }
+            }
+            // `(result, on_ok, on_err)`
+            _ => {
+                let (value, error) = (self.temp(), self.temp());
+                ExprKind::Case {
+                    scrutinee: Box::new(project(param, 0)),
+                    arms: Box::new([
+                        arm("Ok", Some(value), call(project(param, 1), var(value))),
+                        arm("Err", Some(error), call(project(param, 2), var(error))),
+                    ]),
+                }
+            }
+        };
+
+        Some(ExprKind::Lambda {
+            param,
+            body: Box::new(expr(body)),
+            captures: Box::new([]),
+        })
+    }
+
+    /// Lowers the defs of a scope into groups, ordered so that each group
+    /// only depends on the groups before it.
+    fn defs(&mut self, defs: &[&r::Def<'s>]) -> Vec<Group<'s>> {
+        let index = defs
+            .iter()
+            .enumerate()
+            .map(|(i, def)| (def.symbol, i))
+            .collect::<FxHashMap<_, _>>();
+        let deps = defs
+            .iter()
+            .map(|def| {
+                let mut symbols = Vec::new();
+                def.value.symbols(&mut symbols);
+                // and the methods of the impls that it passes in dictionaries
+                let span = def.value.span;
+                let from = self.needs.partition_point(|&(start, _)| start < span.start);
+                let needs = self.needs[from..].iter();
+                let needs = needs.take_while(|&&(start, _)| start < span.end);
+                symbols.extend(needs.map(|&(_, method)| method));
+                symbols
+                    .into_iter()
+                    .filter_map(|symbol| index.get(&symbol).copied())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        scc::strongly_connected_components(defs.len(), |i| deps[i].iter().copied())
+            .into_iter()
+            .map(|group| {
+                let recursive = group.len() > 1 || deps[group[0]].contains(&group[0]);
+                let bindings = group
+                    .into_iter()
+                    .map(|i| {
+                        let def = defs[i];
+                        let var = self.var(def.symbol);
+                        self.vars[var.0 as usize].constant = def.constant;
+                        let typing = self.typing;
+                        let mut value = match typing.constraints.get(&def.symbol) {
+                            Some(constraints) => self.take_dictionaries(constraints, &def.value),
+                            None => self.expr(&def.value),
+                        };
+                        if self.vars[var.0 as usize].cell {
+                            let span = value.span;
+                            value = Expr {
+                                kind: ExprKind::NewRef(Box::new(value)),
+                                span,
+                            };
+                        }
+                        (var, value)
+                    })
+                    .collect();
+
+                Group {
+                    bindings,
+                    recursive,
+                }
+            })
+            .collect()
+    }
+
+    /// Lowers a lambda's argument pattern to a single parameter, adding the
+    /// bindings that take it apart to `lets`.
+    fn param(&mut self, pat: &r::Pattern<'s>, lets: &mut Vec<(VarId, Expr<'s>)>) -> VarId {
+        match pat.kind {
+            r::PatternKind::Bind(symbol) if !self.resolution.symbol(symbol).mutable => {
+                self.var(symbol)
+            }
+            _ => {
+                let param = self.temp();
+                let value = Expr {
+                    kind: ExprKind::Var(param),
+                    span: pat.span,
+                };
+                self.bind(pat, value, lets);
+                param
+            }
+        }
+    }
+
+    /// Binds the parts of `value` to the variables of `pat`.
+    fn bind(&mut self, pat: &r::Pattern<'s>, value: Expr<'s>, lets: &mut Vec<(VarId, Expr<'s>)>) {
+        match &pat.kind {
+            r::PatternKind::Bind(symbol) => {
+                let var = self.var(*symbol);
+                let value = if self.vars[var.0 as usize].cell {
+                    let span = value.span;
+                    Expr {
+                        kind: ExprKind::NewRef(Box::new(value)),
+                        span,
+                    }
+                } else {
+                    value
+                };
+                lets.push((var, value));
+            }
+            r::PatternKind::Tuple(items) if items.is_empty() => {}
+            r::PatternKind::Tuple(items) => {
+                let tuple = match value.kind {
+                    ExprKind::Var(var) => var,
+                    _ => {
+                        let tuple = self.temp();
+                        lets.push((tuple, value));
+                        tuple
+                    }
+                };
+                for (index, item) in items.iter().enumerate() {
+                    let value = Expr {
+                        kind: ExprKind::Project {
+                            tuple: Box::new(Expr {
+                                kind: ExprKind::Var(tuple),
+                                span: pat.span,
+                            }),
+                            index,
+                        },
+                        span: item.span,
+                    };
+                    self.bind(item, value, lets);
+                }
+            }
+            r::PatternKind::Typed { pat, .. } => self.bind(pat, value, lets),
+            r::PatternKind::Wildcard | r::PatternKind::Error => {}
+            r::PatternKind::Literal(_) | r::PatternKind::Variant { .. } => {
+                unreachable!("refutable patterns are only allowed in a match")
+            }
+        }
+    }
+
+    /// Lowers a `match`. Its arms are compiled to a decision tree that
+    /// tests each part of the scrutinee at most once on the way to an arm,
+    /// rather than trying the patterns of the arms one after another. An arm
+    /// that more than one path through the tree leads to is lowered once, to
+    /// a lambda that each of those paths calls with the values of its
+    /// bindings.
+    fn r#match(
+        &mut self,
+        scrutinee: &r::Expr<'s>,
+        arms: &[r::MatchArm<'s>],
+        span: Span,
+    ) -> Expr<'s> {
+        let occurrence = Occurrence {
+            var: self.temp(),
+            ty: self.typing.type_of(scrutinee.span),
+        };
+        let clauses = arms
+            .iter()
+            .enumerate()
+            .map(|(arm, a)| Clause {
+                patterns: vec![Some(&a.pattern)],
+                bindings: Vec::new(),
+                arm,
+            })
+            .collect();
+        let decision = self.decide(vec![occurrence], clauses);
+
+        let mut leaves = vec![0; arms.len()];
+        if decision.leaves(&mut leaves) {
+            self.errors.error(LowerError {
+                kind: LowerErrorKind::NonExhaustive,
+                span,
+            });
+        }
+
+        let mut lets = vec![(occurrence.var, self.expr(scrutinee))];
+        let mut bodies = Vec::with_capacity(arms.len());
+        for (arm, count) in arms.iter().zip(leaves) {
+            let mut symbols = Vec::new();
+            pattern_symbols(&arm.pattern, &mut symbols);
+            let body = match count {
+                0 => ArmBody::Unreachable,
+                1 => ArmBody::Inline(Some(self.expr(&arm.body))),
+                _ => {
+                    let join = self.temp();
+                    lets.push((join, self.join(&symbols, &arm.body)));
+                    ArmBody::Join(join)
+                }
+            };
+            bodies.push((symbols, body));
+        }
+
+        let tree = self.decision(decision, &mut bodies, span);
+        lets.into_iter()
+            .rev()
+            .fold(tree, |body, (var, value)| Expr {
+                kind: ExprKind::Let {
+                    var,
+                    value: Box::new(value),
+                    body: Box::new(body),
+                },
+                span,
+            })
+    }
+
+    /// Compiles the clauses of a `match` to a decision tree, following
+    /// Maranget's "Compiling Pattern Matching to Good Decision Trees": the
+    /// first column that the first clause tests is tested next, and the
+    /// clauses that can still match are specialized to each outcome.
+    fn decide<'p>(
+        &mut self,
+        mut columns: Vec<Occurrence>,
+        mut clauses: Vec<Clause<'p, 's>>,
+    ) -> Decision<'s> {
+        // bindings and wildcards match anything, so they're taken out of
+        // the way first
+        for clause in &mut clauses {
+            for (pattern, occurrence) in clause.patterns.iter_mut().zip(&columns) {
+                while let Some(pat) = *pattern {
+                    match &pat.kind {
+                        r::PatternKind::Typed { pat, .. } => *pattern = Some(pat),
+                        r::PatternKind::Bind(symbol) => {
+                            clause.bindings.push((*symbol, occurrence.var));
+                            *pattern = None;
+                        }
+                        r::PatternKind::Wildcard | r::PatternKind::Error => *pattern = None,
+                        _ => break,
+                    }
+                }
+            }
+        }
+
+        let Some(first) = clauses.first() else {
+            return Decision::Fail;
+        };
+        let Some(column) = first.patterns.iter().position(Option::is_some) else {
+            let first = clauses.swap_remove(0);
+            return Decision::Leaf {
+                arm: first.arm,
+                bindings: first.bindings,
+            };
+        };
+        let occurrence = columns[column];
+        let typing = self.typing;
+        let ty = occurrence.ty.map(|ty| typing.types.get(ty));
+        let pattern = first.patterns[column].unwrap();
+
+        match &pattern.kind {
+            r::PatternKind::Tuple(items) => {
+                let len = items.len();
+                let items = (0..len)
+                    .map(|i| Occurrence {
+                        var: self.temp(),
+                        ty: match ty {
+                            Some(Type::Tuple(types)) => types.get(i).copied(),
+                            _ => None,
+                        },
+                    })
+                    .collect::<Vec<_>>();
+                columns.splice(column..=column, items.iter().copied());
+                for clause in &mut clauses {
+                    let parts = match clause.patterns[column].map(|pat| &pat.kind) {
+                        Some(r::PatternKind::Tuple(items)) => items.iter().map(Some).collect(),
+                        _ => vec![None; len],
+                    };
+                    clause.patterns.splice(column..=column, parts);
+                }
+
+                Decision::Project {
+                    tuple: occurrence.var,
+                    items: items.iter().map(|item| item.var).collect(),
+                    then: Box::new(self.decide(columns, clauses)),
+                }
+            }
+            r::PatternKind::Variant { .. } => {
+                let mut names = Vec::new();
+                for clause in &clauses {
+                    if let Some(r::PatternKind::Variant { name, .. }) =
+                        clause.patterns[column].map(|pat| &pat.kind)
+                    {
+                        if !names.contains(name) {
+                            names.push(*name);
+                        }
+                    }
+                }
+                let row = match ty {
+                    Some(Type::Variant(row)) => Some(typing.types.row(row)),
+                    _ => None,
+                };
+
+                let cases = names
+                    .iter()
+                    .map(|&name| {
+                        let payload = Occurrence {
+                            var: self.temp(),
+                            ty: row.as_ref().and_then(|(entries, _)| {
+                                let entry = entries.iter().find(|(case, _)| *case == name);
+                                entry.map(|&(_, ty)| ty)
+                            }),
+                        };
+                        let mut columns = columns.clone();
+                        columns[column] = payload;
+                        let clauses = clauses
+                            .iter()
+                            .filter_map(|clause| {
+                                let pattern = match clause.patterns[column].map(|pat| &pat.kind) {
+                                    Some(r::PatternKind::Variant { name: case, value })
+                                        if *case == name =>
+                                    {
+                                        value.as_deref()
+                                    }
+                                    Some(_) => return None,
+                                    None => None,
+                                };
+                                let mut clause = clause.clone();
+                                clause.patterns[column] = pattern;
+                                Some(clause)
+                            })
+                            .collect();
+                        (name, payload.var, self.decide(columns, clauses))
+                    })
+                    .collect();
+
+                // a closed variant type whose every case has been tested for
+                // needs no default
+                let complete = matches!(&row, Some((entries, None))
+                    if entries.iter().all(|(name, _)| names.contains(name)));
+                let default = (!complete).then(|| {
+                    columns.remove(column);
+                    clauses.retain(|clause| clause.patterns[column].is_none());
+                    for clause in &mut clauses {
+                        clause.patterns.remove(column);
+                    }
+                    Box::new(self.decide(columns, clauses))
+                });
+
+                Decision::Switch {
+                    variant: occurrence.var,
+                    cases,
+                    default,
+                }
+            }
+            r::PatternKind::Literal(lit) => {
+                let lit = *lit;
+                let literal = self.literal(lit, pattern.span);
+                let (mut on_true, on_false): (Vec<_>, Vec<_>) =
+                    clauses.into_iter().partition(|clause| {
+                        match clause.patterns[column].map(|pat| &pat.kind) {
+                            Some(r::PatternKind::Literal(other)) => same_literal(*other, lit),
+                            _ => false,
+                        }
+                    });
+                // a clause that matches anything here is still a candidate
+                // whether or not the value equals the literal
+                let mut wildcards = on_false
+                    .iter()
+                    .filter(|clause| clause.patterns[column].is_none())
+                    .cloned()
+                    .collect::<Vec<_>>();
+                on_true.append(&mut wildcards);
+                on_true.sort_by_key(|clause| clause.arm);
+                let mut true_columns = columns.clone();
+                true_columns.remove(column);
+                for clause in &mut on_true {
+                    clause.patterns.remove(column);
+                }
+
+                Decision::Test {
+                    value: occurrence.var,
+                    literal,
+                    on_true: Box::new(self.decide(true_columns, on_true)),
+                    on_false: Box::new(self.decide(columns, on_false)),
+                }
+            }
+            _ => unreachable!("only refutable patterns are left"),
+        }
+    }
+
+    /// Lowers the body of an arm that more than one path through a decision
+    /// tree leads to, as a lambda taking the values of the arm's bindings.
+    fn join(&mut self, symbols: &[SymbolId], body: &r::Expr<'s>) -> Expr<'s> {
+        let span = body.span;
+        let param = self.temp();
+        let body = self.expr(body);
+        let body = symbols
+            .iter()
+            .enumerate()
+            .rev()
+            .fold(body, |body, (index, &symbol)| {
+                let param = Expr {
+                    kind: ExprKind::Var(param),
+                    span,
+                };
+                let value = match symbols.len() {
+                    1 => param,
+                    _ => Expr {
+                        kind: ExprKind::Project {
+                            tuple: Box::new(param),
+                            index,
+                        },
+                        span,
+                    },
+                };
+                self.binding(symbol, value, body)
+            });
+
+        let mut captures = Vec::new();
+        free_vars(&body, &mut vec![param], &mut captures);
+        Expr {
+            kind: ExprKind::Lambda {
+                param,
+                body: Box::new(body),
+                captures: captures.into_boxed_slice(),
+            },
+            span,
+        }
+    }
+
+    /// Lowers a decision tree, taking the arms' bodies from `bodies`.
+    fn decision(
+        &mut self,
+        decision: Decision<'s>,
+        bodies: &mut [(Vec<SymbolId>, ArmBody<'s>)],
+        span: Span,
+    ) -> Expr<'s> {
+        let var = |var| Expr {
+            kind: ExprKind::Var(var),
+            span,
+        };
+
+        let kind = match decision {
+            Decision::Leaf { arm, bindings } => {
+                let (symbols, body) = &mut bodies[arm];
+                let occurrence = |symbol| {
+                    let binding = bindings.iter().find(|&&(s, _)| s == symbol);
+                    var(binding.expect("every symbol of the arm is bound").1)
+                };
+                return match body {
+                    ArmBody::Inline(body) => {
+                        let body = body.take().expect("an inline arm is reached once");
+                        symbols.iter().rev().fold(body, |body, &symbol| {
+                            self.binding(symbol, occurrence(symbol), body)
+                        })
+                    }
+                    ArmBody::Join(join) => {
+                        let arg = match &symbols[..] {
+                            [] => unit(span),
+                            [symbol] => occurrence(*symbol),
+                            symbols => Expr {
+                                kind: ExprKind::Tuple(
+                                    symbols.iter().map(|&symbol| occurrence(symbol)).collect(),
+                                ),
+                                span,
+                            },
+                        };
+                        Expr {
+                            kind: ExprKind::Apply {
+                                func: Box::new(var(*join)),
+                                arg: Box::new(arg),
+                                tail: false,
+                            },
+                            span,
+                        }
+                    }
+                    ArmBody::Unreachable => unreachable!("a leaf reaches the arm"),
+                };
+            }
+            // the match isn't exhaustive, which has already been reported
+            Decision::Fail => return unit(span),
+            Decision::Project { tuple, items, then } => {
+                let then = self.decision(*then, bodies, span);
+                return items
+                    .into_iter()
+                    .enumerate()
+                    .rev()
+                    .fold(then, |body, (index, item)| Expr {
+                        kind: ExprKind::Let {
+                            var: item,
+                            value: Box::new(Expr {
+                                kind: ExprKind::Project {
+                                    tuple: Box::new(var(tuple)),
+                                    index,
+                                },
+                                span,
+                            }),
+                            body: Box::new(body),
+                        },
+                        span,
+                    });
+            }
+            Decision::Switch {
+                variant,
+                cases,
+                default,
+            } => {
+                let mut arms = cases
+                    .into_iter()
+                    .map(|(name, payload, decision)| Arm {
+                        pattern: ArmPattern::Variant {
+                            name,
+                            bind: Some(payload),
+                        },
+                        body: self.decision(decision, bodies, span),
+                    })
+                    .collect::<Vec<_>>();
+                if let Some(default) = default {
+                    arms.push(Arm {
+                        pattern: ArmPattern::Wildcard,
+                        body: self.decision(*default, bodies, span),
+                    });
+                }
+                ExprKind::Case {
+                    scrutinee: Box::new(var(variant)),
+                    arms: arms.into_boxed_slice(),
+                }
+            }
+            Decision::Test {
+                value,
+                literal,
+                on_true,
+                on_false,
+            } => ExprKind::Case {
+                scrutinee: Box::new(Expr {
+                    kind: ExprKind::Prim {
+                        op: Prim::Eq,
+                        args: Box::new([var(value), literal]),
+                    },
+                    span,
+                }),
+                arms: Box::new([
+                    Arm {
+                        pattern: ArmPattern::Bool(true),
+                        body: self.decision(*on_true, bodies, span),
+                    },
+                    Arm {
+                        pattern: ArmPattern::Wildcard,
+                        body: self.decision(*on_false, bodies, span),
+                    },
+                ]),
+            },
+        };
+
+        Expr { kind, span }
+    }
+
+    /// Binds `symbol` to `value` around `body`, in a new cell if the symbol
+    /// is mutable.
+    fn binding(&mut self, symbol: SymbolId, value: Expr<'s>, body: Expr<'s>) -> Expr<'s> {
+        let var = self.var(symbol);
+        let span = body.span;
+        let value = if self.vars[var.0 as usize].cell {
+            let span = value.span;
+            Expr {
+                kind: ExprKind::NewRef(Box::new(value)),
+                span,
+            }
+        } else {
+            value
+        };
+
+        Expr {
+            kind: ExprKind::Let {
+                var,
+                value: Box::new(value),
+                body: Box::new(body),
+            },
+            span,
+        }
+    }
+
+    fn var(&mut self, symbol: SymbolId) -> VarId {
+        if let Some(&var) = self.symbols.get(&symbol) {
+            return var;
+        }
+
+        let sym = self.resolution.symbol(symbol);
+        let var = VarId(self.vars.len() as u32);
+        self.vars.push(Var {
+            name: Some(sym.name),
+            cell: sym.mutable,
+            constant: false,
+        });
+        self.symbols.insert(symbol, var);
+        var
+    }
+
+    fn temp(&mut self) -> VarId {
+        let var = VarId(self.vars.len() as u32);
+        self.vars.push(Var {
+            name: None,
+            cell: false,
+            constant: false,
+        });
+        var
+    }
+
+    /// Reports an error and returns a placeholder expression.
+    fn error(&mut self, kind: LowerErrorKind<'s>, span: Span) -> Expr<'s> {
+        self.errors.error(LowerError { kind, span });
+        unit(span)
+    }
+}
+
+/// A part of the scrutinee of a `match`, and its type.
+#[derive(Clone, Copy)]
+struct Occurrence {
+    var: VarId,
+    ty: Option<TypeId>,
+}
+
+/// A row of the clause matrix that a `match` is compiled from. There's a
+/// pattern for each of the occurrences being tested, which is `None` if it
+/// matches anything.
+#[derive(Clone)]
+struct Clause<'p, 's> {
+    patterns: Vec<Option<&'p r::Pattern<'s>>>,
+    /// The symbols that the arm's pattern has bound so far, and the
+    /// occurrences they're bound to.
+    bindings: Vec<(SymbolId, VarId)>,
+    arm: usize,
+}
+
+/// What a `match` compiles to, before the bodies of its arms are lowered.
+enum Decision<'s> {
+    /// Takes an arm, with the symbols of its pattern bound to occurrences.
+    Leaf {
+        arm: usize,
+        bindings: Vec<(SymbolId, VarId)>,
+    },
+    /// No arm matches.
+    Fail,
+    /// Takes a tuple apart into its items.
+    Project {
+        tuple: VarId,
+        items: Vec<VarId>,
+        then: Box<Decision<'s>>,
+    },
+    /// Branches on the case of a variant, binding its payload.
+    Switch {
+        variant: VarId,
+        cases: Vec<(Intern<'s>, VarId, Decision<'s>)>,
+        default: Option<Box<Decision<'s>>>,
+    },
+    /// Branches on whether a value equals a literal.
+    Test {
+        value: VarId,
+        literal: Expr<'s>,
+        on_true: Box<Decision<'s>>,
+        on_false: Box<Decision<'s>>,
+    },
+}
+
+impl Decision<'_> {
+    /// Counts the leaves that lead to each arm, returning whether any path
+    /// through the tree fails to match.
+    fn leaves(&self, counts: &mut [usize]) -> bool {
+        match self {
+            Decision::Leaf { arm, .. } => {
+                counts[*arm] += 1;
+                false
+            }
+            Decision::Fail => true,
+            Decision::Project { then, .. } => then.leaves(counts),
+            Decision::Switch { cases, default, .. } => {
+                let mut fails = false;
+                for (_, _, decision) in cases {
+                    fails |= decision.leaves(counts);
+                }
+                if let Some(default) = default {
+                    fails |= default.leaves(counts);
+                }
+                fails
+            }
+            Decision::Test {
+                on_true, on_false, ..
+            } => on_true.leaves(counts) | on_false.leaves(counts),
+        }
+    }
+}
+
+/// How the leaves of a decision tree take an arm.
+enum ArmBody<'s> {
+    /// The arm is only reached from one leaf, where its body goes.
+    Inline(Option<Expr<'s>>),
+    /// The arm's body is the lambda bound to the variable.
+    Join(VarId),
+    /// The arms before it match everything that it does.
+    Unreachable,
+}
+
+/// Collects the symbols bound by a pattern, in order.
+fn pattern_symbols(pat: &r::Pattern, out: &mut Vec<SymbolId>) {
+    match &pat.kind {
+        r::PatternKind::Bind(symbol) => out.push(*symbol),
+        r::PatternKind::Tuple(items) => items.iter().for_each(|item| pattern_symbols(item, out)),
+        r::PatternKind::Typed { pat, .. } => pattern_symbols(pat, out),
+        r::PatternKind::Variant {
+            value: Some(value), ..
+        } => pattern_symbols(value, out),
+        r::PatternKind::Variant { value: None, .. }
+        | r::PatternKind::Wildcard
+        | r::PatternKind::Literal(_)
+        | r::PatternKind::Error => {}
+    }
+}
+
+fn same_literal(a: r::Literal, b: r::Literal) -> bool {
+    match (a, b) {
+        (r::Literal::Float(a), r::Literal::Float(b)) => a == b,
+        (r::Literal::Integer(a), r::Literal::Integer(b)) => a == b,
+        (r::Literal::BigInteger(a), r::Literal::BigInteger(b))
+        | (r::Literal::String(a), r::Literal::String(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// The methods of the impls that the uses of constrained defs and methods
+/// need, by the offset of each use, sorted by offset.
+fn impl_needs(typing: &Typing) -> Vec<(u32, SymbolId)> {
+    let mut needs = Vec::new();
+    for (span, dictionaries) in &typing.dictionaries {
+        for &(trait_, ty) in dictionaries.iter() {
+            if let Some(methods) = typing.impls.get(&(trait_, typing.display(ty))) {
+                needs.extend(methods.iter().map(|&method| (span.start, method)));
+            }
+        }
+    }
+    needs.sort();
+    needs
+}
+
+/// Collects the variables that `expr` uses without binding them itself,
+/// other than those in `bound`.
+fn free_vars(expr: &Expr, bound: &mut Vec<VarId>, out: &mut Vec<VarId>) {
+    let mut used = |var: VarId, bound: &[VarId]| {
+        if !bound.contains(&var) && !out.contains(&var) {
+            out.push(var);
+        }
+    };
+
+    match &expr.kind {
+        ExprKind::Var(var) => used(*var, bound),
+        ExprKind::Lambda { captures, .. } => {
+            captures.iter().for_each(|&var| used(var, bound));
+        }
+        ExprKind::Let { var, value, body } => {
+            free_vars(value, bound, out);
+            bound.push(*var);
+            free_vars(body, bound, out);
+            bound.pop();
+        }
+        ExprKind::LetRec { group, body } => {
+            let len = bound.len();
+            bound.extend(group.bindings.iter().map(|(var, _)| *var));
+            for (_, value) in group.bindings.iter() {
+                free_vars(value, bound, out);
+            }
+            free_vars(body, bound, out);
+            bound.truncate(len);
+        }
+        ExprKind::Case { scrutinee, arms } => {
+            free_vars(scrutinee, bound, out);
+            for arm in arms.iter() {
+                let len = bound.len();
+                if let ArmPattern::Variant {
+                    bind: Some(bind), ..
+                } = arm.pattern
+                {
+                    bound.push(bind);
+                }
+                free_vars(&arm.body, bound, out);
+                bound.truncate(len);
+            }
+        }
+        _ => expr.for_each_child(|child| free_vars(child, bound, out)),
+    }
+}
+
+/// Wraps `body` in bindings for each group, the first group outermost.
+/// Marks the calls that are the last thing their lambda does, where `tail`
+/// says whether `expr` is itself in tail position.
+fn tail_calls(expr: &mut Expr, tail: bool) {
+    match &mut expr.kind {
+        ExprKind::Lambda { body, .. } => tail_calls(body, true),
+        ExprKind::Apply {
+            func,
+            arg,
+            tail: is_tail,
+        } => {
+            *is_tail = tail;
+            tail_calls(func, false);
+            tail_calls(arg, false);
+        }
+        ExprKind::Let { value, body, .. } => {
+            tail_calls(value, false);
+            tail_calls(body, tail);
+        }
+        ExprKind::LetRec { group, body } => {
+            for (_, value) in group.bindings.iter_mut() {
+                tail_calls(value, false);
+            }
+            tail_calls(body, tail);
+        }
+        ExprKind::Case { scrutinee, arms } => {
+            tail_calls(scrutinee, false);
+            for arm in arms.iter_mut() {
+                tail_calls(&mut arm.body, tail);
+            }
+        }
+        ExprKind::Seq(items) => {
+            if let Some((last, rest)) = items.split_last_mut() {
+                rest.iter_mut().for_each(|item| tail_calls(item, false));
+                tail_calls(last, tail);
+            }
+        }
+        _ => expr.for_each_child_mut(|child| tail_calls(child, false)),
+    }
+}
+
+fn wrap<'s>(groups: Vec<Group<'s>>, body: Expr<'s>) -> Expr<'s> {
+    groups.into_iter().rev().fold(body, |body, mut group| {
+        let span = body.span;
+        let kind = if group.recursive {
+            ExprKind::LetRec {
+                group,
+                body: Box::new(body),
+            }
+        } else {
+            let (var, value) = std::mem::take(&mut group.bindings)
+                .into_vec()
+                .pop()
+                .unwrap();
+            ExprKind::Let {
+                var,
+                value: Box::new(value),
+                body: Box::new(body),
+            }
+        };
+        Expr { kind, span }
+    })
+}
+
+fn seq(mut items: Vec<Expr>, span: Span) -> Expr {
+    if items.len() == 1 {
+        items.pop().unwrap()
+    } else {
+        Expr {
+            kind: ExprKind::Seq(items.into_boxed_slice()),
+            span,
+        }
+    }
+}
+
+fn unit<'s>(span: Span) -> Expr<'s> {
+    Expr {
+        kind: ExprKind::Literal(Literal::Unit),
+        span,
+    }
+}
@@ -0,0 +1,307 @@
+//! The high-level intermediate representation, a small core language that
+//! every backend works from.
+//!
+//! Lowering from the resolved AST removes everything that is only there for
+//! convenience:
+//! - Patterns become a single parameter that is taken apart with
+//!   [ExprKind::Project].
+//! - Mutable bindings become explicit cells: they are created with
+//!   [ExprKind::NewRef], read with [ExprKind::Load] and written with
+//!   [ExprKind::Store], and `^x` of a mutable binding is the cell itself.
+//!   Capturing a cell by value therefore captures the binding by reference.
+//! - Branches and the short-circuiting `&&` and `||` become [ExprKind::Case].
+//! - A `match` is compiled to a decision tree of [ExprKind::Case]s, which
+//!   tests each part of the scrutinee at most once.
+//! - Operators become primitives that know the type of their operands, and
+//!   coerced literals are converted. `BigInt` literals are written the way
+//!   they are displayed, so that equal values have equal literals.
+//! - Scopes become nested [ExprKind::Let]s and [ExprKind::LetRec]s, with
+//!   the defs of each scope grouped and ordered by their dependencies.
+//! - Traits become dictionaries: a [ExprKind::Record] of the methods of an
+//!   impl. A def whose type needs an impl takes the dictionary as an extra
+//!   [ExprKind::Lambda], and a method is an [ExprKind::Field] of one.
+//! - A tuple of one item is the item itself, and the empty tuple is unit.
+//!
+//! There are no operator sections, string interpolations, comprehensions or
+//! defs of several clauses to lower: `for` and `in` are reserved but nothing
+//! parses them yet, and a name defined twice in a scope is a resolve error.
+//!
+//! New syntactic sugar should be desugared here too, so that backends only
+//! ever see the core forms.
+use rustc_hash::FxHashMap;
+
+mod consteval;
+mod fold;
+mod lower;
+mod print;
+pub use consteval::*;
+pub use fold::*;
+pub use lower::*;
+
+use crate::{
+    resolver::{Builtin, SymbolId},
+    tokenizer::{Intern, Span},
+};
+
+#[derive(Debug)]
+pub struct Program<'s> {
+    /// Every variable in the program, indexed by [VarId].
+    pub vars: Vec<Var<'s>>,
+    /// The top-level defs of every module, in the order they have to be
+    /// evaluated in.
+    pub globals: Box<[Group<'s>]>,
+    /// The top-level expressions of every module, evaluated after the globals.
+    pub init: Box<[Expr<'s>]>,
+    /// The variable that each symbol was lowered to.
+    pub symbols: FxHashMap<SymbolId, VarId>,
+}
+
+impl<'s> Program<'s> {
+    pub fn var(&self, id: VarId) -> &Var<'s> {
+        &self.vars[id.0 as usize]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VarId(pub u32);
+
+#[derive(Debug)]
+pub struct Var<'s> {
+    /// `None` for variables introduced by lowering.
+    pub name: Option<Intern<'s>>,
+    /// Whether the variable holds a cell rather than a plain value.
+    pub cell: bool,
+    /// Whether the variable is a `@const` def.
+    pub constant: bool,
+}
+
+/// Defs that are bound together. A group either has a single def that
+/// doesn't refer to itself, or is recursive.
+#[derive(Debug)]
+pub struct Group<'s> {
+    pub bindings: Box<[(VarId, Expr<'s>)]>,
+    pub recursive: bool,
+}
+
+#[derive(Debug)]
+pub struct Expr<'s> {
+    pub kind: ExprKind<'s>,
+    pub span: Span,
+}
+
+impl<'s> Expr<'s> {
+    /// Calls `f` on each of the expression's direct subexpressions, in the
+    /// order they are evaluated in.
+    pub fn for_each_child<'e>(&'e self, mut f: impl FnMut(&'e Expr<'s>)) {
+        match &self.kind {
+            ExprKind::Literal(_)
+            | ExprKind::Var(_)
+            | ExprKind::Builtin(_)
+            | ExprKind::Extern(_) => {}
+            ExprKind::Lambda { body, .. } => f(body),
+            ExprKind::Apply {
+                func: a, arg: b, ..
+            }
+            | ExprKind::Let {
+                value: a, body: b, ..
+            }
+            | ExprKind::Store { cell: a, value: b } => {
+                f(a);
+                f(b);
+            }
+            ExprKind::LetRec { group, body } => {
+                group.bindings.iter().for_each(|(_, value)| f(value));
+                f(body);
+            }
+            ExprKind::Case { scrutinee, arms } => {
+                f(scrutinee);
+                arms.iter().for_each(|arm| f(&arm.body));
+            }
+            ExprKind::Seq(items) | ExprKind::Tuple(items) | ExprKind::Prim { args: items, .. } => {
+                items.iter().for_each(f)
+            }
+            ExprKind::Record(fields) => fields.iter().for_each(|(_, value)| f(value)),
+            ExprKind::Project { tuple: value, .. }
+            | ExprKind::Field { record: value, .. }
+            | ExprKind::Variant { payload: value, .. }
+            | ExprKind::NewRef(value)
+            | ExprKind::Load(value) => f(value),
+        }
+    }
+
+    /// Like [Expr::for_each_child], but lets `f` modify the subexpressions.
+    pub fn for_each_child_mut(&mut self, mut f: impl FnMut(&mut Expr<'s>)) {
+        match &mut self.kind {
+            ExprKind::Literal(_)
+            | ExprKind::Var(_)
+            | ExprKind::Builtin(_)
+            | ExprKind::Extern(_) => {}
+            ExprKind::Lambda { body, .. } => f(body),
+            ExprKind::Apply {
+                func: a, arg: b, ..
+            }
+            | ExprKind::Let {
+                value: a, body: b, ..
+            }
+            | ExprKind::Store { cell: a, value: b } => {
+                f(a);
+                f(b);
+            }
+            ExprKind::LetRec { group, body } => {
+                group.bindings.iter_mut().for_each(|(_, value)| f(value));
+                f(body);
+            }
+            ExprKind::Case { scrutinee, arms } => {
+                f(scrutinee);
+                arms.iter_mut().for_each(|arm| f(&mut arm.body));
+            }
+            ExprKind::Seq(items) | ExprKind::Tuple(items) | ExprKind::Prim { args: items, .. } => {
+                items.iter_mut().for_each(f)
+            }
+            ExprKind::Record(fields) => fields.iter_mut().for_each(|(_, value)| f(value)),
+            ExprKind::Project { tuple: value, .. }
+            | ExprKind::Field { record: value, .. }
+            | ExprKind::Variant { payload: value, .. }
+            | ExprKind::NewRef(value)
+            | ExprKind::Load(value) => f(value),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExprKind<'s> {
+    Literal(Literal<'s>),
+    Var(VarId),
+    Builtin(Builtin),
+    /// A function implemented in C. Calling it with a tuple passes each item
+    /// as a separate argument, and calling it with `()` passes none.
+    Extern(Extern<'s>),
+    Lambda {
+        param: VarId,
+        body: Box<Expr<'s>>,
+        /// The variables from outside the lambda that its body uses.
+        captures: Box<[VarId]>,
+    },
+    Apply {
+        func: Box<Expr<'s>>,
+        arg: Box<Expr<'s>>,
+        /// Whether the call is the last thing the lambda it's in does, so that
+        /// the lambda's frame is done with once the call is made.
+        tail: bool,
+    },
+    Let {
+        var: VarId,
+        value: Box<Expr<'s>>,
+        body: Box<Expr<'s>>,
+    },
+    /// Binds a group of defs. The group is in scope for both their values
+    /// and the body.
+    LetRec {
+        group: Group<'s>,
+        body: Box<Expr<'s>>,
+    },
+    /// Evaluates the first arm whose pattern matches the scrutinee.
+    Case {
+        scrutinee: Box<Expr<'s>>,
+        arms: Box<[Arm<'s>]>,
+    },
+    /// Evaluates each expression in turn, with the value of the last.
+    Seq(Box<[Expr<'s>]>),
+    Tuple(Box<[Expr<'s>]>),
+    Project {
+        tuple: Box<Expr<'s>>,
+        index: usize,
+    },
+    Record(Box<[(Intern<'s>, Expr<'s>)]>),
+    Field {
+        record: Box<Expr<'s>>,
+        name: Intern<'s>,
+    },
+    Variant {
+        name: Intern<'s>,
+        payload: Box<Expr<'s>>,
+    },
+    NewRef(Box<Expr<'s>>),
+    Load(Box<Expr<'s>>),
+    Store {
+        cell: Box<Expr<'s>>,
+        value: Box<Expr<'s>>,
+    },
+    Prim {
+        op: Prim,
+        args: Box<[Expr<'s>]>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Extern<'s> {
+    pub name: Intern<'s>,
+    pub params: Box<[ExternType]>,
+    pub ret: ExternType,
+}
+
+/// The types that can cross into C. `Int` is `int64_t`, `Float` is `double`,
+/// `String` is a NUL-terminated `const char *` and `Bool` is `int`. `Unit` is
+/// only allowed as a return type, where it is `void`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExternType {
+    Int,
+    Float,
+    String,
+    Bool,
+    Unit,
+}
+
+#[derive(Debug)]
+pub struct Arm<'s> {
+    pub pattern: ArmPattern<'s>,
+    pub body: Expr<'s>,
+}
+
+#[derive(Debug)]
+pub enum ArmPattern<'s> {
+    Bool(bool),
+    /// Matches a variant case, binding its payload.
+    Variant {
+        name: Intern<'s>,
+        bind: Option<VarId>,
+    },
+    Wildcard,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Literal<'s> {
+    Int(i64),
+    Float(f64),
+    /// A [BigInt](crate::bigint::BigInt) in decimal, as it is displayed.
+    BigInt(Intern<'s>),
+    String(Intern<'s>),
+    Bool(bool),
+    Unit,
+}
+
+/// The operations built into the language.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prim {
+    Add(Num),
+    Sub(Num),
+    Mul(Num),
+    Div(Num),
+    Mod(Num),
+    Lt(Num),
+    LtEq(Num),
+    Gt(Num),
+    GtEq(Num),
+    Concat,
+    /// Structural equality.
+    Eq,
+    NotEq,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Num {
+    Int,
+    Float,
+    BigInt,
+}
@@ -0,0 +1,222 @@
+//! A readable rendering of the HIR, for debugging the compiler.
+
+use std::fmt::{self, Display, Formatter};
+
+use super::*;
+
+impl Display for Program<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let printer = Printer { program: self };
+        for group in self.globals.iter() {
+            printer.group(f, group, 0)?;
+            writeln!(f)?;
+        }
+        for expr in self.init.iter() {
+            printer.expr(f, expr, 0)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+struct Printer<'p, 's> {
+    program: &'p Program<'s>,
+}
+
+impl Printer<'_, '_> {
+    fn var(&self, f: &mut Formatter<'_>, var: VarId) -> fmt::Result {
+        match self.program.var(var).name {
+            Some(name) => write!(f, "{}#{}", name.0, var.0),
+            None => write!(f, "%{}", var.0),
+        }
+    }
+
+    fn group(&self, f: &mut Formatter<'_>, group: &Group, indent: usize) -> fmt::Result {
+        write!(f, "{}", if group.recursive { "letrec " } else { "let " })?;
+        for (i, (var, value)) in group.bindings.iter().enumerate() {
+            if i > 0 {
+                write!(f, "\n{:indent$}and ", "", indent = indent)?;
+            }
+            self.var(f, *var)?;
+            write!(f, " = ")?;
+            self.expr(f, value, indent + 2)?;
+        }
+        write!(f, ";")
+    }
+
+    fn newline(&self, f: &mut Formatter<'_>, indent: usize) -> fmt::Result {
+        write!(f, "\n{:indent$}", "", indent = indent)
+    }
+
+    fn expr(&self, f: &mut Formatter<'_>, expr: &Expr, indent: usize) -> fmt::Result {
+        match &expr.kind {
+            ExprKind::Literal(lit) => match lit {
+                Literal::Int(i) => write!(f, "{i}"),
+                Literal::Float(x) => write!(f, "{x:?}"),
+                Literal::BigInt(digits) => write!(f, "{}n", digits.0),
+                Literal::String(s) => write!(f, "{:?}", s.0),
+                Literal::Bool(b) => write!(f, "{b}"),
+                Literal::Unit => write!(f, "()"),
+            },
+            ExprKind::Var(var) => self.var(f, *var),
+            ExprKind::Builtin(builtin) => write!(f, "@{}", builtin.name()),
+            ExprKind::Extern(external) => write!(f, "@extern {}", external.name.0),
+            ExprKind::Lambda {
+                param,
+                body,
+                captures,
+            } => {
+                write!(f, "\\")?;
+                self.var(f, *param)?;
+                if !captures.is_empty() {
+                    write!(f, " [")?;
+                    for (i, var) in captures.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        self.var(f, *var)?;
+                    }
+                    write!(f, "]")?;
+                }
+                write!(f, " ->")?;
+                self.newline(f, indent + 2)?;
+                self.expr(f, body, indent + 2)
+            }
+            ExprKind::Apply { func, arg, tail } => {
+                if *tail {
+                    write!(f, "tail ")?;
+                }
+                write!(f, "(")?;
+                self.expr(f, func, indent)?;
+                write!(f, " ")?;
+                self.expr(f, arg, indent)?;
+                write!(f, ")")
+            }
+            ExprKind::Let { var, value, body } => {
+                write!(f, "let ")?;
+                self.var(f, *var)?;
+                write!(f, " = ")?;
+                self.expr(f, value, indent + 2)?;
+                write!(f, ";")?;
+                self.newline(f, indent)?;
+                self.expr(f, body, indent)
+            }
+            ExprKind::LetRec { group, body } => {
+                self.group(f, group, indent)?;
+                self.newline(f, indent)?;
+                self.expr(f, body, indent)
+            }
+            ExprKind::Case { scrutinee, arms } => {
+                write!(f, "case ")?;
+                self.expr(f, scrutinee, indent)?;
+                write!(f, " {{")?;
+                for arm in arms.iter() {
+                    self.newline(f, indent + 2)?;
+                    match &arm.pattern {
+                        ArmPattern::Bool(b) => write!(f, "{b}")?,
+                        ArmPattern::Variant { name, bind } => {
+                            write!(f, "|{}", name.0)?;
+                            if let Some(bind) = bind {
+                                write!(f, ": ")?;
+                                self.var(f, *bind)?;
+                            }
+                        }
+                        ArmPattern::Wildcard => write!(f, "_")?,
+                    }
+                    write!(f, " => ")?;
+                    self.expr(f, &arm.body, indent + 4)?;
+                }
+                self.newline(f, indent)?;
+                write!(f, "}}")
+            }
+            ExprKind::Seq(items) => {
+                write!(f, "{{")?;
+                for item in items.iter() {
+                    self.newline(f, indent + 2)?;
+                    self.expr(f, item, indent + 2)?;
+                    write!(f, ";")?;
+                }
+                self.newline(f, indent)?;
+                write!(f, "}}")
+            }
+            ExprKind::Tuple(items) => {
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    self.expr(f, item, indent)?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Project { tuple, index } => {
+                self.expr(f, tuple, indent)?;
+                write!(f, ".{index}")
+            }
+            ExprKind::Record(fields) => {
+                write!(f, ".{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    write!(f, "{}{} = ", if i > 0 { ", " } else { " " }, name.0)?;
+                    self.expr(f, value, indent)?;
+                }
+                write!(f, " }}")
+            }
+            ExprKind::Field { record, name } => {
+                self.expr(f, record, indent)?;
+                write!(f, ".{}", name.0)
+            }
+            ExprKind::Variant { name, payload } => {
+                write!(f, "|{}: ", name.0)?;
+                self.expr(f, payload, indent)
+            }
+            ExprKind::NewRef(value) => {
+                write!(f, "ref(")?;
+                self.expr(f, value, indent)?;
+                write!(f, ")")
+            }
+            ExprKind::Load(cell) => {
+                self.expr(f, cell, indent)?;
+                write!(f, "^")
+            }
+            ExprKind::Store { cell, value } => {
+                self.expr(f, cell, indent)?;
+                write!(f, " := ")?;
+                self.expr(f, value, indent)
+            }
+            ExprKind::Prim { op, args } => {
+                write!(f, "({op}")?;
+                for arg in args.iter() {
+                    write!(f, " ")?;
+                    self.expr(f, arg, indent)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl Display for Prim {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (name, num) = match self {
+            Prim::Add(num) => ("add", Some(num)),
+            Prim::Sub(num) => ("sub", Some(num)),
+            Prim::Mul(num) => ("mul", Some(num)),
+            Prim::Div(num) => ("div", Some(num)),
+            Prim::Mod(num) => ("mod", Some(num)),
+            Prim::Lt(num) => ("lt", Some(num)),
+            Prim::LtEq(num) => ("le", Some(num)),
+            Prim::Gt(num) => ("gt", Some(num)),
+            Prim::GtEq(num) => ("ge", Some(num)),
+            Prim::Concat => ("concat", None),
+            Prim::Eq => ("eq", None),
+            Prim::NotEq => ("ne", None),
+            Prim::Not => ("not", None),
+        };
+        match num {
+            Some(Num::Int) => write!(f, "{name}.int"),
+            Some(Num::Float) => write!(f, "{name}.float"),
+            Some(Num::BigInt) => write!(f, "{name}.big"),
+            None => write!(f, "{name}"),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! The index as an LSIF dump, a graph of vertices and edges written as a
+//! line of JSON each.
+
+use std::{fmt::Write, path::Path};
+
+use super::{position, uri, Index};
+use crate::{doc::json_string, source_map::SourceMap, tokenizer::Span};
+
+/// The index in the LSIF format, as a line of JSON for each vertex and
+/// edge of its graph. Positions are in UTF-16 code units, and `root` is the
+/// root of the project.
+pub fn lsif(source_map: &SourceMap, index: &Index, root: &Path) -> String {
+    let mut lsif = Lsif {
+        out: String::new(),
+        next_id: 1,
+    };
+    lsif.vertex(
+        "metaData",
+        &format!(
+            "\"version\":\"0.4.3\",\"projectRoot\":{},\"positionEncoding\":\"utf-16\",\
+             \"toolInfo\":{{\"name\":\"radi\",\"version\":\"{}\"}}",
+            json_string(&uri(root)),
+            env!("CARGO_PKG_VERSION")
+        ),
+    );
+    let project = lsif.vertex("project", "\"kind\":\"radi\"");
+    let documents = index
+        .documents
+        .iter()
+        .map(|&file| {
+            let path = &source_map.file(file).path;
+            let fields = format!(
+                "\"uri\":{},\"languageId\":\"radi\"",
+                json_string(&uri(path))
+            );
+            lsif.vertex("document", &fields)
+        })
+        .collect::<Vec<_>>();
+    lsif.edge("contains", project, &documents, "");
+
+    // the ranges in each document, which it's said to contain at the end
+    let mut ranges = vec![Vec::new(); documents.len()];
+    let mut range = |lsif: &mut Lsif, span: Span| {
+        let file = source_map.lookup(span.start);
+        let document = index.documents.iter().position(|&id| id == file);
+        let document = document.expect("the symbols are only in the documents");
+        let file = source_map.file(file);
+        let (start_line, start_column) = position(file, span.start);
+        let (end_line, end_column) = position(file, span.end);
+        let id = lsif.vertex(
+            "range",
+            &format!(
+                "\"start\":{{\"line\":{start_line},\"character\":{start_column}}},\
+                 \"end\":{{\"line\":{end_line},\"character\":{end_column}}}"
+            ),
+        );
+        ranges[document].push(id);
+        (documents[document], id)
+    };
+
+    for symbol in &index.symbols {
+        let result_set = lsif.vertex("resultSet", "");
+        let (document, definition) = range(&mut lsif, symbol.definition);
+        lsif.edge("next", definition, &[result_set], "");
+
+        let result = lsif.vertex("definitionResult", "");
+        lsif.edge("textDocument/definition", result_set, &[result], "");
+        lsif.edge(
+            "item",
+            result,
+            &[definition],
+            &format!(",\"document\":{document}"),
+        );
+
+        let contents = format!(
+            "\"result\":{{\"contents\":{{\"kind\":\"markdown\",\"value\":{}}}}}",
+            json_string(&symbol.hover)
+        );
+        let result = lsif.vertex("hoverResult", &contents);
+        lsif.edge("textDocument/hover", result_set, &[result], "");
+
+        let result = lsif.vertex("referenceResult", "");
+        lsif.edge("textDocument/references", result_set, &[result], "");
+        lsif.edge(
+            "item",
+            result,
+            &[definition],
+            &format!(",\"document\":{document},\"property\":\"definitions\""),
+        );
+        let references = symbol
+            .references
+            .iter()
+            .map(|&span| {
+                let (document, reference) = range(&mut lsif, span);
+                lsif.edge("next", reference, &[result_set], "");
+                (document, reference)
+            })
+            .collect::<Vec<_>>();
+        // the references are grouped by the document they're in
+        let mut seen = Vec::new();
+        for &(document, _) in &references {
+            if seen.contains(&document) {
+                continue;
+            }
+            seen.push(document);
+            let items = references
+                .iter()
+                .filter(|&&(d, _)| d == document)
+                .map(|&(_, reference)| reference)
+                .collect::<Vec<_>>();
+            lsif.edge(
+                "item",
+                result,
+                &items,
+                &format!(",\"document\":{document},\"property\":\"references\""),
+            );
+        }
+    }
+
+    for (document, ranges) in documents.iter().zip(&ranges) {
+        if !ranges.is_empty() {
+            lsif.edge("contains", *document, ranges, "");
+        }
+    }
+    lsif.out
+}
+
+/// Writes the vertices and edges of an LSIF graph, numbering them in the
+/// order they're written.
+struct Lsif {
+    out: String,
+    next_id: u32,
+}
+
+impl Lsif {
+    /// Writes a vertex with the JSON `fields` after its label, and returns
+    /// its ID.
+    fn vertex(&mut self, label: &str, fields: &str) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        let comma = if fields.is_empty() { "" } else { "," };
+        let _ = writeln!(
+            self.out,
+            "{{\"id\":{id},\"type\":\"vertex\",\"label\":\"{label}\"{comma}{fields}}}"
+        );
+        id
+    }
+
+    /// Writes an edge from `out_v` to `in_vs`, with `extra` JSON fields that
+    /// start with a comma.
+    fn edge(&mut self, label: &str, out_v: u32, in_vs: &[u32], extra: &str) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let in_vs = in_vs.iter().map(u32::to_string).collect::<Vec<_>>();
+        let _ = writeln!(
+            self.out,
+            "{{\"id\":{id},\"type\":\"edge\",\"label\":\"{label}\",\"outV\":{out_v},\
+             \"inVs\":[{}]{extra}}}",
+            in_vs.join(",")
+        );
+    }
+}
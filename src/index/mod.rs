@@ -0,0 +1,169 @@
+//! Indexes for `radi index`, which let code hosts and code review tools
+//! navigate a program without running a language server: where each symbol
+//! is defined, where it's referenced and what hovering over it shows. The
+//! symbols are collected once into an [Index], which is then written out in
+//! the format that was asked for: [lsif] or [scip].
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    parse_manager::ParseManager,
+    resolver::{self, Resolution, SymbolId, SymbolKind},
+    source_map::{FileId, SourceFile},
+    tokenizer::Span,
+    typeck::Typing,
+};
+
+mod lsif;
+mod scip;
+
+pub use lsif::lsif;
+pub use scip::scip;
+
+/// The symbols of a program, and the files they're in.
+#[derive(Debug)]
+pub struct Index {
+    /// The files of the program, other than the prelude, in the order they
+    /// were loaded.
+    pub documents: Vec<FileId>,
+    /// The defs and parameters bound in the documents, in the order they're
+    /// bound.
+    pub symbols: Vec<IndexedSymbol>,
+}
+
+#[derive(Debug)]
+pub struct IndexedSymbol {
+    pub id: SymbolId,
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The name where the symbol is bound.
+    pub definition: Span,
+    /// The identifiers that refer to the symbol from the documents, in
+    /// source order.
+    pub references: Vec<Span>,
+    /// What hovering over the symbol shows, as Markdown.
+    pub hover: String,
+    /// Whether the symbol is a public def that other modules can import.
+    pub exported: bool,
+}
+
+/// Collects the symbols bound in every file of the program but the prelude.
+pub fn collect(manager: &ParseManager, resolution: &Resolution, typing: Option<&Typing>) -> Index {
+    let source_map = manager.source_map();
+    let documents = source_map
+        .files()
+        .map(|(id, _)| id)
+        .filter(|&id| Some(id) != manager.prelude())
+        .collect::<Vec<_>>();
+    let in_documents = |span: Span| documents.contains(&source_map.lookup(span.start));
+
+    let symbols = resolution
+        .symbols
+        .iter()
+        .enumerate()
+        .filter(|(_, symbol)| {
+            matches!(
+                symbol.kind,
+                SymbolKind::Def | SymbolKind::Param | SymbolKind::Trait | SymbolKind::Method(_)
+            )
+        })
+        .filter(|(_, symbol)| in_documents(symbol.span))
+        .map(|(i, symbol)| {
+            let id = SymbolId(i as u32);
+            let references = resolver::references(resolution, id)
+                .into_iter()
+                .filter(|&span| in_documents(span))
+                .collect();
+            let ty = typing.and_then(|typing| Some(typing.display(*typing.symbols.get(&id)?)));
+            let doc = match symbol.kind {
+                SymbolKind::Def | SymbolKind::Trait | SymbolKind::Method(_) => {
+                    let file = source_map.file(source_map.lookup(symbol.span.start));
+                    resolver::doc_comment(file, symbol.span.start)
+                }
+                _ => None,
+            };
+            IndexedSymbol {
+                id,
+                name: symbol.name.0.to_string(),
+                kind: symbol.kind,
+                definition: symbol.span,
+                references,
+                hover: hover_markdown(symbol.name.0, symbol.kind, ty, doc),
+                exported: resolution.exported.contains(&id),
+            }
+        })
+        .collect();
+    Index { documents, symbols }
+}
+
+/// The signature of a symbol in a code block, followed by its documentation.
+fn hover_markdown(name: &str, kind: SymbolKind, ty: Option<String>, doc: Option<String>) -> String {
+    let binding = match kind {
+        SymbolKind::Def | SymbolKind::Method(_) => format!("def {name}"),
+        SymbolKind::Trait => format!("trait {name}"),
+        _ => name.to_string(),
+    };
+    let mut out = match ty {
+        Some(ty) => format!("```radi\n{binding} :: {ty}\n```"),
+        None => format!("```radi\n{binding}\n```"),
+    };
+    if let Some(doc) = doc {
+        let _ = write!(out, "\n\n{doc}");
+    }
+    out
+}
+
+/// A `file://` URI for `path`, made absolute if it can be.
+pub fn uri(path: &Path) -> String {
+    let mut out = String::from("file://");
+    for byte in absolute(path).to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                out.push(byte as char)
+            }
+            _ => {
+                let _ = write!(out, "%{byte:02X}");
+            }
+        }
+    }
+    out
+}
+
+/// `path` as an absolute path, or as it is if it can't be made one.
+fn absolute(path: &Path) -> PathBuf {
+    // the root of a file compiled on its own in the current directory
+    let path = match path.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => path,
+    };
+    std::fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| PathBuf::from(path))
+}
+
+/// `path` relative to `root`, with `/` between its components, or made
+/// absolute if it isn't under `root`.
+pub(crate) fn relative_path(root: &Path, path: &Path) -> String {
+    let (root, path) = (absolute(root), absolute(path));
+    match path.strip_prefix(&root) {
+        Ok(relative) => {
+            let components = relative.components();
+            let components = components.map(|component| component.as_os_str().to_string_lossy());
+            components.collect::<Vec<_>>().join("/")
+        }
+        Err(_) => path.to_string_lossy().into_owned(),
+    }
+}
+
+/// Where `offset` is in `file`, as the 0-based line and the 0-based column
+/// in UTF-16 code units that editors count positions in.
+pub fn position(file: &SourceFile, offset: u32) -> (usize, usize) {
+    let before = &file.src[..(offset - file.start) as usize];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = before.matches('\n').count();
+    let column = before[line_start..].encode_utf16().count();
+    (line, column)
+}
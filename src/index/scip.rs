@@ -0,0 +1,150 @@
+//! The index in the SCIP format, a Protocol Buffers message of the
+//! documents of the program and the occurrences of symbols in each of them.
+//! Only the fields of `scip.proto` that radi has something to say about are
+//! written, and nothing is read back, so the encoding is written by hand.
+//!
+//! Public defs, which other modules can refer to, are global symbols named
+//! by the module they're in, as in `radi . . . util/double.`. Every other
+//! symbol is local to its document.
+
+use std::path::Path;
+
+use super::{position, relative_path, uri, Index, IndexedSymbol};
+use crate::{parse_manager::ParseManager, tokenizer::Span};
+
+/// `SymbolRole.Definition`.
+const DEFINITION: u64 = 0x1;
+/// `TextEncoding.UTF8`, since the source files are UTF-8.
+const UTF8: u64 = 1;
+/// `PositionEncoding.UTF16CodeUnitOffsetFromLineStart`, which is what
+/// [position] counts columns in.
+const UTF16_OFFSETS: u64 = 2;
+
+/// The index as an encoded SCIP `Index` message, with the paths of the
+/// documents relative to `root`.
+pub fn scip(manager: &ParseManager, index: &Index, root: &Path) -> Vec<u8> {
+    let source_map = manager.source_map();
+    let mut out = Message::default();
+
+    let mut tool = Message::default();
+    tool.string(1, "radi");
+    tool.string(2, env!("CARGO_PKG_VERSION"));
+    let mut metadata = Message::default();
+    metadata.message(2, tool);
+    metadata.string(3, &uri(root));
+    metadata.varint(4, UTF8);
+    out.message(1, metadata);
+
+    for &file in &index.documents {
+        let source = source_map.file(file);
+        let in_file = |span: Span| source_map.lookup(span.start) == file;
+
+        // every name in the file that a symbol is bound at or referred to by
+        let mut occurrences = Vec::new();
+        for symbol in &index.symbols {
+            if in_file(symbol.definition) {
+                occurrences.push((symbol.definition, symbol, DEFINITION));
+            }
+            let references = symbol.references.iter().filter(|&&span| in_file(span));
+            occurrences.extend(references.map(|&span| (span, symbol, 0)));
+        }
+        occurrences.sort_by_key(|(span, _, _)| span.start);
+
+        let mut document = Message::default();
+        document.string(1, &relative_path(root, &source.path));
+        for (span, symbol, roles) in occurrences {
+            let (start_line, start_column) = position(source, span.start);
+            let (end_line, end_column) = position(source, span.end);
+            // the end line is left out when it's the same as the start's
+            let range = match start_line == end_line {
+                true => vec![start_line, start_column, end_column],
+                false => vec![start_line, start_column, end_line, end_column],
+            };
+            let mut occurrence = Message::default();
+            occurrence.packed(1, range.into_iter().map(|n| n as u64));
+            occurrence.string(2, &symbol_name(manager, symbol));
+            if roles != 0 {
+                occurrence.varint(3, roles);
+            }
+            document.message(2, occurrence);
+        }
+        for symbol in index.symbols.iter().filter(|s| in_file(s.definition)) {
+            let mut information = Message::default();
+            information.string(1, &symbol_name(manager, symbol));
+            information.string(3, &symbol.hover);
+            information.string(6, &symbol.name);
+            document.message(3, information);
+        }
+        document.string(4, "radi");
+        document.varint(6, UTF16_OFFSETS);
+        out.message(2, document);
+    }
+    out.0
+}
+
+/// The SCIP symbol of `symbol`.
+fn symbol_name(manager: &ParseManager, symbol: &IndexedSymbol) -> String {
+    if !symbol.exported {
+        return format!("local {}", symbol.id.0);
+    }
+    let defined_in = manager.source_map().lookup(symbol.definition.start);
+    let module = manager.module(defined_in).map_or("", |module| &module.name);
+    let mut name = String::from("radi . . . ");
+    for segment in module.split('.').filter(|segment| !segment.is_empty()) {
+        name.push_str(segment);
+        name.push('/');
+    }
+    name.push_str(&symbol.name);
+    name.push('.');
+    name
+}
+
+/// A Protocol Buffers message being encoded.
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn tag(&mut self, field: u32, wire_type: u8) {
+        self.raw_varint(((field as u64) << 3) | wire_type as u64);
+    }
+
+    fn raw_varint(&mut self, mut n: u64) {
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                self.0.push(byte);
+                return;
+            }
+            self.0.push(byte | 0x80);
+        }
+    }
+
+    fn varint(&mut self, field: u32, n: u64) {
+        self.tag(field, 0);
+        self.raw_varint(n);
+    }
+
+    fn bytes(&mut self, field: u32, bytes: &[u8]) {
+        self.tag(field, 2);
+        self.raw_varint(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn string(&mut self, field: u32, string: &str) {
+        self.bytes(field, string.as_bytes());
+    }
+
+    fn message(&mut self, field: u32, message: Message) {
+        self.bytes(field, &message.0);
+    }
+
+    /// A repeated field of varints, packed into one.
+    fn packed(&mut self, field: u32, ns: impl IntoIterator<Item = u64>) {
+        let mut packed = Message::default();
+        for n in ns {
+            packed.raw_varint(n);
+        }
+        self.message(field, packed);
+    }
+}
@@ -0,0 +1,1141 @@
+//! Compiles the HIR to native code with Cranelift and runs it in the
+//! compiler's own process, for `radi run --jit`.
+//!
+//! Values are represented the way the C backend represents them: pointers
+//! to a [Value](runtime::Value) that the runtime in [runtime] allocates.
+//! Literals are allocated once, while compiling, and compiled code refers to
+//! them by address, as it does to the table that holds the top-level defs.
+//! Each lambda becomes a function taking its closure and its argument, with
+//! Cranelift's `tail` calling convention, so that a call that is the last
+//! thing a lambda does is made with `return_call` and recursion in tail
+//! position runs in constant stack, as it does in the interpreter.
+//!
+//! Compiled code reads tags, tuple items, captures, variants, cells and
+//! booleans itself, and calls the runtime for everything that allocates or
+//! can fail. A runtime error is reported without where it happened, as in
+//! the C backend, and exits the process.
+//!
+//! An `@extern` function is looked up among the symbols of the process, so
+//! the C library and whatever the compiler is linked with can be called,
+//! and wrapped in a closure that converts its arguments and result.
+//!
+//! Generic top-level defs are monomorphized, as [mono] describes. An
+//! instance is a closure that's allocated while compiling, since it
+//! captures nothing, so a call to one is a direct call, and the code in it
+//! compares values of the types it knows inline.
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use cranelift_codegen::{
+    entity::EntityRef,
+    ir::{
+        condcodes::{FloatCC, IntCC},
+        types::{F64, I32, I64, I8},
+        AbiParam, Block, FuncRef, InstBuilder, MemFlags, SigRef, Signature, TrapCode, Type,
+        UserFuncName, Value as Val,
+    },
+    isa::CallConv,
+    settings::{self, Configurable},
+    Context,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{FuncId, Linkage, Module};
+
+use crate::{
+    bigint::BigInt,
+    hir::{
+        Arm, ArmPattern, Expr, ExprKind, Extern, ExternType, Group, Literal, Num, Prim, Program,
+        VarId,
+    },
+    resolver::Builtin,
+    tokenizer::Span,
+    typeck::{Type as Ty, TypeId, Typing},
+};
+
+mod mono;
+mod runtime;
+
+pub use mono::MonoStats;
+use mono::Subst;
+use runtime::{Name, Ptr, Value, CLOSURE, FIELDS};
+
+/// Compiles a program and runs it, calling `main` with `()` after the
+/// top-level expressions are evaluated. What can't be compiled, such as an
+/// extern that isn't found, is returned as the message that reports it.
+/// Runtime errors exit the process.
+pub fn run(program: &Program, typing: &Typing, main: Option<VarId>) -> Result<(), String> {
+    let mut codegen = Codegen::new(program, typing)?;
+    let init = codegen.init(program, main);
+    if let Some(name) = codegen.missing.first() {
+        return Err(format!("ERROR: couldn't find the C function `{name}`"));
+    }
+    codegen.module.finalize_definitions().unwrap();
+    for &(closure, id) in &codegen.instances {
+        let code = codegen.module.get_finalized_function(id);
+        // SAFETY: the closure was allocated while compiling and nothing has
+        // run that could have read it yet
+        unsafe { runtime::set_code(closure, code) };
+    }
+    let code = codegen.module.get_finalized_function(init);
+    // SAFETY: `init` was compiled with the default calling convention and
+    // takes nothing, and every value it refers to outlives it
+    let init = unsafe { std::mem::transmute::<*const u8, extern "C" fn()>(code) };
+    init();
+    Ok(())
+}
+
+/// Compiles a program without running it, for how big monomorphizing its
+/// generic defs made its code.
+pub fn mono_stats(
+    program: &Program,
+    typing: &Typing,
+    main: Option<VarId>,
+) -> Result<MonoStats, String> {
+    let mut codegen = Codegen::new(program, typing)?;
+    codegen.init(program, main);
+    let mut stats = MonoStats {
+        defs: Vec::new(),
+        total: codegen.code_size,
+    };
+    let globals = program.globals.iter().flat_map(|g| g.bindings.iter());
+    for (var, _) in globals {
+        let Some(generic) = codegen.generics.get(var) else {
+            continue;
+        };
+        let instances = codegen
+            .keys
+            .iter()
+            .enumerate()
+            .filter(|(_, (of, _))| of == var)
+            .map(|(i, (_, types))| (types.clone(), codegen.sizes[&(*var, Some(i))]))
+            .collect::<Vec<_>>();
+        if !instances.is_empty() {
+            stats.defs.push(mono::DefStats {
+                name: generic.name.clone(),
+                generic: codegen.sizes.get(&(*var, None)).copied().unwrap_or(0),
+                instances,
+            });
+        }
+    }
+    Ok(stats)
+}
+
+fn param(ty: runtime::Type) -> AbiParam {
+    AbiParam::new(match ty {
+        runtime::Type::Word => I64,
+        runtime::Type::Int => I32,
+        runtime::Type::Double => F64,
+        runtime::Type::Bool => I8,
+    })
+}
+
+/// The body of a function being generated.
+struct Function<'f> {
+    builder: FunctionBuilder<'f>,
+    /// The variable that each of the program's variables is in this
+    /// function.
+    vars: FxHashMap<VarId, Variable>,
+    /// The functions that this one calls, imported as they're first called.
+    imported: FxHashMap<FuncId, FuncRef>,
+    /// The signature of lambdas, for calling closures.
+    lambda: SigRef,
+}
+
+impl<'f> Function<'f> {
+    fn new(mut builder: FunctionBuilder<'f>, lambda: &Signature) -> Function<'f> {
+        let lambda = builder.import_signature(lambda.clone());
+        let mut f = Function {
+            builder,
+            vars: FxHashMap::default(),
+            imported: FxHashMap::default(),
+            lambda,
+        };
+        let entry = f.builder.create_block();
+        f.builder.append_block_params_for_function_params(entry);
+        f.builder.switch_to_block(entry);
+        f
+    }
+
+    fn pointer(&mut self, value: Ptr) -> Val {
+        self.builder.ins().iconst(I64, value as i64)
+    }
+
+    /// Loads a field of the value at `base`, the `index`th after its tag.
+    fn field(&mut self, ty: Type, base: Val, index: i32) -> Val {
+        let offset = FIELDS + 8 * index;
+        self.builder
+            .ins()
+            .load(ty, MemFlags::trusted(), base, offset)
+    }
+
+    /// The `index`th of the values that `items` points to.
+    fn item(&mut self, items: Val, index: usize) -> Val {
+        let offset = 8 * index as i32;
+        self.builder
+            .ins()
+            .load(I64, MemFlags::trusted(), items, offset)
+    }
+
+    fn set_item(&mut self, items: Val, index: usize, value: Val) {
+        let offset = 8 * index as i32;
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), value, items, offset);
+    }
+
+    /// The shared `true` or `false`, as `condition` is nonzero or not.
+    fn boolean(&mut self, condition: Val) -> Val {
+        let yes = self.pointer(runtime::boolean(true));
+        let no = self.pointer(runtime::boolean(false));
+        self.builder.ins().select(condition, yes, no)
+    }
+
+    fn finish(mut self) {
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+    }
+}
+
+/// A generic top-level def that instances can be made of.
+struct Generic<'p, 's> {
+    name: String,
+    /// The generalized type of the def.
+    scheme: TypeId,
+    /// The generic variables of `scheme`, which instances are told apart by
+    /// the types of.
+    vars: Vec<TypeId>,
+    value: &'p Expr<'s>,
+}
+
+struct Codegen<'p, 's> {
+    module: JITModule,
+    typing: &'p Typing<'s>,
+    helpers: FxHashMap<&'static str, FuncId>,
+    lambda: Signature,
+    strings: FxHashMap<&'s str, Ptr>,
+    names: FxHashMap<&'s str, Name>,
+    /// The names of the fields of each kind of record, by the names.
+    records: FxHashMap<Vec<&'s str>, *const &'static [Name]>,
+    builtins: FxHashMap<Builtin, Ptr>,
+    /// The wrapper function of each extern, by its name and C types.
+    externs: FxHashMap<(&'s str, Box<[ExternType]>, ExternType), FuncId>,
+    /// The index of each top-level def in [Codegen::table].
+    globals: FxHashMap<VarId, usize>,
+    /// The values of the top-level defs.
+    table: *mut Ptr,
+    /// The externs that aren't among the symbols of the process.
+    missing: Vec<String>,
+    next_lambda: usize,
+    generics: FxHashMap<VarId, Generic<'p, 's>>,
+    /// The def and the types of each instance, by its index.
+    keys: Vec<(VarId, Box<[String]>)>,
+    /// The index of each instance, by its def and types.
+    indices: FxHashMap<(VarId, Box<[String]>), usize>,
+    /// The closure and function of each instance.
+    instances: Vec<(Ptr, FuncId)>,
+    /// The instances that have been made but not yet compiled, with what
+    /// their generic variables are bound to.
+    pending: Vec<(usize, Subst)>,
+    /// What the generic variables are bound to in the code being compiled,
+    /// which is nothing outside of instances.
+    subst: Subst,
+    /// The generic def, or the instance of one, that the code being
+    /// compiled is part of.
+    owner: Option<(VarId, Option<usize>)>,
+    /// The size of the code of each generic def and instance.
+    sizes: FxHashMap<(VarId, Option<usize>), usize>,
+    /// The size of all the code compiled so far.
+    code_size: usize,
+}
+
+impl<'p, 's> Codegen<'p, 's> {
+    /// Sets up the JIT for a program, finding the defs that instances can be
+    /// made of.
+    fn new(program: &'p Program<'s>, typing: &'p Typing<'s>) -> Result<Codegen<'p, 's>, String> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").unwrap();
+        // the JIT links with absolute addresses
+        flags.set("use_colocated_libcalls", "false").unwrap();
+        flags.set("is_pic", "false").unwrap();
+        // which Cranelift's tail calls rely on
+        flags.set("preserve_frame_pointers", "true").unwrap();
+        let isa = cranelift_native::builder()
+            .map_err(|err| format!("ERROR: the JIT doesn't support this machine: {err}"))?
+            .finish(settings::Flags::new(flags))
+            .map_err(|err| format!("ERROR: the JIT doesn't support this machine: {err}"))?;
+        let mut builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+        let helpers = runtime::helpers();
+        for helper in &helpers {
+            builder.symbol(helper.name, helper.address);
+        }
+        let mut module = JITModule::new(builder);
+
+        let mut lambda = module.make_signature();
+        lambda.call_conv = CallConv::Tail;
+        lambda.params = vec![AbiParam::new(I64); 2];
+        lambda.returns = vec![AbiParam::new(I64)];
+        let mut declared = FxHashMap::default();
+        for helper in &helpers {
+            let mut signature = module.make_signature();
+            signature.params = helper.params.iter().map(|&ty| param(ty)).collect();
+            signature.returns = helper.returns.iter().map(|&ty| param(ty)).collect();
+            let id = module
+                .declare_function(helper.name, Linkage::Import, &signature)
+                .unwrap();
+            declared.insert(helper.name, id);
+        }
+
+        let mut codegen = Codegen {
+            module,
+            typing,
+            helpers: declared,
+            lambda,
+            strings: FxHashMap::default(),
+            names: FxHashMap::default(),
+            records: FxHashMap::default(),
+            builtins: FxHashMap::default(),
+            externs: FxHashMap::default(),
+            globals: FxHashMap::default(),
+            table: std::ptr::null_mut(),
+            missing: Vec::new(),
+            next_lambda: 0,
+            generics: FxHashMap::default(),
+            keys: Vec::new(),
+            indices: FxHashMap::default(),
+            instances: Vec::new(),
+            pending: Vec::new(),
+            subst: Subst::default(),
+            owner: None,
+            sizes: FxHashMap::default(),
+            code_size: 0,
+        };
+        let globals = program.globals.iter().flat_map(|g| g.bindings.iter());
+        for (i, (var, _)) in globals.clone().enumerate() {
+            codegen.globals.insert(*var, i);
+        }
+        let table = vec![runtime::unit(); codegen.globals.len()].into_boxed_slice();
+        codegen.table = Box::into_raw(table).cast();
+
+        // an instance is a closure made while compiling, so only a def that's
+        // a function capturing nothing can have them
+        let symbols = program.symbols.iter().map(|(&symbol, &var)| (var, symbol));
+        let symbols = symbols.collect::<FxHashMap<_, _>>();
+        for (var, value) in globals {
+            let (Some(symbol), Some(name)) = (symbols.get(var), program.var(*var).name) else {
+                continue;
+            };
+            let Some(&scheme) = typing.symbols.get(symbol) else {
+                continue;
+            };
+            let vars = mono::generic_vars(typing, scheme);
+            let lambda = matches!(value.kind, ExprKind::Lambda { .. });
+            if vars.is_empty() || !lambda || !codegen.captures(value).is_empty() {
+                continue;
+            }
+            let name = name.0.to_string();
+            let generic = Generic {
+                name,
+                scheme,
+                vars,
+                value,
+            };
+            codegen.generics.insert(*var, generic);
+        }
+        Ok(codegen)
+    }
+
+    /// Compiles the function that evaluates the top-level defs and
+    /// expressions, then calls `main`.
+    fn init(&mut self, program: &Program<'s>, main: Option<VarId>) -> FuncId {
+        let signature = self.module.make_signature();
+        let id = self
+            .module
+            .declare_function("init", Linkage::Local, &signature)
+            .unwrap();
+        let mut ctx = self.module.make_context();
+        ctx.func.signature = signature;
+        ctx.func.name = UserFuncName::user(0, id.as_u32());
+        let mut builder = FunctionBuilderContext::new();
+        let mut f = Function::new(
+            FunctionBuilder::new(&mut ctx.func, &mut builder),
+            &self.lambda,
+        );
+        for group in program.globals.iter() {
+            self.group(&mut f, group);
+        }
+        for expr in program.init.iter() {
+            self.expr(&mut f, expr);
+        }
+        if let Some(main) = main {
+            let main = self.var(&mut f, main);
+            let unit = f.pointer(runtime::unit());
+            self.apply(&mut f, main, unit);
+        }
+        f.builder.ins().return_(&[]);
+        f.finish();
+        self.define_function(id, &mut ctx, "init");
+        // compiling an instance can make more of them
+        while let Some((index, subst)) = self.pending.pop() {
+            self.compile_instance(index, subst);
+        }
+        id
+    }
+
+    /// Defines a compiled function, counting its size towards what's being
+    /// compiled.
+    fn define_function(&mut self, id: FuncId, ctx: &mut Context, name: &str) {
+        self.module
+            .define_function(id, ctx)
+            .unwrap_or_else(|err| panic!("couldn't compile {name}: {err:?}"));
+        let size = ctx.compiled_code().unwrap().code_info().total_size as usize;
+        self.code_size += size;
+        if let Some(owner) = self.owner {
+            *self.sizes.entry(owner).or_default() += size;
+        }
+    }
+
+    /// Calls a function of the runtime, returning its result.
+    fn call(&mut self, f: &mut Function, helper: &str, args: &[Val]) -> Val {
+        let func = self.import(f, self.helpers[helper]);
+        let call = f.builder.ins().call(func, args);
+        f.builder.inst_results(call)[0]
+    }
+
+    fn import(&mut self, f: &mut Function, id: FuncId) -> FuncRef {
+        *f.imported
+            .entry(id)
+            .or_insert_with(|| self.module.declare_func_in_func(id, f.builder.func))
+    }
+
+    fn define(&mut self, f: &mut Function, var: VarId, value: Val) {
+        if let Some(&i) = self.globals.get(&var) {
+            let table = f.pointer(self.table.cast());
+            f.set_item(table, i, value);
+            return;
+        }
+        let next = Variable::new(f.vars.len());
+        let variable = *f.vars.entry(var).or_insert_with(|| {
+            f.builder.declare_var(next, I64);
+            next
+        });
+        f.builder.def_var(variable, value);
+    }
+
+    fn var(&mut self, f: &mut Function, var: VarId) -> Val {
+        match self.globals.get(&var) {
+            Some(&i) => {
+                let table = f.pointer(self.table.cast());
+                f.item(table, i)
+            }
+            None => f.builder.use_var(f.vars[&var]),
+        }
+    }
+
+    /// The instance that a use of `var` at `span` refers to, making it if
+    /// this is the first use at its types. `None` if `var` isn't generic, or
+    /// the use is at types that aren't known, so that it refers to the
+    /// generic def.
+    fn instance(&mut self, var: VarId, span: Span) -> Option<usize> {
+        let generic = self.generics.get(&var)?;
+        let actual = self.typing.type_of(span)?;
+        let mut bound = Subst::default();
+        let mut seen = FxHashSet::default();
+        let (typing, subst) = (self.typing, &self.subst);
+        if !mono::bind(typing, subst, generic.scheme, actual, &mut bound, &mut seen) {
+            return None;
+        }
+        // a variable bound to a type that isn't wholly known is left generic,
+        // since the instance couldn't do anything with the part that is
+        let mut types = Vec::new();
+        for var in &generic.vars {
+            match bound.get(var).and_then(|&ty| mono::show(typing, subst, ty)) {
+                Some(shown) => types.push(shown),
+                None => {
+                    bound.remove(var);
+                    types.push("_".to_string());
+                }
+            }
+        }
+        if bound.is_empty() {
+            return None;
+        }
+        let types = types.into_boxed_slice();
+        let key = (var, types);
+        if let Some(&index) = self.indices.get(&key) {
+            return Some(index);
+        }
+        let made = self.keys.iter().filter(|(of, _)| *of == var).count();
+        if made == mono::MAX_INSTANCES {
+            return None;
+        }
+
+        let index = self.keys.len();
+        let id = self
+            .module
+            .declare_function(&format!("instance{index}"), Linkage::Local, &self.lambda)
+            .unwrap();
+        // the code is filled in once it's been compiled
+        let closure = runtime::rt_closure(std::ptr::null(), 0);
+        let mut subst = self.subst.clone();
+        subst.extend(bound);
+        self.keys.push(key.clone());
+        self.indices.insert(key, index);
+        self.instances.push((closure, id));
+        self.pending.push((index, subst));
+        Some(index)
+    }
+
+    fn compile_instance(&mut self, index: usize, subst: Subst) {
+        let (var, _) = self.keys[index];
+        let (_, id) = self.instances[index];
+        let value = self.generics[&var].value;
+        let subst = std::mem::replace(&mut self.subst, subst);
+        let owner = self.owner.replace((var, Some(index)));
+        self.compile_lambda(id, &format!("instance{index}"), value, &[]);
+        self.subst = subst;
+        self.owner = owner;
+    }
+
+    /// The instance that `func` is a use of, if it is one.
+    fn callee(&mut self, func: &Expr<'s>) -> Option<usize> {
+        match func.kind {
+            ExprKind::Var(var) => self.instance(var, func.span),
+            _ => None,
+        }
+    }
+
+    /// Calls an instance directly, since it's known to be a closure.
+    fn call_instance(&mut self, f: &mut Function, index: usize, arg: Val) -> Val {
+        let (closure, id) = self.instances[index];
+        let func = self.import(f, id);
+        let closure = f.pointer(closure);
+        let call = f.builder.ins().call(func, &[closure, arg]);
+        f.builder.inst_results(call)[0]
+    }
+
+    /// The type of the expression at `span`, with the generic variables
+    /// bound in the instance being compiled replaced.
+    fn type_of(&self, span: Span) -> Option<&'p Ty<'s>> {
+        let ty = self.typing.type_of(span)?;
+        Some(
+            self.typing
+                .types
+                .get(mono::resolve(self.typing, &self.subst, ty)),
+        )
+    }
+
+    fn literal(&mut self, lit: Literal<'s>) -> Ptr {
+        match lit {
+            Literal::Int(i) => runtime::alloc(Value::Int(i)),
+            Literal::Float(x) => runtime::alloc(Value::Float(x)),
+            Literal::BigInt(digits) => {
+                runtime::alloc(Value::BigInt(BigInt::parse(digits.0).unwrap()))
+            }
+            Literal::String(s) => *self
+                .strings
+                .entry(s.0)
+                .or_insert_with(|| runtime::alloc(Value::String(s.0.into()))),
+            Literal::Bool(b) => runtime::boolean(b),
+            Literal::Unit => runtime::unit(),
+        }
+    }
+
+    fn name(&mut self, name: &'s str) -> Name {
+        self.names
+            .entry(name)
+            .or_insert_with(|| runtime::name(name))
+    }
+
+    /// Generates the code that computes `expr`, returning its value.
+    fn expr(&mut self, f: &mut Function, expr: &Expr<'s>) -> Val {
+        match &expr.kind {
+            ExprKind::Literal(lit) => {
+                let value = self.literal(*lit);
+                f.pointer(value)
+            }
+            ExprKind::Var(var) => match self.instance(*var, expr.span) {
+                Some(index) => f.pointer(self.instances[index].0),
+                None => self.var(f, *var),
+            },
+            ExprKind::Builtin(builtin) => {
+                let value = *self
+                    .builtins
+                    .entry(*builtin)
+                    .or_insert_with(|| runtime::alloc(Value::Builtin(*builtin)));
+                f.pointer(value)
+            }
+            ExprKind::Extern(external) => {
+                let wrapper = self.external(external);
+                let wrapper = self.import(f, wrapper);
+                let code = f.builder.ins().func_addr(I64, wrapper);
+                let len = f.builder.ins().iconst(I64, 0);
+                self.call(f, "rt_closure", &[code, len])
+            }
+            ExprKind::Lambda { .. } => {
+                let closure = self.closure(f, expr);
+                self.fill(f, closure, expr);
+                closure
+            }
+            ExprKind::Apply { func, arg, .. } => {
+                if let Some(index) = self.callee(func) {
+                    let arg = self.expr(f, arg);
+                    return self.call_instance(f, index, arg);
+                }
+                let func = self.expr(f, func);
+                let arg = self.expr(f, arg);
+                self.apply(f, func, arg)
+            }
+            ExprKind::Let { var, value, body } => {
+                let value = self.expr(f, value);
+                self.define(f, *var, value);
+                self.expr(f, body)
+            }
+            ExprKind::LetRec { group, body } => {
+                self.group(f, group);
+                self.expr(f, body)
+            }
+            ExprKind::Case { scrutinee, arms } => {
+                let scrutinee = self.expr(f, scrutinee);
+                let done = f.builder.create_block();
+                let result = f.builder.append_block_param(done, I64);
+                self.arms(f, scrutinee, arms, Some(done));
+                f.builder.switch_to_block(done);
+                result
+            }
+            ExprKind::Seq(items) => {
+                let mut result = f.pointer(runtime::unit());
+                for item in items.iter() {
+                    result = self.expr(f, item);
+                }
+                result
+            }
+            ExprKind::Tuple(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.expr(f, item))
+                    .collect::<Vec<_>>();
+                self.tuple(f, &items)
+            }
+            ExprKind::Project { tuple, index } => {
+                let tuple = self.expr(f, tuple);
+                let items = f.field(I64, tuple, 0);
+                f.item(items, *index)
+            }
+            ExprKind::Record(fields) => {
+                let values = fields
+                    .iter()
+                    .map(|(_, value)| self.expr(f, value))
+                    .collect::<Vec<_>>();
+                let values = self.tuple(f, &values);
+                let names = fields.iter().map(|(name, _)| name.0).collect::<Vec<_>>();
+                let names = match self.records.get(&names) {
+                    Some(&names) => names,
+                    None => {
+                        let list: Box<[Name]> = names.iter().map(|name| self.name(name)).collect();
+                        let list: &'static [Name] = Box::leak(list);
+                        let address = &*Box::leak(Box::new(list)) as *const _;
+                        self.records.insert(names, address);
+                        address
+                    }
+                };
+                let names = f.pointer(names.cast());
+                self.call(f, "rt_record", &[names, values])
+            }
+            ExprKind::Field { record, name } => {
+                let record = self.expr(f, record);
+                let name = self.name(name.0);
+                let name = f.pointer((name as *const &str).cast());
+                self.call(f, "rt_field", &[record, name])
+            }
+            ExprKind::Variant { name, payload } => {
+                let payload = self.expr(f, payload);
+                let name = self.name(name.0);
+                let name = f.pointer((name as *const &str).cast());
+                self.call(f, "rt_variant", &[name, payload])
+            }
+            ExprKind::NewRef(value) => {
+                let value = self.expr(f, value);
+                self.call(f, "rt_ref", &[value])
+            }
+            ExprKind::Load(cell) => {
+                let cell = self.expr(f, cell);
+                f.field(I64, cell, 0)
+            }
+            ExprKind::Store { cell, value } => {
+                let cell = self.expr(f, cell);
+                let value = self.expr(f, value);
+                f.builder
+                    .ins()
+                    .store(MemFlags::trusted(), value, cell, FIELDS);
+                f.pointer(runtime::unit())
+            }
+            ExprKind::Prim { op, args } => {
+                let span = args.first().map(|arg| arg.span);
+                let args = args.iter().map(|arg| self.expr(f, arg)).collect::<Vec<_>>();
+                self.prim(f, *op, &args, span)
+            }
+        }
+    }
+
+    /// Generates the code for an expression that is the last thing a lambda
+    /// does, which returns its value or makes the call it comes to as a tail
+    /// call.
+    fn tail(&mut self, f: &mut Function, expr: &Expr<'s>) {
+        match &expr.kind {
+            ExprKind::Apply { func, arg, .. } => {
+                if let Some(index) = self.callee(func) {
+                    let arg = self.expr(f, arg);
+                    let (closure, id) = self.instances[index];
+                    let func = self.import(f, id);
+                    let closure = f.pointer(closure);
+                    f.builder.ins().return_call(func, &[closure, arg]);
+                    return;
+                }
+                let func = self.expr(f, func);
+                let arg = self.expr(f, arg);
+                let (closure, builtin) = self.dispatch(f, func);
+                f.builder.switch_to_block(closure);
+                let code = f.field(I64, func, 0);
+                f.builder
+                    .ins()
+                    .return_call_indirect(f.lambda, code, &[func, arg]);
+                f.builder.switch_to_block(builtin);
+                let result = self.call(f, "rt_builtin", &[func, arg]);
+                f.builder.ins().return_(&[result]);
+            }
+            ExprKind::Let { var, value, body } => {
+                let value = self.expr(f, value);
+                self.define(f, *var, value);
+                self.tail(f, body);
+            }
+            ExprKind::LetRec { group, body } => {
+                self.group(f, group);
+                self.tail(f, body);
+            }
+            ExprKind::Case { scrutinee, arms } => {
+                let scrutinee = self.expr(f, scrutinee);
+                self.arms(f, scrutinee, arms, None);
+            }
+            ExprKind::Seq(items) if !items.is_empty() => {
+                let (last, rest) = items.split_last().unwrap();
+                for item in rest {
+                    self.expr(f, item);
+                }
+                self.tail(f, last);
+            }
+            _ => {
+                let value = self.expr(f, expr);
+                f.builder.ins().return_(&[value]);
+            }
+        }
+    }
+
+    /// Branches on whether `func` is a closure or a builtin, returning the
+    /// blocks for each.
+    fn dispatch(&mut self, f: &mut Function, func: Val) -> (Block, Block) {
+        let closure = f.builder.create_block();
+        let builtin = f.builder.create_block();
+        let tag = f.builder.ins().load(I64, MemFlags::trusted(), func, 0);
+        let is_closure = f.builder.ins().icmp_imm(IntCC::Equal, tag, CLOSURE as i64);
+        f.builder.ins().brif(is_closure, closure, &[], builtin, &[]);
+        (closure, builtin)
+    }
+
+    fn apply(&mut self, f: &mut Function, func: Val, arg: Val) -> Val {
+        let (closure, builtin) = self.dispatch(f, func);
+        let done = f.builder.create_block();
+        let result = f.builder.append_block_param(done, I64);
+
+        f.builder.switch_to_block(closure);
+        let code = f.field(I64, func, 0);
+        let call = f.builder.ins().call_indirect(f.lambda, code, &[func, arg]);
+        let value = f.builder.inst_results(call)[0];
+        f.builder.ins().jump(done, &[value]);
+
+        f.builder.switch_to_block(builtin);
+        let value = self.call(f, "rt_builtin", &[func, arg]);
+        f.builder.ins().jump(done, &[value]);
+
+        f.builder.switch_to_block(done);
+        result
+    }
+
+    /// Allocates a tuple of `items`.
+    fn tuple(&mut self, f: &mut Function, items: &[Val]) -> Val {
+        let len = f.builder.ins().iconst(I64, items.len() as i64);
+        let tuple = self.call(f, "rt_tuple", &[len]);
+        let slots = f.field(I64, tuple, 0);
+        for (i, item) in items.iter().enumerate() {
+            f.set_item(slots, i, *item);
+        }
+        tuple
+    }
+
+    fn group(&mut self, f: &mut Function, group: &Group<'s>) {
+        if !group.recursive {
+            for (var, value) in group.bindings.iter() {
+                let owner = self.own(*var);
+                let value = self.expr(f, value);
+                self.owner = owner;
+                self.define(f, *var, value);
+            }
+            return;
+        }
+
+        // the closures are created before any of them captures anything,
+        // so that they can capture each other
+        let mut closures = Vec::new();
+        for (var, value) in group.bindings.iter() {
+            if let ExprKind::Lambda { .. } = value.kind {
+                let owner = self.own(*var);
+                let closure = self.closure(f, value);
+                self.owner = owner;
+                self.define(f, *var, closure);
+                closures.push(closure);
+            } else {
+                let unit = f.pointer(runtime::unit());
+                self.define(f, *var, unit);
+            }
+        }
+        let mut closures = closures.into_iter();
+        for (var, value) in group.bindings.iter() {
+            match value.kind {
+                ExprKind::Lambda { .. } => self.fill(f, closures.next().unwrap(), value),
+                _ => {
+                    let value = self.expr(f, value);
+                    self.define(f, *var, value);
+                }
+            }
+        }
+    }
+
+    /// Counts the code compiled for `var` towards it, if it's a generic def,
+    /// returning what it was counted towards before.
+    fn own(&mut self, var: VarId) -> Option<(VarId, Option<usize>)> {
+        match self.generics.contains_key(&var) {
+            true => self.owner.replace((var, None)),
+            false => self.owner,
+        }
+    }
+
+    /// The variables a lambda captures in its closure. Top-level defs are
+    /// in the table, so they don't need to be captured.
+    fn captures(&self, lambda: &Expr) -> Vec<VarId> {
+        let ExprKind::Lambda { captures, .. } = &lambda.kind else {
+            unreachable!()
+        };
+        captures
+            .iter()
+            .copied()
+            .filter(|var| !self.globals.contains_key(var))
+            .collect()
+    }
+
+    /// Compiles the function for a lambda and allocates a closure for it.
+    /// Its captures are stored separately by [Codegen::fill].
+    fn closure(&mut self, f: &mut Function, lambda: &Expr<'s>) -> Val {
+        let captures = self.captures(lambda);
+        let name = format!("lambda{}", self.next_lambda);
+        self.next_lambda += 1;
+        let id = self
+            .module
+            .declare_function(&name, Linkage::Local, &self.lambda)
+            .unwrap();
+        self.compile_lambda(id, &name, lambda, &captures);
+
+        let func = self.import(f, id);
+        let code = f.builder.ins().func_addr(I64, func);
+        let len = f.builder.ins().iconst(I64, captures.len() as i64);
+        self.call(f, "rt_closure", &[code, len])
+    }
+
+    /// Compiles the function `id` for a lambda, which takes its closure, with
+    /// `captures` in it, and its argument.
+    fn compile_lambda(&mut self, id: FuncId, name: &str, lambda: &Expr<'s>, captures: &[VarId]) {
+        let ExprKind::Lambda { param, body, .. } = &lambda.kind else {
+            unreachable!()
+        };
+        let mut ctx = self.module.make_context();
+        ctx.func.signature = self.lambda.clone();
+        ctx.func.name = UserFuncName::user(0, id.as_u32());
+        let mut builder = FunctionBuilderContext::new();
+        let mut g = Function::new(
+            FunctionBuilder::new(&mut ctx.func, &mut builder),
+            &self.lambda,
+        );
+        let block = g.builder.current_block().unwrap();
+        let (env, arg) = match *g.builder.block_params(block) {
+            [env, arg] => (env, arg),
+            _ => unreachable!(),
+        };
+        self.define(&mut g, *param, arg);
+        if !captures.is_empty() {
+            let slots = g.field(I64, env, 1);
+            for (i, var) in captures.iter().enumerate() {
+                let value = g.item(slots, i);
+                self.define(&mut g, *var, value);
+            }
+        }
+        self.tail(&mut g, body);
+        g.finish();
+        self.define_function(id, &mut ctx, name);
+    }
+
+    /// Stores the captured values of a lambda in its closure.
+    fn fill(&mut self, f: &mut Function, closure: Val, lambda: &Expr) {
+        let captures = self.captures(lambda);
+        if captures.is_empty() {
+            return;
+        }
+        let slots = f.field(I64, closure, 1);
+        for (i, var) in captures.into_iter().enumerate() {
+            let value = self.var(f, var);
+            f.set_item(slots, i, value);
+        }
+    }
+
+    /// Generates the arms of a case. Each arm's value is passed to `done`,
+    /// or returned from the function if there is no `done` because the case
+    /// is in tail position.
+    fn arms(&mut self, f: &mut Function, scrutinee: Val, arms: &[Arm<'s>], done: Option<Block>) {
+        for arm in arms {
+            let (condition, matches) = match &arm.pattern {
+                ArmPattern::Wildcard => {
+                    self.arm(f, &arm.body, done);
+                    return;
+                }
+                ArmPattern::Bool(b) => (f.field(I8, scrutinee, 0), *b),
+                ArmPattern::Variant { name, .. } => {
+                    let name = self.name(name.0) as *const &str as i64;
+                    let actual = f.field(I64, scrutinee, 0);
+                    (f.builder.ins().icmp_imm(IntCC::Equal, actual, name), true)
+                }
+            };
+            let then = f.builder.create_block();
+            let next = f.builder.create_block();
+            match matches {
+                true => f.builder.ins().brif(condition, then, &[], next, &[]),
+                false => f.builder.ins().brif(condition, next, &[], then, &[]),
+            };
+            f.builder.switch_to_block(then);
+            if let ArmPattern::Variant {
+                bind: Some(bind), ..
+            } = arm.pattern
+            {
+                let payload = f.field(I64, scrutinee, 1);
+                self.define(f, bind, payload);
+            }
+            self.arm(f, &arm.body, done);
+            f.builder.switch_to_block(next);
+        }
+        // the type checker makes sure some arm matches
+        f.builder.ins().trap(TrapCode::unwrap_user(1));
+    }
+
+    fn arm(&mut self, f: &mut Function, body: &Expr<'s>, done: Option<Block>) {
+        match done {
+            Some(done) => {
+                let value = self.expr(f, body);
+                f.builder.ins().jump(done, &[value]);
+            }
+            None => self.tail(f, body),
+        }
+    }
+
+    /// Generates a primitive operation, where `span` is where its first
+    /// operand is.
+    fn prim(&mut self, f: &mut Function, op: Prim, args: &[Val], span: Option<Span>) -> Val {
+        let float = |f: &mut Function| (f.field(F64, args[0], 0), f.field(F64, args[1], 0));
+        match op {
+            Prim::Add(Num::Int) => self.call(f, "rt_add_int", args),
+            Prim::Sub(Num::Int) => self.call(f, "rt_sub_int", args),
+            Prim::Mul(Num::Int) => self.call(f, "rt_mul_int", args),
+            Prim::Div(Num::Int) => self.call(f, "rt_div_int", args),
+            Prim::Mod(Num::Int) => self.call(f, "rt_rem_int", args),
+            Prim::Add(Num::BigInt) => self.call(f, "rt_add_big", args),
+            Prim::Sub(Num::BigInt) => self.call(f, "rt_sub_big", args),
+            Prim::Mul(Num::BigInt) => self.call(f, "rt_mul_big", args),
+            Prim::Div(Num::BigInt) => self.call(f, "rt_div_big", args),
+            Prim::Mod(Num::BigInt) => self.call(f, "rt_rem_big", args),
+            Prim::Add(Num::Float)
+            | Prim::Sub(Num::Float)
+            | Prim::Mul(Num::Float)
+            | Prim::Div(Num::Float)
+            | Prim::Mod(Num::Float) => {
+                let (a, b) = float(f);
+                let x = match op {
+                    Prim::Add(_) => f.builder.ins().fadd(a, b),
+                    Prim::Sub(_) => f.builder.ins().fsub(a, b),
+                    Prim::Mul(_) => f.builder.ins().fmul(a, b),
+                    Prim::Div(_) => f.builder.ins().fdiv(a, b),
+                    _ => self.call(f, "rt_rem_float", &[a, b]),
+                };
+                self.call(f, "rt_float", &[x])
+            }
+            Prim::Lt(num) => self.compare(f, IntCC::SignedLessThan, num, args),
+            Prim::LtEq(num) => self.compare(f, IntCC::SignedLessThanOrEqual, num, args),
+            Prim::Gt(num) => self.compare(f, IntCC::SignedGreaterThan, num, args),
+            Prim::GtEq(num) => self.compare(f, IntCC::SignedGreaterThanOrEqual, num, args),
+            Prim::Concat => self.call(f, "rt_concat", args),
+            Prim::Eq => {
+                let equal = self.equals(f, args, span);
+                f.boolean(equal)
+            }
+            Prim::NotEq => {
+                let equal = self.equals(f, args, span);
+                let unequal = f.builder.ins().icmp_imm(IntCC::Equal, equal, 0);
+                f.boolean(unequal)
+            }
+            Prim::Not => {
+                let b = f.field(I8, args[0], 0);
+                let not = f.builder.ins().icmp_imm(IntCC::Equal, b, 0);
+                f.boolean(not)
+            }
+        }
+    }
+
+    /// Whether two values are equal, as a byte that's 1 if they are. Values
+    /// of a type that's known to be compared by its bits, where the first of
+    /// them is at `span`, are compared inline.
+    fn equals(&mut self, f: &mut Function, args: &[Val], span: Option<Span>) -> Val {
+        let load = |f: &mut Function, ty| (f.field(ty, args[0], 0), f.field(ty, args[1], 0));
+        match span.and_then(|span| self.type_of(span)) {
+            Some(Ty::Int) => {
+                let (a, b) = load(f, I64);
+                f.builder.ins().icmp(IntCC::Equal, a, b)
+            }
+            Some(Ty::Bool) => {
+                let (a, b) = load(f, I8);
+                f.builder.ins().icmp(IntCC::Equal, a, b)
+            }
+            Some(Ty::Float) => {
+                let (a, b) = load(f, F64);
+                f.builder.ins().fcmp(FloatCC::Equal, a, b)
+            }
+            Some(Ty::Tuple(items)) if items.is_empty() => f.builder.ins().iconst(I8, 1),
+            _ => self.call(f, "rt_equals", args),
+        }
+    }
+
+    /// Compares two numbers with the integer condition `cc`, which for
+    /// floats is the ordered condition that corresponds to it.
+    fn compare(&mut self, f: &mut Function, cc: IntCC, num: Num, args: &[Val]) -> Val {
+        let condition = match num {
+            Num::Int => {
+                let (a, b) = (f.field(I64, args[0], 0), f.field(I64, args[1], 0));
+                f.builder.ins().icmp(cc, a, b)
+            }
+            Num::Float => {
+                let (a, b) = (f.field(F64, args[0], 0), f.field(F64, args[1], 0));
+                let cc = match cc {
+                    IntCC::SignedLessThan => FloatCC::LessThan,
+                    IntCC::SignedLessThanOrEqual => FloatCC::LessThanOrEqual,
+                    IntCC::SignedGreaterThan => FloatCC::GreaterThan,
+                    _ => FloatCC::GreaterThanOrEqual,
+                };
+                f.builder.ins().fcmp(cc, a, b)
+            }
+            Num::BigInt => {
+                let order = self.call(f, "rt_cmp_big", args);
+                f.builder.ins().icmp_imm(cc, order, 0)
+            }
+        };
+        f.boolean(condition)
+    }
+
+    /// Compiles the function that calls an extern with the items of a
+    /// closure's argument, returning its id.
+    fn external(&mut self, external: &Extern<'s>) -> FuncId {
+        let key = (external.name.0, external.params.clone(), external.ret);
+        if let Some(&wrapper) = self.externs.get(&key) {
+            return wrapper;
+        }
+
+        let address = lookup(external.name.0).unwrap_or_else(|| {
+            self.missing.push(external.name.0.to_string());
+            std::ptr::null()
+        });
+        let mut signature = self.module.make_signature();
+        for ty in external.params.iter() {
+            signature.params.push(AbiParam::new(c_type(*ty)));
+        }
+        if external.ret != ExternType::Unit {
+            signature.returns.push(AbiParam::new(c_type(external.ret)));
+        }
+
+        let name = format!("extern{}", self.externs.len());
+        let id = self
+            .module
+            .declare_function(&name, Linkage::Local, &self.lambda)
+            .unwrap();
+        let mut ctx = self.module.make_context();
+        ctx.func.signature = self.lambda.clone();
+        ctx.func.name = UserFuncName::user(0, id.as_u32());
+        let mut builder = FunctionBuilderContext::new();
+        let mut g = Function::new(
+            FunctionBuilder::new(&mut ctx.func, &mut builder),
+            &self.lambda,
+        );
+        let block = g.builder.current_block().unwrap();
+        let arg = g.builder.block_params(block)[1];
+        let mut args = Vec::new();
+        for (i, &ty) in external.params.iter().enumerate() {
+            let arg = match external.params.len() {
+                1 => arg,
+                _ => {
+                    let items = g.field(I64, arg, 0);
+                    g.item(items, i)
+                }
+            };
+            args.push(match ty {
+                ExternType::Int => g.field(I64, arg, 0),
+                ExternType::Float => g.field(F64, arg, 0),
+                ExternType::String => self.call(&mut g, "rt_to_c_string", &[arg]),
+                ExternType::Bool => {
+                    let b = g.field(I8, arg, 0);
+                    g.builder.ins().uextend(I32, b)
+                }
+                ExternType::Unit => unreachable!("unit parameter"),
+            });
+        }
+        let signature = g.builder.import_signature(signature);
+        let callee = g.pointer(address.cast());
+        let call = g.builder.ins().call_indirect(signature, callee, &args);
+        let result = g.builder.inst_results(call).first().copied();
+        let result = match external.ret {
+            ExternType::Int => self.call(&mut g, "rt_int", &[result.unwrap()]),
+            ExternType::Float => self.call(&mut g, "rt_float", &[result.unwrap()]),
+            ExternType::String => self.call(&mut g, "rt_from_c_string", &[result.unwrap()]),
+            ExternType::Bool => self.call(&mut g, "rt_bool", &[result.unwrap()]),
+            ExternType::Unit => g.pointer(runtime::unit()),
+        };
+        g.builder.ins().return_(&[result]);
+        g.finish();
+        self.define_function(id, &mut ctx, &name);
+        self.externs.insert(key, id);
+        id
+    }
+}
+
+fn c_type(ty: ExternType) -> Type {
+    match ty {
+        ExternType::Int | ExternType::String => I64,
+        ExternType::Float => F64,
+        ExternType::Bool => I32,
+        ExternType::Unit => unreachable!("unit parameter"),
+    }
+}
+
+/// The address of a C function among the symbols of the process.
+#[cfg(unix)]
+fn lookup(name: &str) -> Option<*const u8> {
+    let name = std::ffi::CString::new(name).ok()?;
+    // SAFETY: `name` is NUL-terminated
+    let address = unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr()) };
+    (!address.is_null()).then_some(address.cast_const().cast())
+}
+
+#[cfg(not(unix))]
+fn lookup(_: &str) -> Option<*const u8> {
+    None
+}
@@ -0,0 +1,304 @@
+//! Monomorphization: the generic top-level defs that are used at known
+//! types are compiled again for each of those types, so that the code of
+//! each copy knows what the generic variables stand for.
+//!
+//! A copy is an instance, made for the types that the generic variables of
+//! the def's type are bound to at a use, and made once for each of them. The
+//! types a use binds are worked out by matching the def's type against the
+//! type of the use, through the bindings of the instance the use is in, if
+//! any. A variable that a use leaves unbound, such as the rest of an open
+//! row, or binds to a type that isn't wholly known, is left generic in the
+//! instance, so an instance is only made when at least one of them is bound
+//! to a known type.
+use std::fmt::Write;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::typeck::{Type, TypeId, Typing, GENERIC};
+
+/// How many instances of one def are made at most, since a def that calls
+/// itself at a bigger type each time would otherwise have no end of them.
+pub const MAX_INSTANCES: usize = 16;
+
+/// What the generic variables of the instance being compiled are bound to.
+pub type Subst = FxHashMap<TypeId, TypeId>;
+
+/// The generic variables of `ty`, in the order they're first come across.
+pub fn generic_vars(typing: &Typing, ty: TypeId) -> Vec<TypeId> {
+    fn walk(typing: &Typing, ty: TypeId, seen: &mut FxHashSet<TypeId>, out: &mut Vec<TypeId>) {
+        let ty = typing.types.find(ty);
+        if !seen.insert(ty) {
+            return;
+        }
+        match typing.types.get(ty) {
+            Type::Var { level: GENERIC } => out.push(ty),
+            Type::Tuple(items) => items.iter().for_each(|&t| walk(typing, t, seen, out)),
+            Type::Function(param, ret) => {
+                walk(typing, *param, seen, out);
+                walk(typing, *ret, seen, out);
+            }
+            Type::Ref(t) | Type::List(t) => walk(typing, *t, seen, out),
+            Type::Object(row) | Type::Variant(row) => {
+                let (entries, rest) = typing.types.row(row);
+                entries
+                    .iter()
+                    .for_each(|&(_, t)| walk(typing, t, seen, out));
+                if let Some(rest) = rest {
+                    walk(typing, rest, seen, out);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut out = Vec::new();
+    walk(typing, ty, &mut FxHashSet::default(), &mut out);
+    out
+}
+
+/// Follows `ty` through the links of the types and through `subst`.
+pub fn resolve(typing: &Typing, subst: &Subst, ty: TypeId) -> TypeId {
+    let mut ty = typing.types.find(ty);
+    while let Some(&bound) = subst.get(&ty) {
+        ty = typing.types.find(bound);
+    }
+    ty
+}
+
+/// Binds the generic variables of `generic` to the parts of `actual` they
+/// correspond to, where `actual` is seen through `subst`. Returns false if
+/// the two don't have the same shape, which they always do for a use that
+/// type checked.
+pub fn bind(
+    typing: &Typing,
+    subst: &Subst,
+    generic: TypeId,
+    actual: TypeId,
+    out: &mut Subst,
+    seen: &mut FxHashSet<(TypeId, TypeId)>,
+) -> bool {
+    let generic = typing.types.find(generic);
+    let actual = resolve(typing, subst, actual);
+    if !seen.insert((generic, actual)) {
+        return true;
+    }
+    let types = &typing.types;
+    if let Type::Var { level: GENERIC } = types.get(generic) {
+        // a variable that the use leaves unbound stays generic
+        if !matches!(types.get(actual), Type::Var { .. }) {
+            out.insert(generic, actual);
+        }
+        return true;
+    }
+    let mut bind = |generic, actual| bind(typing, subst, generic, actual, out, seen);
+    match (types.get(generic), types.get(actual)) {
+        (Type::Tuple(xs), Type::Tuple(ys)) if xs.len() == ys.len() => {
+            xs.iter().zip(ys.iter()).all(|(&x, &y)| bind(x, y))
+        }
+        (Type::Function(p1, r1), Type::Function(p2, r2)) => bind(*p1, *p2) && bind(*r1, *r2),
+        (Type::Ref(x), Type::Ref(y)) | (Type::List(x), Type::List(y)) => bind(*x, *y),
+        (Type::Object(x), Type::Object(_)) | (Type::Variant(x), Type::Variant(_)) => {
+            let (xs, _) = types.row(x);
+            let ys = row(typing, subst, actual).0;
+            xs.iter()
+                .all(|(name, x)| match ys.iter().find(|(n, _)| n.0 == name.0) {
+                    Some(&(_, y)) => bind(*x, y),
+                    None => false,
+                })
+        }
+        (Type::Int, Type::Int)
+        | (Type::Float, Type::Float)
+        | (Type::BigInt, Type::BigInt)
+        | (Type::String, Type::String)
+        | (Type::Bool, Type::Bool) => true,
+        (Type::Error, _) | (_, Type::Error) => true,
+        _ => false,
+    }
+}
+
+/// The entries of the object or variant type `ty`, seen through `subst`,
+/// sorted by name, and whether the row is open.
+fn row<'s>(
+    typing: &Typing<'s>,
+    subst: &Subst,
+    ty: TypeId,
+) -> (Vec<(crate::tokenizer::Intern<'s>, TypeId)>, bool) {
+    let mut entries = Vec::new();
+    let mut ty = resolve(typing, subst, ty);
+    while let Type::Object(row) | Type::Variant(row) = typing.types.get(ty) {
+        let (more, rest) = typing.types.row(row);
+        entries.extend(more);
+        match rest {
+            Some(rest) if subst.contains_key(&rest) => ty = resolve(typing, subst, rest),
+            Some(_) => {
+                entries.sort_by(|a, b| a.0 .0.cmp(b.0 .0));
+                return (entries, true);
+            }
+            None => break,
+        }
+    }
+    entries.sort_by(|a, b| a.0 .0.cmp(b.0 .0));
+    (entries, false)
+}
+
+/// Writes `ty` as seen through `subst`, the way types are displayed, or
+/// returns `None` if a variable in it is still generic. Types that are the
+/// same are written the same, so this is what instances are told apart by.
+pub fn show(typing: &Typing, subst: &Subst, ty: TypeId) -> Option<String> {
+    let mut out = String::new();
+    write(typing, subst, ty, false, &mut Vec::new(), &mut out).then_some(out)
+}
+
+/// Writes `ty`, where `stack` holds the rows it's inside of, so that a row
+/// that contains itself is written as `?n`, for the `n`th row out. Returns
+/// whether it's wholly known.
+fn write(
+    typing: &Typing,
+    subst: &Subst,
+    ty: TypeId,
+    nested: bool,
+    stack: &mut Vec<TypeId>,
+    out: &mut String,
+) -> bool {
+    let ty = resolve(typing, subst, ty);
+    let mut known = true;
+    match typing.types.get(ty) {
+        Type::Var { .. } | Type::Link(_) => return false,
+        Type::Int => out.push_str("Int"),
+        Type::Float => out.push_str("Float"),
+        Type::BigInt => out.push_str("BigInt"),
+        Type::String => out.push_str("String"),
+        Type::Bool => out.push_str("Bool"),
+        Type::Error => out.push_str("{error}"),
+        Type::Tuple(items) if items.is_empty() => out.push_str("Unit"),
+        Type::Tuple(items) => {
+            out.push('(');
+            for (i, &item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                known &= write(typing, subst, item, false, stack, out);
+            }
+            out.push(')');
+        }
+        Type::Function(param, ret) => {
+            if nested {
+                out.push('(');
+            }
+            known &= write(typing, subst, *param, true, stack, out);
+            out.push_str(" -> ");
+            known &= write(typing, subst, *ret, false, stack, out);
+            if nested {
+                out.push(')');
+            }
+        }
+        Type::Ref(t) => {
+            out.push('^');
+            known &= write(typing, subst, *t, true, stack, out);
+        }
+        Type::List(t) => {
+            out.push_str("List(");
+            known &= write(typing, subst, *t, false, stack, out);
+            out.push(')');
+        }
+        Type::Object(_) | Type::Variant(_) => {
+            if let Some(n) = stack.iter().position(|&row| row == ty) {
+                write!(out, "?{n}").unwrap();
+                return true;
+            }
+            let object = matches!(typing.types.get(ty), Type::Object(_));
+            // an open row is as known as it needs to be, since nothing can
+            // rely on what else might be in it
+            let (entries, open) = row(typing, subst, ty);
+            stack.push(ty);
+            if object {
+                out.push_str(".{");
+                for (i, (name, t)) in entries.iter().enumerate() {
+                    out.push_str(if i > 0 { ", " } else { " " });
+                    write!(out, "{}: ", name.0).unwrap();
+                    known &= write(typing, subst, *t, false, stack, out);
+                }
+                if open {
+                    out.push_str(if entries.is_empty() { " .." } else { ", .." });
+                }
+                out.push_str(" }");
+            } else {
+                for (i, (name, t)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        out.push(' ');
+                    }
+                    write!(out, "|{}", name.0).unwrap();
+                    let t = resolve(typing, subst, *t);
+                    if !matches!(typing.types.get(t), Type::Tuple(items) if items.is_empty()) {
+                        out.push_str(": ");
+                        known &= write(typing, subst, t, true, stack, out);
+                    }
+                }
+                if open {
+                    out.push_str(" |..");
+                }
+            }
+            stack.pop();
+        }
+    }
+    known
+}
+
+/// How big the code of the generic defs and their instances came out.
+#[derive(Debug, Default)]
+pub struct MonoStats {
+    /// The generic defs that have instances, in the order they're defined.
+    pub defs: Vec<DefStats>,
+    /// The size of all the compiled code, in bytes.
+    pub total: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct DefStats {
+    pub name: String,
+    /// The size of the code of the generic def, which its uses at types
+    /// that aren't known call.
+    pub generic: usize,
+    /// The types each instance is for, in the order of the def's generic
+    /// variables, and the size of its code.
+    pub instances: Vec<(Box<[String]>, usize)>,
+}
+
+impl MonoStats {
+    /// The stats as a table of defs, each followed by its instances.
+    pub fn report(&self) -> String {
+        let instances = self
+            .defs
+            .iter()
+            .map(|def| def.instances.len())
+            .sum::<usize>();
+        let specialized = self
+            .defs
+            .iter()
+            .flat_map(|def| def.instances.iter().map(|(_, size)| size))
+            .sum::<usize>();
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{} generic defs with {instances} instances",
+            self.defs.len()
+        );
+        let _ = writeln!(
+            out,
+            "{} bytes of code, {specialized} of them in instances",
+            self.total
+        );
+        if self.defs.is_empty() {
+            return out;
+        }
+        let _ = writeln!(out, "\n{:<40}{:>10}", "def", "bytes");
+        for def in &self.defs {
+            let _ = writeln!(out, "{:<40}{:>10}", def.name, def.generic);
+            for (types, size) in &def.instances {
+                let types = format!("  [{}]", types.join(", "));
+                let _ = writeln!(out, "{types:<40}{size:>10}");
+            }
+        }
+        out
+    }
+}
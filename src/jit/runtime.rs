@@ -0,0 +1,596 @@
+//! The runtime that compiled code calls into, the counterpart of the C
+//! backend's `runtime.c`.
+//!
+//! Values are laid out so that compiled code can read the parts of them it
+//! needs without a call: a `u64` tag, then the fields of the value's kind,
+//! each 8 bytes wide. Nothing is ever freed, as in the C backend, since the
+//! process ends when the program does.
+
+use std::{
+    cell::Cell,
+    ffi::{c_char, c_int, CStr, CString},
+    fmt::{self, Display, Formatter},
+    io::Write,
+};
+
+use crate::{bigint::BigInt, eval::RuntimeErrorKind, resolver::Builtin};
+
+/// What compiled code passes values around as.
+pub type Ptr = *const Value;
+
+/// The name of a variant or of a field. Each name is allocated once while
+/// compiling, so names are compared by address.
+pub type Name = &'static &'static str;
+
+/// The tag of a closure, which is the one kind of value that compiled code
+/// tells apart from the others when it calls one.
+pub const CLOSURE: u64 = 0;
+
+/// Where the fields of a value start, after its tag.
+pub const FIELDS: i32 = 8;
+
+#[repr(u64)]
+pub enum Value {
+    /// The address of a function taking the closure and its argument, and
+    /// the values it captured. The captures are only read by compiled code,
+    /// at offsets from [FIELDS].
+    #[allow(dead_code)]
+    Closure(*const u8, *mut Ptr, usize) = CLOSURE,
+    Int(i64),
+    Float(f64),
+    BigInt(BigInt),
+    String(Box<str>),
+    Bool(bool),
+    Unit,
+    Tuple(*mut Ptr, usize),
+    /// The names of the fields and their values, in the same order.
+    Record(&'static [Name], *mut Ptr),
+    Variant(Name, Ptr),
+    Builtin(Builtin),
+    Ref(Cell<Ptr>),
+}
+
+/// A value that is shared by every program, which is safe since compiled
+/// code runs on one thread and never writes to these.
+struct Shared(Value);
+
+unsafe impl Sync for Shared {}
+
+static UNIT: Shared = Shared(Value::Unit);
+static TRUE: Shared = Shared(Value::Bool(true));
+static FALSE: Shared = Shared(Value::Bool(false));
+
+pub fn unit() -> Ptr {
+    &UNIT.0
+}
+
+pub fn boolean(b: bool) -> Ptr {
+    match b {
+        true => &TRUE.0,
+        false => &FALSE.0,
+    }
+}
+
+pub fn alloc(value: Value) -> Ptr {
+    Box::into_raw(Box::new(value))
+}
+
+/// Allocates `len` slots for values, which compiled code fills in.
+fn slots(len: usize) -> *mut Ptr {
+    Box::into_raw(vec![unit(); len].into_boxed_slice()).cast()
+}
+
+/// A name that lives as long as the values that have it.
+pub fn name(s: &str) -> Name {
+    Box::leak(Box::new(&*Box::leak(Box::<str>::from(s))))
+}
+
+/// Reports a runtime error and exits, the way the C runtime does, since
+/// compiled code has no way to unwind.
+fn fail(kind: RuntimeErrorKind) -> ! {
+    let _ = std::io::stdout().flush();
+    eprintln!("RUNTIME ERROR: {kind:?}");
+    std::process::exit(1)
+}
+
+/// The items of a tuple, or the captures of a closure.
+///
+/// # Safety
+/// `items` must point to `len` values that outlive the slice.
+unsafe fn items<'v>(items: *mut Ptr, len: usize) -> &'v [&'v Value] {
+    std::slice::from_raw_parts(items.cast(), len)
+}
+
+impl Value {
+    fn int(&self) -> i64 {
+        match self {
+            Value::Int(i) => *i,
+            _ => unreachable!("expected an int"),
+        }
+    }
+
+    fn float(&self) -> f64 {
+        match self {
+            Value::Float(x) => *x,
+            _ => unreachable!("expected a float"),
+        }
+    }
+
+    fn big_int(&self) -> &BigInt {
+        match self {
+            Value::BigInt(i) => i,
+            _ => unreachable!("expected a big integer"),
+        }
+    }
+
+    fn string(&self) -> &str {
+        match self {
+            Value::String(s) => s,
+            _ => unreachable!("expected a string"),
+        }
+    }
+
+    fn tuple(&self) -> &[&Value] {
+        match *self {
+            Value::Tuple(ptr, len) => unsafe { items(ptr, len) },
+            _ => unreachable!("expected a tuple"),
+        }
+    }
+
+    fn field(&self, name: Name) -> &Value {
+        match *self {
+            Value::Record(names, values) => {
+                let i = names.iter().position(|n| std::ptr::eq(*n, name)).unwrap();
+                unsafe { items(values, names.len())[i] }
+            }
+            _ => unreachable!("expected a record"),
+        }
+    }
+
+    /// Structural equality. Functions are never equal to anything, and
+    /// references are equal if they point to the same cell.
+    fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::BigInt(a), Value::BigInt(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Unit, Value::Unit) => true,
+            (Value::Tuple(..), Value::Tuple(..)) => {
+                let (a, b) = (self.tuple(), other.tuple());
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.equals(b))
+            }
+            (Value::Record(a, _), Value::Record(b, _)) => {
+                a.len() == b.len()
+                    && a.iter().all(|name| {
+                        b.iter().any(|n| std::ptr::eq(*n, *name))
+                            && self.field(name).equals(other.field(name))
+                    })
+            }
+            (Value::Variant(a, x), Value::Variant(b, y)) => {
+                std::ptr::eq(*a, *b) && unsafe { (**x).equals(&**y) }
+            }
+            (Value::Ref(a), Value::Ref(b)) => std::ptr::eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// Displays a value the way `print` writes it, which is the way the
+/// interpreter does.
+struct Shown<'v> {
+    value: &'v Value,
+    /// Whether the value is inside another value, where strings are quoted.
+    nested: bool,
+}
+
+impl Shown<'_> {
+    fn nested(f: &mut Formatter<'_>, value: &Value) -> fmt::Result {
+        let nested = Shown {
+            value,
+            nested: true,
+        };
+        write!(f, "{nested}")
+    }
+}
+
+impl Display for Shown<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.value {
+            Value::Int(i) => write!(f, "{i}"),
+            Value::Float(x) => write!(f, "{x:?}"),
+            Value::BigInt(i) => write!(f, "{i}"),
+            Value::String(s) if self.nested => write!(f, "{s:?}"),
+            Value::String(s) => write!(f, "{s}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Unit => write!(f, "()"),
+            Value::Builtin(builtin) => write!(f, "<builtin {}>", builtin.name()),
+            Value::Closure(..) => write!(f, "<function>"),
+            Value::Tuple(..) => {
+                write!(f, "(")?;
+                for (i, item) in self.value.tuple().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    Shown::nested(f, item)?;
+                }
+                write!(f, ")")
+            }
+            Value::Record(names, _) => {
+                write!(f, ".{{")?;
+                for (i, name) in names.iter().enumerate() {
+                    write!(f, "{}{}: ", if i > 0 { ", " } else { " " }, name)?;
+                    Shown::nested(f, self.value.field(name))?;
+                }
+                write!(f, " }}")
+            }
+            Value::Variant(name, payload) => match unsafe { &**payload } {
+                Value::Unit => write!(f, "|{name}"),
+                payload => {
+                    write!(f, "|{name}: ")?;
+                    Shown::nested(f, payload)
+                }
+            },
+            Value::Ref(cell) => {
+                write!(f, "^")?;
+                Shown::nested(f, unsafe { &*cell.get() })
+            }
+        }
+    }
+}
+
+/* Allocation */
+
+pub extern "C" fn rt_int(i: i64) -> Ptr {
+    alloc(Value::Int(i))
+}
+
+pub extern "C" fn rt_float(x: f64) -> Ptr {
+    alloc(Value::Float(x))
+}
+
+pub extern "C" fn rt_bool(b: c_int) -> Ptr {
+    boolean(b != 0)
+}
+
+pub extern "C" fn rt_tuple(len: usize) -> Ptr {
+    alloc(Value::Tuple(slots(len), len))
+}
+
+/// A record with the values of a tuple, which is what compiled code
+/// builds them in.
+pub extern "C" fn rt_record(names: &&'static [Name], values: &Value) -> Ptr {
+    match *values {
+        Value::Tuple(items, _) => alloc(Value::Record(names, items)),
+        _ => unreachable!("expected a tuple"),
+    }
+}
+
+pub extern "C" fn rt_variant(name: Name, payload: Ptr) -> Ptr {
+    alloc(Value::Variant(name, payload))
+}
+
+pub extern "C" fn rt_closure(code: *const u8, len: usize) -> Ptr {
+    alloc(Value::Closure(code, slots(len), len))
+}
+
+/// Points a closure that was allocated before its code was compiled at the
+/// code.
+///
+/// # Safety
+/// `closure` must have been allocated by [rt_closure] and not be in use.
+pub unsafe fn set_code(closure: Ptr, code: *const u8) {
+    if let Value::Closure(address, ..) = &mut *closure.cast_mut() {
+        *address = code;
+    }
+}
+
+pub extern "C" fn rt_ref(value: Ptr) -> Ptr {
+    alloc(Value::Ref(Cell::new(value)))
+}
+
+pub extern "C" fn rt_field(record: &Value, name: Name) -> Ptr {
+    record.field(name)
+}
+
+/* Operations */
+
+fn int(f: fn(i64, i64) -> Option<i64>, a: &Value, b: &Value) -> Ptr {
+    let (a, b) = (a.int(), b.int());
+    match f(a, b) {
+        Some(i) => rt_int(i),
+        None if b == 0 => fail(RuntimeErrorKind::DivisionByZero),
+        None => fail(RuntimeErrorKind::Overflow),
+    }
+}
+
+pub extern "C" fn rt_add_int(a: &Value, b: &Value) -> Ptr {
+    int(i64::checked_add, a, b)
+}
+
+pub extern "C" fn rt_sub_int(a: &Value, b: &Value) -> Ptr {
+    int(i64::checked_sub, a, b)
+}
+
+pub extern "C" fn rt_mul_int(a: &Value, b: &Value) -> Ptr {
+    int(i64::checked_mul, a, b)
+}
+
+pub extern "C" fn rt_div_int(a: &Value, b: &Value) -> Ptr {
+    int(i64::checked_div, a, b)
+}
+
+pub extern "C" fn rt_rem_int(a: &Value, b: &Value) -> Ptr {
+    int(i64::checked_rem, a, b)
+}
+
+/// Cranelift has no remainder of floats.
+pub extern "C" fn rt_rem_float(a: f64, b: f64) -> f64 {
+    a % b
+}
+
+pub extern "C" fn rt_add_big(a: &Value, b: &Value) -> Ptr {
+    alloc(Value::BigInt(a.big_int().add(b.big_int())))
+}
+
+pub extern "C" fn rt_sub_big(a: &Value, b: &Value) -> Ptr {
+    alloc(Value::BigInt(a.big_int().sub(b.big_int())))
+}
+
+pub extern "C" fn rt_mul_big(a: &Value, b: &Value) -> Ptr {
+    alloc(Value::BigInt(a.big_int().mul(b.big_int())))
+}
+
+pub extern "C" fn rt_div_big(a: &Value, b: &Value) -> Ptr {
+    match a.big_int().div_rem(b.big_int()) {
+        Some((quotient, _)) => alloc(Value::BigInt(quotient)),
+        None => fail(RuntimeErrorKind::DivisionByZero),
+    }
+}
+
+pub extern "C" fn rt_rem_big(a: &Value, b: &Value) -> Ptr {
+    match a.big_int().div_rem(b.big_int()) {
+        Some((_, remainder)) => alloc(Value::BigInt(remainder)),
+        None => fail(RuntimeErrorKind::DivisionByZero),
+    }
+}
+
+/// -1, 0 or 1 as `a` is less than, equal to or greater than `b`.
+pub extern "C" fn rt_cmp_big(a: &Value, b: &Value) -> i64 {
+    a.big_int().cmp(b.big_int()) as i64
+}
+
+pub extern "C" fn rt_concat(a: &Value, b: &Value) -> Ptr {
+    let s = format!("{}{}", a.string(), b.string());
+    alloc(Value::String(s.into()))
+}
+
+pub extern "C" fn rt_equals(a: &Value, b: &Value) -> bool {
+    a.equals(b)
+}
+
+/* Externs */
+
+/// Copies a string to pass it to C, which expects it to end with a NUL. A
+/// string with a NUL in it is cut short there.
+pub extern "C" fn rt_to_c_string(s: &Value) -> *const c_char {
+    let s = s.string();
+    let s = &s[..s.find('\0').unwrap_or(s.len())];
+    CString::new(s).unwrap().into_raw()
+}
+
+/// Copies a string returned from C, which might reuse its buffer. NULL is
+/// the empty string.
+///
+/// # Safety
+/// `s` must be NULL or point to a NUL-terminated string.
+pub unsafe extern "C" fn rt_from_c_string(s: *const c_char) -> Ptr {
+    let s = match s.is_null() {
+        true => String::new(),
+        false => CStr::from_ptr(s).to_string_lossy().into_owned(),
+    };
+    alloc(Value::String(s.into()))
+}
+
+/* Builtins */
+
+/// Applies a builtin, which is the one kind of function that isn't a
+/// closure.
+pub extern "C" fn rt_builtin(func: &Value, arg: &Value) -> Ptr {
+    let Value::Builtin(builtin) = func else {
+        unreachable!("applied a value that isn't a function")
+    };
+    let string = |s: String| alloc(Value::String(s.into()));
+    match builtin {
+        Builtin::Print => {
+            let shown = Shown {
+                value: arg,
+                nested: false,
+            };
+            if let Err(err) = writeln!(std::io::stdout(), "{shown}") {
+                fail(RuntimeErrorKind::Io(err));
+            }
+            unit()
+        }
+        Builtin::Add => rt_add_int(arg.tuple()[0], arg.tuple()[1]),
+        Builtin::Sub => rt_sub_int(arg.tuple()[0], arg.tuple()[1]),
+        Builtin::Mul => rt_mul_int(arg.tuple()[0], arg.tuple()[1]),
+        Builtin::Div => rt_div_int(arg.tuple()[0], arg.tuple()[1]),
+        Builtin::Mod => rt_rem_int(arg.tuple()[0], arg.tuple()[1]),
+        Builtin::Neg => rt_sub_int(&Value::Int(0), arg),
+        Builtin::StringLength => rt_int(arg.string().len() as i64),
+        Builtin::Substring => {
+            let [s, start, end] = arg.tuple() else {
+                unreachable!("expected three arguments")
+            };
+            // offsets are in bytes, and are clamped to the string
+            let s = s.string();
+            let start = start.int().clamp(0, s.len() as i64) as usize;
+            let end = end.int().clamp(start as i64, s.len() as i64) as usize;
+            string(String::from_utf8_lossy(&s.as_bytes()[start..end]).into_owned())
+        }
+        Builtin::IntToString => string(arg.int().to_string()),
+        Builtin::ToFloat => rt_float(arg.int() as f64),
+        Builtin::ToInt => {
+            // NaN fails both comparisons
+            let x = arg.float();
+            if !(x >= -(2f64.powi(63)) && x < 2f64.powi(63)) {
+                fail(RuntimeErrorKind::Overflow);
+            }
+            rt_int(x as i64)
+        }
+        Builtin::Sqrt => rt_float(arg.float().sqrt()),
+        Builtin::Floor => rt_float(arg.float().floor()),
+        Builtin::ToBig => alloc(Value::BigInt(BigInt::from_i64(arg.int()))),
+        Builtin::BigToInt => match arg.big_int().to_i64() {
+            Some(i) => rt_int(i),
+            None => fail(RuntimeErrorKind::Overflow),
+        },
+        Builtin::BigToString => string(arg.big_int().to_string()),
+        builtin => unreachable!("applied {}", builtin.name()),
+    }
+}
+
+/// A runtime function that compiled code calls, with the types of its
+/// parameters and results.
+pub struct Helper {
+    pub name: &'static str,
+    pub address: *const u8,
+    pub params: &'static [Type],
+    pub returns: &'static [Type],
+}
+
+/// The types that helpers take and return: pointers and integers, C's
+/// `int`, doubles and booleans.
+#[derive(Clone, Copy)]
+pub enum Type {
+    Word,
+    Int,
+    Double,
+    Bool,
+}
+
+/// Every helper that compiled code can call.
+pub fn helpers() -> Vec<Helper> {
+    use Type::*;
+    let helper = |name, address: *const u8, params, returns| Helper {
+        name,
+        address,
+        params,
+        returns,
+    };
+    vec![
+        helper("rt_int", rt_int as *const u8, &[Word], &[Word]),
+        helper("rt_float", rt_float as *const u8, &[Double], &[Word]),
+        helper("rt_bool", rt_bool as *const u8, &[Int], &[Word]),
+        helper("rt_tuple", rt_tuple as *const u8, &[Word], &[Word]),
+        helper("rt_record", rt_record as *const u8, &[Word, Word], &[Word]),
+        helper(
+            "rt_variant",
+            rt_variant as *const u8,
+            &[Word, Word],
+            &[Word],
+        ),
+        helper(
+            "rt_closure",
+            rt_closure as *const u8,
+            &[Word, Word],
+            &[Word],
+        ),
+        helper("rt_ref", rt_ref as *const u8, &[Word], &[Word]),
+        helper("rt_field", rt_field as *const u8, &[Word, Word], &[Word]),
+        helper(
+            "rt_add_int",
+            rt_add_int as *const u8,
+            &[Word, Word],
+            &[Word],
+        ),
+        helper(
+            "rt_sub_int",
+            rt_sub_int as *const u8,
+            &[Word, Word],
+            &[Word],
+        ),
+        helper(
+            "rt_mul_int",
+            rt_mul_int as *const u8,
+            &[Word, Word],
+            &[Word],
+        ),
+        helper(
+            "rt_div_int",
+            rt_div_int as *const u8,
+            &[Word, Word],
+            &[Word],
+        ),
+        helper(
+            "rt_rem_int",
+            rt_rem_int as *const u8,
+            &[Word, Word],
+            &[Word],
+        ),
+        helper(
+            "rt_rem_float",
+            rt_rem_float as *const u8,
+            &[Double, Double],
+            &[Double],
+        ),
+        helper(
+            "rt_add_big",
+            rt_add_big as *const u8,
+            &[Word, Word],
+            &[Word],
+        ),
+        helper(
+            "rt_sub_big",
+            rt_sub_big as *const u8,
+            &[Word, Word],
+            &[Word],
+        ),
+        helper(
+            "rt_mul_big",
+            rt_mul_big as *const u8,
+            &[Word, Word],
+            &[Word],
+        ),
+        helper(
+            "rt_div_big",
+            rt_div_big as *const u8,
+            &[Word, Word],
+            &[Word],
+        ),
+        helper(
+            "rt_rem_big",
+            rt_rem_big as *const u8,
+            &[Word, Word],
+            &[Word],
+        ),
+        helper(
+            "rt_cmp_big",
+            rt_cmp_big as *const u8,
+            &[Word, Word],
+            &[Word],
+        ),
+        helper("rt_concat", rt_concat as *const u8, &[Word, Word], &[Word]),
+        helper("rt_equals", rt_equals as *const u8, &[Word, Word], &[Bool]),
+        helper(
+            "rt_to_c_string",
+            rt_to_c_string as *const u8,
+            &[Word],
+            &[Word],
+        ),
+        helper(
+            "rt_from_c_string",
+            rt_from_c_string as *const u8,
+            &[Word],
+            &[Word],
+        ),
+        helper(
+            "rt_builtin",
+            rt_builtin as *const u8,
+            &[Word, Word],
+            &[Word],
+        ),
+    ]
+}
@@ -0,0 +1,250 @@
+//! A reader for JSON, for the messages that editors send the language server.
+//! JSON is written with `format!` and [json_string](crate::doc::json_string)
+//! instead, as everywhere else.
+
+use std::{collections::BTreeMap, fmt};
+
+use crate::doc::json_string;
+
+pub type Object = BTreeMap<String, Value>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Object),
+}
+
+impl Value {
+    /// The member `key` of an object, or `None` if this isn't an object or
+    /// has no such member.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(o) => o.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value as a non-negative integer, if it is one.
+    pub fn as_usize(&self) -> Option<usize> {
+        match *self {
+            Value::Number(n) if n >= 0.0 && n.fract() == 0.0 => Some(n as usize),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the value back out as JSON, as when echoing the `id` of a request.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) => write!(f, "{n}"),
+            Value::String(s) => write!(f, "{}", json_string(s)),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    let comma = if i > 0 { "," } else { "" };
+                    write!(f, "{comma}{item}")?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    let comma = if i > 0 { "," } else { "" };
+                    write!(f, "{comma}{}:{value}", json_string(key))?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// The input isn't JSON, or has something after the value.
+#[derive(Debug)]
+pub struct JsonError {
+    /// The byte offset of where the input stopped making sense.
+    pub offset: usize,
+}
+
+pub fn parse(src: &str) -> Result<Value, JsonError> {
+    let mut cursor = Cursor { src, pos: 0 };
+    let value = cursor.value()?;
+    cursor.skip_whitespace();
+    match cursor.pos == src.len() {
+        true => Ok(value),
+        false => Err(cursor.error()),
+    }
+}
+
+struct Cursor<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn error(&self) -> JsonError {
+        JsonError { offset: self.pos }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let eaten = self.peek() == Some(byte);
+        if eaten {
+            self.pos += 1;
+        }
+        eaten
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), JsonError> {
+        match self.eat(byte) {
+            true => Ok(()),
+            false => Err(self.error()),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value, JsonError> {
+        match self.src[self.pos..].starts_with(word) {
+            true => {
+                self.pos += word.len();
+                Ok(value)
+            }
+            false => Err(self.error()),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, JsonError> {
+        self.skip_whitespace();
+        match self.peek().ok_or_else(|| self.error())? {
+            b'n' => self.keyword("null", Value::Null),
+            b't' => self.keyword("true", Value::Bool(true)),
+            b'f' => self.keyword("false", Value::Bool(false)),
+            b'"' => self.string().map(Value::String),
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.value()?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(Value::Array(items))
+            }
+            b'{' => {
+                self.pos += 1;
+                let mut members = Object::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        members.insert(key, self.value()?);
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(Value::Object(members))
+            }
+            _ => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, JsonError> {
+        let start = self.pos;
+        while matches!(
+            self.peek(),
+            Some(b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E')
+        ) {
+            self.pos += 1;
+        }
+        self.src[start..self.pos]
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| JsonError { offset: start })
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error());
+        }
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let rest = &self.src[self.pos..];
+            let end = rest.find(['"', '\\']).ok_or_else(|| self.error())?;
+            out.push_str(&rest[..end]);
+            self.pos += end + 1;
+            if rest.as_bytes()[end] == b'"' {
+                return Ok(out);
+            }
+            let escaped = match self.peek().ok_or_else(|| self.error())? {
+                b'"' => '"',
+                b'\\' => '\\',
+                b'/' => '/',
+                b'b' => '\u{8}',
+                b'f' => '\u{c}',
+                b'n' => '\n',
+                b'r' => '\r',
+                b't' => '\t',
+                b'u' => {
+                    self.pos += 1;
+                    let unit = self.hex()?;
+                    // a character outside the BMP is written as a pair of
+                    // UTF-16 surrogates, each escaped
+                    let ch = match unit {
+                        0xD800..=0xDBFF if self.src[self.pos..].starts_with("\\u") => {
+                            self.pos += 2;
+                            let low = self.hex()?;
+                            char::decode_utf16([unit, low]).next().and_then(Result::ok)
+                        }
+                        _ => char::from_u32(unit as u32),
+                    };
+                    out.push(ch.unwrap_or(char::REPLACEMENT_CHARACTER));
+                    continue;
+                }
+                _ => return Err(self.error()),
+            };
+            out.push(escaped);
+            self.pos += 1;
+        }
+    }
+
+    /// Reads the four hex digits of a `\u` escape.
+    fn hex(&mut self) -> Result<u16, JsonError> {
+        let digits = self
+            .src
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error())?;
+        let unit = u16::from_str_radix(digits, 16).map_err(|_| self.error())?;
+        self.pos += 4;
+        Ok(unit)
+    }
+}
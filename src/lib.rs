@@ -0,0 +1,67 @@
+//! The radi compiler as a library, for embedding its front end in other
+//! programs. [Compiler] runs the whole front end on a file or on a string of
+//! source, [parse_str] only parses, and [Tokens] only tokenizes. The syntax
+//! tree is in [parser], and what goes wrong is reported to an [ErrorStream]
+//! as [Diagnostic]s. [sexp] writes a tree in a form that stays the same
+//! across releases, for golden tests to compare against, and [parse_bytes]
+//! parses any input at all without panicking, for fuzzers.
+//!
+//! Compiling the same files gives the same output byte for byte, however
+//! many threads parse them and in whatever order they finish: files get
+//! their [FileId]s in the order they're loaded, work done in parallel is put
+//! back together in that order, and diagnostics are reported in the order
+//! the passes find them, module by module. Nothing is ordered by a hash map
+//! or by where something is in memory, so what's collected from a map is
+//! sorted before it's used.
+//!
+//! The modules are also what the `radi` binary is built from, and only the
+//! items exported from the root of the crate are kept stable.
+
+mod bigint;
+mod c;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod char_reader;
+mod compiler;
+pub mod corpus;
+pub mod diff;
+pub mod doc;
+mod effects;
+pub mod errors;
+pub mod eval;
+pub mod grammar;
+pub mod highlight;
+pub mod hir;
+pub mod index;
+#[cfg(feature = "jit")]
+mod jit;
+mod json;
+pub mod lints;
+pub mod lsp;
+pub mod parse_manager;
+pub mod parser;
+pub mod passes;
+#[cfg(feature = "playground")]
+pub mod playground;
+pub mod profile;
+pub mod repl;
+pub mod resolver;
+mod scc;
+pub mod serve;
+pub mod source_map;
+pub mod string_storage;
+pub mod tags;
+pub mod tokenizer;
+mod toml;
+pub mod typeck;
+mod wasm;
+
+pub use compiler::Compiler;
+pub use errors::{
+    CompilationError, CompilationErrorKind, Diagnostic, DiagnosticRecord, ErrorStream, Severity,
+};
+pub use parser::{parse_bytes, parse_str, sexp, Module, ParseOutcome};
+pub use passes::Artifacts;
+pub use source_map::{FileId, SourceFile, SourceMap};
+pub use string_storage::StringStorage;
+pub use tokenizer::{Span, Token, TokenKind, Tokens};
@@ -0,0 +1,31 @@
+use crate::resolver::ExprKind;
+
+use super::*;
+
+/// Warns about blocks with nothing in them, like the body of a function
+/// that was never written.
+pub struct EmptyScope;
+
+impl<'s> Lint<'s> for EmptyScope {
+    fn name(&self) -> &'static str {
+        "empty-scope"
+    }
+
+    fn default_level(&self) -> Level {
+        Level::Warn
+    }
+
+    fn check_expr(&mut self, cx: &LintContext<'s, '_>, expr: &Expr<'s>) {
+        let empty = match &expr.kind {
+            ExprKind::Block(scope) => scope.defs.is_empty() && scope.body.is_empty(),
+            // the parser turns `{}` into the empty tuple, like `()`
+            ExprKind::Tuple { items } => {
+                items.is_empty() && cx.source_map.snippet(expr.span).starts_with('{')
+            }
+            _ => false,
+        };
+        if empty {
+            cx.report(LintKind::EmptyScope, expr.span);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Lints are checks for code that is valid but probably not what was meant.
+//! Each one implements [Lint], whose methods are called for the nodes of the
+//! resolved tree as it is walked, and is listed in [all] under a name.
+//!
+//! A lint's level decides what it reports: nothing, a warning or an error.
+//! Every lint has a default level, which a project can change in the
+//! `[lints]` table of its `radi.toml`, along with the options of lints that
+//! have any:
+//!
+//! ```toml
+//! [lints]
+//! shadowing = "allow"
+//! unused-def = "deny"
+//!
+//! [lints.naming]
+//! level = "warn"
+//! types = "CamelCase"
+//! ```
+use std::{cell::RefCell, collections::BTreeMap};
+
+use crate::{
+    errors::ErrorStream,
+    resolver::{self, Def, Expr, Module, Pattern, Resolution, Visitor},
+    source_map::SourceMap,
+    string_storage::StringStorage,
+    tokenizer::{Intern, Span},
+    toml,
+};
+
+mod empty_scope;
+mod naming;
+mod shadowing;
+mod unused_def;
+
+pub use naming::Case;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    pub fn parse(level: &str) -> Option<Level> {
+        match level {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+/// How a project configures a lint.
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    /// The level to use instead of the default.
+    pub level: Option<Level>,
+    /// The lint's own options.
+    pub options: toml::Table,
+}
+
+#[derive(Debug)]
+pub enum LintConfigError {
+    UnknownLint,
+    InvalidLevel,
+    /// The lint has no such option, or it can't have that value.
+    InvalidOption(String),
+}
+
+impl LintConfig {
+    /// Reads how the lint `name` is configured from its entry in the
+    /// `[lints]` table, which is either a level or a table of options,
+    /// possibly including the level.
+    pub fn parse(name: &str, value: &toml::Value) -> Result<LintConfig, LintConfigError> {
+        let mut lint = all()
+            .into_iter()
+            .find(|lint| lint.name() == name)
+            .ok_or(LintConfigError::UnknownLint)?;
+        let mut options = match value {
+            toml::Value::Table(table) => table.clone(),
+            _ => toml::Table::from([("level".to_string(), value.clone())]),
+        };
+        let level = match options.remove("level") {
+            Some(level) => match level.as_str().and_then(Level::parse) {
+                Some(level) => Some(level),
+                None => return Err(LintConfigError::InvalidLevel),
+            },
+            None => None,
+        };
+        for (key, value) in &options {
+            if !lint.configure(key, value) {
+                return Err(LintConfigError::InvalidOption(key.clone()));
+            }
+        }
+        Ok(LintConfig { level, options })
+    }
+}
+
+#[derive(Debug)]
+pub struct LintWarning<'s> {
+    /// The name of the lint that reported it.
+    pub lint: &'static str,
+    pub kind: LintKind<'s>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum LintKind<'s> {
+    /// A def that isn't public is never used.
+    UnusedDef(Intern<'s>),
+    /// A binding has the same name as one in an outer scope.
+    Shadowing(Intern<'s>),
+    /// A block has nothing in it.
+    EmptyScope,
+    /// The name of a def isn't in the case it should be. The suggestion is
+    /// the name in that case, if the def can be renamed to it.
+    Naming {
+        name: Intern<'s>,
+        case: Case,
+        suggestion: Option<String>,
+    },
+}
+
+/// Changes to the source that fix what a lint found: each span is replaced
+/// with the text next to it.
+#[derive(Debug, Clone)]
+pub struct Fix(pub Vec<(Span, String)>);
+
+pub trait Lint<'s> {
+    /// The name the lint is configured by.
+    fn name(&self) -> &'static str;
+
+    fn default_level(&self) -> Level;
+
+    /// Sets one of the lint's options, returning whether it has that option
+    /// and the value is valid for it.
+    fn configure(&mut self, _key: &str, _value: &toml::Value) -> bool {
+        false
+    }
+
+    /// Called for each module before anything in it. The object that makes
+    /// up the module isn't passed to [Lint::check_expr].
+    fn check_module(&mut self, _cx: &LintContext<'s, '_>, _module: &Module<'s>) {}
+
+    fn check_expr(&mut self, _cx: &LintContext<'s, '_>, _expr: &Expr<'s>) {}
+
+    fn check_def(&mut self, _cx: &LintContext<'s, '_>, _def: &Def<'s>) {}
+
+    fn check_pattern(&mut self, _cx: &LintContext<'s, '_>, _pattern: &Pattern<'s>) {}
+}
+
+/// Every lint.
+pub fn all<'s>() -> Vec<Box<dyn Lint<'s>>> {
+    vec![
+        Box::new(unused_def::UnusedDef::default()),
+        Box::new(shadowing::Shadowing),
+        Box::new(empty_scope::EmptyScope),
+        Box::new(naming::Naming::default()),
+    ]
+}
+
+/// What a lint has access to while it checks a node.
+pub struct LintContext<'s, 'c> {
+    pub resolution: &'c Resolution<'s>,
+    pub source_map: &'c SourceMap,
+    pub storage: &'s StringStorage,
+    errors: &'c ErrorStream<'s>,
+    fixes: &'c RefCell<Vec<Fix>>,
+    lint: &'static str,
+    level: Level,
+}
+
+impl<'s> LintContext<'s, '_> {
+    /// Reports something the lint found, as a warning or an error depending
+    /// on its level.
+    pub fn report(&self, kind: LintKind<'s>, span: Span) {
+        let warning = LintWarning {
+            lint: self.lint,
+            kind,
+            span,
+        };
+        match self.level {
+            Level::Allow => {}
+            Level::Warn => self.errors.warning(warning),
+            Level::Deny => self.errors.error(warning),
+        }
+    }
+
+    /// Reports something the lint found along with a way to fix it.
+    pub fn report_with_fix(&self, kind: LintKind<'s>, span: Span, fix: Fix) {
+        self.report(kind, span);
+        self.fixes.borrow_mut().push(fix);
+    }
+}
+
+/// Runs every lint that isn't allowed over `modules`, configured as in
+/// `config`, and returns the fixes for what they found.
+pub fn check<'s>(
+    modules: &[&Module<'s>],
+    resolution: &Resolution<'s>,
+    source_map: &SourceMap,
+    storage: &'s StringStorage,
+    config: &BTreeMap<String, LintConfig>,
+    errors: &ErrorStream<'s>,
+) -> Vec<Fix> {
+    let lints = all()
+        .into_iter()
+        .map(|mut lint| {
+            let config = config.get(lint.name());
+            for (key, value) in config.iter().flat_map(|config| &config.options) {
+                lint.configure(key, value);
+            }
+            let level = config.and_then(|config| config.level);
+            let level = level.unwrap_or_else(|| lint.default_level());
+            (lint, level)
+        })
+        .filter(|(_, level)| *level != Level::Allow)
+        .collect();
+    let mut linter = Linter {
+        lints,
+        resolution,
+        source_map,
+        storage,
+        errors,
+        fixes: RefCell::new(Vec::new()),
+    };
+    for module in modules {
+        linter.each(|lint, cx| lint.check_module(cx, module));
+        resolver::walk_expr(&mut linter, &module.body);
+    }
+    linter.fixes.into_inner()
+}
+
+struct Linter<'s, 'c> {
+    lints: Vec<(Box<dyn Lint<'s>>, Level)>,
+    resolution: &'c Resolution<'s>,
+    source_map: &'c SourceMap,
+    storage: &'s StringStorage,
+    errors: &'c ErrorStream<'s>,
+    fixes: RefCell<Vec<Fix>>,
+}
+
+impl<'s> Linter<'s, '_> {
+    fn each(&mut self, mut f: impl FnMut(&mut dyn Lint<'s>, &LintContext<'s, '_>)) {
+        for (lint, level) in &mut self.lints {
+            let cx = LintContext {
+                resolution: self.resolution,
+                source_map: self.source_map,
+                storage: self.storage,
+                errors: self.errors,
+                fixes: &self.fixes,
+                lint: lint.name(),
+                level: *level,
+            };
+            f(&mut **lint, &cx);
+        }
+    }
+}
+
+impl<'s> Visitor<'s> for Linter<'s, '_> {
+    fn visit_expr(&mut self, expr: &Expr<'s>) {
+        self.each(|lint, cx| lint.check_expr(cx, expr));
+        resolver::walk_expr(self, expr);
+    }
+
+    fn visit_def(&mut self, def: &Def<'s>) {
+        self.each(|lint, cx| lint.check_def(cx, def));
+        resolver::walk_def(self, def);
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern<'s>) {
+        self.each(|lint, cx| lint.check_pattern(cx, pattern));
+        resolver::walk_pattern(self, pattern);
+    }
+}
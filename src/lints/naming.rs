@@ -0,0 +1,184 @@
+use rustc_hash::FxHashSet;
+
+use crate::resolver::{self, ExprKind, SymbolId, SymbolKind};
+
+use super::*;
+
+/// The ways of writing a name made of several words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    /// `snake_case`
+    Snake,
+    /// `SCREAMING_SNAKE_CASE`
+    ScreamingSnake,
+    /// `camelCase`
+    LowerCamel,
+    /// `CamelCase`
+    UpperCamel,
+}
+
+impl Case {
+    pub fn parse(case: &str) -> Option<Case> {
+        match case {
+            "snake_case" => Some(Case::Snake),
+            "SCREAMING_SNAKE_CASE" => Some(Case::ScreamingSnake),
+            "camelCase" => Some(Case::LowerCamel),
+            "CamelCase" => Some(Case::UpperCamel),
+            _ => None,
+        }
+    }
+
+    /// Writes `name` in this case.
+    pub fn apply(self, name: &str) -> String {
+        let words = words(name);
+        let capitalized = |word: &str| {
+            let mut chars = word.chars();
+            chars.next().map_or_else(String::new, |first| {
+                first.to_uppercase().chain(chars).collect()
+            })
+        };
+        match self {
+            Case::Snake => words.join("_"),
+            Case::ScreamingSnake => words.join("_").to_uppercase(),
+            Case::LowerCamel => {
+                let mut words = words.iter();
+                let first = words.next().cloned().unwrap_or_default();
+                first + &words.map(|word| capitalized(word)).collect::<String>()
+            }
+            Case::UpperCamel => words.iter().map(|word| capitalized(word)).collect(),
+        }
+    }
+}
+
+/// Splits a name into its words, in lowercase. Words are separated by `_`
+/// or start with an uppercase letter, except within a run of them like the
+/// `HTTP` in `HTTPServer`.
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for part in name.split('_').filter(|part| !part.is_empty()) {
+        let chars = part.chars().collect::<Vec<_>>();
+        let mut word = String::new();
+        for (i, &ch) in chars.iter().enumerate() {
+            let prev = i.checked_sub(1).map(|i| chars[i]);
+            let next = chars.get(i + 1);
+            let boundary = ch.is_uppercase()
+                && prev.is_some_and(|prev| {
+                    !prev.is_uppercase() || next.is_some_and(|next| next.is_lowercase())
+                });
+            if boundary && !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            word.extend(ch.to_lowercase());
+        }
+        words.push(word);
+    }
+    words
+}
+
+/// Checks that defs are named in the case for what they are: `types` for
+/// defs that are objects, function types or other names for builtin types,
+/// and `values` for the rest. The cases can be set in the manifest, and
+/// default to `CamelCase` and `snake_case`.
+///
+/// Where the def can be renamed to its name in the right case, the warning
+/// suggests that name and comes with a fix that renames every use of it.
+/// The fields of objects are checked but have no fix, since renaming them
+/// wouldn't rename the accesses to them.
+pub struct Naming {
+    values: Case,
+    types: Case,
+    fields: FxHashSet<SymbolId>,
+}
+
+impl Default for Naming {
+    fn default() -> Self {
+        Naming {
+            values: Case::Snake,
+            types: Case::UpperCamel,
+            fields: FxHashSet::default(),
+        }
+    }
+}
+
+impl<'s> Lint<'s> for Naming {
+    fn name(&self) -> &'static str {
+        "naming"
+    }
+
+    fn default_level(&self) -> Level {
+        Level::Warn
+    }
+
+    fn configure(&mut self, key: &str, value: &toml::Value) -> bool {
+        let case = match value.as_str().and_then(Case::parse) {
+            Some(case) => case,
+            None => return false,
+        };
+        match key {
+            "values" => self.values = case,
+            "types" => self.types = case,
+            _ => return false,
+        }
+        true
+    }
+
+    fn check_expr(&mut self, _: &LintContext<'s, '_>, expr: &Expr<'s>) {
+        if let ExprKind::Object(scope) = &expr.kind {
+            self.fields.extend(scope.defs.iter().map(|def| def.symbol));
+        }
+    }
+
+    fn check_def(&mut self, cx: &LintContext<'s, '_>, def: &Def<'s>) {
+        let symbol = cx.resolution.symbol(def.symbol);
+        let name = symbol.name.0;
+        // an impl is named after its trait, which is checked instead
+        if name.starts_with('_') || symbol.kind == SymbolKind::Impl {
+            return;
+        }
+        // a method is declared with its type as its value
+        let case = match is_type(cx, &def.value) && !matches!(symbol.kind, SymbolKind::Method(_)) {
+            true => self.types,
+            false => self.values,
+        };
+        let expected = case.apply(name);
+        if expected == name {
+            return;
+        }
+        let spans = match self.fields.contains(&def.symbol) {
+            true => None,
+            false => resolver::rename(
+                cx.source_map,
+                cx.storage,
+                cx.resolution,
+                symbol.span.start,
+                &expected,
+            )
+            .ok(),
+        };
+        let kind = LintKind::Naming {
+            name: symbol.name,
+            case,
+            suggestion: spans.is_some().then(|| expected.clone()),
+        };
+        match spans {
+            Some(spans) => {
+                let edits = spans.into_iter().map(|span| (span, expected.clone()));
+                cx.report_with_fix(kind, symbol.span, Fix(edits.collect()));
+            }
+            None => cx.report(kind, symbol.span),
+        }
+    }
+}
+
+/// Whether `value` makes the def it's the value of a type, or a thing that
+/// is used like one.
+fn is_type(cx: &LintContext, value: &Expr) -> bool {
+    match &value.kind {
+        ExprKind::Object(_) | ExprKind::Arrow { .. } | ExprKind::Trait { .. } => true,
+        ExprKind::Ident(id) => match cx.resolution.symbol(*id).kind {
+            SymbolKind::Builtin(builtin) => builtin.is_type(),
+            _ => false,
+        },
+        _ => false,
+    }
+}
@@ -0,0 +1,54 @@
+use crate::resolver::{PatternKind, SymbolId};
+
+use super::*;
+
+/// Warns about defs and parameters with the same name as a binding in a
+/// scope around them, which can then no longer be referred to. The prelude
+/// isn't counted, and neither are names that start with `_`.
+pub struct Shadowing;
+
+impl<'s> Lint<'s> for Shadowing {
+    fn name(&self) -> &'static str {
+        "shadowing"
+    }
+
+    fn default_level(&self) -> Level {
+        Level::Warn
+    }
+
+    fn check_def(&mut self, cx: &LintContext<'s, '_>, def: &Def<'s>) {
+        check(cx, def.symbol);
+    }
+
+    fn check_pattern(&mut self, cx: &LintContext<'s, '_>, pattern: &Pattern<'s>) {
+        if let PatternKind::Bind(symbol) = pattern.kind {
+            check(cx, symbol);
+        }
+    }
+}
+
+fn check(cx: &LintContext, id: SymbolId) {
+    let symbol = cx.resolution.symbol(id);
+    if symbol.name.0.starts_with('_') {
+        return;
+    }
+    let around = cx
+        .resolution
+        .scopes
+        .iter()
+        .filter(|scope| scope.span.start <= symbol.span.start && symbol.span.end <= scope.span.end)
+        .collect::<Vec<_>>();
+    // an impl and its methods aren't bound anywhere, so they hide nothing
+    if !around.iter().any(|scope| scope.symbols.contains(&id)) {
+        return;
+    }
+    // the scopes around the one the symbol is bound in
+    let shadows = around
+        .iter()
+        .filter(|scope| !scope.symbols.contains(&id))
+        .flat_map(|scope| scope.symbols.iter())
+        .any(|&other| other != id && cx.resolution.symbol(other).name == symbol.name);
+    if shadows {
+        cx.report(LintKind::Shadowing(symbol.name), symbol.span);
+    }
+}
@@ -0,0 +1,55 @@
+use rustc_hash::FxHashSet;
+
+use crate::resolver::{ExprKind, SymbolId, SymbolKind};
+
+use super::*;
+
+/// Warns about defs that nothing refers to. Public defs are left alone,
+/// since they may be meant for other programs, as is `main`, which is
+/// called by the runtime. The fields of objects are too, since they're
+/// accessed by name rather than resolved, and so are impls and their
+/// methods, which are used through their traits. A def whose name starts
+/// with `_` is unused on purpose.
+#[derive(Default)]
+pub struct UnusedDef {
+    fields: FxHashSet<SymbolId>,
+}
+
+impl<'s> Lint<'s> for UnusedDef {
+    fn name(&self) -> &'static str {
+        "unused-def"
+    }
+
+    fn default_level(&self) -> Level {
+        Level::Warn
+    }
+
+    fn check_expr(&mut self, _: &LintContext<'s, '_>, expr: &Expr<'s>) {
+        match &expr.kind {
+            ExprKind::Object(scope) => self.fields.extend(scope.defs.iter().map(|def| def.symbol)),
+            ExprKind::Impl { defs, .. } => self.fields.extend(defs.iter().map(|def| def.symbol)),
+            _ => {}
+        }
+    }
+
+    fn check_def(&mut self, cx: &LintContext<'s, '_>, def: &Def<'s>) {
+        let symbol = cx.resolution.symbol(def.symbol);
+        let name = symbol.name.0;
+        if name.starts_with('_')
+            || name == "main"
+            || cx.resolution.exported.contains(&def.symbol)
+            || self.fields.contains(&def.symbol)
+            || symbol.kind == SymbolKind::Impl
+        {
+            return;
+        }
+        if cx
+            .resolution
+            .references
+            .get(&def.symbol)
+            .is_none_or(Vec::is_empty)
+        {
+            cx.report(LintKind::UnusedDef(symbol.name), symbol.span);
+        }
+    }
+}
@@ -0,0 +1,591 @@
+//! A language server for `radi lsp`, which answers an editor's questions
+//! about a program over the Language Server Protocol, with messages read from
+//! stdin and written to stdout.
+//!
+//! The server keeps the text of the files the editor has open, which it's
+//! sent in full on every change. Each request compiles the file it's about
+//! afresh, as far as type checking, with the open files in place of what's
+//! on disk, and answers from the library's queries over the result:
+//! - `textDocument/semanticTokens/full` classifies the file's tokens, with
+//!   [semantic_tokens](resolver::semantic_tokens).
+//! - `textDocument/hover` shows the type and doc comment of the symbol at
+//!   the cursor, with [hover](resolver::hover).
+//! - `textDocument/completion` offers the names visible at the cursor, or
+//!   the fields of an object after a `.`, with
+//!   [completions](resolver::completions).
+//! - `textDocument/definition` goes to where the symbol at the cursor is
+//!   bound, in whichever file that is, or to the module named there, with
+//!   [find_definition](resolver::find_definition).
+//! - `textDocument/rename` renames the symbol at the cursor wherever it's
+//!   named, in every file of the project, with [rename](resolver::rename).
+//!   The edits are sent back for the editor to make.
+//!
+//! Positions are sent as lines and UTF-16 columns, which are converted to
+//! and from the offsets that the queries take.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    doc::json_string,
+    json::{self, Value},
+    parse_manager::{FileSystem, Overlay, ParseManager, Project},
+    passes::{self, Context, PassManager},
+    resolver::{self, Resolution, ResolveOptions, SemanticToken, SymbolId, SymbolKind, TokenClass},
+    source_map::{FileId, Lines, SourceMap},
+    tokenizer::Span,
+    typeck::{Type, Typing},
+    CompilationError, ErrorStream, StringStorage,
+};
+
+/// A JSON-RPC error, with its code and what went wrong.
+type ResponseError = (i32, String);
+
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const REQUEST_FAILED: i32 = -32803;
+
+/// The kinds of completion items.
+const METHOD: u8 = 2;
+const FUNCTION: u8 = 3;
+const FIELD: u8 = 5;
+const VARIABLE: u8 = 6;
+const INTERFACE: u8 = 8;
+const MODULE: u8 = 9;
+const TYPE_PARAMETER: u8 = 25;
+
+/// The types that [semantic tokens](TokenClass) are sent as, by index.
+const TOKEN_TYPES: [&str; 9] = [
+    "keyword",
+    "function",
+    "parameter",
+    "namespace",
+    "property",
+    "enumMember",
+    "operator",
+    "number",
+    "string",
+];
+
+/// The modifiers of semantic tokens, sent as bits by index.
+const TOKEN_MODIFIERS: [&str; 2] = ["declaration", "defaultLibrary"];
+
+/// Answers the messages from `input` on `output` until the editor says to
+/// exit or closes `input`. Returns whether the editor asked the server to
+/// shut down first, as it should have.
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> io::Result<bool> {
+    let mut server = Server::default();
+    while let Some(message) = read_message(&mut input)? {
+        let message = match json::parse(&message) {
+            Ok(message) => message,
+            Err(err) => {
+                let error = (PARSE_ERROR, format!("invalid JSON at {}", err.offset));
+                write_message(&mut output, &response(&Value::Null, Err(error)))?;
+                continue;
+            }
+        };
+        // the editor's responses to requests are ignored, since none are sent
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            continue;
+        };
+        let params = message.get("params").unwrap_or(&Value::Null);
+        match message.get("id") {
+            Some(id) => {
+                let result = server.request(method, params);
+                write_message(&mut output, &response(id, result))?;
+            }
+            None if method == "exit" => return Ok(server.shut_down),
+            None => server.notify(method, params),
+        }
+    }
+    Ok(false)
+}
+
+/// Reads the body of the next message, or returns `None` once the input
+/// ends.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        let message = "a message has no Content-Length";
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_message(output: &mut impl Write, body: &str) -> io::Result<()> {
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+/// The response to the request `id`, with its result as JSON or its error.
+fn response(id: &Value, result: Result<String, ResponseError>) -> String {
+    match result {
+        Ok(result) => format!("{{\"jsonrpc\":\"2.0\",\"id\":{id},\"result\":{result}}}"),
+        Err((code, message)) => format!(
+            "{{\"jsonrpc\":\"2.0\",\"id\":{id},\"error\":{{\"code\":{code},\"message\":{}}}}}",
+            json_string(&message)
+        ),
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    /// The text of each file that the editor has open.
+    documents: FxHashMap<PathBuf, String>,
+    shut_down: bool,
+}
+
+impl Server {
+    fn request(&mut self, method: &str, params: &Value) -> Result<String, ResponseError> {
+        match method {
+            "initialize" => Ok(format!("{{\"capabilities\":{}}}", capabilities())),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok("null".to_string())
+            }
+            "textDocument/semanticTokens/full" => {
+                self.analyze(&document(params)?, false, semantic_tokens)
+            }
+            "textDocument/hover" => {
+                let position = position(params)?;
+                self.analyze(&document(params)?, false, |analysis| {
+                    hover(analysis, position)
+                })
+            }
+            "textDocument/completion" => {
+                let position = position(params)?;
+                self.analyze(&document(params)?, false, |analysis| {
+                    completion(analysis, position)
+                })
+            }
+            "textDocument/definition" => {
+                let position = position(params)?;
+                self.analyze(&document(params)?, false, |analysis| {
+                    definition(analysis, position)
+                })
+            }
+            "textDocument/rename" => {
+                let position = position(params)?;
+                let Some(new_name) = params.get("newName").and_then(Value::as_str) else {
+                    return Err((INVALID_PARAMS, "expected a new name".to_string()));
+                };
+                self.analyze(&document(params)?, true, |analysis| {
+                    rename(analysis, position, new_name)
+                })?
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("`{method}` isn't supported"))),
+        }
+    }
+
+    /// Keeps track of the files the editor has open. Other notifications
+    /// are ignored, as the protocol allows.
+    fn notify(&mut self, method: &str, params: &Value) {
+        let Ok(path) = document(params) else {
+            return;
+        };
+        let text = match method {
+            "textDocument/didOpen" => params.get("textDocument").and_then(|d| d.get("text")),
+            // changes are sent as the whole text, so the last one is it
+            "textDocument/didChange" => match params.get("contentChanges") {
+                Some(Value::Array(changes)) => changes.last().and_then(|c| c.get("text")),
+                _ => None,
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&path);
+                return;
+            }
+            _ => return,
+        };
+        if let Some(text) = text.and_then(Value::as_str) {
+            self.documents.insert(path, text.to_string());
+        }
+    }
+
+    /// Compiles the file at `path` as the entry of its project, and answers
+    /// a request about it with `f`. With `whole`, every file of the project
+    /// is compiled too, even those the entry doesn't use.
+    fn analyze<T>(
+        &self,
+        path: &Path,
+        whole: bool,
+        f: impl FnOnce(&Analysis) -> T,
+    ) -> Result<T, ResponseError> {
+        let project = match Project::discover(Some(path)) {
+            Ok(project) => project.expect("a path was given"),
+            Err(err) => return Err((REQUEST_FAILED, CompilationError::from(err).kind.message())),
+        };
+        let prelude = project
+            .manifest
+            .as_ref()
+            .and_then(|manifest| manifest.build.prelude)
+            .unwrap_or(true);
+        let mut sources = Overlay::new(FileSystem);
+        for (path, text) in &self.documents {
+            sources.insert(path, text.clone());
+        }
+
+        let storage = StringStorage::new();
+        let errors = ErrorStream::buffered();
+        let mut manager = ParseManager::new(&storage, &errors, &project.root).with_sources(sources);
+        let mut passes = PassManager::new();
+        passes.add(passes::Parse {
+            path: path.to_owned(),
+            prelude,
+            project: whole && project.manifest.is_some(),
+        });
+        passes.add(passes::Resolve {
+            options: ResolveOptions { prelude },
+        });
+        passes.add(passes::Typecheck);
+        let artifacts = passes.run(&mut Context {
+            storage: &storage,
+            errors: &errors,
+            manager: &mut manager,
+        });
+
+        let (Some(entry), Some(resolution)) = (artifacts.entry, &artifacts.resolution) else {
+            let message = format!("couldn't read {}", path.display());
+            return Err((REQUEST_FAILED, message));
+        };
+        Ok(f(&Analysis {
+            storage: &storage,
+            source_map: manager.source_map(),
+            entry,
+            resolution,
+            typing: artifacts.typing.as_ref(),
+            errors: errors.error_count(),
+        }))
+    }
+}
+
+/// What a request is answered from: a file compiled as the entry of its
+/// project.
+struct Analysis<'a, 's> {
+    storage: &'s StringStorage,
+    source_map: &'a SourceMap,
+    entry: FileId,
+    resolution: &'a Resolution<'s>,
+    typing: Option<&'a Typing<'s>>,
+    /// How many errors the compilation had.
+    errors: usize,
+}
+
+impl Analysis<'_, '_> {
+    /// The offset of a position in the entry file. A position past the end
+    /// of its line is taken to be at the end of it.
+    fn offset(&self, (line, character): (usize, usize)) -> u32 {
+        let src = &self.source_map.file(self.entry).src;
+        let start = src.split_inclusive('\n').take(line).map(str::len).sum();
+        let text = src[start..].split('\n').next().unwrap_or("");
+        let mut units = 0;
+        let column = text
+            .char_indices()
+            .find(|&(_, ch)| {
+                units += ch.len_utf16();
+                units > character
+            })
+            .map_or(text.len(), |(i, _)| i);
+        self.source_map.offset(self.entry, start + column)
+    }
+
+    /// Whether a symbol is known to be a function, from its type.
+    fn is_function(&self, symbol: Option<SymbolId>) -> bool {
+        let ty = self.typing.zip(symbol).and_then(|(typing, id)| {
+            let ty = *typing.symbols.get(&id)?;
+            Some(typing.types.get(typing.types.find(ty)))
+        });
+        matches!(ty, Some(Type::Function(..)))
+    }
+}
+
+fn capabilities() -> String {
+    let legend = |names: &[&str]| {
+        let names = names.iter().map(|name| json_string(name));
+        names.collect::<Vec<_>>().join(",")
+    };
+    format!(
+        "{{\"textDocumentSync\":1,\
+         \"hoverProvider\":true,\
+         \"definitionProvider\":true,\
+         \"renameProvider\":true,\
+         \"completionProvider\":{{\"triggerCharacters\":[\".\"]}},\
+         \"semanticTokensProvider\":{{\"legend\":{{\"tokenTypes\":[{}],\"tokenModifiers\":[{}]}},\"full\":true}}}}",
+        legend(&TOKEN_TYPES),
+        legend(&TOKEN_MODIFIERS)
+    )
+}
+
+/// The semantic tokens of the entry file, each as five numbers: how many
+/// lines it is after the one before, its column, relative to the one before
+/// if on the same line, its length, and its type and modifiers.
+fn semantic_tokens(analysis: &Analysis) -> String {
+    let source_map = analysis.source_map;
+    let file = source_map.file(analysis.entry);
+    let mut lines = Lines::new(source_map);
+    let mut data = Vec::new();
+    let (mut previous_line, mut previous_column) = (0, 0);
+    let tokens = resolver::semantic_tokens(file, analysis.storage, Some(analysis.resolution));
+    for token in tokens {
+        let (ty, modifiers) = token_type(token);
+        // a token is sent a line at a time, since editors needn't support
+        // ones spanning lines, as strings can
+        let mut start = token.span.start;
+        for piece in source_map.snippet(token.span).split_inclusive('\n') {
+            let (_, line, column) = lines.position(start);
+            start += piece.len() as u32;
+            let length = piece.trim_end_matches(['\r', '\n']).encode_utf16().count();
+            if length == 0 {
+                continue;
+            }
+            let delta = match line == previous_line {
+                true => column - previous_column,
+                false => column,
+            };
+            data.extend([line - previous_line, delta, length, ty, modifiers]);
+            (previous_line, previous_column) = (line, column);
+        }
+    }
+    let data = data.iter().map(ToString::to_string).collect::<Vec<_>>();
+    format!("{{\"data\":[{}]}}", data.join(","))
+}
+
+/// The index into [TOKEN_TYPES] of a token, and the bits of its
+/// [TOKEN_MODIFIERS].
+fn token_type(token: SemanticToken) -> (usize, usize) {
+    let declaration = token.declaration as usize;
+    match token.class {
+        TokenClass::Keyword => (0, 0),
+        TokenClass::Def => (1, declaration),
+        TokenClass::Parameter => (2, declaration),
+        TokenClass::Builtin => (1, 2),
+        TokenClass::Module => (3, 0),
+        TokenClass::Property => (4, 0),
+        TokenClass::Variant => (5, 0),
+        TokenClass::Operator => (6, 0),
+        TokenClass::Number => (7, 0),
+        TokenClass::String => (8, 0),
+    }
+}
+
+/// The type and doc comment of the symbol at `position`, or `null` if
+/// there's none there.
+fn hover(analysis: &Analysis, position: (usize, usize)) -> String {
+    let source_map = analysis.source_map;
+    let offset = analysis.offset(position);
+    let Some(hover) = resolver::hover(source_map, analysis.resolution, analysis.typing, offset)
+    else {
+        return "null".to_string();
+    };
+    let name = source_map.snippet(hover.span);
+    let mut value = match hover.ty {
+        Some(ty) => format!("```radi\n{name} :: {ty}\n```"),
+        None => format!("```radi\n{name}\n```"),
+    };
+    if hover.definition.is_none() {
+        value.push_str("\n\nbuiltin");
+    }
+    if let Some(doc) = hover.doc {
+        value.push_str("\n\n");
+        value.push_str(&doc);
+    }
+    format!(
+        "{{\"contents\":{{\"kind\":\"markdown\",\"value\":{}}},\"range\":{}}}",
+        json_string(&value),
+        range(&mut Lines::new(source_map), hover.span)
+    )
+}
+
+/// The names that complete the one at `position`, nearest scope first.
+fn completion(analysis: &Analysis, position: (usize, usize)) -> String {
+    let offset = analysis.offset(position);
+    let completions = resolver::completions(
+        analysis.source_map,
+        analysis.resolution,
+        analysis.typing,
+        offset,
+    );
+    let items = completions.iter().enumerate().map(|(i, completion)| {
+        let kind = match completion
+            .symbol
+            .map(|id| analysis.resolution.symbol(id).kind)
+        {
+            None => FIELD,
+            Some(SymbolKind::Def) if !analysis.is_function(completion.symbol) => VARIABLE,
+            Some(SymbolKind::Def | SymbolKind::Builtin(_)) => FUNCTION,
+            Some(SymbolKind::Param) => VARIABLE,
+            Some(SymbolKind::Module(_)) => MODULE,
+            Some(SymbolKind::Trait | SymbolKind::Impl) => INTERFACE,
+            Some(SymbolKind::Method(_)) => METHOD,
+            Some(SymbolKind::TypeParam) => TYPE_PARAMETER,
+        };
+        let detail = completion
+            .ty
+            .as_deref()
+            .map_or("null".to_string(), json_string);
+        // editors sort by this rather than by the order of the items
+        format!(
+            "{{\"label\":{},\"kind\":{kind},\"detail\":{detail},\"sortText\":\"{i:05}\"}}",
+            json_string(&completion.name)
+        )
+    });
+    format!("[{}]", items.collect::<Vec<_>>().join(","))
+}
+
+/// Where the symbol or module at `position` is defined, or `null` if
+/// there's nothing with a definition there.
+fn definition(analysis: &Analysis, position: (usize, usize)) -> String {
+    let source_map = analysis.source_map;
+    let offset = analysis.offset(position);
+    let (file, range) = match resolver::find_definition(analysis.resolution, offset) {
+        Some(resolver::Definition::Symbol(span, _)) => {
+            let file = source_map.lookup(span.start);
+            (file, range(&mut Lines::new(source_map), span))
+        }
+        Some(resolver::Definition::Module(file)) => {
+            let start = source_map.file(file).start;
+            let span = Span { start, end: start };
+            (file, range(&mut Lines::new(source_map), span))
+        }
+        None => return "null".to_string(),
+    };
+    let uri = uri(&source_map.file(file).path);
+    format!("{{\"uri\":{},\"range\":{range}}}", json_string(&uri))
+}
+
+/// The edits to the files of the project that rename the symbol at
+/// `position` to `new_name`.
+fn rename(
+    analysis: &Analysis,
+    position: (usize, usize),
+    new_name: &str,
+) -> Result<String, ResponseError> {
+    if analysis.errors > 0 {
+        let message = "the program has errors, so not every use may be renamed";
+        return Err((REQUEST_FAILED, message.to_string()));
+    }
+    let source_map = analysis.source_map;
+    let offset = analysis.offset(position);
+    let spans = resolver::rename(
+        source_map,
+        analysis.storage,
+        analysis.resolution,
+        offset,
+        new_name,
+    )
+    .map_err(|err| (REQUEST_FAILED, format!("{err:?}")))?;
+
+    let mut lines = Lines::new(source_map);
+    let mut files = BTreeMap::<FileId, Vec<String>>::new();
+    for span in spans {
+        let edit = format!(
+            "{{\"range\":{},\"newText\":{}}}",
+            range(&mut lines, span),
+            json_string(new_name)
+        );
+        files
+            .entry(source_map.lookup(span.start))
+            .or_default()
+            .push(edit);
+    }
+    let changes = files.iter().map(|(&file, edits)| {
+        let uri = uri(&source_map.file(file).path);
+        format!("{}:[{}]", json_string(&uri), edits.join(","))
+    });
+    let changes = changes.collect::<Vec<_>>().join(",");
+    Ok(format!("{{\"changes\":{{{changes}}}}}"))
+}
+
+/// A span as an LSP range, of lines and UTF-16 columns.
+fn range(lines: &mut Lines, span: Span) -> String {
+    let (_, start_line, start) = lines.position(span.start);
+    let (_, end_line, end) = lines.position(span.end);
+    format!(
+        "{{\"start\":{{\"line\":{start_line},\"character\":{start}}},\
+         \"end\":{{\"line\":{end_line},\"character\":{end}}}}}"
+    )
+}
+
+/// The line and UTF-16 column that a request is about.
+fn position(params: &Value) -> Result<(usize, usize), ResponseError> {
+    let position = params.get("position");
+    let part = |name| position?.get(name)?.as_usize();
+    match (part("line"), part("character")) {
+        (Some(line), Some(character)) => Ok((line, character)),
+        _ => Err((INVALID_PARAMS, "expected a position".to_string())),
+    }
+}
+
+/// The path of the document that a request or notification is about.
+fn document(params: &Value) -> Result<PathBuf, ResponseError> {
+    params
+        .get("textDocument")
+        .and_then(|document| document.get("uri"))
+        .and_then(Value::as_str)
+        .and_then(path)
+        .ok_or_else(|| (INVALID_PARAMS, "expected the URI of a file".to_string()))
+}
+
+/// The `file://` URI of a path.
+fn uri(path: &Path) -> String {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_owned());
+    let mut out = "file://".to_string();
+    for &byte in path.to_string_lossy().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    out
+}
+
+/// The path of a `file://` URI.
+fn path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let escaped = (encoded[i] == b'%')
+            .then(|| std::str::from_utf8(encoded.get(i + 1..i + 3)?).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
@@ -1,26 +1,837 @@
-use crate::parser::utils::ast_size;
-
-mod char_reader;
-mod errors;
-mod parser;
-mod string_storage;
-mod tokenizer;
-mod resolver;
-mod parse_manager;
-
-fn main() {
-    let path = std::env::args().nth(1).unwrap();
-    let storage = string_storage::StringStorage::new();
-    let toks = tokenizer::Tokens::of(
-        char_reader::IoCharReader::<256, _>::new(std::fs::File::open(path).unwrap()),
-        &storage,
-    );
-    let errs = errors::ErrorStream::new();
-    let tree = parser::parse(toks, &errs).unwrap();
-    //println!("{:#?}", tree);
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant, SystemTime},
+};
+
+use cli::{Args, Command};
+use radi::{
+    char_reader, corpus, diff, doc, eval, grammar, highlight, index, lsp,
+    parse_manager::{self, ParseManager},
+    parser,
+    passes::{self, PassManager},
+    profile, repl,
+    resolver::{self, Resolution, ResolveOptions},
+    serve,
+    source_map::{self, FileId},
+    tags,
+    typeck::Typing,
+    ErrorStream, Span, StringStorage, Tokens,
+};
+
+mod cli;
+
+#[global_allocator]
+static ALLOC: profile::CountingAlloc = profile::CountingAlloc;
+
+/// Why the compiler stopped short of what it was asked to do.
+enum Failure {
+    /// The program has errors, or something the compiler needed failed, and
+    /// that was already reported.
+    Errors,
+    /// The compiler wasn't invoked correctly, with what was wrong.
+    Usage(String),
+}
+
+fn main() -> ExitCode {
+    match try_main() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Errors) => ExitCode::from(1),
+        Err(Failure::Usage(message)) => {
+            eprintln!("ERROR: {message}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn try_main() -> Result<(), Failure> {
+    // clap prints help to stdout, and mistakes to stderr along with usage
+    let args = cli::parse(std::env::args().skip(1)).unwrap_or_else(|err| err.exit());
+    if let Some(shell) = args.shell {
+        cli::completions(shell, &mut std::io::stdout());
+        return Ok(());
+    }
+    if args.command == Command::GenCorpus {
+        let program = corpus::generate(args.nodes.unwrap_or(CORPUS_NODES));
+        return write_generated(&args, &program);
+    }
+    if args.command == Command::GenGrammar {
+        let grammar = match args.format.as_deref() {
+            Some("tree-sitter") => grammar::tree_sitter(),
+            _ => grammar::textmate(),
+        };
+        return write_generated(&args, &grammar);
+    }
+    let mut heap_options = eval::HeapOptions {
+        stats: args.gc_stats,
+        ..Default::default()
+    };
+    if let Some(size) = args.heap_size {
+        heap_options.max_objects = size;
+    }
+    if args.timings || args.stats.is_some() {
+        profile::enable();
+    }
+    if args.command == Command::Repl {
+        let options = ResolveOptions {
+            prelude: args.prelude.unwrap_or(true),
+        };
+        if let Err(err) = repl::run(options, heap_options) {
+            eprintln!("ERROR: {err}");
+            return Err(Failure::Errors);
+        }
+        return Ok(());
+    }
+    if args.command == Command::Serve {
+        let options = serve::ServeOptions {
+            resolve: ResolveOptions {
+                prelude: args.prelude.unwrap_or(true),
+            },
+            heap: eval::HeapOptions {
+                max_bytes: serve::MAX_BYTES,
+                ..heap_options
+            },
+            timeout: Duration::from_secs(args.timeout.unwrap_or(SERVE_TIMEOUT) as u64),
+        };
+        let address = args.address.as_deref().unwrap_or(serve::ADDRESS);
+        if let Err(err) = serve::run(address, options) {
+            eprintln!("ERROR: {err}");
+            return Err(Failure::Errors);
+        }
+        return Ok(());
+    }
+    if args.command == Command::Lsp {
+        let stdin = std::io::stdin().lock();
+        return match lsp::run(stdin, std::io::stdout().lock()) {
+            Ok(true) => Ok(()),
+            // exiting without being shut down is an error, as the protocol says
+            Ok(false) => Err(Failure::Errors),
+            Err(err) => {
+                eprintln!("ERROR: {err}");
+                Err(Failure::Errors)
+            }
+        };
+    }
+    if args.command == Command::Fmt {
+        // there's no formatter yet that keeps comments, which printing the
+        // AST back out would drop
+        eprintln!("ERROR: `radi fmt` isn't implemented yet");
+        return Err(Failure::Errors);
+    }
+    if args.command == Command::Diff {
+        return diff(&args.paths[0], &args.paths[1]);
+    }
+    if args.paths.len() > 1 {
+        return check_each(&args, heap_options);
+    }
+    let storage = StringStorage::new();
+    // rendered by the passes once the files they point into are loaded
+    let errs = ErrorStream::deferred();
+    let path = args.paths.first().map(String::as_str);
+    compile(&args, path, &storage, &errs, heap_options)
+}
+
+/// How many nodes `gen-corpus` generates without `--nodes`.
+const CORPUS_NODES: usize = 100_000;
+
+/// How many seconds each program that `serve` runs may take without
+/// `--timeout`.
+const SERVE_TIMEOUT: usize = 5;
+
+/// Prints the structural changes from the file at `old` to the one at `new`.
+fn diff(old: &str, new: &str) -> Result<(), Failure> {
+    let mut source_map = source_map::SourceMap::new();
+    for path in [old, new] {
+        match std::fs::read_to_string(path) {
+            Ok(src) => source_map.add(PathBuf::from(path), src),
+            Err(err) => {
+                eprintln!("ERROR: couldn't read {path}: {err}");
+                return Err(Failure::Errors);
+            }
+        };
+    }
+    let storage = StringStorage::new();
+    let errs = ErrorStream::deferred();
+    let modules = source_map
+        .files()
+        .map(|(_, file)| {
+            let chars = char_reader::StrCharReader::starting_at(&file.src, file.start);
+            parser::parse(Tokens::of(chars, &storage), &errs)
+                .map_err(|err| errs.error(err.at_end(file.end())))
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    errs.render(&source_map);
+    let [Some(old_module), Some(new_module)] = &modules[..] else {
+        return Err(Failure::Errors);
+    };
+    if errs.error_count() > 0 {
+        return Err(Failure::Errors);
+    }
+
+    let changes = diff::diff(&source_map, old_module, new_module);
+    match changes.is_empty() {
+        true => println!("No changes"),
+        false => print!("{}", diff::report(&changes, old, new)),
+    }
+    Ok(())
+}
+
+/// Writes the output of a `gen-` command to `-o`, or to stdout without it.
+fn write_generated(args: &Args, text: &str) -> Result<(), Failure> {
+    match &args.output {
+        Some(path) => std::fs::write(path, text).map_err(|err| {
+            eprintln!("ERROR: couldn't write {path}: {err}");
+            Failure::Errors
+        }),
+        None => {
+            print!("{text}");
+            Ok(())
+        }
+    }
+}
+
+/// Checks several paths one after another, each under a header, and sums up
+/// how many had errors. Each file is checked on its own, even when several
+/// are in the same project, and only paths that come to the same entry as an
+/// earlier one, such as a project's directory given twice, are skipped.
+fn check_each(args: &Args, heap_options: eval::HeapOptions) -> Result<(), Failure> {
+    let storage = StringStorage::new();
+    let mut entries = Vec::new();
+    let (mut checked, mut failed, mut errors, mut warnings) = (0, 0, 0, 0);
+    for path in &args.paths {
+        if let Ok(Some(project)) = parse_manager::Project::discover(Some(Path::new(path))) {
+            if entries.contains(&project.entry) {
+                continue;
+            }
+            entries.push(project.entry);
+        }
+        println!("==> {path} <==");
+        let errs = ErrorStream::deferred();
+        match compile(args, Some(path), &storage, &errs, heap_options) {
+            Ok(()) => {}
+            Err(Failure::Errors) => failed += 1,
+            Err(usage) => return Err(usage),
+        }
+        checked += 1;
+        errors += errs.error_count();
+        warnings += errs.warning_count();
+    }
     println!(
-        "AST size: {}KiB (Expr {} bytes)",
-        ast_size(&tree) / 1024,
-        std::mem::size_of::<parser::Expr>()
-    )
+        "Checked {}: {} with errors ({}, {})",
+        count(checked, "file"),
+        failed,
+        count(errors, "error"),
+        count(warnings, "warning")
+    );
+    match failed {
+        0 => Ok(()),
+        _ => Err(Failure::Errors),
+    }
+}
+
+/// `n` and `thing`, made plural if `n` isn't one.
+fn count(n: usize, thing: &str) -> String {
+    match n {
+        1 => format!("1 {thing}"),
+        _ => format!("{n} {thing}s"),
+    }
+}
+
+/// Compiles the program at `path`, or the project around the current
+/// directory without one, and does what the command asks with it.
+fn compile<'s>(
+    args: &Args,
+    path: Option<&str>,
+    storage: &'s StringStorage,
+    errs: &'s ErrorStream<'s>,
+    heap_options: eval::HeapOptions,
+) -> Result<(), Failure> {
+    let run = args.command == Command::Run;
+    let build = args.command == Command::Build;
+    let highlight = args.command == Command::Highlight;
+    let doc = args.command == Command::Doc;
+    let indexing = args.command == Command::Index;
+    let tagging = args.command == Command::Tags;
+    let (watch, fix, output, format) = (args.watch, args.fix, &args.output, &args.format);
+    let rename = &args.rename;
+    let queries = Queries {
+        definition_at: args.definition_at,
+        references_at: args.references_at,
+        hover_at: args.hover_at,
+        completions_at: args.completions_at,
+        semantic_tokens: args.semantic_tokens,
+    };
+    // source that isn't read from a file, and the name it's compiled under
+    let source = match (path, &args.source) {
+        (Some("-"), _) => {
+            let mut src = String::new();
+            if let Err(err) = std::io::stdin().read_to_string(&mut src) {
+                eprintln!("ERROR: couldn't read stdin: {err}");
+                return Err(Failure::Errors);
+            }
+            Some(("<stdin>.radi", src))
+        }
+        (_, Some(src)) => Some(("<source>.radi", src.clone())),
+        _ => None,
+    };
+    let project = match &source {
+        Some((name, _)) => parse_manager::Project::for_source(name).map(Some),
+        None => parse_manager::Project::discover(path.map(Path::new)),
+    };
+    let project = match project {
+        Ok(Some(project)) => project,
+        Ok(None) => {
+            return Err(Failure::Usage(format!(
+                "no path given, and no radi.toml was found\nRun `radi help {}` for usage",
+                args.command.name()
+            )));
+        }
+        Err(err) => {
+            errs.error(err);
+            errs.render(&source_map::SourceMap::new());
+            return Err(Failure::Errors);
+        }
+    };
+    // the command line wins over the manifest
+    let config = project
+        .manifest
+        .as_ref()
+        .map(|manifest| manifest.build)
+        .unwrap_or_default();
+    let options = ResolveOptions {
+        prelude: args.prelude.or(config.prelude).unwrap_or(true),
+    };
+    let mut manager = ParseManager::new(storage, errs, &project.root);
+    if let Some((_, src)) = source {
+        let mut sources = parse_manager::Overlay::new(parse_manager::FileSystem);
+        sources.insert(&project.entry, src);
+        manager = manager.with_sources(sources);
+    }
+    if let Some(dir) = project.cache_dir().filter(|_| !args.no_cache) {
+        manager = manager.with_cache(parse_manager::Cache::new(dir));
+    }
+    // what `--emit` asks for is written to `-o`, or to stdout without it or
+    // with `-o -`
+    let emit = args.emit.as_deref();
+    let emit_to = match output.as_deref() {
+        Some("-") | None => passes::Output::Stdout,
+        Some(path) => passes::Output::File(PathBuf::from(path)),
+    };
+    let ast_format = match emit {
+        Some("ast") => Some(passes::AstFormat::Tree),
+        Some("ast:dot") => Some(passes::AstFormat::Dot),
+        Some("ast:sexp") => Some(passes::AstFormat::Sexp),
+        Some("json-ast") => Some(passes::AstFormat::Json),
+        Some("ast-stats") => Some(passes::AstFormat::Stats),
+        _ => None,
+    };
+    let mut passes = PassManager::new();
+    passes.add(passes::Parse {
+        path: project.entry.clone(),
+        prelude: options.prelude,
+        // a rename has to find every use, even in files the entry doesn't use
+        project: rename.is_some() && project.manifest.is_some(),
+    });
+    if emit == Some("tokens") {
+        passes.add(passes::DumpTokens {
+            output: emit_to.clone(),
+        });
+    }
+    if let Some(format) = ast_format {
+        passes.add(passes::DumpAst {
+            format,
+            output: emit_to.clone(),
+        });
+    }
+    passes.add(passes::Resolve { options });
+    passes.add(passes::Effects);
+    passes.add(passes::Typecheck);
+    passes.add(passes::Lints {
+        config: project
+            .manifest
+            .as_ref()
+            .map(|manifest| manifest.lints.clone())
+            .unwrap_or_default(),
+    });
+    passes.add(passes::Lower);
+    passes.add(passes::ConstEval);
+    passes.add(passes::Fold);
+    if args.dump_hir {
+        passes.add(passes::DumpHir {
+            output: passes::Output::Stdout,
+        });
+    }
+    if emit == Some("hir") {
+        passes.add(passes::DumpHir {
+            output: emit_to.clone(),
+        });
+    }
+    if args.jit && !cfg!(feature = "jit") {
+        return Err(Failure::Usage(
+            "radi was built without the `jit` feature, so `--jit` isn't available".to_string(),
+        ));
+    }
+    if emit == Some("mono-stats") && !cfg!(feature = "jit") {
+        return Err(Failure::Usage(
+            "radi was built without the `jit` feature, so `--emit=mono-stats` isn't available"
+                .to_string(),
+        ));
+    }
+    let backend = match (run, emit) {
+        #[cfg(feature = "jit")]
+        (true, _) if args.jit => Some(passes::Backend::Jit),
+        (true, _) => Some(passes::Backend::Interpret {
+            options: heap_options,
+            profile: args.profile.as_ref().map(PathBuf::from),
+        }),
+        (false, Some("c")) => Some(passes::Backend::C),
+        #[cfg(feature = "jit")]
+        (false, Some("mono-stats")) => Some(passes::Backend::MonoStats),
+        (false, Some("wasm")) => Some(passes::Backend::Wasm),
+        (false, None) if build => match config.backend {
+            Some(parse_manager::BuildBackend::C) => Some(passes::Backend::C),
+            _ => Some(passes::Backend::Wasm),
+        },
+        _ => None,
+    };
+    if let Some(backend) = backend {
+        passes.add(passes::Codegen {
+            backend,
+            // `build` on its own writes next to the entry file
+            output: (emit.is_some() || output.is_some()).then_some(emit_to),
+        });
+    }
+    if highlight {
+        // only the names are needed
+        for pass in ["effects", "typeck", "lint", "lower", "consteval", "fold"] {
+            passes.set_enabled(pass, false);
+        }
+    }
+    if emit == Some("tokens") || ast_format.is_some() || tagging {
+        // the tokens, AST and tags are written as soon as they're parsed
+        for pass in [
+            "resolve",
+            "effects",
+            "typeck",
+            "lint",
+            "lower",
+            "consteval",
+            "fold",
+        ] {
+            passes.set_enabled(pass, false);
+        }
+    }
+    if doc || indexing {
+        for pass in ["lower", "consteval", "fold"] {
+            passes.set_enabled(pass, false);
+        }
+    }
+    if args.opt_level.or(config.opt_level) == Some(0) {
+        passes.set_enabled("fold", false);
+    }
+    for (name, enabled) in &args.toggled_passes {
+        if !passes.set_enabled(name, *enabled) {
+            let names = passes.names().join(", ");
+            return Err(Failure::Usage(format!(
+                "unknown pass `{name}`, expected one of {names}"
+            )));
+        }
+    }
+    // a pass that needs a disabled one can't run either, which is said
+    // rather than leaving it to do nothing
+    let dependents = passes.disable_dependents();
+    let mut causes = Vec::new();
+    for &(_, cause) in &dependents {
+        if !causes.contains(&cause) {
+            causes.push(cause);
+        }
+    }
+    for cause in causes {
+        let names = dependents
+            .iter()
+            .filter(|&&(_, c)| c == cause)
+            .map(|(name, _)| format!("`{name}`"))
+            .collect::<Vec<_>>();
+        eprintln!(
+            "WARNING: disabling `{cause}` also disables {}, which depend on it",
+            names.join(", ")
+        );
+    }
+
+    let mut modified = None;
+    loop {
+        let mut cx = passes::Context {
+            storage,
+            errors: errs,
+            manager: &mut manager,
+        };
+        let artifacts = passes.run(&mut cx);
+        if args.timings {
+            eprint!("{}", profile::Table(passes.timings()));
+        }
+        if let Some(format) = &args.stats {
+            let mut nodes = BTreeMap::new();
+            for module in manager.modules() {
+                let stats = parser::utils::ast_stats(&module.ast.body);
+                for (name, kind) in stats.kinds {
+                    *nodes.entry(name).or_default() += kind.count;
+                }
+            }
+            let stats = profile::Stats {
+                timings: passes.timings(),
+                peak_rss: profile::peak_rss(),
+                interned: storage.size(),
+                tokens: profile::tokens(),
+                nodes,
+            };
+            match format.as_str() {
+                "json" => eprint!("{}", stats.json()),
+                _ => eprint!("{stats}"),
+            }
+        }
+        if let Some(entry) = artifacts.entry {
+            let file = manager.source_map().file(entry);
+            let offsets = queries
+                .offsets()
+                .chain(rename.as_ref().map(|(offset, _)| *offset));
+            if let Some(offset) = offsets.filter(|&offset| offset > file.src.len()).max() {
+                return Err(Failure::Usage(format!(
+                    "offset {offset} is past the end of {}, which is {} bytes long",
+                    file.path.display(),
+                    file.src.len()
+                )));
+            }
+        }
+        if let (true, Some(entry)) = (highlight, artifacts.entry) {
+            let file = manager.source_map().file(entry);
+            let resolution = artifacts.resolution.as_ref();
+            let tokens = resolver::semantic_tokens(file, storage, resolution);
+            match format.as_deref() {
+                Some("html") => print!("{}", highlight::html(file, &tokens)),
+                _ => print!("{}", highlight::ansi(file, &tokens)),
+            }
+            break;
+        }
+        if let (true, Some(entry), Some(resolution)) = (doc, artifacts.entry, &artifacts.resolution)
+        {
+            let typing = artifacts.typing.as_ref();
+            let modules = doc::collect(&manager, resolution, typing, entry);
+            let dir = match (&output, &project.manifest) {
+                (Some(output), _) => PathBuf::from(output),
+                (None, Some(manifest)) => manifest.root.join("doc"),
+                (None, None) => PathBuf::from("doc"),
+            };
+            if let Err(err) = doc::write(&dir, &modules) {
+                eprintln!("ERROR: couldn't write to {}: {err}", dir.display());
+                return Err(Failure::Errors);
+            }
+            println!("Documented {} modules in {}", modules.len(), dir.display());
+            if errs.error_count() > 0 {
+                return Err(Failure::Errors);
+            }
+            break;
+        }
+        if tagging {
+            // named like the files that `ctags` and `etags` write
+            let etags = format.as_deref() == Some("etags");
+            // `-o -` writes them to stdout, relative to the current directory
+            let path = match output.as_deref() {
+                Some("-") => None,
+                Some(path) => Some(PathBuf::from(path)),
+                None => Some(PathBuf::from(if etags { "TAGS" } else { "tags" })),
+            };
+            let dir = path.as_deref().and_then(Path::parent);
+            let tags = tags::collect(&manager, dir.unwrap_or(Path::new("")));
+            let file = match etags {
+                true => tags::etags(&tags),
+                false => tags::ctags(&tags),
+            };
+            match path {
+                Some(path) => {
+                    if let Err(err) = std::fs::write(&path, file) {
+                        eprintln!("ERROR: couldn't write {}: {err}", path.display());
+                        return Err(Failure::Errors);
+                    }
+                    println!("Tagged {} defs in {}", tags.len(), path.display());
+                }
+                None => print!("{file}"),
+            }
+            if errs.error_count() > 0 {
+                return Err(Failure::Errors);
+            }
+            break;
+        }
+        if let (true, Some(resolution)) = (indexing, &artifacts.resolution) {
+            let typing = artifacts.typing.as_ref();
+            let symbols = index::collect(&manager, resolution, typing);
+            let dump = match format.as_deref() {
+                Some("scip") => index::scip(&manager, &symbols, &project.root),
+                _ => index::lsif(manager.source_map(), &symbols, &project.root).into_bytes(),
+            };
+            match output {
+                Some(path) => {
+                    if let Err(err) = std::fs::write(path, dump) {
+                        eprintln!("ERROR: couldn't write {path}: {err}");
+                        return Err(Failure::Errors);
+                    }
+                }
+                None => {
+                    if let Err(err) = std::io::stdout().write_all(&dump) {
+                        eprintln!("ERROR: couldn't write the index: {err}");
+                        return Err(Failure::Errors);
+                    }
+                }
+            }
+            if errs.error_count() > 0 {
+                return Err(Failure::Errors);
+            }
+            break;
+        }
+        if let (Some((offset, name)), Some(entry), Some(resolution)) =
+            (&rename, artifacts.entry, &artifacts.resolution)
+        {
+            if errs.error_count() > 0 {
+                eprintln!("ERROR: the program has errors, so not every use may be renamed");
+                return Err(Failure::Errors);
+            }
+            let offset = manager.source_map().offset(entry, *offset);
+            apply_rename(storage, &manager, resolution, offset, name)?;
+            break;
+        }
+        if fix && !artifacts.fixes.is_empty() {
+            let edits = artifacts.fixes.iter().flat_map(|fix| fix.0.iter().cloned());
+            let files = apply_edits(&manager, edits.collect())?;
+            println!("Applied {} fixes in {} files", artifacts.fixes.len(), files);
+            break;
+        }
+        if let (false, false, None, Some(entry), Some(resolution)) =
+            (run, build, emit, artifacts.entry, &artifacts.resolution)
+        {
+            let typing = artifacts.typing.as_ref();
+            report(storage, &manager, resolution, typing, entry, &queries);
+        }
+        if !watch || artifacts.entry.is_none() {
+            if errs.error_count() > 0 {
+                return Err(Failure::Errors);
+            }
+            break;
+        }
+        let modified = modified.get_or_insert_with(|| modification_times(&manager));
+
+        // poll for changes, and only reparse the files that changed
+        let changed = loop {
+            std::thread::sleep(Duration::from_millis(100));
+            let times = modification_times(&manager);
+            let changed = (0..modified.len())
+                .filter(|&i| times[i] != modified[i])
+                .map(|i| FileId(i as u32))
+                .collect::<Vec<_>>();
+            if !changed.is_empty() {
+                break changed;
+            }
+        };
+        let start = Instant::now();
+        let mut dirty = changed
+            .iter()
+            .filter_map(|&file| manager.update(file))
+            .flatten()
+            .collect::<Vec<_>>();
+        dirty.sort();
+        dirty.dedup();
+        *modified = modification_times(&manager);
+        println!(
+            "Reparsed {} of {} modules ({} affected) in {:?}",
+            changed.len(),
+            modified.len(),
+            dirty.len(),
+            start.elapsed()
+        );
+    }
+    Ok(())
+}
+
+fn modification_times(manager: &ParseManager) -> Vec<Option<SystemTime>> {
+    manager
+        .source_map()
+        .files()
+        .map(|(_, file)| {
+            std::fs::metadata(&file.path)
+                .and_then(|m| m.modified())
+                .ok()
+        })
+        .collect()
+}
+
+/// Renames the symbol at `offset` in every file it's named in.
+fn apply_rename(
+    storage: &StringStorage,
+    manager: &ParseManager,
+    resolution: &Resolution,
+    offset: u32,
+    new_name: &str,
+) -> Result<(), Failure> {
+    let source_map = manager.source_map();
+    let spans = match resolver::rename(source_map, storage, resolution, offset, new_name) {
+        Ok(spans) => spans,
+        Err(err) => {
+            eprintln!("ERROR: {err:?}");
+            return Err(Failure::Errors);
+        }
+    };
+    let edits = spans.iter().map(|&span| (span, new_name.to_string()));
+    let files = apply_edits(manager, edits.collect())?;
+    println!("Renamed {} occurrences in {} files", spans.len(), files);
+    Ok(())
+}
+
+/// Replaces each span with the text next to it, and writes the files that
+/// changed. Returns how many files that was.
+fn apply_edits(manager: &ParseManager, mut edits: Vec<(Span, String)>) -> Result<usize, Failure> {
+    let source_map = manager.source_map();
+    edits.sort_by_key(|(span, _)| span.start);
+    edits.dedup_by_key(|(span, _)| *span);
+    let mut files = edits
+        .iter()
+        .map(|(span, _)| source_map.lookup(span.start))
+        .collect::<Vec<_>>();
+    files.dedup();
+    for &id in &files {
+        let file = source_map.file(id);
+        let mut src = file.src.clone();
+        // later spans first, so the earlier ones stay where they are
+        for (span, text) in edits.iter().rev() {
+            if source_map.lookup(span.start) == id {
+                src.replace_range(file.range(*span), text);
+            }
+        }
+        if let Err(err) = std::fs::write(&file.path, src) {
+            eprintln!("ERROR: couldn't write {}: {err}", file.path.display());
+            return Err(Failure::Errors);
+        }
+    }
+    Ok(files.len())
+}
+
+/// The editor queries asked for on the command line, with offsets into the
+/// entry file.
+#[derive(Default)]
+struct Queries {
+    definition_at: Option<usize>,
+    references_at: Option<usize>,
+    hover_at: Option<usize>,
+    completions_at: Option<usize>,
+    semantic_tokens: bool,
+}
+
+impl Queries {
+    /// The offsets that the queries are at.
+    fn offsets(&self) -> impl Iterator<Item = usize> {
+        [
+            self.definition_at,
+            self.references_at,
+            self.hover_at,
+            self.completions_at,
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+fn report(
+    storage: &StringStorage,
+    manager: &ParseManager,
+    resolution: &Resolution,
+    typing: Option<&Typing>,
+    entry: FileId,
+    queries: &Queries,
+) {
+    // offsets given on the command line are relative to the entry file
+    let source_map = manager.source_map();
+    if let Some(offset) = queries.definition_at {
+        let global = source_map.offset(entry, offset);
+        match resolver::find_definition(resolution, global) {
+            Some(resolver::Definition::Symbol(span, id)) => {
+                let file = source_map.file(source_map.lookup(span.start));
+                let (line, col) = file.line_col(span.start);
+                println!(
+                    "Definition of `{}`: {}:{}:{}",
+                    resolution.symbol(id).name.0,
+                    file.path.display(),
+                    line,
+                    col
+                )
+            }
+            Some(resolver::Definition::Module(module)) => {
+                let file = source_map.file(module);
+                println!("Definition of module: {}", file.path.display())
+            }
+            None => println!("No definition at {offset}"),
+        }
+    }
+    if let Some(offset) = queries.references_at {
+        let global = source_map.offset(entry, offset);
+        match resolver::symbol_at(resolution, global) {
+            Some((_, id)) => {
+                println!("References to `{}`:", resolution.symbol(id).name.0);
+                for span in resolver::references(resolution, id) {
+                    let file = source_map.file(source_map.lookup(span.start));
+                    let (line, col) = file.line_col(span.start);
+                    println!("  {}:{}:{}", file.path.display(), line, col);
+                }
+            }
+            None => println!("No symbol at {offset}"),
+        }
+    }
+    if let Some(offset) = queries.hover_at {
+        let global = source_map.offset(entry, offset);
+        match resolver::hover(source_map, resolution, typing, global) {
+            Some(hover) => {
+                println!("Hover `{}`:", source_map.snippet(hover.span));
+                if let Some(span) = hover.definition {
+                    let file = source_map.file(source_map.lookup(span.start));
+                    let (line, col) = file.line_col(span.start);
+                    println!("  defined at {}:{}:{}", file.path.display(), line, col);
+                } else {
+                    println!("  builtin");
+                }
+                if let Some(ty) = hover.ty {
+                    println!("  :: {ty}");
+                }
+                for line in hover.doc.iter().flat_map(|doc| doc.lines()) {
+                    println!("{}", format!("  | {line}").trim_end());
+                }
+            }
+            None => println!("No symbol at {offset}"),
+        }
+    }
+    if let Some(offset) = queries.completions_at {
+        let global = source_map.offset(entry, offset);
+        println!("Completions:");
+        for completion in resolver::completions(source_map, resolution, typing, global) {
+            match completion.ty {
+                Some(ty) => println!("  {} :: {ty}", completion.name),
+                None => println!("  {}", completion.name),
+            }
+        }
+    }
+    if queries.semantic_tokens {
+        println!("Semantic tokens:");
+        let file = source_map.file(entry);
+        for token in resolver::semantic_tokens(file, storage, Some(resolution)) {
+            let (line, col) = file.line_col(token.span.start);
+            let declaration = if token.declaration {
+                " (declaration)"
+            } else {
+                ""
+            };
+            println!(
+                "  {line}:{col} {:?} `{}`{declaration}",
+                token.class,
+                source_map.snippet(token.span)
+            );
+        }
+    }
 }
@@ -0,0 +1,622 @@
+//! An on-disk cache of parsed files, so that unchanged files don't have to be
+//! parsed again by the next invocation of the compiler.
+//!
+//! Each entry is named after a hash of the file's contents and holds the
+//! file's AST in a compact binary encoding. Spans are stored relative to the
+//! start of the file and interned strings are interned again when an entry is
+//! read, so an entry can be used no matter where the file ends up in the
+//! [SourceMap](crate::source_map::SourceMap).
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    parser::*,
+    string_storage::StringStorage,
+    tokenizer::{Intern, Span},
+};
+
+/// Identifies the encoding. Bump this whenever the AST or its encoding changes.
+const MAGIC: &[u8] = b"RADIAST\x07";
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Cache {
+        Cache { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn entry(&self, src: &str) -> PathBuf {
+        let mut hash = Fnv::new();
+        hash.write(env!("CARGO_PKG_VERSION").as_bytes());
+        hash.write(MAGIC);
+        hash.write(src.as_bytes());
+        self.dir.join(format!("{:016x}.ast", hash.0))
+    }
+
+    /// Looks up the AST of a file with contents `src` that starts at `start`.
+    pub fn get<'s>(&self, src: &str, start: u32, storage: &'s StringStorage) -> Option<Module<'s>> {
+        let bytes = std::fs::read(self.entry(src)).ok()?;
+        let bytes = bytes.strip_prefix(MAGIC)?;
+
+        let mut decoder = Decoder {
+            bytes,
+            start,
+            storage,
+        };
+        let module = decoder.module()?;
+        decoder.bytes.is_empty().then_some(module)
+    }
+
+    /// Stores the AST of a file with contents `src` that starts at `start`.
+    pub fn put(&self, src: &str, start: u32, module: &Module) -> io::Result<()> {
+        let mut encoder = Encoder {
+            bytes: MAGIC.to_vec(),
+            start,
+        };
+        encoder.module(module);
+
+        std::fs::create_dir_all(&self.dir)?;
+        // write to a temporary file first so that a concurrent reader never sees
+        // a partially written entry
+        let entry = self.entry(src);
+        let tmp = entry.with_extension(format!("tmp{}", std::process::id()));
+        std::fs::write(&tmp, encoder.bytes)?;
+        std::fs::rename(tmp, entry)
+    }
+}
+
+/// The 64-bit FNV-1a hash, used because its output is stable across
+/// platforms and versions of Rust.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+struct Encoder {
+    bytes: Vec<u8>,
+    start: u32,
+}
+
+impl Encoder {
+    fn uint(&mut self, mut n: u64) {
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+
+    fn tag(&mut self, tag: u8) {
+        self.bytes.push(tag);
+    }
+
+    fn bool(&mut self, b: bool) {
+        self.tag(b as u8);
+    }
+
+    fn str(&mut self, s: &str) {
+        self.uint(s.len() as u64);
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+
+    fn span(&mut self, span: Span) {
+        self.uint((span.start - self.start) as u64);
+        self.uint((span.end - span.start) as u64);
+    }
+
+    fn module(&mut self, module: &Module) {
+        self.uint(module.uses.len() as u64);
+        for u in module.uses.iter() {
+            self.uint(u.path.len() as u64);
+            for segment in u.path.iter() {
+                self.str(segment.name.0);
+                self.span(segment.span);
+            }
+            self.span(u.span);
+        }
+        self.expr(&module.body);
+        self.uint(module.expansions.len() as u64);
+        for expansion in module.expansions.iter() {
+            self.str(expansion.name.0);
+            self.span(expansion.call);
+            self.span(expansion.definition);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        self.span(expr.span);
+        match &expr.kind {
+            ExprKind::Object(scope) => {
+                self.tag(0);
+                self.scope(scope);
+            }
+            ExprKind::Block(scope) => {
+                self.tag(1);
+                self.scope(scope);
+            }
+            ExprKind::Lambda { arg, body } => {
+                self.tag(2);
+                self.expr(arg);
+                self.expr(body);
+            }
+            ExprKind::BinOp { op, lhs, rhs } => {
+                self.tag(3);
+                self.tag(match op {
+                    BinOp::Equal => 0,
+                    BinOp::NotEqual => 1,
+                    BinOp::Gt => 2,
+                    BinOp::GtEq => 3,
+                    BinOp::Lt => 4,
+                    BinOp::LtEq => 5,
+                    BinOp::Add => 6,
+                    BinOp::Sub => 7,
+                    BinOp::Mul => 8,
+                    BinOp::Div => 9,
+                    BinOp::Mod => 10,
+                    BinOp::And => 11,
+                    BinOp::Or => 12,
+                });
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprKind::UnOp { op, arg } => {
+                self.tag(4);
+                self.tag(match op {
+                    UnOp::Not => 0,
+                    UnOp::Set => 1,
+                    UnOp::Val => 2,
+                    UnOp::Ref => 3,
+                    UnOp::Deref => 4,
+                });
+                self.expr(arg);
+            }
+            ExprKind::Access { expr, prop } => {
+                self.tag(5);
+                self.expr(expr);
+                match prop {
+                    AccessRhs::Prop(name) => {
+                        self.tag(0);
+                        self.str(name.0);
+                    }
+                    AccessRhs::Expr(expr) => {
+                        self.tag(1);
+                        self.expr(expr);
+                    }
+                }
+            }
+            ExprKind::Branch {
+                cond,
+                on_true,
+                on_false,
+            } => {
+                self.tag(6);
+                self.expr(cond);
+                self.expr(on_true);
+                self.bool(on_false.is_some());
+                if let Some(on_false) = on_false {
+                    self.expr(on_false);
+                }
+            }
+            ExprKind::Match { scrutinee, arms } => {
+                self.tag(16);
+                self.expr(scrutinee);
+                self.uint(arms.len() as u64);
+                for arm in arms.iter() {
+                    self.expr(&arm.pattern);
+                    self.expr(&arm.body);
+                }
+            }
+            ExprKind::Tuple { items } => {
+                self.tag(7);
+                self.uint(items.len() as u64);
+                for item in items.iter() {
+                    self.expr(item);
+                }
+            }
+            ExprKind::Apply { a, b } => {
+                self.tag(8);
+                self.expr(a);
+                self.expr(b);
+            }
+            ExprKind::TypeAssertion { a, b } => {
+                self.tag(9);
+                self.expr(a);
+                self.expr(b);
+            }
+            ExprKind::Arrow { arg, ret } => {
+                self.tag(13);
+                self.expr(arg);
+                self.expr(ret);
+            }
+            ExprKind::Declaration(ty) => {
+                self.tag(14);
+                self.expr(ty);
+            }
+            ExprKind::Trait {
+                param,
+                param_span,
+                defs,
+            } => {
+                self.tag(17);
+                self.str(param.0);
+                self.span(*param_span);
+                self.defs(defs);
+            }
+            ExprKind::Impl { ty, defs } => {
+                self.tag(18);
+                self.expr(ty);
+                self.defs(defs);
+            }
+            ExprKind::Variant(items) => {
+                self.tag(10);
+                self.uint(items.len() as u64);
+                for item in items.iter() {
+                    self.str(item.name.0);
+                    self.span(item.span);
+                    self.bool(item.value.is_some());
+                    if let Some(value) = &item.value {
+                        self.expr(value);
+                    }
+                }
+            }
+            ExprKind::Ident(name) => {
+                self.tag(11);
+                self.str(name.0);
+            }
+            ExprKind::Literal(lit) => {
+                self.tag(12);
+                match lit {
+                    Literal::Float(f) => {
+                        self.tag(0);
+                        self.bytes.extend_from_slice(&f.to_bits().to_le_bytes());
+                    }
+                    Literal::Integer(i) => {
+                        self.tag(1);
+                        self.uint(*i);
+                    }
+                    Literal::String(s) => {
+                        self.tag(2);
+                        self.str(s.0);
+                    }
+                    Literal::BigInteger(i) => {
+                        self.tag(3);
+                        self.str(i.0);
+                    }
+                }
+            }
+            ExprKind::MacroCall { name, args } => {
+                self.tag(15);
+                self.str(name.0);
+                self.uint(args.len() as u64);
+                for arg in args.iter() {
+                    self.expr(arg);
+                }
+            }
+        }
+    }
+
+    fn scope(&mut self, scope: &Scope) {
+        self.defs(&scope.defs);
+        self.uint(scope.body.len() as u64);
+        for expr in scope.body.iter() {
+            self.expr(expr);
+        }
+        self.bool(scope.trailing_semi);
+    }
+
+    fn defs(&mut self, defs: &[Def]) {
+        self.uint(defs.len() as u64);
+        for def in defs.iter() {
+            self.uint(def.attributes.len() as u64);
+            for attribute in def.attributes.iter() {
+                self.str(attribute.name.0);
+                self.bool(attribute.arg.is_some());
+                if let Some(arg) = attribute.arg {
+                    self.str(arg.0);
+                }
+                self.span(attribute.span);
+            }
+            self.str(def.name.0);
+            self.span(def.name_span);
+            self.bool(def.public);
+            self.expr(&def.value);
+            self.span(def.span);
+        }
+    }
+}
+
+/// Decodes what [Encoder] encodes. Every method returns `None` if the input
+/// is malformed, in which case the entry is ignored.
+struct Decoder<'b, 's> {
+    bytes: &'b [u8],
+    start: u32,
+    storage: &'s StringStorage,
+}
+
+impl<'b, 's> Decoder<'b, 's> {
+    fn uint(&mut self) -> Option<u64> {
+        let mut n = 0u64;
+        let mut shift = 0;
+        loop {
+            let (&byte, rest) = self.bytes.split_first()?;
+            self.bytes = rest;
+            n |= ((byte & 0x7f) as u64).checked_shl(shift)?;
+            if byte & 0x80 == 0 {
+                return Some(n);
+            }
+            shift += 7;
+        }
+    }
+
+    fn len(&mut self) -> Option<usize> {
+        let len = self.uint()? as usize;
+        // every item takes at least a byte, which stops a corrupt length from
+        // causing a huge allocation
+        (len <= self.bytes.len()).then_some(len)
+    }
+
+    fn tag(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.bytes.split_first()?;
+        self.bytes = rest;
+        Some(byte)
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        match self.tag()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    fn str(&mut self) -> Option<Intern<'s>> {
+        let len = self.len()?;
+        let (s, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        let s = std::str::from_utf8(s).ok()?;
+        Some(Intern(self.storage.intern(s.to_owned())))
+    }
+
+    fn span(&mut self) -> Option<Span> {
+        let start = self.start + self.uint()? as u32;
+        let end = start + self.uint()? as u32;
+        Some(Span { start, end })
+    }
+
+    fn boxed(&mut self) -> Option<Box<Expr<'s>>> {
+        Some(Box::new(self.expr()?))
+    }
+
+    fn module(&mut self) -> Option<Module<'s>> {
+        let uses = (0..self.len()?)
+            .map(|_| {
+                let path = (0..self.len()?)
+                    .map(|_| {
+                        Some(PathSegment {
+                            name: self.str()?,
+                            span: self.span()?,
+                        })
+                    })
+                    .collect::<Option<_>>()?;
+                Some(Use {
+                    path,
+                    span: self.span()?,
+                })
+            })
+            .collect::<Option<_>>()?;
+
+        let body = self.expr()?;
+        let expansions = (0..self.len()?)
+            .map(|_| {
+                Some(Expansion {
+                    name: self.str()?,
+                    call: self.span()?,
+                    definition: self.span()?,
+                })
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Module {
+            uses,
+            body,
+            expansions,
+        })
+    }
+
+    fn expr(&mut self) -> Option<Expr<'s>> {
+        let span = self.span()?;
+        let kind = match self.tag()? {
+            0 => ExprKind::Object(Box::new(self.scope()?)),
+            1 => ExprKind::Block(Box::new(self.scope()?)),
+            2 => ExprKind::Lambda {
+                arg: self.boxed()?,
+                body: self.boxed()?,
+            },
+            3 => ExprKind::BinOp {
+                op: match self.tag()? {
+                    0 => BinOp::Equal,
+                    1 => BinOp::NotEqual,
+                    2 => BinOp::Gt,
+                    3 => BinOp::GtEq,
+                    4 => BinOp::Lt,
+                    5 => BinOp::LtEq,
+                    6 => BinOp::Add,
+                    7 => BinOp::Sub,
+                    8 => BinOp::Mul,
+                    9 => BinOp::Div,
+                    10 => BinOp::Mod,
+                    11 => BinOp::And,
+                    12 => BinOp::Or,
+                    _ => return None,
+                },
+                lhs: self.boxed()?,
+                rhs: self.boxed()?,
+            },
+            4 => ExprKind::UnOp {
+                op: match self.tag()? {
+                    0 => UnOp::Not,
+                    1 => UnOp::Set,
+                    2 => UnOp::Val,
+                    3 => UnOp::Ref,
+                    4 => UnOp::Deref,
+                    _ => return None,
+                },
+                arg: self.boxed()?,
+            },
+            5 => ExprKind::Access {
+                expr: self.boxed()?,
+                prop: match self.tag()? {
+                    0 => AccessRhs::Prop(self.str()?),
+                    1 => AccessRhs::Expr(self.boxed()?),
+                    _ => return None,
+                },
+            },
+            6 => ExprKind::Branch {
+                cond: self.boxed()?,
+                on_true: self.boxed()?,
+                on_false: if self.bool()? {
+                    Some(self.boxed()?)
+                } else {
+                    None
+                },
+            },
+            7 => ExprKind::Tuple {
+                items: (0..self.len()?)
+                    .map(|_| self.expr())
+                    .collect::<Option<_>>()?,
+            },
+            8 => ExprKind::Apply {
+                a: self.boxed()?,
+                b: self.boxed()?,
+            },
+            9 => ExprKind::TypeAssertion {
+                a: self.boxed()?,
+                b: self.boxed()?,
+            },
+            10 => ExprKind::Variant(
+                (0..self.len()?)
+                    .map(|_| {
+                        Some(VariantItem {
+                            name: self.str()?,
+                            span: self.span()?,
+                            value: if self.bool()? {
+                                Some(self.expr()?)
+                            } else {
+                                None
+                            },
+                        })
+                    })
+                    .collect::<Option<_>>()?,
+            ),
+            11 => ExprKind::Ident(self.str()?),
+            12 => ExprKind::Literal(match self.tag()? {
+                0 => {
+                    let (bits, rest) = self.bytes.split_first_chunk::<8>()?;
+                    self.bytes = rest;
+                    Literal::Float(f64::from_bits(u64::from_le_bytes(*bits)))
+                }
+                1 => Literal::Integer(self.uint()?),
+                2 => Literal::String(self.str()?),
+                3 => Literal::BigInteger(self.str()?),
+                _ => return None,
+            }),
+            13 => ExprKind::Arrow {
+                arg: self.boxed()?,
+                ret: self.boxed()?,
+            },
+            14 => ExprKind::Declaration(self.boxed()?),
+            15 => ExprKind::MacroCall {
+                name: self.str()?,
+                args: (0..self.len()?)
+                    .map(|_| self.expr())
+                    .collect::<Option<_>>()?,
+            },
+            16 => ExprKind::Match {
+                scrutinee: self.boxed()?,
+                arms: (0..self.len()?)
+                    .map(|_| {
+                        Some(MatchArm {
+                            pattern: self.expr()?,
+                            body: self.expr()?,
+                        })
+                    })
+                    .collect::<Option<_>>()?,
+            },
+            17 => ExprKind::Trait {
+                param: self.str()?,
+                param_span: self.span()?,
+                defs: self.defs()?,
+            },
+            18 => ExprKind::Impl {
+                ty: self.boxed()?,
+                defs: self.defs()?,
+            },
+            _ => return None,
+        };
+
+        Some(Expr { kind, span })
+    }
+
+    fn scope(&mut self) -> Option<Scope<'s>> {
+        let defs = self.defs()?;
+        let body = (0..self.len()?)
+            .map(|_| self.expr())
+            .collect::<Option<_>>()?;
+
+        Some(Scope {
+            defs,
+            body,
+            trailing_semi: self.bool()?,
+        })
+    }
+
+    fn defs(&mut self) -> Option<Box<[Def<'s>]>> {
+        (0..self.len()?)
+            .map(|_| {
+                Some(Def {
+                    attributes: (0..self.len()?)
+                        .map(|_| {
+                            Some(Attribute {
+                                name: self.str()?,
+                                arg: if self.bool()? {
+                                    Some(self.str()?)
+                                } else {
+                                    None
+                                },
+                                span: self.span()?,
+                            })
+                        })
+                        .collect::<Option<_>>()?,
+                    name: self.str()?,
+                    name_span: self.span()?,
+                    public: self.bool()?,
+                    value: self.boxed()?,
+                    span: self.span()?,
+                })
+            })
+            .collect()
+    }
+}
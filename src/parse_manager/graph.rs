@@ -0,0 +1,113 @@
+use crate::{scc, source_map::FileId};
+
+use super::ParseManager;
+
+/// The modules of a compilation unit and the imports between them.
+///
+/// There is a node for every loaded file, including those that failed to
+/// parse, which have no edges. An edge from `a` to `b` means that `a`
+/// imports `b`.
+#[derive(Debug, Clone)]
+pub struct ModuleGraph {
+    /// Indexed by [FileId]. Sorted and without duplicates.
+    dependencies: Vec<Box<[FileId]>>,
+    dependents: Vec<Box<[FileId]>>,
+}
+
+impl ModuleGraph {
+    pub fn new(manager: &ParseManager) -> ModuleGraph {
+        let len = manager.modules.len();
+        let mut dependencies = vec![Vec::new(); len];
+        let mut dependents = vec![Vec::new(); len];
+
+        for module in manager.modules() {
+            for import in module.imports.iter() {
+                dependencies[module.file.0 as usize].push(import.module);
+                dependents[import.module.0 as usize].push(module.file);
+            }
+        }
+
+        let finish = |lists: Vec<Vec<FileId>>| {
+            lists
+                .into_iter()
+                .map(|mut list| {
+                    list.sort();
+                    list.dedup();
+                    list.into_boxed_slice()
+                })
+                .collect()
+        };
+
+        ModuleGraph {
+            dependencies: finish(dependencies),
+            dependents: finish(dependents),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.dependencies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dependencies.is_empty()
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = FileId> {
+        (0..self.len() as u32).map(FileId)
+    }
+
+    /// The modules that `file` imports.
+    pub fn dependencies(&self, file: FileId) -> &[FileId] {
+        &self.dependencies[file.0 as usize]
+    }
+
+    /// The modules that import `file`.
+    pub fn dependents(&self, file: FileId) -> &[FileId] {
+        &self.dependents[file.0 as usize]
+    }
+
+    /// Orders the modules so that each comes after everything it imports, or
+    /// returns `None` if there is a cycle.
+    pub fn topological_order(&self) -> Option<Vec<FileId>> {
+        self.strongly_connected_components()
+            .into_iter()
+            .map(|component| match *component {
+                [file] if !self.dependencies(file).contains(&file) => Some(file),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Groups the modules into sets that all (transitively) import each
+    /// other. Every module is in exactly one component, which only has more
+    /// than one module if they form a cycle.
+    ///
+    /// The components are ordered so that each comes after every component
+    /// it imports from, and the modules in each are sorted by [FileId].
+    pub fn strongly_connected_components(&self) -> Vec<Box<[FileId]>> {
+        scc::strongly_connected_components(self.len(), |i| {
+            self.dependencies[i].iter().map(|f| f.0 as usize)
+        })
+        .into_iter()
+        .map(|component| component.into_iter().map(|i| FileId(i as u32)).collect())
+        .collect()
+    }
+
+    /// Every module that imports `file`, directly or not, along with `file`
+    /// itself, in order of [FileId].
+    pub fn transitive_dependents(&self, file: FileId) -> Vec<FileId> {
+        let mut seen = vec![false; self.len()];
+        let mut stack = vec![file];
+        seen[file.0 as usize] = true;
+
+        while let Some(file) = stack.pop() {
+            for &dependent in self.dependents(file) {
+                if !std::mem::replace(&mut seen[dependent.0 as usize], true) {
+                    stack.push(dependent);
+                }
+            }
+        }
+
+        self.nodes().filter(|f| seen[f.0 as usize]).collect()
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    lints::{LintConfig, LintConfigError},
+    toml::{self, TomlError},
+};
+
+pub const MANIFEST_NAME: &str = "radi.toml";
+
+/// The contents of a project's `radi.toml`.
+///
+/// ```toml
+/// [project]
+/// name = "hello"
+/// source = "src"      # relative to the directory containing radi.toml
+/// entry = "main.radi" # relative to the source directory
+///
+/// [lints]
+/// shadowing = "allow"   # or "warn" or "deny"
+///
+/// [lints.naming]        # a lint with options
+/// level = "deny"
+/// values = "camelCase"
+///
+/// [build]               # each is overridden by the command line
+/// backend = "c"         # or "wasm", what `radi build` compiles to
+/// opt-level = 0         # or 1, where 0 doesn't fold constants
+/// prelude = false       # as with --no-prelude
+/// ```
+#[derive(Debug)]
+pub struct Manifest {
+    /// The directory containing the manifest.
+    pub root: PathBuf,
+    pub name: Option<String>,
+    pub source: PathBuf,
+    pub entry: PathBuf,
+    /// How the lints named in the `[lints]` table are configured.
+    pub lints: BTreeMap<String, LintConfig>,
+    pub build: BuildConfig,
+}
+
+/// The `[build]` table, where a key that isn't given is left to the command
+/// line and its defaults.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BuildConfig {
+    pub backend: Option<BuildBackend>,
+    pub opt_level: Option<u8>,
+    pub prelude: Option<bool>,
+}
+
+/// What `radi build` compiles to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildBackend {
+    Wasm,
+    C,
+}
+
+#[derive(Debug)]
+pub struct ManifestError {
+    pub path: PathBuf,
+    pub kind: ManifestErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ManifestErrorKind {
+    Io(io::Error),
+    Toml(TomlError),
+    /// A key had a value of the wrong type.
+    InvalidValue(&'static str),
+    /// A lint in the `[lints]` table is configured wrongly.
+    Lint(String, LintConfigError),
+}
+
+impl Manifest {
+    /// Finds the nearest `radi.toml` in `start` or any of its ancestors.
+    pub fn discover(start: &Path) -> Option<PathBuf> {
+        start
+            .ancestors()
+            .map(|dir| dir.join(MANIFEST_NAME))
+            .find(|path| path.is_file())
+    }
+
+    pub fn load(path: &Path) -> Result<Manifest, ManifestError> {
+        let err = |kind| ManifestError {
+            path: path.to_owned(),
+            kind,
+        };
+
+        let src = std::fs::read_to_string(path).map_err(|e| err(ManifestErrorKind::Io(e)))?;
+        let table = toml::parse(&src).map_err(|e| err(ManifestErrorKind::Toml(e)))?;
+        let project = table.get("project").and_then(|p| p.as_table());
+
+        let string = |key: &'static str| -> Result<Option<String>, ManifestError> {
+            match project.and_then(|p| p.get(key)) {
+                None => Ok(None),
+                Some(value) => match value.as_str() {
+                    Some(s) => Ok(Some(s.to_owned())),
+                    None => Err(err(ManifestErrorKind::InvalidValue(key))),
+                },
+            }
+        };
+
+        let mut lints = BTreeMap::new();
+        let lint_table = table.get("lints").and_then(|l| l.as_table());
+        for (name, value) in lint_table.into_iter().flatten() {
+            let config = LintConfig::parse(name, value)
+                .map_err(|e| err(ManifestErrorKind::Lint(name.clone(), e)))?;
+            lints.insert(name.clone(), config);
+        }
+
+        let build = table.get("build").and_then(|b| b.as_table());
+        let value = |key| build.and_then(|b| b.get(key));
+        let backend = match value("backend").map(|v| v.as_str()) {
+            None => None,
+            Some(Some("wasm")) => Some(BuildBackend::Wasm),
+            Some(Some("c")) => Some(BuildBackend::C),
+            Some(_) => return Err(err(ManifestErrorKind::InvalidValue("backend"))),
+        };
+        let opt_level = match value("opt-level").map(|v| v.as_integer()) {
+            None => None,
+            Some(Some(level @ 0..=1)) => Some(level as u8),
+            Some(_) => return Err(err(ManifestErrorKind::InvalidValue("opt-level"))),
+        };
+        let prelude = match value("prelude").map(|v| v.as_bool()) {
+            None => None,
+            Some(Some(prelude)) => Some(prelude),
+            Some(_) => return Err(err(ManifestErrorKind::InvalidValue("prelude"))),
+        };
+
+        Ok(Manifest {
+            root: path.parent().unwrap_or(Path::new("")).to_owned(),
+            name: string("name")?,
+            source: string("source")?.map_or_else(|| PathBuf::from("src"), PathBuf::from),
+            entry: string("entry")?.map_or_else(|| PathBuf::from("main.radi"), PathBuf::from),
+            lints,
+            build: BuildConfig {
+                backend,
+                opt_level,
+                prelude,
+            },
+        })
+    }
+
+    /// The directory that module paths are relative to.
+    pub fn source_dir(&self) -> PathBuf {
+        self.root.join(&self.source)
+    }
+
+    pub fn entry_path(&self) -> PathBuf {
+        self.source_dir().join(&self.entry)
+    }
+}
@@ -1,4 +1,656 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use rayon::prelude::*;
+
+mod cache;
+mod graph;
+mod manifest;
+mod source_provider;
+pub use cache::Cache;
+pub use graph::ModuleGraph;
+pub use manifest::*;
+pub use source_provider::*;
+
+pub const CACHE_DIR_NAME: &str = ".radi-cache";
+
+use crate::{
+    char_reader::StrCharReader,
+    errors::{CompilationError, CompilationErrorKind, Diagnostic, ErrorStream},
+    parser::{self, ParseError, PathSegment},
+    resolver::prelude,
+    source_map::{FileId, SourceMap},
+    string_storage::StringStorage,
+    tokenizer::{Span, TokenBuffer, Tokens},
+};
+
+/// Where compilation of a project starts.
+#[derive(Debug)]
+pub struct Project {
+    /// The directory that module paths are relative to.
+    pub root: PathBuf,
+    pub entry: PathBuf,
+    pub manifest: Option<Manifest>,
+}
+
+impl Project {
+    /// Finds the project that `input` belongs to, or the project in the current
+    /// directory if there is no input.
+    ///
+    /// If a `radi.toml` is found in the input's directory or any of its
+    /// ancestors, the project's settings are read from it. The input is still
+    /// what's compiled if it's a file, and the manifest's entry only if the
+    /// input is the project's directory or there is no input. Either way the
+    /// entry's path is made absolute, like those of the modules it imports,
+    /// so that a module that imports it finds it already loaded. Without a
+    /// manifest, the input is compiled on its own with its directory as the
+    /// root. Returns `None` if there is neither an input nor a manifest.
+    pub fn discover(input: Option<&Path>) -> Result<Option<Project>, ManifestError> {
+        let file = input.filter(|input| !input.is_dir());
+        let start = match input {
+            Some(dir) if file.is_none() => dir,
+            _ => match file.and_then(Path::parent) {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            },
+        };
+        // relative paths have to be made absolute to be able to walk up past them
+        let start = std::path::absolute(start).unwrap_or_else(|_| start.to_owned());
+
+        if let Some(path) = Manifest::discover(&start) {
+            let manifest = Manifest::load(&path)?;
+            return Ok(Some(Project {
+                root: manifest.source_dir(),
+                entry: match file {
+                    Some(file) => std::path::absolute(file).unwrap_or_else(|_| file.to_owned()),
+                    None => manifest.entry_path(),
+                },
+                manifest: Some(manifest),
+            }));
+        }
+
+        Ok(input.map(|input| Project {
+            root: input.parent().unwrap_or(Path::new("")).to_owned(),
+            entry: input.to_owned(),
+            manifest: None,
+        }))
+    }
+
+    /// The project for source that isn't read from a file, such as source
+    /// piped to the compiler. The source is compiled as the file `name` in
+    /// the root of the project around the current directory, or in the
+    /// current directory if there is no project, so that its `use`s find the
+    /// modules there.
+    pub fn for_source(name: &str) -> Result<Project, ManifestError> {
+        Ok(match Project::discover(None)? {
+            Some(project) => Project {
+                entry: project.root.join(name),
+                ..project
+            },
+            None => Project {
+                root: PathBuf::new(),
+                entry: PathBuf::from(name),
+                manifest: None,
+            },
+        })
+    }
+
+    /// Where parsed files are cached between compilations. Only projects with
+    /// a manifest have a cache, kept next to the manifest.
+    pub fn cache_dir(&self) -> Option<PathBuf> {
+        self.manifest
+            .as_ref()
+            .map(|manifest| manifest.root.join(CACHE_DIR_NAME))
+    }
+}
+
 /// Manages the calling of the parser, including the use of multithreading where applicable.
-pub struct ParseManager {
+///
+/// Starting from an entry file, the manager follows each `use` to the file
+/// that it names and parses that too, building up the module graph of the
+/// compilation unit. A path `a.b.c` is looked up relative to the root
+/// directory, first as the module `a/b/c.radi` and otherwise as the item `c`
+/// of the module `a/b.radi`.
+pub struct ParseManager<'s> {
+    storage: &'s StringStorage,
+    errors: &'s ErrorStream<'s>,
+    root: PathBuf,
+    source_map: SourceMap,
+    /// Indexed by [FileId]. `None` for files that failed to parse.
+    modules: Vec<Option<Module<'s>>>,
+    cache: Option<Cache>,
+    sources: Box<dyn SourceProvider + 's>,
+    /// The prelude module, once it's loaded.
+    prelude: Option<FileId>,
+}
+
+#[derive(Debug)]
+pub struct Module<'s> {
+    pub file: FileId,
+    /// The dotted path that the module is imported by.
+    pub name: String,
+    pub ast: parser::Module<'s>,
+    pub imports: Box<[Import<'s>]>,
+}
+
+/// A `use` whose path has been resolved to a module.
+#[derive(Debug)]
+pub struct Import<'s> {
+    pub module: FileId,
+    /// The item imported from the module, or `None` if the `use` imports
+    /// the module itself.
+    pub item: Option<PathSegment<'s>>,
+    /// The segment of the path that gives the import its name.
+    pub name: PathSegment<'s>,
+    /// The span of the segment of the path that names the module.
+    pub module_span: Span,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub struct ModuleError {
+    pub kind: ModuleErrorKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ModuleErrorKind {
+    /// No file exists for the module at the given dotted path.
+    NotFound(String),
+    /// Modules import each other in a cycle. The edges are given in order,
+    /// starting from the module that was loaded first.
+    Cycle(Box<[CycleEdge]>),
+    /// The file at the path doesn't fit in the source map, which holds at
+    /// most 4 GiB of source.
+    TooLarge(PathBuf),
+    /// The file at the path couldn't be read, as the entry file or as a
+    /// module that is used.
+    Unreadable(PathBuf, io::Error),
+}
+
+/// A `use` in `from` that imports `to`.
+#[derive(Debug)]
+pub struct CycleEdge {
+    pub from: String,
+    pub to: String,
+    pub span: Span,
+}
+
+impl<'s> ParseManager<'s> {
+    pub fn new(
+        storage: &'s StringStorage,
+        errors: &'s ErrorStream<'s>,
+        root: impl Into<PathBuf>,
+    ) -> ParseManager<'s> {
+        ParseManager {
+            storage,
+            errors,
+            root: root.into(),
+            source_map: SourceMap::new(),
+            modules: Vec::new(),
+            cache: None,
+            sources: Box::new(FileSystem),
+            prelude: None,
+        }
+    }
+
+    /// Keeps the ASTs of parsed files in `cache`, and takes files that
+    /// haven't changed since they were cached from it instead of parsing them.
+    pub fn with_cache(mut self, cache: Cache) -> ParseManager<'s> {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Reads sources from `sources` instead of from disk.
+    pub fn with_sources(mut self, sources: impl SourceProvider + 's) -> ParseManager<'s> {
+        self.sources = Box::new(sources);
+        self
+    }
+
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    pub fn module(&self, file: FileId) -> Option<&Module<'s>> {
+        self.modules.get(file.0 as usize)?.as_ref()
+    }
+
+    /// Iterates over the successfully parsed modules, in the order their
+    /// files were loaded.
+    pub fn modules(&self) -> impl Iterator<Item = &Module<'s>> {
+        self.modules.iter().flatten()
+    }
+
+    /// The graph of imports between the loaded modules.
+    pub fn graph(&self) -> ModuleGraph {
+        ModuleGraph::new(self)
+    }
+
+    /// Loads and parses the file at `path` along with everything it imports.
+    ///
+    /// Files are parsed in waves: first the entry file, then everything it
+    /// imports, then everything those import, and so on. The files in each
+    /// wave are parsed in parallel, but are numbered and have their errors
+    /// reported in the order their imports appear, so the result doesn't
+    /// depend on how the threads are scheduled.
+    ///
+    /// Returns `None` if the file itself couldn't be read; errors in the file
+    /// or its imports are reported to the error stream.
+    pub fn load(&mut self, path: &Path) -> Option<FileId> {
+        let name = self.module_name(path);
+
+        let mut wave = Vec::new();
+        let entry = self.register(path.to_owned(), name, None, &mut wave)?;
+        self.parse_waves(wave);
+        self.check_cycles();
+
+        Some(entry)
+    }
+
+    /// Loads every `.radi` file under the root along with everything it
+    /// imports, as [ParseManager::load] does for one file, for queries that
+    /// have to see the whole project rather than what the entry uses, such
+    /// as a rename. Hidden directories, like the cache, are skipped, as are
+    /// files already loaded under another path.
+    pub fn load_project(&mut self) {
+        let mut paths = Vec::new();
+        find_sources(&self.root, &mut paths);
+        paths.sort();
+        let loaded = self
+            .source_map
+            .files()
+            .filter_map(|(_, file)| std::fs::canonicalize(&file.path).ok())
+            .collect::<Vec<_>>();
+
+        let mut wave = Vec::new();
+        for path in paths {
+            if std::fs::canonicalize(&path).is_ok_and(|path| loaded.contains(&path)) {
+                continue;
+            }
+            let name = self.module_name(&path);
+            self.register(path, name, None, &mut wave);
+        }
+        self.parse_waves(wave);
+        self.check_cycles();
+    }
+
+    /// Loads the prelude module, whose source is built into the compiler
+    /// rather than read from the source provider. It is only ever loaded
+    /// once, and can't import anything.
+    pub fn load_prelude(&mut self) -> FileId {
+        if let Some(file) = self.prelude {
+            return file;
+        }
+
+        let file = self
+            .source_map
+            .add(PathBuf::from(prelude::PATH), prelude::SOURCE.to_string());
+        self.modules.push(None);
+        self.parse_waves(vec![(file, "prelude".to_string())]);
+        self.prelude = Some(file);
+        file
+    }
+
+    /// The prelude module, if it has been loaded.
+    pub fn prelude(&self) -> Option<FileId> {
+        self.prelude
+    }
+
+    /// Reads `file` again and, if it changed, parses it along with any new
+    /// modules it imports. Every other module is left as it was.
+    ///
+    /// Returns the modules that have to be checked again as a result: `file`
+    /// and everything that imports it, directly or not. This is empty if the
+    /// file didn't change, and `None` if it couldn't be read or no longer
+    /// fits in the source map.
+    pub fn update(&mut self, file: FileId) -> Option<Vec<FileId>> {
+        let path = &self.source_map.file(file).path;
+        let src = match self.sources.read(path) {
+            Ok(src) => src,
+            Err(err) => {
+                self.errors.error((err, None));
+                return None;
+            }
+        };
+        if src == self.source_map.file(file).src {
+            return Some(Vec::new());
+        }
+        if !self.source_map.has_room(src.len()) {
+            self.too_large(self.source_map.file(file).path.clone(), None);
+            return None;
+        }
+
+        self.source_map.replace(file, src);
+        let name = match self.modules[file.0 as usize].take() {
+            Some(module) => module.name,
+            None => self.module_name(&self.source_map.file(file).path),
+        };
+        self.parse_waves(vec![(file, name)]);
+        self.check_cycles();
+
+        Some(self.graph().transitive_dependents(file))
+    }
+
+    /// Parses each wave of files in turn, with each wave made up of the
+    /// modules first imported by the one before it.
+    fn parse_waves(&mut self, mut wave: Vec<(FileId, String)>) {
+        while !wave.is_empty() {
+            let parsed = self.parse_files(&wave);
+
+            let mut next = Vec::new();
+            for ((file, name), (result, diagnostics)) in wave.into_iter().zip(parsed) {
+                if let Ok(ast) = &result {
+                    self.errors.expanded(&ast.expansions);
+                }
+                for diagnostic in diagnostics {
+                    self.errors.emit(diagnostic);
+                }
+
+                let ast = match result {
+                    Ok(ast) => ast,
+                    Err(err) => {
+                        self.errors.error(err);
+                        continue;
+                    }
+                };
+
+                let imports = ast
+                    .uses
+                    .iter()
+                    .filter_map(|u| self.import(u, &mut next))
+                    .collect();
+
+                self.modules[file.0 as usize] = Some(Module {
+                    file,
+                    name,
+                    ast,
+                    imports,
+                });
+            }
+
+            wave = next;
+        }
+    }
+
+    /// Reads the file at `path` and adds it to the wave to be parsed, unless
+    /// it has already been loaded.
+    fn register(
+        &mut self,
+        path: PathBuf,
+        name: String,
+        span: Option<Span>,
+        wave: &mut Vec<(FileId, String)>,
+    ) -> Option<FileId> {
+        if let Some(file) = self.source_map.find(&path) {
+            return Some(file);
+        }
+
+        let src = match self.sources.read(&path) {
+            Ok(src) => src,
+            Err(err) => {
+                self.errors.error(CompilationError {
+                    kind: CompilationErrorKind::Module(ModuleErrorKind::Unreadable(path, err)),
+                    span,
+                });
+                return None;
+            }
+        };
+
+        if !self.source_map.has_room(src.len()) {
+            self.too_large(path, span);
+            return None;
+        }
+        let file = self.source_map.add(path, src);
+        self.modules.push(None);
+        wave.push((file, name));
+
+        Some(file)
+    }
+
+    /// Reports that the file at `path` can't be added to the source map.
+    fn too_large(&self, path: PathBuf, span: Option<Span>) {
+        self.errors.error(CompilationError {
+            kind: CompilationErrorKind::Module(ModuleErrorKind::TooLarge(path)),
+            span,
+        });
+    }
+
+    /// Parses each of the given files on the threads of [pool]. The results
+    /// are in the same order as the files.
+    ///
+    /// Even a single file is parsed on one of those threads, since the parser
+    /// needs more stack for deeply nested code than the calling thread may
+    /// have.
+    fn parse_files(&self, files: &[(FileId, String)]) -> Vec<ParseResult<'s>> {
+        // the manager itself can't be shared between threads because of its error stream
+        let (storage, source_map, cache) = (self.storage, &self.source_map, self.cache.as_ref());
+
+        pool().install(|| {
+            files
+                .par_iter()
+                .map(|(file, _)| parse_file(storage, source_map, cache, *file))
+                .collect()
+        })
+    }
+
+    fn import(
+        &mut self,
+        u: &parser::Use<'s>,
+        wave: &mut Vec<(FileId, String)>,
+    ) -> Option<Import<'s>> {
+        let segments = u.path.iter().map(|s| s.name.0).collect::<Vec<_>>();
+        let (&last, parent) = u.path.split_last().unwrap();
+
+        let as_module = self.module_path(&segments);
+        if self.sources.is_file(&as_module) {
+            let module = self.register(as_module, segments.join("."), Some(u.span), wave)?;
+            return Some(Import {
+                module,
+                item: None,
+                name: last,
+                module_span: last.span,
+                span: u.span,
+            });
+        }
+
+        let as_item = self.module_path(&segments[..parent.len()]);
+        if !parent.is_empty() && self.sources.is_file(&as_item) {
+            let module = self.register(
+                as_item,
+                segments[..parent.len()].join("."),
+                Some(u.span),
+                wave,
+            )?;
+            return Some(Import {
+                module,
+                item: Some(last),
+                name: last,
+                module_span: parent.last().unwrap().span,
+                span: u.span,
+            });
+        }
+
+        self.errors.error(ModuleError {
+            kind: ModuleErrorKind::NotFound(segments.join(".")),
+            span: u.span,
+        });
+        None
+    }
+
+    /// Reports every cycle of imports, found by a depth-first search from each
+    /// module in the order they were loaded.
+    fn check_cycles(&self) {
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        enum State {
+            Unvisited,
+            OnStack,
+            Done,
+        }
+
+        fn visit(
+            manager: &ParseManager,
+            file: FileId,
+            states: &mut [State],
+            stack: &mut Vec<(FileId, Option<Span>)>,
+        ) {
+            states[file.0 as usize] = State::OnStack;
+
+            for import in manager.module(file).map_or(&[][..], |m| &m.imports) {
+                match states[import.module.0 as usize] {
+                    State::Unvisited => {
+                        stack.push((import.module, Some(import.span)));
+                        visit(manager, import.module, states, stack);
+                        stack.pop();
+                    }
+                    State::OnStack => manager.report_cycle(stack, import),
+                    State::Done => {}
+                }
+            }
+
+            states[file.0 as usize] = State::Done;
+        }
+
+        let mut states = vec![State::Unvisited; self.modules.len()];
+        for module in self.modules() {
+            if states[module.file.0 as usize] == State::Unvisited {
+                visit(
+                    self,
+                    module.file,
+                    &mut states,
+                    &mut vec![(module.file, None)],
+                );
+            }
+        }
+    }
+
+    /// Reports the cycle closed by `import`, which leads back to a module on `stack`.
+    fn report_cycle(&self, stack: &[(FileId, Option<Span>)], import: &Import) {
+        let name = |file: FileId| {
+            self.module(file)
+                .map_or_else(String::new, |m| m.name.clone())
+        };
+
+        let start = stack.iter().position(|(f, _)| *f == import.module).unwrap();
+        let cycle = &stack[start..];
+        let edges = cycle
+            .windows(2)
+            .map(|pair| CycleEdge {
+                from: name(pair[0].0),
+                to: name(pair[1].0),
+                span: pair[1].1.unwrap(),
+            })
+            .chain(std::iter::once(CycleEdge {
+                from: name(cycle.last().unwrap().0),
+                to: name(import.module),
+                span: import.span,
+            }))
+            .collect();
+
+        self.errors.error(ModuleError {
+            kind: ModuleErrorKind::Cycle(edges),
+            span: import.span,
+        });
+    }
+
+    /// The dotted path of the module at `path`.
+    fn module_name(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .with_extension("")
+            .iter()
+            .map(|s| s.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    fn module_path(&self, segments: &[&str]) -> PathBuf {
+        let mut path = self.root.clone();
+        path.extend(segments);
+        path.set_extension("radi");
+        path
+    }
+}
+
+/// The size in bytes of the largest file that is lexed in full before it's
+/// parsed. The tokens of larger files are read as they're parsed, so that
+/// they aren't all held at once.
+const PRELEX_LIMIT: usize = 1024 * 1024;
+
+/// The threads that files are parsed on, each with [parser::STACK_SIZE] of
+/// stack. There is one per core, started the first time files are parsed
+/// and kept for every wave and compilation after, instead of starting new
+/// ones each time.
+fn pool() -> &'static rayon::ThreadPool {
+    static POOL: OnceLock<rayon::ThreadPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        rayon::ThreadPoolBuilder::new()
+            .stack_size(parser::STACK_SIZE)
+            .thread_name(|i| format!("radi-parse-{i}"))
+            .build()
+            .unwrap()
+    })
+}
+
+fn parse_file<'s>(
+    storage: &'s StringStorage,
+    source_map: &SourceMap,
+    cache: Option<&Cache>,
+    file: FileId,
+) -> ParseResult<'s> {
+    let source = source_map.file(file);
+    if let Some(ast) = cache.and_then(|c| c.get(&source.src, source.start, storage)) {
+        return (Ok(ast), Vec::new());
+    }
+
+    let tokens = Tokens::of(
+        StrCharReader::starting_at(&source.src, source.start),
+        storage,
+    );
+    let errors = ErrorStream::buffered();
+    let result = match source.src.len() <= PRELEX_LIMIT {
+        true => parser::parse_parallel(TokenBuffer::lex(tokens), &errors),
+        false => parser::parse(tokens, &errors),
+    };
+    let diagnostics = errors.into_diagnostics();
+
+    // only files without any diagnostics are cached, since those would be
+    // lost when the file is next taken from the cache
+    if let (Some(cache), Ok(ast), true) = (cache, &result, diagnostics.is_empty()) {
+        // failing to write to the cache only makes the next compilation slower
+        let _ = cache.put(&source.src, source.start, ast);
+    }
+
+    (result.map_err(|err| err.at_end(source.end())), diagnostics)
+}
+
+type ParseResult<'s> = (
+    Result<parser::Module<'s>, ParseError<'s>>,
+    Vec<Diagnostic<'s>>,
+);
 
+/// Adds the paths of the `.radi` files in `dir` and the directories in it to
+/// `out`, leaving out hidden files and directories.
+fn find_sources(dir: &Path, out: &mut Vec<PathBuf>) {
+    let listed = match dir.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => dir,
+    };
+    let Ok(entries) = std::fs::read_dir(listed) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        if name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = dir.join(name);
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => find_sources(&path, out),
+            Ok(_) if path.extension().is_some_and(|ext| ext == "radi") => out.push(path),
+            _ => {}
+        }
+    }
 }
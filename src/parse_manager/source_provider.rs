@@ -0,0 +1,99 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use rustc_hash::FxHashMap;
+
+/// Where the [ParseManager](super::ParseManager) gets the contents of files
+/// from. Besides the file system, sources can come from anywhere that maps
+/// paths to text, such as the unsaved buffers of an editor.
+pub trait SourceProvider: Sync {
+    fn read(&self, path: &Path) -> io::Result<String>;
+
+    /// Whether there is a file at `path`, which is how the manager decides
+    /// what a `use` refers to.
+    fn is_file(&self, path: &Path) -> bool;
+}
+
+/// Reads sources from disk.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileSystem;
+
+impl SourceProvider for FileSystem {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+}
+
+/// Has no files at all, for source that mustn't read anything from disk,
+/// such as the programs that `radi serve` is sent.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoFiles;
+
+impl SourceProvider for NoFiles {
+    fn read(&self, _: &Path) -> io::Result<String> {
+        Err(io::ErrorKind::NotFound.into())
+    }
+
+    fn is_file(&self, _: &Path) -> bool {
+        false
+    }
+}
+
+/// Serves some files from memory, and the rest from another provider.
+#[derive(Debug, Default)]
+pub struct Overlay<P> {
+    base: P,
+    files: FxHashMap<PathBuf, String>,
+}
+
+impl<P: SourceProvider> Overlay<P> {
+    pub fn new(base: P) -> Overlay<P> {
+        Overlay {
+            base,
+            files: FxHashMap::default(),
+        }
+    }
+
+    /// Makes `src` the contents of the file at `path`, whether or not it
+    /// exists in the base provider.
+    pub fn insert(&mut self, path: impl Into<PathBuf>, src: String) {
+        self.files.insert(path.into(), src);
+    }
+
+    /// Goes back to reading the file at `path` from the base provider.
+    pub fn remove(&mut self, path: &Path) -> Option<String> {
+        self.files.remove(path)
+    }
+}
+
+impl<P: SourceProvider> SourceProvider for Overlay<P> {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        match self.files.get(path) {
+            Some(src) => Ok(src.clone()),
+            None => self.base.read(path),
+        }
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.contains_key(path) || self.base.is_file(path)
+    }
+}
+
+/// Lets the sources be changed between compilations while a manager reads
+/// from them.
+impl<P: SourceProvider + Send> SourceProvider for &Mutex<P> {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        self.lock().unwrap().read(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.lock().unwrap().is_file(path)
+    }
+}
@@ -1,12 +1,34 @@
 use crate::tokenizer::{Intern, Span};
 
+/// A parsed source file.
 #[derive(Debug)]
+pub struct Module<'s> {
+    pub uses: Box<[Use<'s>]>,
+    pub body: Expr<'s>,
+    /// The macro calls that were expanded in the module, which are gone
+    /// from its body.
+    pub expansions: Box<[Expansion<'s>]>,
+}
+
+#[derive(Debug)]
+pub struct Use<'s> {
+    pub path: Box<[PathSegment<'s>]>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PathSegment<'s> {
+    pub name: Intern<'s>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
 pub struct Expr<'s> {
     pub kind: ExprKind<'s>,
     pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ExprKind<'s> {
     Object(Box<Scope<'s>>),
     Block(Box<Scope<'s>>),
@@ -32,6 +54,12 @@ pub enum ExprKind<'s> {
         on_true: Box<Expr<'s>>,
         on_false: Option<Box<Expr<'s>>>,
     },
+    /// `match scrutinee { pattern => body; ... }`, which evaluates the body
+    /// of the first arm whose pattern the scrutinee matches.
+    Match {
+        scrutinee: Box<Expr<'s>>,
+        arms: Box<[MatchArm<'s>]>,
+    },
     Tuple {
         items: Box<[Expr<'s>]>,
     },
@@ -43,40 +71,145 @@ pub enum ExprKind<'s> {
         a: Box<Expr<'s>>,
         b: Box<Expr<'s>>,
     },
+    /// The function type `arg -> ret`, which can only be written where a
+    /// type is expected.
+    Arrow {
+        arg: Box<Expr<'s>>,
+        ret: Box<Expr<'s>>,
+    },
+    /// The value of a def without a body, `def name :: T;`, holding its type.
+    Declaration(Box<Expr<'s>>),
+    /// The value of a `trait Name(param) { defs }`, whose defs are the
+    /// declarations of its methods, written in terms of `param`.
+    Trait {
+        param: Intern<'s>,
+        param_span: Span,
+        defs: Box<[Def<'s>]>,
+    },
+    /// The value of an `impl Name(ty) { defs }`, which is a def named after
+    /// the trait it implements.
+    Impl {
+        ty: Box<Expr<'s>>,
+        defs: Box<[Def<'s>]>,
+    },
     Variant(Box<[VariantItem<'s>]>),
     Ident(Intern<'s>),
     Literal(Literal<'s>),
+    /// A call of a macro, `name!(args)`, which only exists until the macros
+    /// of the module are expanded.
+    MacroCall {
+        name: Intern<'s>,
+        args: Box<[Expr<'s>]>,
+    },
 }
 
-#[derive(Debug)]
+impl ExprKind<'_> {
+    /// The name of the variant, as it's written here.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExprKind::Object(_) => "Object",
+            ExprKind::Block(_) => "Block",
+            ExprKind::Lambda { .. } => "Lambda",
+            ExprKind::BinOp { .. } => "BinOp",
+            ExprKind::UnOp { .. } => "UnOp",
+            ExprKind::Access { .. } => "Access",
+            ExprKind::Branch { .. } => "Branch",
+            ExprKind::Match { .. } => "Match",
+            ExprKind::Tuple { .. } => "Tuple",
+            ExprKind::Apply { .. } => "Apply",
+            ExprKind::TypeAssertion { .. } => "TypeAssertion",
+            ExprKind::Arrow { .. } => "Arrow",
+            ExprKind::Declaration(_) => "Declaration",
+            ExprKind::Trait { .. } => "Trait",
+            ExprKind::Impl { .. } => "Impl",
+            ExprKind::Variant(_) => "Variant",
+            ExprKind::Ident(_) => "Ident",
+            ExprKind::Literal(_) => "Literal",
+            ExprKind::MacroCall { .. } => "MacroCall",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Scope<'s> {
     pub defs: Box<[Def<'s>]>,
     pub body: Box<[Expr<'s>]>,
     pub trailing_semi: bool,
 }
 
-#[derive(Debug)]
+/// An arm of a `match`. Its pattern is parsed as an expression, like the
+/// parameter of a lambda, and is made sense of by the resolver.
+#[derive(Debug, Clone)]
+pub struct MatchArm<'s> {
+    pub pattern: Expr<'s>,
+    pub body: Expr<'s>,
+}
+
+#[derive(Debug, Clone)]
 pub struct VariantItem<'s> {
     pub name: Intern<'s>,
     pub value: Option<Expr<'s>>,
     pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Def<'s> {
+    pub attributes: Box<[Attribute<'s>]>,
     pub name: Intern<'s>,
+    pub name_span: Span,
+    pub public: bool,
     pub value: Box<Expr<'s>>,
     pub span: Span,
 }
 
+/// An `@name` or `@name("arg")` written before a def.
+#[derive(Debug, Clone)]
+pub struct Attribute<'s> {
+    pub name: Intern<'s>,
+    pub arg: Option<Intern<'s>>,
+    pub span: Span,
+}
+
+/// One arm of a macro, `macro name(params) { template }`. A call of the macro
+/// is replaced by the template of the first of its arms whose parameters
+/// match the arguments.
 #[derive(Debug)]
+pub struct Macro<'s> {
+    pub name: Intern<'s>,
+    pub params: Box<[MacroParam<'s>]>,
+    pub template: Expr<'s>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum MacroParam<'s> {
+    /// Matches any argument, which takes the place of the name in the
+    /// template.
+    Bind(Intern<'s>),
+    /// Matches an argument that is the same literal.
+    Literal(Literal<'s>),
+}
+
+/// Where a macro was expanded: the call of it and the arm it was expanded
+/// from. The parts of the expansion that come from the template keep their
+/// spans in the arm, and the arguments keep their spans in the call.
+#[derive(Debug, Clone, Copy)]
+pub struct Expansion<'s> {
+    pub name: Intern<'s>,
+    pub call: Span,
+    pub definition: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Literal<'s> {
     Float(f64),
     Integer(u64),
+    /// An integer literal too large for a `u64`, as its digits.
+    BigInteger(Intern<'s>),
     String(Intern<'s>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BinOp {
     Equal,
     NotEqual,
@@ -93,7 +226,36 @@ pub enum BinOp {
     Or,
 }
 
-#[derive(Debug)]
+impl BinOp {
+    /// How the operator is written.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Equal => "=",
+            BinOp::NotEqual => "!=",
+            BinOp::Gt => ">",
+            BinOp::GtEq => ">=",
+            BinOp::Lt => "<",
+            BinOp::LtEq => "<=",
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+        }
+    }
+
+    /// Whether the operator compares its operands, resulting in a `Bool`.
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinOp::Equal | BinOp::NotEqual | BinOp::Gt | BinOp::GtEq | BinOp::Lt | BinOp::LtEq
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum UnOp {
     Not,
     Set,
@@ -102,7 +264,7 @@ pub enum UnOp {
     Deref,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AccessRhs<'s> {
     Prop(Intern<'s>),
     Expr(Box<Expr<'s>>),
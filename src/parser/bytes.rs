@@ -0,0 +1,80 @@
+//! Parsing input that can be any bytes at all, as a fuzzer gives, with
+//! [parse_bytes].
+
+use std::thread;
+
+use super::*;
+use crate::errors::DiagnosticRecord;
+
+/// What came of parsing some bytes with [parse_bytes].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOutcome {
+    /// The input as it was parsed, which is the bytes with each run of them
+    /// that isn't UTF-8 replaced by U+FFFD. Spans are offsets into it.
+    pub source: String,
+    /// The tree in the form of [sexp], if the input parsed.
+    pub ast: Option<String>,
+    /// What was reported while parsing, starting with where the input isn't
+    /// UTF-8.
+    pub diagnostics: Vec<DiagnosticRecord>,
+}
+
+/// Parses `bytes` as a module of its own, as [parse_str] does, whatever they
+/// are. It never panics or overflows the stack: bytes that aren't UTF-8 are
+/// reported and parsed as U+FFFD, expressions are only nested as deeply as
+/// [MAX_DEPTH] allows, before and after macros are expanded, and the parse
+/// runs on a thread with [STACK_SIZE] of stack, whatever thread calls this.
+/// The fuzz targets in `fuzz/` call it.
+pub fn parse_bytes(bytes: &[u8]) -> ParseOutcome {
+    thread::scope(|scope| {
+        let parse = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, || parse_outcome(bytes));
+        match parse {
+            Ok(handle) => handle.join().unwrap(),
+            // without a thread to spare, this one will have to do
+            Err(_) => parse_outcome(bytes),
+        }
+    })
+}
+
+fn parse_outcome(bytes: &[u8]) -> ParseOutcome {
+    let storage = StringStorage::new();
+    let errors = ErrorStream::buffered();
+    let mut source = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        source.push_str(chunk.valid());
+        if !chunk.invalid().is_empty() {
+            let start = source.len();
+            source.push(char::REPLACEMENT_CHARACTER);
+            let span = Span {
+                start: start as u32,
+                end: source.len() as u32,
+            };
+            errors.error(ParseError::new(ParseErrorKind::InvalidUtf8, Some(span)));
+        }
+    }
+
+    let ast = if u32::try_from(source.len()).is_err() {
+        errors.error(ParseError::new(ParseErrorKind::TooLarge, None));
+        None
+    } else {
+        match parse_str(&source, &storage, &errors) {
+            Ok(module) => Some(sexp(&module)),
+            Err(err) => {
+                errors.error(err);
+                None
+            }
+        }
+    };
+    let diagnostics = errors
+        .into_diagnostics()
+        .iter()
+        .map(|d| d.record())
+        .collect();
+    ParseOutcome {
+        source,
+        ast,
+        diagnostics,
+    }
+}
@@ -0,0 +1,207 @@
+//! Renders the syntax tree as a graph in Graphviz's DOT language. Each node
+//! is labelled with the kind of its expression and the source it was parsed
+//! from, and each edge with the part of its parent that the child is.
+
+use std::fmt::Write;
+
+use crate::source_map::SourceMap;
+
+use super::*;
+
+/// How many characters of an expression's source are shown in its node.
+const SNIPPET_LEN: usize = 32;
+
+/// The DOT graph of a parsed module.
+pub fn dot(module: &Module, source_map: &SourceMap) -> String {
+    let mut graph = Graph {
+        out: String::from("digraph ast {\n    node [shape=box, fontname=\"monospace\"];\n"),
+        nodes: 0,
+        source_map,
+    };
+    let root = graph.node("Module", None);
+    for item in module.uses.iter() {
+        let path = item.path.iter().map(|segment| segment.name.0);
+        let label = format!("Use {}", path.collect::<Vec<_>>().join("."));
+        let node = graph.node(&label, Some(item.span));
+        graph.edge(root, node, None);
+    }
+    graph.child(root, None, &module.body);
+    graph.out.push_str("}\n");
+    graph.out
+}
+
+struct Graph<'a> {
+    out: String,
+    nodes: usize,
+    source_map: &'a SourceMap,
+}
+
+impl Graph<'_> {
+    fn node(&mut self, label: &str, span: Option<Span>) -> usize {
+        let id = self.nodes;
+        self.nodes += 1;
+        let mut label = escape(label);
+        if let Some(span) = span.filter(|span| span.start < span.end) {
+            label.push_str("\\n");
+            label.push_str(&escape(&self.snippet(span)));
+        }
+        let _ = writeln!(self.out, "    n{id} [label=\"{label}\"];");
+        id
+    }
+
+    fn edge(&mut self, from: usize, to: usize, label: Option<&str>) {
+        let _ = match label {
+            Some(label) => writeln!(
+                self.out,
+                "    n{from} -> n{to} [label=\"{}\"];",
+                escape(label)
+            ),
+            None => writeln!(self.out, "    n{from} -> n{to};"),
+        };
+    }
+
+    /// The source of `span` on one line, shortened to [SNIPPET_LEN].
+    fn snippet(&self, span: Span) -> String {
+        let words = self.source_map.snippet(span).split_whitespace();
+        let snippet = words.collect::<Vec<_>>().join(" ");
+        match snippet.char_indices().nth(SNIPPET_LEN) {
+            Some((end, _)) => format!("{}…", &snippet[..end]),
+            None => snippet,
+        }
+    }
+
+    fn child(&mut self, parent: usize, label: Option<&str>, expr: &Expr) {
+        let child = self.expr(expr);
+        self.edge(parent, child, label);
+    }
+
+    fn defs(&mut self, parent: usize, defs: &[Def]) {
+        for def in defs.iter() {
+            let public = if def.public { "pub " } else { "" };
+            let node = self.node(&format!("{public}Def {}", def.name.0), None);
+            self.edge(parent, node, None);
+            self.child(node, None, &def.value);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> usize {
+        let label = match &expr.kind {
+            ExprKind::Object(_) => "Object".to_string(),
+            ExprKind::Block(_) => "Block".to_string(),
+            ExprKind::Lambda { .. } => "Lambda".to_string(),
+            ExprKind::BinOp { op, .. } => format!("BinOp {op:?}"),
+            ExprKind::UnOp { op, .. } => format!("UnOp {op:?}"),
+            ExprKind::Access {
+                prop: AccessRhs::Prop(name),
+                ..
+            } => format!("Access .{}", name.0),
+            ExprKind::Access { .. } => "Access".to_string(),
+            ExprKind::Branch { .. } => "Branch".to_string(),
+            ExprKind::Match { .. } => "Match".to_string(),
+            ExprKind::Tuple { .. } => "Tuple".to_string(),
+            ExprKind::Apply { .. } => "Apply".to_string(),
+            ExprKind::TypeAssertion { .. } => "TypeAssertion".to_string(),
+            ExprKind::Arrow { .. } => "Arrow".to_string(),
+            ExprKind::Declaration(_) => "Declaration".to_string(),
+            ExprKind::Trait { param, .. } => format!("Trait ({})", param.0),
+            ExprKind::Impl { .. } => "Impl".to_string(),
+            ExprKind::Variant(_) => "Variant".to_string(),
+            ExprKind::Ident(name) => format!("Ident {}", name.0),
+            ExprKind::Literal(_) => "Literal".to_string(),
+            ExprKind::MacroCall { name, .. } => format!("MacroCall {}!", name.0),
+        };
+        let id = self.node(&label, Some(expr.span));
+
+        match &expr.kind {
+            ExprKind::Object(scope) | ExprKind::Block(scope) => {
+                self.defs(id, &scope.defs);
+                for expr in scope.body.iter() {
+                    self.child(id, None, expr);
+                }
+            }
+            ExprKind::Trait { defs, .. } => self.defs(id, defs),
+            ExprKind::Impl { ty, defs } => {
+                self.child(id, Some("type"), ty);
+                self.defs(id, defs);
+            }
+            ExprKind::Lambda { arg, body } => {
+                self.child(id, Some("arg"), arg);
+                self.child(id, Some("body"), body);
+            }
+            ExprKind::BinOp { lhs, rhs, .. } => {
+                self.child(id, Some("lhs"), lhs);
+                self.child(id, Some("rhs"), rhs);
+            }
+            ExprKind::UnOp { arg, .. } | ExprKind::Declaration(arg) => {
+                self.child(id, None, arg);
+            }
+            ExprKind::Access { expr, prop } => {
+                self.child(id, None, expr);
+                if let AccessRhs::Expr(index) = prop {
+                    self.child(id, Some("index"), index);
+                }
+            }
+            ExprKind::Branch {
+                cond,
+                on_true,
+                on_false,
+            } => {
+                self.child(id, Some("cond"), cond);
+                self.child(id, Some("then"), on_true);
+                if let Some(on_false) = on_false {
+                    self.child(id, Some("else"), on_false);
+                }
+            }
+            ExprKind::Match { scrutinee, arms } => {
+                self.child(id, None, scrutinee);
+                for arm in arms.iter() {
+                    let node = self.node("Arm", None);
+                    self.edge(id, node, None);
+                    self.child(node, Some("pattern"), &arm.pattern);
+                    self.child(node, Some("body"), &arm.body);
+                }
+            }
+            ExprKind::Tuple { items } | ExprKind::MacroCall { args: items, .. } => {
+                for item in items.iter() {
+                    self.child(id, None, item);
+                }
+            }
+            ExprKind::Apply { a, b } => {
+                self.child(id, Some("fn"), a);
+                self.child(id, Some("arg"), b);
+            }
+            ExprKind::TypeAssertion { a, b } => {
+                self.child(id, None, a);
+                self.child(id, Some("type"), b);
+            }
+            ExprKind::Arrow { arg, ret } => {
+                self.child(id, Some("arg"), arg);
+                self.child(id, Some("ret"), ret);
+            }
+            ExprKind::Variant(items) => {
+                for item in items.iter() {
+                    let node = self.node(&format!("|{}", item.name.0), None);
+                    self.edge(id, node, None);
+                    if let Some(value) = &item.value {
+                        self.child(node, None, value);
+                    }
+                }
+            }
+            ExprKind::Ident(_) | ExprKind::Literal(_) => {}
+        }
+        id
+    }
+}
+
+/// Escapes `text` for a DOT string.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
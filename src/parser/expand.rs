@@ -0,0 +1,393 @@
+//! Expansion of macros, which replaces each call of a macro with the
+//! template of the first of its arms that matches the arguments, with the
+//! arguments put in place of the parameters. It happens once a module is
+//! parsed, so nothing after the parser sees a macro call.
+//!
+//! Expansion is hygienic: the names that a template binds, with its defs and
+//! the parameters of its lambdas, are renamed in each expansion to names that
+//! can't be written in source. They can then neither capture the names used
+//! in the arguments nor clash with the names around the call.
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    errors::ErrorStream,
+    tokenizer::{Intern, Span},
+};
+
+use super::*;
+
+/// How deeply expansions can contain further calls of macros, which stops a
+/// macro that calls itself from expanding forever.
+const RECURSION_LIMIT: usize = 64;
+
+/// How deeply expressions can be nested once macros are expanded. It's
+/// deeper than the parser lets them be written, since a level of
+/// [MAX_DEPTH] can be a few levels of the tree, so only expansions reach it.
+const MAX_NESTING: usize = 4 * MAX_DEPTH;
+
+/// How many expressions the expansions of a module can add to it in all,
+/// which stops a macro that copies its argument more than once from
+/// doubling it with each call until it runs out of memory.
+const MAX_EXPANDED: usize = 1 << 20;
+
+#[derive(Debug)]
+pub struct ExpandError<'s> {
+    pub kind: ExpandErrorKind<'s>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ExpandErrorKind<'s> {
+    /// No macro of the name is defined in the module.
+    UnknownMacro(Intern<'s>),
+    /// None of the arms of the macro match the arguments of the call.
+    NoMatchingArm { name: Intern<'s>, args: usize },
+    /// The macro expands to calls of macros nested more deeply than
+    /// [RECURSION_LIMIT].
+    RecursionLimit(Intern<'s>),
+    /// The expansion of the macro would nest expressions more deeply than
+    /// the parser lets them be, as a macro that wraps its argument in more
+    /// and more of them does.
+    TooDeep(Intern<'s>),
+    /// The expansions of the module add more than [MAX_EXPANDED] expressions
+    /// to it, with this macro's going over.
+    TooLarge(Intern<'s>),
+}
+
+/// Expands every macro call in `expr` with `macros`, returning where each of
+/// them was expanded. A call that can't be expanded is reported and replaced
+/// with `()`.
+pub(super) fn expand<'s>(
+    expr: &mut Expr<'s>,
+    macros: &[Macro<'s>],
+    intern: &mut dyn FnMut(String) -> Intern<'s>,
+    errors: &ErrorStream<'s>,
+) -> Vec<Expansion<'s>> {
+    let mut expander = Expander {
+        macros,
+        intern,
+        errors,
+        expansions: Vec::new(),
+        depth: 0,
+        nesting: 0,
+        expanded: 0,
+        overflowed: false,
+    };
+    expander.expr(expr);
+    expander.expansions
+}
+
+struct Expander<'s, 'a> {
+    macros: &'a [Macro<'s>],
+    intern: &'a mut dyn FnMut(String) -> Intern<'s>,
+    errors: &'a ErrorStream<'s>,
+    expansions: Vec<Expansion<'s>>,
+    /// How many expansions the expression being expanded is inside of.
+    depth: usize,
+    /// How deeply the expression being expanded is nested in the tree.
+    nesting: usize,
+    /// How many expressions the expansions so far have added.
+    expanded: usize,
+    /// Whether one of the limits on expansion was reached, after which the
+    /// calls that are left are dropped without more errors.
+    overflowed: bool,
+}
+
+impl<'s> Expander<'s, '_> {
+    fn expr(&mut self, expr: &mut Expr<'s>) {
+        let ExprKind::MacroCall { name, args } = &mut expr.kind else {
+            self.nesting += 1;
+            children(expr, &mut |child| self.expr(child));
+            self.nesting -= 1;
+            return;
+        };
+        let (name, args) = (*name, std::mem::take(args));
+
+        if self.overflowed || self.depth == RECURSION_LIMIT {
+            if !self.overflowed {
+                self.error(ExpandErrorKind::RecursionLimit(name), expr.span);
+                self.overflowed = true;
+            }
+            *expr = unit(expr.span);
+            return;
+        }
+
+        *expr = self.call(name, args, expr.span);
+        // the tree is no deeper than [MAX_NESTING] before each expansion, so
+        // that measuring the expansion and dropping it can't run out of stack
+        let (depth, size) = measure(expr);
+        self.expanded += size;
+        let kind = if self.nesting + depth > MAX_NESTING {
+            Some(ExpandErrorKind::TooDeep(name))
+        } else if self.expanded > MAX_EXPANDED {
+            Some(ExpandErrorKind::TooLarge(name))
+        } else {
+            None
+        };
+        if let Some(kind) = kind {
+            self.error(kind, expr.span);
+            self.overflowed = true;
+            *expr = unit(expr.span);
+            return;
+        }
+        self.depth += 1;
+        self.expr(expr);
+        self.depth -= 1;
+    }
+
+    fn call(&mut self, name: Intern<'s>, args: Box<[Expr<'s>]>, span: Span) -> Expr<'s> {
+        let macros = self.macros;
+        let mut arms = macros.iter().filter(|arm| arm.name == name).peekable();
+        if arms.peek().is_none() {
+            self.error(ExpandErrorKind::UnknownMacro(name), span);
+            return unit(span);
+        }
+        let Some(arm) = arms.find(|arm| matches(arm, &args)) else {
+            let args = args.len();
+            self.error(ExpandErrorKind::NoMatchingArm { name, args }, span);
+            return unit(span);
+        };
+
+        let mut bound = FxHashSet::default();
+        binders(&arm.template, &mut bound);
+        let params = arm
+            .params
+            .iter()
+            .zip(args)
+            .filter_map(|(param, arg)| match param {
+                MacroParam::Bind(param) if param.0 != "_" => Some((*param, arg)),
+                _ => None,
+            })
+            .collect::<FxHashMap<_, _>>();
+        let renames = bound
+            .into_iter()
+            .filter(|name| name.0 != "_" && !params.contains_key(name))
+            .map(|name| {
+                let fresh = format!("{}@{}", name.0, self.expansions.len());
+                (name, (self.intern)(fresh))
+            })
+            .collect();
+
+        let mut expansion = arm.template.clone();
+        substitute(&mut expansion, &params, &renames);
+        // the expansion as a whole stands for the call
+        expansion.span = span;
+        self.expansions.push(Expansion {
+            name,
+            call: span,
+            definition: arm.span,
+        });
+        expansion
+    }
+
+    fn error(&self, kind: ExpandErrorKind<'s>, span: Span) {
+        self.errors.error(ExpandError { kind, span });
+    }
+}
+
+fn matches(arm: &Macro, args: &[Expr]) -> bool {
+    arm.params.len() == args.len()
+        && arm.params.iter().zip(args).all(|(param, arg)| match param {
+            MacroParam::Bind(_) => true,
+            MacroParam::Literal(literal) => {
+                matches!(&arg.kind, ExprKind::Literal(arg) if arg == literal)
+            }
+        })
+}
+
+/// Collects the names bound in a template by the defs of its blocks, the
+/// parameters of its lambdas and the patterns of its matches. The fields of objects aren't included, since
+/// they are also reached by name from outside of the template.
+fn binders<'s>(expr: &Expr<'s>, bound: &mut FxHashSet<Intern<'s>>) {
+    match &expr.kind {
+        ExprKind::Block(scope) => bound.extend(scope.defs.iter().map(|def| def.name)),
+        ExprKind::Lambda { arg, .. } => pattern_binders(arg, bound),
+        ExprKind::Match { arms, .. } => arms
+            .iter()
+            .for_each(|arm| pattern_binders(&arm.pattern, bound)),
+        _ => {}
+    }
+    children_ref(expr, &mut |child| binders(child, bound));
+}
+
+fn pattern_binders<'s>(pattern: &Expr<'s>, bound: &mut FxHashSet<Intern<'s>>) {
+    match &pattern.kind {
+        ExprKind::Ident(name) => {
+            bound.insert(*name);
+        }
+        ExprKind::Tuple { items } => items.iter().for_each(|item| pattern_binders(item, bound)),
+        ExprKind::UnOp {
+            op: UnOp::Set | UnOp::Val,
+            arg: pattern,
+        }
+        | ExprKind::TypeAssertion { a: pattern, .. } => pattern_binders(pattern, bound),
+        ExprKind::Variant(items) => items
+            .iter()
+            .filter_map(|item| item.value.as_ref())
+            .for_each(|value| pattern_binders(value, bound)),
+        _ => {}
+    }
+}
+
+/// Puts the arguments of a call in place of the parameters they match in an
+/// expansion, and gives the names bound in it their fresh names. The
+/// arguments are left as they were written.
+fn substitute<'s>(
+    expr: &mut Expr<'s>,
+    params: &FxHashMap<Intern<'s>, Expr<'s>>,
+    renames: &FxHashMap<Intern<'s>, Intern<'s>>,
+) {
+    match &mut expr.kind {
+        ExprKind::Ident(name) => {
+            if let Some(arg) = params.get(name) {
+                *expr = arg.clone();
+            } else if let Some(fresh) = renames.get(name) {
+                *name = *fresh;
+            }
+            return;
+        }
+        ExprKind::Block(scope) => {
+            for def in scope.defs.iter_mut() {
+                if let Some(fresh) = renames.get(&def.name) {
+                    def.name = *fresh;
+                }
+            }
+        }
+        _ => {}
+    }
+    children(expr, &mut |child| substitute(child, params, renames));
+}
+
+/// How many levels deep `expr` is, and how many expressions it has, both
+/// counting itself.
+fn measure(expr: &Expr) -> (usize, usize) {
+    let (mut deepest, mut size) = (0, 1);
+    children_ref(expr, &mut |child| {
+        let (depth, child_size) = measure(child);
+        deepest = deepest.max(depth);
+        size += child_size;
+    });
+    (deepest + 1, size)
+}
+
+fn unit<'s>(span: Span) -> Expr<'s> {
+    Expr {
+        kind: ExprKind::Tuple {
+            items: Box::new([]),
+        },
+        span,
+    }
+}
+
+/// Calls `f` with each expression directly inside of `expr`.
+fn children<'s>(expr: &mut Expr<'s>, f: &mut dyn FnMut(&mut Expr<'s>)) {
+    match &mut expr.kind {
+        ExprKind::Object(scope) | ExprKind::Block(scope) => {
+            scope.defs.iter_mut().for_each(|def| f(&mut def.value));
+            scope.body.iter_mut().for_each(f);
+        }
+        ExprKind::Lambda { arg: a, body: b }
+        | ExprKind::BinOp { lhs: a, rhs: b, .. }
+        | ExprKind::Apply { a, b }
+        | ExprKind::TypeAssertion { a, b }
+        | ExprKind::Arrow { arg: a, ret: b } => {
+            f(a);
+            f(b);
+        }
+        ExprKind::UnOp { arg, .. } | ExprKind::Declaration(arg) => f(arg),
+        ExprKind::Trait { defs, .. } => defs.iter_mut().for_each(|def| f(&mut def.value)),
+        ExprKind::Impl { ty, defs } => {
+            f(ty);
+            defs.iter_mut().for_each(|def| f(&mut def.value));
+        }
+        ExprKind::Access { expr, prop } => {
+            f(expr);
+            if let AccessRhs::Expr(prop) = prop {
+                f(prop);
+            }
+        }
+        ExprKind::Branch {
+            cond,
+            on_true,
+            on_false,
+        } => {
+            f(cond);
+            f(on_true);
+            if let Some(on_false) = on_false {
+                f(on_false);
+            }
+        }
+        ExprKind::Match { scrutinee, arms } => {
+            f(scrutinee);
+            for arm in arms.iter_mut() {
+                f(&mut arm.pattern);
+                f(&mut arm.body);
+            }
+        }
+        ExprKind::Tuple { items } | ExprKind::MacroCall { args: items, .. } => {
+            items.iter_mut().for_each(f)
+        }
+        ExprKind::Variant(items) => items
+            .iter_mut()
+            .filter_map(|item| item.value.as_mut())
+            .for_each(f),
+        ExprKind::Ident(_) | ExprKind::Literal(_) => {}
+    }
+}
+
+/// Like [children], for an expression that is only read.
+pub(super) fn children_ref<'a, 's>(expr: &'a Expr<'s>, f: &mut dyn FnMut(&'a Expr<'s>)) {
+    match &expr.kind {
+        ExprKind::Object(scope) | ExprKind::Block(scope) => {
+            scope.defs.iter().for_each(|def| f(&def.value));
+            scope.body.iter().for_each(f);
+        }
+        ExprKind::Lambda { arg: a, body: b }
+        | ExprKind::BinOp { lhs: a, rhs: b, .. }
+        | ExprKind::Apply { a, b }
+        | ExprKind::TypeAssertion { a, b }
+        | ExprKind::Arrow { arg: a, ret: b } => {
+            f(a);
+            f(b);
+        }
+        ExprKind::UnOp { arg, .. } | ExprKind::Declaration(arg) => f(arg),
+        ExprKind::Trait { defs, .. } => defs.iter().for_each(|def| f(&def.value)),
+        ExprKind::Impl { ty, defs } => {
+            f(ty);
+            defs.iter().for_each(|def| f(&def.value));
+        }
+        ExprKind::Access { expr, prop } => {
+            f(expr);
+            if let AccessRhs::Expr(prop) = prop {
+                f(prop);
+            }
+        }
+        ExprKind::Branch {
+            cond,
+            on_true,
+            on_false,
+        } => {
+            f(cond);
+            f(on_true);
+            if let Some(on_false) = on_false {
+                f(on_false);
+            }
+        }
+        ExprKind::Match { scrutinee, arms } => {
+            f(scrutinee);
+            for arm in arms.iter() {
+                f(&arm.pattern);
+                f(&arm.body);
+            }
+        }
+        ExprKind::Tuple { items } | ExprKind::MacroCall { args: items, .. } => {
+            items.iter().for_each(f)
+        }
+        ExprKind::Variant(items) => items
+            .iter()
+            .filter_map(|item| item.value.as_ref())
+            .for_each(f),
+        ExprKind::Ident(_) | ExprKind::Literal(_) => {}
+    }
+}
@@ -0,0 +1,299 @@
+//! The syntax tree as JSON, for tools outside of the compiler to read.
+//!
+//! The module is an object with the `version` of the format, its `uses` and
+//! its `body`. Each expression is an object with its `kind`, the name of its
+//! variant in [ExprKind], its `span` and the fields of the variant under the
+//! same names, such as `{"kind": "BinOp", "op": "Add", "lhs": ..., "rhs": ...}`.
+//! A field that can be missing is `null` when it is. Spans are `[start, end]`
+//! byte offsets into the file.
+
+use std::fmt::Write;
+
+use super::*;
+use crate::errors::DiagnosticRecord;
+
+/// The version of the format, which changes whenever the output for the
+/// same tree does.
+const VERSION: u32 = 1;
+
+/// The JSON of a parsed module whose file starts at the global offset
+/// `start`, on one line.
+pub fn json(module: &Module, start: u32) -> String {
+    let mut json = Json {
+        out: String::new(),
+        start,
+    };
+    let _ = write!(json.out, "{{\"version\":{VERSION},\"uses\":[");
+    for (i, item) in module.uses.iter().enumerate() {
+        json.comma(i);
+        json.out.push_str("{\"path\":[");
+        for (i, segment) in item.path.iter().enumerate() {
+            json.comma(i);
+            json.string(segment.name.0);
+        }
+        json.out.push(']');
+        json.span(item.span);
+        json.out.push('}');
+    }
+    json.out.push_str("],\"body\":");
+    json.expr(&module.body);
+    json.out.push_str("}\n");
+    json.out
+}
+
+/// Parses `src` as a module of its own, as [parse_str] does, and renders its
+/// tree as JSON if it parsed, along with what was reported while parsing it.
+pub fn parse_to_json(src: &str) -> (Option<String>, Vec<DiagnosticRecord>) {
+    let storage = StringStorage::new();
+    let errors = ErrorStream::buffered();
+    let tree = match parse_str(src, &storage, &errors) {
+        Ok(module) => Some(json(&module, 0)),
+        Err(err) => {
+            errors.error(err);
+            None
+        }
+    };
+    let diagnostics = errors
+        .into_diagnostics()
+        .iter()
+        .map(|d| d.record())
+        .collect();
+    (tree, diagnostics)
+}
+
+struct Json {
+    out: String,
+    /// The offset of the file, which spans are made relative to.
+    start: u32,
+}
+
+impl Json {
+    fn expr(&mut self, e: &Expr) {
+        let kind = e.kind.name();
+        let _ = write!(self.out, "{{\"kind\":\"{kind}\"");
+        self.span(e.span);
+
+        match &e.kind {
+            ExprKind::Object(scope) | ExprKind::Block(scope) => {
+                self.defs(&scope.defs);
+                self.key("body");
+                self.exprs(&scope.body);
+                let _ = write!(self.out, ",\"trailing_semi\":{}", scope.trailing_semi);
+            }
+            ExprKind::Lambda { arg, body } => {
+                self.field("arg", arg);
+                self.field("body", body);
+            }
+            ExprKind::BinOp { op, lhs, rhs } => {
+                let _ = write!(self.out, ",\"op\":\"{op:?}\"");
+                self.field("lhs", lhs);
+                self.field("rhs", rhs);
+            }
+            ExprKind::UnOp { op, arg } => {
+                let _ = write!(self.out, ",\"op\":\"{op:?}\"");
+                self.field("arg", arg);
+            }
+            ExprKind::Access { expr, prop } => {
+                self.field("expr", expr);
+                match prop {
+                    AccessRhs::Prop(name) => {
+                        self.key("prop");
+                        self.string(name.0);
+                    }
+                    AccessRhs::Expr(index) => self.field("index", index),
+                }
+            }
+            ExprKind::Branch {
+                cond,
+                on_true,
+                on_false,
+            } => {
+                self.field("cond", cond);
+                self.field("on_true", on_true);
+                self.key("on_false");
+                match on_false {
+                    Some(on_false) => self.expr(on_false),
+                    None => self.out.push_str("null"),
+                }
+            }
+            ExprKind::Match { scrutinee, arms } => {
+                self.field("scrutinee", scrutinee);
+                self.key("arms");
+                self.out.push('[');
+                for (i, arm) in arms.iter().enumerate() {
+                    self.comma(i);
+                    self.out.push_str("{\"pattern\":");
+                    self.expr(&arm.pattern);
+                    self.field("body", &arm.body);
+                    self.out.push('}');
+                }
+                self.out.push(']');
+            }
+            ExprKind::Tuple { items } => {
+                self.key("items");
+                self.exprs(items);
+            }
+            ExprKind::Apply { a, b } | ExprKind::TypeAssertion { a, b } => {
+                self.field("a", a);
+                self.field("b", b);
+            }
+            ExprKind::Arrow { arg, ret } => {
+                self.field("arg", arg);
+                self.field("ret", ret);
+            }
+            ExprKind::Declaration(ty) => self.field("type", ty),
+            ExprKind::Trait {
+                param,
+                param_span,
+                defs,
+            } => {
+                self.key("param");
+                self.string(param.0);
+                self.key("param_span");
+                self.span_value(*param_span);
+                self.defs(defs);
+            }
+            ExprKind::Impl { ty, defs } => {
+                self.field("type", ty);
+                self.defs(defs);
+            }
+            ExprKind::Variant(items) => {
+                self.key("items");
+                self.out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    self.comma(i);
+                    self.out.push_str("{\"name\":");
+                    self.string(item.name.0);
+                    self.key("value");
+                    match &item.value {
+                        Some(value) => self.expr(value),
+                        None => self.out.push_str("null"),
+                    }
+                    self.span(item.span);
+                    self.out.push('}');
+                }
+                self.out.push(']');
+            }
+            ExprKind::Ident(name) => {
+                self.key("name");
+                self.string(name.0);
+            }
+            ExprKind::Literal(lit) => {
+                let _ = match lit {
+                    Literal::Float(x) => write!(self.out, ",\"type\":\"Float\",\"value\":{x:?}"),
+                    Literal::Integer(i) => write!(self.out, ",\"type\":\"Integer\",\"value\":{i}"),
+                    // as a string, since most readers of JSON would lose
+                    // its precision
+                    Literal::BigInteger(digits) => {
+                        write!(
+                            self.out,
+                            ",\"type\":\"BigInteger\",\"value\":\"{}\"",
+                            digits.0
+                        )
+                    }
+                    Literal::String(s) => {
+                        self.out.push_str(",\"type\":\"String\",\"value\":");
+                        self.string(s.0);
+                        Ok(())
+                    }
+                };
+            }
+            ExprKind::MacroCall { name, args } => {
+                self.key("name");
+                self.string(name.0);
+                self.key("args");
+                self.exprs(args);
+            }
+        }
+        self.out.push('}');
+    }
+
+    fn defs(&mut self, defs: &[Def]) {
+        self.key("defs");
+        self.out.push('[');
+        for (i, d) in defs.iter().enumerate() {
+            self.comma(i);
+            self.def(d);
+        }
+        self.out.push(']');
+    }
+
+    fn def(&mut self, d: &Def) {
+        self.out.push_str("{\"name\":");
+        self.string(d.name.0);
+        let _ = write!(self.out, ",\"public\":{},\"attributes\":[", d.public);
+        for (i, attribute) in d.attributes.iter().enumerate() {
+            self.comma(i);
+            self.out.push_str("{\"name\":");
+            self.string(attribute.name.0);
+            self.key("arg");
+            match attribute.arg {
+                Some(arg) => self.string(arg.0),
+                None => self.out.push_str("null"),
+            }
+            self.span(attribute.span);
+            self.out.push('}');
+        }
+        self.out.push(']');
+        self.field("value", &d.value);
+        self.key("name_span");
+        self.span_value(d.name_span);
+        self.span(d.span);
+        self.out.push('}');
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        self.out.push('[');
+        for (i, e) in exprs.iter().enumerate() {
+            self.comma(i);
+            self.expr(e);
+        }
+        self.out.push(']');
+    }
+
+    /// Writes `,"key":` before the value of a field.
+    fn key(&mut self, key: &str) {
+        let _ = write!(self.out, ",\"{key}\":");
+    }
+
+    fn field(&mut self, key: &str, e: &Expr) {
+        self.key(key);
+        self.expr(e);
+    }
+
+    fn span(&mut self, span: Span) {
+        self.key("span");
+        self.span_value(span);
+    }
+
+    fn span_value(&mut self, span: Span) {
+        let start = span.start.saturating_sub(self.start);
+        let end = span.end.saturating_sub(self.start);
+        let _ = write!(self.out, "[{start},{end}]");
+    }
+
+    /// Writes the comma before the `i`th item of an array.
+    fn comma(&mut self, i: usize) {
+        if i > 0 {
+            self.out.push(',');
+        }
+    }
+
+    fn string(&mut self, s: &str) {
+        self.out.push('"');
+        for ch in s.chars() {
+            match ch {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                ch if ch < ' ' => {
+                    let _ = write!(self.out, "\\u{:04x}", ch as u32);
+                }
+                ch => self.out.push(ch),
+            }
+        }
+        self.out.push('"');
+    }
+}
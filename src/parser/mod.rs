@@ -1,55 +1,285 @@
+use std::fmt;
+
 use crate::{
-    char_reader::CharReader,
+    char_reader::StrCharReader,
     errors::ErrorStream,
-    tokenizer::{Span, Token, TokenKind, TokenizationError, Tokens},
+    string_storage::StringStorage,
+    tokenizer::{Intern, Span, Token, TokenKind, TokenSource, TokenizationError, Tokens},
 };
 
 mod ast;
+mod bytes;
+mod dot;
+mod expand;
+mod json;
+mod parallel;
 mod preds;
+mod print;
+mod sexp;
 pub mod utils;
 
 pub use ast::*;
+pub use bytes::{parse_bytes, ParseOutcome};
+pub use dot::dot;
+pub use expand::{ExpandError, ExpandErrorKind};
+pub use json::{json, parse_to_json};
+pub use parallel::parse_parallel;
 use preds::*;
+pub use sexp::{expr_sexp, sexp};
 
-#[derive(Debug)]
-pub struct ParseError<'s> {
-    pub kind: ParseErrorKind<'s>,
-    pub span: Option<Span>,
+/// What went wrong while parsing, and where. It's boxed so that the results
+/// passed up through the parser's recursion are no larger than what they
+/// hold when parsing succeeds, which is almost always.
+pub struct ParseError<'s>(Box<ParseErrorData<'s>>);
+
+struct ParseErrorData<'s> {
+    kind: ParseErrorKind<'s>,
+    span: Option<Span>,
+}
+
+const _: () = assert!(std::mem::size_of::<ParseError>() == std::mem::size_of::<usize>());
+
+impl<'s> ParseError<'s> {
+    pub fn new(kind: ParseErrorKind<'s>, span: Option<Span>) -> ParseError<'s> {
+        ParseError(Box::new(ParseErrorData { kind, span }))
+    }
+
+    pub fn kind(&self) -> &ParseErrorKind<'s> {
+        &self.0.kind
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.0.span
+    }
+
+    pub fn into_kind(self) -> ParseErrorKind<'s> {
+        self.0.kind
+    }
+
+    /// Points an error about the input ending too soon, which has no token
+    /// to point at, at `end`, the offset just past the input.
+    pub fn at_end(mut self, end: u32) -> ParseError<'s> {
+        if matches!(self.0.kind, ParseErrorKind::Unexpected(None)) && self.0.span.is_none() {
+            self.0.span = Some(Span { start: end, end });
+        }
+        self
+    }
+}
+
+impl fmt::Debug for ParseError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParseError")
+            .field("kind", &self.0.kind)
+            .field("span", &self.0.span)
+            .finish()
+    }
 }
 
 #[derive(Debug)]
 pub enum ParseErrorKind<'s> {
     Unexpected(Option<Token<'s>>),
+    /// A float literal without digits before its point, as in `.5`, with the
+    /// form that's accepted instead.
+    LeadingPoint(String),
+    /// Comparisons chained without parentheses, as in `a < b < c`, with the
+    /// comparison that was likely meant, `a < b && b < c`.
+    ChainedComparison(String),
+    /// `.` followed by a number, as in `t.0`. The items of a tuple are taken
+    /// apart with a pattern instead.
+    NumericAccess,
+    /// Something other than the declaration of a method, `def name :: T;`,
+    /// in the body of a trait.
+    NotADeclaration,
+    /// Expressions nested more deeply than [MAX_DEPTH].
+    TooDeep,
+    /// Bytes of the input that aren't UTF-8, which [parse_bytes] parses as
+    /// U+FFFD instead.
+    InvalidUtf8,
+    /// Input of 4 GiB or more, which spans can't point into.
+    TooLarge,
     TokenizationError(TokenizationError),
 }
 
 impl<'s> From<TokenizationError> for ParseError<'s> {
     fn from(err: TokenizationError) -> Self {
-        ParseError {
-            span: err.span,
-            kind: ParseErrorKind::TokenizationError(err),
-        }
+        let span = err.span;
+        ParseError::new(ParseErrorKind::TokenizationError(err), span)
     }
 }
 
 type Result<'s, T> = std::result::Result<T, ParseError<'s>>;
 
-pub fn parse<'s>(
-    tokens: Tokens<'s, impl CharReader>,
-    errors: &'s ErrorStream<'s>,
+pub fn parse<'s>(tokens: impl TokenSource<'s>, errors: &ErrorStream<'s>) -> Result<'s, Module<'s>> {
+    Parser::new(tokens, errors).parse()
+}
+
+/// Parses `src` as a module of its own, with spans that are offsets into it.
+pub fn parse_str<'s>(
+    src: &str,
+    storage: &'s StringStorage,
+    errors: &ErrorStream<'s>,
+) -> Result<'s, Module<'s>> {
+    let tokens = Tokens::of(StrCharReader::new(src), storage);
+    parse(tokens, errors)
+}
+
+/// Parses a module, handing each of its top-level defs to `f` as soon as
+/// it's parsed instead of keeping it, so that the syntax tree of the whole
+/// module is never held at once. Returns the module's uses.
+///
+/// The defs are as they're written, with any macro calls in them left
+/// unexpanded, since a macro can be defined after where it's used. The
+/// macros and the expressions at the top level are parsed and dropped.
+pub fn parse_streaming<'s>(
+    tokens: impl TokenSource<'s>,
+    mut f: impl FnMut(Def<'s>),
+) -> Result<'s, Box<[Use<'s>]>> {
+    // nothing is reported to it, since it's only the expansion of macros
+    // that reports anything
+    let errors = ErrorStream::buffered();
+    let mut parser = Parser::new(tokens, &errors);
+    let uses = parser.uses()?;
+
+    let mut first = true;
+    while parser.tokens.peek()?.is_some() {
+        if parser.has_peek(bpred!(
+            TokenKind::Def | TokenKind::Pub | TokenKind::At | TokenKind::Trait | TokenKind::Impl
+        ))? {
+            f(parser.def()?);
+        } else if parser.has_peek(bpred!(TokenKind::Macro))? {
+            parser.macro_def()?;
+        } else {
+            parser.tuple()?;
+            if parser.eat(bpred!(TokenKind::Semicolon))?.is_none() {
+                // the top level ends at the first expression without a
+                // semicolon, unless it's the first thing in the module and
+                // there's more after it
+                if first && parser.tokens.peek()?.is_some() {
+                    parser.require(bpred!(TokenKind::Semicolon))?;
+                }
+                break;
+            }
+        }
+        first = false;
+    }
+
+    Ok(uses.into())
+}
+
+/// Parses input that is a single expression, as it would be written at the
+/// top level of a module and optionally followed by a semicolon, such as a
+/// line entered into the REPL.
+pub fn parse_expr<'s>(
+    tokens: impl TokenSource<'s>,
+    errors: &ErrorStream<'s>,
 ) -> Result<'s, Expr<'s>> {
-    Parser { tokens, errors }.parse()
+    let mut parser = Parser::new(tokens, errors);
+    let mut expr = parser.tuple()?;
+    parser.eat(bpred!(TokenKind::Semicolon))?;
+    match parser.tokens.peek()? {
+        None => {
+            expand::expand(
+                &mut expr,
+                &[],
+                &mut |name| parser.tokens.intern(name),
+                errors,
+            );
+            Ok(expr)
+        }
+        Some(token) => Err(ParseError::new(
+            ParseErrorKind::Unexpected(Some(*token)),
+            Some(token.span),
+        )),
+    }
 }
 
-struct Parser<'s, R> {
-    tokens: Tokens<'s, R>,
-    errors: &'s ErrorStream<'s>,
+/// How deeply expressions can be nested in each other, counting each
+/// operator of a chain like `a + b + c` and each argument of a call like
+/// `f a b` as a level. The parser stops there rather than overflowing its
+/// stack, as long as it has [STACK_SIZE] of it, which also keeps the tree
+/// shallow enough for the passes after it to walk.
+pub const MAX_DEPTH: usize = 512;
+
+/// The stack that parsing needs for expressions nested [MAX_DEPTH] deep,
+/// with room to spare even in a debug build, where a level of some kinds of
+/// expression takes tens of kilobytes. It's far more than the threads that
+/// Rust spawns get by default, so the compiler parses on threads of its own.
+pub const STACK_SIZE: usize = 64 << 20;
+
+/// The binary operators, from the loosest to the tightest binding. Those at
+/// the same level are left-associative with each other.
+pub const PRECEDENCE: [&[BinOp]; 5] = [
+    &[BinOp::And, BinOp::Or],
+    &[BinOp::Equal, BinOp::NotEqual],
+    &[BinOp::Gt, BinOp::GtEq, BinOp::Lt, BinOp::LtEq],
+    &[BinOp::Add, BinOp::Sub],
+    &[BinOp::Mul, BinOp::Div, BinOp::Mod],
+];
+
+/// The binary operator that `token` is, if it's one.
+fn bin_op(token: &Token) -> Option<BinOp> {
+    Some(match token.kind {
+        TokenKind::AmpAmp => BinOp::And,
+        TokenKind::PipePipe => BinOp::Or,
+        TokenKind::Equal => BinOp::Equal,
+        TokenKind::NotEqual => BinOp::NotEqual,
+        TokenKind::Gt => BinOp::Gt,
+        TokenKind::GtEq => BinOp::GtEq,
+        TokenKind::Lt => BinOp::Lt,
+        TokenKind::LtEq => BinOp::LtEq,
+        TokenKind::Plus => BinOp::Add,
+        TokenKind::Minus => BinOp::Sub,
+        TokenKind::Star => BinOp::Mul,
+        TokenKind::Slash => BinOp::Div,
+        TokenKind::Percent => BinOp::Mod,
+        _ => return None,
+    })
 }
 
-impl<'s, R: CharReader> Parser<'s, R> {
-    fn parse(mut self) -> Result<'s, Expr<'s>> {
-        let scope = self.scope(vpred!())?;
+struct Parser<'s, 'e, S> {
+    tokens: S,
+    errors: &'e ErrorStream<'s>,
+    /// The macros defined so far, wherever they were written in the module.
+    macros: Vec<Macro<'s>>,
+    /// Scratch space for the items of the tuples and scopes being parsed.
+    /// Each one pushes its items on top of those of the ones it's inside of,
+    /// and takes them off again once it's parsed, so the space is reused
+    /// instead of each of them growing a `Vec` of its own.
+    exprs: Vec<Expr<'s>>,
+    defs: Vec<Def<'s>>,
+    /// How deeply the expression being parsed is nested, up to [MAX_DEPTH].
+    depth: usize,
+}
+
+impl<'s, 'e, S: TokenSource<'s>> Parser<'s, 'e, S> {
+    fn new(tokens: S, errors: &'e ErrorStream<'s>) -> Self {
+        Parser {
+            tokens,
+            errors,
+            macros: Vec::new(),
+            exprs: Vec::new(),
+            defs: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    fn parse(mut self) -> Result<'s, Module<'s>> {
+        let uses = self.uses()?;
+        let scope = self.outer_scope(vpred!())?;
+        Ok(self.module(uses, scope))
+    }
 
+    fn uses(&mut self) -> Result<'s, Vec<Use<'s>>> {
+        let mut uses = Vec::new();
+        while self.has_peek(bpred!(TokenKind::Use))? {
+            uses.push(self.use_item()?);
+        }
+        Ok(uses)
+    }
+
+    /// Puts a module together out of its uses and top-level scope, expanding
+    /// the macros written in it.
+    fn module(mut self, uses: Vec<Use<'s>>, scope: ParsedScope<'s>) -> Module<'s> {
         let (kind, span) = match scope {
             ParsedScope::Scope(scope) => {
                 let mut spans = [
@@ -59,29 +289,102 @@ impl<'s, R: CharReader> Parser<'s, R> {
                     scope.body.last().map(|e| e.span.end),
                 ]
                 .into_iter()
-                .filter_map(std::convert::identity);
+                .flatten();
 
                 (
                     ExprKind::Object(Box::new(scope)),
                     Span {
                         start: spans.next().unwrap_or(0),
-                        end: spans.last().unwrap_or(0),
+                        end: spans.next_back().unwrap_or(0),
                     },
                 )
             }
             ParsedScope::Expr { kind, span } => (kind, span.unwrap_or(Span { start: 0, end: 0 })),
         };
 
-        Ok(Expr { kind, span })
+        let mut body = Expr { kind, span };
+        let expansions = expand::expand(
+            &mut body,
+            &self.macros,
+            &mut |name| self.tokens.intern(name),
+            self.errors,
+        );
+
+        Module {
+            uses: uses.into(),
+            body,
+            expansions: expansions.into(),
+        }
+    }
+
+    fn use_item(&mut self) -> Result<'s, Use<'s>> {
+        let start = self.require(vpred!(:t: TokenKind::Use => t.span.start))?;
+
+        let mut path = Vec::with_capacity(1);
+        loop {
+            let (span, name) = self.require(vpred!(:t: TokenKind::Name(n) => (t.span, n)))?;
+            path.push(PathSegment { name, span });
+
+            if self.eat(bpred!(TokenKind::Dot))?.is_none() {
+                break;
+            }
+        }
+
+        let end = self.require(vpred!(:t: TokenKind::Semicolon => t.span.end))?;
+
+        Ok(Use {
+            path: path.into(),
+            span: Span { start, end },
+        })
     }
 
     fn def(&mut self) -> Result<'s, Def<'s>> {
-        let Token {
-            span: Span { start, .. },
-            ..
-        } = self.require(tpred!(TokenKind::Def))?;
-        let name = self.require(vpred!(TokenKind::Name(n) => n))?;
-        let (value, needs_semi) = self.block_needs_semi()?;
+        let mut attributes = Vec::new();
+        while let Some(at) = self.eat(vpred!(:t: TokenKind::At => t.span.start))? {
+            let (span, name) = self.require(vpred!(:t: TokenKind::Name(n) => (t.span, n)))?;
+            let mut end = span.end;
+            let mut arg = None;
+            if self.eat(tpred!(TokenKind::OpenParen))?.is_some() {
+                arg = Some(self.require(vpred!(TokenKind::String(s) => s))?);
+                end = self.require(vpred!(:t: TokenKind::CloseParen => t.span.end))?;
+            }
+            attributes.push(Attribute {
+                name,
+                arg,
+                span: Span { start: at, end },
+            });
+        }
+        let public = self.eat(vpred!(:t: TokenKind::Pub => t.span.start))?;
+        if attributes.is_empty() {
+            if let Some(start) = self.eat(vpred!(:t: TokenKind::Trait => t.span.start))? {
+                return self.r#trait(public.unwrap_or(start), public.is_some());
+            }
+            if public.is_none() {
+                if let Some(start) = self.eat(vpred!(:t: TokenKind::Impl => t.span.start))? {
+                    return self.r#impl(start);
+                }
+            }
+        }
+        let def = self.require(vpred!(:t: TokenKind::Def => t.span.start))?;
+        let start = attributes
+            .first()
+            .map(|attribute| attribute.span.start)
+            .or(public)
+            .unwrap_or(def);
+        let (name_span, name) = self.require(vpred!(:t: TokenKind::Name(n) => (t.span, n)))?;
+        let (value, needs_semi) =
+            match self.eat(vpred!(:t: TokenKind::ColonColon => t.span.start))? {
+                Some(start) => {
+                    let ty = self.type_expr()?;
+                    let span = Span {
+                        start,
+                        end: ty.span.end,
+                    };
+                    let kind = ExprKind::Declaration(Box::new(ty));
+                    (Expr { kind, span }, NeedsSemi::Yes)
+                }
+                None => self.block_needs_semi()?,
+            };
         let end = if let NeedsSemi::Yes = needs_semi {
             self.require(vpred!(:t: TokenKind::Semicolon => t.span.end))?
         } else {
@@ -89,12 +392,170 @@ impl<'s, R: CharReader> Parser<'s, R> {
         };
 
         Ok(Def {
+            attributes: attributes.into(),
             name,
+            name_span,
+            public: public.is_some(),
             value: Box::new(value),
             span: Span { start, end },
         })
     }
 
+    /// Parses the rest of a `trait Name(param) { defs }`, whose body only
+    /// declares the types of its methods, as in `def show :: a -> String;`.
+    fn r#trait(&mut self, start: u32, public: bool) -> Result<'s, Def<'s>> {
+        let (name_span, name) = self.require(vpred!(:t: TokenKind::Name(n) => (t.span, n)))?;
+        self.require(bpred!(TokenKind::OpenParen))?;
+        let (param_span, param) = self.require(vpred!(:t: TokenKind::Name(n) => (t.span, n)))?;
+        self.require(bpred!(TokenKind::CloseParen))?;
+        let (defs, end) = self.nested(Self::item_defs)?;
+        if let Some(def) = defs.iter().find(|def| {
+            def.public
+                || !def.attributes.is_empty()
+                || !matches!(def.value.kind, ExprKind::Declaration(_))
+        }) {
+            return Err(ParseError::new(
+                ParseErrorKind::NotADeclaration,
+                Some(def.span),
+            ));
+        }
+
+        let kind = ExprKind::Trait {
+            param,
+            param_span,
+            defs,
+        };
+        Ok(Def {
+            attributes: Box::new([]),
+            name,
+            name_span,
+            public,
+            value: Box::new(Expr {
+                kind,
+                span: Span {
+                    start: name_span.start,
+                    end,
+                },
+            }),
+            span: Span { start, end },
+        })
+    }
+
+    /// Parses the rest of an `impl Name(ty) { defs }`, which is a def named
+    /// after the trait that it implements for `ty`.
+    fn r#impl(&mut self, start: u32) -> Result<'s, Def<'s>> {
+        let (name_span, name) = self.require(vpred!(:t: TokenKind::Name(n) => (t.span, n)))?;
+        self.require(bpred!(TokenKind::OpenParen))?;
+        let ty = self.type_expr()?;
+        self.require(bpred!(TokenKind::CloseParen))?;
+        let (defs, end) = self.nested(Self::item_defs)?;
+
+        let kind = ExprKind::Impl {
+            ty: Box::new(ty),
+            defs,
+        };
+        Ok(Def {
+            attributes: Box::new([]),
+            name,
+            name_span,
+            public: false,
+            value: Box::new(Expr {
+                kind,
+                span: Span {
+                    start: name_span.start,
+                    end,
+                },
+            }),
+            span: Span { start, end },
+        })
+    }
+
+    /// Parses the braced body of a `trait` or an `impl`, which has nothing
+    /// but defs in it. Returns them and where the body ends.
+    fn item_defs(&mut self) -> Result<'s, (Box<[Def<'s>]>, u32)> {
+        self.require(bpred!(TokenKind::OpenBrace))?;
+        let defs = self.defs.len();
+        while self.tokens.peek()?.is_some() && !self.has_peek(bpred!(TokenKind::CloseBrace))? {
+            let def = self.def()?;
+            self.defs.push(def);
+        }
+        let end = self.require(vpred!(:t: TokenKind::CloseBrace => t.span.end))?;
+        Ok((self.defs.drain(defs..).collect(), end))
+    }
+
+    /// Parses an arm of a macro, `macro name(params) { template }`. Macros
+    /// can be used anywhere in the module they are written in, so they are
+    /// set aside to be expanded once the whole module is parsed.
+    fn macro_def(&mut self) -> Result<'s, Macro<'s>> {
+        let start = self.require(vpred!(:t: TokenKind::Macro => t.span.start))?;
+        let name = self.require(vpred!(TokenKind::Name(n) => n))?;
+        self.require(bpred!(TokenKind::OpenParen))?;
+        let mut params = Vec::new();
+        while self.eat(bpred!(TokenKind::CloseParen))?.is_none() {
+            if !params.is_empty() {
+                self.require(bpred!(TokenKind::Comma))?;
+            }
+            params.push(self.require(vpred! {
+                TokenKind::Name(n) => MacroParam::Bind(n),
+                TokenKind::Float(f) => MacroParam::Literal(Literal::Float(f)),
+                TokenKind::Integer(i) => MacroParam::Literal(Literal::Integer(i)),
+                TokenKind::BigInteger(i) => MacroParam::Literal(Literal::BigInteger(i)),
+                TokenKind::String(s) => MacroParam::Literal(Literal::String(s)),
+            })?);
+        }
+
+        let open = self.require(tpred!(TokenKind::OpenBrace))?;
+        let scope = self.scope(bpred!(TokenKind::CloseBrace))?;
+        let close = self.require(tpred!(TokenKind::CloseBrace))?;
+        let template = Expr {
+            span: Span {
+                start: open.span.start,
+                end: close.span.end,
+            },
+            kind: match scope {
+                ParsedScope::Scope(scope) => ExprKind::Block(Box::new(scope)),
+                ParsedScope::Expr { kind, .. } => kind,
+            },
+        };
+
+        Ok(Macro {
+            name,
+            params: params.into(),
+            span: Span {
+                start,
+                end: template.span.end,
+            },
+            template,
+        })
+    }
+
+    /// Parses the arguments of a call of the macro `name`, whose `!` has
+    /// just been read.
+    fn macro_call(&mut self, name: Intern<'s>, name_span: Span) -> Result<'s, Expr<'s>> {
+        self.require(bpred!(TokenKind::OpenParen))?;
+        let mut args = Vec::new();
+        let end = loop {
+            if let Some(end) = self.eat(vpred!(:t: TokenKind::CloseParen => t.span.end))? {
+                break end;
+            }
+            if !args.is_empty() {
+                self.require(bpred!(TokenKind::Comma))?;
+            }
+            args.push(self.block()?);
+        };
+
+        Ok(Expr {
+            kind: ExprKind::MacroCall {
+                name,
+                args: args.into(),
+            },
+            span: Span {
+                start: name_span.start,
+                end,
+            },
+        })
+    }
+
     fn block(&mut self) -> Result<'s, Expr<'s>> {
         Ok(self.block_needs_semi()?.0)
     }
@@ -137,11 +598,22 @@ impl<'s, R: CharReader> Parser<'s, R> {
         }
     }
 
+    /// Parses a scope up to `end_pred` inside of what's being parsed, which
+    /// is a level deeper.
     fn scope(
         &mut self,
         end_pred: impl Fn(&Token<'s>) -> Option<()>,
     ) -> Result<'s, ParsedScope<'s>> {
-        if self.has_peek(&end_pred)? {
+        self.nested(|parser| parser.outer_scope(end_pred))
+    }
+
+    /// Parses a scope up to `end_pred` without going a level deeper, as the
+    /// top level of a module is.
+    fn outer_scope(
+        &mut self,
+        end_pred: impl Fn(&Token<'s>) -> Option<()>,
+    ) -> Result<'s, ParsedScope<'s>> {
+        if self.at_end(&end_pred)? {
             return Ok(ParsedScope::Expr {
                 kind: ExprKind::Tuple {
                     items: Box::new([]),
@@ -150,36 +622,63 @@ impl<'s, R: CharReader> Parser<'s, R> {
             });
         }
 
-        let mut defs;
-        let mut body;
-        if !self.has_peek(bpred!(TokenKind::Def))? {
+        let defs = self.defs.len();
+        let body = self.exprs.len();
+        if !self.has_peek(bpred!(
+            TokenKind::Def
+                | TokenKind::Pub
+                | TokenKind::At
+                | TokenKind::Trait
+                | TokenKind::Impl
+                | TokenKind::Macro
+        ))? {
             let first = self.tuple()?;
 
-            if self.has_peek(&end_pred)? {
+            if self.at_end(&end_pred)? {
                 return Ok(ParsedScope::Expr {
                     kind: first.kind,
                     span: Some(first.span),
                 });
             } else {
-                defs = vec![];
-                body = vec![first];
+                self.exprs.push(first);
 
                 self.require(bpred!(TokenKind::Semicolon))?;
             }
-        } else {
-            defs = Vec::new();
-            body = Vec::new();
         }
 
+        let semi = self.scope_items(&end_pred)?;
+
+        Ok(ParsedScope::Scope(Scope {
+            defs: self.defs.drain(defs..).collect(),
+            body: self.exprs.drain(body..).collect(),
+            trailing_semi: semi,
+        }))
+    }
+
+    /// Parses the defs, macros and expressions of a scope up to `end_pred`,
+    /// pushing them onto the scratch stacks. Returns whether the last
+    /// expression was followed by a semicolon, since the scope stops at the
+    /// first one that isn't.
+    fn scope_items(&mut self, end_pred: impl Fn(&Token<'s>) -> Option<()>) -> Result<'s, bool> {
         let mut semi = true;
         while let Some(None) = self.tokens.peek()?.map(&end_pred) {
             if self.has_peek(to_bpred(&end_pred))? {
                 break;
-            } else if self.has_peek(bpred!(TokenKind::Def))? {
-                defs.push(self.def()?)
+            } else if self.has_peek(bpred!(
+                TokenKind::Def
+                    | TokenKind::Pub
+                    | TokenKind::At
+                    | TokenKind::Trait
+                    | TokenKind::Impl
+            ))? {
+                let def = self.def()?;
+                self.defs.push(def);
+            } else if self.has_peek(bpred!(TokenKind::Macro))? {
+                let r#macro = self.macro_def()?;
+                self.macros.push(r#macro);
             } else {
                 let expr = self.tuple()?;
-                body.push(expr);
+                self.exprs.push(expr);
 
                 if self.eat(bpred!(TokenKind::Semicolon))?.is_none() {
                     semi = false;
@@ -190,11 +689,7 @@ impl<'s, R: CharReader> Parser<'s, R> {
             }
         }
 
-        Ok(ParsedScope::Scope(Scope {
-            defs: defs.into(),
-            body: body.into(),
-            trailing_semi: semi,
-        }))
+        Ok(semi)
     }
 
     fn tuple(&mut self) -> Result<'s, Expr<'s>> {
@@ -205,14 +700,16 @@ impl<'s, R: CharReader> Parser<'s, R> {
         let first = self.block_needs_semi()?;
 
         if self.has_peek(bpred!(TokenKind::Comma))? {
-            let mut items = vec![first.0];
+            let start = self.exprs.len();
+            self.exprs.push(first.0);
 
             while self.eat(bpred!(TokenKind::Comma))?.is_some() {
                 let item = self.block()?;
 
-                items.push(item);
+                self.exprs.push(item);
             }
 
+            let items: Box<[_]> = self.exprs.drain(start..).collect();
             let span = Span {
                 start: items.first().unwrap().span.start,
                 end: items.last().unwrap().span.end,
@@ -220,9 +717,7 @@ impl<'s, R: CharReader> Parser<'s, R> {
 
             Ok((
                 Expr {
-                    kind: ExprKind::Tuple {
-                        items: items.into(),
-                    },
+                    kind: ExprKind::Tuple { items },
                     span,
                 },
                 NeedsSemi::Yes,
@@ -233,7 +728,7 @@ impl<'s, R: CharReader> Parser<'s, R> {
     }
 
     fn lambda_needs_semi(&mut self) -> Result<'s, (Expr<'s>, NeedsSemi)> {
-        let mut a = self.expr_needs_semi()?;
+        let mut a = self.nested(Self::expr_needs_semi)?;
 
         if let Some(open) = self.eat(tpred!(TokenKind::OpenBrace))? {
             let scope = self.scope(bpred!(TokenKind::CloseBrace))?;
@@ -292,19 +787,68 @@ impl<'s, R: CharReader> Parser<'s, R> {
         Ok(a)
     }
 
+    /// Parses a type, which is an expression that may also be a function
+    /// type. `->` is right-associative.
+    fn type_expr(&mut self) -> Result<'s, Expr<'s>> {
+        let arg = self.binary(0)?;
+        if self.eat(bpred!(TokenKind::ThinArrow))?.is_none() {
+            return Ok(arg);
+        }
+
+        let ret = self.nested(Self::type_expr)?;
+        Ok(Expr {
+            span: Span {
+                start: arg.span.start,
+                end: ret.span.end,
+            },
+            kind: ExprKind::Arrow {
+                arg: Box::new(arg),
+                ret: Box::new(ret),
+            },
+        })
+    }
+
+    /// Parses `cond ? on_true : on_false`, which is a `case` without the
+    /// braces. It binds looser than `||` and nests to the right, so
+    /// `a ? b : c ? d : e` is `a ? b : (c ? d : e)`.
+    fn conditional(&mut self) -> Result<'s, Expr<'s>> {
+        let cond = self.binary(0)?;
+        if self.eat(bpred!(TokenKind::Question))?.is_none() {
+            return Ok(cond);
+        }
+
+        let on_true = self.nested(Self::conditional)?;
+        self.require(tpred!(TokenKind::Colon))?;
+        let on_false = self.nested(Self::conditional)?;
+        Ok(Expr {
+            span: Span {
+                start: cond.span.start,
+                end: on_false.span.end,
+            },
+            kind: ExprKind::Branch {
+                cond: Box::new(cond),
+                on_true: Box::new(on_true),
+                on_false: Some(Box::new(on_false)),
+            },
+        })
+    }
+
     fn expr(&mut self) -> Result<'s, Expr<'s>> {
-        Ok(self.expr_needs_semi()?.0)
+        Ok(self.nested(Self::expr_needs_semi)?.0)
     }
 
     fn expr_needs_semi(&mut self) -> Result<'s, (Expr<'s>, NeedsSemi)> {
         if self.has_peek(bpred!(TokenKind::Case))? {
             return Ok((self.case()?, NeedsSemi::No));
         }
+        if self.has_peek(bpred!(TokenKind::Match))? {
+            return Ok((self.r#match()?, NeedsSemi::No));
+        }
 
-        let mut a = (self.logical()?, NeedsSemi::Yes);
+        let mut a = (self.conditional()?, NeedsSemi::Yes);
 
         if self.eat(bpred!(TokenKind::ColonColon))?.is_some() {
-            let b = self.logical()?;
+            let b = self.type_expr()?;
 
             a = (
                 Expr {
@@ -330,7 +874,7 @@ impl<'s, R: CharReader> Parser<'s, R> {
         self.case_inner(case.span.start)
     }
 
-    fn case_inner(&mut self, start: usize) -> Result<'s, Expr<'s>> {
+    fn case_inner(&mut self, start: u32) -> Result<'s, Expr<'s>> {
         let cond = self.expr()?;
         let on_true_open = self.require(tpred!(TokenKind::OpenBrace))?;
         let on_true = self.scope(bpred!(TokenKind::CloseBrace))?;
@@ -372,7 +916,7 @@ impl<'s, R: CharReader> Parser<'s, R> {
                     },
                 })
             } else {
-                let inner = self.case_inner(r#else.span.start)?;
+                let inner = self.nested(|parser| parser.case_inner(r#else.span.start))?;
 
                 Ok(Expr {
                     span: Span {
@@ -401,56 +945,47 @@ impl<'s, R: CharReader> Parser<'s, R> {
         }
     }
 
-    fn logical(&mut self) -> Result<'s, Expr<'s>> {
-        self.bin_op(
-            Self::equal,
-            vpred! {
-                TokenKind::AmpAmp => BinOp::And,
-                TokenKind::PipePipe => BinOp::Or,
-            },
-        )
-    }
-
-    fn equal(&mut self) -> Result<'s, Expr<'s>> {
-        self.bin_op(
-            Self::cmp,
-            vpred! {
-                TokenKind::Equal => BinOp::Equal,
-                TokenKind::NotEqual => BinOp::NotEqual,
-            },
-        )
-    }
-
-    fn cmp(&mut self) -> Result<'s, Expr<'s>> {
-        self.bin_op(
-            Self::terms,
-            vpred! {
-                TokenKind::Gt => BinOp::Gt,
-                TokenKind::GtEq => BinOp::GtEq,
-                TokenKind::Lt => BinOp::Lt,
-                TokenKind::LtEq => BinOp::LtEq,
-            },
-        )
-    }
+    /// Parses `match scrutinee { pattern => body; ... }`. An arm's body is
+    /// followed by a semicolon unless it's a block or is the last arm, as
+    /// with the value of a def.
+    fn r#match(&mut self) -> Result<'s, Expr<'s>> {
+        let start = self.require(vpred!(:t: TokenKind::Match => t.span.start))?;
+        let scrutinee = self.expr()?;
+        self.require(bpred!(TokenKind::OpenBrace))?;
+        let mut arms = Vec::new();
+        let end = loop {
+            if let Some(end) = self.eat(vpred!(:t: TokenKind::CloseBrace => t.span.end))? {
+                break end;
+            }
+            let pattern = self.expr()?;
+            self.require(bpred!(TokenKind::FatArrow))?;
+            let (body, needs_semi) = self.block_needs_semi()?;
+            arms.push(MatchArm { pattern, body });
+            if let NeedsSemi::Yes = needs_semi {
+                if !self.has_peek(bpred!(TokenKind::CloseBrace))? {
+                    self.require(bpred!(TokenKind::Semicolon))?;
+                }
+            }
+        };
 
-    fn terms(&mut self) -> Result<'s, Expr<'s>> {
-        self.bin_op(
-            Self::factors,
-            vpred! {
-                TokenKind::Plus => BinOp::Add,
-                TokenKind::Minus => BinOp::Sub,
+        Ok(Expr {
+            kind: ExprKind::Match {
+                scrutinee: Box::new(scrutinee),
+                arms: arms.into(),
             },
-        )
+            span: Span { start, end },
+        })
     }
 
-    fn factors(&mut self) -> Result<'s, Expr<'s>> {
+    /// Parses the binary operators of [PRECEDENCE] from `level` on, and
+    /// then the prefix operators.
+    fn binary(&mut self, level: usize) -> Result<'s, Expr<'s>> {
+        let Some(ops) = PRECEDENCE.get(level) else {
+            return self.prefix();
+        };
         self.bin_op(
-            Self::prefix,
-            vpred! {
-                TokenKind::Star => BinOp::Mul,
-                TokenKind::Slash => BinOp::Div,
-                TokenKind::Percent => BinOp::Mod,
-            },
+            |parser| parser.binary(level + 1),
+            |t| bin_op(t).filter(|op| ops.contains(op)),
         )
     }
 
@@ -461,7 +996,7 @@ impl<'s, R: CharReader> Parser<'s, R> {
             :t: TokenKind::Val => (t.span, UnOp::Val),
             :t: TokenKind::Caret => (t.span, UnOp::Ref),
         })? {
-            let a = self.prefix()?;
+            let a = self.nested(Self::prefix)?;
 
             let span = Span {
                 start: op_span.start,
@@ -482,12 +1017,16 @@ impl<'s, R: CharReader> Parser<'s, R> {
 
     fn suffix(&mut self) -> Result<'s, Expr<'s>> {
         let Some(mut a) = self.maybe_atom()? else {
-            return Err(ParseError {
-                kind: ParseErrorKind::Unexpected(self.tokens.peek()?.cloned()),
-                span: None,
-            })
+            if let Some(dot) = self.eat(tpred!(TokenKind::Dot))? {
+                return Err(self.leading_point(dot)?);
+            }
+            return Err(ParseError::new(
+                ParseErrorKind::Unexpected(self.tokens.peek()?.copied()),
+                None,
+            ));
         };
 
+        let depth = self.depth;
         loop {
             if let Some(caret) = self.eat(tpred!(TokenKind::Caret))? {
                 let span = Span {
@@ -502,7 +1041,16 @@ impl<'s, R: CharReader> Parser<'s, R> {
                     },
                     span,
                 }
-            } else if self.eat(tpred!(TokenKind::Dot))?.is_some() {
+            } else if let Some(dot) = self.eat(tpred!(TokenKind::Dot))? {
+                if let Some(index) = self.eat(vpred! {
+                    :t: TokenKind::Integer(_) | TokenKind::BigInteger(_) => t.span,
+                })? {
+                    let span = Span {
+                        start: dot.span.start,
+                        end: index.end,
+                    };
+                    return Err(ParseError::new(ParseErrorKind::NumericAccess, Some(span)));
+                }
                 let (prop_span, prop) =
                     self.require(vpred!(:t: TokenKind::Name(n) => (t.span, n)))?;
 
@@ -532,7 +1080,10 @@ impl<'s, R: CharReader> Parser<'s, R> {
             } else {
                 break;
             }
+            // each suffix puts what came before it a level deeper
+            self.deeper()?;
         }
+        self.depth = depth;
 
         Ok(a)
     }
@@ -556,15 +1107,43 @@ impl<'s, R: CharReader> Parser<'s, R> {
         } else if let Some((span, kind)) = self.eat(vpred! {
             :t: TokenKind::Float(f) => (t.span, ExprKind::Literal(Literal::Float(f))),
             :t: TokenKind::Integer(i) => (t.span, ExprKind::Literal(Literal::Integer(i))),
+            :t: TokenKind::BigInteger(i) => (t.span, ExprKind::Literal(Literal::BigInteger(i))),
             :t: TokenKind::String(s) => (t.span, ExprKind::Literal(Literal::String(s))),
             :t: TokenKind::Name(n) => (t.span, ExprKind::Ident(n)),
         })? {
+            if let ExprKind::Ident(name) = kind {
+                if self.eat(bpred!(TokenKind::Bang))?.is_some() {
+                    return Ok(Some(self.macro_call(name, span)?));
+                }
+            }
             Ok(Some(Expr { span, kind }))
         } else {
             Ok(None)
         }
     }
 
+    /// The error for a `.` where an expression should start, which is a float
+    /// literal without digits before its point if a number follows it.
+    fn leading_point(&mut self, dot: Token<'s>) -> Result<'s, ParseError<'s>> {
+        let digits = self.eat(vpred! {
+            :t: TokenKind::Integer(i) => {
+                let width = (t.span.end - t.span.start) as usize;
+                (t.span, format!("{i:0width$}"))
+            },
+            :t: TokenKind::BigInteger(i) => (t.span, i.0.to_string()),
+        })?;
+        Ok(match digits {
+            Some((span, digits)) if span.start == dot.span.end => ParseError::new(
+                ParseErrorKind::LeadingPoint(format!("0.{digits}")),
+                Some(Span {
+                    start: dot.span.start,
+                    end: span.end,
+                }),
+            ),
+            _ => ParseError::new(ParseErrorKind::Unexpected(Some(dot)), Some(dot.span)),
+        })
+    }
+
     fn variant(&mut self) -> Result<'s, Expr<'s>> {
         let mut items = Vec::with_capacity(1);
         while let Some(pipe) = self.eat(tpred!(TokenKind::Pipe))? {
@@ -603,14 +1182,27 @@ impl<'s, R: CharReader> Parser<'s, R> {
         pred: impl Fn(&Token<'s>) -> Option<BinOp>,
     ) -> Result<'s, Expr<'s>> {
         let mut a = next(self)?;
+        let mut previous = None;
 
+        let depth = self.depth;
         while let Some(op) = self.eat(&pred)? {
+            // each operator puts what came before it a level deeper
+            self.deeper()?;
             let b = next(self)?;
 
             let span = Span {
                 start: a.span.start,
-                end: a.span.end,
+                end: b.span.end,
             };
+            // `a < b < c` would compare the result of `a < b` to `c`
+            if let Some(first) = previous.filter(|first: &BinOp| first.is_comparison()) {
+                if op.is_comparison() {
+                    let meant = format!("a {} b && b {} c", first.symbol(), op.symbol());
+                    let kind = ParseErrorKind::ChainedComparison(meant);
+                    return Err(ParseError::new(kind, Some(span)));
+                }
+            }
+            previous = Some(op);
 
             a = Expr {
                 kind: ExprKind::BinOp {
@@ -621,10 +1213,29 @@ impl<'s, R: CharReader> Parser<'s, R> {
                 span,
             }
         }
+        self.depth = depth;
 
         Ok(a)
     }
 
+    /// Parses something with `parse` a level deeper than what's being parsed.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<'s, T>) -> Result<'s, T> {
+        self.deeper()?;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Goes a level deeper, failing if that's deeper than [MAX_DEPTH].
+    fn deeper(&mut self) -> Result<'s, ()> {
+        if self.depth == MAX_DEPTH {
+            let span = self.tokens.peek()?.map(|token| token.span);
+            return Err(ParseError::new(ParseErrorKind::TooDeep, span));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
     /// Returns `true` if the current token peek satisfies `pred`.
     fn has_peek(&mut self, pred: impl Fn(&Token<'s>) -> Option<()>) -> Result<'s, bool> {
         if let Some(token) = self.tokens.peek()? {
@@ -638,16 +1249,19 @@ impl<'s, R: CharReader> Parser<'s, R> {
         }
     }
 
+    /// Whether the input ends here or the next token satisfies `end_pred`,
+    /// which is where a scope ends.
+    fn at_end(&mut self, end_pred: impl Fn(&Token<'s>) -> Option<()>) -> Result<'s, bool> {
+        Ok(self.tokens.peek()?.is_none() || self.has_peek(end_pred)?)
+    }
+
     /// Requires that the next token exists and satisfies `pred` and errors otherwise.
     ///
     /// Does not consume the token if it does not satisfy `pred`.
     fn require<T>(&mut self, pred: impl Fn(&Token<'s>) -> Option<T>) -> Result<'s, T> {
         match self.maybe_require(pred) {
             Ok(Some(t)) => Ok(t),
-            Ok(None) => Err(ParseError {
-                kind: ParseErrorKind::Unexpected(None),
-                span: None,
-            }),
+            Ok(None) => Err(ParseError::new(ParseErrorKind::Unexpected(None), None)),
             Err(e) => Err(e),
         }
     }
@@ -664,11 +1278,10 @@ impl<'s, R: CharReader> Parser<'s, R> {
                 self.tokens.next()?;
                 Ok(Some(t))
             } else {
-                println!("{}", std::backtrace::Backtrace::capture());
-                Err(ParseError {
-                    span: Some(token.span),
-                    kind: ParseErrorKind::Unexpected(Some(token.clone())),
-                })
+                Err(ParseError::new(
+                    ParseErrorKind::Unexpected(Some(*token)),
+                    Some(token.span),
+                ))
             }
         } else {
             Ok(None)
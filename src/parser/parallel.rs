@@ -0,0 +1,194 @@
+use std::{mem, ops::Range, thread};
+
+use crate::{
+    errors::ErrorStream,
+    tokenizer::{Token, TokenBuffer, TokenKind, TokenSource},
+};
+
+use super::{
+    bpred, parse, vpred, Def, Expr, Module, ParsedScope, Parser, Result, Scope, Use, STACK_SIZE,
+};
+
+/// The fewest tokens that are worth parsing on a thread of their own.
+const MIN_PART: usize = 16 * 1024;
+
+/// Parses a module like [parse], but splits its top level into parts at the
+/// defs and macros in it and parses each part on a thread of its own if it's
+/// large enough for that to pay off. The parts are put back together in
+/// source order, so the module is the same as if it was parsed in one go.
+pub fn parse_parallel<'s>(
+    tokens: TokenBuffer<'s>,
+    errors: &ErrorStream<'s>,
+) -> Result<'s, Module<'s>> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    // the error that the tokenizer stopped at is only returned to the parser
+    // that reads up to it, so the file is parsed in one go to report it
+    let ranges = match tokens.has_error() {
+        true => Vec::new(),
+        false => split(tokens.tokens(), threads),
+    };
+    if ranges.len() <= 1 {
+        return parse(tokens, errors);
+    }
+
+    let parts = thread::scope(|scope| {
+        let handles = ranges
+            .into_iter()
+            .enumerate()
+            .map(|(i, range)| {
+                let slice = tokens.slice(range);
+                // error streams can't be shared between threads, so each
+                // part reports to one of its own
+                thread::Builder::new()
+                    .stack_size(STACK_SIZE)
+                    .spawn_scoped(scope, move || {
+                        let errors = ErrorStream::buffered();
+                        let mut parser = Parser::new(slice, &errors);
+                        let part = parser.part(i == 0);
+                        let macros = mem::take(&mut parser.macros);
+                        part.map(|part| (part, macros, errors.into_diagnostics()))
+                    })
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    let mut uses = Vec::new();
+    let mut defs = Vec::new();
+    let mut body = Vec::new();
+    let mut macros = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut trailing_semi = true;
+    for part in parts {
+        // a part only fails where parsing the file in one go would have
+        // failed too, but doing that gets the exact error, since the end of
+        // a part isn't the end of the file
+        let Ok((part, part_macros, part_diagnostics)) = part else {
+            return parse(tokens, errors);
+        };
+
+        uses.extend(part.uses);
+        defs.extend(part.defs);
+        body.extend(part.body);
+        macros.extend(part_macros);
+        diagnostics.extend(part_diagnostics);
+
+        // the top level ends at the first expression without a semicolon,
+        // so nothing after it is part of the module
+        if !part.semi {
+            trailing_semi = false;
+            break;
+        }
+    }
+
+    for diagnostic in diagnostics {
+        errors.emit(diagnostic);
+    }
+
+    let mut parser = Parser::new(tokens, errors);
+    parser.macros = macros;
+    let scope = Scope {
+        defs: defs.into(),
+        body: body.into(),
+        trailing_semi,
+    };
+    Ok(parser.module(uses, ParsedScope::Scope(scope)))
+}
+
+/// Splits `tokens` into at most `threads` runs of roughly the same length,
+/// each of which but the first starts with a def or macro at the top level.
+/// Returns the ranges of the runs.
+fn split(tokens: &[Token], threads: usize) -> Vec<Range<usize>> {
+    let parts = threads.min(tokens.len() / MIN_PART);
+    if parts <= 1 {
+        return Vec::new();
+    }
+    let size = tokens.len() / parts;
+
+    let mut starts = vec![0];
+    let mut depth = 0usize;
+    for (i, pair) in tokens.windows(2).enumerate() {
+        match pair[0].kind {
+            TokenKind::OpenParen
+            | TokenKind::OpenBracket
+            | TokenKind::OpenBrace
+            | TokenKind::DotOpenBrace => depth += 1,
+            TokenKind::CloseParen | TokenKind::CloseBracket | TokenKind::CloseBrace => {
+                depth = depth.saturating_sub(1)
+            }
+            _ => {}
+        }
+
+        // an item can only start here if the one before it has ended
+        let ends = matches!(pair[0].kind, TokenKind::CloseBrace | TokenKind::Semicolon);
+        let starts_item = matches!(
+            pair[1].kind,
+            TokenKind::Def
+                | TokenKind::Pub
+                | TokenKind::At
+                | TokenKind::Trait
+                | TokenKind::Impl
+                | TokenKind::Macro
+        );
+        if depth == 0 && ends && starts_item && i + 1 >= starts[starts.len() - 1] + size {
+            starts.push(i + 1);
+            if starts.len() == parts {
+                break;
+            }
+        }
+    }
+
+    starts.push(tokens.len());
+    starts.windows(2).map(|w| w[0]..w[1]).collect()
+}
+
+/// The items of one of the parts that the top level of a module is split
+/// into, in source order.
+struct Part<'s> {
+    uses: Vec<Use<'s>>,
+    defs: Vec<Def<'s>>,
+    body: Vec<Expr<'s>>,
+    /// Whether the last expression was followed by a semicolon, which the
+    /// top level ends at if it wasn't.
+    semi: bool,
+}
+
+impl<'s, S: TokenSource<'s>> Parser<'s, '_, S> {
+    /// Parses one of the parts of the top level of a module. Only the first
+    /// part has the module's uses and can start with an expression, which
+    /// has to be followed by a semicolon since there are more items after it.
+    fn part(&mut self, first: bool) -> Result<'s, Part<'s>> {
+        let mut uses = Vec::new();
+        if first {
+            uses = self.uses()?;
+            if self.tokens.peek()?.is_some()
+                && !self.has_peek(bpred!(
+                    TokenKind::Def
+                        | TokenKind::Pub
+                        | TokenKind::At
+                        | TokenKind::Trait
+                        | TokenKind::Impl
+                        | TokenKind::Macro
+                ))?
+            {
+                let expr = self.tuple()?;
+                self.exprs.push(expr);
+                self.require(bpred!(TokenKind::Semicolon))?;
+            }
+        }
+
+        let semi = self.scope_items(vpred!())?;
+
+        Ok(Part {
+            uses,
+            defs: mem::take(&mut self.defs),
+            body: mem::take(&mut self.exprs),
+            semi,
+        })
+    }
+}
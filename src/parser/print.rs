@@ -0,0 +1,158 @@
+//! A readable rendering of the syntax tree, with a node on each line and its
+//! children indented below it, which shows how an expression was grouped.
+
+use std::fmt::{self, Display, Formatter};
+
+use super::*;
+
+impl Display for Expr<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        expr(f, self, 0)
+    }
+}
+
+fn line(f: &mut Formatter<'_>, indent: usize, text: impl Display) -> fmt::Result {
+    writeln!(f, "{:indent$}{text}", "", indent = indent)
+}
+
+fn expr(f: &mut Formatter<'_>, e: &Expr, indent: usize) -> fmt::Result {
+    let inner = indent + 2;
+    match &e.kind {
+        ExprKind::Object(s) => {
+            line(f, indent, "Object")?;
+            scope(f, s, inner)
+        }
+        ExprKind::Block(s) => {
+            line(f, indent, "Block")?;
+            scope(f, s, inner)
+        }
+        ExprKind::Lambda { arg, body } => {
+            line(f, indent, "Lambda")?;
+            expr(f, arg, inner)?;
+            expr(f, body, inner)
+        }
+        ExprKind::BinOp { op, lhs, rhs } => {
+            line(f, indent, format_args!("BinOp {op:?}"))?;
+            expr(f, lhs, inner)?;
+            expr(f, rhs, inner)
+        }
+        ExprKind::UnOp { op, arg } => {
+            line(f, indent, format_args!("UnOp {op:?}"))?;
+            expr(f, arg, inner)
+        }
+        ExprKind::Access { expr: e, prop } => match prop {
+            AccessRhs::Prop(name) => {
+                line(f, indent, format_args!("Access .{}", name.0))?;
+                expr(f, e, inner)
+            }
+            AccessRhs::Expr(index) => {
+                line(f, indent, "Access")?;
+                expr(f, e, inner)?;
+                expr(f, index, inner)
+            }
+        },
+        ExprKind::Branch {
+            cond,
+            on_true,
+            on_false,
+        } => {
+            line(f, indent, "Branch")?;
+            expr(f, cond, inner)?;
+            expr(f, on_true, inner)?;
+            match on_false {
+                Some(on_false) => expr(f, on_false, inner),
+                None => Ok(()),
+            }
+        }
+        ExprKind::Match { scrutinee, arms } => {
+            line(f, indent, "Match")?;
+            expr(f, scrutinee, inner)?;
+            for arm in arms.iter() {
+                line(f, inner, "Arm")?;
+                expr(f, &arm.pattern, inner + 2)?;
+                expr(f, &arm.body, inner + 2)?;
+            }
+            Ok(())
+        }
+        ExprKind::Tuple { items } => {
+            line(f, indent, "Tuple")?;
+            items.iter().try_for_each(|item| expr(f, item, inner))
+        }
+        ExprKind::Apply { a, b } => {
+            line(f, indent, "Apply")?;
+            expr(f, a, inner)?;
+            expr(f, b, inner)
+        }
+        ExprKind::TypeAssertion { a, b } => {
+            line(f, indent, "TypeAssertion")?;
+            expr(f, a, inner)?;
+            expr(f, b, inner)
+        }
+        ExprKind::Arrow { arg, ret } => {
+            line(f, indent, "Arrow")?;
+            expr(f, arg, inner)?;
+            expr(f, ret, inner)
+        }
+        ExprKind::Declaration(ty) => {
+            line(f, indent, "Declaration")?;
+            expr(f, ty, inner)
+        }
+        ExprKind::Trait { param, defs, .. } => {
+            line(f, indent, format_args!("Trait ({})", param.0))?;
+            self::defs(f, defs, inner)
+        }
+        ExprKind::Impl { ty, defs } => {
+            line(f, indent, "Impl")?;
+            expr(f, ty, inner)?;
+            self::defs(f, defs, inner)
+        }
+        ExprKind::Variant(items) => {
+            line(f, indent, "Variant")?;
+            for item in items.iter() {
+                line(f, inner, format_args!("|{}", item.name.0))?;
+                if let Some(value) = &item.value {
+                    expr(f, value, inner + 2)?;
+                }
+            }
+            Ok(())
+        }
+        ExprKind::Ident(name) => line(f, indent, format_args!("Ident {}", name.0)),
+        ExprKind::Literal(lit) => match lit {
+            Literal::Float(x) => line(f, indent, format_args!("Float {x:?}")),
+            Literal::Integer(i) => line(f, indent, format_args!("Integer {i}")),
+            Literal::BigInteger(digits) => line(f, indent, format_args!("Integer {}", digits.0)),
+            Literal::String(s) => line(f, indent, format_args!("String {:?}", s.0)),
+        },
+        ExprKind::MacroCall { name, args } => {
+            line(f, indent, format_args!("MacroCall {}!", name.0))?;
+            args.iter().try_for_each(|arg| expr(f, arg, inner))
+        }
+    }
+}
+
+fn scope(f: &mut Formatter<'_>, s: &Scope, indent: usize) -> fmt::Result {
+    defs(f, &s.defs, indent)?;
+    s.body.iter().try_for_each(|e| expr(f, e, indent))?;
+    if s.trailing_semi && !s.body.is_empty() {
+        line(f, indent, ";")?;
+    }
+    Ok(())
+}
+
+fn defs(f: &mut Formatter<'_>, defs: &[Def], indent: usize) -> fmt::Result {
+    for def in defs.iter() {
+        let attributes = def.attributes.iter().map(|attribute| match attribute.arg {
+            Some(arg) => format!("@{}({:?}) ", attribute.name.0, arg.0),
+            None => format!("@{} ", attribute.name.0),
+        });
+        let public = if def.public { "pub " } else { "" };
+        let attributes = attributes.collect::<String>();
+        line(
+            f,
+            indent,
+            format_args!("{attributes}{public}Def {}", def.name.0),
+        )?;
+        expr(f, &def.value, indent + 2)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,258 @@
+//! A compact s-expression form of the syntax tree, meant to be compared
+//! against by tests outside of the compiler. It leaves out spans, so that
+//! only changes to the tree itself change it.
+//!
+//! The first line is `;; radi-ast N`, where `N` is the version of the
+//! format, which changes whenever the output for the same tree does. Each
+//! `use` of the module is on a line of its own, followed by each def and
+//! expression at its top level:
+//!
+//! ```text
+//! ;; radi-ast 2
+//! (use std.io)
+//! (def main (lambda (tuple) (block (apply print "hi"))))
+//! ```
+//!
+//! Names and integers are written as they are, and floats in decimal with at
+//! least one digit after the point, never with an exponent. Strings are in
+//! double quotes, with `\"`, `\\`, `\n`, `\r` and `\t` escaped as such and any
+//! other control character as `\u{hex}`. Nodes are written as
+//! `(kind children...)`, with binary operators as their symbol, such as
+//! `(+ a b)`. The defs of a scope come before its expressions, and a
+//! trailing `;` is kept as the atom `;`.
+//!
+//! Unlike the `Debug` form of the tree, the format is kept stable: the
+//! version only changes along with the major version of radi, or the minor
+//! one while that is 0. New syntax only adds kinds of node, so a file that
+//! parsed before is written the same way until then.
+
+use std::fmt::Write;
+
+use super::*;
+
+/// The version of the format, on its first line.
+const VERSION: u32 = 2;
+
+/// The s-expressions of a parsed module, one line per item.
+pub fn sexp(module: &Module) -> String {
+    let mut out = format!(";; radi-ast {VERSION}\n");
+    for item in module.uses.iter() {
+        let path = item.path.iter().map(|segment| segment.name.0);
+        let _ = writeln!(out, "(use {})", path.collect::<Vec<_>>().join("."));
+    }
+    match &module.body.kind {
+        ExprKind::Object(scope) => {
+            for def_ in scope.defs.iter() {
+                def(&mut out, def_);
+                out.push('\n');
+            }
+            for body in scope.body.iter() {
+                expr(&mut out, body);
+                out.push('\n');
+            }
+            if scope.trailing_semi && !scope.body.is_empty() {
+                out.push_str(";\n");
+            }
+        }
+        // an empty module
+        ExprKind::Tuple { items } if items.is_empty() => {}
+        _ => {
+            expr(&mut out, &module.body);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// The s-expression of a single expression, in the same form as in [sexp]
+/// but without the version line.
+pub fn expr_sexp(e: &Expr) -> String {
+    let mut out = String::new();
+    expr(&mut out, e);
+    out
+}
+
+fn expr(out: &mut String, e: &Expr) {
+    match &e.kind {
+        ExprKind::Object(s) => list(out, "object", |out| scope(out, s)),
+        ExprKind::Block(s) => list(out, "block", |out| scope(out, s)),
+        ExprKind::Lambda { arg, body } => list(out, "lambda", |out| {
+            item(out, arg);
+            item(out, body);
+        }),
+        ExprKind::BinOp { op, lhs, rhs } => list(out, op.symbol(), |out| {
+            item(out, lhs);
+            item(out, rhs);
+        }),
+        ExprKind::UnOp { op, arg } => list(out, un_op(op), |out| item(out, arg)),
+        ExprKind::Access { expr: e, prop } => match prop {
+            AccessRhs::Prop(name) => list(out, ".", |out| {
+                item(out, e);
+                let _ = write!(out, " {}", name.0);
+            }),
+            AccessRhs::Expr(index) => list(out, "index", |out| {
+                item(out, e);
+                item(out, index);
+            }),
+        },
+        ExprKind::Branch {
+            cond,
+            on_true,
+            on_false,
+        } => list(out, "case", |out| {
+            item(out, cond);
+            item(out, on_true);
+            if let Some(on_false) = on_false {
+                item(out, on_false);
+            }
+        }),
+        ExprKind::Match { scrutinee, arms } => list(out, "match", |out| {
+            item(out, scrutinee);
+            for arm in arms.iter() {
+                out.push(' ');
+                list(out, "=>", |out| {
+                    item(out, &arm.pattern);
+                    item(out, &arm.body);
+                });
+            }
+        }),
+        ExprKind::Tuple { items } => list(out, "tuple", |out| {
+            items.iter().for_each(|e| item(out, e));
+        }),
+        ExprKind::Apply { a, b } => list(out, "apply", |out| {
+            item(out, a);
+            item(out, b);
+        }),
+        ExprKind::TypeAssertion { a, b } => list(out, "::", |out| {
+            item(out, a);
+            item(out, b);
+        }),
+        ExprKind::Arrow { arg, ret } => list(out, "->", |out| {
+            item(out, arg);
+            item(out, ret);
+        }),
+        ExprKind::Declaration(ty) => list(out, "declare", |out| item(out, ty)),
+        ExprKind::Trait { param, defs, .. } => list(out, "trait", |out| {
+            let _ = write!(out, " {}", param.0);
+            for d in defs.iter() {
+                out.push(' ');
+                def(out, d);
+            }
+        }),
+        ExprKind::Impl { ty, defs } => list(out, "impl", |out| {
+            item(out, ty);
+            for d in defs.iter() {
+                out.push(' ');
+                def(out, d);
+            }
+        }),
+        ExprKind::Variant(items) => list(out, "variant", |out| {
+            for variant in items.iter() {
+                let _ = write!(out, " ({}", variant.name.0);
+                if let Some(value) = &variant.value {
+                    item(out, value);
+                }
+                out.push(')');
+            }
+        }),
+        ExprKind::Ident(name) => out.push_str(name.0),
+        ExprKind::Literal(lit) => match lit {
+            Literal::Float(x) => {
+                // `Display` never uses an exponent, but leaves off the point
+                // of a whole number
+                out.push_str(&x.to_string());
+                if x.fract() == 0.0 {
+                    out.push_str(".0");
+                }
+            }
+            Literal::Integer(i) => {
+                let _ = write!(out, "{i}");
+            }
+            Literal::BigInteger(digits) => out.push_str(digits.0),
+            Literal::String(s) => string(out, s.0),
+        },
+        ExprKind::MacroCall { name, args } => list(out, "macro-call", |out| {
+            let _ = write!(out, " {}", name.0);
+            args.iter().for_each(|arg| item(out, arg));
+        }),
+    }
+}
+
+/// Writes `s` in double quotes, escaped as the format says rather than as
+/// `Debug` does, since that changes between versions of Rust.
+fn string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{{{:x}}}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Writes `(head ...)`, with `items` writing what comes after the head.
+fn list(out: &mut String, head: &str, items: impl FnOnce(&mut String)) {
+    out.push('(');
+    out.push_str(head);
+    items(out);
+    out.push(')');
+}
+
+/// Writes an expression that follows another item of a list.
+fn item(out: &mut String, e: &Expr) {
+    out.push(' ');
+    expr(out, e);
+}
+
+fn scope(out: &mut String, s: &Scope) {
+    for d in s.defs.iter() {
+        out.push(' ');
+        def(out, d);
+    }
+    s.body.iter().for_each(|e| item(out, e));
+    if s.trailing_semi && !s.body.is_empty() {
+        out.push_str(" ;");
+    }
+}
+
+/// Writes `(def name value)`, with the def's attributes and `pub` before
+/// its name.
+fn def(out: &mut String, d: &Def) {
+    out.push_str("(def");
+    for attribute in d.attributes.iter() {
+        match attribute.arg {
+            Some(arg) => {
+                let _ = write!(out, " (@{} ", attribute.name.0);
+                string(out, arg.0);
+                out.push(')');
+            }
+            None => {
+                let _ = write!(out, " @{}", attribute.name.0);
+            }
+        }
+    }
+    if d.public {
+        out.push_str(" pub");
+    }
+    let _ = write!(out, " {}", d.name.0);
+    item(out, &d.value);
+    out.push(')');
+}
+
+fn un_op(op: &UnOp) -> &'static str {
+    match op {
+        UnOp::Not => "not",
+        UnOp::Set => "set",
+        UnOp::Val => "val",
+        UnOp::Ref => "ref",
+        UnOp::Deref => "deref",
+    }
+}
@@ -1,4 +1,7 @@
-use super::*;
+use std::{collections::BTreeMap, fmt::Write};
+
+use super::{expand::children_ref, *};
+use crate::source_map::SourceMap;
 
 pub fn ast_size(expr: &Expr) -> usize {
     std::mem::size_of::<Expr>()
@@ -26,12 +29,30 @@ pub fn ast_size(expr: &Expr) -> usize {
                     + ast_size(on_true)
                     + on_false.as_ref().map_or(0, |on_false| ast_size(on_false))
             }
+            ExprKind::Match { scrutinee, arms } => {
+                ast_size(scrutinee)
+                    + arms
+                        .iter()
+                        .map(|arm| {
+                            std::mem::size_of::<MatchArm>()
+                                + ast_size(&arm.pattern)
+                                + ast_size(&arm.body)
+                        })
+                        .sum::<usize>()
+            }
             ExprKind::Tuple { items: exprs } => exprs.iter().map(ast_size).sum(),
             ExprKind::Apply { a, b } => ast_size(a) + ast_size(b),
             ExprKind::TypeAssertion { a, b } => ast_size(a) + ast_size(b),
+            ExprKind::Arrow { arg, ret } => ast_size(arg) + ast_size(ret),
+            ExprKind::Declaration(ty) => ast_size(ty),
+            ExprKind::Trait { defs, .. } => defs.iter().map(def_size).sum(),
+            ExprKind::Impl { ty, defs } => ast_size(ty) + defs.iter().map(def_size).sum::<usize>(),
             ExprKind::Variant(its) => its.iter().map(varit_size).sum(),
             ExprKind::Ident(i) => i.0.len(),
             ExprKind::Literal(Literal::String(i)) => i.0.len(),
+            ExprKind::MacroCall { name, args } => {
+                name.0.len() + args.iter().map(ast_size).sum::<usize>()
+            }
             _ => 0,
         }
 }
@@ -43,3 +64,126 @@ fn def_size(def: &Def) -> usize {
 fn varit_size(varit: &VariantItem) -> usize {
     std::mem::size_of::<VariantItem>() + varit.value.as_ref().map(ast_size).unwrap_or(0)
 }
+
+/// Every def in `expr`, however deeply nested, in source order.
+pub fn defs<'a, 's>(expr: &'a Expr<'s>) -> Vec<&'a Def<'s>> {
+    fn walk<'a, 's>(expr: &'a Expr<'s>, out: &mut Vec<&'a Def<'s>>) {
+        match &expr.kind {
+            ExprKind::Object(scope) | ExprKind::Block(scope) => out.extend(scope.defs.iter()),
+            ExprKind::Trait { defs, .. } | ExprKind::Impl { defs, .. } => out.extend(defs.iter()),
+            _ => {}
+        }
+        children_ref(expr, &mut |child| walk(child, out));
+    }
+    let mut out = Vec::new();
+    walk(expr, &mut out);
+    out.sort_by_key(|def| def.span.start);
+    out
+}
+
+/// How many of the largest subtrees [AstStats] keeps.
+const LARGEST: usize = 10;
+
+/// The shape of a tree of expressions, from [ast_stats].
+#[derive(Debug, Default)]
+pub struct AstStats {
+    pub nodes: usize,
+    /// The depth of the deepest expression, where the root is at 0.
+    pub max_depth: usize,
+    /// By the name of each [ExprKind].
+    pub kinds: BTreeMap<&'static str, KindStats>,
+    /// The largest subtrees under the root, largest first.
+    pub largest: Vec<Subtree>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct KindStats {
+    pub count: usize,
+    /// The depths of the expressions of the kind, summed.
+    pub total_depth: usize,
+}
+
+impl KindStats {
+    pub fn average_depth(&self) -> f64 {
+        self.total_depth as f64 / self.count.max(1) as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Subtree {
+    pub kind: &'static str,
+    pub span: Span,
+    pub nodes: usize,
+}
+
+/// Counts the expressions in `expr` by their kind, and finds its largest
+/// subtrees.
+pub fn ast_stats(expr: &Expr) -> AstStats {
+    let mut stats = AstStats::default();
+    let mut subtrees = Vec::new();
+    stats.nodes = count(expr, 0, &mut stats, &mut subtrees);
+
+    // the root is every node, so it isn't interesting
+    subtrees.pop();
+    subtrees.sort_by_key(|subtree: &Subtree| std::cmp::Reverse(subtree.nodes));
+    subtrees.truncate(LARGEST);
+    stats.largest = subtrees;
+    stats
+}
+
+/// Adds `expr` to the stats, returning how many nodes it has. Each subtree
+/// is pushed after the subtrees within it.
+fn count(expr: &Expr, depth: usize, stats: &mut AstStats, subtrees: &mut Vec<Subtree>) -> usize {
+    let kind = stats.kinds.entry(expr.kind.name()).or_default();
+    kind.count += 1;
+    kind.total_depth += depth;
+    stats.max_depth = stats.max_depth.max(depth);
+
+    let mut nodes = 1;
+    children_ref(expr, &mut |child| {
+        nodes += count(child, depth + 1, stats, subtrees);
+    });
+    subtrees.push(Subtree {
+        kind: expr.kind.name(),
+        span: expr.span,
+        nodes,
+    });
+    nodes
+}
+
+impl AstStats {
+    /// The stats as tables, with the subtrees located in `source_map`.
+    pub fn report(&self, source_map: &SourceMap) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{} nodes, at most {} deep", self.nodes, self.max_depth);
+        let _ = writeln!(
+            out,
+            "\n{:<14}{:>8}{:>8}{:>11}",
+            "kind", "count", "%", "avg depth"
+        );
+        // the most common kinds first
+        let mut kinds = self.kinds.iter().collect::<Vec<_>>();
+        kinds.sort_by_key(|&(name, kind)| (std::cmp::Reverse(kind.count), *name));
+        for (name, kind) in kinds {
+            let percent = kind.count as f64 * 100.0 / self.nodes as f64;
+            let depth = kind.average_depth();
+            let _ = writeln!(
+                out,
+                "{name:<14}{:>8}{percent:>8.1}{depth:>11.2}",
+                kind.count
+            );
+        }
+        let _ = writeln!(out, "\nlargest subtrees");
+        for subtree in &self.largest {
+            let location = match source_map.locate(subtree.span) {
+                Some(file) => {
+                    let (line, col) = file.line_col(subtree.span.start);
+                    format!("{}:{line}:{col}", file.path.display())
+                }
+                None => "unknown".to_string(),
+            };
+            let _ = writeln!(out, "{:<14}{:>8}  {location}", subtree.kind, subtree.nodes);
+        }
+        out
+    }
+}
@@ -0,0 +1,757 @@
+//! The phases of compilation, run in order by a [PassManager].
+//!
+//! Each pass reads what earlier passes produced from [Artifacts] and adds
+//! its own results. A pass whose inputs are missing, because an earlier
+//! pass was disabled or couldn't produce them, does nothing, so disabling a
+//! pass also disables everything that depends on it.
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    c,
+    char_reader::IoCharReader,
+    effects,
+    errors::ErrorStream,
+    eval, highlight,
+    hir::{self, Program, VarId},
+    lints::{self, LintConfig},
+    parse_manager::ParseManager,
+    parser,
+    profile::{self, Timing},
+    resolver::{self, Resolution, ResolveOptions, SymbolId},
+    source_map::{FileId, SourceMap},
+    string_storage::StringStorage,
+    tokenizer::{Span, Tokens},
+    typeck::{self, Typing},
+    wasm,
+};
+
+/// What every pass has access to.
+pub struct Context<'s, 'c> {
+    pub storage: &'s StringStorage,
+    pub errors: &'s ErrorStream<'s>,
+    pub manager: &'c mut ParseManager<'s>,
+}
+
+impl Context<'_, '_> {
+    pub fn source_map(&self) -> &SourceMap {
+        self.manager.source_map()
+    }
+}
+
+/// The results of the passes that have run so far.
+#[derive(Default)]
+pub struct Artifacts<'s> {
+    pub entry: Option<FileId>,
+    pub resolved: Option<Box<[resolver::Module<'s>]>>,
+    pub resolution: Option<Resolution<'s>>,
+    pub typing: Option<Typing<'s>>,
+    pub program: Option<Program<'s>>,
+    /// The `main` def of the entry module, which is called after the
+    /// top-level expressions are evaluated.
+    pub main: Option<VarId>,
+    /// The fixes for what the lints found.
+    pub fixes: Vec<lints::Fix>,
+}
+
+pub trait Pass<'s> {
+    /// The name the pass is enabled and disabled with.
+    fn name(&self) -> &'static str;
+
+    /// The names of the passes whose artifacts this one works from, without
+    /// which it does nothing.
+    fn needs(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn run(&mut self, cx: &mut Context<'s, '_>, artifacts: &mut Artifacts<'s>);
+}
+
+/// Runs passes in the order they were added.
+#[derive(Default)]
+pub struct PassManager<'s, 'p> {
+    passes: Vec<(Box<dyn Pass<'s> + 'p>, bool)>,
+    /// How each pass that ran last time performed, if profiling is enabled.
+    timings: Vec<Timing>,
+}
+
+impl<'s, 'p> PassManager<'s, 'p> {
+    pub fn new() -> PassManager<'s, 'p> {
+        PassManager {
+            passes: Vec::new(),
+            timings: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, pass: impl Pass<'s> + 'p) {
+        self.passes.push((Box::new(pass), true));
+    }
+
+    /// The names of the passes, in the order they run.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|(pass, _)| pass.name()).collect()
+    }
+
+    /// Enables or disables the pass with the given name, returning whether
+    /// there is one.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        let mut found = false;
+        for (pass, on) in &mut self.passes {
+            if pass.name() == name {
+                *on = enabled;
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Disables the passes that need a disabled pass, directly or through
+    /// another pass, since they would do nothing without it. Returns the
+    /// names of those that were enabled until now, each with the name of the
+    /// disabled pass that it depends on.
+    pub fn disable_dependents(&mut self) -> Vec<(&'static str, &'static str)> {
+        // each pass that won't run, and the pass that was disabled to begin with
+        let mut missing: Vec<(&'static str, &'static str)> = Vec::new();
+        let mut disabled = Vec::new();
+        for (pass, enabled) in &mut self.passes {
+            let cause = pass.needs().iter().find_map(|need| {
+                let (_, cause) = missing.iter().find(|(name, _)| name == need)?;
+                Some(*cause)
+            });
+            match (*enabled, cause) {
+                (true, Some(cause)) => {
+                    *enabled = false;
+                    disabled.push((pass.name(), cause));
+                    missing.push((pass.name(), cause));
+                }
+                (true, None) => {}
+                (false, _) => missing.push((pass.name(), pass.name())),
+            }
+        }
+        disabled
+    }
+
+    pub fn run(&mut self, cx: &mut Context<'s, '_>) -> Artifacts<'s> {
+        let mut artifacts = Artifacts::default();
+        self.timings.clear();
+        for (pass, enabled) in &mut self.passes {
+            if !*enabled {
+                continue;
+            }
+            if profile::enabled() {
+                let start = profile::Snapshot::now();
+                pass.run(cx, &mut artifacts);
+                self.timings.push(start.finish(pass.name()));
+            } else {
+                pass.run(cx, &mut artifacts);
+            }
+            // diagnostics held by a deferred stream are shown after the pass
+            // that reported them, while the source they point at is current
+            cx.errors.render(cx.source_map());
+        }
+        artifacts
+    }
+
+    /// How each pass performed the last time they ran. This is empty unless
+    /// profiling is enabled.
+    pub fn timings(&self) -> &[Timing] {
+        &self.timings
+    }
+}
+
+/// Loads the entry module and everything it imports, along with the
+/// prelude module if it's used. Modules that are already loaded aren't
+/// parsed again, so in watch mode only the files updated since the last run
+/// are.
+pub struct Parse {
+    pub path: PathBuf,
+    pub prelude: bool,
+    /// Whether every file of the project is loaded too, even those the
+    /// entry doesn't use, so that the resolution covers all of them.
+    pub project: bool,
+}
+
+impl<'s> Pass<'s> for Parse {
+    fn name(&self) -> &'static str {
+        "parse"
+    }
+
+    fn run(&mut self, cx: &mut Context<'s, '_>, artifacts: &mut Artifacts<'s>) {
+        if self.prelude {
+            cx.manager.load_prelude();
+        }
+        artifacts.entry = match cx.source_map().find(&self.path) {
+            Some(entry) => Some(entry),
+            None => cx.manager.load(&self.path),
+        };
+        if self.project && artifacts.entry.is_some() {
+            cx.manager.load_project();
+        }
+    }
+}
+
+pub struct Resolve {
+    pub options: ResolveOptions,
+}
+
+impl<'s> Pass<'s> for Resolve {
+    fn name(&self) -> &'static str {
+        "resolve"
+    }
+
+    fn needs(&self) -> &'static [&'static str] {
+        &["parse"]
+    }
+
+    fn run(&mut self, cx: &mut Context<'s, '_>, artifacts: &mut Artifacts<'s>) {
+        if artifacts.entry.is_none() {
+            return;
+        }
+        let (resolved, resolution) = resolver::resolve(cx.manager, &self.options, cx.errors);
+        artifacts.resolved = Some(resolved);
+        artifacts.resolution = Some(resolution);
+    }
+}
+
+/// Checks the uses of `set`, `val` and references.
+pub struct Effects;
+
+impl<'s> Pass<'s> for Effects {
+    fn name(&self) -> &'static str {
+        "effects"
+    }
+
+    fn needs(&self) -> &'static [&'static str] {
+        &["resolve"]
+    }
+
+    fn run(&mut self, cx: &mut Context<'s, '_>, artifacts: &mut Artifacts<'s>) {
+        if let (Some(resolved), Some(resolution)) = (&artifacts.resolved, &artifacts.resolution) {
+            effects::check(resolved, resolution, cx.errors);
+        }
+    }
+}
+
+pub struct Typecheck;
+
+impl<'s> Pass<'s> for Typecheck {
+    fn name(&self) -> &'static str {
+        "typeck"
+    }
+
+    fn needs(&self) -> &'static [&'static str] {
+        &["resolve"]
+    }
+
+    fn run(&mut self, cx: &mut Context<'s, '_>, artifacts: &mut Artifacts<'s>) {
+        let (Some(resolved), Some(resolution)) = (&artifacts.resolved, &artifacts.resolution)
+        else {
+            return;
+        };
+        artifacts.typing = Some(typeck::check(resolved, resolution, cx.errors));
+    }
+}
+
+/// Runs the lints over every module but the prelude. Their fixes are only
+/// kept if there were no errors before, since a fix may need to know every
+/// use of a name.
+pub struct Lints {
+    /// How the project's manifest configures the lints.
+    pub config: BTreeMap<String, LintConfig>,
+}
+
+impl<'s> Pass<'s> for Lints {
+    fn name(&self) -> &'static str {
+        "lint"
+    }
+
+    fn needs(&self) -> &'static [&'static str] {
+        &["resolve"]
+    }
+
+    fn run(&mut self, cx: &mut Context<'s, '_>, artifacts: &mut Artifacts<'s>) {
+        let (Some(resolved), Some(resolution)) = (&artifacts.resolved, &artifacts.resolution)
+        else {
+            return;
+        };
+        let prelude = cx.manager.prelude();
+        let modules = resolved
+            .iter()
+            .filter(|module| Some(module.file) != prelude)
+            .collect::<Vec<_>>();
+        let clean = cx.errors.error_count() == 0;
+        let fixes = lints::check(
+            &modules,
+            resolution,
+            cx.manager.source_map(),
+            cx.storage,
+            &self.config,
+            cx.errors,
+        );
+        if clean {
+            artifacts.fixes = fixes;
+        }
+    }
+}
+
+/// Lowers the program to the HIR, which only happens if there were no
+/// errors before it.
+pub struct Lower;
+
+impl<'s> Pass<'s> for Lower {
+    fn name(&self) -> &'static str {
+        "lower"
+    }
+
+    fn needs(&self) -> &'static [&'static str] {
+        &["resolve", "typeck"]
+    }
+
+    fn run(&mut self, cx: &mut Context<'s, '_>, artifacts: &mut Artifacts<'s>) {
+        let (Some(entry), Some(resolved), Some(resolution), Some(typing)) = (
+            artifacts.entry,
+            &artifacts.resolved,
+            &artifacts.resolution,
+            &artifacts.typing,
+        ) else {
+            return;
+        };
+        if cx.errors.error_count() > 0 {
+            return;
+        }
+
+        let program = hir::lower(resolved, resolution, typing, cx.storage, cx.errors);
+        artifacts.main = entry_main(resolved, resolution, entry).map(|s| program.symbols[&s]);
+        artifacts.program = Some(program);
+    }
+}
+
+fn entry_main(
+    resolved: &[resolver::Module],
+    resolution: &Resolution,
+    entry: FileId,
+) -> Option<SymbolId> {
+    let module = resolved.iter().find(|m| m.file == entry)?;
+    let resolver::ExprKind::Object(scope) = &module.body.kind else {
+        return None;
+    };
+    scope
+        .defs
+        .iter()
+        .map(|def| def.symbol)
+        .find(|&symbol| resolution.symbol(symbol).name.0 == "main")
+}
+
+/// Evaluates the `@const` defs.
+pub struct ConstEval;
+
+impl<'s> Pass<'s> for ConstEval {
+    fn name(&self) -> &'static str {
+        "consteval"
+    }
+
+    fn needs(&self) -> &'static [&'static str] {
+        &["lower"]
+    }
+
+    fn run(&mut self, cx: &mut Context<'s, '_>, artifacts: &mut Artifacts<'s>) {
+        if let Some(program) = &mut artifacts.program {
+            hir::evaluate_consts(program, cx.storage, cx.errors);
+        }
+    }
+}
+
+pub struct Fold;
+
+impl<'s> Pass<'s> for Fold {
+    fn name(&self) -> &'static str {
+        "fold"
+    }
+
+    fn needs(&self) -> &'static [&'static str] {
+        &["lower"]
+    }
+
+    fn run(&mut self, cx: &mut Context<'s, '_>, artifacts: &mut Artifacts<'s>) {
+        if let Some(program) = &mut artifacts.program {
+            hir::fold(program, cx.errors);
+        }
+    }
+}
+
+/// Where a pass writes what it produces.
+#[derive(Debug, Clone)]
+pub enum Output {
+    Stdout,
+    File(PathBuf),
+}
+
+impl Output {
+    fn write(&self, bytes: &[u8]) -> std::io::Result<()> {
+        match self {
+            Output::Stdout => std::io::stdout().write_all(bytes),
+            Output::File(path) => std::fs::write(path, bytes),
+        }
+    }
+}
+
+/// Writes the tokens of the entry file, one per line with where it starts,
+/// its kind and its source.
+pub struct DumpTokens {
+    pub output: Output,
+}
+
+impl<'s> Pass<'s> for DumpTokens {
+    fn name(&self) -> &'static str {
+        "dump-tokens"
+    }
+
+    fn needs(&self) -> &'static [&'static str] {
+        &["parse"]
+    }
+
+    fn run(&mut self, cx: &mut Context<'s, '_>, artifacts: &mut Artifacts<'s>) {
+        let Some(entry) = artifacts.entry else {
+            return;
+        };
+        let file = cx.source_map().file(entry);
+        let chars =
+            IoCharReader::starting_at(file.src.as_bytes(), file.start).sized_for(file.src.len());
+        let mut tokens = Tokens::of(chars, cx.storage);
+        let mut out = String::new();
+        // the file was already parsed, so any error in it was reported
+        while let Ok(Some(token)) = tokens.next() {
+            let (line, col) = file.line_col(token.span.start);
+            // without the contents of names and literals, which are in the
+            // source next to it
+            let kind = format!("{:?}", token.kind);
+            let kind = kind.split('(').next().unwrap_or_default();
+            let src = &file.src[file.range(token.span)];
+            out.push_str(&format!("{line}:{col} {kind} `{src}`\n"));
+        }
+        if let Err(err) = self.output.write(out.as_bytes()) {
+            cx.errors.error((err, None));
+        }
+    }
+}
+
+/// Writes the HIR as it is at this point in the pipeline.
+pub struct DumpHir {
+    pub output: Output,
+}
+
+impl<'s> Pass<'s> for DumpHir {
+    fn name(&self) -> &'static str {
+        "dump-hir"
+    }
+
+    fn needs(&self) -> &'static [&'static str] {
+        &["lower"]
+    }
+
+    fn run(&mut self, cx: &mut Context<'s, '_>, artifacts: &mut Artifacts<'s>) {
+        if let Some(program) = &artifacts.program {
+            if let Err(err) = self.output.write(program.to_string().as_bytes()) {
+                cx.errors.error((err, None));
+            }
+        }
+    }
+}
+
+/// The ways [DumpAst] can write the AST.
+#[derive(Debug, Clone, Copy)]
+pub enum AstFormat {
+    /// A node on each line, with its children indented below it.
+    Tree,
+    /// A Graphviz graph.
+    Dot,
+    /// S-expressions, one line per item of the module.
+    Sexp,
+    /// JSON, for other tools to read.
+    Json,
+    /// How many nodes of each kind there are, and where the largest
+    /// subtrees are, rather than the tree itself.
+    Stats,
+}
+
+/// Writes the AST of the entry module, as it is once its macros are
+/// expanded.
+pub struct DumpAst {
+    pub format: AstFormat,
+    pub output: Output,
+}
+
+impl<'s> Pass<'s> for DumpAst {
+    fn name(&self) -> &'static str {
+        "dump-ast"
+    }
+
+    fn needs(&self) -> &'static [&'static str] {
+        &["parse"]
+    }
+
+    fn run(&mut self, cx: &mut Context<'s, '_>, artifacts: &mut Artifacts<'s>) {
+        let Some(entry) = artifacts.entry else {
+            return;
+        };
+        let Some(module) = cx.manager.module(entry) else {
+            return;
+        };
+        let ast = &module.ast;
+        let out = match self.format {
+            AstFormat::Tree => {
+                let mut out = String::new();
+                for item in ast.uses.iter() {
+                    let path = item.path.iter().map(|segment| segment.name.0);
+                    out.push_str(&format!("Use {}\n", path.collect::<Vec<_>>().join(".")));
+                }
+                out + &ast.body.to_string()
+            }
+            AstFormat::Dot => parser::dot(ast, cx.source_map()),
+            AstFormat::Sexp => parser::sexp(ast),
+            AstFormat::Json => parser::json(ast, cx.source_map().file(entry).start),
+            AstFormat::Stats => parser::utils::ast_stats(&ast.body).report(cx.source_map()),
+        };
+        if let Err(err) = self.output.write(out.as_bytes()) {
+            cx.errors.error((err, None));
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Backend {
+    /// Runs the program with the interpreter, writing its profile to
+    /// `profile` if that's given.
+    Interpret {
+        options: eval::HeapOptions,
+        profile: Option<PathBuf>,
+    },
+    /// Compiles the program to native code and runs it.
+    #[cfg(feature = "jit")]
+    Jit,
+    /// Compiles the program to native code without running it, and writes
+    /// how big monomorphizing made the code.
+    #[cfg(feature = "jit")]
+    MonoStats,
+    Wasm,
+    C,
+}
+
+/// Runs or compiles the program with a backend, exiting if that fails.
+pub struct Codegen {
+    pub backend: Backend,
+    /// Where compiled output is written. Defaults to the entry file with the
+    /// backend's extension.
+    pub output: Option<Output>,
+}
+
+impl<'s> Pass<'s> for Codegen {
+    fn name(&self) -> &'static str {
+        "codegen"
+    }
+
+    fn needs(&self) -> &'static [&'static str] {
+        &["lower"]
+    }
+
+    fn run(&mut self, cx: &mut Context<'s, '_>, artifacts: &mut Artifacts<'s>) {
+        let (Some(entry), Some(program)) = (artifacts.entry, &artifacts.program) else {
+            return;
+        };
+        if cx.errors.error_count() > 0 {
+            return;
+        }
+
+        let extension = match self.backend {
+            Backend::Interpret {
+                options,
+                ref profile,
+            } => {
+                let profile = profile.as_deref();
+                return execute(cx.source_map(), program, artifacts.main, options, profile);
+            }
+            #[cfg(feature = "jit")]
+            Backend::Jit => {
+                let typing = artifacts.typing.as_ref().unwrap();
+                if let Err(message) = jit(program, typing, artifacts.main) {
+                    eprintln!("{message}");
+                    std::process::exit(1);
+                }
+                return;
+            }
+            #[cfg(feature = "jit")]
+            Backend::MonoStats => {
+                let typing = artifacts.typing.as_ref().unwrap();
+                let stats = match crate::jit::mono_stats(program, typing, artifacts.main) {
+                    Ok(stats) => stats,
+                    Err(message) => {
+                        eprintln!("{message}");
+                        std::process::exit(1);
+                    }
+                };
+                let output = self.output.clone().unwrap_or(Output::Stdout);
+                if let Err(err) = output.write(stats.report().as_bytes()) {
+                    cx.errors.error((err, None));
+                }
+                return;
+            }
+            Backend::Wasm => "wasm",
+            Backend::C => "c",
+        };
+        let output = self.output.clone().unwrap_or_else(|| {
+            Output::File(cx.source_map().file(entry).path.with_extension(extension))
+        });
+        let mut outputs = Vec::new();
+        match (&self.backend, &output) {
+            // a module written to a file gets its source map next to it
+            (Backend::Wasm, Output::File(path)) => {
+                let mut map_path = path.clone().into_os_string();
+                map_path.push(".map");
+                let map_path = PathBuf::from(map_path);
+                let (bytes, map) =
+                    wasm::compile(program, artifacts.main, cx.source_map(), Some(&map_path));
+                outputs.push((output, bytes));
+                outputs.push((Output::File(map_path), map.unwrap().into_bytes()));
+            }
+            (Backend::Wasm, _) => {
+                let (bytes, _) = wasm::compile(program, artifacts.main, cx.source_map(), None);
+                outputs.push((output, bytes));
+            }
+            _ => {
+                let source = c::compile(program, artifacts.main, cx.source_map());
+                outputs.push((output, source.into_bytes()));
+            }
+        }
+        for (output, bytes) in outputs {
+            match (output.write(&bytes), &output) {
+                (Ok(()), Output::File(path)) => println!("Wrote {}", path.display()),
+                (Ok(()), Output::Stdout) => {}
+                (Err(err), _) => cx.errors.error((err, None)),
+            }
+        }
+    }
+}
+
+/// Runs the program with the interpreter, and exits if it fails. The
+/// profile is written to `profile` whether the program fails or not.
+fn execute(
+    source_map: &SourceMap,
+    program: &Program,
+    main: Option<VarId>,
+    options: eval::HeapOptions,
+    profile: Option<&Path>,
+) {
+    let mut counts = profile.map(|_| eval::Profile::default());
+    let result = interpret(
+        source_map,
+        program,
+        main,
+        options,
+        counts.as_mut(),
+        |_, _| (),
+    );
+    if let (Some(path), Some(counts)) = (profile, counts) {
+        match std::fs::write(path, counts.collapsed()) {
+            Ok(()) => eprintln!("Wrote {}", path.display()),
+            Err(err) => eprintln!("ERROR: couldn't write {}: {err}", path.display()),
+        }
+    }
+    if let Err(message) = result {
+        eprintln!("{message}");
+        std::process::exit(1);
+    }
+}
+
+/// Compiles the program to native code and runs it, on a thread with as
+/// much stack as the interpreter gets.
+#[cfg(feature = "jit")]
+fn jit(program: &Program, typing: &Typing, main: Option<VarId>) -> Result<(), String> {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(eval::STACK_SIZE)
+            .spawn_scoped(scope, || crate::jit::run(program, typing, main))
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+/// Runs the program on a thread with a stack big enough for the
+/// interpreter's deepest recursion. `finish` is given the value of `main`,
+/// or of the last top-level expression if there is no `main`, while the
+/// heap it lives on is still around. With a `profile`, what the program
+/// evaluates is counted in it. A runtime error is returned as the message
+/// that reports it.
+pub fn interpret<'s, T: Send>(
+    source_map: &SourceMap,
+    program: &Program<'s>,
+    main: Option<VarId>,
+    options: eval::HeapOptions,
+    profile: Option<&mut eval::Profile<'s>>,
+    finish: impl FnOnce(&eval::Heap, eval::Value) -> T + Send,
+) -> Result<T, String> {
+    let result = std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(eval::STACK_SIZE)
+            .spawn_scoped(scope, || {
+                let mut heap = eval::Heap::new(options);
+                let mut out = std::io::stdout().lock();
+                let result = match profile {
+                    Some(profile) => eval::profile(program, main, &mut out, &mut heap, profile),
+                    None => eval::run(program, main, &mut out, &mut heap),
+                };
+                let result = result
+                    .map(|value| finish(&heap, value))
+                    .map_err(|err| (err.span, format!("{:?}", err.kind), err.trace));
+                if options.stats {
+                    eprintln!("GC: {}", heap.stats());
+                }
+                result
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    });
+    result.map_err(|(span, kind, trace)| {
+        let position = |span: Span| {
+            let file = source_map.file(source_map.lookup(span.start));
+            let (line, col) = file.line_col(span.start);
+            format!("{}:{line}:{col}", file.path.display())
+        };
+        let file = source_map.file(source_map.lookup(span.start));
+        let tokens = resolver::semantic_tokens(file, &StringStorage::new(), None);
+        let color = std::io::stderr().is_terminal();
+        let mut message = format!(
+            "RUNTIME ERROR: {kind} at {}\n{}",
+            position(span),
+            highlight::snippet(file, &tokens, span, color)
+        );
+        // a function that recursed is listed once with how many times it did
+        let mut calls = trace.iter().peekable();
+        while let Some(call) = calls.next() {
+            let mut times = 1;
+            while calls
+                .next_if(|next| next.name == call.name && next.span == call.span)
+                .is_some()
+            {
+                times += 1;
+            }
+            let name = match &call.name {
+                Some(name) => format!("`{name}`"),
+                None => "a lambda".to_string(),
+            };
+            let _ = match call.span {
+                Some(span) => write!(message, "\n  in {name} called at {}", position(span)),
+                None => write!(message, "\n  in {name}"),
+            };
+            if times > 1 {
+                let _ = write!(message, " ({times} times)");
+            }
+        }
+        message
+    })
+}
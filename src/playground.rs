@@ -0,0 +1,37 @@
+//! The front end for the browser, so that a playground can show the syntax
+//! tree and the errors of what's typed into it as it's typed. It's built
+//! with the `playground` feature to WebAssembly, and bound to JavaScript by
+//! `wasm-bindgen`:
+//!
+//! ```text
+//! cargo rustc --lib --release --target wasm32-unknown-unknown \
+//!     --features playground --crate-type cdylib
+//! wasm-bindgen --target web --out-dir playground \
+//!     target/wasm32-unknown-unknown/release/radi.wasm
+//! ```
+//!
+//! Both functions return JSON, which is read on the other side with
+//! `JSON.parse`.
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::parser;
+
+/// The syntax tree of `source` as JSON, in the format of `--emit=json-ast`,
+/// or `undefined` if it doesn't parse.
+#[wasm_bindgen]
+pub fn parse_to_json(source: &str) -> Option<String> {
+    parser::parse_to_json(source).0
+}
+
+/// What's wrong with `source`, as a JSON array of objects with a `severity`,
+/// a `message` and a `span`.
+#[wasm_bindgen]
+pub fn diagnostics(source: &str) -> String {
+    let (_, diagnostics) = parser::parse_to_json(source);
+    let diagnostics = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.json())
+        .collect::<Vec<_>>();
+    format!("[{}]", diagnostics.join(","))
+}
@@ -0,0 +1,262 @@
+//! Measurements of the compiler itself for `--timings` and `--stats`: how
+//! long each pass takes and how much it allocates, and for `--stats` how
+//! much the compilation as a whole took up.
+//!
+//! Allocations are counted by [CountingAlloc], the global allocator, which
+//! counts every allocation on every thread. Lexing isn't a pass of its own,
+//! since the parser reads tokens as it goes, so the time spent in the
+//! tokenizer is added up separately while profiling is enabled, as are the
+//! tokens it reads.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static LEXING_NANOS: AtomicU64 = AtomicU64::new(0);
+static TOKENS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Turns on profiling, so that passes and the tokenizer are timed.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Runs `f`, which reads a token, adding the time it takes to the time spent
+/// lexing if profiling is enabled.
+#[inline]
+pub fn lexing<T>(f: impl FnOnce() -> T) -> T {
+    if !enabled() {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    let nanos = start.elapsed().as_nanos() as u64;
+    LEXING_NANOS.fetch_add(nanos, Ordering::Relaxed);
+    result
+}
+
+/// Counts a token that the tokenizer read, if profiling is enabled.
+#[inline]
+pub fn lexed_token() {
+    if enabled() {
+        TOKENS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// How many tokens have been read since profiling was enabled. Files that
+/// are loaded from the parse cache aren't read again, so their tokens
+/// aren't counted.
+pub fn tokens() -> usize {
+    TOKENS.load(Ordering::Relaxed)
+}
+
+/// The most memory that the process has had resident, in bytes, if the
+/// system tells.
+pub fn peak_rss() -> Option<usize> {
+    // only Linux does, in kB
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb = line["VmHWM:".len()..].trim().trim_end_matches("kB").trim();
+    kb.parse::<usize>().ok().map(|kb| kb * 1024)
+}
+
+/// The system allocator, counting the allocations made with it.
+pub struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // only growing counts as allocating more
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        let grown = new_size.saturating_sub(layout.size());
+        ALLOCATED_BYTES.fetch_add(grown, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// The counters at a point in time, which a [Timing] is measured from.
+#[derive(Debug, Clone, Copy)]
+pub struct Snapshot {
+    time: Instant,
+    lexing: u64,
+    allocations: usize,
+    bytes: usize,
+}
+
+impl Snapshot {
+    pub fn now() -> Snapshot {
+        Snapshot {
+            time: Instant::now(),
+            lexing: LEXING_NANOS.load(Ordering::Relaxed),
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+            bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+        }
+    }
+
+    /// What happened between this snapshot and now, while running `pass`.
+    pub fn finish(self, pass: &'static str) -> Timing {
+        let now = Snapshot::now();
+        Timing {
+            pass,
+            time: now.time - self.time,
+            lexing: Duration::from_nanos(now.lexing - self.lexing),
+            allocations: now.allocations - self.allocations,
+            bytes: now.bytes - self.bytes,
+        }
+    }
+}
+
+/// The measurements of one run of a pass.
+#[derive(Debug, Clone, Copy)]
+pub struct Timing {
+    pub pass: &'static str,
+    pub time: Duration,
+    /// The part of `time` spent in the tokenizer, summed over every thread
+    /// that was lexing.
+    pub lexing: Duration,
+    pub allocations: usize,
+    pub bytes: usize,
+}
+
+/// A table of how each pass performed, with a total at the bottom.
+pub struct Table<'a>(pub &'a [Timing]);
+
+impl Display for Table<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let row =
+            |f: &mut Formatter<'_>, name: &str, time: Duration, counts: Option<(usize, usize)>| {
+                let millis = time.as_secs_f64() * 1000.0;
+                match counts {
+                    Some((allocations, bytes)) => writeln!(
+                        f,
+                        "{name:<12} {millis:>10.3} {allocations:>12} {:>12.1}",
+                        bytes as f64 / 1024.0
+                    ),
+                    None => writeln!(f, "{name:<12} {millis:>10.3}"),
+                }
+            };
+        writeln!(
+            f,
+            "{:<12} {:>10} {:>12} {:>12}",
+            "pass", "time (ms)", "allocations", "KiB"
+        )?;
+        for timing in self.0 {
+            let counts = (timing.allocations, timing.bytes);
+            row(f, timing.pass, timing.time, Some(counts))?;
+            if !timing.lexing.is_zero() {
+                row(f, "  lexing", timing.lexing, None)?;
+            }
+        }
+        let time = self.0.iter().map(|t| t.time).sum();
+        let allocations = self.0.iter().map(|t| t.allocations).sum();
+        let bytes = self.0.iter().map(|t| t.bytes).sum();
+        row(f, "total", time, Some((allocations, bytes)))
+    }
+}
+
+/// Everything `--stats` reports about a compilation, printed as tables or,
+/// with [Stats::json], as JSON.
+pub struct Stats<'a> {
+    pub timings: &'a [Timing],
+    /// In bytes, from [peak_rss].
+    pub peak_rss: Option<usize>,
+    /// How many strings were interned, and their length in bytes.
+    pub interned: (usize, usize),
+    /// From [tokens].
+    pub tokens: usize,
+    /// How many expressions of each kind the parsed modules have.
+    pub nodes: BTreeMap<&'static str, usize>,
+}
+
+impl Stats<'_> {
+    /// The stats as a JSON object, on one line.
+    pub fn json(&self) -> String {
+        let millis = |time: Duration| time.as_secs_f64() * 1000.0;
+        let mut out = String::from("{\"passes\":[");
+        for (i, timing) in self.timings.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out += &format!(
+                "{{\"pass\":\"{}\",\"time_ms\":{:.3},\"lexing_ms\":{:.3},\"allocations\":{},\"bytes\":{}}}",
+                timing.pass,
+                millis(timing.time),
+                millis(timing.lexing),
+                timing.allocations,
+                timing.bytes
+            );
+        }
+        let total = self.timings.iter().map(|t| t.time).sum();
+        let peak_rss = self.peak_rss.map_or("null".to_string(), |b| b.to_string());
+        let (strings, bytes) = self.interned;
+        out += &format!(
+            "],\"total_ms\":{:.3},\"peak_rss\":{peak_rss},\"interned\":{{\"strings\":{strings},\"bytes\":{bytes}}},\"tokens\":{},\"nodes\":{{",
+            millis(total),
+            self.tokens
+        );
+        for (i, (kind, count)) in self.nodes.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out += &format!("\"{kind}\":{count}");
+        }
+        out.push_str("}}\n");
+        out
+    }
+}
+
+impl Display for Stats<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Table(self.timings))?;
+        writeln!(f)?;
+        match self.peak_rss {
+            Some(bytes) => {
+                let mib = bytes as f64 / (1024.0 * 1024.0);
+                writeln!(f, "{:<12} {mib:>10.1} MiB", "peak RSS")?
+            }
+            None => writeln!(f, "{:<12} {:>10}", "peak RSS", "unknown")?,
+        }
+        let (strings, bytes) = self.interned;
+        writeln!(
+            f,
+            "{:<12} {strings:>10} strings, {:.1} KiB",
+            "interned",
+            bytes as f64 / 1024.0
+        )?;
+        writeln!(f, "{:<12} {:>10}", "tokens", self.tokens)?;
+        let total = self.nodes.values().sum::<usize>();
+        writeln!(f, "{:<12} {total:>10}", "AST nodes")?;
+        // the most common kinds first
+        let mut nodes = self.nodes.iter().collect::<Vec<_>>();
+        nodes.sort_by_key(|&(kind, count)| (std::cmp::Reverse(*count), *kind));
+        for (kind, count) in nodes {
+            writeln!(f, "  {kind:<13}{count:>8}")?;
+        }
+        Ok(())
+    }
+}
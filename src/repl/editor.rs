@@ -0,0 +1,267 @@
+//! Reads the lines of a REPL session, with editing and history when the
+//! input is a terminal.
+//!
+//! The terminal is switched to raw mode with `stty` while a line is being
+//! read, and the line is redrawn with ANSI escape codes after every key.
+//! The usual readline keys work: the arrow keys, Home and End, Backspace and
+//! Delete, and Ctrl-A, -E, -B, -F, -P, -N, -K, -U, -C and -D. When the input
+//! isn't a terminal, or `stty` isn't available, lines are read as they are.
+use std::{
+    fs::File,
+    io::{self, BufRead, IsTerminal, Read, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+/// How many lines of history are kept.
+const MAX_HISTORY: usize = 1000;
+
+pub struct Editor {
+    history: Vec<String>,
+    /// Where history is kept between sessions.
+    file: Option<PathBuf>,
+}
+
+impl Editor {
+    /// Creates an editor with the history saved in `file`, if it exists.
+    pub fn new(file: Option<PathBuf>) -> Editor {
+        let history = file
+            .as_ref()
+            .and_then(|file| std::fs::read_to_string(file).ok())
+            .map(|history| history.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        Editor { history, file }
+    }
+
+    /// Reads a line, without its line ending. Returns `None` at the end of
+    /// the input.
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let line = match RawMode::enter() {
+            Some(_raw) => self.edit(prompt)?,
+            None => {
+                print!("{prompt}");
+                io::stdout().flush()?;
+                let mut line = String::new();
+                if io::stdin().lock().read_line(&mut line)? == 0 {
+                    println!();
+                    return Ok(None);
+                }
+                Some(line.trim_end_matches(['\n', '\r']).to_string())
+            }
+        };
+        if let Some(line) = &line {
+            self.remember(line);
+        }
+        Ok(line)
+    }
+
+    fn remember(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if self.history.len() > MAX_HISTORY {
+            self.history.remove(0);
+        }
+        if let Some(file) = &self.file {
+            // a session whose history can't be saved works all the same
+            let _ = std::fs::write(file, self.history.join("\n") + "\n");
+        }
+    }
+
+    /// Reads a line from a terminal in raw mode.
+    fn edit(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let mut stdin = io::stdin().lock();
+        let mut line = Line {
+            prompt,
+            chars: Vec::new(),
+            cursor: 0,
+        };
+        // the entry being edited, while going through the history
+        let mut entry = self.history.len();
+        let mut draft = Vec::new();
+        let mut pending = Vec::new();
+        line.draw()?;
+
+        loop {
+            let Some(byte) = read_byte(&mut stdin)? else {
+                if line.chars.is_empty() {
+                    println!();
+                    return Ok(None);
+                }
+                break;
+            };
+            match byte {
+                b'\r' | b'\n' => break,
+                // Ctrl-D
+                0x04 if line.chars.is_empty() => {
+                    println!();
+                    return Ok(None);
+                }
+                0x04 => line.delete(),
+                // Ctrl-C
+                0x03 => {
+                    println!("^C");
+                    line.chars.clear();
+                    line.cursor = 0;
+                    entry = self.history.len();
+                }
+                0x7f | 0x08 => line.backspace(),
+                0x01 => line.cursor = 0,
+                0x05 => line.cursor = line.chars.len(),
+                0x02 => line.cursor = line.cursor.saturating_sub(1),
+                0x06 => line.cursor = (line.cursor + 1).min(line.chars.len()),
+                0x0b => line.chars.truncate(line.cursor),
+                0x15 => {
+                    line.chars.drain(..line.cursor);
+                    line.cursor = 0;
+                }
+                0x10 => self.browse(&mut line, &mut entry, &mut draft, -1),
+                0x0e => self.browse(&mut line, &mut entry, &mut draft, 1),
+                0x1b => match read_escape(&mut stdin)? {
+                    Some(b'A') => self.browse(&mut line, &mut entry, &mut draft, -1),
+                    Some(b'B') => self.browse(&mut line, &mut entry, &mut draft, 1),
+                    Some(b'C') => line.cursor = (line.cursor + 1).min(line.chars.len()),
+                    Some(b'D') => line.cursor = line.cursor.saturating_sub(1),
+                    Some(b'H' | b'1' | b'7') => line.cursor = 0,
+                    Some(b'F' | b'4' | b'8') => line.cursor = line.chars.len(),
+                    Some(b'3') => line.delete(),
+                    _ => {}
+                },
+                _ if byte < 0x20 => {}
+                _ => {
+                    // characters arrive a byte at a time
+                    pending.push(byte);
+                    match std::str::from_utf8(&pending) {
+                        Ok(s) => {
+                            for ch in s.chars() {
+                                line.chars.insert(line.cursor, ch);
+                                line.cursor += 1;
+                            }
+                            pending.clear();
+                        }
+                        Err(err) if err.error_len().is_some() => pending.clear(),
+                        Err(_) => {}
+                    }
+                }
+            }
+            line.draw()?;
+        }
+
+        println!();
+        Ok(Some(line.chars.iter().collect()))
+    }
+
+    /// Moves through the history by `step` entries, keeping the line that
+    /// was being written before browsing started.
+    fn browse(&self, line: &mut Line, entry: &mut usize, draft: &mut Vec<char>, step: isize) {
+        let Some(next) = entry.checked_add_signed(step) else {
+            return;
+        };
+        if next > self.history.len() {
+            return;
+        }
+        if *entry == self.history.len() {
+            *draft = line.chars.clone();
+        }
+        *entry = next;
+        line.chars = match self.history.get(next) {
+            Some(previous) => previous.chars().collect(),
+            None => draft.clone(),
+        };
+        line.cursor = line.chars.len();
+    }
+}
+
+/// The line being edited.
+struct Line<'p> {
+    prompt: &'p str,
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl Line<'_> {
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    /// Redraws the line and puts the cursor back where it was. Every
+    /// character is assumed to take up one column.
+    fn draw(&self) -> io::Result<()> {
+        let mut out = io::stdout().lock();
+        let text = self.chars.iter().collect::<String>();
+        write!(out, "\r{}{text}\x1b[K", self.prompt)?;
+        let after = self.chars.len() - self.cursor;
+        if after > 0 {
+            write!(out, "\x1b[{after}D")?;
+        }
+        out.flush()
+    }
+}
+
+/// Keeps the terminal in raw mode until it's dropped.
+struct RawMode {
+    /// The settings to restore, as printed by `stty -g`.
+    saved: String,
+}
+
+impl RawMode {
+    fn enter() -> Option<RawMode> {
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            return None;
+        }
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "min", "1", "time", "0"])?;
+        Some(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        stty(&[&self.saved]);
+    }
+}
+
+/// Runs `stty` on the terminal, returning what it printed.
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(File::open("/dev/tty").ok()?)
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    Ok(match input.read(&mut byte)? {
+        0 => None,
+        _ => Some(byte[0]),
+    })
+}
+
+/// Reads the rest of an escape sequence such as `ESC [ A`, returning its
+/// letter or, for one like `ESC [ 3 ~`, its number.
+fn read_escape(input: &mut impl Read) -> io::Result<Option<u8>> {
+    if !matches!(read_byte(input)?, Some(b'[' | b'O')) {
+        return Ok(None);
+    }
+    match read_byte(input)? {
+        Some(digit @ b'0'..=b'9') => {
+            while !matches!(read_byte(input)?, Some(b'~') | None) {}
+            Ok(Some(digit))
+        }
+        key => Ok(key),
+    }
+}
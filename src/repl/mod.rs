@@ -0,0 +1,528 @@
+//! An interactive session, started with `radi repl`.
+//!
+//! The `use`s and defs entered so far are kept as source text, and make up
+//! a module that is served from memory rather than read from disk. Entering
+//! a def adds it to the module, replacing an earlier def with the same name,
+//! but only if the module still checks afterwards. Entering an expression
+//! checks the module with the expression at the end and runs it with the
+//! interpreter, printing the expression's value and type. Since the whole
+//! module is run each time, the effects of evaluating the defs happen again
+//! on every run.
+//!
+//! An entry may go on over several lines: while it leaves a bracket open,
+//! stops in the middle of a string or otherwise isn't complete, more lines
+//! are read. A def that is only missing its final semicolon is completed
+//! with one.
+//!
+//! Commands starting with `:` look into the session without running it:
+//! `:type` and `:defs` stop after type checking, and `:ast` only parses.
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+mod editor;
+
+use editor::Editor;
+
+use crate::{
+    char_reader::IoCharReader,
+    errors::{ErrorStream, Severity},
+    eval,
+    parse_manager::{FileSystem, Overlay, ParseManager, Project},
+    parser::{self, ExprKind, ParseError, ParseErrorKind},
+    passes::{self, Artifacts, Context, PassManager},
+    resolver::{self, ResolveOptions},
+    string_storage::StringStorage,
+    tokenizer::{Span, TokenKind, TokenizationErrorKind, Tokens},
+    typeck::{Type, TypeId},
+};
+
+/// The name of the session's module, which can't be imported since it
+/// isn't a valid module path.
+const MODULE: &str = "<repl>.radi";
+
+const PROMPT: &str = "> ";
+const CONTINUE: &str = ". ";
+
+const HELP: &str = "\
+Enter an expression to evaluate it, or a def or use to add it to the session.
+Commands:
+  :type <expr>  show the type of an expression without running it
+  :ast <expr>   show how an expression is parsed
+  :defs         list the uses and defs in the session, with their types
+  :help         show this message
+  :quit         leave the session (or press Ctrl-D)";
+
+/// The passes that only need to run when the session's module is run.
+const BACKEND: [&str; 3] = ["lower", "consteval", "fold"];
+
+/// Runs a session until the input ends.
+pub fn run(options: ResolveOptions, heap: eval::HeapOptions) -> io::Result<()> {
+    let sources = Mutex::new(Overlay::new(FileSystem));
+    let storage = StringStorage::new();
+    let errors = ErrorStream::new();
+    let root = match Project::discover(None) {
+        Ok(Some(project)) => project.root,
+        Ok(None) => std::env::current_dir()?,
+        Err(err) => {
+            errors.error(err);
+            return Ok(());
+        }
+    };
+    let path = root.join(MODULE);
+    let mut passes = PassManager::new();
+    passes.add(passes::Parse {
+        path: path.clone(),
+        prelude: options.prelude,
+        project: false,
+    });
+    passes.add(passes::Resolve { options });
+    passes.add(passes::Effects);
+    passes.add(passes::Typecheck);
+    passes.add(passes::Lower);
+    passes.add(passes::ConstEval);
+    passes.add(passes::Fold);
+    let mut repl = Repl {
+        sources: &sources,
+        storage: &storage,
+        errors: &errors,
+        manager: ParseManager::new(&storage, &errors, &root).with_sources(&sources),
+        passes,
+        path,
+        heap,
+        session: Session::default(),
+    };
+
+    let history = std::env::var_os("HOME").map(|home| Path::new(&home).join(".radi_history"));
+    let mut editor = Editor::new(history);
+    while let Some(input) = editor.read_line(PROMPT)? {
+        if input.trim().is_empty() {
+            continue;
+        }
+        let parsed = match input.trim().strip_prefix(':') {
+            None => read_entry(&mut editor, &storage, input, |input| {
+                Entry::parse(input, &storage, &errors).map(Action::Enter)
+            })?,
+            Some(command) => {
+                let (name, rest) = command
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((command, ""));
+                match name {
+                    "quit" | "q" => break,
+                    "help" | "h" => println!("{HELP}"),
+                    "defs" => repl.defs(),
+                    "type" | "t" | "ast" if rest.trim().is_empty() => {
+                        println!("Usage: `:{name} <expr>`")
+                    }
+                    "type" | "t" => {
+                        let parsed =
+                            read_entry(&mut editor, &storage, rest.to_string(), |input| {
+                                let expr = parse_expr(input, &storage, &errors);
+                                expr.map(|expr| Action::Type(input[range(expr.span)].to_string()))
+                            })?;
+                        repl.act(parsed)?;
+                    }
+                    "ast" => {
+                        let parsed =
+                            read_entry(&mut editor, &storage, rest.to_string(), |input| {
+                                parse_expr(input, &storage, &errors).map(Action::Ast)
+                            })?;
+                        repl.act(parsed)?;
+                    }
+                    _ => println!("Unknown command `:{name}`, try `:help`"),
+                }
+                continue;
+            }
+        };
+        repl.act(parsed)?;
+    }
+    Ok(())
+}
+
+/// Parses `input` with `parse`, reading more lines while it's incomplete.
+/// The result is only incomplete if the input ended first.
+fn read_entry<T>(
+    editor: &mut Editor,
+    storage: &StringStorage,
+    mut input: String,
+    parse: impl Fn(&str) -> Parsed<T>,
+) -> io::Result<Parsed<T>> {
+    loop {
+        // an open bracket would otherwise be reported by the parser when
+        // what's inside it can't start an expression
+        let parsed = match is_open(&input, storage) {
+            true => Parsed::Incomplete,
+            false => parse(&input),
+        };
+        match parsed {
+            Parsed::Incomplete => match editor.read_line(CONTINUE)? {
+                Some(line) => {
+                    input.push('\n');
+                    input.push_str(&line);
+                }
+                None => return Ok(Parsed::Incomplete),
+            },
+            parsed => return Ok(parsed),
+        }
+    }
+}
+
+/// What to do with an entry once it has been read.
+enum Action<'s> {
+    Enter(Entry),
+    /// Show the type of the expression with this source.
+    Type(String),
+    Ast(parser::Expr<'s>),
+}
+
+struct Repl<'s, 'p> {
+    sources: &'s Mutex<Overlay<FileSystem>>,
+    storage: &'s StringStorage,
+    errors: &'s ErrorStream<'s>,
+    manager: ParseManager<'s>,
+    passes: PassManager<'s, 'p>,
+    /// Where the session's module pretends to be.
+    path: PathBuf,
+    heap: eval::HeapOptions,
+    session: Session,
+}
+
+impl<'s> Repl<'s, '_> {
+    /// Carries out a parsed action, failing if the input ended before the
+    /// action was complete.
+    fn act(&mut self, parsed: Parsed<Action>) -> io::Result<()> {
+        match parsed {
+            Parsed::Done(Action::Enter(entry)) => self.enter(entry),
+            Parsed::Done(Action::Type(expr)) => self.type_of(&expr),
+            Parsed::Done(Action::Ast(expr)) => print!("{expr}"),
+            Parsed::Invalid => {}
+            Parsed::Incomplete => return Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self, entry: Entry) {
+        match entry {
+            Entry::Items { uses, defs } => {
+                let mut next = self.session.clone();
+                next.add(uses, defs);
+                if self.check(next.source(None), true).is_some() {
+                    self.session = next;
+                }
+            }
+            Entry::Expr(expr) => {
+                if let Some(artifacts) = self.check(self.session.source(Some(&expr)), true) {
+                    self.show(&artifacts);
+                }
+            }
+        }
+    }
+
+    /// Shows the type of `expr` in the session, without running it.
+    fn type_of(&mut self, expr: &str) {
+        let Some(artifacts) = self.check(self.session.source(Some(expr)), false) else {
+            return;
+        };
+        if let (Some(typing), Some(ty)) = (&artifacts.typing, last_type(&artifacts)) {
+            println!("{expr} :: {}", typing.types.display(ty));
+        }
+    }
+
+    /// Lists the `use`s and defs in the session, with the type of each def.
+    fn defs(&mut self) {
+        let Some(artifacts) = self.check(self.session.source(None), false) else {
+            return;
+        };
+        let (Some(module), Some(resolution), Some(typing)) = (
+            entry_module(&artifacts),
+            &artifacts.resolution,
+            &artifacts.typing,
+        ) else {
+            return;
+        };
+        for item in &self.session.uses {
+            println!("{item}");
+        }
+        if let resolver::ExprKind::Object(scope) = &module.body.kind {
+            for def in scope.defs.iter() {
+                let name = resolution.symbol(def.symbol).name;
+                let ty = typing.symbols[&def.symbol];
+                println!("{} :: {}", name.0, typing.types.display(ty));
+            }
+        }
+    }
+
+    /// Checks the session's module with `src` as its source, returning what
+    /// the passes produced if there were no errors. The passes after type
+    /// checking only run if the module is going to be `run`.
+    fn check(&mut self, src: String, run: bool) -> Option<Artifacts<'s>> {
+        self.sources.lock().unwrap().insert(&self.path, src);
+        if let Some(file) = self.manager.source_map().find(&self.path) {
+            self.manager.update(file);
+        }
+        self.errors.reset();
+        let mut cx = Context {
+            storage: self.storage,
+            errors: self.errors,
+            manager: &mut self.manager,
+        };
+        for pass in BACKEND {
+            self.passes.set_enabled(pass, run);
+        }
+        let artifacts = self.passes.run(&mut cx);
+        let done = |a: &Artifacts| a.typing.is_some() && (!run || a.program.is_some());
+        Some(artifacts).filter(|a| done(a) && self.errors.error_count() == 0)
+    }
+
+    /// Runs the session's module, and prints the value of the expression at
+    /// its end along with its type. Values of type `()` aren't printed.
+    fn show(&self, artifacts: &Artifacts<'s>) {
+        let (Some(typing), Some(program), Some(ty)) =
+            (&artifacts.typing, &artifacts.program, last_type(artifacts))
+        else {
+            return;
+        };
+        let unit = matches!(typing.types.get(ty), Type::Tuple(items) if items.is_empty());
+
+        let source_map = self.manager.source_map();
+        let result =
+            passes::interpret(source_map, program, None, self.heap, None, |heap, value| {
+                heap.display_quoted(&value).to_string()
+            });
+        match result {
+            Ok(_) if unit => {}
+            Ok(value) => println!("{value} :: {}", typing.types.display(ty)),
+            Err(message) => eprintln!("{message}"),
+        }
+    }
+}
+
+/// The session's module, as resolved.
+fn entry_module<'a, 's>(artifacts: &'a Artifacts<'s>) -> Option<&'a resolver::Module<'s>> {
+    let (entry, resolved) = (artifacts.entry?, artifacts.resolved.as_ref()?);
+    resolved.iter().find(|module| module.file == entry)
+}
+
+/// The type of the expression at the end of the session's module.
+fn last_type(artifacts: &Artifacts) -> Option<TypeId> {
+    let span = match &entry_module(artifacts)?.body.kind {
+        resolver::ExprKind::Object(scope) => scope.body.last()?.span,
+        _ => entry_module(artifacts)?.body.span,
+    };
+    artifacts.typing.as_ref()?.type_of(span)
+}
+
+/// The `use`s and defs entered so far, as source text.
+#[derive(Debug, Clone, Default)]
+struct Session {
+    uses: Vec<String>,
+    /// Each def's name and source, in the order they were first entered.
+    defs: Vec<(String, String)>,
+}
+
+impl Session {
+    fn add(&mut self, uses: Vec<String>, defs: Vec<(String, String)>) {
+        for item in uses {
+            if !self.uses.contains(&item) {
+                self.uses.push(item);
+            }
+        }
+        for (name, src) in defs {
+            match self.defs.iter_mut().find(|(n, _)| *n == name) {
+                Some(def) => def.1 = src,
+                None => self.defs.push((name, src)),
+            }
+        }
+    }
+
+    /// The source of the session's module, with `expr` at the end.
+    fn source(&self, expr: Option<&str>) -> String {
+        let items = self.uses.iter().chain(self.defs.iter().map(|(_, src)| src));
+        let mut src = items.fold(String::new(), |src, item| src + item + "\n");
+        if let Some(expr) = expr {
+            src += expr;
+            src += ";";
+        }
+        src
+    }
+}
+
+impl<T> Parsed<T> {
+    fn map<U>(self, f: impl FnOnce(T) -> U) -> Parsed<U> {
+        match self {
+            Parsed::Done(value) => Parsed::Done(f(value)),
+            Parsed::Incomplete => Parsed::Incomplete,
+            Parsed::Invalid => Parsed::Invalid,
+        }
+    }
+}
+
+/// What was entered on one or more lines.
+enum Entry {
+    Items {
+        uses: Vec<String>,
+        /// The name and source of each def.
+        defs: Vec<(String, String)>,
+    },
+    Expr(String),
+}
+
+enum Parsed<T> {
+    Done(T),
+    /// The input ended before the entry did.
+    Incomplete,
+    /// The input has errors, which have been reported.
+    Invalid,
+}
+
+impl Entry {
+    fn parse<'s>(
+        input: &str,
+        storage: &'s StringStorage,
+        errors: &ErrorStream<'s>,
+    ) -> Parsed<Entry> {
+        let mut tokens = Tokens::of(
+            IoCharReader::new(input.as_bytes()).sized_for(input.len()),
+            storage,
+        );
+        let items = match tokens.peek() {
+            Ok(Some(token)) => matches!(
+                token.kind,
+                TokenKind::Def
+                    | TokenKind::Pub
+                    | TokenKind::At
+                    | TokenKind::Trait
+                    | TokenKind::Impl
+                    | TokenKind::Use
+            ),
+            _ => false,
+        };
+        if !items {
+            let expr = parse_expr(input, storage, errors);
+            return expr.map(|expr| Entry::Expr(input[range(expr.span)].to_string()));
+        }
+
+        let parse = |input: &str| {
+            let tokens = Tokens::of(
+                IoCharReader::new(input.as_bytes()).sized_for(input.len()),
+                storage,
+            );
+            let buffered = ErrorStream::buffered();
+            let result = parser::parse(tokens, &buffered);
+            (result, buffered)
+        };
+        let (mut result, mut buffered) = parse(input);
+        let mut input = input.to_string();
+        if is_incomplete(&result) {
+            let completed = input.clone() + ";";
+            let (next, next_buffered) = parse(&completed);
+            if next.is_ok() {
+                (result, buffered, input) = (next, next_buffered, completed);
+            }
+        }
+        let module = match finish(result, buffered, errors) {
+            Ok(module) => module,
+            Err(parsed) => return parsed,
+        };
+
+        let defs = match &module.body.kind {
+            ExprKind::Object(scope) if scope.body.is_empty() => &scope.defs[..],
+            // only `use`s
+            ExprKind::Tuple { items } if items.is_empty() => &[],
+            _ => {
+                println!("Enter definitions and expressions separately");
+                return Parsed::Invalid;
+            }
+        };
+        let uses = module
+            .uses
+            .iter()
+            .map(|item| input[range(item.span)].to_string());
+        let defs = defs
+            .iter()
+            .map(|def| (def.name.0.to_string(), input[range(def.span)].to_string()));
+        Parsed::Done(Entry::Items {
+            uses: uses.collect(),
+            defs: defs.collect(),
+        })
+    }
+}
+
+fn parse_expr<'s>(
+    input: &str,
+    storage: &'s StringStorage,
+    errors: &ErrorStream<'s>,
+) -> Parsed<parser::Expr<'s>> {
+    let tokens = Tokens::of(
+        IoCharReader::new(input.as_bytes()).sized_for(input.len()),
+        storage,
+    );
+    let buffered = ErrorStream::buffered();
+    let result = parser::parse_expr(tokens, &buffered);
+    match finish(result, buffered, errors) {
+        Ok(expr) => Parsed::Done(expr),
+        Err(parsed) => parsed,
+    }
+}
+
+/// Whether the tokens of `input` leave a bracket open or end in the middle
+/// of a string. A closing bracket without an opening one is left for the
+/// parser to report.
+fn is_open(input: &str, storage: &StringStorage) -> bool {
+    let mut tokens = Tokens::of(
+        IoCharReader::new(input.as_bytes()).sized_for(input.len()),
+        storage,
+    );
+    let mut depth = 0usize;
+    loop {
+        match tokens.next() {
+            Ok(Some(token)) => match token.kind {
+                TokenKind::OpenParen
+                | TokenKind::OpenBracket
+                | TokenKind::OpenBrace
+                | TokenKind::DotOpenBrace => depth += 1,
+                TokenKind::CloseParen | TokenKind::CloseBracket | TokenKind::CloseBrace => {
+                    let Some(outer) = depth.checked_sub(1) else {
+                        return false;
+                    };
+                    depth = outer;
+                }
+                _ => {}
+            },
+            Ok(None) => return depth > 0,
+            Err(err) => return matches!(err.kind, TokenizationErrorKind::UnexpectedEof),
+        }
+    }
+}
+
+fn is_incomplete<T>(result: &Result<T, ParseError>) -> bool {
+    matches!(result, Err(err) if matches!(err.kind(), ParseErrorKind::Unexpected(None)))
+}
+
+/// Reports the errors from parsing an entry, unless it's incomplete.
+fn finish<'s, T, U>(
+    result: Result<T, ParseError<'s>>,
+    buffered: ErrorStream<'s>,
+    errors: &ErrorStream<'s>,
+) -> Result<T, Parsed<U>> {
+    if is_incomplete(&result) {
+        return Err(Parsed::Incomplete);
+    }
+    let diagnostics = buffered.into_diagnostics();
+    let failed = diagnostics.iter().any(|d| d.severity == Severity::Error);
+    diagnostics.into_iter().for_each(|d| errors.emit(d));
+    match result {
+        Ok(_) if failed => Err(Parsed::Invalid),
+        Ok(value) => Ok(value),
+        Err(err) => {
+            errors.error(err);
+            Err(Parsed::Invalid)
+        }
+    }
+}
+
+fn range(span: Span) -> std::ops::Range<usize> {
+    span.start as usize..span.end as usize
+}
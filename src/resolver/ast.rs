@@ -1,4 +1,15 @@
-use crate::tokenizer::{Intern, Span};
+use crate::{
+    source_map::FileId,
+    tokenizer::{Intern, Span},
+};
+
+use super::SymbolId;
+
+#[derive(Debug)]
+pub struct Module<'s> {
+    pub file: FileId,
+    pub body: Expr<'s>,
+}
 
 #[derive(Debug)]
 pub struct Expr<'s> {
@@ -11,7 +22,7 @@ pub enum ExprKind<'s> {
     Object(Box<Scope<'s>>),
     Block(Box<Scope<'s>>),
     Lambda {
-        arg: Box<Expr<'s>>,
+        arg: Box<Pattern<'s>>,
         body: Box<Expr<'s>>,
     },
     BinOp {
@@ -43,9 +54,89 @@ pub enum ExprKind<'s> {
         a: Box<Expr<'s>>,
         b: Box<Expr<'s>>,
     },
+    Set {
+        place: Box<Expr<'s>>,
+        value: Box<Expr<'s>>,
+    },
+    /// The function type `arg -> ret`.
+    Arrow {
+        arg: Box<Expr<'s>>,
+        ret: Box<Expr<'s>>,
+    },
+    /// The value of an `@extern("c")` def, the C function `name` of type `ty`.
+    Extern {
+        name: Intern<'s>,
+        ty: Box<Expr<'s>>,
+    },
+    /// `match scrutinee { pattern => body; ... }`, whose arms are tried in
+    /// order.
+    Match {
+        scrutinee: Box<Expr<'s>>,
+        arms: Box<[MatchArm<'s>]>,
+    },
+    /// The value of a `trait` def, whose methods are declared with type
+    /// expressions in terms of `param`.
+    Trait {
+        param: SymbolId,
+        methods: Box<[Def<'s>]>,
+    },
+    /// The value of an `impl` def, whose defs are the methods of `trait_`
+    /// for the type `ty`.
+    Impl {
+        trait_: SymbolId,
+        ty: Box<Expr<'s>>,
+        defs: Box<[Def<'s>]>,
+    },
     Variant(Box<[VariantItem<'s>]>),
-    Ident(Intern<'s>),
+    Ident(SymbolId),
+    Literal(Literal<'s>),
+    /// Stands in for an expression that failed to resolve. An error has
+    /// already been reported for it.
+    Error,
+}
+
+#[derive(Debug)]
+pub struct Pattern<'s> {
+    pub kind: PatternKind<'s>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum PatternKind<'s> {
+    Bind(SymbolId),
+    Tuple(Box<[Pattern<'s>]>),
+    Typed {
+        pat: Box<Pattern<'s>>,
+        ty: Box<Expr<'s>>,
+    },
+    Wildcard,
+    /// Matches only values equal to the literal.
     Literal(Literal<'s>),
+    /// Matches only the variant `name`, and its payload against `value`.
+    Variant {
+        name: Intern<'s>,
+        value: Option<Box<Pattern<'s>>>,
+    },
+    Error,
+}
+
+impl Pattern<'_> {
+    /// Whether some value of the pattern's type might not match it, which
+    /// only a `match` can handle.
+    pub fn is_refutable(&self) -> bool {
+        match &self.kind {
+            PatternKind::Bind(_) | PatternKind::Wildcard | PatternKind::Error => false,
+            PatternKind::Tuple(items) => items.iter().any(Pattern::is_refutable),
+            PatternKind::Typed { pat, .. } => pat.is_refutable(),
+            PatternKind::Literal(_) | PatternKind::Variant { .. } => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MatchArm<'s> {
+    pub pattern: Pattern<'s>,
+    pub body: Expr<'s>,
 }
 
 #[derive(Debug)]
@@ -64,19 +155,34 @@ pub struct VariantItem<'s> {
 
 #[derive(Debug)]
 pub struct Def<'s> {
-    pub name: Intern<'s>,
+    pub symbol: SymbolId,
+    /// Whether the def is marked `@const`, which evaluates it at compile time.
+    pub constant: bool,
     pub value: Box<Expr<'s>>,
     pub span: Span,
 }
 
-#[derive(Debug)]
+impl<'s> Def<'s> {
+    /// The defs that give the def its values: the def itself, or the
+    /// methods of an `impl`. A `trait` only has types.
+    pub fn values(&self) -> &[Def<'s>] {
+        match &self.value.kind {
+            ExprKind::Trait { .. } => &[],
+            ExprKind::Impl { defs, .. } => defs,
+            _ => std::slice::from_ref(self),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum Literal<'s> {
     Float(f64),
     Integer(u64),
+    BigInteger(Intern<'s>),
     String(Intern<'s>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BinOp {
     Equal,
     NotEqual,
@@ -93,9 +199,30 @@ pub enum BinOp {
     Or,
 }
 
-#[derive(Debug)]
+impl BinOp {
+    /// The member that the operator calls when its left operand is an
+    /// object: `a + b` is `a.add(b)`, `a = b` is `a.eq(b)` and `a < b` is
+    /// `a.cmp(b) < 0`. `&&` and `||` only work on `Bool`s.
+    pub fn member(self) -> Option<&'static str> {
+        Some(match self {
+            BinOp::Add => "add",
+            BinOp::Sub => "sub",
+            BinOp::Mul => "mul",
+            BinOp::Div => "div",
+            BinOp::Mod => "mod",
+            BinOp::Equal | BinOp::NotEqual => "eq",
+            BinOp::Gt | BinOp::GtEq | BinOp::Lt | BinOp::LtEq => "cmp",
+            BinOp::And | BinOp::Or => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UnOp {
     Not,
+    /// Reads the current value of a mutable binding, which doesn't capture
+    /// it by reference.
+    Val,
     Ref,
     Deref,
 }
@@ -105,3 +232,65 @@ pub enum AccessRhs<'s> {
     Prop(Intern<'s>),
     Expr(Box<Expr<'s>>),
 }
+
+impl Expr<'_> {
+    /// Collects the symbols referred to within the expression.
+    pub fn symbols(&self, out: &mut Vec<SymbolId>) {
+        let scope = |scope: &Scope, out: &mut Vec<SymbolId>| {
+            for def in scope.defs.iter() {
+                def.value.symbols(out);
+            }
+            for expr in scope.body.iter() {
+                expr.symbols(out);
+            }
+        };
+
+        match &self.kind {
+            ExprKind::Object(s) | ExprKind::Block(s) => scope(s, out),
+            ExprKind::Lambda { body, .. } => body.symbols(out),
+            ExprKind::BinOp { lhs, rhs, .. } => {
+                lhs.symbols(out);
+                rhs.symbols(out);
+            }
+            ExprKind::UnOp { arg, .. } => arg.symbols(out),
+            ExprKind::Access { expr, prop } => {
+                expr.symbols(out);
+                if let AccessRhs::Expr(prop) = prop {
+                    prop.symbols(out);
+                }
+            }
+            ExprKind::Branch {
+                cond,
+                on_true,
+                on_false,
+            } => {
+                cond.symbols(out);
+                on_true.symbols(out);
+                if let Some(on_false) = on_false {
+                    on_false.symbols(out);
+                }
+            }
+            ExprKind::Tuple { items } => items.iter().for_each(|item| item.symbols(out)),
+            ExprKind::Apply { a, b }
+            | ExprKind::TypeAssertion { a, b }
+            | ExprKind::Set { place: a, value: b }
+            | ExprKind::Arrow { arg: a, ret: b } => {
+                a.symbols(out);
+                b.symbols(out);
+            }
+            ExprKind::Extern { ty, .. } => ty.symbols(out),
+            ExprKind::Trait { .. } => {}
+            ExprKind::Impl { defs, .. } => defs.iter().for_each(|def| def.value.symbols(out)),
+            ExprKind::Match { scrutinee, arms } => {
+                scrutinee.symbols(out);
+                arms.iter().for_each(|arm| arm.body.symbols(out));
+            }
+            ExprKind::Variant(items) => items
+                .iter()
+                .filter_map(|item| item.value.as_ref())
+                .for_each(|value| value.symbols(out)),
+            ExprKind::Ident(symbol) => out.push(*symbol),
+            ExprKind::Literal(_) | ExprKind::Error => {}
+        }
+    }
+}
@@ -1,24 +1,1126 @@
 //! This stage resolves references to symbols across a compilation unit.
 //! This does not include object properties - that happens during typechecking.
 //!
-//! Since each compilation unit can contain multiple files, and we don't know
-//! which files are included until they are parsed, this stage is what actually
-//! calls the lexer and parser.
-//! 
-use std::path::Path;
+//! Each compilation unit can contain multiple files, which are found and
+//! parsed by the [ParseManager]. Every module's top-level defs are declared
+//! before any module is resolved, so the order modules are resolved in doesn't
+//! matter.
+//! A `use` brings either a module or a `pub def` of a module into scope, and
+//! accesses through an imported module (`m.x`) are resolved here too, so later
+//! stages never see modules at all.
+//!
+//! Alongside name resolution, this stage performs closure capture analysis:
+//! for every lambda it records which outer bindings the body refers to and
+//! whether they have to be captured by value or by reference. Bindings are
+//! immutable unless they are introduced with `set` (either `def x set 0;` or a
+//! `set x` pattern), and only mutable bindings are ever captured by reference.
+//! Reading a mutable binding through `val x` takes a snapshot of its current
+//! value, so a lambda that only ever uses `val x` captures `x` by value.
+//!
+//! Names that aren't bound anywhere in the program are looked up in the
+//! prelude, an implicit outermost scope of [Builtin]s and the public defs of
+//! the prelude module (see [prelude]). It can be turned off with
+//! [ResolveOptions::prelude].
+//!
+//! The defs of an object, modules included, are mutually recursive, so they
+//! can be used anywhere in it. A block runs in order instead, and a def of a
+//! block can't be used before its value is computed, except from inside a
+//! lambda, which won't run until it's called.
+//!
+//! The output of this stage is a tree in which identifiers and definitions
+//! carry [SymbolId]s instead of names, so later passes never have to look
+//! names up again.
+use rustc_hash::{FxHashMap, FxHashSet};
 
 mod ast;
+pub mod prelude;
+mod query;
+mod visit;
 pub use ast::*;
+pub use prelude::Builtin;
+pub use query::*;
+pub use visit::*;
+
+use crate::{
+    errors::ErrorStream,
+    parse_manager::{Import, ParseManager},
+    parser,
+    source_map::FileId,
+    tokenizer::{Intern, Span},
+};
+
+pub fn resolve<'s>(
+    manager: &ParseManager<'s>,
+    options: &ResolveOptions,
+    errors: &ErrorStream<'s>,
+) -> (Box<[Module<'s>]>, Resolution<'s>) {
+    let mut resolver = Resolver {
+        errors,
+        manager,
+        prelude: FxHashMap::default(),
+        module_defs: FxHashMap::default(),
+        scopes: Vec::new(),
+        lambdas: Vec::new(),
+        ordered: FxHashMap::default(),
+        res: Resolution {
+            symbols: Vec::new(),
+            uses: FxHashMap::default(),
+            references: FxHashMap::default(),
+            captures: FxHashMap::default(),
+            modules: FxHashMap::default(),
+            scopes: Vec::new(),
+            prelude: Box::new([]),
+            exported: FxHashSet::default(),
+            traits: FxHashMap::default(),
+        },
+    };
+
+    if options.prelude {
+        for &builtin in Builtin::ALL {
+            let id = SymbolId(resolver.res.symbols.len() as u32);
+            resolver.res.symbols.push(Symbol {
+                name: Intern(builtin.name()),
+                kind: SymbolKind::Builtin(builtin),
+                mutable: false,
+                span: Span { start: 0, end: 0 },
+            });
+            resolver.prelude.insert(builtin.name(), id);
+        }
+    }
+
+    for module in manager.modules() {
+        if let parser::ExprKind::Object(scope) = &module.ast.body.kind {
+            let mut defs = scope
+                .defs
+                .iter()
+                .map(|def| (resolver.item_symbol(def), def.public))
+                .collect::<Vec<_>>();
+            // the methods of the module's traits are bound alongside its
+            // defs, after them
+            for (i, def) in scope.defs.iter().enumerate() {
+                if let parser::ExprKind::Trait { defs: methods, .. } = &def.value.kind {
+                    let trait_ = defs[i].0;
+                    let methods = methods
+                        .iter()
+                        .map(|method| {
+                            let kind = SymbolKind::Method(trait_);
+                            resolver.symbol(method.name, kind, false, method.name_span)
+                        })
+                        .collect::<Box<[_]>>();
+                    defs.extend(methods.iter().map(|&id| (id, def.public)));
+                    resolver.res.traits.insert(trait_, methods);
+                }
+            }
+            let exported = defs.iter().filter(|(_, public)| *public);
+            resolver.res.exported.extend(exported.map(|(id, _)| *id));
+            resolver.module_defs.insert(module.file, defs);
+        }
+    }
+
+    if let Some(file) = manager.prelude().filter(|_| options.prelude) {
+        for &(id, public) in resolver.module_defs.get(&file).into_iter().flatten() {
+            if public {
+                let name = resolver.res.symbol(id).name;
+                resolver.prelude.insert(name.0, id);
+            }
+        }
+    }
+
+    let modules = manager
+        .modules()
+        .map(|module| {
+            let source = manager.source_map().file(module.file);
+            let file = Span {
+                start: source.start,
+                end: source.end(),
+            };
+            resolver.scopes.push(FxHashMap::default());
+            for import in module.imports.iter() {
+                resolver.import(import);
+            }
+
+            let body = &module.ast.body;
+            let body = match &body.kind {
+                parser::ExprKind::Object(scope) => {
+                    let symbols = resolver.module_defs[&module.file]
+                        .iter()
+                        .map(|(id, _)| *id)
+                        .collect();
+                    Expr {
+                        kind: ExprKind::Object(Box::new(
+                            resolver.scope_with(scope, symbols, file, false),
+                        )),
+                        span: body.span,
+                    }
+                }
+                _ => resolver.expr(body, UseMode::Read),
+            };
+            resolver.pop_scope(file);
+
+            Module {
+                file: module.file,
+                body,
+            }
+        })
+        .collect();
+
+    let mut prelude = resolver.prelude.into_values().collect::<Vec<_>>();
+    prelude.sort();
+    resolver.res.prelude = prelude.into();
+    (modules, resolver.res)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ResolveOptions {
+    /// Whether names may resolve to the builtins in the prelude.
+    pub prelude: bool,
+}
+
+impl Default for ResolveOptions {
+    fn default() -> Self {
+        ResolveOptions { prelude: true }
+    }
+}
+
+/// The tables produced by name resolution.
+#[derive(Debug)]
+pub struct Resolution<'s> {
+    /// Every binding in the compilation unit, indexed by [SymbolId].
+    pub symbols: Vec<Symbol<'s>>,
+    /// Maps the span of each identifier expression to the binding it refers to.
+    pub uses: FxHashMap<Span, SymbolId>,
+    /// The reverse of `uses`: maps each symbol to the spans of the identifiers
+    /// that refer to it.
+    pub references: FxHashMap<SymbolId, Vec<Span>>,
+    /// Maps the span of each lambda to the outer bindings that its body refers to,
+    /// in order of first use.
+    pub captures: FxHashMap<Span, Box<[Capture]>>,
+    /// Maps the span of each segment of a `use` path that names a module to
+    /// that module.
+    pub modules: FxHashMap<Span, FileId>,
+    /// Every scope, with the symbols bound directly in it. A scope is always
+    /// inside the span of the scopes it's nested in.
+    pub scopes: Vec<ScopeSymbols>,
+    /// The builtins and the public defs of the prelude, which are visible
+    /// everywhere unless they're shadowed.
+    pub prelude: Box<[SymbolId]>,
+    /// The public top-level defs of every module, which other modules can
+    /// import.
+    pub exported: FxHashSet<SymbolId>,
+    /// The methods of each trait, in the order they're declared in.
+    pub traits: FxHashMap<SymbolId, Box<[SymbolId]>>,
+}
+
+#[derive(Debug)]
+pub struct ScopeSymbols {
+    /// The span of the expression that makes up the scope, or of the whole
+    /// file for the top level of a module.
+    pub span: Span,
+    pub symbols: Box<[SymbolId]>,
+}
+
+impl<'s> Resolution<'s> {
+    pub fn symbol(&self, id: SymbolId) -> &Symbol<'s> {
+        &self.symbols[id.0 as usize]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SymbolId(pub u32);
+
+#[derive(Debug)]
+pub struct Symbol<'s> {
+    pub name: Intern<'s>,
+    pub kind: SymbolKind,
+    pub mutable: bool,
+    /// The span of the name where the symbol is bound.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Def,
+    Param,
+    Builtin(Builtin),
+    /// A module brought into scope by a `use`.
+    Module(FileId),
+    /// A `trait`, which is only a name for the types of its methods.
+    Trait,
+    /// A method declared by the given trait.
+    Method(SymbolId),
+    /// An `impl`, which is named after the trait it implements but doesn't
+    /// bind the name.
+    Impl,
+    /// The type that the methods of a trait are declared in terms of.
+    TypeParam,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Capture {
+    pub symbol: SymbolId,
+    pub mode: CaptureMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureMode {
+    ByValue,
+    ByRef,
+}
+
+#[derive(Debug)]
+pub struct ResolveError<'s> {
+    pub kind: ResolveErrorKind<'s>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ResolveErrorKind<'s> {
+    Unresolved(Intern<'s>),
+    Duplicate(Intern<'s>),
+    InvalidPattern,
+    /// A lambda's argument pattern has a literal or a variant in it, which
+    /// not every argument would match.
+    RefutablePattern,
+    /// `set` was used on something other than `set <place> <value>`.
+    InvalidSet,
+    NotFoundInModule {
+        name: Intern<'s>,
+        module: String,
+    },
+    PrivateInModule {
+        name: Intern<'s>,
+        module: String,
+    },
+    /// A module was used as a value rather than accessed with `.`.
+    ModuleAsValue(Intern<'s>),
+    UnknownAttribute(Intern<'s>),
+    /// An attribute was given an argument it doesn't take, or is missing one.
+    InvalidAttributeArgument(Intern<'s>),
+    /// `@extern` named a calling convention other than `"c"`.
+    UnknownAbi(Intern<'s>),
+    /// A def without a body, `def name :: T;`, isn't marked `@extern`.
+    MissingBody(Intern<'s>),
+    /// A def marked `@extern` has a body instead of just a type.
+    ExternWithBody(Intern<'s>),
+    /// A def of a block was used before its value is computed, by something
+    /// that runs before it rather than from inside a lambda.
+    UsedBeforeDefinition {
+        name: Intern<'s>,
+        /// The name of the def, which the use is reported next to.
+        definition: Span,
+    },
+    /// A trait was used as a value rather than implemented with `impl`.
+    TraitAsValue(Intern<'s>),
+    /// An `impl` named something other than a trait.
+    NotATrait(Intern<'s>),
+    /// A `trait` or an `impl` isn't at the top level of a module.
+    NotTopLevel(Intern<'s>),
+    /// An `impl` defines something that its trait doesn't declare.
+    NotAMethod {
+        name: Intern<'s>,
+        trait_: Intern<'s>,
+    },
+    /// An `impl` leaves out a method that its trait declares.
+    MissingMethod {
+        name: Intern<'s>,
+        trait_: Intern<'s>,
+    },
+}
+
+/// How an identifier is being used, which determines how it must be captured.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum UseMode {
+    Read,
+    Val,
+}
+
+struct Resolver<'s, 'e> {
+    errors: &'e ErrorStream<'s>,
+    manager: &'e ParseManager<'s>,
+    /// The builtins and the public defs of the prelude module, looked up by
+    /// their contents since builtin names weren't interned by the tokenizer.
+    prelude: FxHashMap<&'s str, SymbolId>,
+    /// The top-level defs of each module, and whether they are public.
+    module_defs: FxHashMap<FileId, Vec<(SymbolId, bool)>>,
+    scopes: Vec<FxHashMap<Intern<'s>, SymbolId>>,
+    lambdas: Vec<LambdaFrame>,
+    /// The defs of the blocks being resolved, with the span of each def and
+    /// the number of lambdas that were open when its block was entered. A
+    /// use of one of them with no more lambdas open has to come after it.
+    ordered: FxHashMap<SymbolId, (Span, usize)>,
+    res: Resolution<'s>,
+}
+
+struct LambdaFrame {
+    /// The number of scopes that were open when the lambda was entered.
+    /// Any symbol found in a scope below this depth is captured.
+    depth: usize,
+    captures: Vec<Capture>,
+}
+
+impl<'s, 'e> Resolver<'s, 'e> {
+    fn expr(&mut self, expr: &parser::Expr<'s>, mode: UseMode) -> Expr<'s> {
+        use parser::ExprKind as P;
+
+        let kind = match &expr.kind {
+            P::Object(scope) => ExprKind::Object(Box::new(self.scope(scope, expr.span, false))),
+            P::Block(scope) => ExprKind::Block(Box::new(self.scope(scope, expr.span, true))),
+            P::Lambda { arg, body } => {
+                self.lambdas.push(LambdaFrame {
+                    depth: self.scopes.len(),
+                    captures: Vec::new(),
+                });
+                self.scopes.push(FxHashMap::default());
+                let arg = self.pattern(arg, false);
+                if arg.is_refutable() {
+                    self.errors.error(ResolveError {
+                        kind: ResolveErrorKind::RefutablePattern,
+                        span: arg.span,
+                    });
+                }
+                let body = self.expr(body, UseMode::Read);
+                self.pop_scope(expr.span);
+
+                let frame = self.lambdas.pop().unwrap();
+                self.res
+                    .captures
+                    .insert(expr.span, frame.captures.into_boxed_slice());
 
-use crate::{parser, string_storage::StringStorage};
+                ExprKind::Lambda {
+                    arg: Box::new(arg),
+                    body: Box::new(body),
+                }
+            }
+            P::BinOp { op, lhs, rhs } => ExprKind::BinOp {
+                op: bin_op(op),
+                lhs: Box::new(self.expr(lhs, UseMode::Read)),
+                rhs: Box::new(self.expr(rhs, UseMode::Read)),
+            },
+            P::UnOp {
+                op: parser::UnOp::Val,
+                arg,
+            } => ExprKind::UnOp {
+                op: UnOp::Val,
+                arg: Box::new(self.expr(arg, UseMode::Val)),
+            },
+            P::UnOp {
+                op: parser::UnOp::Set,
+                arg,
+            } => match &arg.kind {
+                P::Apply { a, b } => ExprKind::Set {
+                    place: Box::new(self.expr(a, UseMode::Read)),
+                    value: Box::new(self.expr(b, UseMode::Read)),
+                },
+                _ => {
+                    self.errors.error(ResolveError {
+                        kind: ResolveErrorKind::InvalidSet,
+                        span: expr.span,
+                    });
+                    ExprKind::Error
+                }
+            },
+            P::UnOp { op, arg } => ExprKind::UnOp {
+                op: match op {
+                    parser::UnOp::Not => UnOp::Not,
+                    parser::UnOp::Ref => UnOp::Ref,
+                    parser::UnOp::Deref => UnOp::Deref,
+                    parser::UnOp::Set | parser::UnOp::Val => unreachable!(),
+                },
+                arg: Box::new(self.expr(arg, UseMode::Read)),
+            },
+            P::Access {
+                expr: module,
+                prop: parser::AccessRhs::Prop(prop),
+            } if self.is_module(module) => {
+                let P::Ident(name) = module.kind else {
+                    unreachable!()
+                };
+                let prop_span = Span {
+                    start: expr.span.end - prop.0.len() as u32,
+                    end: expr.span.end,
+                };
+                let id = self.ident(name, module.span, UseMode::Read);
 
-pub fn resolve<'a>(
-    storage: &'a StringStorage,
-    parse: impl Fn(&Path) -> parser::Expr<'a>,
-) -> Expr<'a> {
-    todo!()
+                match id.and_then(|id| self.module_item(id, *prop, prop_span)) {
+                    Some(id) => ExprKind::Ident(id),
+                    None => ExprKind::Error,
+                }
+            }
+            P::Access { expr, prop } => ExprKind::Access {
+                expr: Box::new(self.expr(expr, UseMode::Read)),
+                prop: match prop {
+                    parser::AccessRhs::Prop(prop) => AccessRhs::Prop(*prop),
+                    parser::AccessRhs::Expr(prop) => {
+                        AccessRhs::Expr(Box::new(self.expr(prop, UseMode::Read)))
+                    }
+                },
+            },
+            P::Branch {
+                cond,
+                on_true,
+                on_false,
+            } => ExprKind::Branch {
+                cond: Box::new(self.expr(cond, UseMode::Read)),
+                on_true: Box::new(self.expr(on_true, UseMode::Read)),
+                on_false: on_false
+                    .as_ref()
+                    .map(|on_false| Box::new(self.expr(on_false, UseMode::Read))),
+            },
+            P::Tuple { items } => ExprKind::Tuple {
+                items: items.iter().map(|item| self.expr(item, mode)).collect(),
+            },
+            P::Apply { a, b } => ExprKind::Apply {
+                a: Box::new(self.expr(a, UseMode::Read)),
+                b: Box::new(self.expr(b, UseMode::Read)),
+            },
+            P::TypeAssertion { a, b } => ExprKind::TypeAssertion {
+                a: Box::new(self.expr(a, UseMode::Read)),
+                b: Box::new(self.expr(b, UseMode::Read)),
+            },
+            P::Arrow { arg, ret } => ExprKind::Arrow {
+                arg: Box::new(self.expr(arg, UseMode::Read)),
+                ret: Box::new(self.expr(ret, UseMode::Read)),
+            },
+            // only the value of a def can be a declaration, a trait or an
+            // impl, which `def` handles
+            P::Declaration(_) | P::Trait { .. } | P::Impl { .. } => ExprKind::Error,
+            P::Variant(items) => ExprKind::Variant(
+                items
+                    .iter()
+                    .map(|item| VariantItem {
+                        name: item.name,
+                        value: item
+                            .value
+                            .as_ref()
+                            .map(|value| self.expr(value, UseMode::Read)),
+                        span: item.span,
+                    })
+                    .collect(),
+            ),
+            P::Match { scrutinee, arms } => ExprKind::Match {
+                scrutinee: Box::new(self.expr(scrutinee, UseMode::Read)),
+                arms: arms
+                    .iter()
+                    .map(|arm| {
+                        self.scopes.push(FxHashMap::default());
+                        let pattern = self.pattern(&arm.pattern, false);
+                        let body = self.expr(&arm.body, UseMode::Read);
+                        self.pop_scope(Span {
+                            start: arm.pattern.span.start,
+                            end: arm.body.span.end,
+                        });
+                        MatchArm { pattern, body }
+                    })
+                    .collect(),
+            },
+            P::Ident(name) => match self.ident(*name, expr.span, mode) {
+                Some(id) if matches!(self.res.symbol(id).kind, SymbolKind::Module(_)) => {
+                    self.errors.error(ResolveError {
+                        kind: ResolveErrorKind::ModuleAsValue(*name),
+                        span: expr.span,
+                    });
+                    ExprKind::Error
+                }
+                Some(id) if self.res.symbol(id).kind == SymbolKind::Trait => {
+                    self.errors.error(ResolveError {
+                        kind: ResolveErrorKind::TraitAsValue(*name),
+                        span: expr.span,
+                    });
+                    ExprKind::Error
+                }
+                Some(id) => ExprKind::Ident(id),
+                None => ExprKind::Error,
+            },
+            P::Literal(lit) => ExprKind::Literal(literal(lit)),
+            P::MacroCall { .. } => unreachable!("macros are expanded by the parser"),
+        };
+
+        Expr {
+            kind,
+            span: expr.span,
+        }
+    }
+
+    fn scope(&mut self, scope: &parser::Scope<'s>, span: Span, ordered: bool) -> Scope<'s> {
+        let symbols = scope.defs.iter().map(|def| self.def_symbol(def)).collect();
+        self.scope_with(scope, symbols, span, ordered)
+    }
+
+    /// Resolves a scope covering `span` whose defs have already been given
+    /// the symbols in `symbols`. The defs of an `ordered` scope, a block,
+    /// can't be used before they're defined.
+    fn scope_with(
+        &mut self,
+        scope: &parser::Scope<'s>,
+        symbols: Vec<SymbolId>,
+        span: Span,
+        ordered: bool,
+    ) -> Scope<'s> {
+        self.scopes.push(FxHashMap::default());
+
+        for (def, &id) in scope.defs.iter().zip(&symbols) {
+            if self.res.symbol(id).kind == SymbolKind::Impl {
+                continue;
+            }
+            self.bind(def.name, id, def.name_span);
+            if ordered {
+                self.ordered.insert(id, (def.span, self.lambdas.len()));
+            }
+        }
+        // the methods of the traits of a module, which come after its defs
+        for &id in &symbols[scope.defs.len().min(symbols.len())..] {
+            let (name, span) = (self.res.symbol(id).name, self.res.symbol(id).span);
+            self.bind(name, id, span);
+        }
+
+        let defs: Box<[Def]> = scope
+            .defs
+            .iter()
+            .zip(symbols)
+            .map(|(def, symbol)| self.def(def, symbol))
+            .collect();
+
+        let body = scope
+            .body
+            .iter()
+            .map(|expr| self.expr(expr, UseMode::Read))
+            .collect();
+
+        if ordered {
+            for def in &defs {
+                self.ordered.remove(&def.symbol);
+            }
+        }
+        self.pop_scope(span);
+
+        Scope {
+            defs,
+            body,
+            trailing_semi: scope.trailing_semi,
+        }
+    }
+
+    /// Resolves a def that has already been given the symbol `symbol`.
+    fn def(&mut self, def: &parser::Def<'s>, symbol: SymbolId) -> Def<'s> {
+        let mut constant = false;
+        let mut external = false;
+        for attribute in def.attributes.iter() {
+            match attribute.name.0 {
+                "const" => constant = true,
+                "extern" => external = true,
+                _ => {}
+            }
+            let kind = match (attribute.name.0, attribute.arg) {
+                ("const", None) => continue,
+                ("extern", Some(abi)) if abi.0 == "c" => continue,
+                ("extern", Some(abi)) => ResolveErrorKind::UnknownAbi(abi),
+                ("const" | "extern", _) => {
+                    ResolveErrorKind::InvalidAttributeArgument(attribute.name)
+                }
+                _ => ResolveErrorKind::UnknownAttribute(attribute.name),
+            };
+            self.errors.error(ResolveError {
+                kind,
+                span: attribute.span,
+            });
+        }
+
+        let value = match &def.value.kind {
+            parser::ExprKind::Declaration(ty) if external => Expr {
+                kind: ExprKind::Extern {
+                    name: def.name,
+                    ty: Box::new(self.expr(ty, UseMode::Read)),
+                },
+                span: def.value.span,
+            },
+            parser::ExprKind::Declaration(_) => {
+                self.errors.error(ResolveError {
+                    kind: ResolveErrorKind::MissingBody(def.name),
+                    span: def.span,
+                });
+                Expr {
+                    kind: ExprKind::Error,
+                    span: def.value.span,
+                }
+            }
+            parser::ExprKind::Trait {
+                param,
+                param_span,
+                defs,
+            } => self.r#trait(def, symbol, *param, *param_span, defs),
+            parser::ExprKind::Impl { ty, defs } => self.r#impl(def, symbol, ty, defs),
+            _ if external => {
+                self.errors.error(ResolveError {
+                    kind: ResolveErrorKind::ExternWithBody(def.name),
+                    span: def.value.span,
+                });
+                self.expr(&def.value, UseMode::Read)
+            }
+            parser::ExprKind::UnOp {
+                op: parser::UnOp::Set,
+                arg,
+            } => self.expr(arg, UseMode::Read),
+            _ => self.expr(&def.value, UseMode::Read),
+        };
+
+        Def {
+            symbol,
+            constant,
+            value: Box::new(value),
+            span: def.span,
+        }
+    }
+
+    /// Resolves the value of a `trait` def, whose methods were declared
+    /// along with the other top-level defs of its module. Their types are
+    /// resolved in a scope of their own, with the trait's parameter in it.
+    fn r#trait(
+        &mut self,
+        def: &parser::Def<'s>,
+        symbol: SymbolId,
+        param: Intern<'s>,
+        param_span: Span,
+        defs: &[parser::Def<'s>],
+    ) -> Expr<'s> {
+        let span = def.value.span;
+        let Some(methods) = self.res.traits.get(&symbol).cloned() else {
+            return self.not_top_level(def);
+        };
+
+        self.scopes.push(FxHashMap::default());
+        let param = self.declare(param, SymbolKind::TypeParam, false, param_span);
+        let methods = defs
+            .iter()
+            .zip(methods.iter())
+            .map(|(method, &id)| {
+                let parser::ExprKind::Declaration(ty) = &method.value.kind else {
+                    unreachable!("a trait only declares its methods")
+                };
+                Def {
+                    symbol: id,
+                    constant: false,
+                    value: Box::new(self.expr(ty, UseMode::Read)),
+                    span: method.span,
+                }
+            })
+            .collect();
+        self.pop_scope(span);
+
+        Expr {
+            kind: ExprKind::Trait { param, methods },
+            span,
+        }
+    }
+
+    /// Resolves the value of an `impl` def. The name of the def is a use of
+    /// the trait, and its defs have to be the trait's methods. They aren't
+    /// bound anywhere, since using a method picks out the impl by its type.
+    fn r#impl(
+        &mut self,
+        def: &parser::Def<'s>,
+        symbol: SymbolId,
+        ty: &parser::Expr<'s>,
+        defs: &[parser::Def<'s>],
+    ) -> Expr<'s> {
+        let span = def.value.span;
+        if self.res.symbol(symbol).kind != SymbolKind::Impl {
+            return self.not_top_level(def);
+        }
+        let error = Expr {
+            kind: ExprKind::Error,
+            span,
+        };
+        let trait_ = match self.ident(def.name, def.name_span, UseMode::Read) {
+            Some(id) if self.res.symbol(id).kind == SymbolKind::Trait => id,
+            Some(_) => {
+                self.errors.error(ResolveError {
+                    kind: ResolveErrorKind::NotATrait(def.name),
+                    span: def.name_span,
+                });
+                return error;
+            }
+            None => return error,
+        };
+        let methods = self.res.traits[&trait_].clone();
+        let ty = self.expr(ty, UseMode::Read);
+
+        let mut names = Vec::new();
+        let defs = defs
+            .iter()
+            .map(|method| {
+                let id = self.def_symbol(method);
+                let kind = if names.contains(&method.name) {
+                    Some(ResolveErrorKind::Duplicate(method.name))
+                } else if !methods
+                    .iter()
+                    .any(|&m| self.res.symbol(m).name == method.name)
+                {
+                    Some(ResolveErrorKind::NotAMethod {
+                        name: method.name,
+                        trait_: def.name,
+                    })
+                } else {
+                    None
+                };
+                if let Some(kind) = kind {
+                    self.errors.error(ResolveError {
+                        kind,
+                        span: method.name_span,
+                    });
+                }
+                names.push(method.name);
+                self.def(method, id)
+            })
+            .collect();
+        for &method in methods.iter() {
+            let name = self.res.symbol(method).name;
+            if !names.contains(&name) {
+                self.errors.error(ResolveError {
+                    kind: ResolveErrorKind::MissingMethod {
+                        name,
+                        trait_: def.name,
+                    },
+                    span: def.name_span,
+                });
+            }
+        }
+
+        Expr {
+            kind: ExprKind::Impl {
+                trait_,
+                ty: Box::new(ty),
+                defs,
+            },
+            span,
+        }
+    }
+
+    /// Reports a `trait` or an `impl` in a nested scope, where it's left
+    /// out.
+    fn not_top_level(&mut self, def: &parser::Def<'s>) -> Expr<'s> {
+        self.errors.error(ResolveError {
+            kind: ResolveErrorKind::NotTopLevel(def.name),
+            span: def.span,
+        });
+        Expr {
+            kind: ExprKind::Error,
+            span: def.value.span,
+        }
+    }
+
+    /// Leaves the innermost scope, recording what was bound in it.
+    fn pop_scope(&mut self, span: Span) {
+        let scope = self.scopes.pop().unwrap();
+        let mut symbols = scope.into_values().collect::<Vec<_>>();
+        symbols.sort();
+        self.res.scopes.push(ScopeSymbols {
+            span,
+            symbols: symbols.into(),
+        });
+    }
+
+    /// Declares the bindings introduced by the argument pattern of a lambda
+    /// or the pattern of a `match` arm.
+    fn pattern(&mut self, pat: &parser::Expr<'s>, mutable: bool) -> Pattern<'s> {
+        use parser::ExprKind as P;
+
+        let kind = match &pat.kind {
+            P::Ident(name) if name.0 == "_" => PatternKind::Wildcard,
+            P::Ident(name) => {
+                PatternKind::Bind(self.declare(*name, SymbolKind::Param, mutable, pat.span))
+            }
+            P::Tuple { items } => PatternKind::Tuple(
+                items
+                    .iter()
+                    .map(|item| self.pattern(item, mutable))
+                    .collect(),
+            ),
+            P::UnOp {
+                op: op @ (parser::UnOp::Set | parser::UnOp::Val),
+                arg,
+            } => return self.pattern(arg, matches!(op, parser::UnOp::Set)),
+            P::TypeAssertion { a, b } => {
+                let ty = self.expr(b, UseMode::Read);
+                PatternKind::Typed {
+                    pat: Box::new(self.pattern(a, mutable)),
+                    ty: Box::new(ty),
+                }
+            }
+            P::Literal(lit) => PatternKind::Literal(literal(lit)),
+            P::Variant(items) if items.len() == 1 => PatternKind::Variant {
+                name: items[0].name,
+                value: items[0]
+                    .value
+                    .as_ref()
+                    .map(|value| Box::new(self.pattern(value, mutable))),
+            },
+            _ => {
+                self.errors.error(ResolveError {
+                    kind: ResolveErrorKind::InvalidPattern,
+                    span: pat.span,
+                });
+                PatternKind::Error
+            }
+        };
+
+        Pattern {
+            kind,
+            span: pat.span,
+        }
+    }
+
+    fn import(&mut self, import: &Import<'s>) {
+        self.res.modules.insert(import.module_span, import.module);
+        let name = import.name;
+        let id = match import.item {
+            None => self.symbol(
+                name.name,
+                SymbolKind::Module(import.module),
+                false,
+                name.span,
+            ),
+            Some(item) => {
+                let Some(module) = self.manager.module(import.module) else {
+                    // the module failed to parse, which has already been reported
+                    return;
+                };
+                let Some(id) = self.find_module_item(module.file, item.name, item.span) else {
+                    return;
+                };
+                self.record_use(item.span, id);
+                id
+            }
+        };
+
+        self.bind(name.name, id, name.span);
+    }
+
+    /// Resolves `name` as an item of the module bound to `module`.
+    fn module_item(&mut self, module: SymbolId, name: Intern<'s>, span: Span) -> Option<SymbolId> {
+        let SymbolKind::Module(file) = self.res.symbol(module).kind else {
+            unreachable!()
+        };
+        let id = self.find_module_item(file, name, span)?;
+        self.record_use(span, id);
+        Some(id)
+    }
+
+    fn find_module_item(&self, file: FileId, name: Intern<'s>, span: Span) -> Option<SymbolId> {
+        let defs = self.module_defs.get(&file).map_or(&[][..], |d| &d[..]);
+        // an impl has the name of its trait without being it
+        let found = defs.iter().find(|(id, _)| {
+            let symbol = self.res.symbol(*id);
+            symbol.name == name && symbol.kind != SymbolKind::Impl
+        });
+
+        match found {
+            Some(&(id, true)) => Some(id),
+            Some(&(_, false)) => {
+                self.errors.error(ResolveError {
+                    kind: ResolveErrorKind::PrivateInModule {
+                        name,
+                        module: self.module_name(file),
+                    },
+                    span,
+                });
+                None
+            }
+            None => {
+                if self.manager.module(file).is_some() {
+                    self.errors.error(ResolveError {
+                        kind: ResolveErrorKind::NotFoundInModule {
+                            name,
+                            module: self.module_name(file),
+                        },
+                        span,
+                    });
+                }
+                None
+            }
+        }
+    }
+
+    fn module_name(&self, file: FileId) -> String {
+        self.manager
+            .module(file)
+            .map_or_else(String::new, |m| m.name.clone())
+    }
+
+    /// Returns whether `expr` is an identifier that refers to a module, without
+    /// recording anything about the identifier.
+    fn is_module(&self, expr: &parser::Expr<'s>) -> bool {
+        let parser::ExprKind::Ident(name) = expr.kind else {
+            return false;
+        };
+
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name))
+            .is_some_and(|id| matches!(self.res.symbol(*id).kind, SymbolKind::Module(_)))
+    }
+
+    /// The symbol of a top-level def of a module, which can also be a
+    /// `trait` or an `impl`.
+    fn item_symbol(&mut self, def: &parser::Def<'s>) -> SymbolId {
+        let kind = match def.value.kind {
+            parser::ExprKind::Trait { .. } => SymbolKind::Trait,
+            parser::ExprKind::Impl { .. } => SymbolKind::Impl,
+            _ => return self.def_symbol(def),
+        };
+        self.symbol(def.name, kind, false, def.name_span)
+    }
+
+    fn def_symbol(&mut self, def: &parser::Def<'s>) -> SymbolId {
+        let mutable = matches!(
+            def.value.kind,
+            parser::ExprKind::UnOp {
+                op: parser::UnOp::Set,
+                ..
+            }
+        );
+        self.symbol(def.name, SymbolKind::Def, mutable, def.name_span)
+    }
+
+    fn declare(
+        &mut self,
+        name: Intern<'s>,
+        kind: SymbolKind,
+        mutable: bool,
+        span: Span,
+    ) -> SymbolId {
+        let id = self.symbol(name, kind, mutable, span);
+        self.bind(name, id, span);
+        id
+    }
+
+    fn symbol(
+        &mut self,
+        name: Intern<'s>,
+        kind: SymbolKind,
+        mutable: bool,
+        span: Span,
+    ) -> SymbolId {
+        let id = SymbolId(self.res.symbols.len() as u32);
+        self.res.symbols.push(Symbol {
+            name,
+            kind,
+            mutable,
+            span,
+        });
+        id
+    }
+
+    fn bind(&mut self, name: Intern<'s>, id: SymbolId, span: Span) {
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.insert(name, id).is_some() {
+            self.errors.error(ResolveError {
+                kind: ResolveErrorKind::Duplicate(name),
+                span,
+            });
+        }
+    }
+
+    fn ident(&mut self, name: Intern<'s>, span: Span, mode: UseMode) -> Option<SymbolId> {
+        let Some((depth, id)) = self
+            .scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(depth, scope)| scope.get(&name).map(|id| (depth, *id)))
+        else {
+            // the prelude is global, so it is never captured
+            if let Some(&id) = self.prelude.get(name.0) {
+                self.record_use(span, id);
+                return Some(id);
+            }
+
+            self.errors.error(ResolveError {
+                kind: ResolveErrorKind::Unresolved(name),
+                span,
+            });
+            return None;
+        };
+
+        self.record_use(span, id);
+
+        if let Some(&(def, lambdas)) = self.ordered.get(&id) {
+            if lambdas == self.lambdas.len() && span.start < def.end {
+                self.errors.error(ResolveError {
+                    kind: ResolveErrorKind::UsedBeforeDefinition {
+                        name,
+                        definition: self.res.symbol(id).span,
+                    },
+                    span,
+                });
+            }
+        }
+
+        // a method isn't a binding of its own but stands for the method of
+        // whichever impl it's used at, so there's nothing to capture
+        if matches!(
+            self.res.symbol(id).kind,
+            SymbolKind::Module(_) | SymbolKind::Method(_)
+        ) {
+            return Some(id);
+        }
+
+        let capture_mode = if self.res.symbol(id).mutable && mode != UseMode::Val {
+            CaptureMode::ByRef
+        } else {
+            CaptureMode::ByValue
+        };
+
+        // every lambda between the use and the definition has to capture the symbol
+        for frame in self.lambdas.iter_mut().rev() {
+            if frame.depth <= depth {
+                break;
+            }
+
+            if let Some(capture) = frame.captures.iter_mut().find(|c| c.symbol == id) {
+                if capture_mode == CaptureMode::ByRef {
+                    capture.mode = CaptureMode::ByRef;
+                }
+            } else {
+                frame.captures.push(Capture {
+                    symbol: id,
+                    mode: capture_mode,
+                });
+            }
+        }
+
+        Some(id)
+    }
+
+    fn record_use(&mut self, span: Span, id: SymbolId) {
+        self.res.uses.insert(span, id);
+        self.res.references.entry(id).or_default().push(span);
+    }
 }
 
-struct Resolver {
+fn literal<'s>(lit: &parser::Literal<'s>) -> Literal<'s> {
+    match lit {
+        parser::Literal::Float(f) => Literal::Float(*f),
+        parser::Literal::Integer(i) => Literal::Integer(*i),
+        parser::Literal::BigInteger(i) => Literal::BigInteger(*i),
+        parser::Literal::String(s) => Literal::String(*s),
+    }
+}
 
+fn bin_op(op: &parser::BinOp) -> BinOp {
+    match op {
+        parser::BinOp::Equal => BinOp::Equal,
+        parser::BinOp::NotEqual => BinOp::NotEqual,
+        parser::BinOp::Gt => BinOp::Gt,
+        parser::BinOp::GtEq => BinOp::GtEq,
+        parser::BinOp::Lt => BinOp::Lt,
+        parser::BinOp::LtEq => BinOp::LtEq,
+        parser::BinOp::Add => BinOp::Add,
+        parser::BinOp::Sub => BinOp::Sub,
+        parser::BinOp::Mul => BinOp::Mul,
+        parser::BinOp::Div => BinOp::Div,
+        parser::BinOp::Mod => BinOp::Mod,
+        parser::BinOp::And => BinOp::And,
+        parser::BinOp::Or => BinOp::Or,
+    }
 }
@@ -0,0 +1,148 @@
+//! The implicit outermost scope of every program.
+//!
+//! It is made up of the [Builtin]s, which the compiler provides, and the
+//! `pub def`s of the prelude module in `prelude.radi`, which is written in
+//! radi on top of them and loaded along with every program.
+//!
+//! Lists, options and results have no dedicated runtime representation: a
+//! list is either `|Nil` or `|Cons: (head, tail)`, an option is `|Some: x`
+//! or `|None`, and a result is `|Ok: x` or `|Err: e`. Only the type checker
+//! knows about lists, and the builtins that build and take apart lists,
+//! options and results are expanded into ordinary HIR by lowering, so
+//! backends never see them.
+
+/// The source of the prelude module.
+pub const SOURCE: &str = include_str!("prelude.radi");
+
+/// The path the prelude module is given in the source map, which can't
+/// clash with a real file.
+pub const PATH: &str = "<prelude>";
+
+/// A symbol provided by the compiler rather than defined in source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Builtin {
+    /* Values */
+    Print,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Neg,
+    True,
+    False,
+
+    /* Strings */
+    StringLength,
+    Substring,
+    IntToString,
+
+    /* Math */
+    ToFloat,
+    ToInt,
+    Sqrt,
+    Floor,
+
+    /* Big integers */
+    ToBig,
+    BigToInt,
+    BigToString,
+
+    /* Lists, options and results */
+    Nil,
+    Cons,
+    MatchList,
+    MatchOption,
+    MatchResult,
+
+    /* Types */
+    Int,
+    Float,
+    BigInt,
+    String,
+    Bool,
+    Unit,
+}
+
+impl Builtin {
+    /// Every builtin, in the order they are declared in the prelude scope.
+    pub const ALL: &'static [Builtin] = &[
+        Builtin::Print,
+        Builtin::Add,
+        Builtin::Sub,
+        Builtin::Mul,
+        Builtin::Div,
+        Builtin::Mod,
+        Builtin::Neg,
+        Builtin::True,
+        Builtin::False,
+        Builtin::StringLength,
+        Builtin::Substring,
+        Builtin::IntToString,
+        Builtin::ToFloat,
+        Builtin::ToInt,
+        Builtin::Sqrt,
+        Builtin::Floor,
+        Builtin::ToBig,
+        Builtin::BigToInt,
+        Builtin::BigToString,
+        Builtin::Nil,
+        Builtin::Cons,
+        Builtin::MatchList,
+        Builtin::MatchOption,
+        Builtin::MatchResult,
+        Builtin::Int,
+        Builtin::Float,
+        Builtin::BigInt,
+        Builtin::String,
+        Builtin::Bool,
+        Builtin::Unit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Print => "print",
+            Builtin::Add => "add",
+            Builtin::Sub => "sub",
+            Builtin::Mul => "mul",
+            Builtin::Div => "div",
+            Builtin::Mod => "mod",
+            Builtin::Neg => "neg",
+            Builtin::True => "true",
+            Builtin::False => "false",
+            Builtin::StringLength => "string_length",
+            Builtin::Substring => "substring",
+            Builtin::IntToString => "int_to_string",
+            Builtin::ToFloat => "to_float",
+            Builtin::ToInt => "to_int",
+            Builtin::Sqrt => "sqrt",
+            Builtin::Floor => "floor",
+            Builtin::ToBig => "to_big",
+            Builtin::BigToInt => "big_to_int",
+            Builtin::BigToString => "big_to_string",
+            Builtin::Nil => "nil",
+            Builtin::Cons => "cons",
+            Builtin::MatchList => "match_list",
+            Builtin::MatchOption => "match_option",
+            Builtin::MatchResult => "match_result",
+            Builtin::Int => "Int",
+            Builtin::Float => "Float",
+            Builtin::BigInt => "BigInt",
+            Builtin::String => "String",
+            Builtin::Bool => "Bool",
+            Builtin::Unit => "Unit",
+        }
+    }
+
+    pub fn is_type(self) -> bool {
+        matches!(
+            self,
+            Builtin::Int
+                | Builtin::Float
+                | Builtin::BigInt
+                | Builtin::String
+                | Builtin::Bool
+                | Builtin::Unit
+        )
+    }
+}
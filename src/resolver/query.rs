@@ -0,0 +1,484 @@
+//! Queries over the resolution tables that map source positions to symbols,
+//! for use by editor tooling.
+
+use crate::{
+    char_reader::IoCharReader,
+    source_map::{FileId, SourceFile, SourceMap},
+    string_storage::StringStorage,
+    tokenizer::{Intern, Span, TokenKind, Tokens},
+    typeck::{Type, Typing},
+};
+
+use super::{Resolution, SymbolId, SymbolKind};
+
+/// Finds the symbol whose name is at `offset`, whether the offset is on a use
+/// of the symbol or on the name where it is bound.
+///
+/// An offset just past the end of a name still counts as being on it, since
+/// that is where the cursor usually is after typing one.
+pub fn symbol_at(file: &Resolution, offset: u32) -> Option<(Span, SymbolId)> {
+    let contains = |span: &Span| span.start <= offset && offset <= span.end;
+
+    file.uses
+        .iter()
+        .find(|(span, _)| contains(span))
+        .map(|(span, id)| (*span, *id))
+        .or_else(|| {
+            file.symbols
+                .iter()
+                .enumerate()
+                .find(|(_, symbol)| {
+                    !matches!(symbol.kind, SymbolKind::Builtin(_)) && contains(&symbol.span)
+                })
+                .map(|(id, symbol)| (symbol.span, SymbolId(id as u32)))
+        })
+}
+
+/// Where going to the definition of something leads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Definition {
+    /// The name where a symbol is bound, which may be in another file when
+    /// the symbol was imported with a `use`.
+    Symbol(Span, SymbolId),
+    /// A whole module, for a module imported with a `use` or named in one.
+    Module(FileId),
+}
+
+/// Maps a cursor position to the definition of the symbol under it.
+///
+/// Builtins have no definition in source, so this returns `None` for them.
+pub fn find_definition(file: &Resolution, offset: u32) -> Option<Definition> {
+    let contains = |span: &Span| span.start <= offset && offset <= span.end;
+    if let Some((_, &module)) = file.modules.iter().find(|(span, _)| contains(span)) {
+        return Some(Definition::Module(module));
+    }
+
+    let (_, id) = symbol_at(file, offset)?;
+    let symbol = file.symbol(id);
+
+    match symbol.kind {
+        SymbolKind::Builtin(_) => None,
+        SymbolKind::Module(module) => Some(Definition::Module(module)),
+        _ => Some(Definition::Symbol(symbol.span, id)),
+    }
+}
+
+/// Finds every identifier that refers to `symbol`, in source order.
+///
+/// The name where the symbol is bound is not included.
+pub fn references(file: &Resolution, symbol: SymbolId) -> Vec<Span> {
+    let mut spans = file.references.get(&symbol).cloned().unwrap_or_default();
+    spans.sort_by_key(|span| span.start);
+    spans
+}
+
+/// What a token is, for highlighting it in an editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenClass {
+    Keyword,
+    Def,
+    Parameter,
+    Builtin,
+    /// A module brought into scope by a `use`.
+    Module,
+    /// The name after a `.`, other than one resolved to a symbol or in the
+    /// path of a `use`.
+    Property,
+    /// The name of a variant, after a `|`.
+    Variant,
+    Operator,
+    Number,
+    String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SemanticToken {
+    pub span: Span,
+    pub class: TokenClass,
+    /// Whether the token is the name where a symbol is bound.
+    pub declaration: bool,
+}
+
+/// Classifies the tokens of `file` using the results of resolving it, so
+/// that a name is highlighted by what it refers to rather than by how it's
+/// written. Without them, only names after a `.` or `|` or in a `use` are
+/// classified. Names that aren't part of any of the classes, such as type
+/// names, are left out, as are punctuation and comments.
+///
+/// The tokens are in source order. If the file can't be tokenized, the
+/// tokens before the error are returned.
+pub fn semantic_tokens(
+    file: &SourceFile,
+    storage: &StringStorage,
+    resolution: Option<&Resolution>,
+) -> Vec<SemanticToken> {
+    // the span of a parameter written in parentheses, as in `(x) { x }`,
+    // takes in the parentheses, so bindings are found by what they contain
+    let mut bindings = resolution
+        .into_iter()
+        .flat_map(|resolution| resolution.symbols.iter().enumerate())
+        .filter(|(_, symbol)| !matches!(symbol.kind, SymbolKind::Builtin(_)))
+        .filter(|(_, symbol)| file.start <= symbol.span.start && symbol.span.end <= file.end())
+        .map(|(id, symbol)| (symbol.span, SymbolId(id as u32)))
+        .collect::<Vec<_>>();
+    bindings.sort_by_key(|(span, _)| span.start);
+    // the kind of symbol a name refers to, and whether it's where it's bound
+    let symbol = |span: Span, name: &str| {
+        let resolution = resolution?;
+        if let Some(&id) = resolution.uses.get(&span) {
+            return Some((resolution.symbol(id).kind, false));
+        }
+        let i = bindings.partition_point(|(binding, _)| binding.start <= span.start);
+        let &(binding, id) = bindings.get(i.checked_sub(1)?)?;
+        let symbol = resolution.symbol(id);
+        let bound = span.end <= binding.end && symbol.name.0 == name;
+        bound.then_some((symbol.kind, true))
+    };
+    let symbol_class = |kind: SymbolKind| match kind {
+        SymbolKind::Def | SymbolKind::Trait | SymbolKind::Method(_) | SymbolKind::Impl => {
+            TokenClass::Def
+        }
+        SymbolKind::Param | SymbolKind::TypeParam => TokenClass::Parameter,
+        SymbolKind::Builtin(_) => TokenClass::Builtin,
+        SymbolKind::Module(_) => TokenClass::Module,
+    };
+
+    let mut tokens = Tokens::of(
+        IoCharReader::starting_at(file.src.as_bytes(), file.start).sized_for(file.src.len()),
+        storage,
+    );
+    let mut out = Vec::new();
+    let mut previous = None;
+    // whether the tokens are in the path of a `use`
+    let mut in_use = false;
+    while let Ok(Some(token)) = tokens.next() {
+        let mut declaration = false;
+        let class = match token.kind {
+            TokenKind::Def
+            | TokenKind::Pub
+            | TokenKind::Use
+            | TokenKind::Val
+            | TokenKind::Set
+            | TokenKind::Type
+            | TokenKind::Case
+            | TokenKind::Else
+            | TokenKind::Match
+            | TokenKind::For
+            | TokenKind::In
+            | TokenKind::Macro
+            | TokenKind::Trait
+            | TokenKind::Impl => Some(TokenClass::Keyword),
+            TokenKind::Bang
+            | TokenKind::Amp
+            | TokenKind::ThinArrow
+            | TokenKind::FatArrow
+            | TokenKind::ColonColon
+            | TokenKind::ColonEqual
+            | TokenKind::Equal
+            | TokenKind::NotEqual
+            | TokenKind::Gt
+            | TokenKind::Lt
+            | TokenKind::GtEq
+            | TokenKind::LtEq
+            | TokenKind::Plus
+            | TokenKind::Minus
+            | TokenKind::Star
+            | TokenKind::Slash
+            | TokenKind::Percent
+            | TokenKind::Caret
+            | TokenKind::Question
+            | TokenKind::AmpAmp
+            | TokenKind::PipePipe => Some(TokenClass::Operator),
+            TokenKind::Integer(_) | TokenKind::BigInteger(_) | TokenKind::Float(_) => {
+                Some(TokenClass::Number)
+            }
+            TokenKind::String(_) => Some(TokenClass::String),
+            TokenKind::Name(name) => {
+                if let Some((kind, bound)) = symbol(token.span, name.0) {
+                    declaration = bound;
+                    Some(symbol_class(kind))
+                } else {
+                    match previous {
+                        _ if in_use => Some(TokenClass::Module),
+                        Some(TokenKind::Dot) => Some(TokenClass::Property),
+                        Some(TokenKind::Pipe) => Some(TokenClass::Variant),
+                        _ => None,
+                    }
+                }
+            }
+            _ => None,
+        };
+        if let Some(class) = class {
+            out.push(SemanticToken {
+                span: token.span,
+                class,
+                declaration,
+            });
+        }
+        match token.kind {
+            TokenKind::Use => in_use = true,
+            TokenKind::Semicolon => in_use = false,
+            _ => {}
+        }
+        previous = Some(token.kind);
+    }
+    out
+}
+
+/// What is shown about the identifier under the cursor.
+#[derive(Debug)]
+pub struct Hover {
+    /// The span of the identifier.
+    pub span: Span,
+    pub symbol: SymbolId,
+    /// The span of the name where the symbol is bound, or `None` for a
+    /// builtin.
+    pub definition: Option<Span>,
+    /// The type of the identifier, if type checking has run. At a use of a
+    /// generic def, this is the type it has there.
+    pub ty: Option<String>,
+    /// The `//` comments on the lines right above a def.
+    pub doc: Option<String>,
+}
+
+/// Describes the symbol under the cursor at `offset`.
+pub fn hover(
+    source_map: &SourceMap,
+    file: &Resolution,
+    typing: Option<&Typing>,
+    offset: u32,
+) -> Option<Hover> {
+    let (span, id) = symbol_at(file, offset)?;
+    let symbol = file.symbol(id);
+    let definition = match symbol.kind {
+        SymbolKind::Builtin(_) => None,
+        _ => Some(symbol.span),
+    };
+    let ty = typing.and_then(|typing| {
+        let ty = typing.type_of(span).or(typing.symbols.get(&id).copied())?;
+        Some(typing.display(ty))
+    });
+    let doc = match symbol.kind {
+        SymbolKind::Def | SymbolKind::Trait | SymbolKind::Method(_) => {
+            let source = source_map.file(source_map.lookup(symbol.span.start));
+            doc_comment(source, symbol.span.start)
+        }
+        _ => None,
+    };
+    Some(Hover {
+        span,
+        symbol: id,
+        definition,
+        ty,
+        doc,
+    })
+}
+
+/// The text of the `//` comments on the lines right above the one `offset`
+/// is on, without the slashes.
+pub fn doc_comment(file: &SourceFile, offset: u32) -> Option<String> {
+    let before = &file.src[..(offset - file.start) as usize];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let mut lines = file.src[..line_start]
+        .lines()
+        .rev()
+        .map_while(|line| line.trim().strip_prefix("//"))
+        .map(|line| {
+            let line = line.trim_start_matches('/');
+            line.strip_prefix(' ').unwrap_or(line)
+        })
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        return None;
+    }
+    lines.reverse();
+    Some(lines.join("\n"))
+}
+
+/// A name that can be written at the cursor.
+#[derive(Debug)]
+pub struct Completion {
+    pub name: String,
+    /// What the name refers to, or `None` for the field of an object.
+    pub symbol: Option<SymbolId>,
+    /// The type of the name, if type checking has run.
+    pub ty: Option<String>,
+}
+
+/// Finds the names that complete the partial identifier before `offset`.
+///
+/// After a `.`, these are the fields of the object before it. Otherwise
+/// they are the symbols visible at the cursor, nearest scope first, then
+/// the prelude. A name that is shadowed by a nearer one is left out.
+pub fn completions(
+    source_map: &SourceMap,
+    file: &Resolution,
+    typing: Option<&Typing>,
+    offset: u32,
+) -> Vec<Completion> {
+    let source = source_map.file(source_map.lookup(offset));
+    let before = &source.src[..(offset - source.start) as usize];
+    let prefix_start = before
+        .trim_end_matches(|ch: char| ch.is_alphanumeric() || ch == '_')
+        .len();
+    let prefix = &before[prefix_start..];
+
+    if before[..prefix_start].ends_with('.') {
+        let dot = source.start + prefix_start as u32 - 1;
+        return fields(typing, dot)
+            .into_iter()
+            .filter(|completion| completion.name.starts_with(prefix))
+            .collect();
+    }
+
+    let mut scopes = file
+        .scopes
+        .iter()
+        .filter(|scope| scope.span.start <= offset && offset <= scope.span.end)
+        .collect::<Vec<_>>();
+    // scopes nest, so the smaller one is the nearer one
+    scopes.sort_by_key(|scope| scope.span.end - scope.span.start);
+    let symbols = scopes
+        .iter()
+        .flat_map(|scope| scope.symbols.iter())
+        .chain(file.prelude.iter());
+
+    let mut seen = Vec::new();
+    let mut out = Vec::new();
+    for &id in symbols {
+        let name = file.symbol(id).name.0;
+        if !name.starts_with(prefix) || seen.contains(&name) {
+            continue;
+        }
+        seen.push(name);
+        let ty = typing.and_then(|typing| Some(typing.display(*typing.symbols.get(&id)?)));
+        out.push(Completion {
+            name: name.to_string(),
+            symbol: Some(id),
+            ty,
+        });
+    }
+    out
+}
+
+/// The fields of the object whose expression ends at `dot`, sorted by name.
+fn fields(typing: Option<&Typing>, dot: u32) -> Vec<Completion> {
+    let Some(typing) = typing else {
+        return Vec::new();
+    };
+    // of the expressions ending at the dot, the outermost is the object
+    let Some(ty) = typing
+        .exprs
+        .iter()
+        .filter(|(span, _)| span.end == dot)
+        .min_by_key(|(span, _)| span.start)
+        .map(|(_, &ty)| ty)
+    else {
+        return Vec::new();
+    };
+    let Type::Object(row) = typing.types.get(typing.types.find(ty)) else {
+        return Vec::new();
+    };
+    let (entries, _) = typing.types.row(row);
+    let mut out = entries
+        .into_iter()
+        .map(|(name, ty)| Completion {
+            name: name.0.to_string(),
+            symbol: None,
+            ty: Some(typing.display(ty)),
+        })
+        .collect::<Vec<_>>();
+    out.sort_by(|a, b| a.name.cmp(&b.name));
+    out
+}
+
+#[derive(Debug)]
+pub enum RenameError<'s> {
+    NoSymbol,
+    /// Builtins, modules and the defs of the prelude aren't named anywhere
+    /// that a rename could change.
+    NotRenamable(Intern<'s>),
+    /// The new name isn't a name, or is a keyword.
+    InvalidName(String),
+    /// Another symbol with the new name would shadow the symbol, or be
+    /// shadowed by it.
+    Conflict(Intern<'s>),
+}
+
+/// Finds where the symbol at `offset` is named, so that it can be renamed to
+/// `new_name`: where it's bound and every use of it, across all files.
+/// Returns the spans of the names in source order.
+pub fn rename<'s>(
+    source_map: &SourceMap,
+    storage: &StringStorage,
+    file: &Resolution<'s>,
+    offset: u32,
+    new_name: &str,
+) -> Result<Vec<Span>, RenameError<'s>> {
+    let (_, id) = symbol_at(file, offset).ok_or(RenameError::NoSymbol)?;
+    let symbol = file.symbol(id);
+    let in_prelude = || {
+        let source = source_map.file(source_map.lookup(symbol.span.start));
+        source.path.as_os_str() == super::prelude::PATH
+    };
+    if matches!(symbol.kind, SymbolKind::Builtin(_) | SymbolKind::Module(_)) || in_prelude() {
+        return Err(RenameError::NotRenamable(symbol.name));
+    }
+
+    let mut tokens = Tokens::of(
+        IoCharReader::new(new_name.as_bytes()).sized_for(new_name.len()),
+        storage,
+    );
+    let valid = match (tokens.next(), tokens.next()) {
+        (Ok(Some(token)), Ok(None)) => {
+            matches!(token.kind, TokenKind::Name(_)) && token.span.end as usize == new_name.len()
+        }
+        _ => false,
+    };
+    if !valid {
+        return Err(RenameError::InvalidName(new_name.to_string()));
+    }
+    let mut spans = references(file, id);
+    spans.push(symbol.span);
+    // another symbol with the new name can't be bound in a scope between
+    // the symbol's and where it's named, and can't be used in the symbol's
+    // scope, since one would then shadow the other
+    let named = |other: &SymbolId| *other != id && file.symbol(*other).name.0 == new_name;
+    let contains = |outer: Span, inner: Span| outer.start <= inner.start && inner.end <= outer.end;
+    let bound = file
+        .scopes
+        .iter()
+        .filter(|scope| scope.symbols.contains(&id))
+        .map(|scope| scope.span)
+        .collect::<Vec<_>>();
+    let in_bound = |span: Span| bound.iter().any(|&scope| contains(scope, span));
+    let visible = spans.iter().find_map(|&span| {
+        let mut scopes = file
+            .scopes
+            .iter()
+            .filter(|scope| contains(scope.span, span) && in_bound(scope.span));
+        scopes.find_map(|scope| scope.symbols.iter().copied().find(named))
+    });
+    let shadowed = || {
+        file.uses
+            .iter()
+            .find(|&(&span, other)| named(other) && in_bound(span))
+            .map(|(_, &other)| other)
+    };
+    if let Some(other) = visible.or_else(shadowed) {
+        return Err(RenameError::Conflict(file.symbol(other).name));
+    }
+
+    // a name in parentheses, as in `(x) { x }`, has them in its span
+    let name_in = |span: Span| {
+        let start = span.start + source_map.snippet(span).find(symbol.name.0).unwrap_or(0) as u32;
+        Span {
+            start,
+            end: start + symbol.name.0.len() as u32,
+        }
+    };
+    let mut spans = spans.into_iter().map(name_in).collect::<Vec<_>>();
+    spans.sort_by_key(|span| span.start);
+    spans.dedup();
+    Ok(spans)
+}
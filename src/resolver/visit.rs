@@ -0,0 +1,106 @@
+//! A walk over the resolved tree, for passes that only care about some kinds
+//! of node. Each method of [Visitor] visits the children of its node by
+//! default, and an implementation that overrides one can call the matching
+//! `walk_` function to keep going deeper.
+
+use super::ast::*;
+
+pub trait Visitor<'s> {
+    fn visit_expr(&mut self, expr: &Expr<'s>) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_def(&mut self, def: &Def<'s>) {
+        walk_def(self, def)
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern<'s>) {
+        walk_pattern(self, pattern)
+    }
+}
+
+pub fn walk_expr<'s, V: Visitor<'s> + ?Sized>(v: &mut V, expr: &Expr<'s>) {
+    match &expr.kind {
+        ExprKind::Object(scope) | ExprKind::Block(scope) => walk_scope(v, scope),
+        ExprKind::Lambda { arg, body } => {
+            v.visit_pattern(arg);
+            v.visit_expr(body);
+        }
+        ExprKind::BinOp { lhs, rhs, .. } => {
+            v.visit_expr(lhs);
+            v.visit_expr(rhs);
+        }
+        ExprKind::UnOp { arg, .. } => v.visit_expr(arg),
+        ExprKind::Access { expr, prop } => {
+            v.visit_expr(expr);
+            if let AccessRhs::Expr(prop) = prop {
+                v.visit_expr(prop);
+            }
+        }
+        ExprKind::Branch {
+            cond,
+            on_true,
+            on_false,
+        } => {
+            v.visit_expr(cond);
+            v.visit_expr(on_true);
+            if let Some(on_false) = on_false {
+                v.visit_expr(on_false);
+            }
+        }
+        ExprKind::Tuple { items } => items.iter().for_each(|item| v.visit_expr(item)),
+        ExprKind::Apply { a, b }
+        | ExprKind::TypeAssertion { a, b }
+        | ExprKind::Set { place: a, value: b }
+        | ExprKind::Arrow { arg: a, ret: b } => {
+            v.visit_expr(a);
+            v.visit_expr(b);
+        }
+        ExprKind::Extern { ty, .. } => v.visit_expr(ty),
+        ExprKind::Trait { methods, .. } => methods.iter().for_each(|def| v.visit_def(def)),
+        ExprKind::Impl { ty, defs, .. } => {
+            v.visit_expr(ty);
+            defs.iter().for_each(|def| v.visit_def(def));
+        }
+        ExprKind::Match { scrutinee, arms } => {
+            v.visit_expr(scrutinee);
+            for arm in arms.iter() {
+                v.visit_pattern(&arm.pattern);
+                v.visit_expr(&arm.body);
+            }
+        }
+        ExprKind::Variant(items) => items
+            .iter()
+            .filter_map(|item| item.value.as_ref())
+            .for_each(|value| v.visit_expr(value)),
+        ExprKind::Ident(_) | ExprKind::Literal(_) | ExprKind::Error => {}
+    }
+}
+
+pub fn walk_scope<'s, V: Visitor<'s> + ?Sized>(v: &mut V, scope: &Scope<'s>) {
+    scope.defs.iter().for_each(|def| v.visit_def(def));
+    scope.body.iter().for_each(|expr| v.visit_expr(expr));
+}
+
+pub fn walk_def<'s, V: Visitor<'s> + ?Sized>(v: &mut V, def: &Def<'s>) {
+    v.visit_expr(&def.value)
+}
+
+pub fn walk_pattern<'s, V: Visitor<'s> + ?Sized>(v: &mut V, pattern: &Pattern<'s>) {
+    match &pattern.kind {
+        PatternKind::Tuple(items) => items.iter().for_each(|item| v.visit_pattern(item)),
+        PatternKind::Typed { pat, ty } => {
+            v.visit_pattern(pat);
+            v.visit_expr(ty);
+        }
+        PatternKind::Variant { value, .. } => {
+            if let Some(value) = value {
+                v.visit_pattern(value);
+            }
+        }
+        PatternKind::Bind(_)
+        | PatternKind::Wildcard
+        | PatternKind::Literal(_)
+        | PatternKind::Error => {}
+    }
+}
@@ -0,0 +1,77 @@
+//! Strongly connected components of a directed graph, found with Tarjan's
+//! algorithm.
+
+/// Groups the nodes `0..len` of a graph into strongly connected components,
+/// given the successors of each node.
+///
+/// The components are in reverse topological order, i.e. each comes after
+/// every component that it has an edge to. The nodes in each component are
+/// sorted.
+pub fn strongly_connected_components<I>(
+    len: usize,
+    successors: impl Fn(usize) -> I,
+) -> Vec<Vec<usize>>
+where
+    I: IntoIterator<Item = usize>,
+{
+    struct Tarjan<F> {
+        successors: F,
+        index: Vec<Option<usize>>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next: usize,
+        components: Vec<Vec<usize>>,
+    }
+
+    impl<F, I> Tarjan<F>
+    where
+        F: Fn(usize) -> I,
+        I: IntoIterator<Item = usize>,
+    {
+        /// Returns the lowest index reachable from `node`.
+        fn visit(&mut self, node: usize) -> usize {
+            let index = self.next;
+            let mut low = index;
+            self.next += 1;
+            self.index[node] = Some(index);
+            self.stack.push(node);
+            self.on_stack[node] = true;
+
+            for succ in (self.successors)(node) {
+                match self.index[succ] {
+                    None => low = low.min(self.visit(succ)),
+                    Some(succ_index) if self.on_stack[succ] => low = low.min(succ_index),
+                    Some(_) => {}
+                }
+            }
+
+            if low == index {
+                let start = self.stack.iter().rposition(|&n| n == node).unwrap();
+                let mut component = self.stack.split_off(start);
+                for &n in &component {
+                    self.on_stack[n] = false;
+                }
+                component.sort();
+                self.components.push(component);
+            }
+
+            low
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        successors,
+        index: vec![None; len],
+        on_stack: vec![false; len],
+        stack: Vec::new(),
+        next: 0,
+        components: Vec::new(),
+    };
+    for node in 0..len {
+        if tarjan.index[node].is_none() {
+            tarjan.visit(node);
+        }
+    }
+
+    tarjan.components
+}
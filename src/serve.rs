@@ -0,0 +1,351 @@
+//! An HTTP server for `radi serve`, so that a web playground or a bot can
+//! use the compiler without starting a process for every program.
+//!
+//! Each endpoint takes the source of a program as the body of a `POST`, and
+//! answers with a JSON object:
+//! - `/parse` only parses it, and answers with its `ast` in the format of
+//!   `--emit=json-ast`, or `null` if it doesn't parse, and its
+//!   `diagnostics`.
+//! - `/check` compiles it as far as `radi check` does, and answers with its
+//!   `diagnostics`.
+//! - `/run` also runs it if it has no errors, and answers with its
+//!   `diagnostics`, what it printed as its `output`, and the runtime `error`
+//!   it stopped with, or `null` if it didn't.
+//!
+//! A diagnostic or runtime error has a `message` and a `span` of byte
+//! offsets into the source as `[start, end]`, or `null` if it isn't in the
+//! source, and a diagnostic also has a `severity`.
+//!
+//! A request is given up on once compiling and running it goes on for
+//! longer than the timeout, and a run is stopped once it has more objects,
+//! or more bytes of strings and big integers, live than the heap allows.
+//! Only so much of its output is kept. Programs are compiled on their own,
+//! and can only `use` the prelude, so that they can't read the files of the
+//! server.
+//!
+//! Each connection is served on a thread of its own, and closed after one
+//! request. At most [MAX_CONNECTIONS] are served at once, and the rest wait
+//! to be accepted. Responses allow any origin, so that a page served from
+//! elsewhere can call the API.
+
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    doc::json_string, eval, parser, resolver::ResolveOptions, Compiler, ErrorStream, SourceMap,
+    Span, StringStorage,
+};
+
+/// Where the server listens without `--address`.
+pub const ADDRESS: &str = "127.0.0.1:7878";
+
+/// The path that programs are compiled as.
+const PATH: &str = "<serve>.radi";
+
+/// The most bytes of source a request may have.
+const MAX_BODY: usize = 1 << 20;
+
+/// The most bytes of the request line and headers.
+const MAX_HEAD: u64 = 1 << 16;
+
+/// The most bytes of output that a run keeps.
+const MAX_OUTPUT: usize = 1 << 20;
+
+/// How long a client may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many connections are served at once. Each takes a thread, and one
+/// with [eval::STACK_SIZE] of stack while its request is worked on.
+pub const MAX_CONNECTIONS: usize = 32;
+
+/// How many bytes of strings and big integers a run may have live at once.
+pub const MAX_BYTES: usize = 1 << 26;
+
+/// How long past its deadline a run is waited for, since it only stops
+/// itself once it notices the deadline has passed.
+const GRACE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+pub struct ServeOptions {
+    pub resolve: ResolveOptions,
+    /// The heap that each run gets, which caps how much memory it uses.
+    pub heap: eval::HeapOptions,
+    /// How long compiling and running a program may take.
+    pub timeout: Duration,
+}
+
+/// Serves requests on `address` until the server fails.
+pub fn run(address: &str, options: ServeOptions) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    println!("Listening on http://{}", listener.local_addr()?);
+    let slots = Arc::new(Slots::default());
+    loop {
+        let slot = Arc::new(slots.take());
+        let Ok((stream, _)) = listener.accept() else {
+            continue;
+        };
+        let spawned = thread::Builder::new().spawn(move || {
+            // a client that went away doesn't need to hear about it
+            let _ = serve(stream, options, slot);
+        });
+        if let Err(err) = spawned {
+            eprintln!("ERROR: couldn't start a thread for a connection: {err}");
+        }
+    }
+}
+
+/// The connections being served, of which there are at most
+/// [MAX_CONNECTIONS].
+#[derive(Default)]
+struct Slots {
+    taken: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl Slots {
+    /// Waits for a connection to be done with if there are too many, and
+    /// takes its place.
+    fn take(self: &Arc<Slots>) -> Slot {
+        let mut taken = self.taken.lock().unwrap();
+        while *taken >= MAX_CONNECTIONS {
+            taken = self.freed.wait(taken).unwrap();
+        }
+        *taken += 1;
+        Slot(Arc::clone(self))
+    }
+}
+
+/// The place of a connection among the [Slots], given back when it's
+/// dropped.
+struct Slot(Arc<Slots>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.0.taken.lock().unwrap() -= 1;
+        self.0.freed.notify_one();
+    }
+}
+
+/// The status of a response, as its code and reason.
+type Status = (u16, &'static str);
+
+const OK: Status = (200, "OK");
+const NO_CONTENT: Status = (204, "No Content");
+const BAD_REQUEST: Status = (400, "Bad Request");
+const NOT_FOUND: Status = (404, "Not Found");
+const METHOD_NOT_ALLOWED: Status = (405, "Method Not Allowed");
+const TOO_LARGE: Status = (413, "Content Too Large");
+const UNAVAILABLE: Status = (503, "Service Unavailable");
+
+/// Reads a request from `stream` and answers it.
+fn serve(stream: TcpStream, options: ServeOptions, slot: Arc<Slot>) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let (status, body) = match read_request(&mut reader) {
+        Ok((method, path, source)) => match (method.as_str(), path.as_str()) {
+            ("OPTIONS", _) => (NO_CONTENT, String::new()),
+            ("POST", "/parse") => within(options.timeout, slot, move |_| (OK, parse(&source))),
+            ("POST", "/check" | "/run") => {
+                let run = path == "/run";
+                within(options.timeout, slot, move |deadline| {
+                    (OK, check(&source, options, run, deadline))
+                })
+            }
+            (_, "/parse" | "/check" | "/run") => error(METHOD_NOT_ALLOWED),
+            _ => error(NOT_FOUND),
+        },
+        Err(status) => error(status),
+    };
+    let mut out = &stream;
+    let (code, reason) = status;
+    write!(
+        out,
+        "HTTP/1.1 {code} {reason}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Methods: POST, OPTIONS\r\n\
+         Access-Control-Allow-Headers: Content-Type\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    out.flush()
+}
+
+/// Reads the method, path and body of a request, whose body has to be
+/// UTF-8.
+fn read_request(reader: &mut impl BufRead) -> Result<(String, String, String), Status> {
+    let mut head = reader.take(MAX_HEAD);
+    let mut line = String::new();
+    head.read_line(&mut line).map_err(|_| BAD_REQUEST)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(BAD_REQUEST);
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let mut length = 0;
+    loop {
+        line.clear();
+        if head.read_line(&mut line).map_err(|_| BAD_REQUEST)? == 0 {
+            // the head ended early, or went on for too long
+            return Err(BAD_REQUEST);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(BAD_REQUEST);
+        };
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            length = value.trim().parse().map_err(|_| BAD_REQUEST)?;
+        }
+    }
+    if length > MAX_BODY {
+        return Err(TOO_LARGE);
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|_| BAD_REQUEST)?;
+    let body = String::from_utf8(body).map_err(|_| BAD_REQUEST)?;
+    Ok((method, path, body))
+}
+
+/// A response saying what went wrong with the request.
+fn error(status: Status) -> (Status, String) {
+    error_because(status, status.1)
+}
+
+fn error_because(status: Status, message: &str) -> (Status, String) {
+    (status, format!("{{\"error\":{}}}", json_string(message)))
+}
+
+/// Works out a response on a thread with the stack that the compiler and
+/// the interpreter need, and gives up on it once `timeout` has passed. The
+/// thread is left to finish with `slot`, so that the work given up on still
+/// counts against [MAX_CONNECTIONS].
+fn within(
+    timeout: Duration,
+    slot: Arc<Slot>,
+    work: impl FnOnce(Instant) -> (Status, String) + Send + 'static,
+) -> (Status, String) {
+    let deadline = Instant::now() + timeout;
+    let (send, receive) = mpsc::channel();
+    let spawned = thread::Builder::new()
+        .stack_size(eval::STACK_SIZE)
+        .spawn(move || {
+            let _slot = slot;
+            let _ = send.send(work(deadline));
+        });
+    if let Err(err) = spawned {
+        eprintln!("ERROR: couldn't start a thread for a request: {err}");
+        return error(UNAVAILABLE);
+    }
+    let wait = deadline.saturating_duration_since(Instant::now()) + GRACE;
+    match receive.recv_timeout(wait) {
+        Ok(response) => response,
+        Err(_) => error_because(UNAVAILABLE, "the program took too long to compile"),
+    }
+}
+
+fn parse(source: &str) -> String {
+    let (ast, diagnostics) = parser::parse_to_json(source);
+    let diagnostics = diagnostics.iter().map(|d| d.json()).collect::<Vec<_>>();
+    format!(
+        "{{\"ast\":{},\"diagnostics\":[{}]}}",
+        ast.as_deref().map_or("null", str::trim_end),
+        diagnostics.join(",")
+    )
+}
+
+/// Compiles `source`, and runs it too if `run` is set and it has no errors,
+/// until `deadline`.
+fn check(source: &str, options: ServeOptions, run: bool, deadline: Instant) -> String {
+    let storage = StringStorage::new();
+    let errors = ErrorStream::buffered();
+    let mut compiler = Compiler::new(&storage, &errors)
+        .with_prelude(options.resolve.prelude)
+        .with_files(false);
+    let artifacts = compiler.compile_str(PATH, source);
+    let source_map = compiler.source_map().unwrap();
+    let start = artifacts.entry.map(|entry| source_map.file(entry).start);
+    let in_source = |span: Option<Span>| in_source(source_map, start, span);
+
+    let failed = errors.error_count() > 0;
+    let diagnostics = errors
+        .take_diagnostics()
+        .iter()
+        .map(|diagnostic| {
+            let mut record = diagnostic.record();
+            record.span = in_source(record.span);
+            record.json()
+        })
+        .collect::<Vec<_>>();
+    let mut out = format!("{{\"diagnostics\":[{}]", diagnostics.join(","));
+    if !run {
+        out.push('}');
+        return out;
+    }
+
+    let mut output = Capped(Vec::new());
+    let mut error = None;
+    if let (Some(program), false) = (&artifacts.program, failed) {
+        let mut heap = eval::Heap::new(options.heap);
+        if let Err(err) = eval::run_until(program, artifacts.main, &mut output, &mut heap, deadline)
+        {
+            error = Some((format!("{:?}", err.kind), in_source(Some(err.span))));
+        }
+    }
+    let output = String::from_utf8_lossy(&output.0);
+    let _ = write!(out, ",\"output\":{},\"error\":", json_string(&output));
+    match error {
+        Some((message, span)) => {
+            let span = span.map_or("null".to_string(), |span| {
+                format!("[{},{}]", span.start, span.end)
+            });
+            let _ = write!(
+                out,
+                "{{\"message\":{},\"span\":{span}}}",
+                json_string(&message)
+            );
+        }
+        None => out.push_str("null"),
+    }
+    out.push('}');
+    out
+}
+
+/// `span` as offsets into the source that starts at `start`, if it's in
+/// that source rather than in a module it uses.
+fn in_source(source_map: &SourceMap, start: Option<u32>, span: Option<Span>) -> Option<Span> {
+    let (start, span) = (start?, span?);
+    let file = source_map.locate(span)?;
+    (file.start == start).then(|| Span {
+        start: span.start - start,
+        end: span.end - start,
+    })
+}
+
+/// Output that fails to be written to once it has [MAX_OUTPUT] bytes.
+struct Capped(Vec<u8>);
+
+impl Write for Capped {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        if self.0.len() + bytes.len() > MAX_OUTPUT {
+            return Err(io::Error::other("the output is too long"));
+        }
+        self.0.extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::tokenizer::Span;
+
+/// Keeps track of every source file in a compilation unit.
+///
+/// Each file is assigned its own range of offsets so that a [Span] on its own
+/// identifies both the file and the position within it. Offsets are laid out
+/// one file after another, with a gap of one between files so that a span at
+/// the very end of a file can't be mistaken for one at the start of the next.
+///
+/// When a file changes, it is moved to a fresh range after every other file,
+/// so that spans into the files that didn't change stay valid.
+///
+/// Offsets are `u32`s, to keep spans small, so the files of a compilation
+/// unit, including every version of those that changed, can't add up to more
+/// than 4 GiB. [SourceMap::has_room] tells whether another file fits.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    /// Indexed by [FileId].
+    files: Vec<SourceFile>,
+    /// Every file, in order of their offsets.
+    by_start: Vec<FileId>,
+    /// The offset given to the next file that is added or replaced.
+    next_start: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(pub u32);
+
+#[derive(Debug)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub src: String,
+    /// The offset of the first byte of the file.
+    pub start: u32,
+}
+
+impl SourceFile {
+    pub fn end(&self) -> u32 {
+        self.start + self.src.len() as u32
+    }
+
+    /// The range of `src` that a span into the file covers.
+    pub fn range(&self, span: Span) -> Range<usize> {
+        (span.start - self.start) as usize..(span.end - self.start) as usize
+    }
+
+    /// Returns the 1-based line and column of the given global offset.
+    pub fn line_col(&self, offset: u32) -> (usize, usize) {
+        let local = &self.src[..(offset - self.start) as usize];
+        let line = local.matches('\n').count() + 1;
+        let col = local.len() - local.rfind('\n').map_or(0, |i| i + 1) + 1;
+        (line, col)
+    }
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    /// Whether a file of `len` bytes can be added, or a file replaced with
+    /// that many, without its offsets overflowing.
+    pub fn has_room(&self, len: usize) -> bool {
+        (self.next_start as usize).saturating_add(len) < u32::MAX as usize
+    }
+
+    /// Adds a file, which there must be room for.
+    pub fn add(&mut self, path: PathBuf, src: String) -> FileId {
+        assert!(self.has_room(src.len()), "source map is full");
+        let id = FileId(self.files.len() as u32);
+        let start = self.next_start;
+        self.next_start = start + src.len() as u32 + 1;
+        self.files.push(SourceFile { path, src, start });
+        self.by_start.push(id);
+        id
+    }
+
+    /// Changes the contents of a file, moving it to a new range of offsets,
+    /// which there must be room for. Spans into the old contents of the file
+    /// are no longer valid.
+    pub fn replace(&mut self, id: FileId, src: String) {
+        assert!(self.has_room(src.len()), "source map is full");
+        let start = self.next_start;
+        self.next_start = start + src.len() as u32 + 1;
+        let file = &mut self.files[id.0 as usize];
+        file.src = src;
+        file.start = start;
+
+        let index = self.by_start.iter().position(|&f| f == id).unwrap();
+        self.by_start.remove(index);
+        self.by_start.push(id);
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0 as usize]
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (FileId, &SourceFile)> {
+        self.files
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (FileId(i as u32), f))
+    }
+
+    pub fn find(&self, path: &Path) -> Option<FileId> {
+        self.files
+            .iter()
+            .position(|f| f.path == path)
+            .map(|i| FileId(i as u32))
+    }
+
+    /// Finds the file containing the given global offset.
+    pub fn lookup(&self, offset: u32) -> FileId {
+        let index = self
+            .by_start
+            .partition_point(|&f| self.file(f).start <= offset);
+        self.by_start[index.saturating_sub(1)]
+    }
+
+    /// The file that the given span is in, if it's still in the current
+    /// contents of one.
+    pub fn locate(&self, span: Span) -> Option<&SourceFile> {
+        if self.files.is_empty() {
+            return None;
+        }
+        let file = self.file(self.lookup(span.start));
+        (file.start <= span.start && span.end <= file.end()).then_some(file)
+    }
+
+    /// Converts an offset local to a file into a global offset.
+    pub fn offset(&self, file: FileId, local: usize) -> u32 {
+        self.file(file).start + local as u32
+    }
+
+    /// Returns the source text covered by the given span.
+    pub fn snippet(&self, span: Span) -> &str {
+        let file = self.file(self.lookup(span.start));
+        &file.src[file.range(span)]
+    }
+}
+
+/// Finds the lines of many offsets without rescanning their files, for
+/// output that refers back to the source all the way through, like the
+/// debug info of compiled code.
+pub struct Lines<'a> {
+    map: &'a SourceMap,
+    /// The offsets that each line of a file starts at, for the files that
+    /// have been looked into.
+    starts: FxHashMap<FileId, Vec<u32>>,
+}
+
+impl<'a> Lines<'a> {
+    pub fn new(map: &'a SourceMap) -> Lines<'a> {
+        Lines {
+            map,
+            starts: FxHashMap::default(),
+        }
+    }
+
+    pub fn map(&self) -> &'a SourceMap {
+        self.map
+    }
+
+    /// The file that the given global offset is in, and the 0-based line and
+    /// the 0-based column in UTF-16 code units of the offset within it.
+    pub fn position(&mut self, offset: u32) -> (FileId, usize, usize) {
+        let id = self.map.lookup(offset);
+        let file = self.map.file(id);
+        let starts = self.starts.entry(id).or_insert_with(|| {
+            let newlines = file.src.match_indices('\n');
+            let after = newlines.map(|(i, _)| file.start + i as u32 + 1);
+            std::iter::once(file.start).chain(after).collect()
+        });
+        let line = starts.partition_point(|&start| start <= offset) - 1;
+        let before =
+            &file.src[(starts[line] - file.start) as usize..(offset - file.start) as usize];
+        (id, line, before.encode_utf16().count())
+    }
+}
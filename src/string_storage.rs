@@ -1,42 +1,116 @@
-use std::cell::RefCell;
+use std::{
+    borrow::{Borrow, Cow},
+    hash::{Hash, Hasher},
+    ptr::NonNull,
+    sync::Mutex,
+};
+
+use rustc_hash::FxHashSet;
 
 /// Storage for strings interned by a [StringInterner]. StringInterners just
 /// need a reference to one of these so that they can keep track of all the
 /// strings that are stored. The real purpose of this struct is to allow the
 /// StringInterner to pass out references to strings while also allowing itself
 /// to be borrowed mutably at the same time to intern new strings.
+///
+/// The storage is shared by the tokenizers of every file in a compilation
+/// unit, possibly across threads, and deduplicates what it stores so that
+/// equal strings from different files are interned to the same address.
+///
+/// [StringInterner]: crate::tokenizer::StringInterner
 pub struct StringStorage {
-    strings: RefCell<Vec<*mut str>>,
+    strings: Mutex<FxHashSet<Stored>>,
+}
+
+/// A string leaked by [StringStorage::intern], compared by its contents.
+struct Stored(NonNull<str>);
+
+// SAFETY: a Stored is only ever used to read an immutable string.
+unsafe impl Send for Stored {}
+unsafe impl Sync for Stored {}
+
+impl Borrow<str> for Stored {
+    fn borrow(&self) -> &str {
+        // SAFETY: the pointer was returned by Box::leak in `intern` and is
+        //         only freed when the StringStorage is dropped.
+        unsafe { self.0.as_ref() }
+    }
+}
+
+impl PartialEq for Stored {
+    fn eq(&self, other: &Self) -> bool {
+        <Self as Borrow<str>>::borrow(self) == <Self as Borrow<str>>::borrow(other)
+    }
+}
+
+impl Eq for Stored {}
+
+impl Hash for Stored {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        <Self as Borrow<str>>::borrow(self).hash(state)
+    }
+}
+
+impl Default for StringStorage {
+    fn default() -> Self {
+        StringStorage::new()
+    }
 }
 
 impl StringStorage {
     pub fn new() -> StringStorage {
         StringStorage {
-            strings: RefCell::new(Vec::new()),
+            strings: Mutex::new(FxHashSet::default()),
         }
     }
 
-    pub fn store(&self, string: Box<str>) -> &str {
-        let string = Box::leak(string);
+    /// Stores the given string, or returns the previously stored string that
+    /// is equal to it.
+    pub fn intern(&self, string: String) -> &str {
+        self.store(Cow::Owned(string))
+    }
+
+    /// Like [StringStorage::intern], but only copies the string if it isn't
+    /// stored yet.
+    pub fn intern_str(&self, string: &str) -> &str {
+        self.store(Cow::Borrowed(string))
+    }
+
+    fn store(&self, string: Cow<str>) -> &str {
+        let mut strings = self.strings.lock().unwrap();
+
+        if let Some(stored) = strings.get(&*string) {
+            // SAFETY: see `Borrow::borrow` above. The lifetime given out is
+            //         tied to the StringStorage, which keeps the string alive.
+            return unsafe { stored.0.as_ref() };
+        }
 
-        let mut strings = self.strings.borrow_mut();
-        strings.push(string as *mut _);
+        let string = Box::leak(string.into_owned().into_boxed_str());
+        strings.insert(Stored(NonNull::from(&mut *string)));
 
         string
     }
+
+    /// How many strings are stored, and how many bytes they take up together.
+    pub fn size(&self) -> (usize, usize) {
+        let strings = self.strings.lock().unwrap();
+        let bytes = strings
+            .iter()
+            .map(|s| <Stored as Borrow<str>>::borrow(s).len());
+        (strings.len(), bytes.sum())
+    }
 }
 
 impl Drop for StringStorage {
     fn drop(&mut self) {
-        let strings = self.strings.borrow();
-        for string in &*strings {
-            // SAFETY: each of these pointers was returned by Box::leak in `store`,
+        let strings = self.strings.get_mut().unwrap();
+        for string in strings.drain() {
+            // SAFETY: each of these pointers was returned by Box::leak in `intern`,
             //         and the lifetime given out for these strings is only valid
             //         for as long as the StringStorage stays alive, so these
             //         strings should no longer be referenced.
-            let string: *mut str = *string;
             unsafe {
-                drop(Box::from_raw(string));
+                drop(Box::from_raw(string.0.as_ptr()));
             }
         }
     }
@@ -0,0 +1,91 @@
+//! Tags files for `radi tags`, which let vim and emacs jump to where a def
+//! is without a language server. Every def in the program is tagged, nested
+//! ones included, since editors only match tags by name.
+
+use std::{fmt::Write, path::Path};
+
+use crate::{index::relative_path, parse_manager::ParseManager, parser::utils};
+
+/// A def, and where it is.
+#[derive(Debug)]
+pub struct Tag {
+    pub name: String,
+    /// The path of the file the def is in, relative to the directory of the
+    /// tags file.
+    pub path: String,
+    /// The 1-based line that the name of the def is on.
+    pub line: usize,
+    /// The byte offset into the file of the start of that line.
+    pub line_start: usize,
+    /// The text of the line up to and including the name.
+    pub prefix: String,
+}
+
+/// Tags the defs of every module but the prelude, with their paths relative
+/// to `dir`, where the tags file is written. The tags are grouped by file,
+/// in the order the files were loaded, and in source order within a file.
+pub fn collect(manager: &ParseManager, dir: &Path) -> Vec<Tag> {
+    let source_map = manager.source_map();
+    let mut tags = Vec::new();
+    for (file, source) in source_map.files() {
+        if Some(file) == manager.prelude() {
+            continue;
+        }
+        let Some(module) = manager.module(file) else {
+            continue;
+        };
+        let path = relative_path(dir, &source.path);
+        for def in utils::defs(&module.ast.body) {
+            let range = source.range(def.name_span);
+            let line_start = source.src[..range.start].rfind('\n').map_or(0, |i| i + 1);
+            tags.push(Tag {
+                name: def.name.0.to_string(),
+                path: path.clone(),
+                line: source.line_col(def.name_span.start).0,
+                line_start,
+                prefix: source.src[line_start..range.end].to_string(),
+            });
+        }
+    }
+    tags
+}
+
+/// The tags in the format of `ctags` that vim reads, sorted by name so that
+/// they can be binary searched.
+pub fn ctags(tags: &[Tag]) -> String {
+    let mut out = String::from(
+        "!_TAG_FILE_FORMAT\t2\t/extended format/\n\
+         !_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted, 2=foldcase/\n\
+         !_TAG_PROGRAM_NAME\tradi\t//\n",
+    );
+    let mut sorted = tags.iter().collect::<Vec<_>>();
+    // the sort is stable, so tags with the same name stay in source order
+    sorted.sort_by(|a, b| a.name.as_bytes().cmp(b.name.as_bytes()));
+    for tag in sorted {
+        let _ = writeln!(out, "{}\t{}\t{};\"\td", tag.name, tag.path, tag.line);
+    }
+    out
+}
+
+/// The tags in the format of `etags` that emacs reads, with a section for
+/// each file.
+pub fn etags(tags: &[Tag]) -> String {
+    let mut out = String::new();
+    let mut rest = tags;
+    while let Some(first) = rest.first() {
+        let len = rest.iter().take_while(|tag| tag.path == first.path).count();
+        let (file, next) = rest.split_at(len);
+        rest = next;
+
+        let mut section = String::new();
+        for tag in file {
+            let _ = writeln!(
+                section,
+                "{}\x7f{}\x01{},{}",
+                tag.prefix, tag.name, tag.line, tag.line_start
+            );
+        }
+        let _ = write!(out, "\x0c\n{},{}\n{section}", first.path, section.len());
+    }
+    out
+}
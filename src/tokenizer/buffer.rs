@@ -0,0 +1,109 @@
+use std::ops::Range;
+
+use crate::char_reader::CharReader;
+
+use super::{Intern, Result, StringInterner, Token, TokenSource, TokenizationError, Tokens};
+
+/// The tokens of a whole file, lexed before any of them are parsed.
+///
+/// Reading the file in one go keeps the tokenizer's state out of the
+/// parser's way, and the parser only has to index into the buffer to peek.
+/// The tokens are held all at once, though, so [Tokens] is still read from
+/// directly for files too large for that.
+pub struct TokenBuffer<'s> {
+    tokens: Vec<Token<'s>>,
+    /// The index of the next token.
+    pos: usize,
+    /// The error that the tokenizer stopped at, which is returned once the
+    /// tokens before it are read.
+    error: Option<TokenizationError>,
+    strings: StringInterner<'s>,
+}
+
+impl<'s> TokenBuffer<'s> {
+    /// Reads every token from `tokens`, up to the end of the input or the
+    /// first error.
+    pub fn lex(mut tokens: Tokens<'s, impl CharReader>) -> TokenBuffer<'s> {
+        let mut buffer = Vec::new();
+        let error = loop {
+            match tokens.next() {
+                Ok(Some(token)) => buffer.push(token),
+                Ok(None) => break None,
+                Err(err) => break Some(err),
+            }
+        };
+        TokenBuffer {
+            tokens: buffer,
+            pos: 0,
+            error,
+            strings: tokens.strings,
+        }
+    }
+
+    /// The tokens that haven't been read yet.
+    pub fn tokens(&self) -> &[Token<'s>] {
+        &self.tokens[self.pos..]
+    }
+
+    /// Whether the tokenizer stopped at an error before the end of the input.
+    pub fn has_error(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// Reads the tokens in `range` of those that haven't been read yet, apart
+    /// from the rest, with an interner of its own so that it can be sent to
+    /// another thread.
+    pub fn slice(&self, range: Range<usize>) -> TokenSlice<'_, 's> {
+        TokenSlice {
+            tokens: &self.tokens()[range],
+            pos: 0,
+            strings: StringInterner::new(self.strings.storage()),
+        }
+    }
+}
+
+/// A run of the tokens of a [TokenBuffer], which ends where the run does.
+pub struct TokenSlice<'t, 's> {
+    tokens: &'t [Token<'s>],
+    /// The index of the next token.
+    pos: usize,
+    strings: StringInterner<'s>,
+}
+
+impl<'s> TokenSource<'s> for TokenSlice<'_, 's> {
+    fn next(&mut self) -> Result<Option<Token<'s>>> {
+        let token = self.tokens.get(self.pos).copied();
+        self.pos += token.is_some() as usize;
+        Ok(token)
+    }
+
+    fn peek(&mut self) -> Result<Option<&Token<'s>>> {
+        Ok(self.tokens.get(self.pos))
+    }
+
+    fn intern(&mut self, string: String) -> Intern<'s> {
+        self.strings.intern(string)
+    }
+}
+
+impl<'s> TokenSource<'s> for TokenBuffer<'s> {
+    fn next(&mut self) -> Result<Option<Token<'s>>> {
+        self.peek()?;
+        let token = self.tokens.get(self.pos).copied();
+        self.pos += token.is_some() as usize;
+        Ok(token)
+    }
+
+    fn peek(&mut self) -> Result<Option<&Token<'s>>> {
+        if self.pos == self.tokens.len() {
+            if let Some(err) = self.error.take() {
+                return Err(err);
+            }
+        }
+        Ok(self.tokens.get(self.pos))
+    }
+
+    fn intern(&mut self, string: String) -> Intern<'s> {
+        self.strings.intern(string)
+    }
+}
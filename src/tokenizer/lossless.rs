@@ -0,0 +1,162 @@
+//! A lossless form of the token stream, which keeps the whitespace and
+//! comments between tokens as pieces of their own, so that the source can be
+//! printed back from it exactly. The formatter and refactorings that edit
+//! source work on these pieces, so that whatever they don't change comes out
+//! as it went in.
+//!
+//! The pieces of a source cover all of it, one after another, and so
+//! [print] gives back exactly the source that [lossless] was given, even
+//! when it doesn't tokenize.
+
+use crate::{char_reader::StrCharReader, string_storage::StringStorage};
+
+use super::{Span, TokenKind, Tokens};
+
+/// A token or the text between tokens, with where it is in the source.
+#[derive(Debug, Clone, Copy)]
+pub struct Piece<'a, 's> {
+    pub kind: PieceKind<'s>,
+    pub text: &'a str,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PieceKind<'s> {
+    Token(TokenKind<'s>),
+    Whitespace,
+    /// A `//` comment, without the newline that ends it.
+    LineComment,
+    /// A `/* */` comment, with the comments nested inside it.
+    BlockComment,
+    /// Text that doesn't tokenize. The tokenizer stops at its first error, so
+    /// from [lossless] this is everything from there to the end.
+    Invalid,
+}
+
+impl PieceKind<'_> {
+    /// Whether the piece is whitespace or a comment, which the parser skips.
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            PieceKind::Whitespace | PieceKind::LineComment | PieceKind::BlockComment
+        )
+    }
+}
+
+/// Splits `src` into its tokens and the trivia between them, up to the first
+/// error, after which the rest of it is one [PieceKind::Invalid] piece.
+pub fn lossless<'a, 's>(src: &'a str, storage: &'s StringStorage) -> Vec<Piece<'a, 's>> {
+    let mut tokens = Tokens::of(StrCharReader::new(src), storage);
+    let mut pieces = Vec::new();
+    let mut pos = 0;
+    let invalid = loop {
+        match tokens.next() {
+            Ok(Some(token)) => {
+                let (start, end) = (token.span.start as usize, token.span.end as usize);
+                push_trivia(src, pos, start, &mut pieces);
+                pieces.push(Piece {
+                    kind: PieceKind::Token(token.kind),
+                    text: &src[start..end],
+                    span: token.span,
+                });
+                pos = end;
+            }
+            Ok(None) => break src.len(),
+            // an error without a span is one at the end of the source, such
+            // as a string that isn't closed, which starts after `pos`
+            Err(err) => break err.span.map_or(pos, |span| (span.start as usize).max(pos)),
+        }
+    };
+    push_trivia(src, pos, invalid, &mut pieces);
+    if invalid < src.len() {
+        pieces.push(Piece {
+            kind: PieceKind::Invalid,
+            text: &src[invalid..],
+            span: span(invalid, src.len()),
+        });
+    }
+    pieces
+}
+
+/// The source that `pieces` were split from, or the source as it's been
+/// edited to if pieces were changed.
+pub fn print(pieces: &[Piece]) -> String {
+    let mut out = String::with_capacity(pieces.iter().map(|piece| piece.text.len()).sum());
+    for piece in pieces {
+        out.push_str(piece.text);
+    }
+    out
+}
+
+fn span(start: usize, end: usize) -> Span {
+    Span {
+        start: start as u32,
+        end: end as u32,
+    }
+}
+
+/// Pushes the trivia between `start` and `end` in `src`.
+fn push_trivia<'a>(src: &'a str, start: usize, end: usize, pieces: &mut Vec<Piece<'a, '_>>) {
+    let mut pos = start;
+    for (text, kind) in trivia(&src[start..end]) {
+        pieces.push(Piece {
+            kind,
+            text,
+            span: span(pos, pos + text.len()),
+        });
+        pos += text.len();
+    }
+}
+
+/// Splits text without tokens in it into whitespace and comments, reading
+/// comments the way the tokenizer does. Anything else in it is invalid, up
+/// to the next whitespace or `/`.
+pub(crate) fn trivia(text: &str) -> Vec<(&str, PieceKind<'static>)> {
+    let mut out = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (len, kind) = if rest.starts_with("//") {
+            let len = rest.find('\n').unwrap_or(rest.len());
+            (len, PieceKind::LineComment)
+        } else if rest.starts_with("/*") {
+            (block_comment_len(rest), PieceKind::BlockComment)
+        } else if rest.starts_with(|ch: char| ch.is_ascii_whitespace()) {
+            let len = rest.find(|ch: char| !ch.is_ascii_whitespace());
+            (len.unwrap_or(rest.len()), PieceKind::Whitespace)
+        } else {
+            // a `/` that doesn't start a comment is invalid on its own
+            let first = rest.chars().next().map_or(1, char::len_utf8);
+            let len = rest[first..].find(|ch: char| ch.is_ascii_whitespace() || ch == '/');
+            (
+                len.map_or(rest.len(), |len| first + len),
+                PieceKind::Invalid,
+            )
+        };
+        out.push((&rest[..len], kind));
+        rest = &rest[len..];
+    }
+    out
+}
+
+/// The length of the block comment at the start of `text`. Like the
+/// tokenizer, this reads a `*` or `/` along with the character after it, so
+/// that `/***/` isn't closed by the `*/` in it.
+fn block_comment_len(text: &str) -> usize {
+    let mut chars = text.char_indices().skip(2);
+    let mut depth = 1;
+    while let Some((_, ch)) = chars.next() {
+        let next = match ch {
+            '*' | '/' => chars.next().map(|(_, next)| next),
+            _ => None,
+        };
+        match (ch, next) {
+            ('*', Some('/')) => depth -= 1,
+            ('/', Some('*')) => depth += 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return chars.next().map_or(text.len(), |(i, _)| i);
+        }
+    }
+    text.len()
+}
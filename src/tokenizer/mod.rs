@@ -1,13 +1,15 @@
 use std::{hash::Hash, io};
 
-use crate::{
-    char_reader::{CharReader, CharReaderSaver},
-    string_storage::StringStorage,
-};
+use crate::{char_reader::CharReader, profile, string_storage::StringStorage};
 
+mod buffer;
+mod lossless;
 mod string_interner;
 
-use string_interner::StringInterner;
+pub use buffer::{TokenBuffer, TokenSlice};
+pub(crate) use lossless::trivia;
+pub use lossless::{lossless, print, Piece, PieceKind};
+pub use string_interner::StringInterner;
 
 #[derive(Debug)]
 pub struct TokenizationError {
@@ -19,6 +21,9 @@ pub struct TokenizationError {
 pub enum TokenizationErrorKind {
     Unexpected,
     UnexpectedEof,
+    /// A float literal without digits after its point, as in `1.`, with the
+    /// form that's accepted instead.
+    TrailingPoint(String),
     Io(io::Error),
 }
 
@@ -37,32 +42,48 @@ pub struct Tokens<'s, R> {
     chars: R,
     strings: StringInterner<'s>,
     peek: Option<Token<'s>>,
+    /// A token that was read along with the one before it, and comes next.
+    pending: Option<Token<'s>>,
+    /// Whether the last token read was a `.`, after which a number is
+    /// always an integer, so that `t.0.1` isn't read as `t`, `.` and `0.1`.
+    after_dot: bool,
 }
 
-#[derive(Debug, Clone)]
+/// A token of the input. Its strings are interned, so it's cheap to copy,
+/// such as into a parse error.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Token<'s> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub kind: TokenKind<'s>,
     pub span: Span,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Span {
-    pub start: usize,
-    pub end: usize,
+    pub start: u32,
+    pub end: u32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TokenKind<'s> {
     /* Keywords */
     Def,
+    Pub,
     Use,
     Val,
     Set,
     Type,
     Case,
     Else,
+    Match,
     For,
     In,
+    Macro,
+    Trait,
+    Impl,
 
     /* Punctuation */
     Dot,
@@ -71,6 +92,7 @@ pub enum TokenKind<'s> {
     ColonColon,
     Semicolon,
     Bang,
+    Question,
     Pipe,
     Amp,
     ThinArrow,
@@ -88,6 +110,7 @@ pub enum TokenKind<'s> {
     Slash,
     Percent,
     Caret,
+    At,
     AmpAmp,
     PipePipe,
     OpenParen,
@@ -100,11 +123,39 @@ pub enum TokenKind<'s> {
 
     Float(f64),
     Integer(u64),
-    Name(Intern<'s>),
-    String(Intern<'s>),
+    /// An integer literal too large for a `u64`, as its digits.
+    BigInteger(#[cfg_attr(feature = "serde", serde(borrow))] Intern<'s>),
+    Name(#[cfg_attr(feature = "serde", serde(borrow))] Intern<'s>),
+    String(#[cfg_attr(feature = "serde", serde(borrow))] Intern<'s>),
 }
 
+/// The names that are read as keywords rather than as [TokenKind::Name]s.
+pub const KEYWORDS: [(&str, TokenKind<'static>); 14] = [
+    ("def", TokenKind::Def),
+    ("pub", TokenKind::Pub),
+    ("use", TokenKind::Use),
+    ("val", TokenKind::Val),
+    ("set", TokenKind::Set),
+    ("type", TokenKind::Type),
+    ("case", TokenKind::Case),
+    ("else", TokenKind::Else),
+    ("match", TokenKind::Match),
+    ("for", TokenKind::For),
+    ("in", TokenKind::In),
+    ("macro", TokenKind::Macro),
+    ("trait", TokenKind::Trait),
+    ("impl", TokenKind::Impl),
+];
+
+/// A string interned by a [StringInterner], which is compared by address.
+/// Deserializing one borrows the string from the input instead of interning
+/// it, so it isn't equal to the interned copy of the same string.
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct Intern<'s>(pub &'s str);
 
 impl<'s> PartialEq for Intern<'s> {
@@ -115,15 +166,38 @@ impl<'s> PartialEq for Intern<'s> {
 
 impl<'s> Eq for Intern<'s> {}
 
+/// Hashes the string rather than its address, so that a map keyed by names
+/// is iterated in the same order on every run. Where a string is interned
+/// changes from run to run, and with which parsing thread gets to it first.
 impl<'s> Hash for Intern<'s> {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        state.write_usize(self.0 as *const str as *const u8 as usize)
+        self.0.hash(state)
     }
 }
 
-impl<'s> Token<'s> {
-    fn new(kind: TokenKind<'s>, span: Span) -> Token<'s> {
-        Token { kind, span }
+/// Where the parser reads tokens from: either straight from the tokenizer,
+/// as [Tokens], or from a [TokenBuffer] that it filled beforehand.
+pub trait TokenSource<'s> {
+    fn next(&mut self) -> Result<Option<Token<'s>>>;
+
+    fn peek(&mut self) -> Result<Option<&Token<'s>>>;
+
+    /// Interns a string that isn't read from the input, such as a name made
+    /// up by the parser.
+    fn intern(&mut self, string: String) -> Intern<'s>;
+}
+
+impl<'s, R: CharReader> TokenSource<'s> for Tokens<'s, R> {
+    fn next(&mut self) -> Result<Option<Token<'s>>> {
+        Tokens::next(self)
+    }
+
+    fn peek(&mut self) -> Result<Option<&Token<'s>>> {
+        Tokens::peek(self)
+    }
+
+    fn intern(&mut self, string: String) -> Intern<'s> {
+        Tokens::intern(self, string)
     }
 }
 
@@ -133,19 +207,40 @@ impl<'s, R: CharReader> Tokens<'s, R> {
             chars,
             strings: StringInterner::new(string_storage),
             peek: None,
+            pending: None,
+            after_dot: false,
         }
     }
 
     /// Reads the next token from in input stream.
+    // not an `Iterator`, so that errors can be returned with `?`
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Result<Option<Token<'s>>> {
         if let Some(peek) = self.peek.take() {
             return Ok(Some(peek));
         }
+        let token = profile::lexing(|| self.read());
+        if let Ok(Some(_)) = token {
+            profile::lexed_token();
+        }
+        self.after_dot = matches!(
+            token,
+            Ok(Some(Token {
+                kind: TokenKind::Dot,
+                ..
+            }))
+        );
+        token
+    }
 
+    fn read(&mut self) -> Result<Option<Token<'s>>> {
+        if let Some(token) = self.pending.take() {
+            return Ok(Some(token));
+        }
         while let Some((start, ch)) = self.chars.peek()? {
             return match ch {
                 _ if ch.is_ascii_whitespace() => {
-                    self.chars.next()?;
+                    self.read_while(|ch| ch.is_ascii_whitespace(), None)?;
                     continue;
                 }
                 '.' => self.advance_double(TokenKind::Dot, |ch| match ch {
@@ -191,6 +286,8 @@ impl<'s, R: CharReader> Tokens<'s, R> {
                 '*' => self.advance_single(TokenKind::Star),
                 '%' => self.advance_single(TokenKind::Percent),
                 '^' => self.advance_single(TokenKind::Caret),
+                '@' => self.advance_single(TokenKind::At),
+                '?' => self.advance_single(TokenKind::Question),
                 '(' => self.advance_single(TokenKind::OpenParen),
                 ')' => self.advance_single(TokenKind::CloseParen),
                 '[' => self.advance_single(TokenKind::OpenBracket),
@@ -214,7 +311,7 @@ impl<'s, R: CharReader> Tokens<'s, R> {
                             kind: TokenKind::Slash,
                             span: Span {
                                 start,
-                                end: start + ch.len_utf8(),
+                                end: start + ch.len_utf8() as u32,
                             },
                         }))
                     }
@@ -227,7 +324,7 @@ impl<'s, R: CharReader> Tokens<'s, R> {
                         kind: TokenizationErrorKind::Unexpected,
                         span: Some(Span {
                             start,
-                            end: start + ch.len_utf8(),
+                            end: start + ch.len_utf8() as u32,
                         }),
                     })
                 }
@@ -245,19 +342,57 @@ impl<'s, R: CharReader> Tokens<'s, R> {
             Ok(self.peek.as_ref())
         }
     }
+
+    /// Interns a string that isn't read from the input, such as a name made
+    /// up by the parser.
+    pub fn intern(&mut self, string: String) -> Intern<'s> {
+        self.strings.intern(string)
+    }
 }
 
 impl<'s, R: CharReader> Tokens<'s, R> {
-    fn skip_line(&mut self) -> Result<()> {
-        while let Some((_, ch)) = self.chars.next()? {
-            if ch == '\n' {
+    /// Reads characters for as long as they satisfy `pred`, adding them to
+    /// `out` if it's given. Readers that hand out chunks are scanned a chunk
+    /// at a time.
+    fn read_while(
+        &mut self,
+        mut pred: impl FnMut(char) -> bool,
+        mut out: Option<&mut std::string::String>,
+    ) -> Result<()> {
+        while let Some((_, chunk)) = self.chars.chunk()? {
+            let len = chunk.find(|ch| !pred(ch)).unwrap_or(chunk.len());
+            if let Some(out) = out.as_mut() {
+                out.push_str(&chunk[..len]);
+            }
+            // the run goes on into the next chunk if it reaches the end of
+            // this one, unless this one is the end of the input
+            let more = len == chunk.len() && len > 0;
+            self.chars.consume(len);
+            if !more {
+                return Ok(());
+            }
+        }
+
+        while let Some((_, ch)) = self.chars.peek()? {
+            if !pred(ch) {
                 break;
             }
+            self.chars.next()?;
+            if let Some(out) = out.as_mut() {
+                out.push(ch);
+            }
         }
 
         Ok(())
     }
 
+    fn skip_line(&mut self) -> Result<()> {
+        self.read_while(|ch| ch != '\n', None)?;
+        self.chars.next()?;
+
+        Ok(())
+    }
+
     fn block_comment(&mut self) -> Result<()> {
         let mut i = 1;
         while let Some((_, ch)) = self.chars.next()? {
@@ -284,21 +419,29 @@ impl<'s, R: CharReader> Tokens<'s, R> {
             return Err(TokenizationError {
                 kind: TokenizationErrorKind::UnexpectedEof,
                 span: None,
-            })
+            });
         };
         if ch != '"' {
             return Err(TokenizationError {
                 kind: TokenizationErrorKind::Unexpected,
                 span: Some(Span {
                     start,
-                    end: start + ch.len_utf8(),
+                    end: start + ch.len_utf8() as u32,
                 }),
             });
         }
 
         self.chars.next()?;
-
-        let mut string = std::string::String::new();
+        let contents = start + 1;
+
+        // the contents of a literal without escapes can be interned straight
+        // from a reader that holds its input, so they're only copied out
+        // once there's an escape or if the reader can't do that
+        let borrow = self.chars.slice(contents, contents).is_some();
+        let mut string = match borrow {
+            true => None,
+            false => Some(std::string::String::new()),
+        };
         let mut slash = false;
         while let Some((curr, ch)) = self.chars.peek()? {
             if ch == '"' && !slash {
@@ -306,20 +449,27 @@ impl<'s, R: CharReader> Tokens<'s, R> {
             }
 
             self.chars.next()?;
+            if ch == '\\' && string.is_none() {
+                let before = self.chars.slice(contents, curr).unwrap_or_default();
+                string = Some(before.to_string());
+            }
+            let Some(string) = string.as_mut() else {
+                continue;
+            };
             if slash {
                 match ch {
-                    '"' => string += "\"",
-                    '\0' => string += "\0",
-                    't' => string += "\t",
-                    'n' => string += "\n",
-                    'r' => string += "\r",
-                    '\\' => string += "\\",
+                    '"' => *string += "\"",
+                    '\0' => *string += "\0",
+                    't' => *string += "\t",
+                    'n' => *string += "\n",
+                    'r' => *string += "\r",
+                    '\\' => *string += "\\",
                     _ => {
                         return Err(TokenizationError {
                             kind: TokenizationErrorKind::Unexpected,
                             span: Some(Span {
                                 start: curr,
-                                end: curr + ch.len_utf8(),
+                                end: curr + ch.len_utf8() as u32,
                             }),
                         })
                     }
@@ -336,14 +486,21 @@ impl<'s, R: CharReader> Tokens<'s, R> {
             return Err(TokenizationError {
                 kind: TokenizationErrorKind::UnexpectedEof,
                 span: None,
-            })
+            });
         };
 
+        let string = match string {
+            Some(string) => self.strings.intern(string),
+            None => {
+                let string = self.chars.slice(contents, inner_end).unwrap_or_default();
+                self.strings.intern_str(string)
+            }
+        };
         Ok(Some(Token {
-            kind: TokenKind::String(self.strings.intern(string)),
+            kind: TokenKind::String(string),
             span: Span {
                 start,
-                end: inner_end + end_ch.len_utf8(),
+                end: inner_end + end_ch.len_utf8() as u32,
             },
         }))
     }
@@ -353,100 +510,108 @@ impl<'s, R: CharReader> Tokens<'s, R> {
             return Err(TokenizationError {
                 kind: TokenizationErrorKind::UnexpectedEof,
                 span: None,
-            })
+            });
         };
         if !ch.is_alphabetic() && ch != '_' {
             return Err(TokenizationError {
                 kind: TokenizationErrorKind::Unexpected,
                 span: Some(Span {
                     start,
-                    end: start + ch.len_utf8(),
+                    end: start + ch.len_utf8() as u32,
                 }),
             });
         }
 
-        let mut saver = CharReaderSaver::with_capacity(&mut self.chars, 16);
-
-        while let Some((_, ch)) = saver.peek()? {
-            if !ch.is_alphanumeric() && ch != '_' {
-                break;
-            } else {
-                saver.next()?;
-            }
-        }
-
-        let name = saver.finish();
-        let end = start + name.len();
+        let mut name = std::string::String::with_capacity(16);
+        self.read_while(|ch| ch.is_alphanumeric() || ch == '_', Some(&mut name))?;
+        let end = start + name.len() as u32;
 
         Ok(Some(Token {
-            kind: match &*name {
-                "def" => TokenKind::Def,
-                "use" => TokenKind::Use,
-                "val" => TokenKind::Val,
-                "set" => TokenKind::Set,
-                "type" => TokenKind::Type,
-                "case" => TokenKind::Case,
-                "else" => TokenKind::Else,
-                "for" => TokenKind::For,
-                "in" => TokenKind::In,
-                _ => TokenKind::Name(self.strings.intern(name)),
+            kind: match KEYWORDS.iter().find(|(keyword, _)| *keyword == name) {
+                Some(&(_, keyword)) => keyword,
+                None => TokenKind::Name(self.strings.intern(name)),
             },
             span: Span { start, end },
         }))
     }
 
     fn number(&mut self) -> Result<Option<Token<'s>>> {
-        let Some((start, ch)) = self.chars.peek()? else { return Ok(None) };
+        let Some((start, ch)) = self.chars.peek()? else {
+            return Ok(None);
+        };
         if !ch.is_ascii_digit() {
             return Ok(None);
         }
 
-        let mut seen_point = false;
+        let mut saved = std::string::String::with_capacity(16);
+        self.read_while(|ch| ch.is_ascii_digit(), Some(&mut saved))?;
 
-        let mut saver = CharReaderSaver::with_capacity(&mut self.chars, 16);
-
-        while let Some((_, ch)) = saver.peek()? {
-            if !seen_point && ch == '.' {
-                seen_point = true;
-                saver.next()?;
-            } else if !ch.is_ascii_digit() {
-                break;
-            } else {
-                saver.next()?;
+        // a point only makes a float if there are digits on both sides of it,
+        // so that `1.max` is an access and `t.0.1` two of them
+        let point = match self.chars.peek()? {
+            Some((point, '.')) if !self.after_dot => Some(point),
+            _ => None,
+        };
+        if let Some(point) = point {
+            self.chars.next()?;
+            match self.chars.peek()? {
+                Some((_, ch)) if ch.is_ascii_digit() => {
+                    saved.push('.');
+                    self.read_while(|ch| ch.is_ascii_digit(), Some(&mut saved))?;
+                    let Ok(value) = saved.parse::<f64>() else {
+                        unreachable!("Compiler bug: Unexpected error from parse::<f64>()")
+                    };
+
+                    return Ok(Some(Token {
+                        kind: TokenKind::Float(value),
+                        span: Span {
+                            start,
+                            end: start + saved.len() as u32,
+                        },
+                    }));
+                }
+                Some((_, ch)) if ch.is_alphabetic() || ch == '_' => {
+                    self.pending = Some(Token {
+                        kind: TokenKind::Dot,
+                        span: Span {
+                            start: point,
+                            end: point + 1,
+                        },
+                    });
+                }
+                _ => {
+                    return Err(TokenizationError {
+                        kind: TokenizationErrorKind::TrailingPoint(format!("{saved}.0")),
+                        span: Some(Span {
+                            start,
+                            end: point + 1,
+                        }),
+                    })
+                }
             }
         }
 
-        let saved = saver.finish();
-        let end = start + saved.len();
-
-        if seen_point {
-            let Ok(value) = saved.parse::<f64>() else {
-                unreachable!("Compiler bug: Unexpected error from parse::<f64>()")
-            };
-
-            Ok(Some(Token {
-                kind: TokenKind::Float(value),
-                span: Span { start, end },
-            }))
-        } else {
-            let Ok(value) = saved.parse::<u64>() else {
-                unreachable!("Compiler bug: Unexpected error from parse::<u128>()")
-            };
+        let end = start + saved.len() as u32;
+        let kind = match saved.parse::<u64>() {
+            Ok(value) => TokenKind::Integer(value),
+            Err(_) => TokenKind::BigInteger(self.strings.intern(saved)),
+        };
 
-            Ok(Some(Token {
-                kind: TokenKind::Integer(value),
-                span: Span { start, end },
-            }))
-        }
+        Ok(Some(Token {
+            kind,
+            span: Span { start, end },
+        }))
     }
 
     fn advance_single(&mut self, kind: TokenKind<'s>) -> Result<Option<Token<'s>>> {
-        let Some((start, ch)) = self.chars.next()? else { return Ok(None) };
+        let Some((start, ch)) = self.chars.next()? else {
+            return Ok(None);
+        };
         Ok(Some(Token {
             kind,
             span: Span {
                 start,
-                end: start + ch.len_utf8(),
+                end: start + ch.len_utf8() as u32,
             },
         }))
     }
@@ -456,7 +621,9 @@ impl<'s, R: CharReader> Tokens<'s, R> {
         primary: TokenKind<'s>,
         secondary: impl FnOnce(char) -> Option<TokenKind<'s>>,
     ) -> Result<Option<Token<'s>>> {
-        let Some((start, ch)) = self.chars.next()? else { return Ok(None) };
+        let Some((start, ch)) = self.chars.next()? else {
+            return Ok(None);
+        };
         if let Some((peek_start, peek)) = self.chars.peek()? {
             if let Some(sec) = secondary(peek) {
                 self.chars.next()?;
@@ -464,7 +631,7 @@ impl<'s, R: CharReader> Tokens<'s, R> {
                     kind: sec,
                     span: Span {
                         start,
-                        end: peek_start + peek.len_utf8(),
+                        end: peek_start + peek.len_utf8() as u32,
                     },
                 }));
             }
@@ -474,7 +641,7 @@ impl<'s, R: CharReader> Tokens<'s, R> {
             kind: primary,
             span: Span {
                 start,
-                end: start + ch.len_utf8(),
+                end: start + ch.len_utf8() as u32,
             },
         }))
     }
@@ -4,8 +4,10 @@ use crate::string_storage::StringStorage;
 
 use super::Intern;
 
-/// Interns strings so that each unique string interned by a given instance of
-/// this struct has a single unique address in memory.
+/// Interns strings so that each unique string has a single unique address in
+/// memory. Strings are deduplicated by the shared [StringStorage]; each
+/// interner keeps a local cache of what it has seen so that it only has to go
+/// to the storage for new strings.
 pub struct StringInterner<'s> {
     storage: &'s StringStorage,
     strings: FxHashSet<&'s str>,
@@ -20,12 +22,28 @@ impl<'s> StringInterner<'s> {
         }
     }
 
+    /// The storage that the strings are kept in.
+    pub fn storage(&self) -> &'s StringStorage {
+        self.storage
+    }
+
     /// Takes ownership of the given string and interns it.
     pub fn intern(&mut self, s: String) -> Intern<'s> {
         if let Some(s) = self.strings.get(&*s) {
             Intern(s)
         } else {
-            let stored = self.storage.store(s.into());
+            let stored = self.storage.intern(s);
+            self.strings.insert(stored);
+            Intern(stored)
+        }
+    }
+
+    /// Interns the given string, copying it only if it hasn't been before.
+    pub fn intern_str(&mut self, s: &str) -> Intern<'s> {
+        if let Some(s) = self.strings.get(s) {
+            Intern(s)
+        } else {
+            let stored = self.storage.intern_str(s);
             self.strings.insert(stored);
             Intern(stored)
         }
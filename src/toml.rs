@@ -0,0 +1,243 @@
+//! A reader for the subset of TOML used by `radi.toml`: tables, dotted table
+//! headers, bare and quoted keys, and string, integer, float, boolean and
+//! single-line array values.
+
+use std::collections::BTreeMap;
+
+pub type Table = BTreeMap<String, Value>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&Table> {
+        match self {
+            Value::Table(t) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TomlError {
+    /// The 1-based line the error was found on.
+    pub line: usize,
+    pub kind: TomlErrorKind,
+}
+
+#[derive(Debug)]
+pub enum TomlErrorKind {
+    InvalidHeader,
+    InvalidKey,
+    ExpectedEquals,
+    InvalidValue,
+    UnterminatedString,
+    /// A key or table was defined twice, or a key was used as both a value
+    /// and a table.
+    Redefined(String),
+}
+
+pub fn parse(src: &str) -> Result<Table, TomlError> {
+    let mut root = Table::new();
+    let mut current: Vec<String> = Vec::new();
+
+    for (i, line) in src.lines().enumerate() {
+        let err = |kind| TomlError { line: i + 1, kind };
+        let mut line = Cursor { rest: line.trim() };
+
+        if line.rest.is_empty() || line.rest.starts_with('#') {
+            continue;
+        }
+
+        if line.eat('[') {
+            current = line.key().ok_or(err(TomlErrorKind::InvalidHeader))?;
+            if !line.eat(']') || !line.at_end() {
+                return Err(err(TomlErrorKind::InvalidHeader));
+            }
+            table_at(&mut root, &current).map_err(err)?;
+            continue;
+        }
+
+        let mut key = line.key().ok_or(err(TomlErrorKind::InvalidKey))?;
+        if !line.eat('=') {
+            return Err(err(TomlErrorKind::ExpectedEquals));
+        }
+        let value = line.value().map_err(err)?;
+        if !line.at_end() {
+            return Err(err(TomlErrorKind::InvalidValue));
+        }
+
+        let name = key.pop().unwrap();
+        let mut path = current.clone();
+        path.extend(key);
+        let table = table_at(&mut root, &path).map_err(err)?;
+        if table.contains_key(&name) {
+            return Err(err(TomlErrorKind::Redefined(name)));
+        }
+        table.insert(name, value);
+    }
+
+    Ok(root)
+}
+
+/// Finds the table at `path`, creating any tables that don't exist yet.
+fn table_at<'t>(mut table: &'t mut Table, path: &[String]) -> Result<&'t mut Table, TomlErrorKind> {
+    for name in path {
+        let entry = table
+            .entry(name.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        table = match entry {
+            Value::Table(t) => t,
+            _ => return Err(TomlErrorKind::Redefined(name.clone())),
+        };
+    }
+
+    Ok(table)
+}
+
+struct Cursor<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    fn skip_ws(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_ws();
+        self.rest.is_empty() || self.rest.starts_with('#')
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        self.skip_ws();
+        if let Some(rest) = self.rest.strip_prefix(ch) {
+            self.rest = rest;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Reads a possibly dotted key.
+    fn key(&mut self) -> Option<Vec<String>> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_ws();
+            if self.rest.starts_with('"') {
+                parts.push(self.string().ok()?);
+            } else {
+                let end = self
+                    .rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                    .unwrap_or(self.rest.len());
+                if end == 0 {
+                    return None;
+                }
+                parts.push(self.rest[..end].to_owned());
+                self.rest = &self.rest[end..];
+            }
+
+            if !self.eat('.') {
+                return Some(parts);
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, TomlErrorKind> {
+        self.skip_ws();
+
+        if self.rest.starts_with('"') {
+            return Ok(Value::String(self.string()?));
+        }
+
+        if self.eat('[') {
+            let mut items = Vec::new();
+            while !self.eat(']') {
+                items.push(self.value()?);
+                if !self.eat(',') {
+                    if !self.eat(']') {
+                        return Err(TomlErrorKind::InvalidValue);
+                    }
+                    break;
+                }
+            }
+            return Ok(Value::Array(items));
+        }
+
+        let end = self
+            .rest
+            .find(|c: char| c.is_whitespace() || c == ',' || c == ']' || c == '#')
+            .unwrap_or(self.rest.len());
+        let word = &self.rest[..end];
+        self.rest = &self.rest[end..];
+
+        let number = word.replace('_', "");
+        match word {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            _ => {
+                if let Ok(i) = number.parse() {
+                    Ok(Value::Integer(i))
+                } else if let Ok(f) = number.parse() {
+                    Ok(Value::Float(f))
+                } else {
+                    Err(TomlErrorKind::InvalidValue)
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, TomlErrorKind> {
+        let mut chars = self.rest.char_indices().skip(1);
+        let mut string = String::new();
+
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '"' => {
+                    self.rest = &self.rest[i + 1..];
+                    return Ok(string);
+                }
+                '\\' => string.push(match chars.next() {
+                    Some((_, 'n')) => '\n',
+                    Some((_, 't')) => '\t',
+                    Some((_, 'r')) => '\r',
+                    Some((_, '"')) => '"',
+                    Some((_, '\\')) => '\\',
+                    _ => return Err(TomlErrorKind::InvalidValue),
+                }),
+                _ => string.push(ch),
+            }
+        }
+
+        Err(TomlErrorKind::UnterminatedString)
+    }
+}
@@ -0,0 +1,1229 @@
+//! This stage infers the type of every expression in a compilation unit and
+//! checks that they are used consistently.
+//!
+//! Inference is Hindley-Milner style: every expression starts out with a type
+//! variable that is narrowed down by unification. The defs of each scope are
+//! checked in dependency order, one strongly connected group at a time, and a
+//! group of defs whose values are all lambdas is generalized afterwards so
+//! that each use gets a fresh copy of its type. The top-level defs of every
+//! module are treated as one scope, so the order modules are checked in
+//! doesn't matter.
+//!
+//! Objects and variants are structural and have row types: accessing `x.a`
+//! only requires `x` to be an object with an `a` field, and a variant value
+//! `|A: 1` fits any variant type with an `A: Int` case.
+//! An object literal has a closed type with just the fields it defines, so
+//! passing one where a field it lacks is needed is an error, which points
+//! back at the literal. Variant types go the other way: constructing a case
+//! doesn't close anything, so the type of a value that may be `|ok: v` or
+//! `|err: e` widens to take in both, until a `match` without a catch-all arm
+//! closes it to the cases the match handles.
+//!
+//! Types can be recursive through the fields of objects and the payloads of
+//! variants, so a list or a tree built out of them checks without declaring
+//! anything, and its type is displayed as `(T as ?n)` with `?n` where `T`
+//! recurs. A type that would contain itself in any other way is an error
+//! naming the variable and what it would have to be.
+//!
+//! Arithmetic and comparison operators work on `Int`, `Float` and `BigInt`
+//! (and `+` on `String`), as long as both operands have the same type. Where
+//! the operand type is left open, it defaults to `Int` when the enclosing def
+//! is generalized. The operand type decides what happens on overflow: `Int`
+//! arithmetic fails at runtime, `Float` arithmetic follows IEEE 754, and
+//! `BigInt` arithmetic never overflows. Numbers are only converted between
+//! types by the builtins, and `big_to_int` and `to_int` fail at runtime when
+//! the value doesn't fit.
+//!
+//! An operator whose left operand is already known to be an object calls a
+//! member of it instead, as [BinOp::member] says, which is recorded in
+//! [Typing::overloads]. `=` and `!=` only do so if the object has an `eq`,
+//! and compare it structurally otherwise. `a + b` can return anything, but
+//! `eq` has to return a `Bool` and `cmp` an `Int`.
+//!
+//! A trait declares the types of its methods in terms of its parameter, and
+//! a method is generic over it, with a constraint that there is an impl of
+//! the trait for the type it's used at. A constraint on a variable that a
+//! group generalizes becomes a constraint of every def in the group, which
+//! each use of them instantiates along with its type, as recorded in
+//! [Typing::dictionaries]. Constraints on anything else are checked against
+//! the impls once the whole program is, so a use that no type decides is an
+//! error rather than picking an impl. There is at most one impl of a trait
+//! for a type, so which one a use gets never depends on where it is.
+//!
+//! An integer literal is an `Int`, unless it is too large for a `u64`, in
+//! which case it is a `BigInt`.
+//!
+//! `e :: T` reads `T` as a type expression (a builtin type, a tuple, object or
+//! variant of types, `^T`, or a function type `A -> B`) and checks `e`
+//! against it. An integer literal asserted to be a `Float` or a `BigInt` is
+//! coerced, which is recorded in [Typing::coercions]. A literal that doesn't
+//! fit in its type is an error, rather than being wrapped or rounded later.
+use rustc_hash::{FxHashMap, FxHashSet};
+
+mod types;
+pub use types::*;
+
+use crate::{
+    errors::ErrorStream,
+    resolver::{
+        AccessRhs, BinOp, Builtin, Def, Expr, ExprKind, Literal, Module, Pattern, PatternKind,
+        Resolution, Scope, SymbolId, SymbolKind, UnOp,
+    },
+    scc,
+    tokenizer::{Intern, Span},
+};
+
+/// The largest integer below which every integer is exactly a `Float`.
+const MAX_EXACT_FLOAT: u64 = 1 << f64::MANTISSA_DIGITS;
+
+/// The tables produced by type checking.
+#[derive(Debug)]
+pub struct Typing<'s> {
+    pub types: Types<'s>,
+    /// Maps the span of each expression to its type.
+    pub exprs: FxHashMap<Span, TypeId>,
+    /// The type of every symbol bound in the program. Generalized symbols
+    /// have generic variables in their types.
+    pub symbols: FxHashMap<SymbolId, TypeId>,
+    /// The spans of the integer literals that were coerced to `Float` or
+    /// `BigInt`.
+    pub coercions: FxHashSet<Span>,
+    /// The spans of the operators that call a member of their left operand.
+    pub overloads: FxHashSet<Span>,
+    /// The constraints of each constrained generic def, as the trait that
+    /// there has to be an impl of and the generic variable it's for. A
+    /// method is constrained by its own trait.
+    pub constraints: FxHashMap<SymbolId, Box<[(SymbolId, TypeId)]>>,
+    /// The impls that each use of a constrained def needs, in the order of
+    /// its constraints, as the trait and the type the impl is for. A use
+    /// from within the def's own group has none and passes on the def's own.
+    pub dictionaries: FxHashMap<Span, Box<[(SymbolId, TypeId)]>>,
+    /// The methods of each impl, in the order its trait declares them, by
+    /// the trait and the type the impl is for as it's displayed.
+    pub impls: FxHashMap<(SymbolId, String), Box<[SymbolId]>>,
+}
+
+impl<'s> Typing<'s> {
+    pub fn type_of(&self, span: Span) -> Option<TypeId> {
+        self.exprs.get(&span).copied()
+    }
+
+    pub fn display(&self, ty: TypeId) -> String {
+        self.types.display(ty)
+    }
+}
+
+#[derive(Debug)]
+pub struct TypeError<'s> {
+    pub kind: TypeErrorKind<'s>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum TypeErrorKind<'s> {
+    Mismatch {
+        expected: String,
+        found: String,
+    },
+    /// The variable `var` would have to be `ty`, which contains it, as the
+    /// type of `f` in `(f) { f(f) }` would. A type can only contain itself
+    /// inside an object or a variant.
+    InfiniteType {
+        var: String,
+        ty: String,
+    },
+    NotAFunction(String),
+    /// `x^` was used on a value whose type is known not to be a reference.
+    NotAReference(String),
+    /// An arithmetic or comparison operator was used on a type that doesn't
+    /// support it.
+    NotNumeric(String),
+    /// An object of type `ty` doesn't have the field `field`, as it's used.
+    NoField {
+        field: Intern<'s>,
+        ty: String,
+        /// Where the type of the object was closed, by the literal that made
+        /// it or a type expression, if it's known.
+        closed: Option<Span>,
+    },
+    /// A variant of type `ty` can't have the case `case`. A variant type
+    /// widens to take in every case that flows into it, until a `match`
+    /// without a catch-all arm or a type expression closes it to just the
+    /// cases it lists.
+    NoCase {
+        case: Intern<'s>,
+        ty: String,
+        /// Where the type was closed, if it's known.
+        closed: Option<Span>,
+    },
+    /// The right-hand side of `::` isn't a type expression.
+    NotAType,
+    /// A type was used where a value was expected.
+    TypeAsValue(Intern<'s>),
+    /// An `@extern` def's type isn't a function that C can call: one taking
+    /// `Int`, `Float`, `String` or `Bool` arguments, or a tuple of them, and
+    /// returning one of them or `()`.
+    InvalidExternType(String),
+    /// A numeric literal is too large for its type, whose values range from
+    /// `min` to `max`. For an integer literal coerced to `Float`, that's the
+    /// range in which every integer is exactly representable.
+    LiteralOutOfRange {
+        ty: &'static str,
+        min: String,
+        max: String,
+    },
+    /// A method of the trait is used on a type for which there's no impl.
+    NoImpl {
+        trait_: Intern<'s>,
+        ty: String,
+    },
+    /// A method of the trait is used on a type that nothing decides, so
+    /// there's no telling which impl it's meant to use.
+    AmbiguousImpl(Intern<'s>),
+    /// There's already an impl of the trait for the type.
+    DuplicateImpl {
+        trait_: Intern<'s>,
+        ty: String,
+    },
+}
+
+pub fn check<'s>(
+    modules: &[Module<'s>],
+    resolution: &Resolution<'s>,
+    errors: &ErrorStream<'s>,
+) -> Typing<'s> {
+    let mut checker = Checker {
+        errors,
+        resolution,
+        level: 0,
+        pending: Vec::new(),
+        typing: Typing {
+            types: Types::new(),
+            exprs: FxHashMap::default(),
+            symbols: FxHashMap::default(),
+            coercions: FxHashSet::default(),
+            overloads: FxHashSet::default(),
+            constraints: FxHashMap::default(),
+            dictionaries: FxHashMap::default(),
+            impls: FxHashMap::default(),
+        },
+        generic: FxHashSet::default(),
+        constrained: FxHashMap::default(),
+        constraints: Vec::new(),
+        type_params: FxHashMap::default(),
+        declared: FxHashMap::default(),
+    };
+
+    let top_level = modules
+        .iter()
+        .filter_map(|module| match &module.body.kind {
+            ExprKind::Object(scope) => Some(scope.defs.iter()),
+            _ => None,
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    checker.items(&top_level);
+    let values = top_level
+        .iter()
+        .flat_map(|def| def.values())
+        .collect::<Vec<_>>();
+    checker.defs(&values);
+
+    for module in modules {
+        match &module.body.kind {
+            ExprKind::Object(scope) => {
+                for expr in scope.body.iter() {
+                    checker.expr(expr);
+                }
+            }
+            _ => {
+                checker.expr(&module.body);
+            }
+        }
+    }
+    checker.default_numeric(0);
+    checker.check_constraints();
+
+    checker.typing
+}
+
+/// An operand of an arithmetic or comparison operator whose type wasn't known
+/// yet when the operator was checked.
+struct Numeric {
+    ty: TypeId,
+    span: Span,
+    /// Whether the operator also works on strings.
+    strings: bool,
+}
+
+/// A use of a method or of a constrained def, which needs an impl of `trait_`
+/// for `ty`.
+struct Constraint {
+    trait_: SymbolId,
+    ty: TypeId,
+    span: Span,
+}
+
+struct Checker<'s, 'e> {
+    errors: &'e ErrorStream<'s>,
+    resolution: &'e Resolution<'s>,
+    /// The number of def groups being checked. Variables created at a deeper
+    /// level than a group are generalized along with it.
+    level: u32,
+    /// Operands of arithmetic operators whose types are still unknown.
+    pending: Vec<Numeric>,
+    typing: Typing<'s>,
+    /// The symbols whose types have been generalized, and so have to be
+    /// instantiated at each use.
+    generic: FxHashSet<SymbolId>,
+    /// The generic symbols that have constraints, with a tuple of their type
+    /// and the variables of their constraints, so that both are instantiated
+    /// together.
+    constrained: FxHashMap<SymbolId, TypeId>,
+    /// The constraints that haven't been generalized or checked yet.
+    constraints: Vec<Constraint>,
+    /// The variable that the parameter of each trait stands for in the types
+    /// of its methods.
+    type_params: FxHashMap<SymbolId, TypeId>,
+    /// The types that the methods of the impls have to have.
+    declared: FxHashMap<SymbolId, TypeId>,
+}
+
+impl<'s, 'e> Checker<'s, 'e> {
+    fn expr(&mut self, expr: &Expr<'s>) -> TypeId {
+        let ty = match &expr.kind {
+            ExprKind::Object(scope) => {
+                self.scope_defs(scope);
+                let entries = scope
+                    .defs
+                    .iter()
+                    .map(|def| {
+                        (
+                            self.resolution.symbol(def.symbol).name,
+                            self.ident(def.symbol, def.span),
+                        )
+                    })
+                    .collect();
+                self.row(entries, Some(expr.span), Type::Object)
+            }
+            ExprKind::Block(scope) => {
+                let last = self.scope_defs(scope);
+                match last {
+                    Some(ty) if !scope.trailing_semi => ty,
+                    _ => self.typing.types.unit(),
+                }
+            }
+            ExprKind::Lambda { arg, body } => {
+                let arg = self.pattern(arg);
+                let body = self.expr(body);
+                self.typing.types.add(Type::Function(arg, body))
+            }
+            ExprKind::BinOp { op, lhs, rhs } => self.bin_op(*op, lhs, rhs, expr.span),
+            ExprKind::UnOp { op, arg } => {
+                let arg_ty = self.expr(arg);
+                match op {
+                    UnOp::Not => {
+                        let bool = self.typing.types.add(Type::Bool);
+                        self.expect(arg_ty, bool, arg.span);
+                        bool
+                    }
+                    UnOp::Val => arg_ty,
+                    UnOp::Ref => self.typing.types.add(Type::Ref(arg_ty)),
+                    UnOp::Deref => match self.typing.types.get(arg_ty).clone() {
+                        Type::Ref(inner) => inner,
+                        Type::Var { .. } => {
+                            let inner = self.typing.types.var(self.level);
+                            let reference = self.typing.types.add(Type::Ref(inner));
+                            self.expect(arg_ty, reference, arg.span);
+                            inner
+                        }
+                        Type::Error => arg_ty,
+                        _ => {
+                            let ty = self.typing.types.display(arg_ty);
+                            self.error(TypeErrorKind::NotAReference(ty), arg.span)
+                        }
+                    },
+                }
+            }
+            ExprKind::Access { expr: object, prop } => match prop {
+                AccessRhs::Prop(field) => self.access(object, *field, expr.span),
+                AccessRhs::Expr(prop) => {
+                    self.expr(object);
+                    self.expr(prop);
+                    self.typing.types.var(self.level)
+                }
+            },
+            ExprKind::Branch {
+                cond,
+                on_true,
+                on_false,
+            } => {
+                let cond_ty = self.expr(cond);
+                let bool = self.typing.types.add(Type::Bool);
+                self.expect(cond_ty, bool, cond.span);
+
+                let on_true_ty = self.expr(on_true);
+                match on_false {
+                    Some(on_false) => {
+                        let on_false_ty = self.expr(on_false);
+                        self.expect(on_false_ty, on_true_ty, on_false.span);
+                        on_true_ty
+                    }
+                    None => self.typing.types.unit(),
+                }
+            }
+            ExprKind::Tuple { items } => {
+                let items = items.iter().map(|item| self.expr(item)).collect();
+                self.typing.types.add(Type::Tuple(items))
+            }
+            ExprKind::Apply { a, b } => {
+                let function = self.expr(a);
+                let arg = self.expr(b);
+                match self.typing.types.get(function).clone() {
+                    Type::Function(param, ret) => {
+                        self.expect(arg, param, b.span);
+                        ret
+                    }
+                    Type::Var { .. } => {
+                        let ret = self.typing.types.var(self.level);
+                        let expected = self.typing.types.add(Type::Function(arg, ret));
+                        self.expect(function, expected, a.span);
+                        ret
+                    }
+                    Type::Error => function,
+                    _ => {
+                        let ty = self.typing.types.display(function);
+                        self.error(TypeErrorKind::NotAFunction(ty), a.span)
+                    }
+                }
+            }
+            ExprKind::TypeAssertion { a, b } => {
+                let ty = self.type_expr(b);
+                let target = self.typing.types.get(ty).clone();
+                let coerces = matches!(target, Type::Float | Type::BigInt);
+                if let (ExprKind::Literal(Literal::Integer(i)), true) = (&a.kind, coerces) {
+                    if matches!(target, Type::Float) && *i > MAX_EXACT_FLOAT {
+                        let max = MAX_EXACT_FLOAT.to_string();
+                        self.out_of_range("Float", format!("-{max}"), max, a.span);
+                    }
+                    self.typing.coercions.insert(a.span);
+                    self.typing.exprs.insert(a.span, ty);
+                } else if let (ExprKind::Literal(Literal::BigInteger(_)), Type::Int) =
+                    (&a.kind, &target)
+                {
+                    self.out_of_range_int(a.span);
+                    self.typing.exprs.insert(a.span, ty);
+                } else {
+                    let found = self.expr(a);
+                    self.expect(found, ty, a.span);
+                }
+                ty
+            }
+            ExprKind::Arrow { .. } => {
+                unreachable!("function types are only parsed where a type is expected")
+            }
+            ExprKind::Trait { .. } | ExprKind::Impl { .. } => {
+                unreachable!("traits and impls are only at the top level of a module")
+            }
+            ExprKind::Extern { ty, .. } => {
+                let ty = self.type_expr(ty);
+                if !self.is_extern_type(ty) {
+                    let found = self.typing.types.display(ty);
+                    self.error(TypeErrorKind::InvalidExternType(found), expr.span);
+                }
+                ty
+            }
+            ExprKind::Set { place, value } => {
+                let place_ty = self.expr(place);
+                let value_ty = self.expr(value);
+                self.expect(value_ty, place_ty, value.span);
+                self.typing.types.unit()
+            }
+            ExprKind::Match { scrutinee, arms } => {
+                let scrutinee_ty = self.expr(scrutinee);
+                let mut result = None;
+                for arm in arms.iter() {
+                    let pattern = self.pattern(&arm.pattern);
+                    self.expect(pattern, scrutinee_ty, arm.pattern.span);
+                    let body = self.expr(&arm.body);
+                    match result {
+                        Some(ty) => self.expect(body, ty, arm.body.span),
+                        None => result = Some(body),
+                    }
+                }
+                let patterns = arms.iter().map(|arm| &arm.pattern).collect::<Vec<_>>();
+                self.close_variants(scrutinee_ty, &patterns, scrutinee.span);
+                result.unwrap_or_else(|| self.typing.types.unit())
+            }
+            ExprKind::Variant(items) => {
+                let entries = items
+                    .iter()
+                    .map(|item| {
+                        let ty = match &item.value {
+                            Some(value) => self.expr(value),
+                            None => self.typing.types.unit(),
+                        };
+                        (item.name, ty)
+                    })
+                    .collect();
+                self.row(entries, None, Type::Variant)
+            }
+            ExprKind::Ident(symbol) => self.ident(*symbol, expr.span),
+            ExprKind::Literal(lit) => self.literal(lit, expr.span),
+            ExprKind::Error => self.typing.types.add(Type::Error),
+        };
+
+        self.typing.exprs.insert(expr.span, ty);
+        ty
+    }
+
+    /// Checks the defs of a scope and then its body, returning the type of the
+    /// last expression in the body.
+    fn scope_defs(&mut self, scope: &Scope<'s>) -> Option<TypeId> {
+        self.defs(&scope.defs.iter().collect::<Vec<_>>());
+        scope.body.iter().map(|expr| self.expr(expr)).last()
+    }
+
+    /// Checks a set of defs that can refer to each other, in groups of defs
+    /// that depend on each other.
+    fn defs(&mut self, defs: &[&Def<'s>]) {
+        let index = defs
+            .iter()
+            .enumerate()
+            .map(|(i, def)| (def.symbol, i))
+            .collect::<FxHashMap<_, _>>();
+        let deps = defs
+            .iter()
+            .map(|def| {
+                let mut symbols = Vec::new();
+                def.value.symbols(&mut symbols);
+                symbols
+                    .into_iter()
+                    .filter_map(|symbol| index.get(&symbol).copied())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        for group in scc::strongly_connected_components(defs.len(), |i| deps[i].iter().copied()) {
+            self.level += 1;
+            for &i in &group {
+                let var = self.typing.types.var(self.level);
+                self.typing.symbols.insert(defs[i].symbol, var);
+            }
+            for &i in &group {
+                let def = defs[i];
+                let ty = self.expr(&def.value);
+                if let Some(&declared) = self.declared.get(&def.symbol) {
+                    self.expect(ty, declared, def.value.span);
+                }
+                let var = self.typing.symbols[&def.symbol];
+                self.expect(ty, var, def.value.span);
+            }
+            self.level -= 1;
+
+            // only lambdas are generalized, since generalizing a mutable value
+            // would let it be set to values of different types
+            let generalize = group.iter().all(|&i| {
+                matches!(defs[i].value.kind, ExprKind::Lambda { .. })
+                    && !self.resolution.symbol(defs[i].symbol).mutable
+            });
+            if generalize {
+                self.default_numeric(self.level);
+                for &i in &group {
+                    let ty = self.typing.symbols[&defs[i].symbol];
+                    self.typing.types.generalize(self.level, ty);
+                    self.generic.insert(defs[i].symbol);
+                }
+
+                let constraints = self.generalize_constraints(self.level);
+                if !constraints.is_empty() {
+                    for &i in &group {
+                        let symbol = defs[i].symbol;
+                        let mut items = vec![self.typing.symbols[&symbol]];
+                        items.extend(constraints.iter().map(|&(_, ty)| ty));
+                        let scheme = self.typing.types.add(Type::Tuple(items.into()));
+                        self.constrained.insert(symbol, scheme);
+                        self.typing.constraints.insert(symbol, constraints.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Declares the methods of the traits among the top-level defs, and the
+    /// types that the methods of each impl have to have.
+    fn items(&mut self, defs: &[&Def<'s>]) {
+        for def in defs {
+            let ExprKind::Trait { param, methods } = &def.value.kind else {
+                continue;
+            };
+            let var = self.typing.types.var(GENERIC);
+            self.type_params.insert(*param, var);
+            for method in methods.iter() {
+                let ty = self.type_expr(&method.value);
+                let scheme = self.typing.types.add(Type::Tuple(Box::new([ty, var])));
+                self.typing.symbols.insert(method.symbol, ty);
+                self.generic.insert(method.symbol);
+                self.constrained.insert(method.symbol, scheme);
+                let constraints = Box::new([(def.symbol, var)]);
+                self.typing.constraints.insert(method.symbol, constraints);
+            }
+        }
+
+        for def in defs {
+            let ExprKind::Impl {
+                trait_,
+                ty,
+                defs: methods,
+            } = &def.value.kind
+            else {
+                continue;
+            };
+            let ty = self.type_expr(ty);
+            if let Type::Error = self.typing.types.get(ty) {
+                continue;
+            }
+            let key = (*trait_, self.typing.display(ty));
+            if self.typing.impls.contains_key(&key) {
+                let trait_ = self.resolution.symbol(*trait_).name;
+                let kind = TypeErrorKind::DuplicateImpl { trait_, ty: key.1 };
+                self.error(kind, def.span);
+                continue;
+            }
+
+            let mut ordered = Vec::new();
+            for &declaration in self.resolution.traits[trait_].iter() {
+                let name = self.resolution.symbol(declaration).name;
+                let Some(method) = methods
+                    .iter()
+                    .find(|method| self.resolution.symbol(method.symbol).name == name)
+                else {
+                    continue;
+                };
+                let scheme = self.constrained[&declaration];
+                let scheme = self.typing.types.instantiate(self.level, scheme);
+                let Type::Tuple(items) = self.typing.types.get(scheme).clone() else {
+                    unreachable!()
+                };
+                self.expect(items[1], ty, def.span);
+                self.declared.insert(method.symbol, items[0]);
+                ordered.push(method.symbol);
+            }
+            self.typing.impls.insert(key, ordered.into());
+        }
+    }
+
+    /// Takes the constraints on the variables that were just generalized at
+    /// `level`, which become the constraints of the group. A variable local
+    /// to the group that wasn't generalized isn't in the type of any of its
+    /// defs, so nothing will ever decide its impl.
+    fn generalize_constraints(&mut self, level: u32) -> Box<[(SymbolId, TypeId)]> {
+        let mut out = Vec::new();
+        for constraint in std::mem::take(&mut self.constraints) {
+            let ty = self.typing.types.find(constraint.ty);
+            match *self.typing.types.get(ty) {
+                Type::Var { level: GENERIC } => {
+                    if !out.contains(&(constraint.trait_, ty)) {
+                        out.push((constraint.trait_, ty));
+                    }
+                }
+                Type::Var { level: l } if l > level => self.ambiguous(&constraint),
+                _ => self.constraints.push(constraint),
+            }
+        }
+        out.into()
+    }
+
+    /// Checks that there's an impl for each constraint left once the whole
+    /// program has been checked.
+    fn check_constraints(&mut self) {
+        for constraint in std::mem::take(&mut self.constraints) {
+            match self.typing.types.get(constraint.ty) {
+                Type::Error => {}
+                Type::Var { .. } => self.ambiguous(&constraint),
+                _ => {
+                    let ty = self.typing.display(constraint.ty);
+                    if !self
+                        .typing
+                        .impls
+                        .contains_key(&(constraint.trait_, ty.clone()))
+                    {
+                        let trait_ = self.resolution.symbol(constraint.trait_).name;
+                        self.error(TypeErrorKind::NoImpl { trait_, ty }, constraint.span);
+                    }
+                }
+            }
+        }
+    }
+
+    fn ambiguous(&mut self, constraint: &Constraint) {
+        let trait_ = self.resolution.symbol(constraint.trait_).name;
+        self.error(TypeErrorKind::AmbiguousImpl(trait_), constraint.span);
+    }
+
+    fn pattern(&mut self, pat: &Pattern<'s>) -> TypeId {
+        match &pat.kind {
+            PatternKind::Bind(symbol) => {
+                let var = self.typing.types.var(self.level);
+                self.typing.symbols.insert(*symbol, var);
+                var
+            }
+            PatternKind::Tuple(items) => {
+                let items = items.iter().map(|item| self.pattern(item)).collect();
+                self.typing.types.add(Type::Tuple(items))
+            }
+            PatternKind::Typed { pat, ty } => {
+                let found = self.pattern(pat);
+                let ty = self.type_expr(ty);
+                self.expect(found, ty, pat.span);
+                ty
+            }
+            PatternKind::Wildcard => self.typing.types.var(self.level),
+            PatternKind::Literal(lit) => self.literal(lit, pat.span),
+            PatternKind::Variant { name, value } => {
+                let payload = match value {
+                    Some(value) => self.pattern(value),
+                    None => self.typing.types.unit(),
+                };
+                self.row(vec![(*name, payload)], None, Type::Variant)
+            }
+            PatternKind::Error => self.typing.types.add(Type::Error),
+        }
+    }
+
+    fn literal(&mut self, lit: &Literal, span: Span) -> TypeId {
+        let ty = literal_type(lit);
+        let in_range = match *lit {
+            Literal::Integer(i) => i64::try_from(i).is_ok(),
+            Literal::Float(f) => f.is_finite(),
+            Literal::BigInteger(_) | Literal::String(_) => true,
+        };
+        if !in_range {
+            match ty {
+                Type::Int => self.out_of_range_int(span),
+                _ => self.out_of_range(
+                    "Float",
+                    format!("{:e}", f64::MIN),
+                    format!("{:e}", f64::MAX),
+                    span,
+                ),
+            }
+        }
+        self.typing.types.add(ty)
+    }
+
+    fn out_of_range_int(&mut self, span: Span) {
+        self.out_of_range("Int", i64::MIN.to_string(), i64::MAX.to_string(), span);
+    }
+
+    /// Reports a literal that doesn't fit in its type.
+    fn out_of_range(&mut self, ty: &'static str, min: String, max: String, span: Span) {
+        self.errors.error(TypeError {
+            kind: TypeErrorKind::LiteralOutOfRange { ty, min, max },
+            span,
+        });
+    }
+
+    /// Closes the variant types that the patterns of a `match` only ever take
+    /// apart into cases, so that a `match` with an arm for every case is
+    /// exhaustive. `ty` is the type of the values at one position in the
+    /// patterns, and `patterns` what each arm has at that position.
+    fn close_variants(&mut self, ty: TypeId, patterns: &[&Pattern<'s>], span: Span) {
+        let patterns = patterns
+            .iter()
+            .map(|&(mut pattern)| {
+                while let PatternKind::Typed { pat, .. } = &pattern.kind {
+                    pattern = pat;
+                }
+                pattern
+            })
+            .collect::<Vec<_>>();
+
+        match patterns.first().map(|pattern| &pattern.kind) {
+            Some(PatternKind::Tuple(items)) => {
+                let Type::Tuple(types) = self.typing.types.get(ty).clone() else {
+                    return;
+                };
+                for (i, &item_ty) in types.iter().enumerate().take(items.len()) {
+                    let items = patterns
+                        .iter()
+                        .map(|pattern| match &pattern.kind {
+                            PatternKind::Tuple(items) => items.get(i),
+                            _ => None,
+                        })
+                        .collect::<Option<Vec<_>>>();
+                    if let Some(items) = items {
+                        self.close_variants(item_ty, &items, span);
+                    }
+                }
+            }
+            Some(PatternKind::Variant { .. }) => {
+                // the payload patterns of each case, or `None` once the case
+                // is matched without one, which matches any payload
+                let mut cases = Vec::<(Intern<'s>, Option<Vec<&Pattern<'s>>>)>::new();
+                for pattern in &patterns {
+                    let PatternKind::Variant { name, value } = &pattern.kind else {
+                        return;
+                    };
+                    let index = match cases.iter().position(|(case, _)| case == name) {
+                        Some(index) => index,
+                        None => {
+                            cases.push((*name, Some(Vec::new())));
+                            cases.len() - 1
+                        }
+                    };
+                    let values = &mut cases[index].1;
+                    match value {
+                        Some(value) => values.iter_mut().for_each(|values| values.push(value)),
+                        None => *values = None,
+                    }
+                }
+
+                let entries = cases
+                    .iter()
+                    .map(|(name, _)| (*name, self.typing.types.var(self.level)))
+                    .collect::<Vec<_>>();
+                let closed = self.row(entries.clone(), Some(span), Type::Variant);
+                self.expect(ty, closed, span);
+                for ((_, payload), (_, values)) in entries.into_iter().zip(cases) {
+                    if let Some(values) = values {
+                        self.close_variants(payload, &values, span);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ident(&mut self, symbol: SymbolId, span: Span) -> TypeId {
+        let sym = self.resolution.symbol(symbol);
+        if let SymbolKind::Builtin(builtin) = sym.kind {
+            return self.builtin(builtin, sym.name, span);
+        }
+
+        match self.typing.symbols.get(&symbol) {
+            Some(_) if self.constrained.contains_key(&symbol) => self.constrained(symbol, span),
+            Some(&ty) if self.generic.contains(&symbol) => {
+                self.typing.types.instantiate(self.level, ty)
+            }
+            Some(&ty) => ty,
+            None => {
+                let var = self.typing.types.var(self.level);
+                self.typing.symbols.insert(symbol, var);
+                var
+            }
+        }
+    }
+
+    /// Instantiates the type of a constrained symbol at a use of it, which
+    /// needs impls for the types its constraints are instantiated with.
+    fn constrained(&mut self, symbol: SymbolId, span: Span) -> TypeId {
+        let scheme = self
+            .typing
+            .types
+            .instantiate(self.level, self.constrained[&symbol]);
+        let Type::Tuple(items) = self.typing.types.get(scheme).clone() else {
+            unreachable!()
+        };
+        let dictionaries = self.typing.constraints[&symbol]
+            .iter()
+            .zip(&items[1..])
+            .map(|(&(trait_, _), &ty)| (trait_, ty))
+            .collect::<Box<[_]>>();
+        for &(trait_, ty) in dictionaries.iter() {
+            self.constraints.push(Constraint { trait_, ty, span });
+        }
+        self.typing.dictionaries.insert(span, dictionaries);
+        items[0]
+    }
+
+    fn builtin(&mut self, builtin: Builtin, name: Intern<'s>, span: Span) -> TypeId {
+        let types = &mut self.typing.types;
+        match builtin {
+            Builtin::Print => {
+                let arg = types.var(self.level);
+                let unit = types.unit();
+                types.add(Type::Function(arg, unit))
+            }
+            Builtin::Add | Builtin::Sub | Builtin::Mul | Builtin::Div | Builtin::Mod => {
+                let int = types.add(Type::Int);
+                let args = types.add(Type::Tuple(Box::new([int, int])));
+                types.add(Type::Function(args, int))
+            }
+            Builtin::Neg => {
+                let int = types.add(Type::Int);
+                types.add(Type::Function(int, int))
+            }
+            Builtin::True | Builtin::False => types.add(Type::Bool),
+            Builtin::StringLength => {
+                let (string, int) = (types.add(Type::String), types.add(Type::Int));
+                types.add(Type::Function(string, int))
+            }
+            Builtin::Substring => {
+                let (string, int) = (types.add(Type::String), types.add(Type::Int));
+                let args = types.add(Type::Tuple(Box::new([string, int, int])));
+                types.add(Type::Function(args, string))
+            }
+            Builtin::IntToString => {
+                let (int, string) = (types.add(Type::Int), types.add(Type::String));
+                types.add(Type::Function(int, string))
+            }
+            Builtin::ToFloat => {
+                let (int, float) = (types.add(Type::Int), types.add(Type::Float));
+                types.add(Type::Function(int, float))
+            }
+            Builtin::ToInt => {
+                let (float, int) = (types.add(Type::Float), types.add(Type::Int));
+                types.add(Type::Function(float, int))
+            }
+            Builtin::Sqrt | Builtin::Floor => {
+                let float = types.add(Type::Float);
+                types.add(Type::Function(float, float))
+            }
+            Builtin::ToBig => {
+                let (int, big) = (types.add(Type::Int), types.add(Type::BigInt));
+                types.add(Type::Function(int, big))
+            }
+            Builtin::BigToInt => {
+                let (big, int) = (types.add(Type::BigInt), types.add(Type::Int));
+                types.add(Type::Function(big, int))
+            }
+            Builtin::BigToString => {
+                let (big, string) = (types.add(Type::BigInt), types.add(Type::String));
+                types.add(Type::Function(big, string))
+            }
+            Builtin::Nil => {
+                let item = types.var(self.level);
+                types.add(Type::List(item))
+            }
+            Builtin::Cons => {
+                let item = types.var(self.level);
+                let list = types.add(Type::List(item));
+                let args = types.add(Type::Tuple(Box::new([item, list])));
+                types.add(Type::Function(args, list))
+            }
+            // match_list(list, on_nil, (head, tail) { on_cons })
+            Builtin::MatchList => {
+                let (item, result) = (types.var(self.level), types.var(self.level));
+                let list = types.add(Type::List(item));
+                let cons = types.add(Type::Tuple(Box::new([item, list])));
+                let on_cons = types.add(Type::Function(cons, result));
+                let args = types.add(Type::Tuple(Box::new([list, result, on_cons])));
+                types.add(Type::Function(args, result))
+            }
+            // match_option(option, on_none, (x) { on_some })
+            Builtin::MatchOption => {
+                let (value, result) = (types.var(self.level), types.var(self.level));
+                let none = types.unit();
+                let option = types.add(Type::Variant(Row {
+                    entries: Box::new([(Intern("None"), none), (Intern("Some"), value)]),
+                    rest: None,
+                    closed: None,
+                }));
+                let on_some = types.add(Type::Function(value, result));
+                let args = types.add(Type::Tuple(Box::new([option, result, on_some])));
+                types.add(Type::Function(args, result))
+            }
+            // match_result(result, (x) { on_ok }, (e) { on_err })
+            Builtin::MatchResult => {
+                let value = types.var(self.level);
+                let error = types.var(self.level);
+                let result = types.var(self.level);
+                let variant = types.add(Type::Variant(Row {
+                    entries: Box::new([(Intern("Err"), error), (Intern("Ok"), value)]),
+                    rest: None,
+                    closed: None,
+                }));
+                let on_ok = types.add(Type::Function(value, result));
+                let on_err = types.add(Type::Function(error, result));
+                let args = types.add(Type::Tuple(Box::new([variant, on_ok, on_err])));
+                types.add(Type::Function(args, result))
+            }
+            Builtin::Int
+            | Builtin::Float
+            | Builtin::BigInt
+            | Builtin::String
+            | Builtin::Bool
+            | Builtin::Unit => self.error(TypeErrorKind::TypeAsValue(name), span),
+        }
+    }
+
+    fn bin_op(&mut self, op: BinOp, lhs: &Expr<'s>, rhs: &Expr<'s>, span: Span) -> TypeId {
+        let lhs_ty = self.expr(lhs);
+        let rhs_ty = self.expr(rhs);
+        if let Some(ty) = self.overload(op, lhs_ty, rhs_ty, lhs.span, span) {
+            return ty;
+        }
+        let bool = self.typing.types.add(Type::Bool);
+
+        match op {
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => {
+                self.expect(rhs_ty, lhs_ty, rhs.span);
+                self.numeric(lhs_ty, span, op == BinOp::Add);
+                lhs_ty
+            }
+            BinOp::Gt | BinOp::GtEq | BinOp::Lt | BinOp::LtEq => {
+                self.expect(rhs_ty, lhs_ty, rhs.span);
+                self.numeric(lhs_ty, span, false);
+                bool
+            }
+            BinOp::Equal | BinOp::NotEqual => {
+                self.expect(rhs_ty, lhs_ty, rhs.span);
+                bool
+            }
+            BinOp::And | BinOp::Or => {
+                self.expect(lhs_ty, bool, lhs.span);
+                self.expect(rhs_ty, bool, rhs.span);
+                bool
+            }
+        }
+    }
+
+    /// Checks `lhs op rhs` as a call of a member of `lhs`, if `lhs` is an
+    /// object, and returns the type of the result.
+    fn overload(
+        &mut self,
+        op: BinOp,
+        lhs: TypeId,
+        rhs: TypeId,
+        lhs_span: Span,
+        span: Span,
+    ) -> Option<TypeId> {
+        let member = op.member()?;
+        let Type::Object(row) = self.typing.types.get(lhs) else {
+            return None;
+        };
+        if matches!(op, BinOp::Equal | BinOp::NotEqual) {
+            let (entries, _) = self.typing.types.row(row);
+            if !entries.iter().any(|(name, _)| name.0 == member) {
+                return None;
+            }
+        }
+        self.typing.overloads.insert(span);
+
+        let function = self.field(lhs, Intern(member), lhs_span, span);
+        let ret = self.typing.types.var(self.level);
+        let expected = self.typing.types.add(Type::Function(rhs, ret));
+        self.expect(function, expected, span);
+        let bool = self.typing.types.add(Type::Bool);
+        Some(match op {
+            BinOp::Equal | BinOp::NotEqual => {
+                self.expect(ret, bool, span);
+                bool
+            }
+            BinOp::Gt | BinOp::GtEq | BinOp::Lt | BinOp::LtEq => {
+                let int = self.typing.types.add(Type::Int);
+                self.expect(ret, int, span);
+                bool
+            }
+            _ => ret,
+        })
+    }
+
+    /// Checks that `ty` supports arithmetic, or remembers to check it once
+    /// it's known.
+    fn numeric(&mut self, ty: TypeId, span: Span, strings: bool) {
+        match self.typing.types.get(ty) {
+            Type::Int | Type::Float | Type::BigInt | Type::Error => {}
+            Type::String if strings => {}
+            Type::Var { .. } => self.pending.push(Numeric { ty, span, strings }),
+            _ => {
+                let ty = self.typing.types.display(ty);
+                self.error(TypeErrorKind::NotNumeric(ty), span);
+            }
+        }
+    }
+
+    /// Checks the operands left by [Checker::numeric] whose types are now
+    /// known, and defaults those that are still unknown and belong to a level
+    /// deeper than `level` to `Int`.
+    fn default_numeric(&mut self, level: u32) {
+        for numeric in std::mem::take(&mut self.pending) {
+            match *self.typing.types.get(numeric.ty) {
+                Type::Var { level: l } if l > level => {
+                    let int = self.typing.types.add(Type::Int);
+                    self.typing.types.unify(numeric.ty, int).unwrap();
+                }
+                Type::Var { .. } => self.pending.push(numeric),
+                _ => self.numeric(numeric.ty, numeric.span, numeric.strings),
+            }
+        }
+    }
+
+    fn access(&mut self, object: &Expr<'s>, field: Intern<'s>, span: Span) -> TypeId {
+        let object_ty = self.expr(object);
+        self.field(object_ty, field, object.span, span)
+    }
+
+    /// The type of the field `field` of an object of type `object_ty`.
+    fn field(
+        &mut self,
+        object_ty: TypeId,
+        field: Intern<'s>,
+        object_span: Span,
+        span: Span,
+    ) -> TypeId {
+        if let Type::Object(row) = self.typing.types.get(object_ty) {
+            let (entries, rest) = self.typing.types.row(row);
+            if let Some(&(_, ty)) = entries.iter().find(|(name, _)| name.0 == field.0) {
+                return ty;
+            }
+            if rest.is_none() {
+                let closed = self.typing.types.closed(object_ty);
+                let ty = self.typing.types.display(object_ty);
+                return self.error(TypeErrorKind::NoField { field, ty, closed }, span);
+            }
+        }
+
+        let ty = self.typing.types.var(self.level);
+        let expected = self.row(vec![(field, ty)], None, Type::Object);
+        self.expect(object_ty, expected, object_span);
+        ty
+    }
+
+    /// A row type of `entries`, closed by the expression at `closed`, or
+    /// open to more entries if that's `None`.
+    fn row(
+        &mut self,
+        mut entries: Vec<(Intern<'s>, TypeId)>,
+        closed: Option<Span>,
+        kind: fn(Row<'s>) -> Type<'s>,
+    ) -> TypeId {
+        entries.sort_by(|a, b| a.0 .0.cmp(b.0 .0));
+        entries.dedup_by(|a, b| a.0 .0 == b.0 .0);
+        let rest = closed.is_none().then(|| self.typing.types.var(self.level));
+        self.typing.types.add(kind(Row {
+            entries: entries.into_boxed_slice(),
+            rest,
+            closed,
+        }))
+    }
+
+    /// Evaluates a type expression.
+    fn type_expr(&mut self, expr: &Expr<'s>) -> TypeId {
+        let ty = match &expr.kind {
+            ExprKind::Ident(symbol) => match self.resolution.symbol(*symbol).kind {
+                SymbolKind::Builtin(builtin) if builtin.is_type() => match builtin {
+                    Builtin::Int => Type::Int,
+                    Builtin::Float => Type::Float,
+                    Builtin::BigInt => Type::BigInt,
+                    Builtin::String => Type::String,
+                    Builtin::Bool => Type::Bool,
+                    _ => Type::Tuple(Box::new([])),
+                },
+                SymbolKind::TypeParam => return self.type_params[symbol],
+                _ => return self.error(TypeErrorKind::NotAType, expr.span),
+            },
+            ExprKind::Tuple { items } => {
+                Type::Tuple(items.iter().map(|item| self.type_expr(item)).collect())
+            }
+            ExprKind::UnOp { op: UnOp::Ref, arg } => Type::Ref(self.type_expr(arg)),
+            ExprKind::Arrow { arg, ret } => {
+                Type::Function(self.type_expr(arg), self.type_expr(ret))
+            }
+            ExprKind::Object(scope) if scope.body.is_empty() => {
+                let entries = scope
+                    .defs
+                    .iter()
+                    .map(|def| {
+                        let name = self.resolution.symbol(def.symbol).name;
+                        (name, self.type_expr(&def.value))
+                    })
+                    .collect();
+                return self.row(entries, Some(expr.span), Type::Object);
+            }
+            ExprKind::Variant(items) => {
+                let entries = items
+                    .iter()
+                    .map(|item| {
+                        let ty = match &item.value {
+                            Some(value) => self.type_expr(value),
+                            None => self.typing.types.unit(),
+                        };
+                        (item.name, ty)
+                    })
+                    .collect();
+                return self.row(entries, Some(expr.span), Type::Variant);
+            }
+            ExprKind::Error => Type::Error,
+            _ => return self.error(TypeErrorKind::NotAType, expr.span),
+        };
+
+        self.typing.types.add(ty)
+    }
+
+    /// Returns whether `ty` is the type of a C function, see
+    /// [TypeErrorKind::InvalidExternType].
+    fn is_extern_type(&self, ty: TypeId) -> bool {
+        let types = &self.typing.types;
+        let value = |ty| {
+            matches!(
+                types.get(ty),
+                Type::Int | Type::Float | Type::String | Type::Bool | Type::Error
+            )
+        };
+        match types.get(ty) {
+            Type::Function(param, ret) => {
+                let params = match types.get(*param) {
+                    Type::Tuple(items) => items.iter().all(|&item| value(item)),
+                    _ => value(*param),
+                };
+                let ret = value(*ret)
+                    || matches!(types.get(*ret), Type::Tuple(items) if items.is_empty());
+                params && ret
+            }
+            Type::Error => true,
+            _ => false,
+        }
+    }
+
+    /// Unifies the type `found` of the expression at `span` with the type it
+    /// is `expected` to have, reporting an error if they don't match.
+    fn expect(&mut self, found: TypeId, expected: TypeId, span: Span) {
+        if let Err(err) = self.typing.types.unify(found, expected) {
+            let found = self.typing.types.display(found);
+            let kind = match err {
+                UnifyError::Mismatch => TypeErrorKind::Mismatch {
+                    expected: self.typing.types.display(expected),
+                    found,
+                },
+                UnifyError::Infinite { var, ty } => TypeErrorKind::InfiniteType {
+                    var: self.typing.types.display(var),
+                    ty: self.typing.types.display(ty),
+                },
+                UnifyError::Missing { name, row } => {
+                    let ty = self.typing.types.display(row);
+                    let closed = self.typing.types.closed(row);
+                    match self.typing.types.get(row) {
+                        Type::Variant(_) => TypeErrorKind::NoCase {
+                            case: name,
+                            ty,
+                            closed,
+                        },
+                        _ => TypeErrorKind::NoField {
+                            field: name,
+                            ty,
+                            closed,
+                        },
+                    }
+                }
+            };
+            self.error(kind, span);
+        }
+    }
+
+    /// Reports an error and returns the error type.
+    fn error(&mut self, kind: TypeErrorKind<'s>, span: Span) -> TypeId {
+        self.errors.error(TypeError { kind, span });
+        self.typing.types.add(Type::Error)
+    }
+}
+
+fn literal_type<'s>(lit: &Literal) -> Type<'s> {
+    match lit {
+        Literal::Float(_) => Type::Float,
+        Literal::Integer(_) => Type::Int,
+        Literal::BigInteger(_) => Type::BigInt,
+        Literal::String(_) => Type::String,
+    }
+}
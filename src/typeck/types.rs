@@ -0,0 +1,564 @@
+use std::fmt::Write;
+
+use rustc_hash::FxHashSet;
+
+use crate::tokenizer::{Intern, Span};
+
+/// An index into [Types].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TypeId(pub u32);
+
+#[derive(Debug, Clone)]
+pub enum Type<'s> {
+    /// A type that hasn't been inferred yet. Variables at [GENERIC] level are
+    /// the quantified variables of a generalized type.
+    Var {
+        level: u32,
+    },
+    /// A variable that has been unified with another type.
+    Link(TypeId),
+    Int,
+    Float,
+    /// An integer of any size.
+    BigInt,
+    String,
+    Bool,
+    /// The empty tuple is the unit type.
+    Tuple(Box<[TypeId]>),
+    Function(TypeId, TypeId),
+    Ref(TypeId),
+    /// A list of the given element type. At runtime, lists are variants,
+    /// but the builtins that make and take them apart only know this type.
+    List(TypeId),
+    Object(Row<'s>),
+    Variant(Row<'s>),
+    /// The type of an expression that failed to check. It unifies with
+    /// everything so that one mistake is only reported once.
+    Error,
+}
+
+/// The named fields of an object or the cases of a variant.
+///
+/// A row with a `rest` variable is open: it may have more entries than are
+/// listed, and `rest` is eventually linked to a row of the same kind holding
+/// them.
+#[derive(Debug, Clone)]
+pub struct Row<'s> {
+    /// Sorted by name.
+    pub entries: Box<[(Intern<'s>, TypeId)]>,
+    pub rest: Option<TypeId>,
+    /// For a closed row, what closed it, if that's known: an object literal,
+    /// the scrutinee of a `match` or a type expression.
+    pub closed: Option<Span>,
+}
+
+/// The level of variables that have been generalized.
+pub const GENERIC: u32 = u32::MAX;
+
+/// The types of a compilation unit, with unification variables kept as a
+/// union-find forest.
+///
+/// Types are equi-recursive, as long as the recursion goes through the
+/// entries of an object or a variant: a variable can be linked to a type that
+/// contains it there, which makes the graph of types cyclic. A linked list is
+/// `|nil |cons: .{ head: Int, tail: ?1 }` where `?1` is the list itself. Any
+/// other cycle, as in the type of `f` in `f(f)`, is an error.
+#[derive(Debug, Default)]
+pub struct Types<'s> {
+    types: Vec<Type<'s>>,
+    /// The pairs of rows that the current call to [Types::unify] has started
+    /// unifying, which it assumes are equal if it comes across them again.
+    unifying: FxHashSet<(TypeId, TypeId)>,
+}
+
+/// Unification failed.
+#[derive(Debug)]
+pub enum UnifyError<'s> {
+    Mismatch,
+    /// The variable `var` would have to be `ty`, which contains it outside of
+    /// any object or variant.
+    Infinite {
+        var: TypeId,
+        ty: TypeId,
+    },
+    /// The object or variant type `row` is closed and doesn't have a field
+    /// or case that the other type does.
+    Missing {
+        name: Intern<'s>,
+        row: TypeId,
+    },
+}
+
+impl<'s> Types<'s> {
+    pub fn new() -> Types<'s> {
+        Types::default()
+    }
+
+    pub fn add(&mut self, ty: Type<'s>) -> TypeId {
+        let id = TypeId(self.types.len() as u32);
+        self.types.push(ty);
+        id
+    }
+
+    pub fn var(&mut self, level: u32) -> TypeId {
+        self.add(Type::Var { level })
+    }
+
+    pub fn unit(&mut self) -> TypeId {
+        self.add(Type::Tuple(Box::new([])))
+    }
+
+    /// Follows links to the representative of `ty`.
+    pub fn find(&self, mut ty: TypeId) -> TypeId {
+        while let Type::Link(next) = self.types[ty.0 as usize] {
+            ty = next;
+        }
+        ty
+    }
+
+    /// The type that `ty` currently stands for.
+    pub fn get(&self, ty: TypeId) -> &Type<'s> {
+        &self.types[self.find(ty).0 as usize]
+    }
+
+    /// Collects every entry of a row, following its rest variable through
+    /// any rows it has been linked to. Returns the entries sorted by name and
+    /// the unbound rest variable, if any.
+    pub fn row(&self, row: &Row<'s>) -> (Vec<(Intern<'s>, TypeId)>, Option<TypeId>) {
+        let mut entries = row.entries.to_vec();
+        let mut rest = row.rest;
+        while let Some(r) = rest {
+            match self.get(r) {
+                Type::Object(row) | Type::Variant(row) => {
+                    entries.extend_from_slice(&row.entries);
+                    rest = row.rest;
+                }
+                _ => break,
+            }
+        }
+        entries.sort_by(|a, b| a.0 .0.cmp(b.0 .0));
+        (entries, rest.map(|r| self.find(r)))
+    }
+
+    /// What closed the object or variant type `ty`, following its rest
+    /// variable to the row that ends it.
+    pub fn closed(&self, ty: TypeId) -> Option<Span> {
+        match self.get(ty) {
+            Type::Object(row) | Type::Variant(row) => self.row_closed(row),
+            _ => None,
+        }
+    }
+
+    fn row_closed(&self, row: &Row<'s>) -> Option<Span> {
+        match row.rest {
+            Some(rest) => self.closed(rest),
+            None => row.closed,
+        }
+    }
+
+    pub fn unify(&mut self, a: TypeId, b: TypeId) -> Result<(), UnifyError<'s>> {
+        let result = self.unify_types(a, b);
+        self.unifying.clear();
+        result
+    }
+
+    fn unify_types(&mut self, a: TypeId, b: TypeId) -> Result<(), UnifyError<'s>> {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return Ok(());
+        }
+
+        match (self.get(a).clone(), self.get(b).clone()) {
+            (Type::Error, _) | (_, Type::Error) => Ok(()),
+            (Type::Var { level }, _) => self.bind(a, level, b),
+            (_, Type::Var { level }) => self.bind(b, level, a),
+            (Type::Int, Type::Int)
+            | (Type::Float, Type::Float)
+            | (Type::BigInt, Type::BigInt)
+            | (Type::String, Type::String)
+            | (Type::Bool, Type::Bool) => Ok(()),
+            (Type::Tuple(xs), Type::Tuple(ys)) if xs.len() == ys.len() => {
+                for (x, y) in xs.iter().zip(ys.iter()) {
+                    self.unify_types(*x, *y)?;
+                }
+                Ok(())
+            }
+            (Type::Function(p1, r1), Type::Function(p2, r2)) => {
+                self.unify_types(p1, p2)?;
+                self.unify_types(r1, r2)
+            }
+            (Type::Ref(x), Type::Ref(y)) | (Type::List(x), Type::List(y)) => self.unify_types(x, y),
+            (Type::Object(x), Type::Object(y)) => self.unify_rows((a, x), (b, y), Type::Object),
+            (Type::Variant(x), Type::Variant(y)) => self.unify_rows((a, x), (b, y), Type::Variant),
+            _ => Err(UnifyError::Mismatch),
+        }
+    }
+
+    /// Unifies the rows of the types `a_id` and `b_id`, which are of the
+    /// same kind.
+    fn unify_rows(
+        &mut self,
+        (a_id, a): (TypeId, Row<'s>),
+        (b_id, b): (TypeId, Row<'s>),
+        kind: fn(Row<'s>) -> Type<'s>,
+    ) -> Result<(), UnifyError<'s>> {
+        // recursive types are unified coinductively: a pair of rows that is
+        // already being unified further up is equal if the rest of it is
+        if !self.unifying.insert((a_id, b_id)) {
+            return Ok(());
+        }
+        let (a_entries, a_rest) = self.row(&a);
+        let (b_entries, b_rest) = self.row(&b);
+
+        let mut only_a = Vec::new();
+        let mut only_b = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < a_entries.len() || j < b_entries.len() {
+            match (a_entries.get(i), b_entries.get(j)) {
+                (Some(x), Some(y)) if x.0 .0 == y.0 .0 => {
+                    self.unify_types(x.1, y.1)?;
+                    i += 1;
+                    j += 1;
+                }
+                (Some(x), Some(y)) if x.0 .0 < y.0 .0 => {
+                    only_a.push(*x);
+                    i += 1;
+                }
+                (Some(x), None) => {
+                    only_a.push(*x);
+                    i += 1;
+                }
+                (_, Some(y)) => {
+                    only_b.push(*y);
+                    j += 1;
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+
+        let row = |entries: Vec<_>, rest, closed| {
+            kind(Row {
+                entries: entries.into_boxed_slice(),
+                rest,
+                closed,
+            })
+        };
+
+        match (a_rest, b_rest) {
+            (None, None) if only_a.is_empty() && only_b.is_empty() => Ok(()),
+            (Some(ra), None) if only_a.is_empty() => {
+                let rest = self.add(row(only_b, None, self.row_closed(&b)));
+                self.unify_types(ra, rest)
+            }
+            (None, Some(rb)) if only_b.is_empty() => {
+                let rest = self.add(row(only_a, None, self.row_closed(&a)));
+                self.unify_types(rb, rest)
+            }
+            (Some(ra), Some(rb)) if ra == rb => {
+                if only_a.is_empty() && only_b.is_empty() {
+                    Ok(())
+                } else {
+                    // each row would need the other's entries on top of its own
+                    let ty = self.add(row(only_a, Some(ra), None));
+                    Err(UnifyError::Infinite { var: ra, ty })
+                }
+            }
+            (Some(ra), Some(rb)) => {
+                let level = self.level(ra).min(self.level(rb));
+                let rest = self.var(level);
+                let for_a = self.add(row(only_b, Some(rest), None));
+                let for_b = self.add(row(only_a, Some(rest), None));
+                self.unify_types(ra, for_a)?;
+                self.unify_types(rb, for_b)
+            }
+            (_, None) if !only_a.is_empty() => Err(UnifyError::Missing {
+                name: only_a[0].0,
+                row: b_id,
+            }),
+            _ => Err(UnifyError::Missing {
+                name: only_b[0].0,
+                row: a_id,
+            }),
+        }
+    }
+
+    fn level(&self, var: TypeId) -> u32 {
+        match self.get(var) {
+            Type::Var { level } => *level,
+            _ => GENERIC,
+        }
+    }
+
+    /// Links the variable `var` to `ty`, after checking that `ty` doesn't
+    /// contain it other than through a row and lowering the levels of the
+    /// variables in `ty` so that they aren't generalized any sooner than
+    /// `var` would be.
+    fn bind(&mut self, var: TypeId, level: u32, ty: TypeId) -> Result<(), UnifyError<'s>> {
+        if !self.occurs(var, level, ty, false, &mut FxHashSet::default()) {
+            return Err(UnifyError::Infinite { var, ty });
+        }
+        self.types[var.0 as usize] = Type::Link(ty);
+        Ok(())
+    }
+
+    /// Returns false if `var` occurs in `ty` without an object or variant
+    /// entry between them, unless `guarded` says there already is one.
+    /// `seen` holds the rows already visited, so that cycles are only
+    /// followed once.
+    fn occurs(
+        &mut self,
+        var: TypeId,
+        level: u32,
+        ty: TypeId,
+        guarded: bool,
+        seen: &mut FxHashSet<TypeId>,
+    ) -> bool {
+        let ty = self.find(ty);
+        if ty == var {
+            return guarded;
+        }
+
+        match self.get(ty).clone() {
+            Type::Var { level: l } => {
+                if l > level {
+                    self.types[ty.0 as usize] = Type::Var { level };
+                }
+                true
+            }
+            Type::Tuple(items) => items
+                .iter()
+                .all(|&t| self.occurs(var, level, t, guarded, seen)),
+            Type::Function(param, ret) => {
+                self.occurs(var, level, param, guarded, seen)
+                    && self.occurs(var, level, ret, guarded, seen)
+            }
+            Type::Ref(t) | Type::List(t) => self.occurs(var, level, t, guarded, seen),
+            Type::Object(row) | Type::Variant(row) => {
+                if !seen.insert(ty) {
+                    return true;
+                }
+                row.entries
+                    .iter()
+                    .all(|&(_, t)| self.occurs(var, level, t, true, seen))
+                    && row
+                        .rest
+                        .is_none_or(|rest| self.occurs(var, level, rest, guarded, seen))
+            }
+            Type::Int
+            | Type::Float
+            | Type::BigInt
+            | Type::String
+            | Type::Bool
+            | Type::Error
+            | Type::Link(_) => true,
+        }
+    }
+
+    /// Marks every variable in `ty` above `level` as generic.
+    pub fn generalize(&mut self, level: u32, ty: TypeId) {
+        self.generalize_in(level, ty, &mut FxHashSet::default());
+    }
+
+    fn generalize_in(&mut self, level: u32, ty: TypeId, seen: &mut FxHashSet<TypeId>) {
+        let ty = self.find(ty);
+        match self.get(ty).clone() {
+            Type::Var { level: l } if l > level && l != GENERIC => {
+                self.types[ty.0 as usize] = Type::Var { level: GENERIC }
+            }
+            Type::Tuple(items) => items
+                .iter()
+                .for_each(|&t| self.generalize_in(level, t, seen)),
+            Type::Function(param, ret) => {
+                self.generalize_in(level, param, seen);
+                self.generalize_in(level, ret, seen);
+            }
+            Type::Ref(t) | Type::List(t) => self.generalize_in(level, t, seen),
+            Type::Object(row) | Type::Variant(row) if seen.insert(ty) => {
+                row.entries
+                    .iter()
+                    .for_each(|&(_, t)| self.generalize_in(level, t, seen));
+                if let Some(rest) = row.rest {
+                    self.generalize_in(level, rest, seen);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Copies `ty`, replacing its generic variables with fresh variables at
+    /// `level`. A recursive type is copied into one with the same cycles.
+    pub fn instantiate(&mut self, level: u32, ty: TypeId) -> TypeId {
+        fn copy<'s>(
+            types: &mut Types<'s>,
+            level: u32,
+            ty: TypeId,
+            fresh: &mut Vec<(TypeId, TypeId)>,
+        ) -> TypeId {
+            let ty = types.find(ty);
+            if let Some(&(_, new)) = fresh.iter().find(|(old, _)| *old == ty) {
+                return new;
+            }
+            let copied = match types.get(ty).clone() {
+                Type::Var { level: GENERIC } => {
+                    let new = types.var(level);
+                    fresh.push((ty, new));
+                    return new;
+                }
+                Type::Tuple(items) => Type::Tuple(
+                    items
+                        .iter()
+                        .map(|&t| copy(types, level, t, fresh))
+                        .collect(),
+                ),
+                Type::Function(param, ret) => Type::Function(
+                    copy(types, level, param, fresh),
+                    copy(types, level, ret, fresh),
+                ),
+                Type::Ref(t) => Type::Ref(copy(types, level, t, fresh)),
+                Type::List(t) => Type::List(copy(types, level, t, fresh)),
+                Type::Object(row) | Type::Variant(row) => {
+                    // the copy is added before its entries, which may lead
+                    // back to it
+                    let new = types.add(Type::Error);
+                    fresh.push((ty, new));
+                    let row = copy_row(types, level, &row, fresh);
+                    types.types[new.0 as usize] = match types.get(ty) {
+                        Type::Object(_) => Type::Object(row),
+                        _ => Type::Variant(row),
+                    };
+                    return new;
+                }
+                _ => return ty,
+            };
+            types.add(copied)
+        }
+
+        fn copy_row<'s>(
+            types: &mut Types<'s>,
+            level: u32,
+            row: &Row<'s>,
+            fresh: &mut Vec<(TypeId, TypeId)>,
+        ) -> Row<'s> {
+            Row {
+                entries: row
+                    .entries
+                    .iter()
+                    .map(|&(name, t)| (name, copy(types, level, t, fresh)))
+                    .collect(),
+                rest: row.rest.map(|rest| copy(types, level, rest, fresh)),
+                closed: row.closed,
+            }
+        }
+
+        copy(self, level, ty, &mut Vec::new())
+    }
+
+    /// Renders `ty` the way it would be written in a type expression, with
+    /// function types as `A -> B` and variables as `?0`, `?1`, ...
+    pub fn display(&self, ty: TypeId) -> String {
+        let mut out = String::new();
+        self.write(&mut out, &mut Vec::new(), ty, false);
+        out
+    }
+
+    /// Writes `ty`, where `stack` holds the rows it's inside of and whether
+    /// each one has been referred to from inside itself. A row that contains
+    /// itself is written as `(T as ?n)`, with `?n` where it recurs.
+    fn write(&self, out: &mut String, stack: &mut Vec<(TypeId, bool)>, ty: TypeId, nested: bool) {
+        let ty = self.find(ty);
+        if !matches!(self.get(ty), Type::Object(_) | Type::Variant(_)) {
+            return self.write_type(out, stack, ty, nested);
+        }
+        if let Some((_, recursive)) = stack.iter_mut().find(|(row, _)| *row == ty) {
+            *recursive = true;
+            write!(out, "?{}", ty.0).unwrap();
+            return;
+        }
+        stack.push((ty, false));
+        let start = out.len();
+        self.write_type(out, stack, ty, nested);
+        if let Some((_, true)) = stack.pop() {
+            out.insert(start, '(');
+            write!(out, " as ?{})", ty.0).unwrap();
+        }
+    }
+
+    fn write_type(
+        &self,
+        out: &mut String,
+        stack: &mut Vec<(TypeId, bool)>,
+        ty: TypeId,
+        nested: bool,
+    ) {
+        match self.get(ty) {
+            Type::Var { .. } => write!(out, "?{}", ty.0).unwrap(),
+            Type::Link(_) => unreachable!(),
+            Type::Int => out.push_str("Int"),
+            Type::Float => out.push_str("Float"),
+            Type::BigInt => out.push_str("BigInt"),
+            Type::String => out.push_str("String"),
+            Type::Bool => out.push_str("Bool"),
+            Type::Error => out.push_str("{error}"),
+            Type::Tuple(items) if items.is_empty() => out.push_str("Unit"),
+            Type::Tuple(items) => {
+                out.push('(');
+                for (i, &item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    self.write(out, stack, item, false);
+                }
+                out.push(')');
+            }
+            Type::Function(param, ret) => {
+                if nested {
+                    out.push('(');
+                }
+                self.write(out, stack, *param, true);
+                out.push_str(" -> ");
+                self.write(out, stack, *ret, false);
+                if nested {
+                    out.push(')');
+                }
+            }
+            Type::Ref(t) => {
+                out.push('^');
+                self.write(out, stack, *t, true);
+            }
+            Type::List(t) => {
+                out.push_str("List(");
+                self.write(out, stack, *t, false);
+                out.push(')');
+            }
+            Type::Object(row) => {
+                let (entries, rest) = self.row(row);
+                out.push_str(".{");
+                for (i, (name, t)) in entries.iter().enumerate() {
+                    out.push_str(if i > 0 { ", " } else { " " });
+                    write!(out, "{}: ", name.0).unwrap();
+                    self.write(out, stack, *t, false);
+                }
+                if rest.is_some() {
+                    out.push_str(if entries.is_empty() { " .." } else { ", .." });
+                }
+                out.push_str(" }");
+            }
+            Type::Variant(row) => {
+                let (entries, rest) = self.row(row);
+                for (i, (name, t)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        out.push(' ');
+                    }
+                    write!(out, "|{}", name.0).unwrap();
+                    if !matches!(self.get(*t), Type::Tuple(items) if items.is_empty()) {
+                        out.push_str(": ");
+                        self.write(out, stack, *t, true);
+                    }
+                }
+                if rest.is_some() {
+                    out.push_str(" |..");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,732 @@
+//! The WebAssembly binary format, just as much of it as code generation
+//! needs.
+
+use crate::tokenizer::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValType {
+    I32,
+    I64,
+    F64,
+}
+
+impl ValType {
+    fn byte(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7f,
+            ValType::I64 => 0x7e,
+            ValType::F64 => 0x7c,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+/// The body of a function: its locals and instructions.
+#[derive(Debug)]
+pub struct Code {
+    params: u32,
+    locals: Vec<ValType>,
+    bytes: Vec<u8>,
+    /// The span that the instructions from each offset into `bytes` on were
+    /// generated for.
+    marks: Vec<(u32, Span)>,
+}
+
+/// The type of a block, loop or if.
+#[derive(Debug, Clone, Copy)]
+pub enum Block {
+    Empty,
+    Result(ValType),
+}
+
+impl Code {
+    pub fn new(params: u32) -> Code {
+        Code {
+            params,
+            locals: Vec::new(),
+            bytes: Vec::new(),
+            marks: Vec::new(),
+        }
+    }
+
+    /// Says that the instructions added from here on are for `span`.
+    pub fn mark(&mut self, span: Span) {
+        let offset = self.bytes.len() as u32;
+        match self.marks.last_mut() {
+            Some((_, last)) if *last == span => {}
+            Some((at, last)) if *at == offset => *last = span,
+            _ => self.marks.push((offset, span)),
+        }
+    }
+
+    /// Adds a local, returning its index.
+    pub fn local(&mut self, ty: ValType) -> u32 {
+        self.locals.push(ty);
+        self.params + self.locals.len() as u32 - 1
+    }
+
+    fn op(&mut self, op: u8) -> &mut Self {
+        self.bytes.push(op);
+        self
+    }
+
+    fn op_u32(&mut self, op: u8, value: u32) -> &mut Self {
+        self.bytes.push(op);
+        unsigned(&mut self.bytes, value as u64);
+        self
+    }
+
+    fn block_op(&mut self, op: u8, ty: Block) -> &mut Self {
+        self.bytes.push(op);
+        self.bytes.push(match ty {
+            Block::Empty => 0x40,
+            Block::Result(ty) => ty.byte(),
+        });
+        self
+    }
+
+    /// A load or store, which all take an alignment and a static offset.
+    fn mem(&mut self, op: u8, align: u32, offset: u32) -> &mut Self {
+        self.bytes.push(op);
+        unsigned(&mut self.bytes, align as u64);
+        unsigned(&mut self.bytes, offset as u64);
+        self
+    }
+
+    pub fn unreachable(&mut self) -> &mut Self {
+        self.op(0x00)
+    }
+
+    pub fn block(&mut self, ty: Block) -> &mut Self {
+        self.block_op(0x02, ty)
+    }
+
+    pub fn loop_(&mut self, ty: Block) -> &mut Self {
+        self.block_op(0x03, ty)
+    }
+
+    pub fn if_(&mut self, ty: Block) -> &mut Self {
+        self.block_op(0x04, ty)
+    }
+
+    pub fn else_(&mut self) -> &mut Self {
+        self.op(0x05)
+    }
+
+    pub fn end(&mut self) -> &mut Self {
+        self.op(0x0b)
+    }
+
+    pub fn br(&mut self, depth: u32) -> &mut Self {
+        self.op_u32(0x0c, depth)
+    }
+
+    pub fn br_if(&mut self, depth: u32) -> &mut Self {
+        self.op_u32(0x0d, depth)
+    }
+
+    pub fn return_(&mut self) -> &mut Self {
+        self.op(0x0f)
+    }
+
+    pub fn call(&mut self, func: u32) -> &mut Self {
+        self.op_u32(0x10, func)
+    }
+
+    pub fn call_indirect(&mut self, ty: u32) -> &mut Self {
+        self.op_u32(0x11, ty);
+        self.bytes.push(0);
+        self
+    }
+
+    pub fn drop(&mut self) -> &mut Self {
+        self.op(0x1a)
+    }
+
+    pub fn select(&mut self) -> &mut Self {
+        self.op(0x1b)
+    }
+
+    pub fn local_get(&mut self, local: u32) -> &mut Self {
+        self.op_u32(0x20, local)
+    }
+
+    pub fn local_set(&mut self, local: u32) -> &mut Self {
+        self.op_u32(0x21, local)
+    }
+
+    pub fn local_tee(&mut self, local: u32) -> &mut Self {
+        self.op_u32(0x22, local)
+    }
+
+    pub fn global_get(&mut self, global: u32) -> &mut Self {
+        self.op_u32(0x23, global)
+    }
+
+    pub fn global_set(&mut self, global: u32) -> &mut Self {
+        self.op_u32(0x24, global)
+    }
+
+    pub fn i32_load(&mut self, offset: u32) -> &mut Self {
+        self.mem(0x28, 2, offset)
+    }
+
+    pub fn i64_load(&mut self, offset: u32) -> &mut Self {
+        self.mem(0x29, 3, offset)
+    }
+
+    pub fn f64_load(&mut self, offset: u32) -> &mut Self {
+        self.mem(0x2b, 3, offset)
+    }
+
+    pub fn i32_load8_u(&mut self, offset: u32) -> &mut Self {
+        self.mem(0x2d, 0, offset)
+    }
+
+    pub fn i32_store(&mut self, offset: u32) -> &mut Self {
+        self.mem(0x36, 2, offset)
+    }
+
+    pub fn i32_store8(&mut self, offset: u32) -> &mut Self {
+        self.mem(0x3a, 0, offset)
+    }
+
+    pub fn i64_store(&mut self, offset: u32) -> &mut Self {
+        self.mem(0x37, 3, offset)
+    }
+
+    pub fn f64_store(&mut self, offset: u32) -> &mut Self {
+        self.mem(0x39, 3, offset)
+    }
+
+    pub fn memory_size(&mut self) -> &mut Self {
+        self.op(0x3f).op(0x00)
+    }
+
+    pub fn memory_grow(&mut self) -> &mut Self {
+        self.op(0x40).op(0x00)
+    }
+
+    pub fn memory_copy(&mut self) -> &mut Self {
+        self.op(0xfc);
+        unsigned(&mut self.bytes, 10);
+        self.bytes.extend([0, 0]);
+        self
+    }
+
+    pub fn i32_const(&mut self, value: i32) -> &mut Self {
+        self.bytes.push(0x41);
+        signed(&mut self.bytes, value as i64);
+        self
+    }
+
+    pub fn i64_const(&mut self, value: i64) -> &mut Self {
+        self.bytes.push(0x42);
+        signed(&mut self.bytes, value);
+        self
+    }
+
+    pub fn f64_const(&mut self, value: f64) -> &mut Self {
+        self.bytes.push(0x44);
+        self.bytes.extend(value.to_le_bytes());
+        self
+    }
+
+    pub fn i32_eqz(&mut self) -> &mut Self {
+        self.op(0x45)
+    }
+
+    pub fn i32_eq(&mut self) -> &mut Self {
+        self.op(0x46)
+    }
+
+    pub fn i32_ne(&mut self) -> &mut Self {
+        self.op(0x47)
+    }
+
+    pub fn i32_lt_s(&mut self) -> &mut Self {
+        self.op(0x48)
+    }
+
+    pub fn i32_lt_u(&mut self) -> &mut Self {
+        self.op(0x49)
+    }
+
+    pub fn i32_gt_s(&mut self) -> &mut Self {
+        self.op(0x4a)
+    }
+
+    pub fn i32_gt_u(&mut self) -> &mut Self {
+        self.op(0x4b)
+    }
+
+    pub fn i32_le_s(&mut self) -> &mut Self {
+        self.op(0x4c)
+    }
+
+    pub fn i32_ge_s(&mut self) -> &mut Self {
+        self.op(0x4e)
+    }
+
+    pub fn i32_ge_u(&mut self) -> &mut Self {
+        self.op(0x4f)
+    }
+
+    pub fn i64_eqz(&mut self) -> &mut Self {
+        self.op(0x50)
+    }
+
+    pub fn i64_eq(&mut self) -> &mut Self {
+        self.op(0x51)
+    }
+
+    pub fn i64_ne(&mut self) -> &mut Self {
+        self.op(0x52)
+    }
+
+    pub fn i64_lt_s(&mut self) -> &mut Self {
+        self.op(0x53)
+    }
+
+    pub fn i64_gt_s(&mut self) -> &mut Self {
+        self.op(0x55)
+    }
+
+    pub fn i64_le_s(&mut self) -> &mut Self {
+        self.op(0x57)
+    }
+
+    pub fn i64_ge_s(&mut self) -> &mut Self {
+        self.op(0x59)
+    }
+
+    pub fn f64_eq(&mut self) -> &mut Self {
+        self.op(0x61)
+    }
+
+    pub fn f64_lt(&mut self) -> &mut Self {
+        self.op(0x63)
+    }
+
+    pub fn f64_gt(&mut self) -> &mut Self {
+        self.op(0x64)
+    }
+
+    pub fn f64_le(&mut self) -> &mut Self {
+        self.op(0x65)
+    }
+
+    pub fn f64_ge(&mut self) -> &mut Self {
+        self.op(0x66)
+    }
+
+    pub fn i32_add(&mut self) -> &mut Self {
+        self.op(0x6a)
+    }
+
+    pub fn i32_sub(&mut self) -> &mut Self {
+        self.op(0x6b)
+    }
+
+    pub fn i32_mul(&mut self) -> &mut Self {
+        self.op(0x6c)
+    }
+
+    pub fn i32_and(&mut self) -> &mut Self {
+        self.op(0x71)
+    }
+
+    pub fn i32_or(&mut self) -> &mut Self {
+        self.op(0x72)
+    }
+
+    pub fn i32_xor(&mut self) -> &mut Self {
+        self.op(0x73)
+    }
+
+    pub fn i32_shl(&mut self) -> &mut Self {
+        self.op(0x74)
+    }
+
+    pub fn i32_shr_u(&mut self) -> &mut Self {
+        self.op(0x76)
+    }
+
+    pub fn i64_add(&mut self) -> &mut Self {
+        self.op(0x7c)
+    }
+
+    pub fn i64_sub(&mut self) -> &mut Self {
+        self.op(0x7d)
+    }
+
+    pub fn i64_mul(&mut self) -> &mut Self {
+        self.op(0x7e)
+    }
+
+    pub fn i64_div_s(&mut self) -> &mut Self {
+        self.op(0x7f)
+    }
+
+    pub fn i64_div_u(&mut self) -> &mut Self {
+        self.op(0x80)
+    }
+
+    pub fn i64_rem_s(&mut self) -> &mut Self {
+        self.op(0x81)
+    }
+
+    pub fn i64_rem_u(&mut self) -> &mut Self {
+        self.op(0x82)
+    }
+
+    pub fn i64_and(&mut self) -> &mut Self {
+        self.op(0x83)
+    }
+
+    pub fn i64_or(&mut self) -> &mut Self {
+        self.op(0x84)
+    }
+
+    pub fn i64_xor(&mut self) -> &mut Self {
+        self.op(0x85)
+    }
+
+    pub fn i64_shl(&mut self) -> &mut Self {
+        self.op(0x86)
+    }
+
+    pub fn i64_shr_u(&mut self) -> &mut Self {
+        self.op(0x88)
+    }
+
+    pub fn f64_floor(&mut self) -> &mut Self {
+        self.op(0x9c)
+    }
+
+    pub fn f64_trunc(&mut self) -> &mut Self {
+        self.op(0x9d)
+    }
+
+    pub fn f64_sqrt(&mut self) -> &mut Self {
+        self.op(0x9f)
+    }
+
+    pub fn f64_add(&mut self) -> &mut Self {
+        self.op(0xa0)
+    }
+
+    pub fn f64_sub(&mut self) -> &mut Self {
+        self.op(0xa1)
+    }
+
+    pub fn f64_mul(&mut self) -> &mut Self {
+        self.op(0xa2)
+    }
+
+    pub fn f64_div(&mut self) -> &mut Self {
+        self.op(0xa3)
+    }
+
+    pub fn i32_wrap_i64(&mut self) -> &mut Self {
+        self.op(0xa7)
+    }
+
+    pub fn i64_extend_i32_u(&mut self) -> &mut Self {
+        self.op(0xad)
+    }
+
+    pub fn i64_trunc_f64_s(&mut self) -> &mut Self {
+        self.op(0xb0)
+    }
+
+    pub fn f64_convert_i64_s(&mut self) -> &mut Self {
+        self.op(0xb9)
+    }
+}
+
+pub struct Import {
+    pub module: &'static str,
+    pub name: String,
+    pub ty: u32,
+}
+
+pub struct Global {
+    pub ty: ValType,
+    pub mutable: bool,
+    /// The initial value, or for an `F64` global its bits, as given by
+    /// [f64::to_bits].
+    pub init: i64,
+}
+
+/// A module under construction. Functions are numbered after the imports,
+/// in the order they're added.
+#[derive(Default)]
+pub struct Module {
+    types: Vec<FuncType>,
+    imports: Vec<Import>,
+    functions: Vec<(u32, Code)>,
+    /// The functions in the table, which closures refer to by index.
+    table: Vec<u32>,
+    globals: Vec<Global>,
+    exports: Vec<(String, u8, u32)>,
+    /// The contents of memory, starting at address 0.
+    data: Vec<u8>,
+    /// The minimum number of pages of memory.
+    pages: u32,
+    /// The URL of the module's source map, which goes in a custom section.
+    source_map_url: Option<String>,
+}
+
+impl Module {
+    /// Returns the index of a function type, adding it if it's new.
+    pub fn ty(&mut self, params: &[ValType], results: &[ValType]) -> u32 {
+        let ty = FuncType {
+            params: params.to_vec(),
+            results: results.to_vec(),
+        };
+        match self.types.iter().position(|t| *t == ty) {
+            Some(index) => index as u32,
+            None => {
+                self.types.push(ty);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+
+    /// Imports a function. All imports must be added before any function.
+    pub fn import(&mut self, module: &'static str, name: impl Into<String>, ty: u32) -> u32 {
+        assert!(self.functions.is_empty());
+        self.imports.push(Import {
+            module,
+            name: name.into(),
+            ty,
+        });
+        self.imports.len() as u32 - 1
+    }
+
+    /// Reserves the index of a function whose code is given later with
+    /// [Module::define].
+    pub fn declare(&mut self, ty: u32) -> u32 {
+        let params = self.types[ty as usize].params.len() as u32;
+        self.functions.push((ty, Code::new(params)));
+        (self.imports.len() + self.functions.len()) as u32 - 1
+    }
+
+    pub fn define(&mut self, func: u32, code: Code) {
+        self.functions[func as usize - self.imports.len()].1 = code;
+    }
+
+    /// Adds a function to the table, returning its index there.
+    pub fn table_entry(&mut self, func: u32) -> u32 {
+        self.table.push(func);
+        self.table.len() as u32 - 1
+    }
+
+    pub fn global(&mut self, global: Global) -> u32 {
+        self.globals.push(global);
+        self.globals.len() as u32 - 1
+    }
+
+    /// Changes the initial value of a global, for values that aren't known
+    /// until the rest of the module is built.
+    pub fn set_global_init(&mut self, global: u32, init: i64) {
+        self.globals[global as usize].init = init;
+    }
+
+    pub fn export_func(&mut self, name: &str, func: u32) {
+        self.exports.push((name.to_string(), 0x00, func));
+    }
+
+    pub fn export_memory(&mut self, name: &str) {
+        self.exports.push((name.to_string(), 0x02, 0));
+    }
+
+    /// Appends static data aligned to 8 bytes, returning its address.
+    pub fn data(&mut self, bytes: &[u8]) -> u32 {
+        while !self.data.len().is_multiple_of(8) {
+            self.data.push(0);
+        }
+        let address = self.data.len() as u32;
+        self.data.extend_from_slice(bytes);
+        address
+    }
+
+    /// The end of the static data, where the heap can start.
+    pub fn data_end(&self) -> u32 {
+        (self.data.len() as u32 + 7) & !7
+    }
+
+    pub fn min_pages(&mut self, pages: u32) {
+        self.pages = self.pages.max(pages);
+    }
+
+    pub fn source_map_url(&mut self, url: &str) {
+        self.source_map_url = Some(url.to_string());
+    }
+
+    /// The bytes of the module, and where the spans that its code was marked
+    /// with start, as offsets into those bytes.
+    pub fn finish(self) -> (Vec<u8>, Vec<(u32, Span)>) {
+        let mut out = b"\0asm\x01\0\0\0".to_vec();
+
+        section(&mut out, 1, self.types.len(), |s| {
+            for ty in &self.types {
+                s.push(0x60);
+                vec(s, &ty.params, |s, t| s.push(t.byte()));
+                vec(s, &ty.results, |s, t| s.push(t.byte()));
+            }
+        });
+        section(&mut out, 2, self.imports.len(), |s| {
+            for import in &self.imports {
+                name(s, import.module);
+                name(s, &import.name);
+                s.push(0x00);
+                unsigned(s, import.ty as u64);
+            }
+        });
+        section(&mut out, 3, self.functions.len(), |s| {
+            for (ty, _) in &self.functions {
+                unsigned(s, *ty as u64);
+            }
+        });
+        section(&mut out, 4, 1, |s| {
+            s.extend([0x70, 0x00]);
+            unsigned(s, self.table.len() as u64);
+        });
+        section(&mut out, 5, 1, |s| {
+            let pages = self.pages.max(self.data.len().div_ceil(1 << 16) as u32);
+            s.push(0x00);
+            unsigned(s, pages as u64);
+        });
+        section(&mut out, 6, self.globals.len(), |s| {
+            for global in &self.globals {
+                s.push(global.ty.byte());
+                s.push(global.mutable as u8);
+                match global.ty {
+                    ValType::I32 => {
+                        s.push(0x41);
+                        signed(s, global.init);
+                    }
+                    ValType::I64 => {
+                        s.push(0x42);
+                        signed(s, global.init);
+                    }
+                    ValType::F64 => {
+                        s.push(0x44);
+                        s.extend(global.init.to_le_bytes());
+                    }
+                }
+                s.push(0x0b);
+            }
+        });
+        section(&mut out, 7, self.exports.len(), |s| {
+            for (export, kind, index) in &self.exports {
+                name(s, export);
+                s.push(*kind);
+                unsigned(s, *index as u64);
+            }
+        });
+        section(&mut out, 9, 1, |s| {
+            s.extend([0x00, 0x41, 0x00, 0x0b]);
+            vec(s, &self.table, |s, func| unsigned(s, *func as u64));
+        });
+        // the marks are offsets into the section until its size is known
+        let mut marks = Vec::new();
+        let mut len = 0;
+        section(&mut out, 10, self.functions.len(), |s| {
+            for (_, code) in &self.functions {
+                let mut body = Vec::new();
+                // locals are declared in runs of the same type
+                let mut runs: Vec<(u32, ValType)> = Vec::new();
+                for &ty in &code.locals {
+                    match runs.last_mut() {
+                        Some((count, last)) if *last == ty => *count += 1,
+                        _ => runs.push((1, ty)),
+                    }
+                }
+                vec(&mut body, &runs, |s, (count, ty)| {
+                    unsigned(s, *count as u64);
+                    s.push(ty.byte());
+                });
+                let start = body.len();
+                body.extend_from_slice(&code.bytes);
+                body.push(0x0b);
+                unsigned(s, body.len() as u64);
+                let start = (s.len() + start) as u32;
+                marks.extend(code.marks.iter().map(|&(at, span)| (start + at, span)));
+                s.extend(body);
+            }
+            len = s.len();
+        });
+        let code_start = (out.len() - len) as u32;
+        for (at, _) in marks.iter_mut() {
+            *at += code_start;
+        }
+        section(&mut out, 11, 1, |s| {
+            s.extend([0x00, 0x41, 0x00, 0x0b]);
+            unsigned(s, self.data.len() as u64);
+            s.extend_from_slice(&self.data);
+        });
+        if let Some(url) = &self.source_map_url {
+            let mut s = Vec::new();
+            name(&mut s, "sourceMappingURL");
+            name(&mut s, url);
+            out.push(0);
+            unsigned(&mut out, s.len() as u64);
+            out.extend(s);
+        }
+
+        (out, marks)
+    }
+}
+
+fn section(out: &mut Vec<u8>, id: u8, len: usize, contents: impl FnOnce(&mut Vec<u8>)) {
+    let mut s = Vec::new();
+    unsigned(&mut s, len as u64);
+    contents(&mut s);
+    out.push(id);
+    unsigned(out, s.len() as u64);
+    out.extend(s);
+}
+
+fn vec<T>(out: &mut Vec<u8>, items: &[T], mut item: impl FnMut(&mut Vec<u8>, &T)) {
+    unsigned(out, items.len() as u64);
+    for i in items {
+        item(out, i);
+    }
+}
+
+fn name(out: &mut Vec<u8>, name: &str) {
+    unsigned(out, name.len() as u64);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
@@ -0,0 +1,81 @@
+//! Source maps, in version 3 of the JSON format that browsers read. A
+//! module is treated as a generated file of one line, so a mapping's column
+//! is an offset into the bytes of the module.
+
+use std::path::Path;
+
+use crate::{
+    doc::json_string,
+    index::relative_path,
+    source_map::{FileId, Lines, SourceMap},
+    tokenizer::Span,
+};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The source map of a module whose code starts the spans of `marks` at
+/// the offsets with them, with the paths of its sources relative to `dir`,
+/// where the map is. The sources are included in the map, so that it works
+/// wherever it's served from.
+pub fn source_map(marks: &[(u32, Span)], source_map: &SourceMap, dir: &Path) -> String {
+    let mut lines = Lines::new(source_map);
+    let mut sources: Vec<FileId> = Vec::new();
+    let mut mappings = String::new();
+    // each field of a segment is relative to the one in the segment before
+    let mut last = [0; 4];
+    for &(offset, span) in marks {
+        let (file, line, column) = lines.position(span.start);
+        let source = match sources.iter().position(|&f| f == file) {
+            Some(source) => source,
+            None => {
+                sources.push(file);
+                sources.len() - 1
+            }
+        };
+        let segment = [offset as i64, source as i64, line as i64, column as i64];
+        // spans that start at the same place don't need a segment each
+        if !mappings.is_empty() && segment[1..] == last[1..] {
+            continue;
+        }
+        if !mappings.is_empty() {
+            mappings.push(',');
+        }
+        for (field, last) in segment.into_iter().zip(last.iter_mut()) {
+            vlq(&mut mappings, field - *last);
+            *last = field;
+        }
+    }
+
+    let files = sources.iter().map(|&file| source_map.file(file));
+    let (paths, contents): (Vec<_>, Vec<_>) = files
+        .map(|file| {
+            // files that aren't on disk, like the prelude, keep their names
+            let path = match file.path.exists() {
+                true => relative_path(dir, &file.path),
+                false => file.path.to_string_lossy().into_owned(),
+            };
+            (json_string(&path), json_string(&file.src))
+        })
+        .unzip();
+    format!(
+        "{{\"version\": 3, \"sources\": [{}], \"sourcesContent\": [{}], \"names\": [], \"mappings\": {}}}\n",
+        paths.join(", "),
+        contents.join(", "),
+        json_string(&mappings)
+    )
+}
+
+/// Writes a number as a base64 variable-length quantity, with its sign in
+/// the lowest bit and five bits to a digit, least significant first.
+fn vlq(out: &mut String, n: i64) {
+    let mut n = (n.unsigned_abs() << 1) | (n < 0) as u64;
+    loop {
+        let digit = (n & 0x1f) as usize;
+        n >>= 5;
+        if n == 0 {
+            out.push(BASE64[digit] as char);
+            return;
+        }
+        out.push(BASE64[digit | 0x20] as char);
+    }
+}
@@ -0,0 +1,713 @@
+//! Compiles the HIR to a WebAssembly module.
+//!
+//! Every value is an `i32` address of an object in linear memory, whose
+//! first word is its [Tag]:
+//! - `Int` and `Float` hold their number at offset 8.
+//! - `BigInt` holds its number of 32-bit limbs at 4, 1 at 8 if it's
+//!   negative, and its limbs from 12, least significant first.
+//! - `String` holds its length at 4 and its UTF-8 bytes from 8.
+//! - `Bool` holds 0 or 1 at 4, and `Unit` holds nothing.
+//! - `Tuple` holds its length at 4 and its items from 8.
+//! - `Record` holds its length at 4 and `(name, value)` pairs from 8, where
+//!   names are the addresses of static strings.
+//! - `Variant` holds its case's name at 4 and its payload at 8.
+//! - `Closure` holds the table index of its function at 4, the number of
+//!   captured values at 8 and the values from 12.
+//! - `Builtin` holds its number at 4 and its name at 8.
+//! - `Ref` holds the value it points to at 4.
+//!
+//! Literals are placed in static memory, and everything else is allocated
+//! from a heap that is never freed. Each lambda becomes a function taking
+//! its closure and its argument, and top-level defs become globals.
+//!
+//! The module exports its `memory` and a `main` function that runs the
+//! program, and imports these from `radi`:
+//! - `write_str(address, length)`, `write_int(i64)` and `write_float(f64)`,
+//!   which `print` writes its output with.
+//! - `fail(code)`, called before trapping with the [runtime::Failure] code of a
+//!   runtime error.
+//!
+//! Each `@extern("c")` function is imported from `c` under its own name.
+//! `Int` and `Float` arguments are passed as `i64` and `f64`, `Bool` as an
+//! `i32` that is 0 or 1, and `String` as the address of the string object.
+//! A function returning a `String` has to return the address of a string
+//! object too.
+//!
+//! A module can come with a source map that maps the offsets of its
+//! instructions to radi source, which browsers and other tools that find it
+//! through the module's `sourceMappingURL` section show in place of the
+//! instructions.
+use std::path::Path;
+
+use rustc_hash::FxHashMap;
+
+mod encode;
+mod mappings;
+mod runtime;
+
+use encode::{Block, Code, Global, Module, ValType};
+use runtime::Runtime;
+
+use crate::{
+    bigint::BigInt,
+    hir::{
+        ArmPattern, Expr, ExprKind, Extern, ExternType, Group, Literal, Num, Prim, Program, VarId,
+    },
+    resolver::Builtin,
+    source_map::SourceMap,
+    tokenizer::Span,
+};
+
+const I32: ValType = ValType::I32;
+
+/// Identifies an extern by its name and signature.
+type ExternKey = (String, Box<[ExternType]>, ExternType);
+
+#[derive(Debug, Clone, Copy)]
+#[repr(i32)]
+pub enum Tag {
+    Int,
+    Float,
+    BigInt,
+    String,
+    Bool,
+    Unit,
+    Tuple,
+    Record,
+    Variant,
+    Closure,
+    Builtin,
+    Ref,
+}
+
+/// Compiles a program to the bytes of a `.wasm` file. `main` is called
+/// with `()` after the top-level expressions are evaluated.
+///
+/// Given the path that a source map is going to be written to, this also
+/// returns the source map, which the module refers to by its file name and
+/// which refers to the files of `source_map` relative to it.
+pub fn compile(
+    program: &Program,
+    main: Option<VarId>,
+    source_map: &SourceMap,
+    map_path: Option<&Path>,
+) -> (Vec<u8>, Option<String>) {
+    let mut out = Output::default();
+    let mut externs = FxHashMap::default();
+    let mut imports = Vec::new();
+    for expr in program
+        .globals
+        .iter()
+        .flat_map(|g| g.bindings.iter().map(|(_, value)| value))
+        .chain(program.init.iter())
+    {
+        collect_externs(expr, &mut imports);
+    }
+    for external in imports {
+        let key = extern_key(&external);
+        if externs.contains_key(&key) {
+            continue;
+        }
+        let params = external
+            .params
+            .iter()
+            .map(|&ty| val_type(ty))
+            .collect::<Vec<_>>();
+        let results = match external.ret {
+            ExternType::Unit => vec![],
+            ret => vec![val_type(ret)],
+        };
+        let ty = out.module.ty(&params, &results);
+        let func = out.module.import("c", external.name.0, ty);
+        externs.insert(key, ExternImport { func, table: None });
+    }
+
+    let runtime = Runtime::new(&mut out);
+    let entry_ty = out.module.ty(&[], &[]);
+    let entry = out.module.declare(entry_ty);
+
+    let mut codegen = Codegen {
+        out,
+        rt: runtime,
+        globals: FxHashMap::default(),
+        externs,
+    };
+    for (var, _) in program.globals.iter().flat_map(|g| g.bindings.iter()) {
+        let global = codegen.out.module.global(Global {
+            ty: I32,
+            mutable: true,
+            init: codegen.rt.unit as i64,
+        });
+        codegen.globals.insert(*var, global);
+    }
+
+    let mut f = Function::new(0);
+    for group in program.globals.iter() {
+        codegen.group(&mut f, group);
+    }
+    for expr in program.init.iter() {
+        codegen.expr(&mut f, expr);
+        f.code.drop();
+    }
+    if let Some(main) = main {
+        codegen.get(&mut f, main);
+        f.code
+            .i32_const(codegen.rt.unit as i32)
+            .call(codegen.rt.apply)
+            .drop();
+    }
+
+    let Codegen { mut out, rt, .. } = codegen;
+    out.module.define(entry, f.code);
+    out.module.export_func("main", entry);
+    out.module.export_memory("memory");
+    let heap = out.module.data_end();
+    out.module.set_global_init(rt.heap, heap as i64);
+    out.module.min_pages(1);
+    let Some(map_path) = map_path else {
+        return (out.module.finish().0, None);
+    };
+    if let Some(name) = map_path.file_name() {
+        out.module.source_map_url(&name.to_string_lossy());
+    }
+    let (bytes, marks) = out.module.finish();
+    let dir = map_path.parent().unwrap_or(Path::new(""));
+    (bytes, Some(mappings::source_map(&marks, source_map, dir)))
+}
+
+/// Collects the externs used in `expr`.
+fn collect_externs<'s>(expr: &Expr<'s>, out: &mut Vec<Extern<'s>>) {
+    if let ExprKind::Extern(external) = &expr.kind {
+        out.push(external.clone());
+    }
+    expr.for_each_child(|child| collect_externs(child, out));
+}
+
+fn extern_key(external: &Extern) -> ExternKey {
+    (
+        external.name.0.to_string(),
+        external.params.clone(),
+        external.ret,
+    )
+}
+
+fn val_type(ty: ExternType) -> ValType {
+    match ty {
+        ExternType::Int => ValType::I64,
+        ExternType::Float => ValType::F64,
+        _ => I32,
+    }
+}
+
+/// An imported extern, and the table index of the function that calls it
+/// from a closure once that function has been generated.
+struct ExternImport {
+    func: u32,
+    table: Option<u32>,
+}
+
+/// The module being built, along with the static objects in its memory.
+#[derive(Default)]
+struct Output {
+    module: Module,
+    strings: FxHashMap<String, u32>,
+    builtins: FxHashMap<Builtin, u32>,
+}
+
+impl Output {
+    /// Adds a static object made of 32-bit words.
+    fn object(&mut self, words: &[i32]) -> u32 {
+        let bytes = words
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect::<Vec<_>>();
+        self.module.data(&bytes)
+    }
+
+    fn string(&mut self, s: &str) -> u32 {
+        if let Some(&address) = self.strings.get(s) {
+            return address;
+        }
+        let mut bytes = (Tag::String as i32).to_le_bytes().to_vec();
+        bytes.extend((s.len() as i32).to_le_bytes());
+        bytes.extend(s.as_bytes());
+        let address = self.module.data(&bytes);
+        self.strings.insert(s.to_string(), address);
+        address
+    }
+
+    fn int(&mut self, i: i64) -> u32 {
+        let mut bytes = [0; 16];
+        bytes[..4].copy_from_slice(&(Tag::Int as i32).to_le_bytes());
+        bytes[8..].copy_from_slice(&i.to_le_bytes());
+        self.module.data(&bytes)
+    }
+
+    fn float(&mut self, x: f64) -> u32 {
+        let mut bytes = [0; 16];
+        bytes[..4].copy_from_slice(&(Tag::Float as i32).to_le_bytes());
+        bytes[8..].copy_from_slice(&x.to_le_bytes());
+        self.module.data(&bytes)
+    }
+
+    fn big_int(&mut self, i: &BigInt) -> u32 {
+        let mut words = vec![
+            Tag::BigInt as i32,
+            i.limbs().len() as i32,
+            i.is_negative() as i32,
+        ];
+        words.extend(i.limbs().iter().map(|&limb| limb as i32));
+        self.object(&words)
+    }
+
+    fn builtin(&mut self, builtin: Builtin) -> u32 {
+        if let Some(&address) = self.builtins.get(&builtin) {
+            return address;
+        }
+        let name = self.string(builtin.name());
+        let address = self.object(&[Tag::Builtin as i32, builtin as i32, name as i32]);
+        self.builtins.insert(builtin, address);
+        address
+    }
+}
+
+/// A function being generated, with the locals its variables are in.
+struct Function {
+    code: Code,
+    locals: FxHashMap<VarId, u32>,
+    /// The span of the expression that code is being generated for.
+    span: Option<Span>,
+}
+
+impl Function {
+    fn new(params: u32) -> Function {
+        Function {
+            code: Code::new(params),
+            locals: FxHashMap::default(),
+            span: None,
+        }
+    }
+
+    fn temp(&mut self) -> u32 {
+        self.code.local(I32)
+    }
+}
+
+struct Codegen {
+    out: Output,
+    rt: Runtime,
+    /// The globals that top-level defs are stored in.
+    globals: FxHashMap<VarId, u32>,
+    externs: FxHashMap<ExternKey, ExternImport>,
+}
+
+impl Codegen {
+    /// Generates code that leaves the value of `expr` on the stack.
+    fn expr(&mut self, f: &mut Function, expr: &Expr) {
+        let outer = f.span.replace(expr.span);
+        f.code.mark(expr.span);
+        self.expr_kind(f, expr);
+        f.span = outer;
+        // what comes after is for the expression this one is part of
+        if let Some(outer) = outer {
+            f.code.mark(outer);
+        }
+    }
+
+    fn expr_kind(&mut self, f: &mut Function, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Literal(lit) => {
+                let address = match *lit {
+                    Literal::Int(i) => self.out.int(i),
+                    Literal::Float(x) => self.out.float(x),
+                    Literal::BigInt(digits) => self.out.big_int(&BigInt::parse(digits.0).unwrap()),
+                    Literal::String(s) => self.out.string(s.0),
+                    Literal::Bool(true) => self.rt.true_,
+                    Literal::Bool(false) => self.rt.false_,
+                    Literal::Unit => self.rt.unit,
+                };
+                f.code.i32_const(address as i32);
+            }
+            ExprKind::Var(var) => self.get(f, *var),
+            ExprKind::Builtin(builtin) => {
+                let address = self.out.builtin(*builtin);
+                f.code.i32_const(address as i32);
+            }
+            ExprKind::Extern(external) => {
+                let index = self.external(external);
+                let closure = self.alloc(f, Tag::Closure, 12);
+                f.code
+                    .local_get(closure)
+                    .i32_const(index as i32)
+                    .i32_store(4);
+                f.code.local_get(closure).i32_const(0).i32_store(8);
+                f.code.local_get(closure);
+            }
+            ExprKind::Lambda { .. } => {
+                let closure = self.closure(f, expr);
+                self.fill(f, closure, expr);
+                f.code.local_get(closure);
+            }
+            ExprKind::Apply { func, arg, .. } => {
+                self.expr(f, func);
+                self.expr(f, arg);
+                f.code.call(self.rt.apply);
+            }
+            ExprKind::Let { var, value, body } => {
+                self.expr(f, value);
+                self.set(f, *var);
+                self.expr(f, body);
+            }
+            ExprKind::LetRec { group, body } => {
+                self.group(f, group);
+                self.expr(f, body);
+            }
+            ExprKind::Case { scrutinee, arms } => {
+                let scrutinee_local = f.temp();
+                self.expr(f, scrutinee);
+                f.code.local_set(scrutinee_local);
+                self.arms(f, scrutinee_local, arms);
+            }
+            ExprKind::Seq(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.code.drop();
+                    }
+                    self.expr(f, item);
+                }
+                if items.is_empty() {
+                    f.code.i32_const(self.rt.unit as i32);
+                }
+            }
+            ExprKind::Tuple(items) => {
+                let tuple = self.alloc(f, Tag::Tuple, 8 + 4 * items.len() as i32);
+                f.code
+                    .local_get(tuple)
+                    .i32_const(items.len() as i32)
+                    .i32_store(4);
+                for (i, item) in items.iter().enumerate() {
+                    f.code.local_get(tuple);
+                    self.expr(f, item);
+                    f.code.i32_store(8 + 4 * i as u32);
+                }
+                f.code.local_get(tuple);
+            }
+            ExprKind::Project { tuple, index } => {
+                self.expr(f, tuple);
+                f.code.i32_load(8 + 4 * *index as u32);
+            }
+            ExprKind::Record(fields) => {
+                let record = self.alloc(f, Tag::Record, 8 + 8 * fields.len() as i32);
+                f.code
+                    .local_get(record)
+                    .i32_const(fields.len() as i32)
+                    .i32_store(4);
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    let name = self.out.string(name.0);
+                    let offset = 8 + 8 * i as u32;
+                    f.code
+                        .local_get(record)
+                        .i32_const(name as i32)
+                        .i32_store(offset);
+                    f.code.local_get(record);
+                    self.expr(f, value);
+                    f.code.i32_store(offset + 4);
+                }
+                f.code.local_get(record);
+            }
+            ExprKind::Field { record, name } => {
+                self.expr(f, record);
+                let name = self.out.string(name.0);
+                f.code.i32_const(name as i32).call(self.rt.field);
+            }
+            ExprKind::Variant { name, payload } => {
+                let variant = self.alloc(f, Tag::Variant, 12);
+                let name = self.out.string(name.0);
+                f.code
+                    .local_get(variant)
+                    .i32_const(name as i32)
+                    .i32_store(4);
+                f.code.local_get(variant);
+                self.expr(f, payload);
+                f.code.i32_store(8).local_get(variant);
+            }
+            ExprKind::NewRef(value) => {
+                let cell = self.alloc(f, Tag::Ref, 8);
+                f.code.local_get(cell);
+                self.expr(f, value);
+                f.code.i32_store(4).local_get(cell);
+            }
+            ExprKind::Load(cell) => {
+                self.expr(f, cell);
+                f.code.i32_load(4);
+            }
+            ExprKind::Store { cell, value } => {
+                self.expr(f, cell);
+                self.expr(f, value);
+                f.code.i32_store(4).i32_const(self.rt.unit as i32);
+            }
+            ExprKind::Prim { op, args } => self.prim(f, *op, args),
+        }
+    }
+
+    /// Allocates an object with the given tag, returning the local that
+    /// holds its address.
+    fn alloc(&mut self, f: &mut Function, tag: Tag, size: i32) -> u32 {
+        let object = f.temp();
+        f.code.i32_const(size).call(self.rt.alloc).local_tee(object);
+        f.code.i32_const(tag as i32).i32_store(0);
+        object
+    }
+
+    fn get(&mut self, f: &mut Function, var: VarId) {
+        match (f.locals.get(&var), self.globals.get(&var)) {
+            (Some(&local), _) => f.code.local_get(local),
+            (None, Some(&global)) => f.code.global_get(global),
+            (None, None) => unreachable!("{var:?} isn't bound"),
+        };
+    }
+
+    /// Stores the value on top of the stack in a variable.
+    fn set(&mut self, f: &mut Function, var: VarId) {
+        if let Some(&global) = self.globals.get(&var) {
+            f.code.global_set(global);
+        } else {
+            let local = *f.locals.entry(var).or_insert_with(|| f.code.local(I32));
+            f.code.local_set(local);
+        }
+    }
+
+    fn group(&mut self, f: &mut Function, group: &Group) {
+        if !group.recursive {
+            for (var, value) in group.bindings.iter() {
+                self.expr(f, value);
+                self.set(f, *var);
+            }
+            return;
+        }
+
+        // the closures are created before any of them captures anything,
+        // so that they can capture each other
+        let mut closures = Vec::new();
+        for (var, value) in group.bindings.iter() {
+            if let ExprKind::Lambda { .. } = value.kind {
+                let closure = self.closure(f, value);
+                f.code.local_get(closure);
+                self.set(f, *var);
+                closures.push(closure);
+            }
+        }
+        let mut closures = closures.into_iter();
+        for (var, value) in group.bindings.iter() {
+            match value.kind {
+                ExprKind::Lambda { .. } => self.fill(f, closures.next().unwrap(), value),
+                _ => {
+                    self.expr(f, value);
+                    self.set(f, *var);
+                }
+            }
+        }
+    }
+
+    /// The variables a lambda captures in its closure. Top-level defs are
+    /// in globals, so they don't need to be captured.
+    fn captures(&self, lambda: &Expr) -> Vec<VarId> {
+        let ExprKind::Lambda { captures, .. } = &lambda.kind else {
+            unreachable!()
+        };
+        captures
+            .iter()
+            .copied()
+            .filter(|var| !self.globals.contains_key(var))
+            .collect()
+    }
+
+    /// Generates the function for a lambda and allocates a closure for it,
+    /// returning the local holding the closure. Its captures are stored
+    /// separately by [Codegen::fill].
+    fn closure(&mut self, f: &mut Function, lambda: &Expr) -> u32 {
+        let ExprKind::Lambda { param, body, .. } = &lambda.kind else {
+            unreachable!()
+        };
+        let captures = self.captures(lambda);
+
+        let func = self.out.module.declare(self.rt.closure_ty);
+        let index = self.out.module.table_entry(func);
+        let mut g = Function::new(2);
+        g.locals.insert(*param, 1);
+        for (i, var) in captures.iter().enumerate() {
+            let local = g.temp();
+            g.code
+                .local_get(0)
+                .i32_load(12 + 4 * i as u32)
+                .local_set(local);
+            g.locals.insert(*var, local);
+        }
+        self.expr(&mut g, body);
+        self.out.module.define(func, g.code);
+
+        let closure = self.alloc(f, Tag::Closure, 12 + 4 * captures.len() as i32);
+        f.code
+            .local_get(closure)
+            .i32_const(index as i32)
+            .i32_store(4);
+        f.code
+            .local_get(closure)
+            .i32_const(captures.len() as i32)
+            .i32_store(8);
+        closure
+    }
+
+    /// Returns the table index of the function that calls an extern with
+    /// the items of a closure's argument, generating it the first time.
+    fn external(&mut self, external: &Extern) -> u32 {
+        let import = self
+            .externs
+            .get_mut(&extern_key(external))
+            .expect("extern wasn't imported");
+        if let Some(index) = import.table {
+            return index;
+        }
+        let func = self.out.module.declare(self.rt.closure_ty);
+        let index = self.out.module.table_entry(func);
+        import.table = Some(index);
+        let callee = import.func;
+
+        let mut code = Code::new(2);
+        for (i, ty) in external.params.iter().enumerate() {
+            code.local_get(1);
+            if external.params.len() > 1 {
+                code.i32_load(8 + 4 * i as u32);
+            }
+            match ty {
+                ExternType::Int => code.i64_load(8),
+                ExternType::Float => code.f64_load(8),
+                ExternType::Bool => code.i32_load(4),
+                ExternType::String | ExternType::Unit => &mut code,
+            };
+        }
+        code.call(callee);
+        match external.ret {
+            ExternType::Int => code.call(self.rt.boxed_int),
+            ExternType::Float => code.call(self.rt.boxed_float),
+            ExternType::Bool => code.call(self.rt.boolean),
+            ExternType::String => &mut code,
+            ExternType::Unit => code.i32_const(self.rt.unit as i32),
+        };
+        self.out.module.define(func, code);
+        index
+    }
+
+    /// Stores the captured values of a lambda in its closure.
+    fn fill(&mut self, f: &mut Function, closure: u32, lambda: &Expr) {
+        for (i, var) in self.captures(lambda).into_iter().enumerate() {
+            f.code.local_get(closure);
+            self.get(f, var);
+            f.code.i32_store(12 + 4 * i as u32);
+        }
+    }
+
+    fn arms(&mut self, f: &mut Function, scrutinee: u32, arms: &[crate::hir::Arm]) {
+        let Some((arm, rest)) = arms.split_first() else {
+            // the type checker makes sure some arm matches
+            f.code.unreachable();
+            return;
+        };
+
+        match &arm.pattern {
+            ArmPattern::Wildcard => return self.expr(f, &arm.body),
+            ArmPattern::Bool(b) => {
+                f.code.local_get(scrutinee).i32_load(4);
+                if !b {
+                    f.code.i32_eqz();
+                }
+                f.code.if_(Block::Result(I32));
+            }
+            ArmPattern::Variant { name, bind } => {
+                let name = self.out.string(name.0);
+                f.code
+                    .local_get(scrutinee)
+                    .i32_load(4)
+                    .i32_const(name as i32);
+                f.code.i32_eq().if_(Block::Result(I32));
+                if let Some(bind) = bind {
+                    f.code.local_get(scrutinee).i32_load(8);
+                    self.set(f, *bind);
+                }
+            }
+        }
+        self.expr(f, &arm.body);
+        f.code.else_();
+        self.arms(f, scrutinee, rest);
+        f.code.end();
+    }
+
+    fn prim(&mut self, f: &mut Function, op: Prim, args: &[Expr]) {
+        let num = match op {
+            Prim::Add(num)
+            | Prim::Sub(num)
+            | Prim::Mul(num)
+            | Prim::Div(num)
+            | Prim::Mod(num)
+            | Prim::Lt(num)
+            | Prim::LtEq(num)
+            | Prim::Gt(num)
+            | Prim::GtEq(num) => Some(num),
+            _ => None,
+        };
+
+        // numbers other than big integers are unboxed before the operation
+        for arg in args {
+            self.expr(f, arg);
+            match num {
+                Some(Num::Int) => f.code.i64_load(8),
+                Some(Num::Float) => f.code.f64_load(8),
+                Some(Num::BigInt) | None => &mut f.code,
+            };
+        }
+
+        let rt = &self.rt;
+        let c = &mut f.code;
+        match (op, num) {
+            (Prim::Add(_), Some(Num::Int)) => c.call(rt.add_int).call(rt.boxed_int),
+            (Prim::Sub(_), Some(Num::Int)) => c.call(rt.sub_int).call(rt.boxed_int),
+            (Prim::Mul(_), Some(Num::Int)) => c.call(rt.mul_int).call(rt.boxed_int),
+            (Prim::Div(_), Some(Num::Int)) => c.call(rt.div_int).call(rt.boxed_int),
+            (Prim::Mod(_), Some(Num::Int)) => c.call(rt.rem_int).call(rt.boxed_int),
+            (Prim::Add(_), Some(Num::BigInt)) => c.i32_const(0).call(rt.big_add),
+            (Prim::Sub(_), Some(Num::BigInt)) => c.i32_const(1).call(rt.big_add),
+            (Prim::Mul(_), Some(Num::BigInt)) => c.call(rt.big_mul),
+            (Prim::Div(_), Some(Num::BigInt)) => c.i32_const(0).call(rt.big_divmod),
+            (Prim::Mod(_), Some(Num::BigInt)) => c.i32_const(1).call(rt.big_divmod),
+            (Prim::Add(_), _) => c.f64_add().call(rt.boxed_float),
+            (Prim::Sub(_), _) => c.f64_sub().call(rt.boxed_float),
+            (Prim::Mul(_), _) => c.f64_mul().call(rt.boxed_float),
+            (Prim::Div(_), _) => c.f64_div().call(rt.boxed_float),
+            (Prim::Mod(_), _) => c.call(rt.rem_float).call(rt.boxed_float),
+            (Prim::Lt(_), Some(Num::Int)) => c.i64_lt_s().call(rt.boolean),
+            (Prim::LtEq(_), Some(Num::Int)) => c.i64_le_s().call(rt.boolean),
+            (Prim::Gt(_), Some(Num::Int)) => c.i64_gt_s().call(rt.boolean),
+            (Prim::GtEq(_), Some(Num::Int)) => c.i64_ge_s().call(rt.boolean),
+            (Prim::Lt(_), Some(Num::BigInt)) => {
+                c.call(rt.big_cmp).i32_const(0).i32_lt_s().call(rt.boolean)
+            }
+            (Prim::LtEq(_), Some(Num::BigInt)) => {
+                c.call(rt.big_cmp).i32_const(0).i32_le_s().call(rt.boolean)
+            }
+            (Prim::Gt(_), Some(Num::BigInt)) => {
+                c.call(rt.big_cmp).i32_const(0).i32_gt_s().call(rt.boolean)
+            }
+            (Prim::GtEq(_), Some(Num::BigInt)) => {
+                c.call(rt.big_cmp).i32_const(0).i32_ge_s().call(rt.boolean)
+            }
+            (Prim::Lt(_), _) => c.f64_lt().call(rt.boolean),
+            (Prim::LtEq(_), _) => c.f64_le().call(rt.boolean),
+            (Prim::Gt(_), _) => c.f64_gt().call(rt.boolean),
+            (Prim::GtEq(_), _) => c.f64_ge().call(rt.boolean),
+            (Prim::Concat, _) => c.call(rt.concat),
+            (Prim::Eq, _) => c.call(rt.equals).call(rt.boolean),
+            (Prim::NotEq, _) => c.call(rt.equals).i32_eqz().call(rt.boolean),
+            (Prim::Not, _) => c.i32_load(4).i32_eqz().call(rt.boolean),
+        };
+    }
+}
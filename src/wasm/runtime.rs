@@ -0,0 +1,1311 @@
+//! The functions that generated code calls into: allocation, calling
+//! closures and builtins, printing, equality, checked arithmetic, big
+//! integer arithmetic and the string and number conversions of the builtins.
+
+use crate::resolver::Builtin;
+
+use super::{
+    encode::{Block, Code, Global, ValType},
+    Output, Tag,
+};
+
+const I32: ValType = ValType::I32;
+const I64: ValType = ValType::I64;
+const F64: ValType = ValType::F64;
+const EMPTY: Block = Block::Empty;
+
+/// The codes passed to the host's `fail` import.
+#[derive(Debug, Clone, Copy)]
+#[repr(i32)]
+pub enum Failure {
+    DivisionByZero = 0,
+    Overflow = 1,
+    OutOfMemory = 2,
+}
+
+pub struct Runtime {
+    pub write_str: u32,
+    pub write_int: u32,
+    pub write_float: u32,
+    pub fail: u32,
+    /// The type of every closure's function, `(env, arg) -> result`.
+    pub closure_ty: u32,
+    /// The global holding the next free address.
+    pub heap: u32,
+    pub alloc: u32,
+    pub boxed_int: u32,
+    pub boxed_float: u32,
+    pub boolean: u32,
+    pub apply: u32,
+    pub builtin: u32,
+    pub show: u32,
+    pub equals: u32,
+    pub field: u32,
+    pub concat: u32,
+    pub substring: u32,
+    pub int_to_string: u32,
+    pub to_int: u32,
+    pub add_int: u32,
+    pub sub_int: u32,
+    pub mul_int: u32,
+    pub div_int: u32,
+    pub rem_int: u32,
+    pub rem_float: u32,
+    pub big_new: u32,
+    pub big_trim: u32,
+    pub mag_cmp: u32,
+    pub mag_sub: u32,
+    pub big_cmp: u32,
+    pub big_add: u32,
+    pub big_mul: u32,
+    pub big_divmod: u32,
+    pub big_from_int: u32,
+    pub big_to_int: u32,
+    pub big_to_string: u32,
+    /// The addresses of the static `()`, `true` and `false` values.
+    pub unit: u32,
+    pub true_: u32,
+    pub false_: u32,
+}
+
+impl Runtime {
+    /// Adds the runtime to the module. This has to happen before any other
+    /// function is added, since it adds imports.
+    pub fn new(out: &mut Output) -> Runtime {
+        let m = &mut out.module;
+        let str_ty = m.ty(&[I32, I32], &[]);
+        let int_ty = m.ty(&[I64], &[]);
+        let float_ty = m.ty(&[F64], &[]);
+        let fail_ty = m.ty(&[I32], &[]);
+        let write_str = m.import("radi", "write_str", str_ty);
+        let write_int = m.import("radi", "write_int", int_ty);
+        let write_float = m.import("radi", "write_float", float_ty);
+        let fail = m.import("radi", "fail", fail_ty);
+
+        let unary = m.ty(&[I32], &[I32]);
+        let binary = m.ty(&[I32, I32], &[I32]);
+        let int_op = m.ty(&[I64, I64], &[I64]);
+        let show_ty = m.ty(&[I32, I32], &[]);
+        let box_int_ty = m.ty(&[I64], &[I32]);
+        let box_float_ty = m.ty(&[F64], &[I32]);
+        let float_op = m.ty(&[F64, F64], &[F64]);
+        let substring_ty = m.ty(&[I32, I64, I64], &[I32]);
+        let to_int_ty = m.ty(&[F64], &[I64]);
+        let ternary = m.ty(&[I32, I32, I32], &[I32]);
+        let big_to_int_ty = m.ty(&[I32], &[I64]);
+        let rt = Runtime {
+            write_str,
+            write_int,
+            write_float,
+            fail,
+            closure_ty: binary,
+            heap: m.global(Global {
+                ty: I32,
+                mutable: true,
+                init: 0,
+            }),
+            alloc: m.declare(unary),
+            boxed_int: m.declare(box_int_ty),
+            boxed_float: m.declare(box_float_ty),
+            boolean: m.declare(unary),
+            apply: m.declare(binary),
+            builtin: m.declare(binary),
+            show: m.declare(show_ty),
+            equals: m.declare(binary),
+            field: m.declare(binary),
+            concat: m.declare(binary),
+            substring: m.declare(substring_ty),
+            int_to_string: m.declare(box_int_ty),
+            to_int: m.declare(to_int_ty),
+            add_int: m.declare(int_op),
+            sub_int: m.declare(int_op),
+            mul_int: m.declare(int_op),
+            div_int: m.declare(int_op),
+            rem_int: m.declare(int_op),
+            rem_float: m.declare(float_op),
+            big_new: m.declare(unary),
+            big_trim: m.declare(binary),
+            mag_cmp: m.declare(binary),
+            mag_sub: m.declare(show_ty),
+            big_cmp: m.declare(binary),
+            big_add: m.declare(ternary),
+            big_mul: m.declare(binary),
+            big_divmod: m.declare(ternary),
+            big_from_int: m.declare(box_int_ty),
+            big_to_int: m.declare(big_to_int_ty),
+            big_to_string: m.declare(unary),
+            unit: out.object(&[Tag::Unit as i32]),
+            true_: out.object(&[Tag::Bool as i32, 1]),
+            false_: out.object(&[Tag::Bool as i32, 0]),
+        };
+
+        let code = rt.alloc_code();
+        out.module.define(rt.alloc, code);
+        let code = rt.boxed_int_code();
+        out.module.define(rt.boxed_int, code);
+        let code = rt.boxed_float_code();
+        out.module.define(rt.boxed_float, code);
+        let code = rt.boolean_code();
+        out.module.define(rt.boolean, code);
+        let code = rt.apply_code();
+        out.module.define(rt.apply, code);
+        let code = rt.builtin_code(out);
+        out.module.define(rt.builtin, code);
+        let code = rt.show_code(out);
+        out.module.define(rt.show, code);
+        let code = rt.equals_code();
+        out.module.define(rt.equals, code);
+        let code = rt.field_code();
+        out.module.define(rt.field, code);
+        let code = rt.concat_code();
+        out.module.define(rt.concat, code);
+        let code = rt.substring_code();
+        out.module.define(rt.substring, code);
+        let code = rt.int_to_string_code();
+        out.module.define(rt.int_to_string, code);
+        let code = rt.to_int_code();
+        out.module.define(rt.to_int, code);
+        let code = rt.add_int_code();
+        out.module.define(rt.add_int, code);
+        let code = rt.sub_int_code();
+        out.module.define(rt.sub_int, code);
+        let code = rt.mul_int_code();
+        out.module.define(rt.mul_int, code);
+        let code = rt.div_int_code(false);
+        out.module.define(rt.div_int, code);
+        let code = rt.div_int_code(true);
+        out.module.define(rt.rem_int, code);
+        let code = rt.rem_float_code();
+        out.module.define(rt.rem_float, code);
+        let code = rt.big_new_code();
+        out.module.define(rt.big_new, code);
+        let code = rt.big_trim_code();
+        out.module.define(rt.big_trim, code);
+        let code = rt.mag_cmp_code();
+        out.module.define(rt.mag_cmp, code);
+        let code = rt.mag_sub_code();
+        out.module.define(rt.mag_sub, code);
+        let code = rt.big_cmp_code();
+        out.module.define(rt.big_cmp, code);
+        let code = rt.big_add_code();
+        out.module.define(rt.big_add, code);
+        let code = rt.big_mul_code();
+        out.module.define(rt.big_mul, code);
+        let code = rt.big_divmod_code();
+        out.module.define(rt.big_divmod, code);
+        let code = rt.big_from_int_code();
+        out.module.define(rt.big_from_int, code);
+        let code = rt.big_to_int_code();
+        out.module.define(rt.big_to_int, code);
+        let code = rt.big_to_string_code();
+        out.module.define(rt.big_to_string, code);
+
+        rt
+    }
+
+    /// Reports a failure to the host and traps.
+    pub fn failure(&self, code: &mut Code, failure: Failure) {
+        code.i32_const(failure as i32).call(self.fail).unreachable();
+    }
+
+    /// `alloc(size) -> address` bumps the heap pointer, growing memory when
+    /// it runs out. Nothing is ever freed.
+    fn alloc_code(&self) -> Code {
+        let mut c = Code::new(1);
+        let (size, ptr, end) = (0, c.local(I32), c.local(I32));
+        c.global_get(self.heap).local_tee(ptr);
+        c.local_get(size).i32_add().i32_const(7).i32_add();
+        c.i32_const(-8)
+            .i32_and()
+            .local_tee(end)
+            .global_set(self.heap);
+
+        c.local_get(end)
+            .memory_size()
+            .i32_const(16)
+            .i32_shl()
+            .i32_gt_u();
+        c.if_(EMPTY);
+        c.local_get(end)
+            .memory_size()
+            .i32_const(16)
+            .i32_shl()
+            .i32_sub();
+        c.i32_const(0xffff).i32_add().i32_const(16).i32_shr_u();
+        c.memory_grow().i32_const(-1).i32_eq();
+        c.if_(EMPTY);
+        self.failure(&mut c, Failure::OutOfMemory);
+        c.end();
+        c.end();
+        c.local_get(ptr);
+        c
+    }
+
+    fn boxed_int_code(&self) -> Code {
+        let mut c = Code::new(1);
+        let ptr = c.local(I32);
+        c.i32_const(16).call(self.alloc).local_tee(ptr);
+        c.i32_const(Tag::Int as i32).i32_store(0);
+        c.local_get(ptr).local_get(0).i64_store(8);
+        c.local_get(ptr);
+        c
+    }
+
+    fn boxed_float_code(&self) -> Code {
+        let mut c = Code::new(1);
+        let ptr = c.local(I32);
+        c.i32_const(16).call(self.alloc).local_tee(ptr);
+        c.i32_const(Tag::Float as i32).i32_store(0);
+        c.local_get(ptr).local_get(0).f64_store(8);
+        c.local_get(ptr);
+        c
+    }
+
+    fn boolean_code(&self) -> Code {
+        let mut c = Code::new(1);
+        c.i32_const(self.true_ as i32)
+            .i32_const(self.false_ as i32)
+            .local_get(0)
+            .select();
+        c
+    }
+
+    /// `apply(func, arg)` calls a closure with its environment or a
+    /// builtin with its number.
+    fn apply_code(&self) -> Code {
+        let mut c = Code::new(2);
+        let (func, arg) = (0, 1);
+        c.local_get(func)
+            .i32_load(0)
+            .i32_const(Tag::Closure as i32)
+            .i32_eq();
+        c.if_(Block::Result(I32));
+        c.local_get(func).local_get(arg).local_get(func).i32_load(4);
+        c.call_indirect(self.closure_ty);
+        c.else_();
+        c.local_get(func)
+            .i32_load(4)
+            .local_get(arg)
+            .call(self.builtin);
+        c.end();
+        c
+    }
+
+    fn builtin_code(&self, out: &mut Output) -> Code {
+        let mut c = Code::new(2);
+        let (id, arg) = (0, 1);
+        let (x, y) = (c.local(I64), c.local(I64));
+        let is = |c: &mut Code, builtin: Builtin| {
+            c.local_get(id)
+                .i32_const(builtin as i32)
+                .i32_eq()
+                .if_(EMPTY);
+        };
+
+        is(&mut c, Builtin::Print);
+        c.local_get(arg).i32_const(0).call(self.show);
+        self.write(&mut c, out, "\n");
+        c.i32_const(self.unit as i32).return_().end();
+
+        is(&mut c, Builtin::Neg);
+        c.i64_const(0).local_get(arg).i64_load(8).call(self.sub_int);
+        c.call(self.boxed_int).return_().end();
+
+        is(&mut c, Builtin::StringLength);
+        c.local_get(arg).i32_load(4).i64_extend_i32_u();
+        c.call(self.boxed_int).return_().end();
+
+        is(&mut c, Builtin::Substring);
+        c.local_get(arg).i32_load(8);
+        c.local_get(arg).i32_load(12).i64_load(8);
+        c.local_get(arg).i32_load(16).i64_load(8);
+        c.call(self.substring).return_().end();
+
+        is(&mut c, Builtin::IntToString);
+        c.local_get(arg).i64_load(8).call(self.int_to_string);
+        c.return_().end();
+
+        is(&mut c, Builtin::ToFloat);
+        c.local_get(arg).i64_load(8).f64_convert_i64_s();
+        c.call(self.boxed_float).return_().end();
+
+        is(&mut c, Builtin::ToInt);
+        c.local_get(arg).f64_load(8).call(self.to_int);
+        c.call(self.boxed_int).return_().end();
+
+        is(&mut c, Builtin::Sqrt);
+        c.local_get(arg).f64_load(8).f64_sqrt();
+        c.call(self.boxed_float).return_().end();
+
+        is(&mut c, Builtin::Floor);
+        c.local_get(arg).f64_load(8).f64_floor();
+        c.call(self.boxed_float).return_().end();
+
+        is(&mut c, Builtin::ToBig);
+        c.local_get(arg).i64_load(8).call(self.big_from_int);
+        c.return_().end();
+
+        is(&mut c, Builtin::BigToInt);
+        c.local_get(arg).call(self.big_to_int).call(self.boxed_int);
+        c.return_().end();
+
+        is(&mut c, Builtin::BigToString);
+        c.local_get(arg).call(self.big_to_string).return_().end();
+
+        // the arithmetic builtins take a pair of integers
+        c.local_get(arg).i32_load(8).i64_load(8).local_set(x);
+        c.local_get(arg).i32_load(12).i64_load(8).local_set(y);
+        for (builtin, func) in [
+            (Builtin::Add, self.add_int),
+            (Builtin::Sub, self.sub_int),
+            (Builtin::Mul, self.mul_int),
+            (Builtin::Div, self.div_int),
+            (Builtin::Mod, self.rem_int),
+        ] {
+            is(&mut c, builtin);
+            c.local_get(x).local_get(y).call(func).call(self.boxed_int);
+            c.return_().end();
+        }
+        c.unreachable();
+        c
+    }
+
+    /// Writes a string that's known at compile time.
+    fn write(&self, c: &mut Code, out: &mut Output, s: &str) {
+        let string = out.string(s);
+        c.i32_const(string as i32 + 8)
+            .i32_const(s.len() as i32)
+            .call(self.write_str);
+    }
+
+    /// Writes the string object held in a local.
+    fn write_string(&self, c: &mut Code, string: u32) {
+        c.local_get(string).i32_const(8).i32_add();
+        c.local_get(string).i32_load(4).call(self.write_str);
+    }
+
+    /// `show(value, nested)` prints a value the way `print` does, quoting
+    /// strings when they're nested in another value.
+    fn show_code(&self, out: &mut Output) -> Code {
+        let mut c = Code::new(2);
+        let (value, nested) = (0, 1);
+        let (tag, i, item) = (c.local(I32), c.local(I32), c.local(I32));
+        let name = c.local(I32);
+        c.local_get(value).i32_load(0).local_set(tag);
+        let is = |c: &mut Code, t: Tag| {
+            c.local_get(tag).i32_const(t as i32).i32_eq().if_(EMPTY);
+        };
+
+        is(&mut c, Tag::Int);
+        c.local_get(value)
+            .i64_load(8)
+            .call(self.write_int)
+            .return_()
+            .end();
+
+        is(&mut c, Tag::Float);
+        c.local_get(value)
+            .f64_load(8)
+            .call(self.write_float)
+            .return_()
+            .end();
+
+        is(&mut c, Tag::BigInt);
+        c.local_get(value).call(self.big_to_string).local_set(item);
+        self.write_string(&mut c, item);
+        c.return_().end();
+
+        is(&mut c, Tag::String);
+        c.local_get(nested).if_(EMPTY);
+        self.write(&mut c, out, "\"");
+        c.end();
+        self.write_string(&mut c, value);
+        c.local_get(nested).if_(EMPTY);
+        self.write(&mut c, out, "\"");
+        c.end().return_().end();
+
+        is(&mut c, Tag::Bool);
+        c.local_get(value).i32_load(4).if_(EMPTY);
+        self.write(&mut c, out, "true");
+        c.else_();
+        self.write(&mut c, out, "false");
+        c.end().return_().end();
+
+        is(&mut c, Tag::Unit);
+        self.write(&mut c, out, "()");
+        c.return_().end();
+
+        is(&mut c, Tag::Tuple);
+        self.write(&mut c, out, "(");
+        each(&mut c, value, i, |c| {
+            c.local_get(i).if_(EMPTY);
+            self.write(c, out, ", ");
+            c.end();
+            c.local_get(value)
+                .local_get(i)
+                .i32_const(2)
+                .i32_shl()
+                .i32_add();
+            c.i32_load(8).i32_const(1).call(self.show);
+        });
+        self.write(&mut c, out, ")");
+        c.return_().end();
+
+        is(&mut c, Tag::Record);
+        self.write(&mut c, out, ".{");
+        each(&mut c, value, i, |c| {
+            c.local_get(i).if_(EMPTY);
+            self.write(c, out, ", ");
+            c.else_();
+            self.write(c, out, " ");
+            c.end();
+            c.local_get(value)
+                .local_get(i)
+                .i32_const(3)
+                .i32_shl()
+                .i32_add();
+            c.local_set(item);
+            c.local_get(item).i32_load(8).local_set(name);
+            self.write_string(c, name);
+            self.write(c, out, ": ");
+            c.local_get(item).i32_load(12).i32_const(1).call(self.show);
+        });
+        self.write(&mut c, out, " }");
+        c.return_().end();
+
+        is(&mut c, Tag::Variant);
+        self.write(&mut c, out, "|");
+        c.local_get(value).i32_load(4).local_set(name);
+        self.write_string(&mut c, name);
+        c.local_get(value).i32_load(8).local_tee(item);
+        c.i32_load(0)
+            .i32_const(Tag::Unit as i32)
+            .i32_ne()
+            .if_(EMPTY);
+        self.write(&mut c, out, ": ");
+        c.local_get(item).i32_const(1).call(self.show);
+        c.end().return_().end();
+
+        is(&mut c, Tag::Closure);
+        self.write(&mut c, out, "<function>");
+        c.return_().end();
+
+        is(&mut c, Tag::Builtin);
+        self.write(&mut c, out, "<builtin ");
+        c.local_get(value).i32_load(8).local_set(name);
+        self.write_string(&mut c, name);
+        self.write(&mut c, out, ">");
+        c.return_().end();
+
+        // references are all that's left
+        self.write(&mut c, out, "^");
+        c.local_get(value).i32_load(4).i32_const(1).call(self.show);
+        c
+    }
+
+    /// `equals(a, b)` compares values structurally. Functions are never
+    /// equal, and references are equal if they are the same cell.
+    fn equals_code(&self) -> Code {
+        let mut c = Code::new(2);
+        let (a, b) = (0, 1);
+        let (tag, i, item) = (c.local(I32), c.local(I32), c.local(I32));
+        c.local_get(a).i32_load(0).local_tee(tag);
+        c.local_get(b).i32_load(0).i32_ne().if_(EMPTY);
+        c.i32_const(0).return_().end();
+        let is = |c: &mut Code, t: Tag| {
+            c.local_get(tag).i32_const(t as i32).i32_eq().if_(EMPTY);
+        };
+
+        is(&mut c, Tag::Int);
+        c.local_get(a).i64_load(8).local_get(b).i64_load(8).i64_eq();
+        c.return_().end();
+
+        is(&mut c, Tag::Float);
+        c.local_get(a).f64_load(8).local_get(b).f64_load(8).f64_eq();
+        c.return_().end();
+
+        is(&mut c, Tag::BigInt);
+        c.local_get(a).local_get(b).call(self.big_cmp).i32_eqz();
+        c.return_().end();
+
+        is(&mut c, Tag::String);
+        c.local_get(a).i32_load(4).local_get(b).i32_load(4).i32_ne();
+        c.if_(EMPTY).i32_const(0).return_().end();
+        each(&mut c, a, i, |c| {
+            c.local_get(a).local_get(i).i32_add().i32_load8_u(8);
+            c.local_get(b).local_get(i).i32_add().i32_load8_u(8);
+            c.i32_ne().if_(EMPTY).i32_const(0).return_().end();
+        });
+        c.i32_const(1).return_().end();
+
+        is(&mut c, Tag::Bool);
+        c.local_get(a).i32_load(4).local_get(b).i32_load(4).i32_eq();
+        c.return_().end();
+
+        is(&mut c, Tag::Unit);
+        c.i32_const(1).return_().end();
+
+        // tuples and records of the same type have the same length
+        is(&mut c, Tag::Tuple);
+        each(&mut c, a, i, |c| {
+            c.local_get(a).local_get(i).i32_const(2).i32_shl().i32_add();
+            c.i32_load(8);
+            c.local_get(b).local_get(i).i32_const(2).i32_shl().i32_add();
+            c.i32_load(8);
+            c.call(self.equals)
+                .i32_eqz()
+                .if_(EMPTY)
+                .i32_const(0)
+                .return_()
+                .end();
+        });
+        c.i32_const(1).return_().end();
+
+        is(&mut c, Tag::Record);
+        each(&mut c, a, i, |c| {
+            c.local_get(a).local_get(i).i32_const(3).i32_shl().i32_add();
+            c.local_tee(item).i32_load(12);
+            c.local_get(b).local_get(item).i32_load(8).call(self.field);
+            c.call(self.equals)
+                .i32_eqz()
+                .if_(EMPTY)
+                .i32_const(0)
+                .return_()
+                .end();
+        });
+        c.i32_const(1).return_().end();
+
+        is(&mut c, Tag::Variant);
+        c.local_get(a).i32_load(4).local_get(b).i32_load(4).i32_ne();
+        c.if_(EMPTY).i32_const(0).return_().end();
+        c.local_get(a)
+            .i32_load(8)
+            .local_get(b)
+            .i32_load(8)
+            .call(self.equals);
+        c.return_().end();
+
+        is(&mut c, Tag::Ref);
+        c.local_get(a).local_get(b).i32_eq().return_().end();
+
+        c.i32_const(0);
+        c
+    }
+
+    /// `field(record, name)` finds a field by its name, which is the
+    /// address of the name's static string.
+    fn field_code(&self) -> Code {
+        let mut c = Code::new(2);
+        let (record, name) = (0, 1);
+        let (i, entry) = (c.local(I32), c.local(I32));
+        each(&mut c, record, i, |c| {
+            c.local_get(record)
+                .local_get(i)
+                .i32_const(3)
+                .i32_shl()
+                .i32_add();
+            c.local_tee(entry).i32_load(8).local_get(name).i32_eq();
+            c.if_(EMPTY).local_get(entry).i32_load(12).return_().end();
+        });
+        c.unreachable();
+        c
+    }
+
+    fn concat_code(&self) -> Code {
+        let mut c = Code::new(2);
+        let (a, b) = (0, 1);
+        let (len_a, len_b, result) = (c.local(I32), c.local(I32), c.local(I32));
+        c.local_get(a).i32_load(4).local_set(len_a);
+        c.local_get(b).i32_load(4).local_set(len_b);
+        c.i32_const(8)
+            .local_get(len_a)
+            .i32_add()
+            .local_get(len_b)
+            .i32_add();
+        c.call(self.alloc).local_tee(result);
+        c.i32_const(Tag::String as i32).i32_store(0);
+        c.local_get(result)
+            .local_get(len_a)
+            .local_get(len_b)
+            .i32_add();
+        c.i32_store(4);
+        c.local_get(result).i32_const(8).i32_add();
+        c.local_get(a).i32_const(8).i32_add();
+        c.local_get(len_a).memory_copy();
+        c.local_get(result)
+            .i32_const(8)
+            .i32_add()
+            .local_get(len_a)
+            .i32_add();
+        c.local_get(b).i32_const(8).i32_add();
+        c.local_get(len_b).memory_copy();
+        c.local_get(result);
+        c
+    }
+
+    /// `substring(string, start, end)` copies the bytes from `start` up to
+    /// `end`, with both offsets clamped to the string.
+    fn substring_code(&self) -> Code {
+        let mut c = Code::new(3);
+        let (string, start, end) = (0, 1, 2);
+        let (len, result, n) = (c.local(I64), c.local(I32), c.local(I32));
+        c.local_get(string)
+            .i32_load(4)
+            .i64_extend_i32_u()
+            .local_set(len);
+        c.local_get(start).i64_const(0).i64_lt_s().if_(EMPTY);
+        c.i64_const(0).local_set(start).end();
+        for (offset, bound, below) in [(start, len, false), (end, start, true), (end, len, false)] {
+            c.local_get(offset).local_get(bound);
+            if below {
+                c.i64_lt_s();
+            } else {
+                c.i64_gt_s();
+            }
+            c.if_(EMPTY).local_get(bound).local_set(offset).end();
+        }
+
+        c.local_get(end)
+            .local_get(start)
+            .i64_sub()
+            .i32_wrap_i64()
+            .local_set(n);
+        c.i32_const(8).local_get(n).i32_add();
+        c.call(self.alloc).local_tee(result);
+        c.i32_const(Tag::String as i32).i32_store(0);
+        c.local_get(result).local_get(n).i32_store(4);
+        c.local_get(result).i32_const(8).i32_add();
+        c.local_get(string).i32_const(8).i32_add();
+        c.local_get(start).i32_wrap_i64().i32_add();
+        c.local_get(n).memory_copy();
+        c.local_get(result);
+        c
+    }
+
+    /// `int_to_string(i)` writes an integer in decimal.
+    fn int_to_string_code(&self) -> Code {
+        let mut c = Code::new(1);
+        let i = 0;
+        let (magnitude, rest) = (c.local(I64), c.local(I64));
+        let (len, result, at) = (c.local(I32), c.local(I32), c.local(I32));
+        // the magnitude of the minimum integer only fits when unsigned
+        c.local_get(i).local_set(magnitude);
+        c.local_get(i).i64_const(0).i64_lt_s().if_(EMPTY);
+        c.i64_const(0).local_get(i).i64_sub().local_set(magnitude);
+        c.end();
+
+        // count the digits, and the sign
+        c.i32_const(1).local_set(len);
+        c.local_get(magnitude)
+            .i64_const(10)
+            .i64_div_u()
+            .local_set(rest);
+        c.block(EMPTY).loop_(EMPTY);
+        c.local_get(rest).i64_eqz().br_if(1);
+        c.local_get(len).i32_const(1).i32_add().local_set(len);
+        c.local_get(rest).i64_const(10).i64_div_u().local_set(rest);
+        c.br(0).end().end();
+        c.local_get(i).i64_const(0).i64_lt_s().if_(EMPTY);
+        c.local_get(len).i32_const(1).i32_add().local_set(len);
+        c.end();
+
+        c.i32_const(8).local_get(len).i32_add();
+        c.call(self.alloc).local_tee(result);
+        c.i32_const(Tag::String as i32).i32_store(0);
+        c.local_get(result).local_get(len).i32_store(4);
+
+        // the digits are written from the last one
+        c.local_get(len).local_set(at);
+        c.loop_(EMPTY);
+        c.local_get(at).i32_const(1).i32_sub().local_set(at);
+        c.local_get(result).local_get(at).i32_add();
+        c.local_get(magnitude)
+            .i64_const(10)
+            .i64_rem_u()
+            .i32_wrap_i64();
+        c.i32_const(b'0' as i32).i32_add().i32_store8(8);
+        c.local_get(magnitude)
+            .i64_const(10)
+            .i64_div_u()
+            .local_tee(magnitude);
+        c.i64_eqz().i32_eqz().br_if(0);
+        c.end();
+        c.local_get(i).i64_const(0).i64_lt_s().if_(EMPTY);
+        c.local_get(result).i32_const(b'-' as i32).i32_store8(8);
+        c.end();
+        c.local_get(result);
+        c
+    }
+
+    /// `to_int(x)` truncates a float towards zero, failing if the result
+    /// doesn't fit.
+    fn to_int_code(&self) -> Code {
+        let mut c = Code::new(1);
+        let x = 0;
+        // NaN fails both comparisons
+        c.local_get(x).f64_const(-(2f64.powi(63))).f64_ge();
+        c.local_get(x).f64_const(2f64.powi(63)).f64_lt();
+        c.i32_and().i32_eqz().if_(EMPTY);
+        self.failure(&mut c, Failure::Overflow);
+        c.end();
+        c.local_get(x).i64_trunc_f64_s();
+        c
+    }
+
+    fn add_int_code(&self) -> Code {
+        let mut c = Code::new(2);
+        let (a, b, r) = (0, 1, c.local(I64));
+        c.local_get(a).local_get(b).i64_add().local_set(r);
+        // overflowed if both operands have a different sign than the result
+        c.local_get(a).local_get(r).i64_xor();
+        c.local_get(b).local_get(r).i64_xor();
+        c.i64_and().i64_const(0).i64_lt_s().if_(EMPTY);
+        self.failure(&mut c, Failure::Overflow);
+        c.end();
+        c.local_get(r);
+        c
+    }
+
+    fn sub_int_code(&self) -> Code {
+        let mut c = Code::new(2);
+        let (a, b, r) = (0, 1, c.local(I64));
+        c.local_get(a).local_get(b).i64_sub().local_set(r);
+        // overflowed if the operands have different signs and the result's
+        // sign differs from the first operand's
+        c.local_get(a).local_get(b).i64_xor();
+        c.local_get(a).local_get(r).i64_xor();
+        c.i64_and().i64_const(0).i64_lt_s().if_(EMPTY);
+        self.failure(&mut c, Failure::Overflow);
+        c.end();
+        c.local_get(r);
+        c
+    }
+
+    fn mul_int_code(&self) -> Code {
+        let mut c = Code::new(2);
+        let (a, b) = (0, 1);
+        c.local_get(a).i64_const(-1).i64_eq().if_(EMPTY);
+        c.local_get(b).i64_const(i64::MIN).i64_eq().if_(EMPTY);
+        self.failure(&mut c, Failure::Overflow);
+        c.end();
+        c.else_();
+        // dividing the wrapped product by one operand doesn't give back the
+        // other exactly when it overflowed
+        c.local_get(a).i64_eqz().i32_eqz().if_(EMPTY);
+        c.local_get(a)
+            .local_get(b)
+            .i64_mul()
+            .local_get(a)
+            .i64_div_s();
+        c.local_get(b).i64_ne().if_(EMPTY);
+        self.failure(&mut c, Failure::Overflow);
+        c.end();
+        c.end();
+        c.end();
+        c.local_get(a).local_get(b).i64_mul();
+        c
+    }
+
+    fn div_int_code(&self, rem: bool) -> Code {
+        let mut c = Code::new(2);
+        let (a, b) = (0, 1);
+        c.local_get(b).i64_eqz().if_(EMPTY);
+        self.failure(&mut c, Failure::DivisionByZero);
+        c.end();
+        c.local_get(a).i64_const(i64::MIN).i64_eq();
+        c.local_get(b).i64_const(-1).i64_eq();
+        c.i32_and().if_(EMPTY);
+        self.failure(&mut c, Failure::Overflow);
+        c.end();
+        c.local_get(a).local_get(b);
+        if rem {
+            c.i64_rem_s();
+        } else {
+            c.i64_div_s();
+        }
+        c
+    }
+
+    /// Wasm has no float remainder, so it's computed as `a - trunc(a / b) * b`.
+    fn rem_float_code(&self) -> Code {
+        let mut c = Code::new(2);
+        let (a, b) = (0, 1);
+        c.local_get(a);
+        c.local_get(a).local_get(b).f64_div().f64_trunc();
+        c.local_get(b).f64_mul().f64_sub();
+        c
+    }
+
+    /// `big_new(len)` allocates a big integer of `len` zero limbs, which is
+    /// positive. The heap is never reused, so fresh memory is zeroed already.
+    fn big_new_code(&self) -> Code {
+        let mut c = Code::new(1);
+        let (len, big) = (0, c.local(I32));
+        c.local_get(len)
+            .i32_const(2)
+            .i32_shl()
+            .i32_const(12)
+            .i32_add();
+        c.call(self.alloc).local_tee(big);
+        c.i32_const(Tag::BigInt as i32).i32_store(0);
+        c.local_get(big).local_get(len).i32_store(4);
+        c.local_get(big);
+        c
+    }
+
+    /// `big_trim(big, negative)` drops the leading zero limbs of a new big
+    /// integer and sets its sign, which is never negative for zero.
+    fn big_trim_code(&self) -> Code {
+        let mut c = Code::new(2);
+        let (big, negative, len) = (0, 1, c.local(I32));
+        c.local_get(big).i32_load(4).local_set(len);
+        c.block(EMPTY).loop_(EMPTY);
+        c.local_get(len).i32_eqz().br_if(1);
+        limb(&mut c, big, len);
+        c.i32_load(8).br_if(1);
+        c.local_get(len).i32_const(1).i32_sub().local_set(len);
+        c.br(0).end().end();
+        c.local_get(big).local_get(len).i32_store(4);
+        c.local_get(big).local_get(negative);
+        c.local_get(len)
+            .i32_const(0)
+            .i32_ne()
+            .i32_and()
+            .i32_store(8);
+        c.local_get(big);
+        c
+    }
+
+    /// `mag_cmp(a, b)` compares the magnitudes of big integers, which may
+    /// have leading zero limbs, returning -1, 0 or 1.
+    fn mag_cmp_code(&self) -> Code {
+        let mut c = Code::new(2);
+        let (a, b) = (0, 1);
+        let (i, x, y) = (c.local(I32), c.local(I32), c.local(I32));
+        c.local_get(a).i32_load(4).local_get(b).i32_load(4);
+        c.local_get(a)
+            .i32_load(4)
+            .local_get(b)
+            .i32_load(4)
+            .i32_gt_u();
+        c.select().local_set(i);
+        c.block(EMPTY).loop_(EMPTY);
+        c.local_get(i).i32_eqz().br_if(1);
+        c.local_get(i).i32_const(1).i32_sub().local_set(i);
+        limb_or_zero(&mut c, a, i);
+        c.local_set(x);
+        limb_or_zero(&mut c, b, i);
+        c.local_set(y);
+        c.local_get(x).local_get(y).i32_ne().if_(EMPTY);
+        c.i32_const(-1).i32_const(1);
+        c.local_get(x).local_get(y).i32_lt_u().select().return_();
+        c.end();
+        c.br(0).end().end();
+        c.i32_const(0);
+        c
+    }
+
+    /// `mag_sub(a, b)` subtracts the magnitude of `b` from `a` in place,
+    /// where `a` is at least `b`.
+    fn mag_sub_code(&self) -> Code {
+        let mut c = Code::new(2);
+        let (a, b, i) = (0, 1, c.local(I32));
+        let (t, borrow) = (c.local(I64), c.local(I64));
+        each(&mut c, a, i, |c| {
+            limb(c, a, i);
+            c.i32_load(12).i64_extend_i32_u();
+            limb_or_zero(c, b, i);
+            c.i64_extend_i32_u().i64_sub();
+            c.local_get(borrow).i64_sub().local_set(t);
+            limb(c, a, i);
+            c.local_get(t).i32_wrap_i64().i32_store(12);
+            c.local_get(t)
+                .i64_const(0)
+                .i64_lt_s()
+                .i64_extend_i32_u()
+                .local_set(borrow);
+        });
+        c
+    }
+
+    /// `big_cmp(a, b)` compares big integers, returning -1, 0 or 1.
+    fn big_cmp_code(&self) -> Code {
+        let mut c = Code::new(2);
+        let (a, b, order) = (0, 1, c.local(I32));
+        c.local_get(a).i32_load(8).local_get(b).i32_load(8).i32_ne();
+        c.if_(EMPTY);
+        c.i32_const(-1)
+            .i32_const(1)
+            .local_get(a)
+            .i32_load(8)
+            .select();
+        c.return_().end();
+        c.local_get(a)
+            .local_get(b)
+            .call(self.mag_cmp)
+            .local_set(order);
+        c.i32_const(0).local_get(order).i32_sub().local_get(order);
+        c.local_get(a).i32_load(8).select();
+        c
+    }
+
+    /// `big_add(a, b, flip)` adds `b` to `a`, or subtracts it if `flip` is
+    /// 1.
+    fn big_add_code(&self) -> Code {
+        let mut c = Code::new(3);
+        let (a, b, flip) = (0, 1, 2);
+        let (negative, result, i, t) = (c.local(I32), c.local(I32), c.local(I32), c.local(I32));
+        let carry = c.local(I64);
+        c.local_get(b).i32_load(8).local_get(flip).i32_xor();
+        c.local_set(negative);
+        c.local_get(a).i32_load(4).local_get(b).i32_load(4);
+        c.local_get(a)
+            .i32_load(4)
+            .local_get(b)
+            .i32_load(4)
+            .i32_gt_u();
+        c.select().i32_const(1).i32_add();
+        c.call(self.big_new).local_set(result);
+
+        c.local_get(a).i32_load(8).local_get(negative).i32_eq();
+        c.if_(EMPTY);
+        each(&mut c, result, i, |c| {
+            c.local_get(carry);
+            limb_or_zero(c, a, i);
+            c.i64_extend_i32_u().i64_add();
+            limb_or_zero(c, b, i);
+            c.i64_extend_i32_u().i64_add().local_set(carry);
+            limb(c, result, i);
+            c.local_get(carry).i32_wrap_i64().i32_store(12);
+            c.local_get(carry)
+                .i64_const(32)
+                .i64_shr_u()
+                .local_set(carry);
+        });
+        c.local_get(result).local_get(negative).call(self.big_trim);
+        c.return_().end();
+
+        // the smaller magnitude is subtracted from the larger one, whose
+        // sign the result has
+        c.local_get(a).local_get(b).call(self.mag_cmp);
+        c.i32_const(0).i32_lt_s().if_(EMPTY);
+        c.local_get(a).local_set(t);
+        c.local_get(b).local_set(a);
+        c.local_get(t).local_set(b);
+        c.else_();
+        c.local_get(a).i32_load(8).local_set(negative);
+        c.end();
+        c.local_get(result).i32_const(12).i32_add();
+        c.local_get(a).i32_const(12).i32_add();
+        c.local_get(a).i32_load(4).i32_const(2).i32_shl();
+        c.memory_copy();
+        c.local_get(result).local_get(b).call(self.mag_sub);
+        c.local_get(result).local_get(negative).call(self.big_trim);
+        c
+    }
+
+    /// `big_mul(a, b)` multiplies big integers the schoolbook way.
+    fn big_mul_code(&self) -> Code {
+        let mut c = Code::new(2);
+        let (a, b) = (0, 1);
+        let (result, i, j, at) = (c.local(I32), c.local(I32), c.local(I32), c.local(I32));
+        let carry = c.local(I64);
+        c.local_get(a)
+            .i32_load(4)
+            .local_get(b)
+            .i32_load(4)
+            .i32_add();
+        c.call(self.big_new).local_set(result);
+        each(&mut c, a, i, |c| {
+            c.i64_const(0).local_set(carry);
+            each(c, b, j, |c| {
+                c.local_get(i).local_get(j).i32_add().local_set(at);
+                c.local_get(carry);
+                limb(c, result, at);
+                c.i32_load(12).i64_extend_i32_u().i64_add();
+                limb(c, a, i);
+                c.i32_load(12).i64_extend_i32_u();
+                limb(c, b, j);
+                c.i32_load(12).i64_extend_i32_u();
+                c.i64_mul().i64_add().local_set(carry);
+                limb(c, result, at);
+                c.local_get(carry).i32_wrap_i64().i32_store(12);
+                c.local_get(carry)
+                    .i64_const(32)
+                    .i64_shr_u()
+                    .local_set(carry);
+            });
+            c.local_get(i)
+                .local_get(b)
+                .i32_load(4)
+                .i32_add()
+                .local_set(at);
+            limb(c, result, at);
+            c.local_get(carry).i32_wrap_i64().i32_store(12);
+        });
+        c.local_get(result);
+        c.local_get(a)
+            .i32_load(8)
+            .local_get(b)
+            .i32_load(8)
+            .i32_xor();
+        c.call(self.big_trim);
+        c
+    }
+
+    /// `big_divmod(a, b, rem)` divides one bit at a time, truncating towards
+    /// zero, and returns the quotient or, if `rem` is 1, the remainder,
+    /// which has the sign of `a`.
+    fn big_divmod_code(&self) -> Code {
+        let mut c = Code::new(3);
+        let (a, b, rem) = (0, 1, 2);
+        let (quotient, remainder) = (c.local(I32), c.local(I32));
+        let (bit, i, carry, next) = (c.local(I32), c.local(I32), c.local(I32), c.local(I32));
+        let word = c.local(I32);
+        c.local_get(b).i32_load(4).i32_eqz().if_(EMPTY);
+        self.failure(&mut c, Failure::DivisionByZero);
+        c.end();
+        c.local_get(a)
+            .i32_load(4)
+            .call(self.big_new)
+            .local_set(quotient);
+        c.local_get(b).i32_load(4).i32_const(1).i32_add();
+        c.call(self.big_new).local_set(remainder);
+
+        c.local_get(a)
+            .i32_load(4)
+            .i32_const(5)
+            .i32_shl()
+            .local_set(bit);
+        c.block(EMPTY).loop_(EMPTY);
+        c.local_get(bit).i32_eqz().br_if(1);
+        c.local_get(bit).i32_const(1).i32_sub().local_tee(bit);
+        c.i32_const(5).i32_shr_u().local_set(word);
+        // shift the next bit of `a` into the remainder
+        limb(&mut c, a, word);
+        c.i32_load(12)
+            .local_get(bit)
+            .i32_shr_u()
+            .i32_const(1)
+            .i32_and();
+        c.local_set(carry);
+        each(&mut c, remainder, i, |c| {
+            limb(c, remainder, i);
+            c.i32_load(12).i32_const(31).i32_shr_u().local_set(next);
+            limb(c, remainder, i);
+            limb(c, remainder, i);
+            c.i32_load(12)
+                .i32_const(1)
+                .i32_shl()
+                .local_get(carry)
+                .i32_or();
+            c.i32_store(12);
+            c.local_get(next).local_set(carry);
+        });
+        c.local_get(remainder).local_get(b).call(self.mag_cmp);
+        c.i32_const(0).i32_ge_s().if_(EMPTY);
+        c.local_get(remainder).local_get(b).call(self.mag_sub);
+        limb(&mut c, quotient, word);
+        limb(&mut c, quotient, word);
+        c.i32_load(12)
+            .i32_const(1)
+            .local_get(bit)
+            .i32_shl()
+            .i32_or();
+        c.i32_store(12);
+        c.end();
+        c.br(0).end().end();
+
+        c.local_get(rem).if_(Block::Result(I32));
+        c.local_get(remainder)
+            .local_get(a)
+            .i32_load(8)
+            .call(self.big_trim);
+        c.else_();
+        c.local_get(quotient);
+        c.local_get(a)
+            .i32_load(8)
+            .local_get(b)
+            .i32_load(8)
+            .i32_xor();
+        c.call(self.big_trim);
+        c.end();
+        c
+    }
+
+    fn big_from_int_code(&self) -> Code {
+        let mut c = Code::new(1);
+        let (i, magnitude, big) = (0, c.local(I64), c.local(I32));
+        c.i64_const(0).local_get(i).i64_sub().local_get(i);
+        c.local_get(i)
+            .i64_const(0)
+            .i64_lt_s()
+            .select()
+            .local_set(magnitude);
+        c.i32_const(2).call(self.big_new).local_tee(big);
+        c.local_get(magnitude).i32_wrap_i64().i32_store(12);
+        c.local_get(big)
+            .local_get(magnitude)
+            .i64_const(32)
+            .i64_shr_u();
+        c.i32_wrap_i64().i32_store(16);
+        c.local_get(big).local_get(i).i64_const(0).i64_lt_s();
+        c.call(self.big_trim);
+        c
+    }
+
+    /// `big_to_int(big)` fails if the integer doesn't fit in 64 bits.
+    fn big_to_int_code(&self) -> Code {
+        let mut c = Code::new(1);
+        let (big, i, magnitude) = (0, c.local(I32), c.local(I64));
+        c.local_get(big)
+            .i32_load(4)
+            .i32_const(2)
+            .i32_gt_u()
+            .if_(EMPTY);
+        self.failure(&mut c, Failure::Overflow);
+        c.end();
+        c.i32_const(1).local_set(i);
+        limb_or_zero(&mut c, big, i);
+        c.i64_extend_i32_u().i64_const(32).i64_shl();
+        c.i32_const(0).local_set(i);
+        limb_or_zero(&mut c, big, i);
+        c.i64_extend_i32_u().i64_or().local_set(magnitude);
+
+        // a magnitude with the top bit set only fits when it's the
+        // magnitude of the minimum integer
+        c.local_get(magnitude).i64_const(0).i64_lt_s();
+        c.local_get(big).i32_load(8).i32_eqz();
+        c.local_get(magnitude).i64_const(i64::MIN).i64_ne();
+        c.i32_or().i32_and().if_(EMPTY);
+        self.failure(&mut c, Failure::Overflow);
+        c.end();
+        c.i64_const(0)
+            .local_get(magnitude)
+            .i64_sub()
+            .local_get(magnitude);
+        c.local_get(big).i32_load(8).select();
+        c
+    }
+
+    /// `big_to_string(big)` writes a big integer in decimal, dividing a copy
+    /// of it by a billion to get nine digits at a time from the end.
+    fn big_to_string_code(&self) -> Code {
+        let mut c = Code::new(1);
+        let big = 0;
+        let (copy, len, i, digits) = (c.local(I32), c.local(I32), c.local(I32), c.local(I32));
+        let (buffer, size, at, result) = (c.local(I32), c.local(I32), c.local(I32), c.local(I32));
+        let (chunk, t) = (c.local(I64), c.local(I64));
+        c.local_get(big).i32_load(4).local_tee(len);
+        c.call(self.big_new).local_set(copy);
+        c.local_get(copy).i32_const(12).i32_add();
+        c.local_get(big).i32_const(12).i32_add();
+        c.local_get(len).i32_const(2).i32_shl().memory_copy();
+        c.local_get(len)
+            .i32_const(10)
+            .i32_mul()
+            .i32_const(2)
+            .i32_add();
+        c.local_tee(size)
+            .local_tee(at)
+            .call(self.alloc)
+            .local_set(buffer);
+
+        c.loop_(EMPTY);
+        c.i64_const(0).local_set(chunk);
+        c.local_get(len).local_set(i);
+        c.block(EMPTY).loop_(EMPTY);
+        c.local_get(i).i32_eqz().br_if(1);
+        c.local_get(i).i32_const(1).i32_sub().local_set(i);
+        c.local_get(chunk).i64_const(32).i64_shl();
+        limb(&mut c, copy, i);
+        c.i32_load(12).i64_extend_i32_u().i64_or().local_set(t);
+        limb(&mut c, copy, i);
+        c.local_get(t).i64_const(1_000_000_000).i64_div_u();
+        c.i32_wrap_i64().i32_store(12);
+        c.local_get(t)
+            .i64_const(1_000_000_000)
+            .i64_rem_u()
+            .local_set(chunk);
+        c.br(0).end().end();
+        c.block(EMPTY).loop_(EMPTY);
+        c.local_get(len).i32_eqz().br_if(1);
+        limb(&mut c, copy, len);
+        c.i32_load(8).br_if(1);
+        c.local_get(len).i32_const(1).i32_sub().local_set(len);
+        c.br(0).end().end();
+
+        // every chunk but the first is padded with zeros
+        c.i32_const(0).local_set(digits);
+        c.block(EMPTY).loop_(EMPTY);
+        c.local_get(digits).i32_const(9).i32_eq().br_if(1);
+        c.local_get(len)
+            .i32_eqz()
+            .local_get(chunk)
+            .i64_eqz()
+            .i32_and();
+        c.local_get(digits).i32_const(0).i32_ne().i32_and().br_if(1);
+        c.local_get(at).i32_const(1).i32_sub().local_set(at);
+        c.local_get(buffer).local_get(at).i32_add();
+        c.local_get(chunk).i64_const(10).i64_rem_u().i32_wrap_i64();
+        c.i32_const(b'0' as i32).i32_add().i32_store8(0);
+        c.local_get(chunk)
+            .i64_const(10)
+            .i64_div_u()
+            .local_set(chunk);
+        c.local_get(digits).i32_const(1).i32_add().local_set(digits);
+        c.br(0).end().end();
+        c.local_get(len).br_if(0);
+        c.end();
+
+        c.local_get(big).i32_load(8).if_(EMPTY);
+        c.local_get(at).i32_const(1).i32_sub().local_set(at);
+        c.local_get(buffer).local_get(at).i32_add();
+        c.i32_const(b'-' as i32).i32_store8(0);
+        c.end();
+
+        c.local_get(size).local_get(at).i32_sub().local_set(len);
+        c.i32_const(8).local_get(len).i32_add();
+        c.call(self.alloc).local_tee(result);
+        c.i32_const(Tag::String as i32).i32_store(0);
+        c.local_get(result).local_get(len).i32_store(4);
+        c.local_get(result).i32_const(8).i32_add();
+        c.local_get(buffer).local_get(at).i32_add();
+        c.local_get(len).memory_copy();
+        c.local_get(result);
+        c
+    }
+}
+
+/// Pushes the address of limb `i` of a big integer, less the 12 bytes of
+/// its header.
+fn limb(c: &mut Code, big: u32, i: u32) {
+    c.local_get(big)
+        .local_get(i)
+        .i32_const(2)
+        .i32_shl()
+        .i32_add();
+}
+
+/// Pushes limb `i` of a big integer, or 0 past its last limb.
+fn limb_or_zero(c: &mut Code, big: u32, i: u32) {
+    c.local_get(i).local_get(big).i32_load(4).i32_lt_u();
+    c.if_(Block::Result(I32));
+    limb(c, big, i);
+    c.i32_load(12);
+    c.else_().i32_const(0).end();
+}
+
+/// Runs `body` for each `i` below the length stored in `object`.
+fn each(c: &mut Code, object: u32, i: u32, body: impl FnOnce(&mut Code)) {
+    c.i32_const(0).local_set(i);
+    c.block(EMPTY).loop_(EMPTY);
+    c.local_get(i)
+        .local_get(object)
+        .i32_load(4)
+        .i32_ge_u()
+        .br_if(1);
+    body(c);
+    c.local_get(i).i32_const(1).i32_add().local_set(i);
+    c.br(0).end().end();
+}
@@ -0,0 +1,59 @@
+//! Programs compiled with `--emit c` build without warnings and print what
+//! they print with the interpreter.
+
+use std::{
+    fs,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+/// Compiles `src` to C and then with `cc`, and runs it.
+fn run_c(name: &str, src: &str) -> Output {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("main.radi"), src).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_radi"))
+        .args([
+            "build",
+            "main.radi",
+            "--no-cache",
+            "--emit",
+            "c",
+            "-o",
+            "main.c",
+        ])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let output = Command::new("cc")
+        .args(["-Werror", "main.c", "-lm", "-o", "main"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    Command::new(dir.join("main")).output().unwrap()
+}
+
+fn run(src: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_radi"))
+        .args(["run", "--no-cache", "-e", src])
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn min_int() {
+    let src = "def main() { print(0 - 9223372036854775807 - 1) }";
+    let (interpreted, compiled) = (run(src), run_c("min_int", src));
+    assert!(compiled.status.success(), "{compiled:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&compiled.stdout),
+        String::from_utf8_lossy(&interpreted.stdout)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&compiled.stdout),
+        "-9223372036854775808\n"
+    );
+}
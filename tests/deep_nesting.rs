@@ -0,0 +1,66 @@
+//! Deeply nested input is parsed, or rejected as too deep, without
+//! overflowing the stack of the thread that [radi::parse_bytes] is called on,
+//! even when that has the 2 MiB that Rust spawns threads with by default.
+
+use std::thread;
+
+use radi::{parse_bytes, ParseOutcome};
+
+/// The stack that threads spawned without a size get.
+const DEFAULT_STACK: usize = 2 << 20;
+
+/// Parses `open` repeated `depth` times, then `atom`, then `close` repeated
+/// as often, on a thread with the default stack.
+fn parse_nested(open: &str, atom: &str, close: &str, depth: usize) -> ParseOutcome {
+    let src = format!("{}{atom}{}", open.repeat(depth), close.repeat(depth));
+    thread::Builder::new()
+        .stack_size(DEFAULT_STACK)
+        .spawn(move || parse_bytes(src.as_bytes()))
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+fn too_deep(outcome: &ParseOutcome) -> bool {
+    let message = |d: &radi::DiagnosticRecord| d.message.contains("TooDeep");
+    outcome.ast.is_none() && outcome.diagnostics.iter().any(message)
+}
+
+/// Checks that `open` nests when it's shallow, and is too deep well past
+/// [radi::parser::MAX_DEPTH], with depths in between parsed either way.
+fn nests(open: &str, atom: &str, close: &str) {
+    assert!(parse_nested(open, atom, close, 10).ast.is_some());
+    for depth in [100, radi::parser::MAX_DEPTH - 10, 500, 1000] {
+        parse_nested(open, atom, close, depth);
+    }
+    assert!(too_deep(&parse_nested(open, atom, close, 10_000)));
+}
+
+#[test]
+fn variants() {
+    nests("|A: ", "1", "");
+}
+
+#[test]
+fn parens() {
+    nests("(", "1", ")");
+}
+
+#[test]
+fn conditionals() {
+    nests("a ? b : ", "c", "");
+}
+
+#[test]
+fn prefix_operators() {
+    nests("!", "a", "");
+}
+
+#[test]
+fn minuses() {
+    // `-` is only a binary operator, so this stops at the second one
+    for depth in [10, 1000, 10_000] {
+        let outcome = parse_nested("-", "1", "", depth);
+        assert!(outcome.ast.is_none() && !too_deep(&outcome));
+    }
+}
@@ -0,0 +1,152 @@
+//! Programs run with `radi run --jit` print what they print with the
+//! interpreter.
+#![cfg(feature = "jit")]
+
+use std::process::{Command, Output};
+
+fn run(src: &str, jit: bool) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_radi"));
+    command.args(["run", "--no-cache", "-e", src]);
+    if jit {
+        command.arg("--jit");
+    }
+    command.output().unwrap()
+}
+
+/// Checks that `src` prints the same to stdout either way, and succeeds.
+fn same(src: &str) {
+    let (interpreted, jitted) = (run(src, false), run(src, true));
+    assert!(interpreted.status.success(), "{interpreted:?}");
+    assert!(jitted.status.success(), "{jitted:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&interpreted.stdout),
+        String::from_utf8_lossy(&jitted.stdout)
+    );
+}
+
+#[test]
+fn arithmetic() {
+    same(
+        "def fact(n) { n = 0 ? 1 : n * fact(n - 1) }
+         def main() {
+             print(fact(20));
+             print(7.5 % 2.0);
+             print(to_big(0 - 17) / to_big(5));
+             print(to_big(0 - 17) % to_big(5));
+             print(sqrt(2.0));
+             print(floor(2.7));
+             print(to_int(3.9));
+             print(1 < 2);
+         }",
+    );
+}
+
+#[test]
+fn tail_calls() {
+    same(
+        "def count(n, acc) { n = 0 ? acc : count(n - 1, acc + 1) }
+         def main() { print(count(100000, 0)) }",
+    );
+}
+
+#[test]
+fn data() {
+    same(
+        "def shape(s) {
+             match s { |Circle: r => r * r * 3; |Square: w => w * w; |Point => 0 }
+         }
+         def main() {
+             def p .{ def x 1; def y \"two\"; }
+             def q .{ def x 1; def y \"two\"; }
+             print(p);
+             print(p.y);
+             print(p = q);
+             print((1, \"a\"));
+             print((1, 2) != (1, 3));
+             print(shape(|Circle: 2));
+             print(shape(|Point));
+             print(print);
+             print(main);
+         }",
+    );
+}
+
+#[test]
+fn mutation() {
+    same(
+        "def main() {
+             match 0 { set counter => { set counter (counter + 5); print(counter) } };
+             def r ^1;
+             set r^ (r^ + 1);
+             print(r^);
+         }",
+    );
+}
+
+#[test]
+fn strings() {
+    same(
+        "def main() {
+             print(int_to_string(42) + \"!\");
+             print(string_length(\"héllo\"));
+             print(substring(\"hello\", 1, 3));
+         }",
+    );
+}
+
+#[test]
+fn externs() {
+    let output = run(
+        "@extern(\"c\") def labs :: Int -> Int;
+         @extern(\"c\") def atoi :: String -> Int;
+         def main() { print(labs(0 - 42)); print(atoi(\"123\")) }",
+        true,
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n123\n");
+}
+
+#[test]
+fn runtime_errors() {
+    let output = run("def main() { print(1 / 0) }", true);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("DivisionByZero"));
+}
+
+const GENERIC: &str = "def same(a, b) { a = b }
+     def twice(f, x) { f(f(x)) }
+     def main() {
+         print(same(1, 1));
+         print(same(1.5, 2.5));
+         print(same(true, false));
+         print(same((), ()));
+         print(same(\"a\", \"a\"));
+         print(same((1, \"b\"), (1, \"b\")));
+         print(twice((n) { n + 1 }, 5));
+         print(twice((s) { s + \"!\" }, \"hi\"));
+     }";
+
+#[test]
+fn monomorphized() {
+    same(GENERIC);
+}
+
+#[test]
+fn mono_stats() {
+    let output = Command::new(env!("CARGO_BIN_EXE_radi"))
+        .args(["check", "--no-cache", "--emit=mono-stats", "-e", GENERIC])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let stats = String::from_utf8_lossy(&output.stdout);
+    let instances = |def: &str| {
+        let mut lines = stats.lines().skip_while(|line| !line.starts_with(def));
+        lines.next();
+        let instances = lines.take_while(|line| line.starts_with("  ["));
+        let types = instances.map(|line| line.split(']').next().unwrap()[3..].to_string());
+        types.collect::<Vec<_>>()
+    };
+    let same = ["Int", "Float", "Bool", "Unit", "String", "(Int, String)"];
+    assert_eq!(instances("same "), same);
+    assert_eq!(instances("twice "), ["Int", "String"]);
+}
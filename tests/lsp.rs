@@ -0,0 +1,223 @@
+//! `radi lsp` answers the requests of an editor about the files it has open.
+
+use std::{
+    fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+/// Opens a file with `src` in the language server, next to the given files
+/// on disk, sends it `requests` of methods and their params, and returns the
+/// responses to them in order. `{uri}` in the params is replaced with the URI
+/// of the file, and `{dir}` in the responses with the directory of the files.
+fn session(
+    name: &str,
+    src: &str,
+    files: &[(&str, &str)],
+    requests: &[(&str, &str)],
+) -> Vec<String> {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (path, src) in files {
+        fs::write(dir.join(path), src).unwrap();
+    }
+    let path = dir.join("main.radi");
+    // what's on disk is out of date, and the open file is what counts
+    fs::write(&path, "def main() { }\n").unwrap();
+    let uri = format!("file://{}", path.display());
+
+    let open = format!(
+        r#"{{"textDocument":{{"uri":"{uri}","languageId":"radi","version":1,"text":{src:?}}}}}"#
+    );
+    let mut messages = vec![
+        r#"{"jsonrpc":"2.0","id":0,"method":"initialize","params":{}}"#.to_string(),
+        format!(r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{open}}}"#),
+    ];
+    for (i, (method, params)) in requests.iter().enumerate() {
+        let params = params.replace("{uri}", &uri);
+        let id = i + 1;
+        messages.push(format!(
+            r#"{{"jsonrpc":"2.0","id":{id},"method":"{method}","params":{params}}}"#
+        ));
+    }
+    messages.push(r#"{"jsonrpc":"2.0","id":-1,"method":"shutdown"}"#.to_string());
+    messages.push(r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string());
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_radi"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    for message in messages {
+        write!(stdin, "Content-Length: {}\r\n\r\n{message}", message.len()).unwrap();
+    }
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut responses = stdout
+        .split("Content-Length: ")
+        .filter_map(|message| Some(message.split_once("\r\n\r\n")?.1.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(responses.len(), requests.len() + 2, "{stdout}");
+    // without those to initialize and shut down
+    responses.pop();
+    responses.remove(0);
+    let dir = dir.display().to_string();
+    responses.iter().map(|r| r.replace(&dir, "{dir}")).collect()
+}
+
+/// The params of a request about the file at a line and UTF-16 column.
+fn position(line: usize, character: usize) -> String {
+    format!(
+        r#"{{"textDocument":{{"uri":"{{uri}}"}},"position":{{"line":{line},"character":{character}}}}}"#
+    )
+}
+
+#[test]
+fn semantic_tokens() {
+    let src = "def main() {\n    print(\"a\nb\")\n}\n";
+    let responses = session(
+        "semantic_tokens",
+        src,
+        &[],
+        &[(
+            "textDocument/semanticTokens/full",
+            r#"{"textDocument":{"uri":"{uri}"}}"#,
+        )],
+    );
+    // `def`, `main` where it's bound, the builtin `print`, then the string a
+    // line at a time
+    let data = "[0,0,3,0,0,0,4,4,1,1,1,4,5,1,2,0,6,2,8,0,1,0,2,8,0]";
+    assert_eq!(
+        responses[0],
+        format!(r#"{{"jsonrpc":"2.0","id":1,"result":{{"data":{data}}}}}"#)
+    );
+}
+
+#[test]
+fn hover() {
+    let src = "// Doubles `n`.\ndef double(n) { n * 2 }\ndef main() { print(double(2)) }\n";
+    let responses = session(
+        "hover",
+        src,
+        &[],
+        &[
+            ("textDocument/hover", &position(2, 20)),
+            ("textDocument/hover", &position(2, 11)),
+        ],
+    );
+    let value = r#""```radi\ndouble :: Int -> Int\n```\n\nDoubles `n`.""#;
+    let range = r#"{"start":{"line":2,"character":19},"end":{"line":2,"character":25}}"#;
+    assert_eq!(
+        responses[0],
+        format!(
+            r#"{{"jsonrpc":"2.0","id":1,"result":{{"contents":{{"kind":"markdown","value":{value}}},"range":{range}}}}}"#
+        )
+    );
+    assert_eq!(responses[1], r#"{"jsonrpc":"2.0","id":2,"result":null}"#);
+}
+
+#[test]
+fn completion() {
+    let src = "def total 1;\ndef main() { def tone 2; print(to) }\n";
+    let responses = session(
+        "completion",
+        src,
+        &[],
+        &[("textDocument/completion", &position(1, 33))],
+    );
+    // the def in the nearer scope comes first, then the one in the module,
+    // then the builtins of the prelude
+    let items = [
+        r#"{"label":"tone","kind":6,"detail":"Int","sortText":"00000"}"#,
+        r#"{"label":"total","kind":6,"detail":"Int","sortText":"00001"}"#,
+    ];
+    let expected = format!(r#"{{"jsonrpc":"2.0","id":1,"result":[{}"#, items.join(","));
+    assert!(responses[0].starts_with(&expected), "{}", responses[0]);
+}
+
+#[test]
+fn definition() {
+    let src = "use lib.twice;\nuse lib;\ndef main() { print(twice(1)) }\n";
+    let lib = "def unused 0;\npub def twice(n) { n * 2 }\n";
+    let responses = session(
+        "definition",
+        src,
+        &[("lib.radi", lib)],
+        &[
+            ("textDocument/definition", &position(2, 20)),
+            ("textDocument/definition", &position(1, 5)),
+            ("textDocument/definition", &position(2, 14)),
+        ],
+    );
+    // through the `use` to where it's bound in the other file
+    let range = r#"{"start":{"line":1,"character":8},"end":{"line":1,"character":13}}"#;
+    assert_eq!(
+        responses[0],
+        format!(
+            r#"{{"jsonrpc":"2.0","id":1,"result":{{"uri":"file://{{dir}}/lib.radi","range":{range}}}}}"#
+        )
+    );
+    let range = r#"{"start":{"line":0,"character":0},"end":{"line":0,"character":0}}"#;
+    assert_eq!(
+        responses[1],
+        format!(
+            r#"{{"jsonrpc":"2.0","id":2,"result":{{"uri":"file://{{dir}}/lib.radi","range":{range}}}}}"#
+        )
+    );
+    // builtins aren't defined anywhere
+    assert_eq!(responses[2], r#"{"jsonrpc":"2.0","id":3,"result":null}"#);
+}
+
+#[test]
+fn rename() {
+    let src = "use lib.twice;\ndef main() { print(twice(1)) }\n";
+    let files = [
+        (
+            "radi.toml",
+            "[project]\nname = \"p\"\nsource = \".\"\nentry = \"main.radi\"\n",
+        ),
+        ("lib.radi", "pub def twice(n) { n * 2 }\n"),
+        // used by neither the open file nor the module it uses
+        (
+            "other.radi",
+            "use lib.twice;\ndef main() { print(twice(3)) }\n",
+        ),
+    ];
+    let mut params = position(1, 20);
+    params.insert_str(params.len() - 1, r#","newName":"double""#);
+    let responses = session("rename", src, &files, &[("textDocument/rename", &params)]);
+    let edit = |line, start: usize| {
+        let end = start + "twice".len();
+        format!(
+            r#"{{"range":{{"start":{{"line":{line},"character":{start}}},"end":{{"line":{line},"character":{end}}}}},"newText":"double"}}"#
+        )
+    };
+    // by file, in the order they were loaded in
+    let changes = [
+        format!(
+            r#""file://{{dir}}/main.radi":[{},{}]"#,
+            edit(0, 8),
+            edit(1, 19)
+        ),
+        format!(r#""file://{{dir}}/lib.radi":[{}]"#, edit(0, 8)),
+        format!(
+            r#""file://{{dir}}/other.radi":[{},{}]"#,
+            edit(0, 8),
+            edit(1, 19)
+        ),
+    ];
+    assert_eq!(
+        responses[0],
+        format!(
+            r#"{{"jsonrpc":"2.0","id":1,"result":{{"changes":{{{}}}}}}}"#,
+            changes.join(",")
+        )
+    );
+}
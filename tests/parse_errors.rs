@@ -0,0 +1,31 @@
+//! Parse errors are printed at the line and column they're at, even when
+//! the input just stops.
+
+use std::process::Command;
+
+fn run(src: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_radi"))
+        .args(["run", "--no-cache", "-e", src])
+        .output()
+        .unwrap();
+    assert!(!output.status.success(), "{output:?}");
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn unexpected_token() {
+    let stderr = run("def main() { def = 1 }");
+    assert!(
+        stderr.starts_with("ERROR: Parse(Unexpected(Equal)) at <source>.radi:1:18\n"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn unexpected_end() {
+    let stderr = run("1 +");
+    assert!(
+        stderr.starts_with("ERROR: Parse(UnexpectedEof) at <source>.radi:1:4\n"),
+        "{stderr}"
+    );
+}
@@ -0,0 +1,21 @@
+//! Passes are disabled with `--disable-pass`, along with the passes that
+//! depend on them.
+
+use std::process::Command;
+
+#[test]
+fn disables_dependents() {
+    let output = Command::new(env!("CARGO_BIN_EXE_radi"))
+        .args(["run", "--no-cache", "-e", "def main() { print(1) }"])
+        .args(["--disable-pass", "typeck"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "WARNING: disabling `typeck` also disables `lower`, `consteval`, `fold`, `codegen`, \
+         which depend on it\n"
+    );
+    // the program isn't run
+    assert!(output.stdout.is_empty());
+}
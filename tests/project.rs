@@ -0,0 +1,106 @@
+//! Files inside a project with a `radi.toml` are compiled when they're named
+//! on the command line, with the manifest only giving the project's settings.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+const MANIFEST: &str = "[project]\nname = \"p\"\nsource = \"src\"\nentry = \"main.radi\"\n";
+
+/// Makes a project called `name` with the given files in its source
+/// directory, and `main.radi` as its entry, and returns its directory.
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("radi.toml"), MANIFEST).unwrap();
+    fs::write(dir.join("src/main.radi"), "def main() { print(42) }\n").unwrap();
+    for (path, src) in files {
+        fs::write(dir.join("src").join(path), src).unwrap();
+    }
+    dir
+}
+
+fn radi(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_radi"))
+        .args(args)
+        .arg("--no-cache")
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn runs_the_named_file() {
+    let dir = project(
+        "runs_the_named_file",
+        &[("other.radi", "def main() { print(7) }\n")],
+    );
+    let output = radi(&dir, &["run", "src/other.radi"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "7\n");
+
+    // without a path, the manifest's entry is run
+    let output = radi(&dir, &["run"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+}
+
+#[test]
+fn checks_the_named_file() {
+    let bad = "def main() { print(1 + \"a\") }\n";
+    let dir = project("checks_the_named_file", &[("bad.radi", bad)]);
+    let output = radi(&dir, &["check", "src/bad.radi"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Mismatch") && stderr.contains("bad.radi"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn checks_each_file() {
+    let files = [
+        ("bad.radi", "def main() { print(1 + \"a\") }\n"),
+        ("other.radi", "def main() { print(7) }\n"),
+    ];
+    let dir = project("checks_each_file", &files);
+    let output = radi(&dir, &["check", "src/bad.radi", "src/other.radi"]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("==> src/bad.radi <=="), "{stdout}");
+    assert!(stdout.contains("==> src/other.radi <=="), "{stdout}");
+    assert!(
+        stdout.contains("Checked 2 files: 1 with errors (1 error, 0 warnings)"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn renames_in_every_file() {
+    let files = [
+        ("lib.radi", "pub def twice(n) { n * 2 }\n"),
+        (
+            "main.radi",
+            "use lib.twice;\ndef main() { print(twice(1)) }\n",
+        ),
+        // used by neither the entry nor the file being renamed in
+        (
+            "other.radi",
+            "use lib.twice;\ndef main() { print(twice(3)) }\n",
+        ),
+    ];
+    let dir = project("renames_in_every_file", &files);
+    let output = radi(&dir, &["rename", "src/lib.radi", "8", "double"]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Renamed 5 occurrences in 3 files\n"
+    );
+    let other = fs::read_to_string(dir.join("src/other.radi")).unwrap();
+    assert_eq!(other, "use lib.double;\ndef main() { print(double(3)) }\n");
+    let output = radi(&dir, &["run", "src/other.radi"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "6\n");
+}
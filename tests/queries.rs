@@ -0,0 +1,33 @@
+//! The editor queries of `radi check` answer about offsets into the file
+//! they're given.
+
+use std::process::{Command, Output};
+
+fn check(src: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_radi"))
+        .args(["check", "--no-cache", "-e", src])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn nothing_at_offset() {
+    let output = check(
+        "def main() {\n\n    print(1)\n}\n",
+        &[
+            "--definition-at",
+            "12",
+            "--references-at",
+            "12",
+            "--hover-at",
+            "12",
+        ],
+    );
+    assert!(output.status.success(), "{output:?}");
+    // the offset given, and not where it is among every file loaded
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "No definition at 12\nNo symbol at 12\nNo symbol at 12\n"
+    );
+}
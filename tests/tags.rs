@@ -0,0 +1,21 @@
+//! `radi tags` writes a tags file for the defs of a project.
+
+use std::{fs, path::PathBuf, process::Command};
+
+#[test]
+fn to_stdout() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("tags_to_stdout");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("main.radi"), "def main() { print(1) }\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_radi"))
+        .args(["tags", "main.radi", "--no-cache", "-o", "-"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.ends_with("main\tmain.radi\t1;\"\td\n"), "{stdout}");
+    // and not to a file named `-`
+    assert!(!dir.join("-").exists());
+}